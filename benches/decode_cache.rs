@@ -0,0 +1,151 @@
+// Compares execution speed with and without the decoded-instruction cache.
+//
+// Run with: cargo bench --bench decode_cache
+//
+// Set RUST_DOS_BENCH_PROGRAM to a .COM/.EXE file to also time a real program
+// for a fixed number of instructions.
+
+use rust_dos::cpu::{Cpu, CpuState};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+// MOV CX, 0 / L: ADD AX, BX / XOR DX, AX / LOOP L / HLT
+const TIGHT_LOOP: &[u8] = &[
+    0xB9, 0x00, 0x00, // MOV CX, 0 (65536 iterations)
+    0x01, 0xD8, // ADD AX, BX
+    0x31, 0xC2, // XOR DX, AX
+    0xE2, 0xFA, // LOOP -6
+    0xF4, // HLT
+];
+
+// Sieve of Eratosthenes over 8190 flags at DS:1000, run 10 times (the classic BYTE benchmark).
+// Leaves the number of primes (1899) in DX.
+const SIEVE: &[u8] = &[
+    0xBD, 0x0A, 0x00, // MOV BP, 10
+    // outer:
+    0xBF, 0x00, 0x10, // MOV DI, 1000h
+    0xB9, 0xFF, 0x1F, // MOV CX, 8191
+    0xB0, 0x01, // MOV AL, 1
+    0x1E, // PUSH DS
+    0x07, // POP ES
+    0xFC, // CLD
+    0xF3, 0xAA, // REP STOSB
+    0x31, 0xDB, // XOR BX, BX
+    0x31, 0xD2, // XOR DX, DX
+    // next_i:
+    0x80, 0xBF, 0x00, 0x10, 0x00, // CMP BYTE [BX+1000h], 0
+    0x74, 0x1B, // JE skip
+    0x89, 0xD8, // MOV AX, BX
+    0x01, 0xC0, // ADD AX, AX
+    0x05, 0x03, 0x00, // ADD AX, 3
+    0x89, 0xDE, // MOV SI, BX
+    0x01, 0xC6, // ADD SI, AX
+    // kill:
+    0x81, 0xFE, 0xFE, 0x1F, // CMP SI, 8190
+    0x77, 0x09, // JA done_kill
+    0xC6, 0x84, 0x00, 0x10, 0x00, // MOV BYTE [SI+1000h], 0
+    0x01, 0xC6, // ADD SI, AX
+    0xEB, 0xF1, // JMP kill
+    // done_kill:
+    0x42, // INC DX
+    // skip:
+    0x43, // INC BX
+    0x81, 0xFB, 0xFE, 0x1F, // CMP BX, 8190
+    0x76, 0xD7, // JBE next_i
+    0x4D, // DEC BP
+    0x75, 0xC3, // JNZ outer
+    0xF4, // HLT
+];
+
+const REAL_PROGRAM_STEPS: u64 = 5_000_000;
+
+fn setup(code: &[u8], cache: bool) -> Cpu {
+    let mut cpu = Cpu::new(PathBuf::from("."));
+    cpu.bus.decode_cache.enabled = cache;
+    cpu.cs = 0x1000;
+    cpu.ds = 0x2000;
+    cpu.es = 0x2000;
+    cpu.ss = 0x3000;
+    cpu.sp = 0xFFFE;
+    cpu.ip = 0x100;
+    let base = cpu.get_physical_addr(cpu.cs, cpu.ip);
+    for (i, &b) in code.iter().enumerate() {
+        cpu.bus.write_8(base + i, b);
+    }
+    cpu
+}
+
+// Runs until HLT, returns (elapsed, instructions, cpu)
+fn run_to_halt(code: &[u8], cache: bool) -> (Duration, u64, Cpu) {
+    let mut cpu = setup(code, cache);
+    let mut steps = 0u64;
+    let start = Instant::now();
+    while cpu.state == CpuState::Running {
+        cpu.step();
+        steps += 1;
+    }
+    (start.elapsed(), steps, cpu)
+}
+
+fn run_program(path: &str, cache: bool) -> Option<(Duration, u64)> {
+    let path = PathBuf::from(path);
+    let dir = path.parent()?.to_path_buf();
+    let name = path.file_name()?.to_str()?.to_string();
+
+    let mut cpu = Cpu::new(dir);
+    cpu.bus.decode_cache.enabled = cache;
+    if !cpu.load_executable(&name, None) {
+        return None;
+    }
+
+    let start = Instant::now();
+    let mut steps = 0;
+    while steps < REAL_PROGRAM_STEPS && cpu.state != CpuState::RebootShell {
+        cpu.step();
+        steps += 1;
+    }
+    Some((start.elapsed(), steps))
+}
+
+fn report(name: &str, without: (Duration, u64), with: (Duration, u64)) {
+    let mips = |(d, n): (Duration, u64)| n as f64 / d.as_secs_f64() / 1_000_000.0;
+    println!(
+        "{:<12} uncached {:>8.2?} ({:>6.2} MIPS)   cached {:>8.2?} ({:>6.2} MIPS)   speedup {:.2}x",
+        name,
+        without.0,
+        mips(without),
+        with.0,
+        mips(with),
+        without.0.as_secs_f64() / with.0.as_secs_f64()
+    );
+}
+
+fn main() {
+    // Tight loop
+    let (t0, n0, _) = run_to_halt(TIGHT_LOOP, false);
+    let (t1, n1, cpu) = run_to_halt(TIGHT_LOOP, true);
+    report("tight loop", (t0, n0), (t1, n1));
+    println!(
+        "             cache hits {} misses {}",
+        cpu.bus.decode_cache.hits, cpu.bus.decode_cache.misses
+    );
+
+    // Sieve
+    let (t0, n0, _) = run_to_halt(SIEVE, false);
+    let (t1, n1, cpu) = run_to_halt(SIEVE, true);
+    assert_eq!(cpu.dx, 1899, "sieve produced the wrong prime count");
+    report("sieve", (t0, n0), (t1, n1));
+    println!(
+        "             cache hits {} misses {}",
+        cpu.bus.decode_cache.hits, cpu.bus.decode_cache.misses
+    );
+
+    // Real program
+    match std::env::var("RUST_DOS_BENCH_PROGRAM") {
+        Ok(path) => match (run_program(&path, false), run_program(&path, true)) {
+            (Some(without), Some(with)) => report("program", without, with),
+            _ => println!("Could not load {}", path),
+        },
+        Err(_) => println!("Set RUST_DOS_BENCH_PROGRAM=path/to/PROGRAM.EXE to time a real program"),
+    }
+}
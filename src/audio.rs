@@ -1,13 +1,36 @@
 use crate::bus::Bus;
+use sdl2::audio::AudioQueue;
 
 const SAMPLE_RATE: f32 = 44100.0;
 const VOLUME: i16 = 3000;
 const BASE_FREQ: f32 = 1_193_182.0;
 
+/// Receives the PC speaker output as mono 16-bit samples at 44.1 kHz
+pub trait AudioSink {
+    /// Samples queued but not played yet
+    fn queued_samples(&self) -> u32;
+    fn queue(&mut self, samples: &[i16]) -> Result<(), String>;
+    fn resume(&mut self) {}
+}
+
+impl AudioSink for AudioQueue<i16> {
+    fn queued_samples(&self) -> u32 {
+        self.size() / 2 // i16 = 2 bytes
+    }
+
+    fn queue(&mut self, samples: &[i16]) -> Result<(), String> {
+        self.queue_audio(samples)
+    }
+
+    fn resume(&mut self) {
+        AudioQueue::resume(self);
+    }
+}
+
 // Helper for System Beep (INT 10,07)
 pub fn play_sdl_beep(bus: &mut Bus) {
     if let Some(device) = &mut bus.audio_device {
-        if device.size() > 0 { return; }
+        if device.queued_samples() > 0 { return; }
 
         let frequency = 880.0;
         let duration_ms = 200;
@@ -24,7 +47,7 @@ pub fn play_sdl_beep(bus: &mut Bus) {
             buffer.push(sample);
         }
 
-        if let Err(e) = device.queue_audio(&buffer) {
+        if let Err(e) = device.queue(&buffer) {
             eprintln!("[AUDIO] Beep queue error: {}", e);
         }
         device.resume();
@@ -32,17 +55,19 @@ pub fn play_sdl_beep(bus: &mut Bus) {
 }
 
 pub fn pump_audio(bus: &mut Bus) {
+    let speaker_on = bus.speaker().on;
+    let divisor = match bus.pit().ch2_divisor { 0 => 65536, d => d as u32 };
+
     if let Some(device) = &mut bus.audio_device {
-        let current_bytes = device.size();
+        let current_samples = device.queued_samples();
         
         // WBuffer Underrun Detection
-        if current_bytes == 0 && bus.speaker_on {
+        if current_samples == 0 && speaker_on {
             println!("[AUDIO] Buffer Underrun detected!");
         }
 
         // Maintain about 50ms of audio (approx 2048 samples).
         let target_samples = 1024*10; 
-        let current_samples = current_bytes / 2; // i16 = 2 bytes
 
         // If we are mostly full, don't add latency.
         if current_samples >= target_samples {
@@ -51,14 +76,13 @@ pub fn pump_audio(bus: &mut Bus) {
 
         let needed = target_samples - current_samples;
         let mut buffer = Vec::with_capacity(needed as usize);
-        let divisor = if bus.pit_divisor == 0 { 65536 } else { bus.pit_divisor as u32 };
         let frequency = BASE_FREQ / divisor as f32;
         let phase_step = frequency / SAMPLE_RATE;
 
         // Generate Audio
         for _ in 0..needed {
             // Filter out low frequencies (< 20Hz)
-            let sample = if bus.speaker_on && frequency > 20.0 {
+            let sample = if speaker_on && frequency > 20.0 {
                 
                 // Advance Phase
                 bus.audio_phase += phase_step;
@@ -77,7 +101,7 @@ pub fn pump_audio(bus: &mut Bus) {
             buffer.push(sample);
         }
 
-        if let Err(e) = device.queue_audio(&buffer) {
+        if let Err(e) = device.queue(&buffer) {
             eprintln!("[AUDIO] Queue error: {}", e);
         }
     }
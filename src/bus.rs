@@ -1,51 +1,82 @@
-use sdl2::audio::AudioQueue;
 use std::collections::VecDeque;
 use std::fs::{File, OpenOptions};
 use std::io::{BufWriter, Write};
+use std::ops::Range;
 use std::time::Instant;
 
+use crate::audio::AudioSink;
+use crate::clock::{self, Clock};
+use crate::decode_cache::{self, DecodeCache};
+use crate::devices::ems::{self, Ems};
+use crate::devices::kbc::Kbc;
+use crate::devices::pic::Pic;
+use crate::devices::pit::Pit;
+use crate::devices::port92::SystemControlA;
+use crate::devices::rtc::Rtc;
+use crate::devices::speaker::Speaker;
+use crate::devices::{self, Effects, IoPorts, TimeSlice};
+use crate::errors::DosErrors;
+use crate::mcb;
+use crate::nls::Nls;
+use crate::memory::{self, MemoryMap, Region};
+use crate::video::vga::VgaCard;
+use crate::xms::Xms;
 use crate::disk::DiskController;
-use crate::video::{ADDR_VGA_GRAPHICS, ADDR_VGA_TEXT, SIZE_GRAPHICS, SIZE_TEXT, VideoMode};
-
-pub trait Device {
-    fn ports(&self) -> Vec<u16>;
-    fn io_read(&mut self, port: u16) -> u8;
-    fn io_write(&mut self, port: u16, value: u8);
-    fn step(&mut self) {}
-}
+use crate::video::{ADDR_VGA_TEXT, VideoMode};
 
 pub struct Bus {
-    pub ram: Vec<u8>,          // 1MB System RAM
+    pub ram: Vec<u8>,          // 1MB System RAM + extended memory
+    pub memory: MemoryMap,     // What backs each page of the address space
+    a20_mask: usize,           // Applied to every memory access
     pub video_mode: VideoMode, // Current State
     pub disk: DiskController,
     pub keyboard_buffer: VecDeque<u16>, // Stores (Scancode << 8) | ASCII
     pub cursor_x: usize,
     pub cursor_y: usize,
     pub start_time: Instant, // System timer
-    pub audio_device: Option<AudioQueue<i16>>,
-    pub speaker_on: bool,    // Is the speaker playing?
-    pub pit_divisor: u16,    // Current Frequency Divisor
-    pub pit_mode: u8,        // PIT Command Mode
-    pub pit_write_msb: bool, // Toggle to handle 2-byte writes (LSB/MSB)
-    pub pit0_divisor: u16,
-    pub pit0_write_msb: bool,
-    pub pic_mask: u8,
+    pub audio_device: Option<Box<dyn AudioSink>>,
+    pub pit_cycle_accum: u64, // CPU cycles not yet converted into PIT ticks
     pub audio_phase: f32, // Track wave position to prevent clicking
     pub dta_segment: u16,
     pub dta_offset: u16,
+    pub alloc_strategy: u8, // INT 21h 58h
+    pub umb_linked: bool,
+    pub nls: Nls, // Country and code page
+    pub errors: DosErrors, // INT 21h 59h, INT 24h
     pub log_file: Option<BufWriter<File>>,
 
-    // VGA State
-    pub vga: crate::video::vga::VgaCard,
+    // Port I/O: PIC, PIT, speaker, VGA and anything attached later
+    pub io: IoPorts,
     pub search_handles: std::collections::HashMap<u32, String>,
+
+    // Decoded instructions, invalidated by writes
+    pub decode_cache: DecodeCache,
+
+    // Extended memory blocks handed out by the XMS driver
+    pub xms: Xms,
+
+    // Upper memory blocks: segment ranges of RAM between C800 and F000
+    upper_memory: Vec<Range<u16>>,
 }
 
 use std::path::PathBuf;
 
+/// PIT input clock in Hz
+pub const PIT_FREQUENCY: u64 = 1_193_182;
+// BOP stubs, one 4-byte slot per vector (F000:1000-13FF)
+pub const HLE_TRAP_BASE: usize = 0xF1000;
+/// Extended memory of a freshly built machine (16MB total)
+pub const DEFAULT_EXTENDED_KB: u16 = 15 * 1024;
+/// Expanded memory of a freshly built machine: 4MB, banked in at E000
+pub const DEFAULT_EMS_PAGES: u16 = 256;
+pub const DEFAULT_EMS_FRAME: u16 = 0xE000;
+
 impl Bus {
     pub fn new(root_path: PathBuf) -> Self {
         let mut bus = Self {
-            ram: vec![0; 1024 * 1024],
+            ram: Vec::new(),
+            memory: MemoryMap::new(memory::ADDRESS_SPACE),
+            a20_mask: memory::A20_OFF_MASK,
             video_mode: VideoMode::Text80x25, // Start in Text Mode (BIOS default)
             disk: DiskController::new(root_path),
             keyboard_buffer: VecDeque::new(),
@@ -53,20 +84,46 @@ impl Bus {
             cursor_y: 0,
             start_time: Instant::now(),
             audio_device: None,
-            speaker_on: false,
-            pit_divisor: 0xFFFF,
-            pit_mode: 0,
-            pit_write_msb: false,
-            pit0_divisor: 0xFFFF,
-            pit0_write_msb: false,
-            pic_mask: 0x00,
+            pit_cycle_accum: 0,
             audio_phase: 0.0,
             log_file: None,
             dta_segment: 0x1000,
             dta_offset: 0x0000,
-            vga: crate::video::vga::VgaCard::new(),
+            alloc_strategy: mcb::FIRST_FIT,
+            umb_linked: false,
+            nls: Nls::new(),
+            errors: DosErrors::default(),
+            io: IoPorts::new(),
             search_handles: std::collections::HashMap::new(),
+            decode_cache: DecodeCache::new(),
+            xms: Xms::new(0..0),
+            upper_memory: Vec::new(),
         };
+        bus.set_extended_memory(DEFAULT_EXTENDED_KB);
+        // Built-in hardware, in the order of the ids in `devices`
+        bus.io.attach(Box::new(Pic::new()));
+        bus.io.attach(Box::new(Pit::new()));
+        bus.io.attach(Box::new(Speaker::new()));
+        bus.io.attach(Box::new(VgaCard::new()));
+        bus.io.attach(Box::new(Kbc::new()));
+        bus.io.attach(Box::new(SystemControlA::new()));
+        bus.io.attach(Box::new(Ems::new(0, DEFAULT_EMS_FRAME)));
+        bus.io.attach(Box::new(Rtc::new()));
+
+        // Upper memory: see memory.rs for the layout
+        bus.memory.map(0xA0000, 0x10000, Region::Mmio(devices::VGA));
+        bus.memory.map(0xB0000, 0x8000, Region::Unmapped);
+        bus.memory.map(ADDR_VGA_TEXT, 0x8000, Region::Mmio(devices::VGA));
+        bus.memory.map(0xC0000, 0x8000, Region::Rom);
+        bus.memory.map(
+            memory::OPTION_ROM_START,
+            memory::OPTION_ROM_END - memory::OPTION_ROM_START,
+            Region::Unmapped,
+        );
+        bus.memory.map(0xF0000, 0x10000, Region::Rom);
+        bus.set_expanded_memory(DEFAULT_EMS_PAGES, DEFAULT_EMS_FRAME)
+            .expect("Default EMS page frame");
+
         // BIOS Data Area (BDA) Initialization
         // 0x0449: Current Video Mode (03 = 80x25 Color)
         bus.write_8(0x0449, 0x03);
@@ -85,6 +142,12 @@ impl Bus {
         // Bit 0 = Floppy. 0x21 (Floppy + Color)
         bus.write_16(0x0410, 0x0021);
 
+        // 0x0413: Conventional Memory Size in KB (INT 12h)
+        bus.write_16(0x0413, 640);
+
+        // 0x046C: Timer ticks since midnight
+        clock::sync_bda(&mut bus);
+
         // 0x0484: Rows on Screen (minus 1). 24
         bus.write_8(0x0484, 24);
 
@@ -109,16 +172,14 @@ impl Bus {
 
         // Initialize SFT at F000:E000 (Address 0xFE000)
         // 00-02: Modes supported (All)
-        bus.write_8(0xFE000, 0xFF);
-        bus.write_8(0xFE001, 0xFF);
-        bus.write_8(0xFE002, 0xFF);
+        bus.write_rom(0xFE000, &[0xFF, 0xFF, 0xFF]);
         // 03-06: Reserved (0)
         // 07: Scanlines supported (All?) -> Let's say FF
-        bus.write_8(0xFE007, 0xFF);
+        bus.write_rom(0xFE007, &[0xFF]);
         // 0B: Total Char Blocks (8)
-        bus.write_8(0xFE00B, 0x08);
+        bus.write_rom(0xFE00B, &[0x08]);
         // 0C: Max Active Blocks (2)
-        bus.write_8(0xFE00C, 0x02);
+        bus.write_rom(0xFE00C, &[0x02]);
         // 0D: Misc Flags (0)
         // 10: Save Pointer Caps (0)
 
@@ -128,25 +189,14 @@ impl Bus {
             bus.ram[0xC2000 + i] = (i % 256) as u8;
         }
 
-        // Install HLE traps
-
-        bus.install_hle_trap(0x10, 0xF1000); // Video
-        bus.install_hle_trap(0x11, 0xF1004); // Equipment
-        bus.install_hle_trap(0x12, 0xF1008); // Memory
-        bus.install_hle_trap(0x15, 0xF100C); // System
-        bus.install_hle_trap(0x16, 0xF1010); // Keyboard
-        bus.install_hle_trap(0x1A, 0xF1014); // Time
-        bus.install_hle_trap(0x20, 0xF1018); // Terminate
-        bus.install_hle_trap(0x21, 0xF101C); // DOS
-        bus.install_hle_trap(0x2F, 0xF1020); // Shell Command
-        bus.install_hle_trap(0x33, 0xF1024); // Mouse
-
         bus
     }
 
-    /// Installs a Magic Trap (FE 38 <Vector> CF) at the given Physical Address
-    /// and updates the IVT to point to it.
-    fn install_hle_trap(&mut self, vector: u8, phys_addr: usize) {
+    /// Installs a Magic Trap (FE 38 <Vector> CF) for the vector and updates the IVT to
+    /// point to it. Every vector has its own 4-byte slot at F000:1000 + vector * 4.
+    pub fn install_hle_trap(&mut self, vector: u8) {
+        let phys_addr = HLE_TRAP_BASE + (vector as usize) * 4;
+
         // Update IVT (0000:Vector*4)
         let ivt_offset = (vector as usize) * 4;
         let handler_offset = (phys_addr & 0xFFFF) as u16; // Offset part of F000:Offset
@@ -154,11 +204,172 @@ impl Bus {
         self.write_16(ivt_offset, handler_offset); // IP
         self.write_16(ivt_offset + 2, 0xF000); // CS
 
-        // Write Trap Code
-        self.write_8(phys_addr, 0xFE); // BOP
-        self.write_8(phys_addr + 1, 0x38); // Magic
-        self.write_8(phys_addr + 2, vector); // The Vector ID
-        self.write_8(phys_addr + 3, 0xCF); // IRET
+        // Write Trap Code: BOP, Magic, The Vector ID, IRET
+        self.write_rom(phys_addr, &[0xFE, 0x38, vector, 0xCF]);
+    }
+
+    /// Installs `kb` of memory above 1MB, clearing all of it.
+    /// The first 64KB are the HMA; the XMS driver manages the rest.
+    pub fn set_extended_memory(&mut self, kb: u16) {
+        let hma_present = kb >= 64;
+        self.ram.truncate(memory::HMA_START);
+        self.ram.resize(memory::HMA_START + kb as usize * 1024, 0);
+        self.memory.map(
+            memory::HMA_START,
+            memory::ADDRESS_SPACE - memory::HMA_START,
+            if hma_present { Region::Ram } else { Region::Unmapped },
+        );
+        self.xms = Xms::new(memory::ADDRESS_SPACE.min(self.ram.len())..self.ram.len());
+    }
+
+    /// Installs an EMS board with `pages` 16KB pages, banked into a 64KB frame at
+    /// `frame_segment`. The frame must be 16KB aligned and sit in free space
+    /// between C8000 and F0000. No pages means no EMS driver.
+    pub fn set_expanded_memory(&mut self, pages: u16, frame_segment: u16) -> Result<(), String> {
+        let umbs = self.release_upper_memory();
+        let result = self.install_ems(pages, frame_segment);
+        self.set_upper_memory(umbs);
+        result
+    }
+
+    fn install_ems(&mut self, pages: u16, frame_segment: u16) -> Result<(), String> {
+        let frame = (frame_segment as usize) << 4;
+        let frame_len = ems::PHYSICAL_PAGES * ems::PAGE_SIZE;
+        let old_frame = self.ems().frame_base();
+        let in_use = (frame..frame + frame_len)
+            .step_by(memory::PAGE_SIZE)
+            .any(|addr| !matches!(self.memory.region(addr), Region::Unmapped | Region::Mmio(devices::EMS)));
+        if pages > 0
+            && (!frame.is_multiple_of(ems::PAGE_SIZE)
+                || frame < memory::OPTION_ROM_START
+                || frame + frame_len > memory::OPTION_ROM_END
+                || in_use)
+        {
+            return Err(format!("EMS page frame can't go at {:04X}", frame_segment));
+        }
+
+        if self.memory.region(old_frame) == Region::Mmio(devices::EMS) {
+            self.memory.map(old_frame, frame_len, Region::Unmapped);
+        }
+        *self.ems_mut() = Ems::new(pages, frame_segment);
+        if pages > 0 {
+            self.memory.map(frame, frame_len, Region::Mmio(devices::EMS));
+        }
+        Ok(())
+    }
+
+    /// Turns the open bus left between C8000 and F0000 into RAM for upper memory
+    /// blocks, or hands it back. The EMS frame and option ROMs keep their space.
+    pub fn set_upper_memory(&mut self, enabled: bool) {
+        self.release_upper_memory();
+        if !enabled {
+            self.umb_linked = false;
+            return;
+        }
+        let mut start = None;
+        for addr in (memory::OPTION_ROM_START..=memory::OPTION_ROM_END).step_by(memory::PAGE_SIZE) {
+            let free = addr < memory::OPTION_ROM_END && self.memory.region(addr) == Region::Unmapped;
+            match (free, start) {
+                (true, None) => start = Some(addr),
+                (false, Some(first)) => {
+                    self.memory.map(first, addr - first, Region::Ram);
+                    self.ram[first..addr].fill(0);
+                    self.upper_memory.push((first >> 4) as u16..(addr >> 4) as u16);
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // Unmaps the upper memory blocks, returning whether there were any
+    fn release_upper_memory(&mut self) -> bool {
+        let ranges = std::mem::take(&mut self.upper_memory);
+        for range in &ranges {
+            let start = (range.start as usize) << 4;
+            let end = (range.end as usize) << 4;
+            self.memory.map(start, end - start, Region::Unmapped);
+        }
+        !ranges.is_empty()
+    }
+
+    /// Segment ranges of upper memory, lowest first
+    pub fn upper_memory(&self) -> &[Range<u16>] {
+        &self.upper_memory
+    }
+
+    /// Memory above 1MB, HMA included
+    pub fn extended_kb(&self) -> u16 {
+        ((self.ram.len() - memory::HMA_START) / 1024) as u16
+    }
+
+    /// Byte at a 24-bit physical address, for block moves that reach past the HMA.
+    /// The A20 gate doesn't apply; extended memory that isn't installed reads open bus.
+    pub fn read_linear(&self, addr: usize) -> u8 {
+        if addr < memory::HMA_START {
+            self.read_8(addr)
+        } else {
+            self.ram.get(addr).copied().unwrap_or(0xFF)
+        }
+    }
+
+    pub fn write_linear(&mut self, addr: usize, value: u8) {
+        if addr < memory::HMA_START {
+            self.write_8(addr, value);
+        } else if addr < self.ram.len() {
+            self.decode_cache.invalidate(addr);
+            self.ram[addr] = value;
+        }
+    }
+
+    /// Copies `len` bytes between physical addresses as if through a buffer,
+    /// so overlapping ranges come out right
+    pub fn copy_linear(&mut self, src: usize, dst: usize, len: usize) {
+        let bytes: Vec<u8> = (src..src + len).map(|a| self.read_linear(a)).collect();
+        for (i, byte) in bytes.into_iter().enumerate() {
+            self.write_linear(dst + i, byte);
+        }
+    }
+
+    /// Host-side write that goes straight to the backing RAM, ROM included.
+    /// This is how the BIOS tables and trap stubs get into the F000 segment.
+    pub fn write_rom(&mut self, addr: usize, bytes: &[u8]) {
+        for (i, &byte) in bytes.iter().enumerate() {
+            self.decode_cache.invalidate(addr + i);
+            self.ram[addr + i] = byte;
+        }
+    }
+
+    /// Maps an option ROM image at `addr` (C8000-EFFFF, 2KB aligned) after
+    /// checking its header and checksum. The ROM's init entry isn't called.
+    pub fn load_option_rom(&mut self, addr: usize, image: &[u8]) -> Result<(), String> {
+        let umbs = self.release_upper_memory();
+        let result = self.map_option_rom(addr, image);
+        self.set_upper_memory(umbs);
+        result
+    }
+
+    fn map_option_rom(&mut self, addr: usize, image: &[u8]) -> Result<(), String> {
+        let len = memory::validate_option_rom(image)?;
+        let mapped = len.next_multiple_of(memory::PAGE_SIZE);
+        if !addr.is_multiple_of(memory::PAGE_SIZE)
+            || addr < memory::OPTION_ROM_START
+            || addr + mapped > memory::OPTION_ROM_END
+        {
+            return Err(format!("{} byte ROM doesn't fit at {:05X}", len, addr));
+        }
+        if (addr..addr + mapped)
+            .step_by(memory::PAGE_SIZE)
+            .any(|page| self.memory.region(page) != Region::Unmapped)
+        {
+            return Err(format!("ROM at {:05X} overlaps mapped memory", addr));
+        }
+
+        self.write_rom(addr, &image[..len]);
+        self.ram[addr + len..addr + mapped].fill(0xFF);
+        self.memory.map(addr, mapped, Region::Rom);
+        self.log_string(&format!("[BUS] Option ROM at {:05X}, {} bytes", addr, len));
+        Ok(())
     }
 
     // Helper: Scroll the text screen up by 1 line
@@ -168,69 +379,79 @@ impl Bus {
         let row_size = 160;
         let screen_size = 25 * row_size;
 
+        let vram = &mut self.vga_mut().vram_text;
+
         // Move memory back
-        for i in 0..(screen_size - row_size) {
-            self.vga.vram_text[i] = self.vga.vram_text[i + row_size];
-        }
+        vram.copy_within(row_size..screen_size, 0);
 
         // Clear bottom row
-        for i in (screen_size - row_size)..screen_size {
-            if i % 2 == 0 {
-                self.vga.vram_text[i] = 0x20;
-            }
-            // Space
-            else {
-                self.vga.vram_text[i] = 0x07;
-            } // Light Gray
+        for cell in vram[(screen_size - row_size)..screen_size].chunks_exact_mut(2) {
+            cell[0] = 0x20; // Space
+            cell[1] = 0x07; // Light Gray
         }
     }
 
     pub fn read_8(&self, addr: usize) -> u8 {
-        // if addr >= 0x116F2 && addr < 0x116F2 + 12 {
-        //      println!("[MEM WATCH] CPU reading DTA Filename @ {:05X}. Value: {:02X} ({})",
-        //               addr, self.ram[addr], self.ram[addr] as char);
-        // }
-        if addr >= ADDR_VGA_GRAPHICS && addr < ADDR_VGA_GRAPHICS + SIZE_GRAPHICS {
-            self.vga.vram_graphics[addr - ADDR_VGA_GRAPHICS]
-        } else if addr >= ADDR_VGA_TEXT && addr < ADDR_VGA_TEXT + SIZE_TEXT {
-            self.vga.vram_text[addr - ADDR_VGA_TEXT]
-        } else {
-            self.ram[addr]
+        let addr = addr & self.a20_mask;
+        match self.memory.region(addr) {
+            Region::Ram | Region::Rom => self.ram[addr],
+            Region::Mmio(id) => self.io.mem_read(id, addr),
+            Region::Unmapped => 0xFF, // Open bus
         }
     }
 
     // Returns true if a write occurred to the *active* video memory
     pub fn write_8(&mut self, addr: usize, value: u8) -> bool {
-        //if addr >= 0xB8000 && addr < 0xB8FA0 && (addr % 2 == 0) {
-        // if value >= 0x20 && value <= 0x7E { // Printable chars only
-        //     let offset = (addr - 0xB8000) / 2;
-        //     let row = offset / 80;
-        //     let col = offset % 80;
-        //     self.log_string(&format!("[VIDEO] '{}' @ {},{}", value as char, col, row));
-        // }
-        //}
-
-        if addr >= ADDR_VGA_GRAPHICS && addr < ADDR_VGA_GRAPHICS + SIZE_GRAPHICS {
-            self.vga.write_graphics(addr - ADDR_VGA_GRAPHICS, value);
-            self.video_mode == VideoMode::Graphics320x200
-        } else if addr >= ADDR_VGA_TEXT && addr < ADDR_VGA_TEXT + SIZE_TEXT {
-            self.vga.vram_text[addr - ADDR_VGA_TEXT] = value;
-
-            // Check if current mode uses this memory
-            match self.video_mode {
-                VideoMode::Text80x25
-                | VideoMode::Text80x25Color
-                | VideoMode::Text40x25
-                | VideoMode::Text40x25Color
-                | VideoMode::Cga320x200
-                | VideoMode::Cga320x200Color
-                | VideoMode::Cga640x200 => true, // Dirty!
-                _ => false,
+        let addr = addr & self.a20_mask;
+        // Self-modifying code: forget anything decoded from this page
+        self.decode_cache.invalidate(addr);
+
+        match self.memory.region(addr) {
+            Region::Ram => {
+                self.ram[addr] = value;
+                false
             }
-        } else {
-            self.ram[addr] = value;
-            false
+            Region::Mmio(id) => {
+                self.io.mem_write(id, addr, value);
+                id == devices::VGA && self.is_active_vram(addr)
+            }
+            // Guest writes to ROM or empty space go nowhere
+            Region::Rom | Region::Unmapped => false,
+        }
+    }
+
+    // Check if current mode uses this memory
+    fn is_active_vram(&self, addr: usize) -> bool {
+        if addr < ADDR_VGA_TEXT {
+            return self.video_mode == VideoMode::Graphics320x200;
+        }
+        match self.video_mode {
+            VideoMode::Text80x25
+            | VideoMode::Text80x25Color
+            | VideoMode::Text40x25
+            | VideoMode::Text40x25Color
+            | VideoMode::Cga320x200
+            | VideoMode::Cga320x200Color
+            | VideoMode::Cga640x200 => true, // Dirty!
+            _ => false,
+        }
+    }
+
+    // Decode the instruction at the given physical address, using the cache when possible
+    pub fn fetch_instruction(&mut self, phys: usize, ip: u16) -> iced_x86::Instruction {
+        // The longest instruction is 15 bytes, so it can reach into one more page
+        let last = phys + 14;
+        let off_ram = |region| matches!(region, Region::Mmio(_) | Region::Unmapped);
+        if off_ram(self.memory.region(phys))
+            || off_ram(self.memory.region(last))
+            || last & self.a20_mask != last
+        {
+            // Device memory (e.g. an EMS page frame) can change under us, and empty
+            // space or the A20 wrap isn't what's in `ram`: decode it fresh
+            let bytes: Vec<u8> = (phys..phys + 15).map(|addr| self.read_8(addr)).collect();
+            return decode_cache::decode(&bytes, 0, ip);
         }
+        self.decode_cache.fetch(&self.ram, phys, ip)
     }
 
     // Write a 16-bit value to memory (Little Endian)
@@ -271,137 +492,161 @@ impl Bus {
         self.write_32(addr + 4, (value >> 32) as u32);
     }
 
+    // Advance timers by the given number of CPU cycles.
+    // The PIT runs at 1.193182 MHz regardless of the CPU clock, so we convert
+    // cycles into PIT ticks before handing the time to the devices.
+    pub fn tick_devices(&mut self, cycles: u64, cpu_hz: u64) {
+        if cpu_hz == 0 {
+            return;
+        }
+        self.pit_cycle_accum += cycles * PIT_FREQUENCY;
+        let pit_ticks = self.pit_cycle_accum / cpu_hz;
+        self.pit_cycle_accum %= cpu_hz;
+        if pit_ticks == 0 {
+            return;
+        }
+
+        let mut slice = TimeSlice {
+            pit_ticks,
+            irqs: 0,
+        };
+        self.io.step(&mut slice);
+        self.pic_mut().raise(slice.irqs);
+    }
+
+    /// First segment past conventional memory, from the BDA memory size
+    pub fn memory_top(&self) -> u16 {
+        (self.read_16(0x0413) as u32 * 64).min(0xA000) as u16
+    }
+
     // Write to an I/O Port
     pub fn io_write(&mut self, port: u16, value: u8) {
-        match port {
-            // PIC (Programmable Interrupt Controller) 0x20 / 0x21
-            // We ignore initialization words (ICWs) but acknowledge EOI (0x20).
-            0x20 => {
-                self.log_string("[PIC] EOI Received");
-                // Command Register. 0x20 = End of Interrupt (EOI).
-                // log_string("[PIC] Command received");
-            }
-            0x21 => {
-                self.log_string(&format!("[PIC] IMR Set to {:02X}", value));
-                self.pic_mask = value;
-            }
+        let Some(effects) = self.io.write(port, value) else {
+            self.log_string(&format!(
+                "[Unhandled IO Write] Port: {:04X}, Value: {:02X}",
+                port, value
+            ));
+            return;
+        };
 
-            // Port 0x40: Channel 0 Data (System Timer)
-            // Controls the system tick rate (IRQ 0).
-            // Default is 18.2 Hz (Divisor 65535).
-            0x40 => {
-                if !self.pit0_write_msb {
-                    // Write LSB
-                    self.pit0_divisor = (self.pit0_divisor & 0xFF00) | (value as u16);
-                    self.pit0_write_msb = true; // Next write is MSB
-                } else {
-                    // Write MSB
-                    self.pit0_divisor = (self.pit0_divisor & 0x00FF) | ((value as u16) << 8);
-                    self.pit0_write_msb = false; // Reset to LSB
-
-                    if self.pit0_divisor > 0 {
-                        let hz = 1_193_182 / self.pit0_divisor as u32;
-                        self.log_string(&format!("[PIT] Channel 0 Frequency set to {} Hz", hz));
-                    }
+        if effects.contains(Effects::VIDEO_MODE) {
+            // Log manual mode register writes
+            self.log_string(&format!(
+                "[VGA-IO] Write Port {:04X} Value {:02X}",
+                port, value
+            ));
+
+            // Check if video mode changed
+            if let Some(new_mode) = self.vga().check_video_mode() {
+                if self.video_mode != new_mode && new_mode == VideoMode::Graphics320x200 {
+                    self.log_string("[VGA] Switch to Graphics320x200 detected via IO");
+                    self.video_mode = new_mode;
                 }
             }
+        }
 
-            // PIT Channel 2 Data (Port 0x42)
-            // This sets the frequency.
-            // Frequency = 1,193,182 Hz / Divisor
-            0x42 => {
-                if !self.pit_write_msb {
-                    // Write LSB
-                    self.pit_divisor = (self.pit_divisor & 0xFF00) | (value as u16);
-                    self.pit_write_msb = true; // Next write will be MSB
-                } else {
-                    // Write MSB
-                    self.pit_divisor = (self.pit_divisor & 0x00FF) | ((value as u16) << 8);
-                    self.pit_write_msb = false; // Reset to LSB
-                    // println!("[PIT] Frequency Divisor Set to: {}", self.pit_divisor);
-                }
-            }
+        if effects.contains(Effects::A20) {
+            self.update_a20();
+        }
+    }
 
-            // PIT Command Register (Port 0x43)
-            0x43 => {
-                self.pit_mode = value;
+    // Read from an I/O Port
+    pub fn io_read(&mut self, port: u16) -> u8 {
+        self.io.read(port).unwrap_or(0xFF) // Default open bus
+    }
 
-                // Extract the Channel bits (7-6)
-                // 00 = Channel 0, 01 = Channel 1, 10 = Channel 2
-                let channel = (value >> 6) & 0x03;
+    // --- Built-in devices ---
+    // IoPorts won't detach these, so they're there for the lifetime of the bus
 
-                // If the command is for the Counter (not Read-Back), reset the flip-flop.
-                // We check Access bits (5-4) to ensure it's not a Latch command (00).
-                let access = (value >> 4) & 0x03;
+    pub fn pic(&self) -> &Pic {
+        self.io.get(devices::PIC).expect("PIC detached")
+    }
 
-                if access != 0 {
-                    match channel {
-                        0 => self.pit0_write_msb = false, // Reset Channel 0 LSB/MSB
-                        2 => self.pit_write_msb = false,  // Reset Channel 2 LSB/MSB
-                        _ => {}
-                    }
-                }
-            }
+    pub fn pic_mut(&mut self) -> &mut Pic {
+        self.io.get_mut(devices::PIC).expect("PIC detached")
+    }
 
-            // PPI Port B (Speaker Control 0x61)
-            // Bit 0: Timer 2 Gate (Must be 1 for timer to run)
-            // Bit 1: Speaker Data (Must be 1 for sound to pass to speaker)
-            0x61 => {
-                // If both Bit 0 and Bit 1 are set, the speaker is ON
-                let enabled = (value & 0x03) == 0x03;
-                self.speaker_on = enabled;
-            }
+    pub fn pit(&self) -> &Pit {
+        self.io.get(devices::PIT).expect("PIT detached")
+    }
 
-            // Dispatch to Devices
-            // TODO: Use a proper map lookup
-            _ => {
-                if self.vga.ports().contains(&port) {
-                    self.vga.io_write(port, value);
-                    // Log manual VGA writes
-                    self.log_string(&format!(
-                        "[VGA-IO] Write Port {:04X} Value {:02X}",
-                        port, value
-                    ));
-
-                    // Check if video mode changed
-                    if let Some(new_mode) = self.vga.check_video_mode() {
-                        if self.video_mode != new_mode && new_mode == VideoMode::Graphics320x200 {
-                            self.log_string("[VGA] Switch to Graphics320x200 detected via IO");
-                            self.video_mode = new_mode;
-                            // Clear VRAM?
-                            // memset(&mut self.vram_graphics, 0);
-                        }
-                    }
-                } else {
-                    // Unhandled port write
-                    self.log_string(&format!(
-                        "[Unhandled IO Write] Port: {:04X}, Value: {:02X}",
-                        port, value
-                    ));
-                }
-            }
-        }
+    pub fn pit_mut(&mut self) -> &mut Pit {
+        self.io.get_mut(devices::PIT).expect("PIT detached")
     }
 
-    // Read from an I/O Port
-    pub fn io_read(&mut self, port: u16) -> u8 {
-        match port {
-            // Read PPI Port B (Speaker State)
-            0x61 => {
-                let mut val = 0;
-                if self.speaker_on {
-                    val |= 0x03;
-                }
-                val
-            }
+    pub fn speaker(&self) -> &Speaker {
+        self.io.get(devices::SPEAKER).expect("Speaker detached")
+    }
 
-            _ => {
-                if self.vga.ports().contains(&port) {
-                    self.vga.io_read(port)
-                } else {
-                    0xFF // Default open bus
-                }
-            }
+    pub fn ems(&self) -> &Ems {
+        self.io.get(devices::EMS).expect("EMS detached")
+    }
+
+    pub fn ems_mut(&mut self) -> &mut Ems {
+        self.io.get_mut(devices::EMS).expect("EMS detached")
+    }
+
+    pub fn vga(&self) -> &VgaCard {
+        self.io.get(devices::VGA).expect("VGA detached")
+    }
+
+    pub fn vga_mut(&mut self) -> &mut VgaCard {
+        self.io.get_mut(devices::VGA).expect("VGA detached")
+    }
+
+    pub fn rtc(&self) -> &Rtc {
+        self.io.get(devices::RTC).expect("RTC detached")
+    }
+
+    pub fn rtc_mut(&mut self) -> &mut Rtc {
+        self.io.get_mut(devices::RTC).expect("RTC detached")
+    }
+
+    /// Date and time of day, kept by the RTC
+    pub fn clock(&self) -> &Clock {
+        &self.rtc().clock
+    }
+
+    pub fn clock_mut(&mut self) -> &mut Clock {
+        &mut self.rtc_mut().clock
+    }
+
+    // --- A20 gate ---
+    // The 8042 output port and port 92h are wired together: either one enables A20
+
+    pub fn a20_enabled(&self) -> bool {
+        self.a20_mask == memory::A20_ON_MASK
+    }
+
+    /// Mask for physical addresses under the current A20 state
+    pub fn a20_mask(&self) -> usize {
+        self.a20_mask
+    }
+
+    /// Sets both gates, as the BIOS does for INT 15h AH=24h
+    pub fn set_a20(&mut self, on: bool) {
+        if let Some(kbc) = self.io.get_mut::<Kbc>(devices::KBC) {
+            kbc.set_a20(on);
+        }
+        if let Some(port92) = self.io.get_mut::<SystemControlA>(devices::PORT92) {
+            port92.set_a20(on);
+        }
+        self.update_a20();
+    }
+
+    fn update_a20(&mut self) {
+        let on = self.io.get::<Kbc>(devices::KBC).is_some_and(|kbc| kbc.a20())
+            || self
+                .io
+                .get::<SystemControlA>(devices::PORT92)
+                .is_some_and(|port92| port92.a20());
+        if on != self.a20_enabled() {
+            self.a20_mask = if on {
+                memory::A20_ON_MASK
+            } else {
+                memory::A20_OFF_MASK
+            };
+            self.log_string(&format!("[BUS] A20 {}", if on { "enabled" } else { "disabled" }));
         }
     }
 
@@ -0,0 +1,128 @@
+use chrono::{Local, NaiveDateTime, NaiveTime, TimeDelta, Timelike};
+
+use crate::bus::{Bus, PIT_FREQUENCY};
+
+// The machine's date and time.
+//
+// One clock backs the BIOS time services (INT 1Ah), the DOS ones (INT 21h
+// 2Ah-2Dh) and the tick count the BIOS keeps in the BDA:
+//
+//   046C  Ticks since midnight, 32 bits, at 18.2 Hz
+//   0470  Set when the count wraps past midnight, cleared by INT 1Ah 00h
+//
+// It starts from the host's local time, or wherever the configuration puts
+// it, then runs on emulated time: the PIT input clock drives it as it drives
+// the timer interrupt, so the two never drift apart. The RTC owns it and
+// keeps it running (devices/rtc.rs).
+
+/// BIOS ticks in a day: 65536 PIT cycles each
+pub const TICKS_PER_DAY: u32 = 0x1800B0;
+
+const BDA_TICKS: usize = 0x046C;
+const BDA_MIDNIGHT: usize = 0x0470;
+
+/// Where the clock starts at power-on
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StartTime {
+    /// The host's local time
+    Host,
+    /// A fixed date and time, for software that won't run past a certain date
+    Fixed(NaiveDateTime),
+    /// The host's local time, moved
+    Offset(TimeDelta),
+}
+
+pub struct Clock {
+    now: NaiveDateTime, // Whole seconds
+    pit_ticks: u64,     // PIT cycles into the current second
+    midnight: bool,     // A day has passed since the BDA last heard
+}
+
+impl Clock {
+    pub fn new(start: StartTime) -> Self {
+        let host = Local::now().naive_local();
+        let now = match start {
+            StartTime::Host => host,
+            StartTime::Fixed(time) => time,
+            StartTime::Offset(offset) => host + offset,
+        };
+        let mut clock = Self {
+            now,
+            pit_ticks: 0,
+            midnight: false,
+        };
+        clock.set(now);
+        clock
+    }
+
+    pub fn now(&self) -> NaiveDateTime {
+        let nanos = self.pit_ticks * 1_000_000_000 / PIT_FREQUENCY;
+        self.now + TimeDelta::nanoseconds(nanos as i64)
+    }
+
+    pub fn set(&mut self, now: NaiveDateTime) {
+        self.now = now.with_nanosecond(0).unwrap_or(now);
+        self.pit_ticks = now.nanosecond() as u64 * PIT_FREQUENCY / 1_000_000_000;
+        self.midnight = false;
+    }
+
+    /// Moves the clock on by `pit_ticks` cycles of the PIT input clock
+    pub fn advance(&mut self, pit_ticks: u64) {
+        let total = self.pit_ticks + pit_ticks;
+        let day = self.now.date();
+        self.now += TimeDelta::seconds((total / PIT_FREQUENCY) as i64);
+        self.pit_ticks = total % PIT_FREQUENCY;
+        self.midnight |= self.now.date() != day;
+    }
+
+    /// BIOS ticks since midnight
+    pub fn ticks(&self) -> u32 {
+        let seconds = self.now.num_seconds_from_midnight() as u64;
+        ((seconds * PIT_FREQUENCY + self.pit_ticks) >> 16) as u32
+    }
+
+    /// PIT cycles into the current second
+    pub fn pit_ticks(&self) -> u64 {
+        self.pit_ticks
+    }
+}
+
+/// Starts the clock over from `start`, as at power-on
+pub fn start(bus: &mut Bus, start: StartTime) {
+    *bus.clock_mut() = Clock::new(start);
+    sync_bda(bus);
+}
+
+/// Sets the date and time, and the BDA tick count with them
+pub fn set(bus: &mut Bus, now: NaiveDateTime) {
+    bus.clock_mut().set(now);
+    sync_bda(bus);
+}
+
+/// Sets the time of day from a BIOS tick count (INT 1Ah 01h)
+pub fn set_ticks(bus: &mut Bus, ticks: u32) {
+    let pit_ticks = ticks.min(TICKS_PER_DAY) as u64 * 0x10000;
+    let clock = bus.clock_mut();
+    let midnight = clock.now.date().and_time(NaiveTime::MIN);
+    clock.set(midnight);
+    clock.advance(pit_ticks);
+    sync_bda(bus);
+}
+
+/// Brings the BDA tick count up to date, flagging a midnight passed since
+pub fn sync_bda(bus: &mut Bus) {
+    let ticks = bus.clock().ticks();
+    bus.write_32(BDA_TICKS, ticks);
+    if std::mem::take(&mut bus.clock_mut().midnight) {
+        bus.write_8(BDA_MIDNIGHT, 1);
+    }
+}
+
+/// INT 1Ah 00h: ticks since midnight, and whether midnight has passed since
+/// the last call
+pub fn read_ticks(bus: &mut Bus) -> (u32, bool) {
+    sync_bda(bus);
+    let midnight = bus.read_8(BDA_MIDNIGHT) != 0;
+    bus.write_8(BDA_MIDNIGHT, 0);
+    (bus.read_32(BDA_TICKS), midnight)
+}
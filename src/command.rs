@@ -1,6 +1,7 @@
-use crate::cpu::Cpu;
+use crate::clock;
+use crate::cpu::{Cpu, CpuState};
 use crate::video::print_string;
-use chrono::{DateTime, Local};
+use chrono::{DateTime, Local, NaiveDate, NaiveTime};
 use std::collections::HashMap;
 use std::fs;
 
@@ -28,6 +29,10 @@ impl CommandDispatcher {
         dispatcher.register("EXIT", Box::new(ExitCommand));
         dispatcher.register("CD", Box::new(CdCommand));
         dispatcher.register("CHDIR", Box::new(CdCommand));
+        dispatcher.register("LH", Box::new(LoadHighCommand));
+        dispatcher.register("LOADHIGH", Box::new(LoadHighCommand));
+        dispatcher.register("DATE", Box::new(DateCommand));
+        dispatcher.register("TIME", Box::new(TimeCommand));
 
         dispatcher
     }
@@ -80,7 +85,12 @@ impl ShellCommand for DirCommand {
                     .map(|t| t.into())
                     .unwrap_or_else(|| Local::now());
 
-                let date_str = timestamp.format("%m/%d/%Y  %I:%M %p").to_string();
+                let country = cpu.bus.nls.country();
+                let date_str = format!(
+                    "{}  {}",
+                    country.format_date(timestamp.date_naive()),
+                    country.format_time(timestamp.time())
+                );
 
                 // Get File Size or <DIR> tag
                 let size_str = if path.is_dir() {
@@ -91,7 +101,7 @@ impl ShellCommand for DirCommand {
                     let size = metadata.as_ref().map(|m| m.len()).unwrap_or(0);
                     total_bytes += size;
                     // Format size with simple commas (optional, but looks "real")
-                    format_size(size)
+                    format_size(size, cpu.bus.nls.country().thousands)
                 };
 
                 // Get Filename
@@ -113,7 +123,7 @@ impl ShellCommand for DirCommand {
             &format!(
                 "{:>16} File(s) {:>14} bytes\r\n",
                 file_count,
-                format_size(total_bytes)
+                format_size(total_bytes, cpu.bus.nls.country().thousands)
             ),
         );
         print_string(
@@ -128,13 +138,13 @@ impl ShellCommand for DirCommand {
 }
 
 /// Format u64 as string with commas (e.g. 1,024)
-fn format_size(n: u64) -> String {
+fn format_size(n: u64, separator: char) -> String {
     let s = n.to_string();
     let mut result = String::new();
     let mut count = 0;
     for c in s.chars().rev() {
         if count > 0 && count % 3 == 0 {
-            result.push(',');
+            result.push(separator);
         }
         result.push(c);
         count += 1;
@@ -216,7 +226,7 @@ impl ShellCommand for ExitCommand {
     fn execute(&self, cpu: &mut Cpu, _args: &str) {
         cpu.bus
             .log_string("[SHELL] Exiting Emulator via command...");
-        std::process::exit(0);
+        cpu.state = CpuState::PoweredOff;
     }
 }
 
@@ -237,3 +247,94 @@ impl ShellCommand for CdCommand {
         }
     }
 }
+
+struct LoadHighCommand;
+impl ShellCommand for LoadHighCommand {
+    fn execute(&self, cpu: &mut Cpu, args: &str) {
+        // The program's own arguments aren't passed on, as for plain commands
+        let program = args.split_whitespace().next().unwrap_or("");
+        let loaded = if program.is_empty() {
+            false
+        } else if !program.contains('.') {
+            cpu.load_high(&format!("{}.com", program)) || cpu.load_high(&format!("{}.exe", program))
+        } else {
+            cpu.load_high(program)
+        };
+        if !loaded {
+            print_string(cpu, "Bad command or file name.\r\n");
+        }
+    }
+}
+
+struct DateCommand;
+impl ShellCommand for DateCommand {
+    fn execute(&self, cpu: &mut Cpu, args: &str) {
+        let args = args.trim();
+        let country = cpu.bus.nls.country();
+        if args.is_empty() {
+            let today = cpu.bus.clock().now().date();
+            let date = country.format_date(today);
+            print_string(cpu, &format!("Current date is {} {}\r\n", today.format("%a"), date));
+            return;
+        }
+        // In the country's order, e.g. mm-dd-yy[yy], with '-', '/', '.' or
+        // its own separator between
+        let parts: Vec<u32> = args
+            .split(['-', '/', '.', country.date_separator])
+            .map_while(|part| part.parse().ok())
+            .collect();
+        let date = match parts[..] {
+            [a, b, c] => match country.date_parts([a, b, c]) {
+                (day, month, year @ 80..=99) => NaiveDate::from_ymd_opt(1900 + year as i32, month, day),
+                (day, month, year @ 0..=79) => NaiveDate::from_ymd_opt(2000 + year as i32, month, day),
+                (day, month, year @ 1980..=2099) => NaiveDate::from_ymd_opt(year as i32, month, day),
+                _ => None,
+            },
+            _ => None,
+        };
+        match date {
+            Some(date) => {
+                let time = cpu.bus.clock().now().time();
+                clock::set(&mut cpu.bus, date.and_time(time));
+            }
+            None => print_string(cpu, "Invalid date\r\n"),
+        }
+    }
+}
+
+struct TimeCommand;
+impl ShellCommand for TimeCommand {
+    fn execute(&self, cpu: &mut Cpu, args: &str) {
+        let args = args.trim();
+        let country = cpu.bus.nls.country();
+        if args.is_empty() {
+            let (sep, decimal) = (country.time_separator, country.decimal);
+            let format = format!("%H{sep}%M{sep}%S{decimal}%3f");
+            let now = cpu.bus.clock().now().format(&format).to_string();
+            // Hundredths, as DOS shows them
+            let now = &now[..now.len() - 1];
+            print_string(cpu, &format!("Current time is {}\r\n", now));
+            return;
+        }
+        // hh:mm[:ss[.xx]], with the country's separators also accepted
+        let parts: Vec<u32> = args
+            .split([':', '.', country.time_separator, country.decimal])
+            .map_while(|part| part.parse().ok())
+            .collect();
+        let time = match parts[..] {
+            [hour, minute] => NaiveTime::from_hms_opt(hour, minute, 0),
+            [hour, minute, second] => NaiveTime::from_hms_opt(hour, minute, second),
+            [hour, minute, second, hundredths @ 0..=99] => {
+                NaiveTime::from_hms_milli_opt(hour, minute, second, hundredths * 10)
+            }
+            _ => None,
+        };
+        match time {
+            Some(time) => {
+                let date = cpu.bus.clock().now().date();
+                clock::set(&mut cpu.bus, date.and_time(time));
+            }
+            None => print_string(cpu, "Invalid time\r\n"),
+        }
+    }
+}
@@ -1,10 +1,15 @@
 use bitflags::bitflags;
-use iced_x86::{Decoder, DecoderOptions, Instruction, MemorySize, Mnemonic, OpKind, Register};
+use iced_x86::{Instruction, MemorySize, Mnemonic, OpKind, Register};
 use std::collections::VecDeque;
 
 use crate::bus::Bus;
+use crate::crash::{self, TRACE_LOG_LEN, TraceEntry};
 use crate::f80::F80;
+use crate::handles;
+use crate::interrupts::hooks::{HleAction, HleHooks};
 use crate::instructions::utils::calculate_addr;
+use crate::lazy_flags::{FlagOp, LazyFlags};
+use crate::mcb::{self, Mcb};
 use crate::shell::get_shell_code;
 
 // FPU Tag Word Values
@@ -97,10 +102,11 @@ pub struct Cpu {
 
     pub bus: Bus,
     flags: CpuFlags,
+    lazy_flags: LazyFlags,
     pub state: CpuState,
     pub pending_command: Option<String>,
+    pub exit_code: Option<u8>, // Return code of the last program that terminated
     pub current_psp: u16,
-    pub heap_pointer: u16,
 
     // FPU State
     pub fpu_stack: [F80; 8],
@@ -108,15 +114,179 @@ pub struct Cpu {
     fpu_flags: FpuFlags,
     pub fpu_control: u16,
     pub fpu_tags: [u8; 8],
+    pub fpu_model: FpuModel,
+    pub fpu_error_line: FpuErrorLine,
+    // Set once an unmasked exception has been reported, until ES clears again
+    pub fpu_error_latched: bool,
 
     // REMOVEME: FLOAT DEBUGGING
     pub debug_qb_print: bool,
     pub last_fstp_addr: usize,
 
     // Execution Trace
-    pub trace_log: VecDeque<String>,
+    // Always-on history of the last instructions, for crash reports
+    pub trace_log: VecDeque<TraceEntry>,
+    // Location of the last crash report, so a repeating fault is reported once
+    pub last_crash: Option<(u16, u16)>,
+    pub trace_enabled: bool,
     pub process_stack: Vec<ProcessContext>,
-    pub last_timer_tick: u128,
+
+    // Host-side interrupt handlers
+    pub hle_hooks: HleHooks,
+
+    // Timing
+    pub model: CpuModel,
+    pub clock: ClockSpeed,
+    pub cycles: u64,
+}
+
+/// The CPU generation being emulated. Selects instruction timings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CpuModel {
+    I8088,
+    I8086,
+    I80186,
+    I80286,
+    I80386,
+}
+
+impl CpuModel {
+    /// Clock speed of the typical machine built around this CPU
+    pub fn nominal_hz(&self) -> u64 {
+        match self {
+            CpuModel::I8088 => 4_772_727,
+            CpuModel::I8086 => 8_000_000,
+            CpuModel::I80186 => 8_000_000,
+            CpuModel::I80286 => 12_000_000,
+            CpuModel::I80386 => 33_000_000,
+        }
+    }
+}
+
+impl std::str::FromStr for CpuModel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "8088" => Ok(CpuModel::I8088),
+            "8086" => Ok(CpuModel::I8086),
+            "186" | "80186" => Ok(CpuModel::I80186),
+            "286" | "80286" => Ok(CpuModel::I80286),
+            "386" | "80386" => Ok(CpuModel::I80386),
+            _ => Err(format!("Unknown CPU model: {}", s)),
+        }
+    }
+}
+
+/// The x87 coprocessor fitted to the machine. Selects which ESC opcodes exist.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FpuModel {
+    /// No coprocessor: ESC opcodes are ignored
+    None,
+    I8087,
+    I80287,
+    I80387,
+    /// P6-class integrated FPU: adds FCMOVcc, FCOMI, FXSAVE (and FISTTP from SSE3)
+    PentiumPro,
+}
+
+impl FpuModel {
+    /// The coprocessor normally paired with a given CPU
+    pub fn for_cpu(model: CpuModel) -> Self {
+        match model {
+            CpuModel::I8088 | CpuModel::I8086 | CpuModel::I80186 => FpuModel::I8087,
+            CpuModel::I80286 => FpuModel::I80287,
+            CpuModel::I80386 => FpuModel::I80387,
+        }
+    }
+}
+
+impl std::str::FromStr for FpuModel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(FpuModel::None),
+            "8087" => Ok(FpuModel::I8087),
+            "287" | "80287" => Ok(FpuModel::I80287),
+            "387" | "80387" => Ok(FpuModel::I80387),
+            "p6" | "ppro" => Ok(FpuModel::PentiumPro),
+            _ => Err(format!("Unknown FPU model: {}", s)),
+        }
+    }
+}
+
+/// How the coprocessor's error output reaches the CPU
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FpuErrorLine {
+    /// PC/XT: the 8087 INT pin is wired to NMI (INT 02h)
+    Nmi,
+    /// AT: ERROR# raises IRQ13 (INT 75h), which the BIOS reflects to INT 02h
+    Irq13,
+}
+
+impl FpuErrorLine {
+    pub fn for_cpu(model: CpuModel) -> Self {
+        match model {
+            CpuModel::I8088 | CpuModel::I8086 | CpuModel::I80186 => FpuErrorLine::Nmi,
+            CpuModel::I80286 | CpuModel::I80386 => FpuErrorLine::Irq13,
+        }
+    }
+}
+
+impl std::str::FromStr for FpuErrorLine {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "nmi" => Ok(FpuErrorLine::Nmi),
+            "irq13" => Ok(FpuErrorLine::Irq13),
+            _ => Err(format!("Unknown FPU error line: {}", s)),
+        }
+    }
+}
+
+/// Target emulation speed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockSpeed {
+    Hz(u64),
+    /// Run as fast as the host allows
+    Max,
+}
+
+impl ClockSpeed {
+    /// Clock used to convert cycles into device time.
+    /// When unthrottled, devices still tick as if running at the model's nominal speed.
+    pub fn effective_hz(&self, model: CpuModel) -> u64 {
+        match self {
+            ClockSpeed::Hz(hz) => *hz,
+            ClockSpeed::Max => model.nominal_hz(),
+        }
+    }
+}
+
+impl std::str::FromStr for ClockSpeed {
+    type Err = String;
+
+    // Accepts "max" or a frequency in MHz ("4.77", "8", "33")
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_ascii_lowercase();
+        if s == "max" {
+            return Ok(ClockSpeed::Max);
+        }
+        let mhz: f64 = s
+            .trim_end_matches("mhz")
+            .parse()
+            .map_err(|_| format!("Invalid clock speed: {}", s))?;
+        if mhz <= 0.0 {
+            return Err(format!("Invalid clock speed: {}", s));
+        }
+        // 4.77 is shorthand for the PC's 14.31818 MHz / 3 crystal
+        if (mhz - 4.77).abs() < 0.005 {
+            return Ok(ClockSpeed::Hz(4_772_727));
+        }
+        Ok(ClockSpeed::Hz((mhz * 1_000_000.0) as u64))
+    }
 }
 
 #[derive(PartialEq, Debug)]
@@ -125,6 +295,8 @@ pub enum CpuState {
     Running,
     Halted,
     RebootShell,
+    // The shell's EXIT command switched the machine off
+    PoweredOff,
 }
 
 #[derive(Debug, Clone)]
@@ -144,14 +316,16 @@ pub struct ProcessContext {
     pub ip: u16,
     pub flags: CpuFlags,
     pub psp: u16,
-    pub heap_pointer: u16,
 }
 
 use std::path::PathBuf;
 
+// PSP offsets of INT 22h, 23h and 24h as they were when the program started
+const PSP_EXIT_VECTORS: [(usize, usize); 3] = [(0x0A, 0x22), (0x0E, 0x23), (0x12, 0x24)];
+
 impl Cpu {
     pub fn new(root_path: PathBuf) -> Self {
-        Self {
+        let mut cpu = Self {
             ax: 0,
             bx: 0,
             cx: 0,
@@ -177,21 +351,65 @@ impl Cpu {
             ip: 0x100,
             bus: Bus::new(root_path),
             flags: CpuFlags::from_bits_truncate(0x0002), // Default Flag State, Bit 1 is always set
+            lazy_flags: LazyFlags::new(),
             state: CpuState::Running,
             pending_command: None,
+            exit_code: None,
             fpu_stack: [F80::new(); 8],
             fpu_top: 0,
             fpu_flags: FpuFlags::from_bits_truncate(0x0000),
             fpu_control: 0x037F, // Default Control Word
             fpu_tags: [FPU_TAG_EMPTY; 8],
+            fpu_model: FpuModel::I80387,
+            fpu_error_line: FpuErrorLine::Irq13,
+            fpu_error_latched: false,
             debug_qb_print: false,
             last_fstp_addr: 0,
-            trace_log: VecDeque::new(),
+            trace_log: VecDeque::with_capacity(TRACE_LOG_LEN),
+            last_crash: None,
+            trace_enabled: false,
             current_psp: 0, // Will be set by loader
-            heap_pointer: 0x2000,
             process_stack: Vec::new(),
-            last_timer_tick: 0,
+            hle_hooks: HleHooks::new(),
+            model: CpuModel::I80386,
+            clock: ClockSpeed::Hz(CpuModel::I80386.nominal_hz()),
+            cycles: 0,
+        };
+        cpu.install_bios_traps();
+        mcb::init(&mut cpu.bus);
+        handles::init(&mut cpu.bus);
+        cpu
+    }
+
+    // Charge cycles to the CPU and let the timers catch up
+    pub fn add_cycles(&mut self, cycles: u32) {
+        self.cycles += cycles as u64;
+        let hz = self.clock.effective_hz(self.model);
+        self.bus.tick_devices(cycles as u64, hz);
+    }
+
+    // Deliver the highest priority pending IRQ if interrupts are enabled.
+    // Returns true if CS:IP was redirected to a handler.
+    pub fn service_irqs(&mut self) -> bool {
+        if !self.bus.pic().has_request() {
+            return false;
+        }
+
+        // With IF clear the request stays latched until interrupts are re-enabled
+        if !self.get_cpu_flag(CpuFlags::IF) {
+            return false;
+        }
+
+        let Some(irq) = self.bus.pic_mut().acknowledge() else {
+            return false;
+        };
+        if self.state == CpuState::Halted {
+            self.state = CpuState::Running;
         }
+        // The slave PIC's lines start at INT 70h
+        let vector = if irq < 8 { 0x08 + irq } else { 0x70 + irq - 8 };
+        crate::interrupts::handle_interrupt(self, vector);
+        true
     }
 
     pub fn save_process_context(&mut self) {
@@ -209,9 +427,8 @@ impl Cpu {
             es: self.es,
             ss: self.ss,
             ip: self.ip,
-            flags: self.flags,
+            flags: self.get_cpu_flags(),
             psp: self.current_psp,
-            heap_pointer: self.heap_pointer,
         };
         self.process_stack.push(context);
         self.bus.log_string(&format!(
@@ -235,9 +452,8 @@ impl Cpu {
             self.es = context.es;
             self.ss = context.ss;
             self.ip = context.ip;
-            self.flags = context.flags;
+            self.set_cpu_flags(context.flags);
             self.current_psp = context.psp;
-            self.heap_pointer = context.heap_pointer; // Restore heap specifically for that process? Maybe not... but safer.
             self.bus.log_string(&format!(
                 "[CPU] Context Restored. Stack Depth: {}",
                 self.process_stack.len()
@@ -249,39 +465,115 @@ impl Cpu {
         }
     }
 
-    // ... step ...
+    /// INT 22h, 23h and 24h (terminate address, Ctrl-C and critical error) as
+    /// the IVT holds them now
+    pub fn exit_vectors(&self) -> [u32; 3] {
+        PSP_EXIT_VECTORS.map(|(_, vector)| self.bus.read_32(vector * 4))
+    }
 
-    pub fn step(&mut self) {
-        if self.state != CpuState::Running {
+    pub fn set_exit_vectors(&mut self, vectors: [u32; 3]) {
+        for ((_, vector), value) in PSP_EXIT_VECTORS.into_iter().zip(vectors) {
+            self.bus.write_32(vector * 4, value);
+        }
+    }
+
+    /// Keeps the vectors a program starts with in its PSP
+    pub fn save_exit_vectors(&mut self, psp: u16, vectors: [u32; 3]) {
+        let psp_phys = self.get_physical_addr(psp, 0);
+        for ((offset, _), value) in PSP_EXIT_VECTORS.into_iter().zip(vectors) {
+            self.bus.write_32(psp_phys + offset, value);
+        }
+    }
+
+    /// Puts back the vectors the current program started with, as DOS does when
+    /// it ends, so nothing points into the memory it's about to lose
+    pub fn restore_exit_vectors(&mut self) {
+        if self.current_psp == 0 {
             return;
         }
+        let psp_phys = self.get_physical_addr(self.current_psp, 0);
+        let vectors = PSP_EXIT_VECTORS.map(|(offset, _)| self.bus.read_32(psp_phys + offset));
+        self.set_exit_vectors(vectors);
+    }
 
-        // Timer Check (Approx 18.2 Hz -> ~55ms)
-        let now = self.bus.start_time.elapsed().as_millis();
-        if now - self.last_timer_tick >= 55 {
-            self.last_timer_tick = now;
+    // Remember the instruction at CS:IP (not executed yet) for crash reports
+    pub fn record_trace(&mut self, phys_ip: usize, len: usize) {
+        if self.trace_log.len() == TRACE_LOG_LEN {
+            self.trace_log.pop_front();
+        }
 
-            // println!("[DEBUG] Injecting INT 08h");
+        let mut bytes = [0u8; 15];
+        let len = len.min(bytes.len());
+        for (i, byte) in bytes.iter_mut().take(len).enumerate() {
+            *byte = self.bus.read_8(phys_ip + i);
+        }
 
-            // Inject INT 08h (Timer)
-            let ivt_offset = 0x08 * 4;
-            let handler_ip = self.bus.read_16(ivt_offset as usize);
-            let handler_cs = self.bus.read_16((ivt_offset + 2) as usize);
+        self.trace_log.push_back(TraceEntry {
+            cs: self.cs,
+            ip: self.ip,
+            bytes,
+            len: len as u8,
+            ax: self.ax,
+            bx: self.bx,
+            cx: self.cx,
+            dx: self.dx,
+            si: self.si,
+            di: self.di,
+            bp: self.bp,
+            sp: self.sp,
+            ds: self.ds,
+            es: self.es,
+            ss: self.ss,
+        });
+    }
 
-            // Push Flags, CS, IP
-            self.push(self.flags.bits());
-            self.push(self.cs);
-            self.push(self.ip);
+    // Write the crash report to the log, once per faulting location
+    pub fn dump_crash_report(&mut self, reason: &str) {
+        let location = crash::crash_location(self);
+        if self.last_crash == Some(location) {
+            return;
+        }
+        self.last_crash = Some(location);
 
-            // Jump to Handler
-            self.ip = handler_ip;
-            self.cs = handler_cs;
+        let report = crash::report(self, reason);
+        for line in report.lines() {
+            self.bus.log_string(line);
+        }
+        if let Some(writer) = self.bus.log_file.as_mut() {
+            let _ = std::io::Write::flush(writer);
+        }
+    }
 
-            // Disable Interrupts (IF=0) and Trap Flag (TF=0)
-            self.set_cpu_flag(CpuFlags::IF, false);
-            self.set_cpu_flag(CpuFlags::TF, false);
+    // Executing 00 00 (ADD [BX+SI],AL) nearly always means a jump into empty memory.
+    // Reported when entering such a run, not for every instruction of it.
+    pub fn check_zeroed_memory(&mut self, b0: u8, b1: u8) {
+        if b0 != 0x00 || b1 != 0x00 {
+            return;
+        }
+        // The newest entry is this instruction, look at the one before
+        let in_run = self
+            .trace_log
+            .iter()
+            .rev()
+            .nth(1)
+            .is_some_and(|entry| entry.bytes().starts_with(&[0x00, 0x00]));
+        if !in_run {
+            self.dump_crash_report("Executing zeroed memory");
+        }
+    }
 
-            // We changed CS:IP, so we should return to fetch from new location
+    // ... step ...
+
+    pub fn step(&mut self) {
+        // Timer and device interrupts
+        self.service_irqs();
+
+        if self.state == CpuState::Halted {
+            // Let time pass until an interrupt wakes us up
+            self.add_cycles(crate::instructions::timing::HALT_QUANTUM);
+            return;
+        }
+        if self.state != CpuState::Running {
             return;
         }
 
@@ -302,9 +594,11 @@ impl Cpu {
             let vector = self
                 .bus
                 .read_8(self.get_physical_addr(self.cs, self.ip.wrapping_add(2)));
+            self.record_trace(phys_ip, 3);
 
             // Run the HLE handler
-            crate::interrupts::handle_hle(self, vector);
+            crash::guard(self, |cpu| crate::interrupts::handle_hle(cpu, vector));
+            self.add_cycles(crate::instructions::timing::hle_cycles(self.model));
 
             // Simulate IRET
             self.ip = self.pop();
@@ -321,20 +615,72 @@ impl Cpu {
             return;
         }
 
+        // Far-call flavour -> FE 39 XX, for driver entry points reached with CALL FAR
+        if b0 == 0xFE && b1 == 0x39 {
+            let service = self
+                .bus
+                .read_8(self.get_physical_addr(self.cs, self.ip.wrapping_add(2)));
+            self.record_trace(phys_ip, 3);
+
+            crash::guard(self, |cpu| crate::interrupts::handle_hle_call(cpu, service));
+            self.add_cycles(crate::instructions::timing::hle_cycles(self.model));
+
+            // Simulate RETF
+            self.ip = self.pop();
+            self.cs = self.pop();
+            return;
+        }
+
         // Decode
-        // We slice safe
-        let bytes = &self.bus.ram[phys_ip..];
-        let mut decoder = Decoder::with_ip(16, bytes, self.ip as u64, DecoderOptions::NONE);
-        let instr = decoder.decode();
+        let instr = self.bus.fetch_instruction(phys_ip, self.ip);
+        self.record_trace(phys_ip, instr.len());
+        self.check_zeroed_memory(b0, b1);
 
-        let disasm = format!("{:04X}:{:04X} {}", self.cs, self.ip, instr);
-        self.bus.log_trace(&disasm);
+        if self.trace_enabled || self.debug_qb_print {
+            self.log_instruction(&instr);
+        }
+        self.trace_qb_conversion(&instr);
 
         // Update IP
         self.ip = instr.next_ip() as u16;
 
         // Execute
-        crate::instructions::execute_instruction(self, &instr);
+        crash::guard(self, |cpu| crate::instructions::timing::execute_timed(cpu, &instr));
+    }
+
+    // Debug trace (F12): one line per instruction with the main registers
+    fn log_instruction(&mut self, instr: &Instruction) {
+        // Filter out the 'Wait for Key' interrupt loop to save disk space
+        if (instr.mnemonic() == Mnemonic::Int && instr.immediate8() == 0x16)
+            || (instr.mnemonic() == Mnemonic::Jmp && instr.near_branch16() == 0x10E)
+        {
+            return;
+        }
+        // Skip BIOS area noise
+        if self.cs >= 0xF000 {
+            return;
+        }
+
+        let log_line = format!(
+            "{:04X}:{:04X}  AX:{:04X} BX:{:04X} CX:{:04X} DX:{:04X} SP:{:04X}  {}",
+            self.cs, self.ip, self.ax, self.bx, self.cx, self.dx, self.sp, instr
+        );
+        self.bus.log_string(&log_line);
+
+        if instr.mnemonic() == Mnemonic::Int {
+            let vector = instr.immediate8();
+            // Read IVT (Vector * 4) to find where this points
+            let ivt_addr = (vector as usize) * 4;
+            let target_cs = self.bus.read_16(ivt_addr + 2);
+            let target_ip = self.bus.read_16(ivt_addr);
+
+            if target_cs == 0xF000 {
+                self.bus.log_string(&format!(
+                    "[CPU-DEBUG] Hooked INT {:02X} detected -> Points to F000:{:04X}",
+                    vector, target_ip
+                ));
+            }
+        }
     }
 
     // REMOVEME: Debugging QuickBASIC Float Conversion Issues
@@ -454,7 +800,49 @@ impl Cpu {
 
     // Helper to get a flag state
     pub fn get_cpu_flag(&self, mask: CpuFlags) -> bool {
-        (self.flags & mask) != CpuFlags::empty()
+        (self.get_cpu_flags() & mask) != CpuFlags::empty()
+    }
+
+    // Record an ALU operation whose flags are computed on demand
+    fn set_lazy_flags(&mut self, op: FlagOp, dest: u16, src: u16, result: u16, carry_in: bool) {
+        // A pending operation that defines flags the new one doesn't must be resolved first
+        if self.lazy_flags.is_pending()
+            && !LazyFlags::mask(op).contains(LazyFlags::mask(self.lazy_flags.op))
+        {
+            self.materialize_flags();
+        }
+        self.lazy_flags = LazyFlags {
+            op,
+            dest,
+            src,
+            result,
+            carry_in,
+        };
+    }
+
+    // Fold any pending lazy flags into the flags register
+    fn materialize_flags(&mut self) {
+        if self.lazy_flags.is_pending() {
+            self.flags = self.lazy_flags.resolve(self.flags);
+            self.lazy_flags = LazyFlags::new();
+        }
+    }
+
+    // Flags for AND/OR/XOR/TEST: CF and OF cleared, AF left alone
+    pub fn set_logic_flags(&mut self, result: u16, is_8bit: bool) {
+        let op = if is_8bit { FlagOp::Logic8 } else { FlagOp::Logic16 };
+        self.set_lazy_flags(op, 0, 0, result, false);
+    }
+
+    // Flags for INC/DEC: CF is preserved
+    pub fn set_inc_dec_flags(&mut self, dest: u16, result: u16, is_8bit: bool, inc: bool) {
+        let op = match (inc, is_8bit) {
+            (true, true) => FlagOp::Inc8,
+            (true, false) => FlagOp::Inc16,
+            (false, true) => FlagOp::Dec8,
+            (false, false) => FlagOp::Dec16,
+        };
+        self.set_lazy_flags(op, dest, 1, result, false);
     }
 
     // Helper to set/clear a flag
@@ -473,6 +861,7 @@ impl Cpu {
         //     }
         // }
 
+        self.materialize_flags();
         if value {
             self.flags.insert(mask);
         } else {
@@ -501,10 +890,11 @@ impl Cpu {
         let sanitized_bits = (raw_bits & 0x0FD5) | 0x0002;
 
         self.flags = CpuFlags::from_bits_truncate(sanitized_bits);
+        self.lazy_flags = LazyFlags::new();
     }
 
     pub fn get_cpu_flags(&self) -> CpuFlags {
-        self.flags
+        self.lazy_flags.resolve(self.flags)
     }
 
     pub fn set_fpu_flag(&mut self, flag: FpuFlags, value: bool) {
@@ -554,8 +944,8 @@ impl Cpu {
     // Calculate Physical Address from Segment:Offset
     pub fn get_physical_addr(&self, segment: u16, offset: u16) -> usize {
         let phys_addr = (segment as usize * 16) + offset as usize;
-        // MASK TO 20 BITS to emulate 8086 wrap-around
-        phys_addr & 0xFFFFF
+        // Wraps at 1MB unless A20 is enabled, which opens up the HMA
+        phys_addr & self.bus.a20_mask()
     }
 
     /// Helper to read the first operand (Destination).
@@ -697,220 +1087,61 @@ impl Cpu {
 
     // ADD 16 bit
     pub fn alu_add_16(&mut self, dest: u16, src: u16) -> u16 {
-        let (result, carry) = dest.overflowing_add(src);
-
-        self.set_cpu_flag(CpuFlags::CF, carry);
-        self.set_cpu_flag(CpuFlags::ZF, result == 0);
-        self.set_cpu_flag(CpuFlags::SF, (result & 0x8000) != 0); // High bit set?
-
-        self.update_pf(result);
-
-        // Overflow (Signed): if operands have same sign, but result has diff sign
-        let op1_sign = (dest & 0x8000) != 0;
-        let op2_sign = (src & 0x8000) != 0;
-        let res_sign = (result & 0x8000) != 0;
-        let overflow = (op1_sign == op2_sign) && (res_sign != op1_sign);
-        self.set_cpu_flag(CpuFlags::OF, overflow);
-        self.set_cpu_flag(CpuFlags::AF, ((dest ^ src ^ result) & 0x10) != 0);
-
+        let result = dest.wrapping_add(src);
+        self.set_lazy_flags(FlagOp::Add16, dest, src, result, false);
         result
     }
 
     // SUB (and CMP) 16 bit
     pub fn alu_sub_16(&mut self, dest: u16, src: u16) -> u16 {
-        let (result, borrow) = dest.overflowing_sub(src);
-
-        self.set_cpu_flag(CpuFlags::CF, borrow); // In SUB, CF acts as Borrow
-        self.set_cpu_flag(CpuFlags::ZF, result == 0);
-        self.set_cpu_flag(CpuFlags::SF, (result & 0x8000) != 0);
-
-        self.update_pf(result);
-
-        // Overflow (Signed): operands diff sign, result diff sign from dest
-        let op1_sign = (dest & 0x8000) != 0;
-        let op2_sign = (src & 0x8000) != 0;
-        let res_sign = (result & 0x8000) != 0;
-        let overflow = (op1_sign != op2_sign) && (res_sign != op1_sign);
-        self.set_cpu_flag(CpuFlags::OF, overflow);
-        self.set_cpu_flag(CpuFlags::AF, ((dest ^ src ^ result) & 0x10) != 0);
-
+        let result = dest.wrapping_sub(src);
+        self.set_lazy_flags(FlagOp::Sub16, dest, src, result, false);
         result
     }
 
     // SUB/CMP 8-bit
     pub fn alu_sub_8(&mut self, dest: u8, src: u8) -> u8 {
-        let (result, borrow) = dest.overflowing_sub(src);
-
-        self.set_cpu_flag(CpuFlags::CF, borrow);
-        self.set_cpu_flag(CpuFlags::ZF, result == 0);
-        self.set_cpu_flag(CpuFlags::SF, (result & 0x80) != 0); // Check Bit 7
-
-        self.update_pf(result as u16);
-
-        // 8-bit overflow (signed)
-        let op1_sign = (dest & 0x80) != 0;
-        let op2_sign = (src & 0x80) != 0;
-        let res_sign = (result & 0x80) != 0;
-        let overflow = (op1_sign != op2_sign) && (res_sign != op1_sign);
-        self.set_cpu_flag(CpuFlags::OF, overflow);
-        self.set_cpu_flag(CpuFlags::AF, ((dest ^ src ^ result) & 0x10) != 0);
-
+        let result = dest.wrapping_sub(src);
+        self.set_lazy_flags(FlagOp::Sub8, dest as u16, src as u16, result as u16, false);
         result
     }
 
     // ADD 8-bit
     pub fn alu_add_8(&mut self, dest: u8, src: u8) -> u8 {
-        let (result, carry) = dest.overflowing_add(src);
-
-        self.set_cpu_flag(CpuFlags::CF, carry);
-        self.set_cpu_flag(CpuFlags::ZF, result == 0);
-        self.set_cpu_flag(CpuFlags::SF, (result & 0x80) != 0);
-
-        self.update_pf(result as u16);
-
-        // 8-bit overflow (signed)
-        let op1_sign = (dest & 0x80) != 0;
-        let op2_sign = (src & 0x80) != 0;
-        let res_sign = (result & 0x80) != 0;
-        let overflow = (op1_sign == op2_sign) && (res_sign != op1_sign);
-        self.set_cpu_flag(CpuFlags::OF, overflow);
-        self.set_cpu_flag(CpuFlags::AF, ((dest ^ src ^ result) & 0x10) != 0);
-
+        let result = dest.wrapping_add(src);
+        self.set_lazy_flags(FlagOp::Add8, dest as u16, src as u16, result as u16, false);
         result
     }
 
     // SBB 8-bit
     pub fn alu_sbb_8(&mut self, dest: u8, src: u8) -> u8 {
-        let carry_in = if self.get_cpu_flag(CpuFlags::CF) {
-            1
-        } else {
-            0
-        };
-
-        // We perform the math using u16 to easily detect borrows
-        let result_wide = (dest as u16)
-            .wrapping_sub(src as u16)
-            .wrapping_sub(carry_in as u16);
-        let result = result_wide as u8;
-
-        // Flags
-        self.set_cpu_flag(CpuFlags::ZF, result == 0);
-        self.set_cpu_flag(CpuFlags::SF, (result & 0x80) != 0);
-
-        self.update_pf(result as u16);
-
-        // Carry (Borrow) happens if the result wrapped (result_wide > 0xFF)
-        self.set_cpu_flag(CpuFlags::CF, result_wide > 0xFF);
-
-        // Overflow for subtraction: (dest_sign != src_sign) && (dest_sign != result_sign)
-        let res_sign = (result & 0x80) != 0;
-        let src_sign = (src & 0x80) != 0;
-        let dest_sign = (dest & 0x80) != 0;
-
-        self.set_cpu_flag(
-            CpuFlags::OF,
-            (dest_sign != src_sign) && (dest_sign != res_sign),
-        );
-        self.set_cpu_flag(CpuFlags::AF, (dest & 0x0F) < ((src & 0x0F) + carry_in));
-
+        let carry_in = self.get_cpu_flag(CpuFlags::CF);
+        let result = dest.wrapping_sub(src).wrapping_sub(carry_in as u8);
+        self.set_lazy_flags(FlagOp::Sbb8, dest as u16, src as u16, result as u16, carry_in);
         result
     }
 
     // SBB 16-bit
     pub fn alu_sbb_16(&mut self, dest: u16, src: u16) -> u16 {
-        let carry_in = if self.get_cpu_flag(CpuFlags::CF) {
-            1
-        } else {
-            0
-        };
-
-        // Use u32 to capture borrows
-        let result_wide = (dest as u32)
-            .wrapping_sub(src as u32)
-            .wrapping_sub(carry_in as u32);
-        let result = result_wide as u16;
-
-        self.set_cpu_flag(CpuFlags::ZF, result == 0);
-        self.set_cpu_flag(CpuFlags::SF, (result & 0x8000) != 0);
-
-        self.update_pf(result);
-
-        // Carry flag if we wrapped past 0
-        self.set_cpu_flag(CpuFlags::CF, result_wide > 0xFFFF);
-
-        // Set OF if the sign of the destination was different from the source,
-        // AND the sign of the result is different from the destination.
-        let dest_s = (dest & 0x8000) != 0;
-        let src_s = (src & 0x8000) != 0;
-        let res_s = (result & 0x8000) != 0;
-        self.set_cpu_flag(CpuFlags::OF, (dest_s != src_s) && (res_s != dest_s));
-        self.set_cpu_flag(CpuFlags::AF, ((dest ^ src ^ result) & 0x10) != 0);
-
+        let carry_in = self.get_cpu_flag(CpuFlags::CF);
+        let result = dest.wrapping_sub(src).wrapping_sub(carry_in as u16);
+        self.set_lazy_flags(FlagOp::Sbb16, dest, src, result, carry_in);
         result
     }
 
     // ADC 8-bit
     pub fn alu_adc_8(&mut self, dest: u8, src: u8) -> u8 {
-        let cf_in = if self.get_cpu_flag(CpuFlags::CF) {
-            1
-        } else {
-            0
-        };
-
-        // Use u16 to capture the carry out
-        let res_wide = (dest as u16) + (src as u16) + (cf_in as u16);
-        let result = res_wide as u8;
-
-        self.set_cpu_flag(CpuFlags::CF, res_wide > 0xFF);
-        self.set_cpu_flag(CpuFlags::ZF, result == 0);
-        self.set_cpu_flag(CpuFlags::SF, (result & 0x80) != 0);
-        self.update_pf(result as u16);
-
-        // Overflow (Signed)
-        let op1_sign = (dest & 0x80) != 0;
-        let op2_sign = (src & 0x80) != 0;
-        let res_sign = (result & 0x80) != 0;
-        // Overflow happens if adding two numbers of same sign results in different sign
-        self.set_cpu_flag(
-            CpuFlags::OF,
-            (op1_sign == op2_sign) && (res_sign != op1_sign),
-        );
-
-        // AF: (op1 ^ op2 ^ result) & 0x10
-        // This detects if a carry occurred from bit 3 to bit 4
-        self.set_cpu_flag(CpuFlags::AF, ((dest ^ src ^ result) & 0x10) != 0);
+        let carry_in = self.get_cpu_flag(CpuFlags::CF);
+        let result = dest.wrapping_add(src).wrapping_add(carry_in as u8);
+        self.set_lazy_flags(FlagOp::Adc8, dest as u16, src as u16, result as u16, carry_in);
         result
     }
 
     // ADC 16-bit
     pub fn alu_adc_16(&mut self, dest: u16, src: u16) -> u16 {
-        let cf_in = if self.get_cpu_flag(CpuFlags::CF) {
-            1
-        } else {
-            0
-        };
-
-        // Use u32 to capture carry out
-        let res_wide = (dest as u32) + (src as u32) + (cf_in as u32);
-        let result = res_wide as u16;
-
-        self.set_cpu_flag(CpuFlags::CF, res_wide > 0xFFFF);
-        self.set_cpu_flag(CpuFlags::ZF, result == 0);
-        self.set_cpu_flag(CpuFlags::SF, (result & 0x8000) != 0);
-        self.update_pf(result);
-
-        // Overflow (Signed)
-        let op1_sign = (dest & 0x8000) != 0;
-        let op2_sign = (src & 0x8000) != 0;
-        let res_sign = (result & 0x8000) != 0;
-        self.set_cpu_flag(
-            CpuFlags::OF,
-            (op1_sign == op2_sign) && (res_sign != op1_sign),
-        );
-
-        // AF: Carry from bit 3 to 4
-        self.set_cpu_flag(CpuFlags::AF, ((dest ^ src ^ result) & 0x10) != 0);
-
+        let carry_in = self.get_cpu_flag(CpuFlags::CF);
+        let result = dest.wrapping_add(src).wrapping_add(carry_in as u16);
+        self.set_lazy_flags(FlagOp::Adc16, dest, src, result, carry_in);
         result
     }
 
@@ -986,45 +1217,97 @@ impl Cpu {
     }
 
     pub fn load_int_to_f80(&self, addr: usize, size: MemorySize) -> F80 {
-        let (val, neg) = match size {
-            MemorySize::Int16 => {
-                let v = self.bus.read_16(addr) as i16;
-                (v.abs() as u128, v < 0)
-            }
-            MemorySize::Int32 => {
-                let v = self.bus.read_32(addr) as i32;
-                (v.abs() as u128, v < 0)
-            }
-            _ => (0, false),
+        let val = match size {
+            MemorySize::Int16 => self.bus.read_16(addr) as i16 as i64,
+            MemorySize::Int32 => self.bus.read_32(addr) as i32 as i64,
+            MemorySize::Int64 => self.bus.read_64(addr) as i64,
+            _ => 0,
         };
 
-        let mut f = F80::new();
-        f.st = F80::encode_from_u128(val, neg);
-        f
+        F80::from_i64(val)
     }
 
-    fn install_bios_traps(&mut self) {
-        let mut phys_addr = 0xF1000;
-        let hle_vectors = vec![
-            0x08, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x1A, 0x20, 0x21, 0x2F, 0x33,
-        ];
+    /// Attach a host handler to every call of `vector`.
+    /// It runs before handlers registered earlier and before the built-in one.
+    pub fn hook_interrupt(
+        &mut self,
+        vector: u8,
+        handler: impl FnMut(&mut Cpu) -> HleAction + 'static,
+    ) {
+        self.hle_hooks.add(vector, None, Box::new(handler));
+        self.bus.install_hle_trap(vector);
+    }
 
-        for vec in hle_vectors {
-            let ivt_offset = (vec as usize) * 4;
-            let handler_offset = (phys_addr & 0xFFFF) as u16;
+    /// Attach a host handler to one AH function of `vector`
+    pub fn hook_interrupt_function(
+        &mut self,
+        vector: u8,
+        ah: u8,
+        handler: impl FnMut(&mut Cpu) -> HleAction + 'static,
+    ) {
+        self.hle_hooks.add(vector, Some(ah), Box::new(handler));
+        self.bus.install_hle_trap(vector);
+    }
+
+    fn install_bios_traps(&mut self) {
+        for &vector in crate::interrupts::BIOS_VECTORS {
+            self.bus.install_hle_trap(vector);
+        }
+        crate::interrupts::int67::install_driver(&mut self.bus);
+        for vector in self.hle_hooks.hooked_vectors() {
+            self.bus.install_hle_trap(vector);
+        }
 
-            // Point IVT to F000:Offset
-            self.bus.write_16(ivt_offset, handler_offset); // IP
-            self.bus.write_16(ivt_offset + 2, 0xF000); // CS
+        // INT 70h (IRQ8, RTC) reads register C to find out why, calls the user
+        // alarm hook INT 4Ah on an alarm and acknowledges both PICs.
+        // INT 4Ah itself is just an IRET until someone hooks it.
+        // F000:0F08  CF           IRET
+        // F000:0F10  50           PUSH AX
+        //            B0 0C        MOV AL, 0Ch
+        //            E6 70        OUT 70h, AL
+        //            E4 71        IN AL, 71h
+        //            A8 20        TEST AL, 20h
+        //            74 02        JZ +2
+        //            CD 4A        INT 4Ah
+        //            B0 20        MOV AL, 20h
+        //            E6 A0        OUT A0h, AL
+        //            E6 20        OUT 20h, AL
+        //            58           POP AX
+        //            CF           IRET
+        let alarm_stub = 0xF0F08;
+        let irq8_stub = 0xF0F10;
+        self.bus.write_rom(alarm_stub, &[0xCF]);
+        self.bus.write_rom(
+            irq8_stub,
+            &[
+                0x50, 0xB0, 0x0C, 0xE6, 0x70, 0xE4, 0x71, 0xA8, 0x20, 0x74, 0x02, 0xCD, 0x4A,
+                0xB0, 0x20, 0xE6, 0xA0, 0xE6, 0x20, 0x58, 0xCF,
+            ],
+        );
 
-            // Ensure the Trap Instruction exists (FE 38 XX CF)
-            self.bus.write_8(phys_addr, 0xFE);
-            self.bus.write_8(phys_addr + 1, 0x38);
-            self.bus.write_8(phys_addr + 2, vec);
-            self.bus.write_8(phys_addr + 3, 0xCF);
+        // INT 75h (IRQ13, coprocessor error) is plain code in the AT BIOS:
+        // it acknowledges both PICs and reflects the error to the NMI handler.
+        // F000:0F48  50     PUSH AX
+        //            B0 20  MOV AL, 20h
+        //            E6 A0  OUT A0h, AL
+        //            E6 20  OUT 20h, AL
+        //            58     POP AX
+        //            CD 02  INT 02h
+        //            CF     IRET
+        let irq13_stub = 0xF0F48;
+        self.bus.write_rom(
+            irq13_stub,
+            &[0x50, 0xB0, 0x20, 0xE6, 0xA0, 0xE6, 0x20, 0x58, 0xCD, 0x02, 0xCF],
+        );
 
-            phys_addr += 4;
+        for (vector, stub) in [(0x4A, alarm_stub), (0x70, irq8_stub), (0x75, irq13_stub)] {
+            self.bus.write_16(vector * 4, (stub & 0xFFFF) as u16);
+            self.bus.write_16(vector * 4 + 2, 0xF000);
         }
+
+        crate::interrupts::xms::install_entry(&mut self.bus);
+        crate::nls::install(&mut self.bus);
+        crate::interrupts::int24::install(&mut self.bus);
     }
 
     pub fn load_shell(&mut self) {
@@ -1042,9 +1325,19 @@ impl Cpu {
         for i in 0x0500..0xFFFF {
             self.bus.ram[i] = 0;
         }
+        // RAM was written directly, bypassing the decode cache invalidation
+        self.bus.decode_cache.clear();
 
         // Re-install the HLE Interrupt Vectors
         self.install_bios_traps();
+        mcb::init(&mut self.bus);
+        // What POST would have found, in CMOS
+        let (base_kb, extended_kb) = (self.bus.read_16(0x0413), self.bus.extended_kb());
+        let fpu = self.fpu_model != FpuModel::None;
+        self.bus.rtc_mut().set_configuration(base_kb, extended_kb, fpu);
+        // The shell isn't a process; it gets the standard handles again
+        handles::init(&mut self.bus);
+        self.current_psp = 0;
 
         // DOS "Underscore" cursor
         // High Byte (0x06) = Start Scanline, Low Byte (0x07) = End Scanline
@@ -1071,7 +1364,7 @@ impl Cpu {
         self.si = 0;
         self.di = 0;
 
-        self.flags = CpuFlags::from_bits_truncate(0x0002); // Reset Flags
+        self.set_cpu_flags(CpuFlags::from_bits_truncate(0x0002)); // Reset Flags
         self.state = CpuState::Running;
 
         self.bus.log_string("[SYSTEM] Shell Loaded. Ready.");
@@ -1104,21 +1397,111 @@ impl Cpu {
         ));
 
         // Check for EXE Signature ("MZ")
-        if bytes.len() > 2 && bytes[0] == 0x4D && bytes[1] == 0x5A {
-            return self.load_exe(&bytes, segment);
+        let loaded = if bytes.len() > 2 && bytes[0] == 0x4D && bytes[1] == 0x5A {
+            self.load_exe(&bytes, segment)
         } else {
-            return self.load_com(&bytes, segment);
+            self.load_com(&bytes, segment)
+        };
+
+        // The program's name goes in its memory block, as DOS 4+ does
+        if loaded {
+            let base = filename.rsplit(['\\', '/', ':']).next().unwrap_or(filename);
+            let stem = base.split('.').next().unwrap_or(base);
+            mcb::set_name(&mut self.bus, self.current_psp, stem);
         }
+        loaded
+    }
+
+    /// LOADHIGH: loads a top-level program into the largest upper memory block,
+    /// leaving conventional memory free. Falls back to a normal load when no UMB
+    /// is big enough.
+    pub fn load_high(&mut self, filename: &str) -> bool {
+        if self.bus.disk.resolve_path(filename).is_none() {
+            return false;
+        }
+        mcb::init(&mut self.bus);
+        handles::init(&mut self.bus);
+        let umb = match mcb::allocate_upper(&mut self.bus, 0xFFFF, mcb::OWNER_DOS) {
+            Err((_, largest)) if largest > 0 => {
+                mcb::allocate_upper(&mut self.bus, largest, mcb::OWNER_DOS).ok()
+            }
+            _ => None,
+        };
+        if let Some(segment) = umb {
+            if self.load_executable(filename, Some(segment)) {
+                return true;
+            }
+            let _ = mcb::free(&mut self.bus, segment);
+            self.bus
+                .log_string(&format!("[DOS] {} doesn't fit in upper memory", filename));
+        }
+        self.load_executable(filename, None)
+    }
+
+    // Gives a new program its JFT. A top-level program starts with just the
+    // standard handles; a child inherits from the current process.
+    fn start_handles(&mut self, psp: u16, top_level: bool) {
+        let parent = if top_level {
+            handles::init(&mut self.bus);
+            0
+        } else {
+            self.current_psp
+        };
+        handles::init_psp(&mut self.bus, psp, parent);
+    }
+
+    // Finds the memory block a program loads into: a fresh arena and all of it
+    // for a top-level program, or the block EXEC allocated, trimmed to `max_paras`.
+    // Returns the block's segment and the segment just past it.
+    fn claim_program_block(
+        &mut self,
+        segment: Option<u16>,
+        min_paras: u16,
+        max_paras: u16,
+    ) -> Option<(u16, u16)> {
+        let segment = match segment {
+            Some(segment) => segment,
+            None => {
+                mcb::init(&mut self.bus);
+                let all = mcb::largest_free(&mut self.bus);
+                mcb::allocate(&mut self.bus, all, mcb::OWNER_DOS, mcb::FIRST_FIT).ok()?
+            }
+        };
+        mcb::set_owner(&mut self.bus, segment, segment);
+        let size = Mcb::read(&self.bus, segment - 1).ok()?.size;
+        if size < min_paras {
+            self.bus.log_string(&format!(
+                "[DOS] Not enough memory: {:04X} paras needed, block has {:04X}",
+                min_paras, size
+            ));
+            return None;
+        }
+        if size > max_paras {
+            mcb::resize(&mut self.bus, segment, max_paras).ok()?;
+        }
+        let block = Mcb::read(&self.bus, segment - 1).ok()?;
+        Some((segment, block.end()))
     }
 
     // COM loader
     fn load_com(&mut self, bytes: &[u8], segment: Option<u16>) -> bool {
-        let load_segment = segment.unwrap_or(0x1000);
         let start_offset = 0x100; // COM files always start at 100h
 
-        // Clear 64KB of RAM segment for safety (simulating clean load)
+        // A COM program gets its whole block; it needs room for the PSP, the image and a stack
+        let min_paras = (0x200 + bytes.len()).div_ceil(16).min(0x1000) as u16;
+        let Some((load_segment, block_end)) =
+            self.claim_program_block(segment, min_paras, 0xFFFF)
+        else {
+            return false;
+        };
+        let segment_bytes = ((block_end - load_segment) as usize * 16).min(0x10000);
+
+        // The image is copied straight into RAM below
+        self.bus.decode_cache.clear();
+
+        // Clear the RAM segment for safety (simulating clean load)
         let phys_start_seg = self.get_physical_addr(load_segment, 0);
-        for i in 0..0x10000 {
+        for i in 0..segment_bytes {
             if phys_start_seg + i < self.bus.ram.len() {
                 self.bus.ram[phys_start_seg + i] = 0;
             }
@@ -1141,7 +1524,8 @@ impl Cpu {
         self.es = load_segment;
         self.ss = load_segment; // Stack is in the same segment
         self.ip = 0x100; // Entry Point
-        self.sp = 0xFFFE; // End of segment (64KB - 2)
+        self.sp = (segment_bytes - 2) as u16; // End of segment (64KB - 2 unless the block is smaller)
+        self.set_cpu_flag(CpuFlags::IF, true); // DOS starts programs with interrupts enabled
 
         // Setup PSP (Program Segment Prefix) at CS:0000
         let psp_phys = self.get_physical_addr(load_segment, 0);
@@ -1151,10 +1535,8 @@ impl Cpu {
         self.bus.write_8(psp_phys + 1, 0x20);
 
         // Offset 0x02: Top of Memory (Segment)
-        // 0xA000 corresponds to 640KB (standard DOS conventional memory limit)
-        // We write it in Little Endian (00 A0)
-        self.bus.write_8(psp_phys + 2, 0x00);
-        self.bus.write_8(psp_phys + 3, 0xA0);
+        // The first segment past the program's memory block
+        self.bus.write_16(psp_phys + 2, block_end);
 
         // [0x06] Bytes in Segment (CP/M compatibility)
         self.bus.write_8(psp_phys + 6, 0x03);
@@ -1185,6 +1567,8 @@ impl Cpu {
 
         // Point PSP to this environment
         self.bus.write_16(psp_phys + 0x2C, env_seg);
+        self.save_exit_vectors(load_segment, self.exit_vectors());
+        self.start_handles(load_segment, segment.is_none());
         self.current_psp = load_segment;
 
         self.bus.log_string(&format!(
@@ -1198,9 +1582,6 @@ impl Cpu {
             "[DOS] Loaded COM file at {:04X}:{:04X}",
             self.cs, self.ip
         ));
-        // Simple heuristic: COM files own the 64KB segment.
-        // Heap starts after that.
-        self.heap_pointer = load_segment + 0x1000;
         true
     }
 
@@ -1211,6 +1592,9 @@ impl Cpu {
             return false;
         }
 
+        // The image is copied straight into RAM below
+        self.bus.decode_cache.clear();
+
         // Parse Header
         let header_paragraphs = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
         let header_size = header_paragraphs * 16;
@@ -1224,17 +1608,13 @@ impl Cpu {
 
         // Clear RAM (Only if starting fresh at 0x1000, probably shouldn't blindly wipe if nested)
         if segment.is_none() {
-            for i in 0x500..self.bus.ram.len() {
-                self.bus.ram[i] = 0;
-            }
+            // Conventional memory only: ROMs and extended memory stay intact
+            self.bus.ram[0x500..0xA0000].fill(0);
         }
 
         // Re-install the HLE Interrupt Vectors
         self.install_bios_traps();
 
-        let load_segment: u16 = segment.unwrap_or(0x1000);
-        let relocation_base_segment = load_segment + 0x10;
-
         // Load Binary
         // Safety check: ensure header doesn't point past EOF
         if header_size > bytes.len() {
@@ -1243,6 +1623,19 @@ impl Cpu {
             return false;
         }
 
+        // Memory: PSP and image, plus the extra paragraphs the header asks for
+        let image_paras = (bytes.len() - header_size).div_ceil(16);
+        let min_alloc = u16::from_le_bytes([bytes[10], bytes[11]]) as usize;
+        let max_alloc = u16::from_le_bytes([bytes[12], bytes[13]]) as usize;
+        let min_paras = (0x10 + image_paras + min_alloc).min(0xFFFF) as u16;
+        let max_paras = (0x10 + image_paras + max_alloc).clamp(min_paras as usize, 0xFFFF) as u16;
+        let Some((load_segment, block_end)) =
+            self.claim_program_block(segment, min_paras, max_paras)
+        else {
+            return false;
+        };
+        let relocation_base_segment = load_segment + 0x10;
+
         // Standard loader
         // DOS behavior: Skip the header, load the rest to CS:0000 (after PSP)
         let image_start_phys = self.get_physical_addr(relocation_base_segment, 0);
@@ -1295,6 +1688,7 @@ impl Cpu {
         self.ss = relocation_base_segment.wrapping_add(init_ss);
         self.ip = init_ip;
         self.sp = init_sp;
+        self.set_cpu_flag(CpuFlags::IF, true); // DOS starts programs with interrupts enabled
 
         let psp_phys = self.get_physical_addr(load_segment, 0);
 
@@ -1303,11 +1697,8 @@ impl Cpu {
         self.bus.write_8(psp_phys + 1, 0x20);
 
         // Offset 0x02: Top of Memory (Segment)
-        // Programs read this to know how much RAM they have.
-        // We report 640KB (0xA000 paragraphs).
-        // Little Endian: 00 A0
-        self.bus.write_8(psp_phys + 2, 0x00);
-        self.bus.write_8(psp_phys + 3, 0xA0);
+        // Programs read this to know how much RAM they have: the end of their block.
+        self.bus.write_16(psp_phys + 2, block_end);
 
         // TODO: Pass Command Line Arguments via PSP
         // Offset 0x80: Command Tail Length (0 bytes)
@@ -1324,6 +1715,8 @@ impl Cpu {
         }
 
         self.bus.write_16(psp_phys + 0x2C, env_seg);
+        self.save_exit_vectors(load_segment, self.exit_vectors());
+        self.start_handles(load_segment, segment.is_none());
         self.current_psp = load_segment;
 
         self.bus.log_string(&format!(
@@ -1331,15 +1724,10 @@ impl Cpu {
             self.cs, self.ip
         ));
 
-        // Calculate heap pointer (First free paragraph after image)
-        // relocation_base_segment is where image starts.
-        // Image length is bytes.len() - header_size
-        let image_len = bytes.len() - header_size;
-        let image_paras = (image_len + 15) / 16;
-        self.heap_pointer = relocation_base_segment + image_paras as u16 + 1;
-
-        self.bus
-            .log_string(&format!("[DEBUG] Heap starts at {:04X}", self.heap_pointer));
+        self.bus.log_string(&format!(
+            "[DEBUG] Memory block {:04X}-{:04X}",
+            load_segment, block_end
+        ));
 
         // Enable to do detailed debugging of exe programs
         //self.debug_qb_conversion = true;
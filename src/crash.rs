@@ -0,0 +1,138 @@
+use iced_x86::{Decoder, DecoderOptions};
+use std::fmt::Write;
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::cpu::Cpu;
+
+// Post-mortem crash reports.
+//
+// The CPU keeps the last few executed instructions in `Cpu::trace_log`. Entries
+// are plain copies of CS:IP, the instruction bytes and the general registers,
+// so recording them costs next to nothing; disassembly only happens when a
+// report is actually written.
+
+/// Number of instructions kept in the history
+pub const TRACE_LOG_LEN: usize = 64;
+
+/// Stack words shown in the snapshot
+const STACK_WORDS: u16 = 16;
+
+/// One executed instruction, captured just before it ran
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub cs: u16,
+    pub ip: u16,
+    pub bytes: [u8; 15],
+    pub len: u8,
+    pub ax: u16,
+    pub bx: u16,
+    pub cx: u16,
+    pub dx: u16,
+    pub si: u16,
+    pub di: u16,
+    pub bp: u16,
+    pub sp: u16,
+    pub ds: u16,
+    pub es: u16,
+    pub ss: u16,
+}
+
+impl TraceEntry {
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+
+    fn disassemble(&self) -> String {
+        if self.bytes().starts_with(&[0xFE, 0x38]) {
+            return format!("BOP {:02X}h", self.bytes[2]);
+        }
+        let mut decoder = Decoder::new(16, self.bytes(), DecoderOptions::NONE);
+        decoder.set_ip(self.ip as u64);
+        format!("{}", decoder.decode())
+    }
+}
+
+/// Builds the report text: reason, registers, stack and instruction history
+pub fn report(cpu: &mut Cpu, reason: &str) -> String {
+    let (cs, ip) = crash_location(cpu);
+    let mut out = String::new();
+
+    let _ = writeln!(out, "=== CRASH REPORT: {} at {:04X}:{:04X} ===", reason, cs, ip);
+    let _ = writeln!(
+        out,
+        "AX={:04X} BX={:04X} CX={:04X} DX={:04X} SI={:04X} DI={:04X} BP={:04X} SP={:04X}",
+        cpu.ax, cpu.bx, cpu.cx, cpu.dx, cpu.si, cpu.di, cpu.bp, cpu.sp
+    );
+    let _ = writeln!(
+        out,
+        "CS={:04X} DS={:04X} ES={:04X} SS={:04X} IP={:04X} FLAGS={:04X}",
+        cpu.cs,
+        cpu.ds,
+        cpu.es,
+        cpu.ss,
+        cpu.ip,
+        cpu.get_cpu_flags().bits()
+    );
+
+    let _ = writeln!(out, "Stack:");
+    for row in 0..STACK_WORDS / 8 {
+        let offset = cpu.sp.wrapping_add(row * 16);
+        let _ = write!(out, "  {:04X}:{:04X} ", cpu.ss, offset);
+        for i in 0..8 {
+            let addr = cpu.get_physical_addr(cpu.ss, offset.wrapping_add(i * 2));
+            let _ = write!(out, " {:04X}", cpu.bus.read_16(addr));
+        }
+        let _ = writeln!(out);
+    }
+
+    let _ = writeln!(out, "Last {} instructions (oldest first):", cpu.trace_log.len());
+    for entry in &cpu.trace_log {
+        let hex: String = entry.bytes().iter().map(|b| format!("{:02X}", b)).collect();
+        let _ = writeln!(
+            out,
+            "  {:04X}:{:04X}  {:<16} {:<28} AX={:04X} BX={:04X} CX={:04X} DX={:04X} SI={:04X} DI={:04X} BP={:04X} SP={:04X} DS={:04X} ES={:04X} SS={:04X}",
+            entry.cs,
+            entry.ip,
+            hex,
+            entry.disassemble(),
+            entry.ax,
+            entry.bx,
+            entry.cx,
+            entry.dx,
+            entry.si,
+            entry.di,
+            entry.bp,
+            entry.sp,
+            entry.ds,
+            entry.es,
+            entry.ss
+        );
+    }
+    let _ = writeln!(out, "=== END OF CRASH REPORT ===");
+    out
+}
+
+// The instruction being executed is the newest history entry; CS:IP has
+// usually moved past it already.
+pub fn crash_location(cpu: &Cpu) -> (u16, u16) {
+    match cpu.trace_log.back() {
+        Some(entry) => (entry.cs, entry.ip),
+        None => (cpu.cs, cpu.ip),
+    }
+}
+
+/// Runs `f`, writing a crash report before letting an emulator panic continue
+pub fn guard<R>(cpu: &mut Cpu, f: impl FnOnce(&mut Cpu) -> R) -> R {
+    match panic::catch_unwind(AssertUnwindSafe(|| f(cpu))) {
+        Ok(result) => result,
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown".to_string());
+            cpu.dump_crash_report(&format!("Emulator panic ({})", message));
+            panic::resume_unwind(payload)
+        }
+    }
+}
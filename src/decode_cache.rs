@@ -0,0 +1,132 @@
+use iced_x86::{Decoder, DecoderOptions, Instruction};
+
+// Cache of decoded instructions, keyed by physical address and IP.
+//
+// The IP is part of the key because iced bakes it into the instruction
+// (next_ip, branch targets), and the same byte can be reached through
+// different CS:IP pairs.
+//
+// Invalidation is done per 256-byte page: every page that holds the bytes
+// of a cached instruction is flagged, and the first write into a flagged
+// page bumps its generation, which makes all entries touching it stale.
+// This keeps the check in `Bus::write_8` down to a single array lookup.
+//
+// The table itself is direct-mapped on the low bits of the physical address,
+// so a lookup is one index and a tag compare.
+
+const PAGE_SHIFT: usize = 8;
+const PAGE_COUNT: usize = crate::memory::ADDRESS_SPACE >> PAGE_SHIFT;
+
+// Longest possible x86 instruction
+const MAX_INSTRUCTION_LEN: usize = 15;
+
+const SLOT_COUNT: usize = 1 << 16;
+
+#[derive(Clone, Copy)]
+struct CachedInstruction {
+    key: u64,
+    instr: Instruction,
+    first_page_gen: u32,
+    last_page_gen: u32,
+}
+
+pub struct DecodeCache {
+    pub enabled: bool,
+    slots: Vec<Option<CachedInstruction>>,
+    page_gen: Vec<u32>,
+    code_pages: Vec<bool>,
+
+    // Statistics
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl Default for DecodeCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DecodeCache {
+    pub fn new() -> Self {
+        Self {
+            enabled: true,
+            slots: vec![None; SLOT_COUNT],
+            page_gen: vec![0; PAGE_COUNT],
+            code_pages: vec![false; PAGE_COUNT],
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    // Drop everything, e.g. after a program image was copied straight into RAM
+    pub fn clear(&mut self) {
+        self.slots.fill(None);
+        for (page_gen, has_code) in self.page_gen.iter_mut().zip(self.code_pages.iter_mut()) {
+            if *has_code {
+                *page_gen = page_gen.wrapping_add(1);
+                *has_code = false;
+            }
+        }
+    }
+
+    // Called for every memory write. Cheap unless the page holds cached code.
+    #[inline]
+    pub fn invalidate(&mut self, addr: usize) {
+        let page = addr >> PAGE_SHIFT;
+        if let Some(has_code) = self.code_pages.get_mut(page)
+            && *has_code
+        {
+            *has_code = false;
+            self.page_gen[page] = self.page_gen[page].wrapping_add(1);
+        }
+    }
+
+    /// Returns the instruction at `phys` (reached as offset `ip`), decoding `ram` on a miss.
+    pub fn fetch(&mut self, ram: &[u8], phys: usize, ip: u16) -> Instruction {
+        if !self.enabled {
+            return decode(ram, phys, ip);
+        }
+
+        let key = ((phys as u64) << 16) | ip as u64;
+        let slot = phys & (SLOT_COUNT - 1);
+        if let Some(entry) = &self.slots[slot]
+            && entry.key == key
+        {
+            let len = entry.instr.len().max(1);
+            let first = phys >> PAGE_SHIFT;
+            let last = (phys + len - 1) >> PAGE_SHIFT;
+            if self.page_gen.get(first) == Some(&entry.first_page_gen)
+                && self.page_gen.get(last) == Some(&entry.last_page_gen)
+            {
+                self.hits += 1;
+                return entry.instr;
+            }
+        }
+
+        self.misses += 1;
+        let instr = decode(ram, phys, ip);
+
+        let len = instr.len().max(1);
+        let first = phys >> PAGE_SHIFT;
+        let last = (phys + len - 1) >> PAGE_SHIFT;
+        if last < PAGE_COUNT {
+            self.code_pages[first] = true;
+            self.code_pages[last] = true;
+            self.slots[slot] = Some(CachedInstruction {
+                key,
+                instr,
+                first_page_gen: self.page_gen[first],
+                last_page_gen: self.page_gen[last],
+            });
+        }
+        instr
+    }
+}
+
+pub fn decode(ram: &[u8], phys: usize, ip: u16) -> Instruction {
+    let end = (phys + MAX_INSTRUCTION_LEN).min(ram.len());
+    let bytes = ram.get(phys..end).unwrap_or(&[]);
+    let mut decoder = Decoder::with_ip(16, bytes, ip as u64, DecoderOptions::NONE);
+    decoder.decode()
+}
@@ -0,0 +1,287 @@
+use super::Device;
+
+/// Size of a logical page and of each window in the page frame
+pub const PAGE_SIZE: usize = 16 * 1024;
+/// Windows in the 64KB page frame
+pub const PHYSICAL_PAGES: usize = 4;
+/// Handle numbers fit in a byte; handle 0 belongs to the operating system
+pub const MAX_HANDLES: usize = 255;
+
+// EMS status codes, returned in AH
+pub const ERR_INVALID_HANDLE: u8 = 0x83;
+pub const ERR_UNDEFINED_FUNCTION: u8 = 0x84;
+pub const ERR_OUT_OF_HANDLES: u8 = 0x85;
+pub const ERR_CONTEXT_SAVED: u8 = 0x86;
+pub const ERR_MORE_THAN_TOTAL: u8 = 0x87;
+pub const ERR_MORE_THAN_FREE: u8 = 0x88;
+pub const ERR_ZERO_PAGES: u8 = 0x89;
+pub const ERR_LOGICAL_PAGE: u8 = 0x8A;
+pub const ERR_PHYSICAL_PAGE: u8 = 0x8B;
+pub const ERR_ALREADY_SAVED: u8 = 0x8D;
+pub const ERR_NOT_SAVED: u8 = 0x8E;
+pub const ERR_SUBFUNCTION: u8 = 0x8F;
+pub const ERR_NOT_SUPPORTED: u8 = 0x91;
+pub const OVERLAP_MOVED: u8 = 0x92; // Not an error: the move was done
+pub const ERR_LENGTH: u8 = 0x93;
+pub const ERR_OFFSET: u8 = 0x95;
+pub const ERR_REGION_TOO_BIG: u8 = 0x96;
+pub const ERR_OVERLAP_EXCHANGE: u8 = 0x97;
+pub const ERR_MEMORY_TYPE: u8 = 0x98;
+pub const ERR_ALT_MAP_SET: u8 = 0x9C;
+pub const ERR_NAME_NOT_FOUND: u8 = 0xA0;
+pub const ERR_NAME_EXISTS: u8 = 0xA1;
+pub const ERR_WRAPS: u8 = 0xA2; // Conventional region runs past 1MB
+
+/// (handle, logical page) shown in one window of the page frame
+pub type PageMap = [Option<(u16, u16)>; PHYSICAL_PAGES];
+
+struct EmsHandle {
+    pages: Vec<u16>, // Logical page n lives in raw page pages[n]
+    name: [u8; 8],
+    saved_map: Option<PageMap>, // Function 47h
+}
+
+/// LIM EMS 4.0 expanded memory board.
+/// Its logical pages are banked into the 64KB page frame, which the memory map
+/// routes here. The INT 67h driver (interrupts/int67.rs) runs the board.
+pub struct Ems {
+    pub frame_segment: u16,
+    storage: Vec<u8>,
+    free: Vec<u16>, // Unallocated raw pages, lowest last
+    handles: Vec<Option<EmsHandle>>,
+    map: PageMap,
+    raw_map: [Option<u16>; PHYSICAL_PAGES], // Raw page behind each window
+    /// Page maps to restore when calls made through function 56h return
+    pub call_stack: Vec<(u16, Vec<(u16, u16)>)>,
+}
+
+impl Ems {
+    pub fn new(pages: u16, frame_segment: u16) -> Self {
+        let mut handles: Vec<Option<EmsHandle>> = (0..MAX_HANDLES).map(|_| None).collect();
+        handles[0] = Some(EmsHandle {
+            pages: Vec::new(),
+            name: [0; 8],
+            saved_map: None,
+        });
+        Self {
+            frame_segment,
+            storage: vec![0; pages as usize * PAGE_SIZE],
+            free: (0..pages).rev().collect(),
+            handles,
+            map: [None; PHYSICAL_PAGES],
+            raw_map: [None; PHYSICAL_PAGES],
+            call_stack: Vec::new(),
+        }
+    }
+
+    pub fn frame_base(&self) -> usize {
+        (self.frame_segment as usize) << 4
+    }
+
+    pub fn total_pages(&self) -> u16 {
+        (self.storage.len() / PAGE_SIZE) as u16
+    }
+
+    pub fn free_pages(&self) -> u16 {
+        self.free.len() as u16
+    }
+
+    fn handle(&self, handle: u16) -> Result<&EmsHandle, u8> {
+        self.handles
+            .get(handle as usize)
+            .and_then(Option::as_ref)
+            .ok_or(ERR_INVALID_HANDLE)
+    }
+
+    fn handle_mut(&mut self, handle: u16) -> Result<&mut EmsHandle, u8> {
+        self.handles
+            .get_mut(handle as usize)
+            .and_then(Option::as_mut)
+            .ok_or(ERR_INVALID_HANDLE)
+    }
+
+    pub fn handle_pages(&self, handle: u16) -> Result<u16, u8> {
+        Ok(self.handle(handle)?.pages.len() as u16)
+    }
+
+    /// Open handles and their page counts
+    pub fn active_handles(&self) -> Vec<(u16, u16)> {
+        self.handles
+            .iter()
+            .enumerate()
+            .filter_map(|(n, h)| h.as_ref().map(|h| (n as u16, h.pages.len() as u16)))
+            .collect()
+    }
+
+    pub fn allocate(&mut self, count: u16) -> Result<u16, u8> {
+        if count > self.total_pages() {
+            return Err(ERR_MORE_THAN_TOTAL);
+        }
+        if count > self.free_pages() {
+            return Err(ERR_MORE_THAN_FREE);
+        }
+        let slot = self
+            .handles
+            .iter()
+            .position(Option::is_none)
+            .ok_or(ERR_OUT_OF_HANDLES)?;
+        let pages = self.free.split_off(self.free.len() - count as usize);
+        self.handles[slot] = Some(EmsHandle {
+            pages: pages.into_iter().rev().collect(),
+            name: [0; 8],
+            saved_map: None,
+        });
+        Ok(slot as u16)
+    }
+
+    /// Frees the handle's pages. The operating system's handle 0 stays open.
+    pub fn deallocate(&mut self, handle: u16) -> Result<(), u8> {
+        if self.handle(handle)?.saved_map.is_some() {
+            return Err(ERR_CONTEXT_SAVED);
+        }
+        self.reallocate(handle, 0)?;
+        if handle != 0 {
+            self.handles[handle as usize] = None;
+        }
+        Ok(())
+    }
+
+    pub fn reallocate(&mut self, handle: u16, count: u16) -> Result<(), u8> {
+        let owned = self.handle_pages(handle)?;
+        if count > self.total_pages() {
+            return Err(ERR_MORE_THAN_TOTAL);
+        }
+        if count > owned && count - owned > self.free_pages() {
+            return Err(ERR_MORE_THAN_FREE);
+        }
+
+        if count > owned {
+            let grow = self.free.split_off(self.free.len() - (count - owned) as usize);
+            self.handle_mut(handle)?.pages.extend(grow.into_iter().rev());
+        } else {
+            let released = self.handle_mut(handle)?.pages.split_off(count as usize);
+            self.free.extend(released.into_iter().rev());
+            // Windows showing pages that are gone go blank
+            for window in 0..PHYSICAL_PAGES {
+                if matches!(self.map[window], Some((h, logical)) if h == handle && logical >= count) {
+                    self.map[window] = None;
+                    self.raw_map[window] = None;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Shows a logical page of `handle` in `window`; None unmaps the window
+    pub fn map_page(&mut self, window: usize, handle: u16, logical: Option<u16>) -> Result<(), u8> {
+        let pages = &self.handle(handle)?.pages;
+        if window >= PHYSICAL_PAGES {
+            return Err(ERR_PHYSICAL_PAGE);
+        }
+        let raw = match logical {
+            Some(logical) => Some(*pages.get(logical as usize).ok_or(ERR_LOGICAL_PAGE)?),
+            None => None,
+        };
+        self.map[window] = logical.map(|logical| (handle, logical));
+        self.raw_map[window] = raw;
+        Ok(())
+    }
+
+    pub fn page_map(&self) -> PageMap {
+        self.map
+    }
+
+    /// Restores a map from `page_map`. Entries that no longer exist are unmapped.
+    pub fn set_page_map(&mut self, map: &PageMap) {
+        for (window, entry) in map.iter().enumerate() {
+            let restored = match *entry {
+                Some((handle, logical)) => self.map_page(window, handle, Some(logical)),
+                None => Err(ERR_LOGICAL_PAGE),
+            };
+            if restored.is_err() {
+                self.map[window] = None;
+                self.raw_map[window] = None;
+            }
+        }
+    }
+
+    pub fn save_map(&mut self, handle: u16) -> Result<(), u8> {
+        let map = self.map;
+        let entry = self.handle_mut(handle)?;
+        if entry.saved_map.is_some() {
+            return Err(ERR_ALREADY_SAVED);
+        }
+        entry.saved_map = Some(map);
+        Ok(())
+    }
+
+    pub fn restore_map(&mut self, handle: u16) -> Result<(), u8> {
+        let map = self.handle_mut(handle)?.saved_map.take().ok_or(ERR_NOT_SAVED)?;
+        self.set_page_map(&map);
+        Ok(())
+    }
+
+    pub fn name(&self, handle: u16) -> Result<[u8; 8], u8> {
+        Ok(self.handle(handle)?.name)
+    }
+
+    pub fn set_name(&mut self, handle: u16, name: [u8; 8]) -> Result<(), u8> {
+        self.handle(handle)?;
+        if name != [0; 8] && self.find_name(&name).is_some_and(|other| other != handle) {
+            return Err(ERR_NAME_EXISTS);
+        }
+        self.handle_mut(handle)?.name = name;
+        Ok(())
+    }
+
+    pub fn find_name(&self, name: &[u8; 8]) -> Option<u16> {
+        self.handles
+            .iter()
+            .position(|h| h.as_ref().is_some_and(|h| &h.name == name))
+            .map(|n| n as u16)
+    }
+
+    /// Index into the board's storage of byte `offset` of a handle's memory,
+    /// counting from the start of logical page `logical`
+    pub fn storage_index(&self, handle: u16, logical: u16, offset: usize) -> Result<usize, u8> {
+        let pages = &self.handle(handle)?.pages;
+        let page = logical as usize + offset / PAGE_SIZE;
+        let raw = *pages.get(page).ok_or(ERR_LOGICAL_PAGE)?;
+        Ok(raw as usize * PAGE_SIZE + offset % PAGE_SIZE)
+    }
+
+    pub fn storage(&self) -> &[u8] {
+        &self.storage
+    }
+
+    pub fn storage_mut(&mut self) -> &mut [u8] {
+        &mut self.storage
+    }
+}
+
+impl Device for Ems {
+    fn ports(&self) -> Vec<u16> {
+        Vec::new()
+    }
+
+    fn io_read(&mut self, _port: u16) -> u8 {
+        0xFF
+    }
+
+    fn io_write(&mut self, _port: u16, _value: u8) {}
+
+    fn mem_read(&self, addr: usize) -> u8 {
+        let offset = addr.wrapping_sub(self.frame_base());
+        match self.raw_map.get(offset / PAGE_SIZE).copied().flatten() {
+            Some(raw) => self.storage[raw as usize * PAGE_SIZE + offset % PAGE_SIZE],
+            None => 0xFF,
+        }
+    }
+
+    fn mem_write(&mut self, addr: usize, value: u8) {
+        let offset = addr.wrapping_sub(self.frame_base());
+        if let Some(raw) = self.raw_map.get(offset / PAGE_SIZE).copied().flatten() {
+            self.storage[raw as usize * PAGE_SIZE + offset % PAGE_SIZE] = value;
+        }
+    }
+}
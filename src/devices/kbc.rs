@@ -0,0 +1,100 @@
+use super::{Device, Effects};
+
+/// Output port bit 1 drives the A20 gate
+pub const OUTPUT_PORT_A20: u8 = 0x02;
+
+/// 8042 keyboard controller.
+/// Keystrokes are delivered by the BIOS layer, so this only models the command
+/// interface: the output port (A20 gate) and the status register software polls
+/// before talking to it. Port 60h reads the last byte the controller produced.
+pub struct Kbc {
+    /// Bit 0: system reset (active low), bit 1: A20 gate
+    pub output_port: u8,
+    data: u8,
+    output_full: bool,
+    pending: Option<u8>, // Command waiting for its parameter byte on port 60h
+    effects: Effects,
+}
+
+impl Kbc {
+    pub fn new() -> Self {
+        Self {
+            output_port: 0xDD, // A20 off, as after power-on
+            data: 0xFF,
+            output_full: false,
+            pending: None,
+            effects: Effects::empty(),
+        }
+    }
+
+    pub fn a20(&self) -> bool {
+        self.output_port & OUTPUT_PORT_A20 != 0
+    }
+
+    pub fn set_a20(&mut self, on: bool) {
+        if on {
+            self.output_port |= OUTPUT_PORT_A20;
+        } else {
+            self.output_port &= !OUTPUT_PORT_A20;
+        }
+    }
+
+    fn output(&mut self, value: u8) {
+        self.data = value;
+        self.output_full = true;
+    }
+}
+
+impl Default for Kbc {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Device for Kbc {
+    fn ports(&self) -> Vec<u16> {
+        vec![0x60, 0x64]
+    }
+
+    fn io_read(&mut self, port: u16) -> u8 {
+        match port {
+            0x60 => {
+                self.output_full = false;
+                self.data
+            }
+            // Status: input buffer always empty, system flag set (POST passed)
+            _ => 0x04 | self.output_full as u8,
+        }
+    }
+
+    fn io_write(&mut self, port: u16, value: u8) {
+        let a20 = self.a20();
+        match port {
+            0x64 => {
+                self.pending = None;
+                match value {
+                    0xD0 => self.output(self.output_port), // Read output port
+                    0xD1 => self.pending = Some(value),    // Write output port
+                    0xDD => self.set_a20(false),           // Disable A20 (HP Vectra)
+                    0xDF => self.set_a20(true),            // Enable A20 (HP Vectra)
+                    0xAA => self.output(0x55),             // Self test passed
+                    0xAB => self.output(0x00),             // Interface test passed
+                    _ => {}                                // 0xFF and friends: no-op
+                }
+            }
+            _ => match self.pending.take() {
+                // The reset line can't be pulled from here, keep it high
+                Some(0xD1) => self.output_port = value | 0x01,
+                // Bytes for the keyboard itself are acknowledged
+                _ => self.output(0xFA),
+            },
+        }
+        if self.a20() != a20 {
+            self.effects |= Effects::A20;
+        }
+    }
+
+    fn take_effects(&mut self) -> Effects {
+        std::mem::take(&mut self.effects)
+    }
+}
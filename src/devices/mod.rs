@@ -0,0 +1,182 @@
+use bitflags::bitflags;
+use std::any::Any;
+
+pub mod ems;
+pub mod kbc;
+pub mod pic;
+pub mod pit;
+pub mod port92;
+pub mod rtc;
+pub mod speaker;
+
+// Port-mapped I/O.
+//
+// Every piece of hardware behind IN/OUT is a `Device` living in the bus's
+// `IoPorts` registry. The registry keeps a table of all 65,536 ports pointing
+// at the device that answers them, so dispatch is a single index instead of a
+// search. Devices with memory windows (VGA) also get the accesses to the
+// regions the memory map assigns to them. Devices can come and go at runtime;
+// the built-in ones are attached first, in a fixed order, so the bus can find
+// them again by id, and stay attached. A write that changes something outside
+// the device, like the video mode or the A20 gate, is reported back to the
+// bus as `Effects`.
+
+pub type DeviceId = usize;
+
+// Built-in devices, in the order Bus::new attaches them
+pub const PIC: DeviceId = 0;
+pub const PIT: DeviceId = 1;
+pub const SPEAKER: DeviceId = 2;
+pub const VGA: DeviceId = 3;
+pub const KBC: DeviceId = 4;
+pub const PORT92: DeviceId = 5;
+pub const EMS: DeviceId = 6;
+pub const RTC: DeviceId = 7;
+/// Ids below this are the built-in devices
+pub const BUILT_IN: DeviceId = RTC + 1;
+
+/// Port slot not claimed by any device
+const UNMAPPED: u16 = u16::MAX;
+
+bitflags! {
+    /// What a port write changed that the rest of the machine has to follow
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct Effects: u8 {
+        /// The registers that pick the video mode were written
+        const VIDEO_MODE = 0x01;
+        /// The A20 gate opened or closed
+        const A20 = 0x02;
+    }
+}
+
+pub trait Device: Any {
+    /// Ports claimed when the device is attached
+    fn ports(&self) -> Vec<u16>;
+    fn io_read(&mut self, port: u16) -> u8;
+    fn io_write(&mut self, port: u16, value: u8);
+    /// Effects of the writes since the last call, collected by the bus after each one
+    fn take_effects(&mut self) -> Effects {
+        Effects::empty()
+    }
+    /// Called as emulated time passes
+    fn step(&mut self, _slice: &mut TimeSlice) {}
+    /// Accesses to memory regions mapped to the device, by physical address
+    fn mem_read(&self, _addr: usize) -> u8 {
+        0xFF
+    }
+    fn mem_write(&mut self, _addr: usize, _value: u8) {}
+}
+
+/// What a device gets to see and do while it steps
+#[derive(Debug, Default)]
+pub struct TimeSlice {
+    /// PIT input clocks (1.193182 MHz) elapsed since the last step
+    pub pit_ticks: u64,
+    /// IRQ lines raised during this slice, bit n = IRQ n
+    pub irqs: u16,
+}
+
+impl TimeSlice {
+    pub fn raise_irq(&mut self, irq: u8) {
+        self.irqs |= 1 << irq;
+    }
+}
+
+pub struct IoPorts {
+    map: Vec<u16>,
+    devices: Vec<Option<Box<dyn Device>>>,
+}
+
+impl IoPorts {
+    pub fn new() -> Self {
+        Self {
+            map: vec![UNMAPPED; 0x10000],
+            devices: Vec::new(),
+        }
+    }
+
+    /// Plugs in a device. It takes over any of its ports another device had claimed.
+    pub fn attach(&mut self, device: Box<dyn Device>) -> DeviceId {
+        let id = self.devices.len();
+        for port in device.ports() {
+            self.map[port as usize] = id as u16;
+        }
+        self.devices.push(Some(device));
+        id
+    }
+
+    /// Unplugs a device. Ports it had taken over go back to their previous owner.
+    /// Ids are never reused, and the built-in devices can't be unplugged.
+    pub fn detach(&mut self, id: DeviceId) -> Option<Box<dyn Device>> {
+        if id < BUILT_IN {
+            return None;
+        }
+        let device = self.devices.get_mut(id)?.take()?;
+        self.map.fill(UNMAPPED);
+        for (id, device) in self.devices.iter().enumerate() {
+            if let Some(device) = device {
+                for port in device.ports() {
+                    self.map[port as usize] = id as u16;
+                }
+            }
+        }
+        Some(device)
+    }
+
+    /// The device answering `port`
+    pub fn owner(&self, port: u16) -> Option<DeviceId> {
+        match self.map[port as usize] {
+            UNMAPPED => None,
+            id => Some(id as DeviceId),
+        }
+    }
+
+    /// None if nothing answers the port
+    pub fn read(&mut self, port: u16) -> Option<u8> {
+        let id = self.owner(port)?;
+        self.devices[id].as_mut().map(|d| d.io_read(port))
+    }
+
+    /// None if nothing answers the port
+    pub fn write(&mut self, port: u16, value: u8) -> Option<Effects> {
+        let id = self.owner(port)?;
+        let device = self.devices[id].as_mut()?;
+        device.io_write(port, value);
+        Some(device.take_effects())
+    }
+
+    pub fn mem_read(&self, id: DeviceId, addr: usize) -> u8 {
+        match self.devices.get(id) {
+            Some(Some(device)) => device.mem_read(addr),
+            _ => 0xFF,
+        }
+    }
+
+    pub fn mem_write(&mut self, id: DeviceId, addr: usize, value: u8) {
+        if let Some(Some(device)) = self.devices.get_mut(id) {
+            device.mem_write(addr, value);
+        }
+    }
+
+    pub fn step(&mut self, slice: &mut TimeSlice) {
+        for device in self.devices.iter_mut().flatten() {
+            device.step(slice);
+        }
+    }
+
+    pub fn get<T: Device>(&self, id: DeviceId) -> Option<&T> {
+        let device: &dyn Any = self.devices.get(id)?.as_deref()?;
+        device.downcast_ref()
+    }
+
+    pub fn get_mut<T: Device>(&mut self, id: DeviceId) -> Option<&mut T> {
+        let device: &mut dyn Any = self.devices.get_mut(id)?.as_deref_mut()?;
+        device.downcast_mut()
+    }
+}
+
+impl Default for IoPorts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
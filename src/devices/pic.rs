@@ -0,0 +1,82 @@
+use super::Device;
+
+/// 8259 Programmable Interrupt Controllers: the master (IRQ 0-7) and, as on
+/// the AT, a slave cascaded on its IRQ2 (IRQ 8-15).
+/// Initialization words (ICWs) are ignored; the BIOS layout (IRQ0 = INT 08h,
+/// IRQ8 = INT 70h) is fixed.
+pub struct Pic {
+    /// Interrupt Mask Register: a set bit disables that IRQ
+    pub mask: u8,
+    /// The slave's mask, bit n for IRQ 8+n
+    pub slave_mask: u8,
+    /// IRQ lines raised but not yet delivered to the CPU
+    pub pending: u16,
+}
+
+// The slave's lines take the place of IRQ2 in the master's priority order
+const PRIORITY: [u8; 16] = [0, 1, 8, 9, 10, 11, 12, 13, 14, 15, 2, 3, 4, 5, 6, 7];
+
+impl Pic {
+    pub fn new() -> Self {
+        Self {
+            mask: 0x00,
+            slave_mask: 0x00,
+            pending: 0,
+        }
+    }
+
+    pub fn raise(&mut self, irqs: u16) {
+        self.pending |= irqs;
+    }
+
+    // Raised lines neither controller masks
+    fn requests(&self) -> u16 {
+        let mut masked = self.mask as u16 | (self.slave_mask as u16) << 8;
+        if self.mask & 0x04 != 0 {
+            masked |= 0xFF00;
+        }
+        self.pending & !masked
+    }
+
+    pub fn has_request(&self) -> bool {
+        self.requests() != 0
+    }
+
+    /// Highest priority unmasked request, acknowledged as it's returned
+    pub fn acknowledge(&mut self) -> Option<u8> {
+        let requests = self.requests();
+        let irq = PRIORITY.into_iter().find(|&irq| requests & (1 << irq) != 0)?;
+        self.pending &= !(1 << irq);
+        Some(irq)
+    }
+}
+
+impl Default for Pic {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Device for Pic {
+    fn ports(&self) -> Vec<u16> {
+        vec![0x20, 0x21, 0xA0, 0xA1]
+    }
+
+    fn io_read(&mut self, port: u16) -> u8 {
+        match port {
+            0x21 => self.mask,
+            0xA1 => self.slave_mask,
+            _ => 0x00, // No request in service
+        }
+    }
+
+    fn io_write(&mut self, port: u16, value: u8) {
+        // Ports 0x20 and 0xA0 take commands, of which only EOI (0x20) matters and
+        // needs no action: requests are cleared as soon as they're acknowledged.
+        match port {
+            0x21 => self.mask = value,
+            0xA1 => self.slave_mask = value,
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use super::{Device, TimeSlice};
+
+/// 8253/8254 Programmable Interval Timer.
+/// Channel 0 drives the system tick (IRQ 0), channel 2 the speaker tone.
+/// Channel 1 (DRAM refresh) isn't modelled and counters can't be read back.
+pub struct Pit {
+    pub mode: u8, // Last command byte
+    pub ch0_divisor: u16,
+    pub ch0_write_msb: bool, // Toggle to handle 2-byte writes (LSB/MSB)
+    pub ch0_counter: u32,    // PIT ticks elapsed in the current channel 0 period
+    pub ch2_divisor: u16,    // Speaker frequency = 1,193,182 Hz / divisor
+    pub ch2_write_msb: bool,
+}
+
+impl Pit {
+    pub fn new() -> Self {
+        Self {
+            mode: 0,
+            ch0_divisor: 0xFFFF,
+            ch0_write_msb: false,
+            ch0_counter: 0,
+            ch2_divisor: 0xFFFF,
+            ch2_write_msb: false,
+        }
+    }
+}
+
+impl Default for Pit {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Writes the next byte of a 16-bit divisor, LSB first
+fn write_divisor(divisor: &mut u16, write_msb: &mut bool, value: u8) {
+    if !*write_msb {
+        *divisor = (*divisor & 0xFF00) | (value as u16);
+    } else {
+        *divisor = (*divisor & 0x00FF) | ((value as u16) << 8);
+    }
+    *write_msb = !*write_msb;
+}
+
+impl Device for Pit {
+    fn ports(&self) -> Vec<u16> {
+        vec![0x40, 0x42, 0x43]
+    }
+
+    fn io_read(&mut self, _port: u16) -> u8 {
+        0xFF
+    }
+
+    fn io_write(&mut self, port: u16, value: u8) {
+        match port {
+            // Channel 0 Data (System Timer)
+            // Default is 18.2 Hz (Divisor 65535).
+            0x40 => {
+                write_divisor(&mut self.ch0_divisor, &mut self.ch0_write_msb, value);
+                if !self.ch0_write_msb {
+                    self.ch0_counter = 0; // Reload restarts the count
+                }
+            }
+
+            // Channel 2 Data (Speaker)
+            0x42 => write_divisor(&mut self.ch2_divisor, &mut self.ch2_write_msb, value),
+
+            // Command Register
+            0x43 => {
+                self.mode = value;
+
+                // Extract the Channel bits (7-6)
+                // 00 = Channel 0, 01 = Channel 1, 10 = Channel 2
+                let channel = (value >> 6) & 0x03;
+
+                // If the command is for the Counter (not Read-Back), reset the flip-flop.
+                // We check Access bits (5-4) to ensure it's not a Latch command (00).
+                let access = (value >> 4) & 0x03;
+
+                if access != 0 {
+                    match channel {
+                        0 => self.ch0_write_msb = false,
+                        2 => self.ch2_write_msb = false,
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Count channel 0 down and raise IRQ 0 each time it wraps
+    fn step(&mut self, slice: &mut TimeSlice) {
+        if slice.pit_ticks == 0 {
+            return;
+        }
+
+        // A divisor of 0 means 65536
+        let period = match self.ch0_divisor {
+            0 => 0x10000,
+            d => d as u32,
+        };
+        let total = self.ch0_counter as u64 + slice.pit_ticks;
+        if total >= period as u64 {
+            slice.raise_irq(0);
+        }
+        self.ch0_counter = (total % period as u64) as u32;
+    }
+}
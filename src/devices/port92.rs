@@ -0,0 +1,57 @@
+use super::{Device, Effects};
+
+/// System Control Port A (0x92), the PS/2 "fast A20" gate.
+/// Bit 0: fast reset (ignored), bit 1: A20 gate
+pub struct SystemControlA {
+    pub value: u8,
+    effects: Effects,
+}
+
+impl SystemControlA {
+    pub fn new() -> Self {
+        Self {
+            value: 0x00,
+            effects: Effects::empty(),
+        }
+    }
+
+    pub fn a20(&self) -> bool {
+        self.value & 0x02 != 0
+    }
+
+    pub fn set_a20(&mut self, on: bool) {
+        if on {
+            self.value |= 0x02;
+        } else {
+            self.value &= !0x02;
+        }
+    }
+}
+
+impl Default for SystemControlA {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Device for SystemControlA {
+    fn ports(&self) -> Vec<u16> {
+        vec![0x92]
+    }
+
+    fn io_read(&mut self, _port: u16) -> u8 {
+        self.value
+    }
+
+    fn io_write(&mut self, _port: u16, value: u8) {
+        let a20 = self.a20();
+        self.value = value & !0x01;
+        if self.a20() != a20 {
+            self.effects |= Effects::A20;
+        }
+    }
+
+    fn take_effects(&mut self) -> Effects {
+        std::mem::take(&mut self.effects)
+    }
+}
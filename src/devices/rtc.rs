@@ -0,0 +1,369 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use chrono::{Datelike, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
+
+use super::{Device, TimeSlice};
+use crate::bus::PIT_FREQUENCY;
+use crate::clock::{Clock, StartTime};
+
+// MC146818 real-time clock and its CMOS RAM, behind ports 70h (index) and
+// 71h (data). The 128 bytes are laid out as on the AT:
+//
+//   00-09  Seconds, alarm seconds, minutes, alarm minutes, hours, alarm
+//          hours, day of week (1 = Sunday), day, month, year
+//   0A-0D  Status registers A-D
+//   0E-0F  POST diagnostics and shutdown status
+//   10-2D  Configuration: floppies (10), equipment (14), base memory (15-16),
+//          extended memory (17-18)
+//   2E-2F  Checksum of 10-2D, high byte first
+//   30-31  Extended memory found by POST
+//   32     Century
+//
+// The time registers are a view of the machine's clock, which the RTC keeps
+// running. Everything from 0E up is the battery-backed NVRAM, which can be
+// kept in a host file from one run to the next.
+
+pub const REG_A: usize = 0x0A;
+pub const REG_B: usize = 0x0B;
+pub const REG_C: usize = 0x0C;
+pub const REG_D: usize = 0x0D;
+pub const REG_CENTURY: usize = 0x32;
+
+// Register A
+const UIP: u8 = 0x80; // Update in progress
+const RATE: u8 = 0x0F; // Periodic interrupt rate select
+// Register B
+const SET: u8 = 0x80; // Updates stopped, time registers open for writing
+const PIE: u8 = 0x40; // Periodic interrupt enable
+const AIE: u8 = 0x20; // Alarm interrupt enable
+const UIE: u8 = 0x10; // Update-ended interrupt enable
+const DM: u8 = 0x04; // Binary rather than BCD
+const HOURS_24: u8 = 0x02;
+// Register C, whose flags line up with their enables in B
+const IRQF: u8 = 0x80;
+const PF: u8 = 0x40;
+const AF: u8 = 0x20;
+const UF: u8 = 0x10;
+// Register D
+const VRT: u8 = 0x80; // Valid RAM and time: the battery is fine
+
+const NVRAM_START: usize = 0x0E;
+const CHECKSUM: usize = 0x2E;
+const CHECKSUMMED: std::ops::Range<usize> = 0x10..0x2E;
+
+/// The update cycle takes 244 us, flagged in register A while it runs
+const UPDATE_PIT_TICKS: u64 = PIT_FREQUENCY * 244 / 1_000_000;
+
+/// IRQ line on the slave PIC
+pub const RTC_IRQ: u8 = 8;
+
+pub struct Rtc {
+    pub clock: Clock,
+    cmos: [u8; 128],
+    index: usize,
+    periodic_accum: u64, // PIT ticks times the periodic rate, toward one period
+    nvram_file: Option<PathBuf>,
+}
+
+impl Rtc {
+    pub fn new() -> Self {
+        let mut cmos = [0; 128];
+        cmos[REG_A] = 0x26; // 32.768 kHz time base, 1024 Hz periodic rate
+        cmos[REG_B] = HOURS_24;
+        cmos[REG_D] = VRT;
+        Self {
+            clock: Clock::new(StartTime::Host),
+            cmos,
+            index: 0,
+            periodic_accum: 0,
+            nvram_file: None,
+        }
+    }
+
+    /// Keeps the NVRAM in `path`: loaded from it if it exists, written back
+    /// whenever a byte changes
+    pub fn set_nvram_file(&mut self, path: PathBuf) -> io::Result<()> {
+        match fs::read(&path) {
+            Ok(bytes) if bytes.len() == self.cmos.len() => {
+                self.cmos[NVRAM_START..].copy_from_slice(&bytes[NVRAM_START..]);
+            }
+            Ok(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "NVRAM file is not 128 bytes",
+                ))
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                fs::write(&path, self.cmos)?;
+            }
+            Err(e) => return Err(e),
+        }
+        self.nvram_file = Some(path);
+        Ok(())
+    }
+
+    /// Records the machine's configuration the way POST does, with a fresh
+    /// checksum
+    pub fn set_configuration(&mut self, base_kb: u16, extended_kb: u16, fpu: bool) {
+        let mut cmos = self.cmos;
+        cmos[0x10] = 0x40; // Drive A: 1.44MB, no B:
+        cmos[0x14] = 0x01 | (fpu as u8) << 1; // One floppy, VGA, coprocessor
+        cmos[0x15..0x17].copy_from_slice(&base_kb.to_le_bytes());
+        cmos[0x17..0x19].copy_from_slice(&extended_kb.to_le_bytes());
+        cmos[0x30..0x32].copy_from_slice(&extended_kb.to_le_bytes());
+        let sum: u16 = cmos[CHECKSUMMED].iter().map(|&b| b as u16).sum();
+        cmos[CHECKSUM..CHECKSUM + 2].copy_from_slice(&sum.to_be_bytes());
+        if cmos != self.cmos {
+            self.cmos = cmos;
+            self.save();
+        }
+    }
+
+    // NVRAM writes go straight through to the file; it's only a handful of
+    // bytes and they rarely change
+    fn save(&self) {
+        if let Some(path) = &self.nvram_file {
+            let _ = fs::write(path, self.cmos);
+        }
+    }
+
+    // --- Time registers ---
+
+    fn binary(&self) -> bool {
+        self.cmos[REG_B] & DM != 0
+    }
+
+    fn encode(&self, value: u32) -> u8 {
+        if self.binary() {
+            value as u8
+        } else {
+            (((value / 10) << 4) | (value % 10)) as u8
+        }
+    }
+
+    fn decode(&self, value: u8) -> Option<u32> {
+        if self.binary() {
+            return Some(value as u32);
+        }
+        let (high, low) = (value >> 4, value & 0x0F);
+        (high < 10 && low < 10).then_some((high * 10 + low) as u32)
+    }
+
+    // In 12-hour mode, hours run 1-12 with bit 7 set after noon
+    fn encode_hour(&self, hour: u32) -> u8 {
+        if self.cmos[REG_B] & HOURS_24 != 0 {
+            return self.encode(hour);
+        }
+        let pm = if hour >= 12 { 0x80 } else { 0x00 };
+        self.encode((hour + 11) % 12 + 1) | pm
+    }
+
+    fn decode_hour(&self, value: u8) -> Option<u32> {
+        if self.cmos[REG_B] & HOURS_24 != 0 {
+            return self.decode(value);
+        }
+        let hour = self.decode(value & 0x7F).filter(|h| (1..=12).contains(h))?;
+        Some(hour % 12 + if value & 0x80 != 0 { 12 } else { 0 })
+    }
+
+    /// Copies the clock into the time registers
+    fn latch(&mut self) {
+        let now = self.clock.now();
+        let year = now.year() as u32;
+        let fields = [
+            (0x00, self.encode(now.second())),
+            (0x02, self.encode(now.minute())),
+            (0x04, self.encode_hour(now.hour())),
+            (0x06, self.encode(now.weekday().number_from_sunday())),
+            (0x07, self.encode(now.day())),
+            (0x08, self.encode(now.month())),
+            (0x09, self.encode(year % 100)),
+            (REG_CENTURY, self.encode(year / 100)),
+        ];
+        for (index, value) in fields {
+            self.cmos[index] = value;
+        }
+    }
+
+    /// Sets the clock from the time registers, if they hold a valid date
+    fn commit(&mut self) {
+        let time = (|| {
+            let year = self.decode(self.cmos[REG_CENTURY])? * 100 + self.decode(self.cmos[0x09])?;
+            let date = NaiveDate::from_ymd_opt(
+                year as i32,
+                self.decode(self.cmos[0x08])?,
+                self.decode(self.cmos[0x07])?,
+            )?;
+            let time = NaiveTime::from_hms_opt(
+                self.decode_hour(self.cmos[0x04])?,
+                self.decode(self.cmos[0x02])?,
+                self.decode(self.cmos[0x00])?,
+            )?;
+            Some(NaiveDateTime::new(date, time))
+        })();
+        if let Some(time) = time {
+            self.clock.set(time);
+        }
+    }
+
+    fn is_time_register(index: usize) -> bool {
+        matches!(index, 0x00 | 0x02 | 0x04 | 0x06..=0x09 | REG_CENTURY)
+    }
+
+    // --- Alarm (INT 1Ah 06h/07h) ---
+
+    pub fn alarm_enabled(&self) -> bool {
+        self.cmos[REG_B] & AIE != 0
+    }
+
+    /// Sets the alarm registers and enables the alarm interrupt, or disables it
+    pub fn set_alarm(&mut self, alarm: Option<NaiveTime>) {
+        match alarm {
+            Some(time) => {
+                self.cmos[0x01] = self.encode(time.second());
+                self.cmos[0x03] = self.encode(time.minute());
+                self.cmos[0x05] = self.encode_hour(time.hour());
+                self.cmos[REG_B] |= AIE;
+            }
+            None => self.cmos[REG_B] &= !AIE,
+        }
+    }
+
+    // Alarm bytes of C0h or above match anything
+    fn alarm_matches(&mut self) -> bool {
+        self.latch();
+        [(0x01, 0x00), (0x03, 0x02), (0x05, 0x04)]
+            .iter()
+            .all(|&(alarm, time)| self.cmos[alarm] >= 0xC0 || self.cmos[alarm] == self.cmos[time])
+    }
+
+    // --- Interrupts ---
+
+    /// Periodic interrupt frequency in Hz, from the rate select in register A
+    fn periodic_hz(&self) -> Option<u64> {
+        match self.cmos[REG_A] & RATE {
+            0 => None,
+            1 => Some(256),
+            2 => Some(128),
+            rate => Some(32768 >> (rate - 1)),
+        }
+    }
+
+    fn irq_asserted(&self) -> bool {
+        self.cmos[REG_C] & self.cmos[REG_B] & (PIE | AIE | UIE) != 0
+    }
+
+    // The IRQ line stays up until register C is read, so a handler that
+    // never reads it gets no further interrupts
+    fn flag(&mut self, flags: u8, slice: &mut TimeSlice) {
+        let asserted = self.irq_asserted();
+        self.cmos[REG_C] |= flags;
+        if !asserted && self.irq_asserted() {
+            slice.raise_irq(RTC_IRQ);
+        }
+    }
+}
+
+impl Default for Rtc {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Device for Rtc {
+    fn ports(&self) -> Vec<u16> {
+        vec![0x70, 0x71]
+    }
+
+    fn io_read(&mut self, port: u16) -> u8 {
+        if port == 0x70 {
+            return 0xFF; // Write-only
+        }
+        let index = self.index;
+        match index {
+            REG_A => {
+                let updating = self.clock.pit_ticks() >= PIT_FREQUENCY - UPDATE_PIT_TICKS
+                    && self.cmos[REG_B] & SET == 0;
+                self.cmos[REG_A] | if updating { UIP } else { 0 }
+            }
+            REG_C => {
+                let flags = self.cmos[REG_C] | if self.irq_asserted() { IRQF } else { 0 };
+                self.cmos[REG_C] = 0;
+                flags
+            }
+            _ if Self::is_time_register(index) && self.cmos[REG_B] & SET == 0 => {
+                self.latch();
+                self.cmos[index]
+            }
+            _ => self.cmos[index],
+        }
+    }
+
+    fn io_write(&mut self, port: u16, value: u8) {
+        if port == 0x70 {
+            // Bit 7 gates NMI, which nothing here raises
+            self.index = (value & 0x7F) as usize;
+            return;
+        }
+        let index = self.index;
+        match index {
+            REG_A => self.cmos[REG_A] = value & !UIP,
+            REG_B => {
+                let old = self.cmos[REG_B];
+                if value & SET != 0 && old & SET == 0 {
+                    self.latch();
+                }
+                self.cmos[REG_B] = value;
+                if value & SET == 0 && old & SET != 0 {
+                    self.commit();
+                }
+            }
+            REG_C | REG_D => {} // Read-only
+            _ if Self::is_time_register(index) => {
+                let running = self.cmos[REG_B] & SET == 0;
+                if running {
+                    self.latch();
+                }
+                self.cmos[index] = value;
+                if running {
+                    self.commit();
+                }
+            }
+            _ => {
+                if self.cmos[index] != value {
+                    self.cmos[index] = value;
+                    if index >= NVRAM_START {
+                        self.save();
+                    }
+                }
+            }
+        }
+    }
+
+    fn step(&mut self, slice: &mut TimeSlice) {
+        let mut flags = 0;
+        if let Some(hz) = self.periodic_hz() {
+            self.periodic_accum += slice.pit_ticks * hz;
+            if self.periodic_accum >= PIT_FREQUENCY {
+                self.periodic_accum %= PIT_FREQUENCY;
+                flags |= PF;
+            }
+        }
+        // SET holds the clock still
+        if self.cmos[REG_B] & SET == 0 {
+            let updated = self.clock.pit_ticks() + slice.pit_ticks >= PIT_FREQUENCY;
+            self.clock.advance(slice.pit_ticks);
+            if updated {
+                flags |= UF;
+                if self.alarm_matches() {
+                    flags |= AF;
+                }
+            }
+        }
+        if flags != 0 {
+            self.flag(flags, slice);
+        }
+    }
+}
@@ -0,0 +1,34 @@
+use super::Device;
+
+/// PC speaker, switched through PPI Port B (0x61).
+/// Bit 0: Timer 2 Gate (Must be 1 for timer to run)
+/// Bit 1: Speaker Data (Must be 1 for sound to pass to speaker)
+pub struct Speaker {
+    pub on: bool, // Both bits set: the speaker plays the channel 2 tone
+}
+
+impl Speaker {
+    pub fn new() -> Self {
+        Self { on: false }
+    }
+}
+
+impl Default for Speaker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Device for Speaker {
+    fn ports(&self) -> Vec<u16> {
+        vec![0x61]
+    }
+
+    fn io_read(&mut self, _port: u16) -> u8 {
+        if self.on { 0x03 } else { 0x00 }
+    }
+
+    fn io_write(&mut self, _port: u16, value: u8) {
+        self.on = (value & 0x03) == 0x03;
+    }
+}
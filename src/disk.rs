@@ -1,11 +1,48 @@
-use chrono::{DateTime, Datelike, Local, Timelike};
+use chrono::{DateTime, Datelike, Local, TimeZone, Timelike};
 use std::collections::HashMap;
 use std::fs::{self, File, OpenOptions};
-use std::io::{Read, Seek, SeekFrom, Write};
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::errors::{NOT_READY, SHARING_VIOLATION, WRITE_PROTECTED};
+
+/// Entries in the system file table, as set by FILES=
+pub const SFT_SIZE: usize = 40;
+
+// The devices behind the standard handles always occupy the first entries
+pub const SFT_CON: u8 = 0;
+pub const SFT_AUX: u8 = 1;
+pub const SFT_PRN: u8 = 2;
+
+/// What a system file table entry refers to
+pub enum SftFile {
+    Con,
+    Aux,
+    Prn,
+    Disk(File),
+    /// A Z: file, read from its in-memory contents
+    Virtual(Cursor<Vec<u8>>),
+}
+
+/// An open file, shared by every handle that refers to it
+pub struct SftEntry {
+    pub file: SftFile,
+    /// Open mode from INT 21h 3Dh; bit 7 keeps children from inheriting it
+    pub mode: u8,
+    /// Handles referring to this entry, across all processes
+    pub refs: u16,
+    /// Host file, for sharing checks
+    pub path: Option<PathBuf>,
+    /// Process that opened it through an FCB, outside any JFT
+    pub fcb_owner: Option<u16>,
+}
 
-// DOS defines standard handles: 0=Stdin, 1=Stdout, 2=Stderr, 3=Aux, 4=Printer
-pub const FIRST_USER_HANDLE: u16 = 5;
+impl SftEntry {
+    pub fn is_device(&self) -> bool {
+        matches!(self.file, SftFile::Con | SftFile::Aux | SftFile::Prn)
+    }
+}
 
 /// Helper struct to transfer directory search results back to the CPU
 #[allow(dead_code)]
@@ -19,15 +56,16 @@ pub struct DosDirEntry {
 }
 
 pub struct DiskController {
-    // Map DOS Handle (u16) -> Rust File Object
-    open_files: HashMap<u16, File>,
-    next_handle: u16,
+    // System file table; handles map to it through each process's JFT
+    sft: Vec<Option<SftEntry>>,
 
     // File System State
     root_path: PathBuf,                      // The host directory acting as C:\
     current_dir: String,                     // The current DOS directory (e.g., "GAMES\DOOM")
     current_drive: u8,                       // 0=A, ... 2=C, ... 25=Z
     virtual_files: HashMap<String, Vec<u8>>, // In-memory files for Z: drive
+    write_protected: bool,                   // C: refuses writes
+    error_drive: u8,                         // Where the last critical error happened
 }
 
 impl DiskController {
@@ -47,14 +85,17 @@ impl DiskController {
         // Create a dummy COMMAND.COM on Z:
         virtual_files.insert("COMMAND.COM".to_string(), vec![0x90; 5000]);
 
-        Self {
-            open_files: HashMap::new(),
-            next_handle: FIRST_USER_HANDLE,
+        let mut disk = Self {
+            sft: Vec::new(),
             root_path: canonical,
             current_dir: String::new(),
             current_drive: 2, // Default to C:
             virtual_files,
-        }
+            write_protected: false,
+            error_drive: 2,
+        };
+        disk.reset_files();
+        disk
     }
 
     pub fn set_current_drive(&mut self, drive: u8) -> u8 {
@@ -70,6 +111,21 @@ impl DiskController {
         self.current_drive
     }
 
+    /// Makes C: refuse to change, like a write-protected disk
+    pub fn set_write_protected(&mut self, protected: bool) {
+        self.write_protected = protected;
+    }
+
+    /// Drive of the last critical error (write protect, not ready, sharing), 0=A
+    pub fn error_drive(&self) -> u8 {
+        self.error_drive
+    }
+
+    fn critical(&mut self, code: u8, drive: u8) -> u8 {
+        self.error_drive = drive;
+        code
+    }
+
     /// Resolves a DOS path (e.g., "GAMES\DOOM.EXE" or "..\FILE.TXT")
     /// to a Host Path, ensuring it stays within `root_path`.
     /// Handles case-insensitivity and short filenames (8.3).
@@ -192,19 +248,17 @@ impl DiskController {
 
     // Helper to get virtual file size
     pub fn get_virtual_file_size(&self, filename: &str) -> u32 {
+        self.virtual_contents(filename)
+            .map_or(0, |data| data.len() as u32)
+    }
+
+    // Contents of a Z: file
+    fn virtual_contents(&self, filename: &str) -> Option<&Vec<u8>> {
         let upper = filename.to_ascii_uppercase();
         // Simplistic stripping
-        let name = if upper.starts_with("Z:") {
-            &upper[2..]
-        } else {
-            &upper
-        };
+        let name = upper.strip_prefix("Z:").unwrap_or(&upper);
         let name = name.trim_start_matches('\\').trim_start_matches('/');
-
-        if let Some(data) = self.virtual_files.get(name) {
-            return data.len() as u32;
-        }
-        0
+        self.virtual_files.get(name)
     }
 
     /// Helper to find a child in a directory matching DOS semantics
@@ -304,107 +358,396 @@ impl DiskController {
     // FILE I/O OPERATIONS
     // ========================================================================
 
-    // INT 21h, AH=3Dh: Open File
-    pub fn open_file(&mut self, filename: &str, mode: u8) -> Result<u16, u8> {
-        // Handle Virtual Z: files
+    /// Closes every file, leaving only the standard devices in the SFT
+    pub fn reset_files(&mut self) {
+        self.sft.clear();
+        self.sft.resize_with(SFT_SIZE, || None);
+        for (index, file) in [(SFT_CON, SftFile::Con), (SFT_AUX, SftFile::Aux), (SFT_PRN, SftFile::Prn)] {
+            self.sft[index as usize] = Some(SftEntry {
+                file,
+                mode: 0x02,
+                refs: 0,
+                path: None,
+                fcb_owner: None,
+            });
+        }
+    }
+
+    pub fn sft_entry(&self, index: u8) -> Option<&SftEntry> {
+        self.sft.get(index as usize)?.as_ref()
+    }
+
+    fn sft_entry_mut(&mut self, index: u8) -> Result<&mut SftEntry, u8> {
+        self.sft
+            .get_mut(index as usize)
+            .and_then(|e| e.as_mut())
+            .ok_or(0x06) // Invalid handle
+    }
+
+    /// Another handle now refers to SFT entry `index`
+    pub fn add_ref(&mut self, index: u8) {
+        if let Ok(entry) = self.sft_entry_mut(index) {
+            entry.refs += 1;
+        }
+    }
+
+    /// A handle to SFT entry `index` went away; the last one closes the file
+    pub fn release(&mut self, index: u8) {
+        let Ok(entry) = self.sft_entry_mut(index) else {
+            return;
+        };
+        entry.refs = entry.refs.saturating_sub(1);
+        if entry.refs == 0 && !entry.is_device() {
+            self.sft[index as usize] = None;
+        }
+    }
+
+    /// An FCB of process `psp` now refers to SFT entry `index`
+    pub fn add_fcb_ref(&mut self, index: u8, psp: u16) {
+        if let Ok(entry) = self.sft_entry_mut(index) {
+            entry.refs += 1;
+            entry.fcb_owner = Some(psp);
+        }
+    }
+
+    /// SFT entries process `psp` holds through FCBs
+    pub fn fcb_entries(&self, psp: u16) -> Vec<u8> {
+        (0..self.sft.len() as u8)
+            .filter(|&index| self.sft_entry(index).is_some_and(|e| e.fcb_owner == Some(psp)))
+            .collect()
+    }
+
+    fn free_slot(&self) -> Result<usize, u8> {
+        self.sft.iter().position(|e| e.is_none()).ok_or(0x04) // Too many open files
+    }
+
+    fn add_entry(&mut self, slot: usize, file: SftFile, mode: u8, path: Option<PathBuf>) -> u8 {
+        self.sft[slot] = Some(SftEntry {
+            file,
+            mode,
+            refs: 0,
+            path,
+            fcb_owner: None,
+        });
+        slot as u8
+    }
+
+    // Host path of a file to open, create, change or remove. Its directory
+    // must exist (03); Z: can't be changed (05). A: and B: are floppy drives
+    // with no disk in them.
+    fn host_file_path(&mut self, filename: &str) -> Result<PathBuf, u8> {
         if self.is_virtual_file(filename) {
-            // For now, we don't support actually reading/seeking virtual files with standard file handles
-            // nicely. We will just return a dummy handle and special case read/seek if needed?
-            // OR: We return an error if we don't support it, but since we just want EXEC to work,
-            // we might not need `open_file` to succeed for COMMAND.COM unless NC tries to read it.
-            // NC *does* check if COMMAND.COM exists.
-
-            // Let's create a temporary file or use a special handle range?
-            // Using a special handle range is cleaner.
-            let handle = 0xAA00 + (self.next_handle % 100);
-            self.next_handle += 1;
-            // storing nothing in `open_files` means read/write fails, which is fine for now
-            // or we could store a special marker.
-            // For this specific task (EXEC), NC just needs to know it exists or "load" it (which uses EXEC).
-            // EXEC loading handles file reading itself usually via `load_executable` in `cpu.rs`
-            // (which uses `open_file`? No, `load_executable` uses `disk.read_file` maybe?
-            // `cpu.load_executable` uses `disk.open_file` -> `read_file` flow usually).
-            // We'll see. If `open_file` succeeds, that's step 1.
-            return Ok(handle);
-        }
-
-        let path = self.resolve_path(filename).ok_or(0x03)?; // Path not found
-
-        let mut options = OpenOptions::new();
-        match mode & 0x03 {
-            0 => {
-                options.read(true);
+            return Err(0x05);
+        }
+        let drive = self.drive_of(filename);
+        if drive < 2 {
+            return Err(self.critical(NOT_READY, drive));
+        }
+        let path = self.resolve_path(filename).ok_or(0x03)?;
+        if !path.parent().is_some_and(Path::is_dir) {
+            return Err(0x03);
+        }
+        Ok(path)
+    }
+
+    // As host_file_path, for a change to the directory
+    fn writable_path(&mut self, filename: &str) -> Result<PathBuf, u8> {
+        let path = self.host_file_path(filename)?;
+        if self.write_protected {
+            return Err(self.critical(WRITE_PROTECTED, 2));
+        }
+        Ok(path)
+    }
+
+    // Sharing violation if an open file and an open of `path` with `mode`
+    // deny each other
+    fn check_sharing(&mut self, path: &Path, mode: u8) -> Result<(), u8> {
+        let conflict = self
+            .sft
+            .iter()
+            .flatten()
+            .any(|entry| entry.path.as_deref() == Some(path) && !shares(mode, entry.mode));
+        if conflict {
+            return Err(self.critical(SHARING_VIOLATION, 2));
+        }
+        Ok(())
+    }
+
+    // Files on C: refuse writes when it's write-protected
+    fn check_writable(&mut self, index: u8) -> Result<(), u8> {
+        let on_disk = self
+            .sft_entry(index)
+            .is_some_and(|entry| matches!(entry.file, SftFile::Disk(_)));
+        if on_disk && self.write_protected {
+            return Err(self.critical(WRITE_PROTECTED, 2));
+        }
+        Ok(())
+    }
+
+    // Drive a path names, 0=A
+    fn drive_of(&self, filename: &str) -> u8 {
+        match filename.as_bytes() {
+            [letter, b':', ..] => letter.to_ascii_uppercase().wrapping_sub(b'A'),
+            _ => self.current_drive,
+        }
+    }
+
+    // INT 21h, AH=3Dh: Open File
+    // Returns the SFT entry, with no references yet
+    pub fn open_file(&mut self, filename: &str, mode: u8) -> Result<u8, u8> {
+        let slot = self.free_slot()?;
+        if mode & 0x03 == 0x03 {
+            return Err(0x0C); // Invalid access code
+        }
+
+        let virtual_file = self
+            .is_virtual_file(filename)
+            .then(|| self.virtual_contents(filename))
+            .flatten();
+        let (file, path) = if let Some(contents) = virtual_file {
+            // Z: is read-only
+            if mode & 0x03 != 0 {
+                return Err(0x05);
+            }
+            (SftFile::Virtual(Cursor::new(contents.clone())), None)
+        } else {
+            let path = self
+                .host_file_path(filename)
+                .map_err(|e| if e == NOT_READY { e } else { 0x03 })?; // Path not found
+            let metadata = fs::metadata(&path).map_err(|_| 0x02)?; // File not found
+            if metadata.is_dir() || (mode & 0x03 != 0 && metadata.permissions().readonly()) {
+                return Err(0x05);
+            }
+            self.check_sharing(&path, mode)?;
+
+            let mut options = OpenOptions::new();
+            match mode & 0x03 {
+                0 => options.read(true),
+                1 => options.write(true),
+                _ => options.read(true).write(true),
+            };
+            let file = options.open(&path).map_err(|_| 0x05)?;
+            (SftFile::Disk(file), Some(path))
+        };
+
+        Ok(self.add_entry(slot, file, mode, path))
+    }
+
+    // INT 21h, AH=3Ch/5Bh: Create File, truncating an existing one unless
+    // `exclusive` (50h). Attribute bit 0 makes it read-only.
+    pub fn create_file(
+        &mut self,
+        filename: &str,
+        mode: u8,
+        attributes: u16,
+        exclusive: bool,
+    ) -> Result<u8, u8> {
+        let slot = self.free_slot()?;
+        // Volume labels and directories aren't files
+        if attributes & 0x18 != 0 {
+            return Err(0x05);
+        }
+        let path = self.writable_path(filename)?;
+        if let Ok(metadata) = fs::metadata(&path) {
+            if exclusive {
+                return Err(0x50); // File exists
             }
-            1 => {
-                options.write(true).create(true).truncate(false);
-            } // logic tweak for safety
-            2 => {
-                options.read(true).write(true).create(true);
+            if metadata.is_dir() || metadata.permissions().readonly() {
+                return Err(0x05);
             }
-            _ => return Err(0x0C),
+            self.check_sharing(&path, mode)?;
         }
 
-        match options.open(path) {
-            Ok(f) => {
-                let handle = self.next_handle;
-                self.next_handle += 1;
-                self.open_files.insert(handle, f);
-                // println!("[DISK] Opened '{}' as Handle {}", filename, handle);
-                Ok(handle)
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .map_err(|_| 0x05)?;
+        if attributes & 0x01 != 0 {
+            set_host_readonly(&path, true).map_err(|_| 0x05)?;
+        }
+        Ok(self.add_entry(slot, SftFile::Disk(file), mode, Some(path)))
+    }
+
+    // INT 21h, AH=5Ah: Create Temporary File in `dir`. Returns the entry and
+    // the generated name.
+    pub fn create_temp_file(&mut self, dir: &str, attributes: u16) -> Result<(u8, String), u8> {
+        let mut prefix = dir.to_string();
+        if !prefix.is_empty() && !prefix.ends_with('\\') && !prefix.ends_with(':') {
+            prefix.push('\\');
+        }
+        let seed = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as u32);
+        for n in 0..0x100 {
+            let name = format!("{:08X}", seed.wrapping_add(n));
+            match self.create_file(&format!("{}{}", prefix, name), 0x02, attributes, true) {
+                Err(0x50) => continue,
+                result => return result.map(|index| (index, name)),
+            }
+        }
+        Err(0x05)
+    }
+
+    // INT 21h, AH=6Ch: Extended Open/Create. The low nibble of `action` says
+    // what to do if the file exists (0 fail, 1 open, 2 replace), the high one
+    // if it doesn't (0 fail, 1 create). Returns the entry and what was done:
+    // 1 opened, 2 created, 3 replaced.
+    pub fn extended_open(
+        &mut self,
+        filename: &str,
+        mode: u8,
+        attributes: u16,
+        action: u16,
+    ) -> Result<(u8, u16), u8> {
+        let exists = self.is_virtual_file(filename)
+            || self
+                .resolve_path(filename)
+                .is_some_and(|path| path.exists());
+        match (exists, action & 0x0F, action >> 4) {
+            (true, 0x01, _) => self.open_file(filename, mode).map(|index| (index, 1)),
+            (true, 0x02, _) => self
+                .create_file(filename, mode, attributes, false)
+                .map(|index| (index, 3)),
+            (true, 0x00, _) => Err(0x50),
+            (false, _, 0x01) => self
+                .create_file(filename, mode, attributes, true)
+                .map(|index| (index, 2)),
+            (false, _, 0x00) => {
+                self.host_file_path(filename)?;
+                Err(0x02)
             }
-            Err(_) => Err(0x02),
+            _ => Err(0x01), // Invalid action
         }
     }
 
-    // INT 21h, AH=3Eh: Close File
-    pub fn close_file(&mut self, handle: u16) -> bool {
-        self.open_files.remove(&handle).is_some()
+    // INT 21h, AH=41h: Delete File
+    pub fn delete_file(&mut self, filename: &str) -> Result<(), u8> {
+        let path = self.writable_path(filename)?;
+        let metadata = fs::metadata(&path).map_err(|_| 0x02)?;
+        if metadata.is_dir() || metadata.permissions().readonly() {
+            return Err(0x05);
+        }
+        fs::remove_file(&path).map_err(|_| 0x05)
+    }
+
+    // INT 21h, AH=56h: Rename File. It can move a file to another directory
+    // of the same drive, but not over an existing one.
+    pub fn rename_file(&mut self, old_name: &str, new_name: &str) -> Result<(), u8> {
+        if self.drive_of(old_name) != self.drive_of(new_name) {
+            return Err(0x11); // Not same device
+        }
+        let from = self.writable_path(old_name)?;
+        if !from.exists() {
+            return Err(0x02);
+        }
+        let to = self.writable_path(new_name)?;
+        if to.exists() {
+            return Err(0x05);
+        }
+        fs::rename(from, to).map_err(|_| 0x05)
+    }
+
+    // INT 21h, AX=5700h: Get File Date and Time, as (time, date)
+    pub fn file_time(&mut self, index: u8) -> Result<(u16, u16), u8> {
+        let entry = self.sft_entry_mut(index)?;
+        match &entry.file {
+            SftFile::Disk(file) => file
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .map(to_dos_datetime)
+                .map_err(|_| 0x05),
+            // Z: files date from the day DOS started
+            SftFile::Virtual(_) => Ok((0x0000, 0x0021)),
+            _ => Ok(to_dos_datetime(SystemTime::now())),
+        }
+    }
+
+    // INT 21h, AX=5701h: Set File Date and Time
+    pub fn set_file_time(&mut self, index: u8, time: u16, date: u16) -> Result<(), u8> {
+        let entry = self.sft_entry_mut(index)?;
+        match &entry.file {
+            SftFile::Disk(file) => {
+                let modified = from_dos_datetime(time, date).ok_or(0x0D)?; // Invalid data
+                file.set_modified(modified).map_err(|_| 0x05)
+            }
+            SftFile::Virtual(_) => Err(0x05),
+            _ => Ok(()),
+        }
+    }
+
+    // Truncates or extends a file, as INT 21h 28h does with CX=0
+    pub fn set_file_size(&mut self, index: u8, size: u64) -> Result<(), u8> {
+        self.check_writable(index)?;
+        let entry = self.sft_entry_mut(index)?;
+        match &entry.file {
+            SftFile::Disk(file) => file.set_len(size).map_err(|_| 0x05),
+            SftFile::Virtual(_) => Err(0x05),
+            _ => Ok(()),
+        }
+    }
+
+    // INT 21h, AH=68h/6Ah: Commit File
+    pub fn commit_file(&mut self, index: u8) -> Result<(), u8> {
+        let entry = self.sft_entry_mut(index)?;
+        match &entry.file {
+            SftFile::Disk(file) => file.sync_all().map_err(|_| 0x05),
+            _ => Ok(()),
+        }
     }
 
     // INT 21h, AH=3Fh: Read from File
-    pub fn read_file(&mut self, handle: u16, count: usize) -> Result<Vec<u8>, u16> {
-        if let Some(file) = self.open_files.get_mut(&handle) {
-            let mut buffer = vec![0u8; count];
-            match file.read(&mut buffer) {
-                Ok(bytes_read) => {
-                    buffer.truncate(bytes_read);
-                    Ok(buffer)
-                }
-                Err(_) => Err(0x05),
+    pub fn read_file(&mut self, index: u8, count: usize) -> Result<Vec<u8>, u16> {
+        let entry = self.sft_entry_mut(index).map_err(u16::from)?;
+        let mut buffer = vec![0u8; count];
+        let result = match &mut entry.file {
+            SftFile::Disk(file) => file.read(&mut buffer),
+            SftFile::Virtual(cursor) => cursor.read(&mut buffer),
+            // AUX and PRN have nothing to read
+            _ => Ok(0),
+        };
+        match result {
+            Ok(bytes_read) => {
+                buffer.truncate(bytes_read);
+                Ok(buffer)
             }
-        } else {
-            Err(0x06)
+            Err(_) => Err(0x05),
         }
     }
 
     // INT 21h, AH=40h: Write to File
-    pub fn write_file(&mut self, handle: u16, data: &[u8]) -> Result<u16, u8> {
-        if let Some(file) = self.open_files.get_mut(&handle) {
-            match file.write(data) {
+    pub fn write_file(&mut self, index: u8, data: &[u8]) -> Result<u16, u8> {
+        self.check_writable(index)?;
+        let entry = self.sft_entry_mut(index)?;
+        match &mut entry.file {
+            SftFile::Disk(file) => match file.write(data) {
                 Ok(bytes_written) => Ok(bytes_written as u16),
                 Err(_) => Err(0x05),
-            }
-        } else {
-            Err(0x06)
+            },
+            SftFile::Virtual(_) => Err(0x05),
+            // Nothing is attached to AUX and PRN
+            _ => Ok(data.len() as u16),
         }
     }
 
     // INT 21h, AH=42h: Seek
-    pub fn seek_file(&mut self, handle: u16, offset: i64, origin: u8) -> Result<u64, u16> {
-        if let Some(file) = self.open_files.get_mut(&handle) {
-            let seek_from = match origin {
-                0 => SeekFrom::Start(offset as u64),
-                1 => SeekFrom::Current(offset),
-                2 => SeekFrom::End(offset),
-                _ => return Err(0x01),
-            };
-            match file.seek(seek_from) {
-                Ok(new_pos) => Ok(new_pos),
-                Err(_) => Err(0x19),
-            }
-        } else {
-            Err(0x06)
-        }
+    pub fn seek_file(&mut self, index: u8, offset: i64, origin: u8) -> Result<u64, u16> {
+        let entry = self.sft_entry_mut(index).map_err(u16::from)?;
+        let seek_from = match origin {
+            0 => SeekFrom::Start(offset as u64),
+            1 => SeekFrom::Current(offset),
+            2 => SeekFrom::End(offset),
+            _ => return Err(0x01),
+        };
+        let result = match &mut entry.file {
+            SftFile::Disk(file) => file.seek(seek_from),
+            SftFile::Virtual(cursor) => cursor.seek(seek_from),
+            // Devices stay at position 0
+            _ => Ok(0),
+        };
+        result.map_err(|_| 0x19)
     }
 
     // ========================================================================
@@ -431,20 +774,40 @@ impl DiskController {
         }
     }
 
-    // INT 21h, AH=43h: Get File Attributes
-    // Returns: Attribute Byte (0x20 = Archive, 0x10 = Subdir, etc.)
-    #[allow(dead_code)]
+    // INT 21h, AX=4300h: Get File Attributes
+    // Returns: Attribute Byte (0x20 = Archive, 0x10 = Subdir, 0x01 = Read-only)
     pub fn get_file_attribute(&self, filename: &str) -> Result<u16, u8> {
+        if self.is_virtual_file(filename) {
+            return Ok(0x21);
+        }
         let path = self.resolve_path(filename).ok_or(0x03)?;
-        if path.exists() {
-            if path.is_dir() {
-                Ok(0x10) // Directory
+        let metadata = fs::metadata(&path).map_err(|_| {
+            if path.parent().is_some_and(Path::is_dir) {
+                0x02 // File Not Found
             } else {
-                Ok(0x20) // Archive (Standard File)
+                0x03
             }
+        })?;
+        if metadata.is_dir() {
+            Ok(0x10) // Directory
         } else {
-            Err(0x02) // File Not Found
+            // Archive (Standard File)
+            Ok(0x20 | metadata.permissions().readonly() as u16)
+        }
+    }
+
+    // INT 21h, AX=4301h: Set File Attributes
+    // Only read-only reaches the host; hidden, system and archive are accepted
+    pub fn set_file_attribute(&mut self, filename: &str, attributes: u16) -> Result<(), u8> {
+        if attributes & 0x18 != 0 {
+            return Err(0x05); // Can't turn a file into a directory or label
         }
+        let path = self.writable_path(filename)?;
+        let metadata = fs::metadata(&path).map_err(|_| 0x02)?;
+        if metadata.is_dir() {
+            return Err(0x05);
+        }
+        set_host_readonly(&path, attributes & 0x01 != 0).map_err(|_| 0x05)
     }
 
     // Returns the path string relative to root, e.g., "GAMES\DOOM"
@@ -680,19 +1043,8 @@ impl DiskController {
                     continue;
                 }
 
-                let sys_time = metadata.modified().unwrap_or(std::time::SystemTime::now());
-                let datetime: DateTime<Local> = sys_time.into();
-                let dos_time = ((datetime.hour() as u16) << 11)
-                    | ((datetime.minute() as u16) << 5)
-                    | ((datetime.second() as u16) / 2);
-                let year = datetime.year();
-                let dos_date = if year < 1980 {
-                    0x0021
-                } else {
-                    (((year - 1980) as u16) << 9)
-                        | ((datetime.month() as u16) << 5)
-                        | (datetime.day() as u16)
-                };
+                let sys_time = metadata.modified().unwrap_or(SystemTime::now());
+                let (dos_time, dos_date) = to_dos_datetime(sys_time);
 
                 valid_entries.push(DosDirEntry {
                     filename: final_name,
@@ -712,3 +1064,61 @@ impl DiskController {
         }
     }
 }
+
+/// Host time to DOS time and date words. Dates before 1980 become 1-1-1980.
+pub fn to_dos_datetime(time: SystemTime) -> (u16, u16) {
+    let datetime: DateTime<Local> = time.into();
+    let dos_time = ((datetime.hour() as u16) << 11)
+        | ((datetime.minute() as u16) << 5)
+        | ((datetime.second() as u16) / 2);
+    let year = datetime.year();
+    let dos_date = if year < 1980 {
+        0x0021
+    } else {
+        (((year - 1980) as u16) << 9) | ((datetime.month() as u16) << 5) | (datetime.day() as u16)
+    };
+    (dos_time, dos_date)
+}
+
+/// DOS time and date words to host time, or None if they don't make a date
+pub fn from_dos_datetime(time: u16, date: u16) -> Option<SystemTime> {
+    let datetime = Local
+        .with_ymd_and_hms(
+            1980 + (date >> 9) as i32,
+            ((date >> 5) & 0x0F) as u32,
+            (date & 0x1F) as u32,
+            (time >> 11) as u32,
+            ((time >> 5) & 0x3F) as u32,
+            ((time & 0x1F) * 2) as u32,
+        )
+        .earliest()?;
+    Some(datetime.into())
+}
+
+// Sets or clears the owner's write permission, DOS's read-only attribute
+// Whether an open with `mode` can share a file opened with `other`. Bits 4-6
+// are the sharing mode: 1 denies others reading and writing, 2 writing, 3
+// reading, 4 nothing. Compatibility mode (0) is taken as denying nothing.
+fn shares(mode: u8, other: u8) -> bool {
+    let denies = |mode: u8, access: u8| match (mode >> 4) & 0x07 {
+        1 => true,
+        2 => access != 0,
+        3 => access != 1,
+        _ => false,
+    };
+    !denies(other, mode & 0x03) && !denies(mode, other & 0x03)
+}
+
+fn set_host_readonly(path: &Path, readonly: bool) -> std::io::Result<()> {
+    let mut permissions = fs::metadata(path)?.permissions();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = permissions.mode();
+        permissions.set_mode(if readonly { mode & !0o222 } else { mode | 0o200 });
+    }
+    #[cfg(not(unix))]
+    #[allow(clippy::permissions_set_readonly_false)]
+    permissions.set_readonly(readonly);
+    fs::set_permissions(path, permissions)
+}
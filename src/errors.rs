@@ -0,0 +1,112 @@
+use crate::bus::Bus;
+
+// DOS error state: the extended error information INT 21h 59h reports, and
+// critical errors on their way to the program's INT 24h handler.
+//
+// Every INT 21h call that fails records its error code here. Codes 13h-21h
+// are critical: device trouble the user may be able to fix, so before the
+// call returns DOS asks the INT 24h handler whether to abort the program,
+// retry, ignore the error or fail the call.
+
+pub const WRITE_PROTECTED: u8 = 0x13;
+pub const NOT_READY: u8 = 0x15;
+pub const SHARING_VIOLATION: u8 = 0x20;
+/// What a call returns when the INT 24h handler chose Fail
+pub const FAIL_ON_INT24: u8 = 0x53;
+
+// Error classes
+const CLASS_OUT_OF_RESOURCE: u8 = 0x01;
+const CLASS_AUTHORIZATION: u8 = 0x03;
+const CLASS_HARDWARE: u8 = 0x05;
+const CLASS_APPLICATION: u8 = 0x07;
+const CLASS_NOT_FOUND: u8 = 0x08;
+const CLASS_BAD_FORMAT: u8 = 0x09;
+const CLASS_LOCKED: u8 = 0x0A;
+const CLASS_MEDIA: u8 = 0x0B;
+const CLASS_ALREADY_EXISTS: u8 = 0x0C;
+const CLASS_UNKNOWN: u8 = 0x0D;
+
+// Suggested actions
+const ACTION_DELAYED_RETRY: u8 = 0x02;
+const ACTION_PROMPT_USER: u8 = 0x03;
+const ACTION_ABORT: u8 = 0x04;
+const ACTION_IMMEDIATE_ABORT: u8 = 0x05;
+const ACTION_USER_RETRY: u8 = 0x07;
+
+// Where the error happened
+const LOCUS_UNKNOWN: u8 = 0x01;
+const LOCUS_BLOCK_DEVICE: u8 = 0x02;
+const LOCUS_MEMORY: u8 = 0x05;
+
+/// INT 21h 59h: the last error, with its class, suggested action and locus
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExtendedError {
+    pub code: u8,
+    pub class: u8,
+    pub action: u8,
+    pub locus: u8,
+}
+
+impl ExtendedError {
+    pub fn new(code: u8) -> Self {
+        let (class, action, locus) = match code {
+            0x01 | 0x06 | 0x0C => (CLASS_APPLICATION, ACTION_ABORT, LOCUS_UNKNOWN),
+            0x02 | 0x03 | 0x0F | 0x12 => (CLASS_NOT_FOUND, ACTION_PROMPT_USER, LOCUS_BLOCK_DEVICE),
+            0x04 => (CLASS_OUT_OF_RESOURCE, ACTION_ABORT, LOCUS_UNKNOWN),
+            0x05 | 0x10 => (CLASS_AUTHORIZATION, ACTION_PROMPT_USER, LOCUS_BLOCK_DEVICE),
+            0x07 => (CLASS_APPLICATION, ACTION_IMMEDIATE_ABORT, LOCUS_MEMORY),
+            0x08 => (CLASS_OUT_OF_RESOURCE, ACTION_ABORT, LOCUS_MEMORY),
+            0x09 | 0x0A => (CLASS_APPLICATION, ACTION_ABORT, LOCUS_MEMORY),
+            0x0B | 0x0D => (CLASS_BAD_FORMAT, ACTION_ABORT, LOCUS_UNKNOWN),
+            0x11 => (CLASS_UNKNOWN, ACTION_PROMPT_USER, LOCUS_BLOCK_DEVICE),
+            WRITE_PROTECTED | NOT_READY => (CLASS_MEDIA, ACTION_USER_RETRY, LOCUS_BLOCK_DEVICE),
+            0x14..=0x1F => (CLASS_HARDWARE, ACTION_USER_RETRY, LOCUS_BLOCK_DEVICE),
+            0x20 | 0x21 => (CLASS_LOCKED, ACTION_DELAYED_RETRY, LOCUS_BLOCK_DEVICE),
+            0x50 => (CLASS_ALREADY_EXISTS, ACTION_PROMPT_USER, LOCUS_BLOCK_DEVICE),
+            _ => (CLASS_UNKNOWN, ACTION_ABORT, LOCUS_UNKNOWN),
+        };
+        Self {
+            code,
+            class,
+            action,
+            locus,
+        }
+    }
+}
+
+/// A critical error: the extended error code and the drive it happened on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CriticalError {
+    pub code: u8,
+    pub drive: u8,
+    /// AH for the handler: disk area, read or write, and the answers allowed
+    pub info: u8,
+    /// The default handler has shown its prompt
+    pub prompted: bool,
+}
+
+#[derive(Default)]
+pub struct DosErrors {
+    pub last: ExtendedError,
+    /// Run into by the current INT 21h call, for INT 24h once it's done
+    pub pending: Option<CriticalError>,
+    /// Being handled: the INT 24h handler is running
+    pub active: Option<CriticalError>,
+}
+
+pub fn is_critical(code: u8) -> bool {
+    (WRITE_PROTECTED..=0x21).contains(&code)
+}
+
+/// Records a failed call. Critical errors are left pending for INT 24h.
+pub fn record(bus: &mut Bus, code: u8) {
+    bus.errors.last = ExtendedError::new(code);
+    if is_critical(code) {
+        bus.errors.pending = Some(CriticalError {
+            code,
+            drive: bus.disk.error_drive(),
+            info: 0,
+            prompted: false,
+        });
+    }
+}
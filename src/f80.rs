@@ -1,3 +1,5 @@
+use std::cmp::Ordering;
+
 pub const FPU_80_BITS_MAX: u128 = (1u128 << 80) - 1;
 pub const REAL_INDEFINITE: u128 = 0xffff_c000_0000_0000_0000 & FPU_80_BITS_MAX;
 pub const QNAN: u128 = 0x7fff_c000_0000_0000_0000 & FPU_80_BITS_MAX;
@@ -10,9 +12,9 @@ pub const MANTISSA_MASK: u128 = (1u128 << 64) - 1;
 pub const MANTISSA_MASK_NOINT: u128 = 0x7FFF_FFFF_FFFF_FFFF;
 pub const INT_BIT_MASK: u128 = 1 << 63;
 pub const INT_BIT_MASK64: u64 = 1 << 63;
-pub const BCD_SIGN_POSITIVE: u8 = 0x0A;
-pub const BCD_SIGN_NEGATIVE: u8 = 0x0B;
-pub const F64_EXP_BIAS: i32 = 1023;
+// Byte 9 of a packed BCD value: only bit 7 (the sign) is significant
+pub const BCD_SIGN_POSITIVE: u8 = 0x00;
+pub const BCD_SIGN_NEGATIVE: u8 = 0x80;
 pub const F80_EXP_BIAS: i32 = 16383;
 #[allow(dead_code)]
 pub const MANTISSA_BITS: u32 = 64;
@@ -175,150 +177,14 @@ impl F80 {
         (mantissa as u64) | (int_bit << 63)
     }
 
-    fn normalize_add(&self, mantissa: u64, exponent: u16) -> (u64, u16) {
-        let mut man = mantissa;
-        let mut exp = exponent;
-        if man == 0 {
-            return (0, 0);
-        }
-        while (man & INT_BIT_MASK64) == 0 {
-            man <<= 1;
-            exp -= 1;
-        }
-        (man, exp)
-    }
-
+    /// Adds `b` with round-to-nearest at full precision
     pub fn add(&mut self, b: F80) {
-        let a_sign = self.get_sign();
-        let b_sign = b.get_sign();
-
-        let mut a_exp = self.get_exponent();
-        let b_exp = b.get_exponent();
-
-        let mut a_man = self.get_mantissa() as u128;
-        let mut b_man = b.get_mantissa() as u128;
-
-        // Clamp the shift amount. 
-        // Shifting a u128 by >= 128 causes a Rust panic.
-        if a_exp > b_exp {
-            let shift = (a_exp - b_exp) as u32;
-            if shift >= 128 {
-                b_man = 0; // Underflow: b is negligible compared to a
-            } else {
-                b_man >>= shift;
-            }
-        } else if b_exp > a_exp {
-            let shift = (b_exp - a_exp) as u32;
-            if shift >= 128 {
-                a_man = 0; // Underflow: a is negligible compared to b
-            } else {
-                a_man >>= shift;
-            }
-            a_exp = b_exp;
-        }
-
-        let mut result_sign;
-        let mut result_exp = a_exp;
-        let mut result_man: u128;
-
-        if a_sign == b_sign {
-            result_man = a_man + b_man;
-            if (result_man & (1u128 << 64)) != 0 {
-                result_man >>= 1;
-                result_exp += 1;
-            }
-            result_sign = a_sign;
-        } else {
-            if a_man >= b_man {
-                result_man = a_man - b_man;
-                result_sign = a_sign;
-            } else {
-                result_man = b_man - a_man;
-                result_sign = b_sign;
-            }
-            let (norm_man, norm_exp) = self.normalize_add(result_man as u64, result_exp);
-            result_man = norm_man as u128;
-            result_exp = norm_exp;
-
-            if result_man == 0 {
-                result_sign = false;
-                result_exp = 0;
-            }
-        }
-
-        self.set_sign(result_sign);
-        self.set_exponent(result_exp);
-        self.set_mantissa(result_man as u64);
+        *self = self.add_with(b, &mut FpEnv::default());
     }
 
+    /// Subtracts `b` with round-to-nearest at full precision
     pub fn sub(&mut self, b: F80) {
-        let a_sign = self.get_sign();
-        let b_sign = b.get_sign();
-
-        let mut a_exp = self.get_exponent();
-        let b_exp = b.get_exponent();
-
-        let mut a_man = self.get_mantissa() as u128;
-        let mut b_man = b.get_mantissa() as u128;
-
-        // Clamp the shift amount.
-        if a_exp > b_exp {
-            let shift = (a_exp - b_exp) as u32;
-            if shift >= 128 {
-                b_man = 0;
-            } else {
-                b_man >>= shift;
-            }
-        } else if b_exp > a_exp {
-            let shift = (b_exp - a_exp) as u32;
-            if shift >= 128 {
-                a_man = 0;
-            } else {
-                a_man >>= shift;
-            }
-            a_exp = b_exp;
-        }
-
-        let mut result_sign;
-        let mut result_exp = a_exp;
-        let mut result_man: u128;
-
-        if a_sign != b_sign {
-            result_man = a_man + b_man;
-            if (result_man & (1u128 << 64)) != 0 {
-                result_man >>= 1;
-                result_exp += 1;
-            }
-            result_sign = a_sign;
-        } else {
-            if a_man >= b_man {
-                result_man = a_man - b_man;
-                result_sign = a_sign;
-            } else {
-                result_man = b_man - a_man;
-                result_sign = !a_sign;
-            }
-            let (norm_man, norm_exp) = self.normalize_sub(result_man as u64, result_exp);
-            result_man = norm_man as u128;
-            result_exp = norm_exp;
-
-            if result_man == 0 {
-                result_sign = false; // +0
-                result_exp = 0;
-            }
-        }
-
-        self.set_sign(result_sign);
-        self.set_exponent(result_exp);
-        self.set_mantissa(result_man as u64);
-    }
-
-    fn normalize_sub(&self, mut mant: u64, mut exp: u16) -> (u64, u16) {
-        while mant != 0 && (mant & (1 << 63)) == 0 {
-            mant <<= 1;
-            exp -= 1;
-        }
-        (mant, exp)
+        *self = self.sub_with(b, &mut FpEnv::default());
     }
 
     #[allow(dead_code)]
@@ -342,16 +208,18 @@ impl F80 {
         exponent == 0
     }
 
+    // The explicit integer bit is set for infinities and NaNs alike,
+    // only the fraction tells them apart
     pub fn is_infinite(&self) -> bool {
         let exponent = (self.st >> 64) & 0x7FFF;
-        let mantissa = self.st & ((1u128 << 64) - 1);
-        exponent == 0x7FFF && mantissa == 0
+        let fraction = self.st & MANTISSA_MASK_NOINT;
+        exponent == 0x7FFF && fraction == 0
     }
 
     pub fn is_nan(&self) -> bool {
         let exponent = (self.st >> 64) & 0x7FFF;
-        let mantissa = self.st & MANTISSA_MASK;
-        exponent == 0x7FFF && mantissa != 0
+        let fraction = self.st & MANTISSA_MASK_NOINT;
+        exponent == 0x7FFF && fraction != 0
     }
 
     pub fn to_bcd_packed(&self) -> [u8; 10] {
@@ -376,25 +244,19 @@ impl F80 {
         bcd
     }
 
-    #[allow(dead_code)]
     pub fn from_bcd_packed(&mut self, bcd: &[u8; 10]) {
         let mut value: u128 = 0;
 
+        // Digits above 9 give undefined results on real hardware, so just let them through
         for i in (0..9).rev() {
             let byte = bcd[i];
             let hi = (byte >> 4) & 0x0F;
             let lo = byte & 0x0F;
 
-            assert!(hi <= 9 && lo <= 9, "Invalid BCD digit");
-
             value = value * 100 + (hi as u128) * 10 + (lo as u128);
         }
 
-        let is_negative = match bcd[9] & 0x0F {
-            BCD_SIGN_NEGATIVE => true,
-            BCD_SIGN_POSITIVE => false,
-            _ => panic!("Invalid BCD sign"),
-        };
+        let is_negative = bcd[9] & BCD_SIGN_NEGATIVE != 0;
 
         self.st = F80::encode_from_u128(value, is_negative);
     }
@@ -442,63 +304,1307 @@ impl F80 {
     }
 
     pub fn set_f64(&mut self, value: f64) {
-        let bits = value.to_bits();
-        let sign = (bits >> 63) & 1;
-        let exp = ((bits >> 52) & 0x7FF) as u16;
-        let mantissa = bits & 0xFFFFFFFFFFFFF;
-
-        let st = if exp == 0 {
-            // Subnormal or zero in f64 → represent as 0.0 en FPU
-            (sign as u128) << 79
-        } else if exp == 0x7FF {
-            // Inf o NaN
-            let is_nan = mantissa != 0;
-            let extended_exp = 0x7FFFu128;
-            let extended_mantissa = (mantissa as u128) << 11;
-            let nan_bit = if is_nan { 1u128 << 62 } else { 0 }; // QNaN bit (optional)
-            ((sign as u128) << 79) | (extended_exp << 64) | extended_mantissa | nan_bit
+        // Every double fits exactly
+        *self = F80::from_f64_bits(value.to_bits(), &mut FpEnv::default());
+    }
+
+    pub fn get_f64(&self) -> f64 {
+        f64::from_bits(self.to_f64_bits(&mut FpEnv::default()))
+    }
+}
+
+// --- Software floating point ---
+//
+// Bit-exact 80-bit arithmetic. Operands are unpacked into a sign, a biased
+// exponent and a significand with the integer bit at the top, the operation is
+// carried out exactly (or with a sticky bit standing in for everything that was
+// shifted out), and the result is rounded once, honouring the RC and PC fields
+// of the control word the same way the 387 does.
+
+// Exception bits, in the same positions as the x87 status word
+pub const FP_INVALID: u8 = 0x01;
+pub const FP_DENORMAL: u8 = 0x02;
+pub const FP_ZERO_DIVIDE: u8 = 0x04;
+pub const FP_OVERFLOW: u8 = 0x08;
+pub const FP_UNDERFLOW: u8 = 0x10;
+pub const FP_PRECISION: u8 = 0x20;
+
+const BIAS: i32 = F80_EXP_BIAS;
+const MAX_EXP: i32 = 0x7FFF;
+const QUIET_BIT: u64 = 1 << 62;
+
+/// Rounding control (RC, control word bits 10-11)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    Nearest,
+    Down,
+    Up,
+    Zero,
+}
+
+/// Precision control (PC, control word bits 8-9)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Precision {
+    Single,
+    Double,
+    Extended,
+}
+
+impl Precision {
+    fn bits(self) -> u32 {
+        match self {
+            Precision::Single => 24,
+            Precision::Double => 53,
+            Precision::Extended => 64,
+        }
+    }
+}
+
+/// Rounding settings for an operation, and the exceptions it raised
+#[derive(Debug, Clone, Copy)]
+pub struct FpEnv {
+    pub rounding: Rounding,
+    pub precision: Precision,
+    /// A masked underflow is only reported when the tiny result is also inexact
+    pub underflow_masked: bool,
+    /// FP_* bits raised so far
+    pub exceptions: u8,
+    /// The last rounding went away from zero (reported in C1)
+    pub rounded_up: bool,
+}
+
+impl Default for FpEnv {
+    fn default() -> Self {
+        Self {
+            rounding: Rounding::Nearest,
+            precision: Precision::Extended,
+            underflow_masked: true,
+            exceptions: 0,
+            rounded_up: false,
+        }
+    }
+}
+
+impl FpEnv {
+    pub fn from_control_word(cw: u16) -> Self {
+        let rounding = match (cw >> 10) & 3 {
+            0 => Rounding::Nearest,
+            1 => Rounding::Down,
+            2 => Rounding::Up,
+            _ => Rounding::Zero,
+        };
+        // PC=01 is reserved, the 387 treats it like extended
+        let precision = match (cw >> 8) & 3 {
+            0 => Precision::Single,
+            2 => Precision::Double,
+            _ => Precision::Extended,
+        };
+        Self {
+            rounding,
+            precision,
+            underflow_masked: cw & 0x10 != 0,
+            ..Self::default()
+        }
+    }
+}
+
+// Unpacked operand
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Class {
+    Zero,
+    Finite,
+    Infinity,
+    NaN,
+    // Unnormals, pseudo-NaNs and pseudo-infinities: the 387 rejects these
+    Unsupported,
+}
+
+#[derive(Clone, Copy)]
+struct Unpacked {
+    class: Class,
+    sign: bool,
+    // Biased exponent. Denormals are normalized, so this can go below 1.
+    exp: i32,
+    // Integer bit in bit 63
+    sig: u64,
+}
+
+// Result of rounding a significand to a given width
+enum Rounded {
+    // exp is 0 when the result stayed denormal
+    Finite { exp: i32, sig: u128 },
+    Overflow,
+}
+
+// Shift right, ORing everything shifted out into bit 0
+fn shift_right_jam(x: u128, n: u32) -> u128 {
+    if n == 0 {
+        x
+    } else if n < 128 {
+        (x >> n) | ((x << (128 - n)) != 0) as u128
+    } else {
+        (x != 0) as u128
+    }
+}
+
+// Rounds `sig` (top bit set, value = sig / 2^127 * 2^(exp - bias)) to `bits`
+// significant bits in a format whose all-ones exponent is `max_exp`.
+// `exp` is already biased for the target format.
+fn round_sig(sign: bool, mut exp: i32, mut sig: u128, bits: u32, max_exp: i32, env: &mut FpEnv) -> Rounded {
+    let shift = 128 - bits;
+    let mask = (1u128 << shift) - 1;
+    let half = 1u128 << (shift - 1);
+
+    // Tininess is detected before rounding, like the 387 does
+    let tiny = exp <= 0;
+    if tiny {
+        sig = shift_right_jam(sig, (1 - exp) as u32);
+        exp = 1;
+    }
+
+    let rem = sig & mask;
+    let inexact = rem != 0;
+    let increment = match env.rounding {
+        Rounding::Nearest => rem > half || (rem == half && (sig >> shift) & 1 == 1),
+        Rounding::Up => inexact && !sign,
+        Rounding::Down => inexact && sign,
+        Rounding::Zero => false,
+    };
+
+    sig &= !mask;
+    if increment {
+        let (sum, carry) = sig.overflowing_add(1u128 << shift);
+        if carry {
+            sig = 1u128 << 127;
+            exp += 1;
+        } else {
+            sig = sum;
+        }
+    }
+
+    if tiny {
+        if inexact || !env.underflow_masked {
+            env.exceptions |= FP_UNDERFLOW;
+        }
+        if sig >> 127 == 0 {
+            exp = 0;
+        }
+    }
+
+    if exp >= max_exp {
+        env.exceptions |= FP_OVERFLOW | FP_PRECISION;
+        return Rounded::Overflow;
+    }
+
+    env.rounded_up = increment;
+    if inexact {
+        env.exceptions |= FP_PRECISION;
+    }
+    Rounded::Finite { exp, sig }
+}
+
+// Largest finite value with the given significand width, or infinity, depending on RC
+fn overflow_to_infinity(sign: bool, env: &mut FpEnv) -> bool {
+    let to_inf = match env.rounding {
+        Rounding::Nearest => true,
+        Rounding::Zero => false,
+        Rounding::Up => !sign,
+        Rounding::Down => sign,
+    };
+    env.rounded_up = to_inf;
+    to_inf
+}
+
+impl F80 {
+    fn pack(sign: bool, exp: u16, mantissa: u64) -> F80 {
+        F80 {
+            st: ((sign as u128) << SIGN_SHIFT) | ((exp as u128) << EXP_SHIFT) | mantissa as u128,
+        }
+    }
+
+    pub fn zero(sign: bool) -> F80 {
+        F80::pack(sign, 0, 0)
+    }
+
+    pub fn infinity(sign: bool) -> F80 {
+        F80::pack(sign, MAX_EXP as u16, INT_BIT_MASK64)
+    }
+
+    pub fn indefinite() -> F80 {
+        F80 { st: REAL_INDEFINITE }
+    }
+
+    fn unpack(&self, env: &mut FpEnv) -> Unpacked {
+        let sign = self.get_sign();
+        let exp = self.get_exponent() as i32;
+        let sig = self.get_mantissa();
+        let int_bit = sig & INT_BIT_MASK64 != 0;
+
+        let (class, exp, sig) = if exp == MAX_EXP {
+            if !int_bit {
+                (Class::Unsupported, exp, sig)
+            } else if sig << 1 == 0 {
+                (Class::Infinity, exp, sig)
+            } else {
+                (Class::NaN, exp, sig)
+            }
+        } else if exp == 0 {
+            if sig == 0 {
+                (Class::Zero, 0, 0)
+            } else {
+                // Denormal (or pseudo-denormal): same scale as exponent 1
+                env.exceptions |= FP_DENORMAL;
+                let lz = sig.leading_zeros();
+                (Class::Finite, 1 - lz as i32, sig << lz)
+            }
+        } else if !int_bit {
+            (Class::Unsupported, exp, sig)
         } else {
-            // Normal number
-            let adjusted_exp = (exp as i32 - F64_EXP_BIAS) + F80_EXP_BIAS;
-            let extended_exp = adjusted_exp as u128;
-            let full_mantissa = ((1u64 << 52) | mantissa) as u128; // add implicit bit
-            let extended_mantissa = full_mantissa << 11; // 63 bits align
-            ((sign as u128) << 79) | (extended_exp << 64) | extended_mantissa
+            (Class::Finite, exp, sig)
         };
 
-        self.set(st); // masked setter
+        Unpacked { class, sign, exp, sig }
     }
 
-    pub fn get_f64(&self) -> f64 {
-        let value = self.get();
-        let sign = ((value >> 79) & 1) as u64;
-        let exp = ((value >> 64) & 0x7FFF) as u16;
-        let mantissa = value & 0xFFFFFFFFFFFFFFFF;
-
-        let f64_bits: u64 = if exp == 0 {
-            // Zero or denormal extended → 0.0
-            sign << 63
-        } else if exp == 0x7FFF {
-            // Inf or NaN
-            let f64_mantissa = (mantissa >> 11) as u64;
-            let nan_mask = if f64_mantissa != 0 { 1 << 51 } else { 0 }; // set QNaN bit if needed
-            (sign << 63) | (0x7FF << 52) | f64_mantissa | nan_mask
+    fn is_signaling(&self) -> bool {
+        self.is_nan() && self.get_mantissa() & QUIET_BIT == 0
+    }
+
+    fn quieted(&self) -> F80 {
+        F80 { st: self.st | QUIET_BIT as u128 }
+    }
+
+    // Rounds an exact result to the precision in `env` and packs it
+    fn round_pack(sign: bool, exp: i32, sig: u128, env: &mut FpEnv) -> F80 {
+        if sig == 0 {
+            return F80::zero(sign);
+        }
+        let lz = sig.leading_zeros();
+        let sig = sig << lz;
+        let exp = exp - lz as i32;
+
+        let bits = env.precision.bits();
+        match round_sig(sign, exp, sig, bits, MAX_EXP, env) {
+            Rounded::Finite { exp, sig } => F80::pack(sign, exp as u16, (sig >> 64) as u64),
+            Rounded::Overflow => {
+                if overflow_to_infinity(sign, env) {
+                    F80::infinity(sign)
+                } else {
+                    F80::pack(sign, (MAX_EXP - 1) as u16, u64::MAX << (64 - bits))
+                }
+            }
+        }
+    }
+
+    // NaN operands and unsupported encodings. Returns the result if there was one.
+    fn propagate_nan(a: &F80, ua: &Unpacked, b: Option<(&F80, &Unpacked)>, env: &mut FpEnv) -> Option<F80> {
+        let b_class = b.map(|(_, ub)| ub.class);
+        if ua.class == Class::Unsupported || b_class == Some(Class::Unsupported) {
+            env.exceptions |= FP_INVALID;
+            return Some(F80::indefinite());
+        }
+
+        let a_nan = ua.class == Class::NaN;
+        let b_nan = b_class == Some(Class::NaN);
+        if !a_nan && !b_nan {
+            return None;
+        }
+
+        if a.is_signaling() || b.is_some_and(|(b, _)| b.is_signaling()) {
+            env.exceptions |= FP_INVALID;
+        }
+
+        // Two NaNs: the one with the larger significand wins
+        let result = match b {
+            Some((b, _)) if b_nan && (!a_nan || (b.get_mantissa() | QUIET_BIT) > (a.get_mantissa() | QUIET_BIT)) => *b,
+            _ => *a,
+        };
+        Some(result.quieted())
+    }
+
+    fn invalid(env: &mut FpEnv) -> F80 {
+        env.exceptions |= FP_INVALID;
+        F80::indefinite()
+    }
+
+    /// self + b, rounded according to `env`
+    pub fn add_with(&self, b: F80, env: &mut FpEnv) -> F80 {
+        let ua = self.unpack(env);
+        let ub = b.unpack(env);
+        if let Some(nan) = F80::propagate_nan(self, &ua, Some((&b, &ub)), env) {
+            return nan;
+        }
+
+        match (ua.class, ub.class) {
+            (Class::Infinity, Class::Infinity) if ua.sign != ub.sign => return F80::invalid(env),
+            (Class::Infinity, _) => return F80::infinity(ua.sign),
+            (_, Class::Infinity) => return F80::infinity(ub.sign),
+            (Class::Zero, Class::Zero) => {
+                // Opposite zeros give +0, or -0 when rounding down
+                let sign = if ua.sign == ub.sign { ua.sign } else { env.rounding == Rounding::Down };
+                return F80::zero(sign);
+            }
+            _ => {}
+        }
+
+        // Significands at bit 126, leaving room for the carry
+        let (mut hi, mut lo) = (ua, ub);
+        if lo.class != Class::Zero && (hi.class == Class::Zero || lo.exp > hi.exp) {
+            std::mem::swap(&mut hi, &mut lo);
+        }
+        let x = (hi.sig as u128) << 63;
+        let y = if lo.class == Class::Zero {
+            0
+        } else {
+            shift_right_jam((lo.sig as u128) << 63, (hi.exp - lo.exp) as u32)
+        };
+
+        let (sign, sum) = if hi.sign == lo.sign {
+            (hi.sign, x + y)
+        } else if x >= y {
+            (hi.sign, x - y)
         } else {
-            let unbiased_exp = exp as i32 - F80_EXP_BIAS;
-            let f64_exp = unbiased_exp + F64_EXP_BIAS;
-
-            if f64_exp <= 0 {
-                // Subnormal in f64 → round to 0.0
-                sign << 63
-            } else if f64_exp >= 0x7FF {
-                // Exponent too large → ∞
-                (sign << 63) | (0x7FF << 52)
+            (lo.sign, y - x)
+        };
+
+        if sum == 0 {
+            return F80::zero(env.rounding == Rounding::Down);
+        }
+        F80::round_pack(sign, hi.exp + 1, sum, env)
+    }
+
+    /// self - b, rounded according to `env`
+    pub fn sub_with(&self, b: F80, env: &mut FpEnv) -> F80 {
+        let mut neg_b = b;
+        // A NaN keeps its sign
+        if !b.is_nan() {
+            neg_b.neg();
+        }
+        self.add_with(neg_b, env)
+    }
+
+    /// self * b, rounded according to `env`
+    pub fn mul_with(&self, b: F80, env: &mut FpEnv) -> F80 {
+        let ua = self.unpack(env);
+        let ub = b.unpack(env);
+        if let Some(nan) = F80::propagate_nan(self, &ua, Some((&b, &ub)), env) {
+            return nan;
+        }
+
+        let sign = ua.sign != ub.sign;
+        match (ua.class, ub.class) {
+            (Class::Infinity, Class::Zero) | (Class::Zero, Class::Infinity) => return F80::invalid(env),
+            (Class::Infinity, _) | (_, Class::Infinity) => return F80::infinity(sign),
+            (Class::Zero, _) | (_, Class::Zero) => return F80::zero(sign),
+            _ => {}
+        }
+
+        let product = ua.sig as u128 * ub.sig as u128;
+        F80::round_pack(sign, ua.exp + ub.exp - BIAS + 1, product, env)
+    }
+
+    /// self / b, rounded according to `env`
+    pub fn div_with(&self, b: F80, env: &mut FpEnv) -> F80 {
+        let ua = self.unpack(env);
+        let ub = b.unpack(env);
+        if let Some(nan) = F80::propagate_nan(self, &ua, Some((&b, &ub)), env) {
+            return nan;
+        }
+
+        let sign = ua.sign != ub.sign;
+        match (ua.class, ub.class) {
+            (Class::Infinity, Class::Infinity) | (Class::Zero, Class::Zero) => return F80::invalid(env),
+            (Class::Infinity, _) => return F80::infinity(sign),
+            (_, Class::Infinity) | (Class::Zero, _) => return F80::zero(sign),
+            (_, Class::Zero) => {
+                env.exceptions |= FP_ZERO_DIVIDE;
+                return F80::infinity(sign);
+            }
+            _ => {}
+        }
+
+        // 128 quotient bits in two steps, plus a sticky bit for the rest
+        let divisor = ub.sig as u128;
+        let n = (ua.sig as u128) << 63;
+        let q_hi = n / divisor;
+        let r = n % divisor;
+        let q_lo = (r << 64) / divisor;
+        let sticky = !(r << 64).is_multiple_of(divisor);
+        let q = (q_hi << 64) | q_lo | sticky as u128;
+
+        F80::round_pack(sign, ua.exp - ub.exp + BIAS, q, env)
+    }
+
+    /// Square root, rounded according to `env`
+    pub fn sqrt_with(&self, env: &mut FpEnv) -> F80 {
+        let ua = self.unpack(env);
+        if let Some(nan) = F80::propagate_nan(self, &ua, None, env) {
+            return nan;
+        }
+
+        match ua.class {
+            Class::Zero => return *self,
+            _ if ua.sign => return F80::invalid(env),
+            Class::Infinity => return *self,
+            _ => {}
+        }
+
+        // value = sig * 2^(e - 63). Scale the radicand by 2^67 or 2^68 so the
+        // exponent is even and the root comes out with 66 bits.
+        let e = ua.exp - BIAS - 63;
+        let s: i32 = if (e - 68) % 2 == 0 { 68 } else { 67 };
+
+        let radicand_bit = |j: i32| -> u128 {
+            if j >= s && j - s < 64 {
+                ((ua.sig >> (j - s)) & 1) as u128
+            } else {
+                0
+            }
+        };
+
+        // Digit-by-digit square root over the 132-bit radicand
+        let mut root: u128 = 0;
+        let mut rem: u128 = 0;
+        for k in 0..66 {
+            let j = 131 - 2 * k;
+            rem = (rem << 2) | (radicand_bit(j) << 1) | radicand_bit(j - 1);
+            let trial = (root << 2) | 1;
+            if rem >= trial {
+                rem -= trial;
+                root = (root << 1) | 1;
             } else {
-                let f64_mantissa = ((mantissa >> 11) & 0xFFFFFFFFFFFFF) as u64;
-                (sign << 63) | ((f64_exp as u64) << 52) | f64_mantissa
+                root <<= 1;
+            }
+        }
+
+        let sig = (root << 62) | (rem != 0) as u128;
+        F80::round_pack(false, BIAS + 65 + (e - s) / 2, sig, env)
+    }
+
+    /// Partial remainder of self / b, as computed by FPREM (quotient chopped) or
+    /// FPREM1 (quotient rounded to nearest).
+    /// Returns the remainder, the low bits of the quotient, and whether the
+    /// reduction is complete (C2 clear).
+    pub fn rem_with(&self, b: F80, round_nearest: bool, env: &mut FpEnv) -> (F80, u64, bool) {
+        let ua = self.unpack(env);
+        let ub = b.unpack(env);
+        if let Some(nan) = F80::propagate_nan(self, &ua, Some((&b, &ub)), env) {
+            return (nan, 0, true);
+        }
+
+        match (ua.class, ub.class) {
+            (Class::Infinity, _) | (_, Class::Zero) => return (F80::invalid(env), 0, true),
+            (Class::Zero, _) | (_, Class::Infinity) => return (*self, 0, true),
+            _ => {}
+        }
+
+        // The remainder is always exact; it's only rounded if it ends up denormal
+        let mut exact = FpEnv { precision: Precision::Extended, ..*env };
+        let diff = ua.exp - ub.exp;
+
+        let (sign, exp, r, q, complete) = if diff >= 64 {
+            // Too far apart to finish in one go: knock the exponent gap down to
+            // between 32 and 63 and let the program loop on C2
+            let n = 32 | (diff & 31);
+            let scaled_exp = ub.exp + (diff - n);
+            let num = (ua.sig as u128) << n;
+            let q = num / ub.sig as u128;
+            let r = num % ub.sig as u128;
+            (ua.sign, scaled_exp, r, q as u64, false)
+        } else if diff >= 0 {
+            let num = (ua.sig as u128) << diff;
+            let mut q = num / ub.sig as u128;
+            let mut r = num % ub.sig as u128;
+            let mut sign = ua.sign;
+            if round_nearest {
+                let twice = r << 1;
+                let divisor = ub.sig as u128;
+                if twice > divisor || (twice == divisor && q & 1 == 1) {
+                    q += 1;
+                    r = divisor - r;
+                    sign = !sign;
+                }
+            }
+            (sign, ub.exp, r, q as u64, true)
+        } else if round_nearest && diff == -1 && ua.sig > ub.sig {
+            // |a| > |b| / 2: the nearest quotient is 1
+            let r = ((ub.sig as u128) << 1) - ua.sig as u128;
+            (!ua.sign, ua.exp, r, 1, true)
+        } else {
+            (ua.sign, ua.exp, ua.sig as u128, 0, true)
+        };
+
+        let result = if r == 0 {
+            F80::zero(ua.sign)
+        } else {
+            F80::round_pack(sign, exp, r << 64, &mut exact)
+        };
+        env.exceptions |= exact.exceptions;
+        (result, q, complete)
+    }
+
+    /// self * 2^trunc(n), as computed by FSCALE
+    pub fn scale_with(&self, n: F80, env: &mut FpEnv) -> F80 {
+        let ua = self.unpack(env);
+        let un = n.unpack(env);
+        if let Some(nan) = F80::propagate_nan(self, &ua, Some((&n, &un)), env) {
+            return nan;
+        }
+
+        match (ua.class, un.class) {
+            (Class::Zero, Class::Infinity) if !un.sign => return F80::invalid(env),
+            (Class::Infinity, Class::Infinity) if un.sign => return F80::invalid(env),
+            (Class::Zero, _) | (Class::Infinity, _) => return *self,
+            (_, Class::Infinity) => {
+                return if un.sign { F80::zero(ua.sign) } else { F80::infinity(ua.sign) };
+            }
+            (_, Class::Zero) => return *self,
+            _ => {}
+        }
+
+        // trunc(n), clamped well past the point where the result is 0 or infinite
+        let e = un.exp - BIAS;
+        let k: i32 = if e < 0 {
+            0
+        } else if e > 20 {
+            1 << 20
+        } else {
+            (un.sig >> (63 - e)) as i32
+        };
+        let k = if un.sign { -k } else { k };
+
+        let mut ext = FpEnv { precision: Precision::Extended, ..*env };
+        let result = F80::round_pack(ua.sign, ua.exp + k, (ua.sig as u128) << 64, &mut ext);
+        env.exceptions |= ext.exceptions;
+        env.rounded_up = ext.rounded_up;
+        result
+    }
+
+    // Rounds |value| to an integer using RC. Returns (magnitude, inexact, rounded up),
+    // or None if it doesn't fit in 64 bits.
+    fn round_to_integer(u: &Unpacked, rounding: Rounding) -> Option<(u128, bool, bool)> {
+        let e = u.exp - BIAS;
+        if e >= 64 {
+            return None;
+        }
+        if e >= 63 {
+            return Some(((u.sig as u128) << (e - 63), false, false));
+        }
+
+        // Integer part followed by a round bit and a sticky bit
+        let t = shift_right_jam((u.sig as u128) << 2, (63 - e) as u32);
+        let int = t >> 2;
+        let round_bits = t & 3;
+        let inexact = round_bits != 0;
+        let increment = match rounding {
+            Rounding::Nearest => round_bits == 3 || (round_bits == 2 && int & 1 == 1),
+            Rounding::Up => inexact && !u.sign,
+            Rounding::Down => inexact && u.sign,
+            Rounding::Zero => false,
+        };
+        Some((int + increment as u128, inexact, increment))
+    }
+
+    /// Round to an integral value using RC, as computed by FRNDINT
+    pub fn round_int_with(&self, env: &mut FpEnv) -> F80 {
+        let u = self.unpack(env);
+        if let Some(nan) = F80::propagate_nan(self, &u, None, env) {
+            return nan;
+        }
+        if u.class != Class::Finite {
+            return *self;
+        }
+
+        match F80::round_to_integer(&u, env.rounding) {
+            // Too big to have a fraction
+            None => *self,
+            Some((int, inexact, up)) => {
+                if inexact {
+                    env.exceptions |= FP_PRECISION;
+                }
+                env.rounded_up = up;
+                if int == 0 {
+                    F80::zero(u.sign)
+                } else {
+                    let mut ext = FpEnv { precision: Precision::Extended, ..*env };
+                    F80::round_pack(u.sign, BIAS + 127, int, &mut ext)
+                }
+            }
+        }
+    }
+
+    /// Convert to a signed integer of `bits` width using RC (FIST).
+    /// Returns None (invalid operation) when the value doesn't fit.
+    pub fn to_int_with(&self, bits: u32, env: &mut FpEnv) -> Option<i64> {
+        let u = self.unpack(env);
+        match u.class {
+            Class::Zero => return Some(0),
+            Class::Finite => {}
+            _ => {
+                env.exceptions |= FP_INVALID;
+                return None;
+            }
+        }
+
+        let fits = F80::round_to_integer(&u, env.rounding).filter(|&(int, _, _)| {
+            let limit = 1u128 << (bits - 1);
+            if u.sign { int <= limit } else { int < limit }
+        });
+        match fits {
+            Some((int, inexact, up)) => {
+                if inexact {
+                    env.exceptions |= FP_PRECISION;
+                }
+                env.rounded_up = up;
+                let value = if u.sign { -(int as i128) } else { int as i128 };
+                Some(value as i64)
+            }
+            None => {
+                env.exceptions |= FP_INVALID;
+                None
+            }
+        }
+    }
+
+    /// Exact conversion from a 64-bit integer (FILD)
+    pub fn from_i64(value: i64) -> F80 {
+        let mut f = F80::new();
+        f.st = F80::encode_from_u128(value.unsigned_abs() as u128, value < 0);
+        f
+    }
+
+    // Loads an IEEE single/double given its raw bits and field widths
+    fn from_ieee(bits: u64, exp_bits: u32, frac_bits: u32, env: &mut FpEnv) -> F80 {
+        let sign = (bits >> (exp_bits + frac_bits)) & 1 != 0;
+        let exp_max = (1u64 << exp_bits) - 1;
+        let exp = (bits >> frac_bits) & exp_max;
+        let frac = bits & ((1u64 << frac_bits) - 1);
+        let bias = (1i32 << (exp_bits - 1)) - 1;
+
+        if exp == exp_max {
+            if frac == 0 {
+                return F80::infinity(sign);
+            }
+            let nan = F80::pack(sign, MAX_EXP as u16, INT_BIT_MASK64 | (frac << (63 - frac_bits)));
+            if nan.is_signaling() {
+                env.exceptions |= FP_INVALID;
+            }
+            return nan.quieted();
+        }
+        if exp == 0 {
+            if frac == 0 {
+                return F80::zero(sign);
+            }
+            // Denormal source: normalize, the extended format has the range for it
+            env.exceptions |= FP_DENORMAL;
+            let lz = frac.leading_zeros() - (64 - frac_bits);
+            let sig = frac << (63 - frac_bits + lz + 1);
+            let e = 1 - bias - 1 - lz as i32 + BIAS;
+            return F80::pack(sign, e as u16, sig);
+        }
+
+        let sig = INT_BIT_MASK64 | (frac << (63 - frac_bits));
+        F80::pack(sign, (exp as i32 - bias + BIAS) as u16, sig)
+    }
+
+    // Rounds to an IEEE single/double using RC and returns the raw bits
+    fn to_ieee(self, exp_bits: u32, frac_bits: u32, env: &mut FpEnv) -> u64 {
+        let u = self.unpack(env);
+        let sign_bit = (u.sign as u64) << (exp_bits + frac_bits);
+        let exp_max = (1u64 << exp_bits) - 1;
+        let frac_mask = (1u64 << frac_bits) - 1;
+        let bias = (1i32 << (exp_bits - 1)) - 1;
+
+        match u.class {
+            Class::Zero => return sign_bit,
+            Class::Infinity => return sign_bit | (exp_max << frac_bits),
+            Class::NaN | Class::Unsupported => {
+                let src = if u.class == Class::NaN { self } else { F80::invalid(env) };
+                if src.is_signaling() {
+                    env.exceptions |= FP_INVALID;
+                }
+                let payload = (src.quieted().get_mantissa() >> (63 - frac_bits)) & frac_mask;
+                let sign = (src.get_sign() as u64) << (exp_bits + frac_bits);
+                return sign | (exp_max << frac_bits) | payload;
+            }
+            Class::Finite => {}
+        }
+
+        let exp = u.exp - BIAS + bias;
+        match round_sig(u.sign, exp, (u.sig as u128) << 64, frac_bits + 1, exp_max as i32, env) {
+            Rounded::Finite { exp, sig } => {
+                let frac = (sig >> (127 - frac_bits)) as u64 & frac_mask;
+                sign_bit | ((exp as u64) << frac_bits) | frac
+            }
+            Rounded::Overflow => {
+                if overflow_to_infinity(u.sign, env) {
+                    sign_bit | (exp_max << frac_bits)
+                } else {
+                    sign_bit | ((exp_max - 1) << frac_bits) | frac_mask
+                }
+            }
+        }
+    }
+
+    /// Exact conversion from a single (FLD m32)
+    pub fn from_f32_bits(bits: u32, env: &mut FpEnv) -> F80 {
+        F80::from_ieee(bits as u64, 8, 23, env)
+    }
+
+    /// Exact conversion from a double (FLD m64)
+    pub fn from_f64_bits(bits: u64, env: &mut FpEnv) -> F80 {
+        F80::from_ieee(bits, 11, 52, env)
+    }
+
+    /// Rounded conversion to a single (FST m32)
+    pub fn to_f32_bits(&self, env: &mut FpEnv) -> u32 {
+        self.to_ieee(8, 23, env) as u32
+    }
+
+    /// Rounded conversion to a double (FST m64)
+    pub fn to_f64_bits(&self, env: &mut FpEnv) -> u64 {
+        self.to_ieee(11, 52, env)
+    }
+}
+
+impl F80 {
+    /// Orders self against b, as FCOM and FUCOM do. None means unordered.
+    /// Any NaN is an Invalid Operation for the ordered compare, only a
+    /// signaling one for the unordered compare (`quiet`).
+    pub fn compare_with(&self, b: F80, quiet: bool, env: &mut FpEnv) -> Option<Ordering> {
+        let ua = self.unpack(env);
+        let ub = b.unpack(env);
+        if ua.class == Class::Unsupported || ub.class == Class::Unsupported {
+            env.exceptions |= FP_INVALID;
+            return None;
+        }
+        if ua.class == Class::NaN || ub.class == Class::NaN {
+            if !quiet || self.is_signaling() || b.is_signaling() {
+                env.exceptions |= FP_INVALID;
+            }
+            return None;
+        }
+
+        // +0 and -0 are equal
+        let magnitude = |u: &Unpacked| match u.class {
+            Class::Zero => (0, 0, 0),
+            Class::Infinity => (2, 0, 0),
+            _ => (1, u.exp, u.sig),
+        };
+        let negative = |u: &Unpacked| u.sign && u.class != Class::Zero;
+        Some(match (negative(&ua), negative(&ub)) {
+            (false, false) => magnitude(&ua).cmp(&magnitude(&ub)),
+            (true, true) => magnitude(&ub).cmp(&magnitude(&ua)),
+            (true, false) => Ordering::Less,
+            (false, true) => Ordering::Greater,
+        })
+    }
+
+    /// Splits self into its unbiased exponent and its significand, both exact,
+    /// as computed by FXTRACT. Returns (exponent, significand).
+    pub fn extract_with(&self, env: &mut FpEnv) -> (F80, F80) {
+        let u = self.unpack(env);
+        if let Some(nan) = F80::propagate_nan(self, &u, None, env) {
+            return (nan, nan);
+        }
+        match u.class {
+            Class::Zero => {
+                env.exceptions |= FP_ZERO_DIVIDE;
+                (F80::infinity(true), *self)
+            }
+            Class::Infinity => (F80::infinity(false), *self),
+            // Denormals come out normalized
+            _ => (F80::from_i64((u.exp - BIAS) as i64), F80::pack(u.sign, BIAS as u16, u.sig)),
+        }
+    }
+
+    /// 2^self - 1, as computed by F2XM1. The 387 only defines it for -1..1;
+    /// outside that the result is still 2^x - 1.
+    pub fn f2xm1_with(&self, env: &mut FpEnv) -> F80 {
+        let u = self.unpack(env);
+        if let Some(nan) = F80::propagate_nan(self, &u, None, env) {
+            return nan;
+        }
+        match u.class {
+            Class::Zero => return *self,
+            Class::Infinity if u.sign => return F80::from_i64(-1),
+            Class::Infinity => return *self,
+            _ => {}
+        }
+
+        let x = Wide::from_unpacked(&u);
+        let result = if u.exp < BIAS {
+            expm1(x.mul(Wide::LN2))
+        } else {
+            // 2^n * 2^f - 1, with n the integer part. Far enough out the
+            // result is -1 or overflows anyway.
+            let n = (x.trunc_int()).clamp(-0x8000, 0x8000);
+            let f = x.add(Wide::from_int(-n));
+            let scaled = expm1(f.mul(Wide::LN2)).add(Wide::ONE);
+            Wide { exp: scaled.exp + n as i32, ..scaled }.add(Wide::ONE.neg())
+        };
+        result.round(env)
+    }
+
+    /// y * log2(self), as computed by FYL2X
+    pub fn yl2x_with(&self, y: F80, env: &mut FpEnv) -> F80 {
+        let ux = self.unpack(env);
+        let uy = y.unpack(env);
+        if let Some(nan) = F80::propagate_nan(self, &ux, Some((&y, &uy)), env) {
+            return nan;
+        }
+        if ux.sign && ux.class != Class::Zero {
+            return F80::invalid(env);
+        }
+
+        match (ux.class, uy.class) {
+            (Class::Zero, Class::Zero) | (Class::Infinity, Class::Zero) => return F80::invalid(env),
+            (Class::Zero, Class::Infinity) => return F80::infinity(!uy.sign),
+            (Class::Zero, _) => {
+                env.exceptions |= FP_ZERO_DIVIDE;
+                return F80::infinity(!uy.sign);
+            }
+            (Class::Infinity, _) => return F80::infinity(uy.sign),
+            _ => {}
+        }
+
+        let log = Wide::from_unpacked(&ux).log2();
+        // log2(x) is negative below 1, and exactly 0 at 1
+        let sign = uy.sign != log.sign;
+        match uy.class {
+            Class::Infinity if log.is_zero() => F80::invalid(env),
+            Class::Infinity => F80::infinity(sign),
+            Class::Zero => F80::zero(sign),
+            _ => Wide::from_unpacked(&uy).mul(log).round(env),
+        }
+    }
+
+    /// y * log2(self + 1), as computed by FYL2XP1. The 387 only defines it for
+    /// |x| < 1 - sqrt(2)/2, where it keeps full precision for x near 0.
+    pub fn yl2xp1_with(&self, y: F80, env: &mut FpEnv) -> F80 {
+        let ux = self.unpack(env);
+        let uy = y.unpack(env);
+        if let Some(nan) = F80::propagate_nan(self, &ux, Some((&y, &uy)), env) {
+            return nan;
+        }
+        let x = Wide::from_unpacked(&ux);
+        let one = Wide::ONE;
+        // x + 1 <= 0
+        let below = ux.sign && (ux.class == Class::Infinity || (ux.class == Class::Finite && ux.exp >= BIAS));
+        let at_minus_one = ux.sign && ux.class == Class::Finite && x.add(one).is_zero();
+
+        match (ux.class, uy.class) {
+            _ if below && !at_minus_one => return F80::invalid(env),
+            (_, Class::Zero) if at_minus_one => return F80::invalid(env),
+            (_, Class::Infinity) if at_minus_one => return F80::infinity(!uy.sign),
+            _ if at_minus_one => {
+                env.exceptions |= FP_ZERO_DIVIDE;
+                return F80::infinity(!uy.sign);
             }
+            (Class::Zero, Class::Infinity) | (Class::Infinity, Class::Zero) => return F80::invalid(env),
+            (Class::Infinity, _) => return F80::infinity(uy.sign),
+            (Class::Zero, _) | (_, Class::Zero) => return F80::zero(ux.sign != uy.sign),
+            (_, Class::Infinity) => return F80::infinity(ux.sign != uy.sign),
+            _ => {}
+        }
+
+        let log = if ux.exp < BIAS - 1 {
+            // ln(1 + x) = 2 atanh(x / (2 + x)), without forming 1 + x
+            let two = Wide::from_int(2);
+            atanh2(x.div(two.add(x))).div(Wide::LN2)
+        } else {
+            x.add(one).log2()
         };
+        Wide::from_unpacked(&uy).mul(log).round(env)
+    }
+
+    // Operand of FSIN, FCOS, FSINCOS and FPTAN, sorted out before any series runs
+    fn trig_operand(&self, env: &mut FpEnv) -> TrigOperand {
+        let u = self.unpack(env);
+        if let Some(nan) = F80::propagate_nan(self, &u, None, env) {
+            return TrigOperand::Special(nan);
+        }
+        match u.class {
+            Class::Infinity => TrigOperand::Special(F80::invalid(env)),
+            Class::Zero => TrigOperand::Zero,
+            // The 387 leaves |x| >= 2^63 alone and sets C2
+            _ if u.exp - BIAS >= 63 => TrigOperand::OutOfRange,
+            _ => {
+                let (r, quadrant) = reduce_half_pi(&u);
+                let (sin, cos) = sin_cos(r);
+                TrigOperand::Reduced { sign: u.sign, quadrant, sin, cos }
+            }
+        }
+    }
+
+    /// sin(self), as computed by FSIN. None if |self| >= 2^63.
+    pub fn sin_with(&self, env: &mut FpEnv) -> Option<F80> {
+        Some(match self.trig_operand(env) {
+            TrigOperand::Special(result) => result,
+            TrigOperand::OutOfRange => return None,
+            TrigOperand::Zero => *self,
+            TrigOperand::Reduced { sign, quadrant, sin, cos } => {
+                let result = [sin, cos, sin.neg(), cos.neg()][quadrant];
+                Wide { sign: result.sign != sign, ..result }.round(env)
+            }
+        })
+    }
+
+    /// cos(self), as computed by FCOS. None if |self| >= 2^63.
+    pub fn cos_with(&self, env: &mut FpEnv) -> Option<F80> {
+        Some(match self.trig_operand(env) {
+            TrigOperand::Special(result) => result,
+            TrigOperand::OutOfRange => return None,
+            TrigOperand::Zero => F80::from_i64(1),
+            TrigOperand::Reduced { quadrant, sin, cos, .. } => {
+                [cos, sin.neg(), cos.neg(), sin][quadrant].round(env)
+            }
+        })
+    }
+
+    /// (sin(self), cos(self)), as computed by FSINCOS. None if |self| >= 2^63.
+    pub fn sin_cos_with(&self, env: &mut FpEnv) -> Option<(F80, F80)> {
+        Some(match self.trig_operand(env) {
+            TrigOperand::Special(result) => (result, result),
+            TrigOperand::OutOfRange => return None,
+            TrigOperand::Zero => (*self, F80::from_i64(1)),
+            TrigOperand::Reduced { sign, quadrant, sin, cos } => {
+                let s = [sin, cos, sin.neg(), cos.neg()][quadrant];
+                let c = [cos, sin.neg(), cos.neg(), sin][quadrant];
+                (Wide { sign: s.sign != sign, ..s }.round(env), c.round(env))
+            }
+        })
+    }
 
-        f64::from_bits(f64_bits)
+    /// tan(self), as computed by FPTAN. None if |self| >= 2^63.
+    pub fn tan_with(&self, env: &mut FpEnv) -> Option<F80> {
+        Some(match self.trig_operand(env) {
+            TrigOperand::Special(result) => result,
+            TrigOperand::OutOfRange => return None,
+            TrigOperand::Zero => *self,
+            TrigOperand::Reduced { sign, quadrant, sin, cos } => {
+                // tan(r + k pi/2) is tan(r) for even k and -cot(r) for odd k
+                let result = if quadrant & 1 == 0 { sin.div(cos) } else { cos.div(sin).neg() };
+                Wide { sign: result.sign != sign, ..result }.round(env)
+            }
+        })
     }
+
+    /// The angle of the point (self, y), as computed by FPATAN: atan(y / self)
+    /// moved into the quadrant the signs give, between -pi and pi.
+    pub fn atan2_with(&self, y: F80, env: &mut FpEnv) -> F80 {
+        let ux = self.unpack(env);
+        let uy = y.unpack(env);
+        if let Some(nan) = F80::propagate_nan(self, &ux, Some((&y, &uy)), env) {
+            return nan;
+        }
+
+        let half_pi = Wide { exp: 0, ..Wide::PI };
+        let angle = match (uy.class, ux.class) {
+            // Zero y: 0 on the positive side (including +0), pi on the negative side
+            (Class::Zero, _) if !ux.sign => return F80::zero(uy.sign),
+            (Class::Zero, _) => Wide::PI,
+            (Class::Infinity, Class::Infinity) if !ux.sign => Wide { exp: -1, ..Wide::PI },
+            (Class::Infinity, Class::Infinity) => Wide { exp: -2, ..Wide::PI.mul(Wide::from_int(3)) },
+            (Class::Infinity, _) | (_, Class::Zero) => half_pi,
+            (_, Class::Infinity) if !ux.sign => return F80::zero(uy.sign),
+            (_, Class::Infinity) => Wide::PI,
+            _ => {
+                let ax = Wide { sign: false, ..Wide::from_unpacked(&ux) };
+                let ay = Wide { sign: false, ..Wide::from_unpacked(&uy) };
+                // Keep the atan argument within 1, where the series converges quickly
+                let first_octant = (ay.exp, ay.sig) <= (ax.exp, ax.sig);
+                let angle = if first_octant {
+                    atan(ay.div(ax))
+                } else {
+                    half_pi.add(atan(ax.div(ay)).neg())
+                };
+                if ux.sign { Wide::PI.add(angle.neg()) } else { angle }
+            }
+        };
+        Wide { sign: uy.sign, ..angle }.round(env)
+    }
+}
+
+enum TrigOperand {
+    Special(F80),
+    Zero,
+    OutOfRange,
+    // |x| = r + quadrant * pi/2 (mod 2 pi), with sin(r) and cos(r)
+    Reduced { sign: bool, quadrant: usize, sin: Wide, cos: Wide },
+}
+
+// --- Transcendental functions ---
+//
+// Worked out with 128-bit significands, twice what the result keeps, and
+// rounded once at the end. The error of the series and the intermediate
+// truncations stays far below the last bit of the extended result.
+
+// sig * 2^(exp - 127), with the top bit of sig set unless the value is 0
+#[derive(Clone, Copy)]
+struct Wide {
+    sign: bool,
+    exp: i32,
+    sig: u128,
+}
+
+// Full 256-bit product, as (high, low)
+fn mul_wide(a: u128, b: u128) -> (u128, u128) {
+    let (a1, a0) = (a >> 64, a & u64::MAX as u128);
+    let (b1, b0) = (b >> 64, b & u64::MAX as u128);
+    let low = a0 * b0;
+    let (mid, carry) = (a1 * b0).overflowing_add(a0 * b1);
+    let (low, c0) = low.overflowing_add(mid << 64);
+    let high = a1 * b1 + (mid >> 64) + ((carry as u128) << 64) + c0 as u128;
+    (high, low)
+}
+
+impl Wide {
+    const ZERO: Wide = Wide { sign: false, exp: 0, sig: 0 };
+    const ONE: Wide = Wide { sign: false, exp: 0, sig: 1 << 127 };
+    // ln(2), truncated
+    const LN2: Wide = Wide { sign: false, exp: -1, sig: 0xB172_17F7_D1CF_79AB_C9E3_B398_03F2_F6AF };
+    // pi, truncated
+    const PI: Wide = Wide { sign: false, exp: 1, sig: 0xC90F_DAA2_2168_C234_C4C6_628B_80DC_1CD1 };
+
+    fn new(sign: bool, exp: i32, sig: u128) -> Wide {
+        if sig == 0 {
+            return Wide { sign, ..Wide::ZERO };
+        }
+        let lz = sig.leading_zeros();
+        Wide { sign, exp: exp - lz as i32, sig: sig << lz }
+    }
+
+    fn from_unpacked(u: &Unpacked) -> Wide {
+        match u.class {
+            Class::Zero => Wide { sign: u.sign, ..Wide::ZERO },
+            _ => Wide::new(u.sign, u.exp - BIAS, (u.sig as u128) << 64),
+        }
+    }
+
+    fn from_int(n: i64) -> Wide {
+        Wide::new(n < 0, 127, n.unsigned_abs() as u128)
+    }
+
+    fn is_zero(&self) -> bool {
+        self.sig == 0
+    }
+
+    fn neg(self) -> Wide {
+        Wide { sign: !self.sign, ..self }
+    }
+
+    // Integer part, for values that fit
+    fn trunc_int(&self) -> i64 {
+        if self.is_zero() || self.exp < 0 {
+            return 0;
+        }
+        let int = (self.sig >> (127 - self.exp.min(62))) as i64;
+        if self.sign { -int } else { int }
+    }
+
+    fn add(self, b: Wide) -> Wide {
+        if b.is_zero() {
+            return self;
+        }
+        if self.is_zero() {
+            return b;
+        }
+        let (hi, lo) = if self.exp >= b.exp { (self, b) } else { (b, self) };
+        // One bit of headroom for the carry
+        let x = shift_right_jam(hi.sig, 1);
+        let y = shift_right_jam(lo.sig, (hi.exp - lo.exp + 1).min(128) as u32);
+        let (sign, sum) = if hi.sign == lo.sign {
+            (hi.sign, x + y)
+        } else if x >= y {
+            (hi.sign, x - y)
+        } else {
+            (lo.sign, y - x)
+        };
+        Wide::new(sign, hi.exp + 1, sum)
+    }
+
+    fn mul(self, b: Wide) -> Wide {
+        let sign = self.sign != b.sign;
+        if self.is_zero() || b.is_zero() {
+            return Wide { sign, ..Wide::ZERO };
+        }
+        let (high, low) = mul_wide(self.sig, b.sig);
+        Wide::new(sign, self.exp + b.exp + 1, high | (low != 0) as u128)
+    }
+
+    fn div(self, b: Wide) -> Wide {
+        let sign = self.sign != b.sign;
+        if self.is_zero() {
+            return Wide { sign, ..Wide::ZERO };
+        }
+        // 128 quotient bits by shift and subtract, plus a sticky bit
+        let mut rem = self.sig;
+        let mut q: u128 = 0;
+        if rem >= b.sig {
+            rem -= b.sig;
+            q = 1;
+        }
+        for _ in 0..127 {
+            let carry = rem >> 127 != 0;
+            rem <<= 1;
+            q <<= 1;
+            if carry || rem >= b.sig {
+                rem = rem.wrapping_sub(b.sig);
+                q |= 1;
+            }
+        }
+        Wide::new(sign, self.exp - b.exp, q | (rem != 0) as u128)
+    }
+
+    // log2 of a positive value
+    fn log2(self) -> Wide {
+        // self = m * 2^e with m between sqrt(2)/2 and sqrt(2)
+        let (m, e) = if self.sig > 0xB504_F333_F9DE_6484_597D_89B3_754A_BE9F {
+            (Wide { exp: -1, ..self }, self.exp + 1)
+        } else {
+            (Wide { exp: 0, ..self }, self.exp)
+        };
+        let t = m.add(Wide::ONE.neg()).div(m.add(Wide::ONE));
+        atanh2(t).div(Wide::LN2).add(Wide::from_int(e as i64))
+    }
+
+    fn round(self, env: &mut FpEnv) -> F80 {
+        if self.is_zero() {
+            return F80::zero(self.sign);
+        }
+        // The result is extended precision whatever PC says
+        let mut ext = FpEnv { precision: Precision::Extended, ..*env };
+        let result = F80::round_pack(self.sign, self.exp + BIAS, self.sig, &mut ext);
+        env.exceptions |= ext.exceptions;
+        env.rounded_up = ext.rounded_up;
+        result
+    }
+}
+
+// Sums a series until the terms no longer reach the working precision
+fn series(first: Wide, mut next: impl FnMut(Wide, i64) -> Wide) -> Wide {
+    let mut sum = first;
+    let mut term = first;
+    for n in 1..200 {
+        term = next(term, n);
+        if term.is_zero() {
+            break;
+        }
+        // The last term still counts towards the sticky bit
+        sum = sum.add(term);
+        if term.exp < sum.exp - 130 {
+            break;
+        }
+    }
+    sum
+}
+
+// e^z - 1 = z + z^2/2! + z^3/3! + ...
+fn expm1(z: Wide) -> Wide {
+    if z.is_zero() {
+        return z;
+    }
+    series(z, |term, n| term.mul(z).div(Wide::from_int(n + 1)))
+}
+
+// 2 atanh(t) = ln((1 + t) / (1 - t)) = 2 (t + t^3/3 + t^5/5 + ...)
+fn atanh2(t: Wide) -> Wide {
+    if t.is_zero() {
+        return t;
+    }
+    let t2 = t.mul(t);
+    // Odd powers kept apart from the divisors so the error doesn't build up
+    let mut power = t;
+    let sum = series(t, |_, n| {
+        power = power.mul(t2);
+        power.div(Wide::from_int(2 * n + 1))
+    });
+    Wide { exp: sum.exp + 1, ..sum }
+}
+
+// sin(r) and cos(r) for |r| <= pi/4:
+// r - r^3/3! + r^5/5! - ...  and  1 - r^2/2! + r^4/4! - ...
+fn sin_cos(r: Wide) -> (Wide, Wide) {
+    let r2 = r.mul(r);
+    let sin = series(r, |term, n| term.mul(r2).div(Wide::from_int(2 * n * (2 * n + 1))).neg());
+    let cos = series(Wide::ONE, |term, n| term.mul(r2).div(Wide::from_int((2 * n - 1) * (2 * n))).neg());
+    (sin, cos)
+}
+
+// atan(t) for 0 <= t <= 1, by Euler's series, whose terms are all positive:
+// t/(1+t^2) * (1 + 2/3 z + (2*4)/(3*5) z^2 + ...)  with z = t^2/(1+t^2) <= 1/2
+fn atan(t: Wide) -> Wide {
+    if t.is_zero() {
+        return t;
+    }
+    let t2 = t.mul(t);
+    let denominator = Wide::ONE.add(t2);
+    let z = t2.div(denominator);
+    series(t.div(denominator), |term, n| {
+        term.mul(z).mul(Wide::from_int(2 * n)).div(Wide::from_int(2 * n + 1))
+    })
+}
+
+// pi/2 to 256 bits, as an integer scaled by 2^255
+const HALF_PI_FIXED: [u64; 4] = [
+    0x020B_BEA6_3B13_9B22,
+    0x2902_4E08_8A67_CC74,
+    0xC4C6_628B_80DC_1CD1,
+    0xC90F_DAA2_2168_C234,
+];
+
+// Splits |x| < 2^63 into r + k pi/2 with |r| <= pi/4 and returns (r, k mod 4).
+// x is exact in 320-bit fixed point, so the only error is pi/2 being cut at
+// 256 bits: at most 2^-192 after multiplying by k, which keeps r good to
+// 128 bits even when x is very close to a multiple of pi/2.
+fn reduce_half_pi(u: &Unpacked) -> (Wide, usize) {
+    let e = u.exp - BIAS;
+    let x = Wide::from_unpacked(&Unpacked { sign: false, ..*u });
+    // Below 1/2 it's already in range
+    if e < -1 {
+        return (x, 0);
+    }
+
+    // k = round(x / (pi/2)), 128 bits are plenty to find the nearest integer
+    let half = Wide { exp: -1, ..Wide::ONE };
+    let k = x.div(Wide { exp: 0, ..Wide::PI }).add(half).trunc_int() as u64;
+
+    // Little endian 64-bit limbs, scaled by 2^255
+    let mut fixed = [0u64; 5];
+    let shift = (192 + e) as usize;
+    let sig = (u.sig as u128) << (shift % 64);
+    fixed[shift / 64] = sig as u64;
+    fixed[shift / 64 + 1] = (sig >> 64) as u64;
+
+    let mut multiple = [0u64; 5];
+    let mut carry = 0u128;
+    for (i, &limb) in HALF_PI_FIXED.iter().enumerate() {
+        let product = limb as u128 * k as u128 + carry;
+        multiple[i] = product as u64;
+        carry = product >> 64;
+    }
+    multiple[4] = carry as u64;
+
+    let negative = multiple.iter().rev().cmp(fixed.iter().rev()) == Ordering::Greater;
+    let (big, small) = if negative { (multiple, fixed) } else { (fixed, multiple) };
+    let mut diff = [0u64; 5];
+    let mut borrow = false;
+    for i in 0..5 {
+        let (d, b1) = big[i].overflowing_sub(small[i]);
+        let (d, b2) = d.overflowing_sub(borrow as u64);
+        diff[i] = d;
+        borrow = b1 || b2;
+    }
+
+    // Top 128 bits of the difference, the rest folded into a sticky bit
+    let bit = |n: i64| n >= 0 && diff[n as usize / 64] >> (n % 64) & 1 != 0;
+    let Some(top) = (0..320).rev().find(|&n| bit(n)) else {
+        return (Wide::ZERO, (k & 3) as usize);
+    };
+    let low = top - 127;
+    let sig = (0..128).filter(|&i| bit(low + i)).fold(0u128, |acc, i| acc | 1 << i);
+    let sticky = (0..low).any(bit);
+    let r = Wide::new(negative, top as i32 - 255, sig | sticky as u128);
+    (r, (k & 3) as usize)
 }
@@ -0,0 +1,383 @@
+use crate::bus::Bus;
+use crate::errors;
+use crate::handles::UNUSED;
+use crate::interrupts::utils::{pattern_to_fcb, read_dta_template};
+
+// File control blocks (INT 21h 0Fh-17h, 21h-24h, 27h-28h).
+//
+// An FCB names a file and carries the caller's position in it:
+//
+//   00h  Drive (0=default, 1=A)
+//   01h  Name and extension, blank-padded
+//   0Ch  Current block, of 128 records
+//   0Eh  Record size
+//   10h  File size
+//   14h  Date, 16h Time
+//   18h  SFT entry of the open file (reserved to DOS)
+//   20h  Current record within the block
+//   21h  Random record; 3 bytes when records are 64 bytes or more
+//
+// An extended FCB puts FFh, 5 reserved bytes and an attribute in front.
+// Opening one takes a reference on an SFT entry, as a handle does, but
+// outside any JFT; the entry remembers the process, so whatever it leaves
+// open is closed when it ends. Records go to and from the DTA.
+
+// Results, returned in AL
+pub const SUCCESS: u8 = 0x00;
+/// Read: nothing left
+pub const END_OF_FILE: u8 = 0x01;
+/// Write: no room for the records
+pub const DISK_FULL: u8 = 0x01;
+/// Read: the last record was short and padded with zeros
+pub const PARTIAL_RECORD: u8 = 0x03;
+pub const FAILURE: u8 = 0xFF;
+
+const SFT_FIELD: usize = 0x18;
+const RECORDS_PER_BLOCK: u32 = 128;
+
+// Records the error for INT 21h 59h, and INT 24h if it's critical
+fn failed(bus: &mut Bus, code: u8) -> u8 {
+    errors::record(bus, code);
+    FAILURE
+}
+
+// The FCB proper and the attribute an extended one asks for
+fn locate(bus: &Bus, addr: usize) -> (usize, u8) {
+    if bus.read_8(addr) == 0xFF {
+        (addr + 7, bus.read_8(addr + 6))
+    } else {
+        (addr, 0)
+    }
+}
+
+fn drive_prefix(bus: &Bus, fcb: usize) -> String {
+    match bus.read_8(fcb) {
+        0 => String::new(),
+        drive => format!("{}:", (b'A' + drive - 1) as char),
+    }
+}
+
+fn filename(bus: &Bus, fcb: usize) -> String {
+    drive_prefix(bus, fcb) + &read_dta_template(bus, fcb)
+}
+
+// "NAME.EXT" from a blank-padded name and extension
+fn join_name(name: &[u8]) -> String {
+    let part = |bytes: &[u8]| String::from_utf8_lossy(bytes).trim_end().to_string();
+    let (stem, ext) = (part(&name[..8]), part(&name[8..11]));
+    if ext.is_empty() {
+        stem
+    } else {
+        format!("{}.{}", stem, ext)
+    }
+}
+
+// Files the FCB's name matches, wildcards and all
+fn matching(bus: &Bus, fcb: usize) -> Vec<String> {
+    let pattern = filename(bus, fcb);
+    (0..)
+        .map_while(|i| bus.disk.find_directory_entry(&pattern, i, 0).ok())
+        .filter(|entry| !entry.is_dir)
+        .map(|entry| entry.filename)
+        .collect()
+}
+
+fn record_size(bus: &Bus, fcb: usize) -> u32 {
+    match bus.read_16(fcb + 0x0E) {
+        0 => 128,
+        size => size as u32,
+    }
+}
+
+fn current_record(bus: &Bus, fcb: usize) -> u32 {
+    bus.read_16(fcb + 0x0C) as u32 * RECORDS_PER_BLOCK + bus.read_8(fcb + 0x20) as u32
+}
+
+fn set_current_record(bus: &mut Bus, fcb: usize, record: u32) {
+    bus.write_16(fcb + 0x0C, (record / RECORDS_PER_BLOCK) as u16);
+    bus.write_8(fcb + 0x20, (record % RECORDS_PER_BLOCK) as u8);
+}
+
+fn random_record(bus: &Bus, fcb: usize) -> u32 {
+    let record = bus.read_32(fcb + 0x21);
+    if record_size(bus, fcb) >= 64 {
+        record & 0x00FF_FFFF
+    } else {
+        record
+    }
+}
+
+fn set_random_record(bus: &mut Bus, fcb: usize, record: u32) {
+    if record_size(bus, fcb) >= 64 {
+        bus.write_16(fcb + 0x21, record as u16);
+        bus.write_8(fcb + 0x23, (record >> 16) as u8);
+    } else {
+        bus.write_32(fcb + 0x21, record);
+    }
+}
+
+// The SFT entry of an open FCB
+fn sft(bus: &Bus, fcb: usize) -> Option<u8> {
+    let index = bus.read_8(fcb + SFT_FIELD);
+    bus.disk
+        .sft_entry(index)
+        .filter(|entry| !entry.is_device())
+        .map(|_| index)
+}
+
+// Fills in a newly opened or created FCB
+fn attach(bus: &mut Bus, fcb: usize, sft: u8, psp: u16) {
+    bus.disk.add_fcb_ref(sft, psp);
+    if bus.read_8(fcb) == 0 {
+        let drive = bus.disk.get_current_drive() + 1;
+        bus.write_8(fcb, drive);
+    }
+    let size = bus.disk.seek_file(sft, 0, 2).unwrap_or(0);
+    let (time, date) = bus.disk.file_time(sft).unwrap_or((0, 0x0021));
+    bus.write_16(fcb + 0x0C, 0);
+    bus.write_16(fcb + 0x0E, 128);
+    bus.write_32(fcb + 0x10, size as u32);
+    bus.write_16(fcb + 0x14, date);
+    bus.write_16(fcb + 0x16, time);
+    bus.write_8(fcb + SFT_FIELD, sft);
+}
+
+fn dta(bus: &Bus) -> usize {
+    ((bus.dta_segment as usize) << 4) + bus.dta_offset as usize
+}
+
+// Reads `count` records from `record` on into the DTA. Returns the result
+// and the number of records read, a partial one included.
+fn read_records(bus: &mut Bus, fcb: usize, record: u32, count: u32) -> (u8, u32) {
+    let Some(sft) = sft(bus, fcb) else {
+        return (END_OF_FILE, 0);
+    };
+    let size = record_size(bus, fcb);
+    let data = bus
+        .disk
+        .seek_file(sft, record as i64 * size as i64, 0)
+        .and_then(|_| bus.disk.read_file(sft, (size * count) as usize))
+        .unwrap_or_default();
+
+    let dta = dta(bus);
+    for (i, &byte) in data.iter().enumerate() {
+        bus.write_8(dta + i, byte);
+    }
+    let read = data.len() as u32;
+    if !read.is_multiple_of(size) {
+        for i in read..read.next_multiple_of(size) {
+            bus.write_8(dta + i as usize, 0);
+        }
+        (PARTIAL_RECORD, read / size + 1)
+    } else if read / size < count {
+        (END_OF_FILE, read / size)
+    } else {
+        (SUCCESS, count)
+    }
+}
+
+// Writes `count` records from the DTA at `record`, growing the file size
+fn write_records(bus: &mut Bus, fcb: usize, record: u32, count: u32) -> (u8, u32) {
+    let Some(sft) = sft(bus, fcb) else {
+        return (DISK_FULL, 0);
+    };
+    let size = record_size(bus, fcb);
+    let dta = dta(bus);
+    let data: Vec<u8> = (0..(size * count) as usize)
+        .map(|i| bus.read_8(dta + i))
+        .collect();
+    let written = match bus.disk.seek_file(sft, record as i64 * size as i64, 0) {
+        Ok(_) => bus.disk.write_file(sft, &data).unwrap_or(0) as u32,
+        Err(_) => 0,
+    };
+
+    let end = record * size + written;
+    if end > bus.read_32(fcb + 0x10) {
+        bus.write_32(fcb + 0x10, end);
+    }
+    if written < size * count {
+        (DISK_FULL, written / size)
+    } else {
+        (SUCCESS, count)
+    }
+}
+
+/// INT 21h 0Fh
+pub fn open(bus: &mut Bus, addr: usize, psp: u16) -> u8 {
+    let (fcb, _) = locate(bus, addr);
+    let name = filename(bus, fcb);
+    // Read-only files open for reading
+    let opened = bus
+        .disk
+        .open_file(&name, 0x02)
+        .or_else(|_| bus.disk.open_file(&name, 0x00));
+    match opened {
+        Ok(sft) => {
+            attach(bus, fcb, sft, psp);
+            SUCCESS
+        }
+        Err(code) => failed(bus, code),
+    }
+}
+
+/// INT 21h 10h
+pub fn close(bus: &mut Bus, addr: usize) -> u8 {
+    let (fcb, _) = locate(bus, addr);
+    let Some(sft) = sft(bus, fcb) else {
+        return failed(bus, 0x06); // Invalid handle
+    };
+    bus.disk.release(sft);
+    bus.write_8(fcb + SFT_FIELD, UNUSED);
+    SUCCESS
+}
+
+/// Closes the files process `psp` still has open through FCBs, as it ends
+pub fn close_all(bus: &mut Bus, psp: u16) {
+    for sft in bus.disk.fcb_entries(psp) {
+        bus.disk.release(sft);
+    }
+}
+
+/// INT 21h 13h: deletes every file the name matches
+pub fn delete(bus: &mut Bus, addr: usize) -> u8 {
+    let (fcb, _) = locate(bus, addr);
+    let prefix = drive_prefix(bus, fcb);
+    let mut result = Err(0x02); // File not found
+    for name in matching(bus, fcb) {
+        let deleted = bus.disk.delete_file(&(prefix.clone() + &name));
+        if result.is_err() {
+            result = deleted;
+        }
+    }
+    match result {
+        Ok(()) => SUCCESS,
+        Err(code) => failed(bus, code),
+    }
+}
+
+/// INT 21h 14h
+pub fn read_sequential(bus: &mut Bus, addr: usize) -> u8 {
+    let (fcb, _) = locate(bus, addr);
+    let record = current_record(bus, fcb);
+    let (result, read) = read_records(bus, fcb, record, 1);
+    set_current_record(bus, fcb, record + read);
+    result
+}
+
+/// INT 21h 15h
+pub fn write_sequential(bus: &mut Bus, addr: usize) -> u8 {
+    let (fcb, _) = locate(bus, addr);
+    let record = current_record(bus, fcb);
+    let (result, written) = write_records(bus, fcb, record, 1);
+    set_current_record(bus, fcb, record + written);
+    result
+}
+
+/// INT 21h 16h: creates the file, or truncates an existing one
+pub fn create(bus: &mut Bus, addr: usize, psp: u16) -> u8 {
+    let (fcb, attribute) = locate(bus, addr);
+    let name = filename(bus, fcb);
+    match bus.disk.create_file(&name, 0x02, attribute as u16, false) {
+        Ok(sft) => {
+            attach(bus, fcb, sft, psp);
+            SUCCESS
+        }
+        Err(code) => failed(bus, code),
+    }
+}
+
+/// INT 21h 17h: the new name sits at 11h; its wildcards keep the old
+/// name's characters
+pub fn rename(bus: &mut Bus, addr: usize) -> u8 {
+    let (fcb, _) = locate(bus, addr);
+    let prefix = drive_prefix(bus, fcb);
+    let target: Vec<u8> = (0..11).map(|i| bus.read_8(fcb + 0x11 + i)).collect();
+    let names = matching(bus, fcb);
+    if names.is_empty() {
+        return failed(bus, 0x02);
+    }
+    for name in names {
+        let new: Vec<u8> = target
+            .iter()
+            .zip(pattern_to_fcb(&name))
+            .map(|(&t, old)| if t == b'?' { old } else { t })
+            .collect();
+        let renamed = bus
+            .disk
+            .rename_file(&(prefix.clone() + &name), &(prefix.clone() + &join_name(&new)));
+        if let Err(code) = renamed {
+            return failed(bus, code);
+        }
+    }
+    SUCCESS
+}
+
+/// INT 21h 21h: reads the random record, which becomes the current one
+pub fn read_random(bus: &mut Bus, addr: usize) -> u8 {
+    let (fcb, _) = locate(bus, addr);
+    let record = random_record(bus, fcb);
+    set_current_record(bus, fcb, record);
+    read_records(bus, fcb, record, 1).0
+}
+
+/// INT 21h 22h
+pub fn write_random(bus: &mut Bus, addr: usize) -> u8 {
+    let (fcb, _) = locate(bus, addr);
+    let record = random_record(bus, fcb);
+    set_current_record(bus, fcb, record);
+    write_records(bus, fcb, record, 1).0
+}
+
+/// INT 21h 23h: sets the random record to the file's size in records
+pub fn file_size(bus: &mut Bus, addr: usize) -> u8 {
+    let (fcb, _) = locate(bus, addr);
+    let name = filename(bus, fcb);
+    match bus.disk.find_directory_entry(&name, 0, 0) {
+        Ok(entry) if !entry.is_dir => {
+            let records = entry.size.div_ceil(record_size(bus, fcb));
+            set_random_record(bus, fcb, records);
+            SUCCESS
+        }
+        _ => failed(bus, 0x02),
+    }
+}
+
+/// INT 21h 24h: sets the random record from the current one
+pub fn set_random(bus: &mut Bus, addr: usize) {
+    let (fcb, _) = locate(bus, addr);
+    let record = current_record(bus, fcb);
+    set_random_record(bus, fcb, record);
+}
+
+/// INT 21h 27h: reads `count` records from the random record on. Returns
+/// the result and the records read; both positions move past them.
+pub fn read_block(bus: &mut Bus, addr: usize, count: u16) -> (u8, u16) {
+    let (fcb, _) = locate(bus, addr);
+    let record = random_record(bus, fcb);
+    let (result, read) = read_records(bus, fcb, record, count as u32);
+    set_random_record(bus, fcb, record + read);
+    set_current_record(bus, fcb, record + read);
+    (result, read as u16)
+}
+
+/// INT 21h 28h: writes `count` records at the random record. A count of
+/// zero sets the file size there instead.
+pub fn write_block(bus: &mut Bus, addr: usize, count: u16) -> (u8, u16) {
+    let (fcb, _) = locate(bus, addr);
+    let record = random_record(bus, fcb);
+    if count == 0 {
+        let Some(sft) = sft(bus, fcb) else {
+            return (DISK_FULL, 0);
+        };
+        let size = record * record_size(bus, fcb);
+        if bus.disk.set_file_size(sft, size as u64).is_err() {
+            return (DISK_FULL, 0);
+        }
+        bus.write_32(fcb + 0x10, size);
+        return (SUCCESS, 0);
+    }
+    let (result, written) = write_records(bus, fcb, record, count as u32);
+    set_random_record(bus, fcb, record + written);
+    set_current_record(bus, fcb, record + written);
+    (result, written as u16)
+}
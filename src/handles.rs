@@ -0,0 +1,191 @@
+use crate::bus::Bus;
+use crate::disk::{SFT_AUX, SFT_CON, SFT_PRN};
+use crate::mcb;
+
+// Job file tables (INT 21h 3Ch-46h, 67h).
+//
+// A handle is an index into its process's JFT, whose bytes are indices into
+// the system file table in `DiskController`, FFh marking a free slot. The
+// table starts out in the PSP at 18h; INT 21h 67h can move it to a block of
+// its own. The PSP records where it is:
+//
+//   32h  Number of entries
+//   34h  Far pointer to the table
+//
+// DUP and inheritance share SFT entries, which count their references.
+
+pub const JFT_OFFSET: u16 = 0x18;
+pub const DEFAULT_JFT_SIZE: u16 = 20;
+
+/// Free JFT slot
+pub const UNUSED: u8 = 0xFF;
+
+/// Open mode bit that keeps a child from inheriting the handle
+pub const NO_INHERIT: u8 = 0x80;
+
+/// The shell runs without a PSP; its handles live here, in DOS's data area
+pub const SHELL_JFT: usize = 0x0600;
+
+// DOS error codes, returned in AX
+pub const ERR_TOO_MANY_OPEN_FILES: u8 = 0x04;
+pub const ERR_INVALID_HANDLE: u8 = 0x06;
+
+// stdin, stdout and stderr share CON; then stdaux and stdprn
+const STANDARD_HANDLES: [u8; 5] = [SFT_CON, SFT_CON, SFT_CON, SFT_AUX, SFT_PRN];
+
+// Address and size of the JFT of `psp`
+fn table(bus: &Bus, psp: u16) -> (usize, u16) {
+    if psp == 0 {
+        return (SHELL_JFT, DEFAULT_JFT_SIZE);
+    }
+    let base = (psp as usize) << 4;
+    let offset = bus.read_16(base + 0x34) as usize;
+    let segment = bus.read_16(base + 0x36) as usize;
+    ((segment << 4) + offset, bus.read_16(base + 0x32))
+}
+
+fn entries(bus: &Bus, psp: u16) -> Vec<u8> {
+    let (addr, size) = table(bus, psp);
+    (0..size as usize).map(|i| bus.read_8(addr + i)).collect()
+}
+
+/// Starts over: closes every file and gives the shell the standard handles
+pub fn init(bus: &mut Bus) {
+    bus.disk.reset_files();
+    for i in 0..DEFAULT_JFT_SIZE as usize {
+        let sft = STANDARD_HANDLES.get(i).copied().unwrap_or(UNUSED);
+        bus.write_8(SHELL_JFT + i, sft);
+        if sft != UNUSED {
+            bus.disk.add_ref(sft);
+        }
+    }
+}
+
+/// Sets up the JFT of a new process in its PSP. It inherits the parent's
+/// handles, except those opened with the no-inherit bit.
+pub fn init_psp(bus: &mut Bus, psp: u16, parent: u16) {
+    let inherited = entries(bus, parent);
+    let base = (psp as usize) << 4;
+    bus.write_16(base + 0x32, DEFAULT_JFT_SIZE);
+    bus.write_16(base + 0x34, JFT_OFFSET);
+    bus.write_16(base + 0x36, psp);
+
+    for i in 0..DEFAULT_JFT_SIZE as usize {
+        let sft = inherited.get(i).copied().unwrap_or(UNUSED);
+        let inherit = bus
+            .disk
+            .sft_entry(sft)
+            .is_some_and(|entry| entry.mode & NO_INHERIT == 0);
+        if inherit {
+            bus.disk.add_ref(sft);
+            bus.write_8(base + JFT_OFFSET as usize + i, sft);
+        } else {
+            bus.write_8(base + JFT_OFFSET as usize + i, UNUSED);
+        }
+    }
+}
+
+/// The SFT entry behind `handle`
+pub fn sft_index(bus: &Bus, psp: u16, handle: u16) -> Result<u8, u8> {
+    let (addr, size) = table(bus, psp);
+    if handle >= size {
+        return Err(ERR_INVALID_HANDLE);
+    }
+    match bus.read_8(addr + handle as usize) {
+        UNUSED => Err(ERR_INVALID_HANDLE),
+        sft if bus.disk.sft_entry(sft).is_some() => Ok(sft),
+        _ => Err(ERR_INVALID_HANDLE),
+    }
+}
+
+/// Gives SFT entry `sft` the lowest free handle
+pub fn allocate(bus: &mut Bus, psp: u16, sft: u8) -> Result<u16, u8> {
+    let (addr, size) = table(bus, psp);
+    let handle = (0..size)
+        .find(|&h| bus.read_8(addr + h as usize) == UNUSED)
+        .ok_or(ERR_TOO_MANY_OPEN_FILES)?;
+    bus.write_8(addr + handle as usize, sft);
+    bus.disk.add_ref(sft);
+    Ok(handle)
+}
+
+/// INT 21h 3Eh
+pub fn close(bus: &mut Bus, psp: u16, handle: u16) -> Result<(), u8> {
+    let sft = sft_index(bus, psp, handle)?;
+    let (addr, _) = table(bus, psp);
+    bus.write_8(addr + handle as usize, UNUSED);
+    bus.disk.release(sft);
+    Ok(())
+}
+
+/// INT 21h 45h: a second handle for the same open file
+pub fn dup(bus: &mut Bus, psp: u16, handle: u16) -> Result<u16, u8> {
+    let sft = sft_index(bus, psp, handle)?;
+    allocate(bus, psp, sft)
+}
+
+/// INT 21h 46h: makes `target` refer to the same file as `handle`,
+/// closing whatever `target` had open
+pub fn force_dup(bus: &mut Bus, psp: u16, handle: u16, target: u16) -> Result<(), u8> {
+    let sft = sft_index(bus, psp, handle)?;
+    let (addr, size) = table(bus, psp);
+    if target >= size {
+        return Err(ERR_INVALID_HANDLE);
+    }
+    if handle == target {
+        return Ok(());
+    }
+    bus.disk.add_ref(sft);
+    if let Ok(old) = sft_index(bus, psp, target) {
+        bus.disk.release(old);
+    }
+    bus.write_8(addr + target as usize, sft);
+    Ok(())
+}
+
+/// INT 21h 67h: resizes the JFT. Beyond 20 handles it moves to a memory
+/// block owned by the process; otherwise it goes back into the PSP.
+pub fn set_count(bus: &mut Bus, psp: u16, count: u16) -> Result<(), u8> {
+    if psp == 0 {
+        return Err(ERR_TOO_MANY_OPEN_FILES);
+    }
+    let old = entries(bus, psp);
+    let count = count.max(DEFAULT_JFT_SIZE);
+    if old.iter().skip(count as usize).any(|&sft| sft != UNUSED) {
+        return Err(ERR_TOO_MANY_OPEN_FILES);
+    }
+
+    let (old_addr, _) = table(bus, psp);
+    let base = (psp as usize) << 4;
+    let (segment, offset) = if count == DEFAULT_JFT_SIZE {
+        (psp, JFT_OFFSET)
+    } else {
+        let paras = (count as usize).div_ceil(16) as u16;
+        let strategy = bus.alloc_strategy;
+        let segment = mcb::allocate(bus, paras, psp, strategy).map_err(|(e, _)| e)?;
+        (segment, 0)
+    };
+
+    let addr = ((segment as usize) << 4) + offset as usize;
+    for i in 0..count as usize {
+        bus.write_8(addr + i, old.get(i).copied().unwrap_or(UNUSED));
+    }
+    bus.write_16(base + 0x32, count);
+    bus.write_16(base + 0x34, offset);
+    bus.write_16(base + 0x36, segment);
+
+    // An earlier table of its own goes back to the free pool
+    if old_addr != base + JFT_OFFSET as usize && old_addr != addr {
+        let _ = mcb::free(bus, (old_addr >> 4) as u16);
+    }
+    Ok(())
+}
+
+/// Closes every handle of a terminating process
+pub fn close_all(bus: &mut Bus, psp: u16) {
+    for (handle, sft) in entries(bus, psp).into_iter().enumerate() {
+        if sft != UNUSED {
+            let _ = close(bus, psp, handle as u16);
+        }
+    }
+}
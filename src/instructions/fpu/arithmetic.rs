@@ -1,5 +1,6 @@
 use crate::cpu::{Cpu, FpuFlags};
-use crate::f80::F80;
+use crate::f80::{F80, FpEnv};
+use crate::instructions::fpu::{record_exceptions, rounding_env, unmasked_fault};
 use crate::instructions::utils::calculate_addr;
 use iced_x86::{Instruction, MemorySize, OpKind, Register};
 
@@ -13,400 +14,210 @@ fn get_pop_dst_index(instr: &Instruction) -> usize {
     }
 }
 
+fn st_index(reg: Register) -> usize {
+    reg.number() - Register::ST0.number()
+}
+
+// Basic operations. The reverse forms swap the operands.
+#[derive(Clone, Copy)]
+enum Op {
+    Add,
+    Sub,
+    SubR,
+    Mul,
+    Div,
+    DivR,
+}
+
+fn apply(op: Op, dst: F80, src: F80, env: &mut FpEnv) -> F80 {
+    match op {
+        Op::Add => dst.add_with(src, env),
+        Op::Sub => dst.sub_with(src, env),
+        Op::SubR => src.sub_with(dst, env),
+        Op::Mul => dst.mul_with(src, env),
+        Op::Div => dst.div_with(src, env),
+        Op::DivR => src.div_with(dst, env),
+    }
+}
+
+// Real memory operand (m32fp / m64fp), converted exactly
+fn load_real(cpu: &mut Cpu, instr: &Instruction, env: &mut FpEnv) -> F80 {
+    let addr = calculate_addr(cpu, instr);
+    match instr.memory_size() {
+        MemorySize::Float32 => F80::from_f32_bits(cpu.bus.read_32(addr), env),
+        MemorySize::Float64 => F80::from_f64_bits(cpu.bus.read_64(addr), env),
+        _ => F80::new(),
+    }
+}
+
+// ST(0) op [mem]  or  ST(dst) op ST(src)
+fn binary(cpu: &mut Cpu, instr: &Instruction, op: Op) {
+    let mut env = rounding_env(cpu);
+    let (dst_idx, res) = if instr.op0_kind() == OpKind::Memory {
+        let src = load_real(cpu, instr, &mut env);
+        (0, apply(op, cpu.fpu_get(0), src, &mut env))
+    } else {
+        let dst_idx = st_index(instr.op0_register());
+        let src_idx = st_index(instr.op1_register());
+        (dst_idx, apply(op, cpu.fpu_get(dst_idx), cpu.fpu_get(src_idx), &mut env))
+    };
+    if !unmasked_fault(cpu, &env) {
+        cpu.fpu_set(dst_idx, res);
+    }
+    record_exceptions(cpu, &env);
+}
+
+// ST(i) = ST(i) op ST(0), then pop
+fn binary_pop(cpu: &mut Cpu, instr: &Instruction, op: Op) {
+    let idx = get_pop_dst_index(instr);
+    let mut env = rounding_env(cpu);
+    let res = apply(op, cpu.fpu_get(idx), cpu.fpu_get(0), &mut env);
+    // Neither the store nor the pop happens on an unmasked fault
+    let fault = unmasked_fault(cpu, &env);
+    if !fault {
+        cpu.fpu_set(idx, res);
+    }
+    record_exceptions(cpu, &env);
+    if !fault {
+        cpu.fpu_pop();
+    }
+}
+
+// ST(0) = ST(0) op [mem_int]
+fn binary_int(cpu: &mut Cpu, instr: &Instruction, op: Op) {
+    let addr = calculate_addr(cpu, instr);
+    let val = cpu.load_int_to_f80(addr, instr.memory_size());
+    let mut env = rounding_env(cpu);
+    let res = apply(op, cpu.fpu_get(0), val, &mut env);
+    if !unmasked_fault(cpu, &env) {
+        cpu.fpu_set(0, res);
+    }
+    record_exceptions(cpu, &env);
+}
+
 // FIADD: Add Integer
 // ST(0) = ST(0) + [mem_int]
 pub fn fiadd(cpu: &mut Cpu, instr: &Instruction) {
-    let addr = calculate_addr(cpu, instr);
-    let val = cpu.load_int_to_f80(addr, instr.memory_size());
-    let mut st0 = cpu.fpu_get(0);
-    st0.add(val);
-    cpu.fpu_set(0, st0);
+    binary_int(cpu, instr, Op::Add);
 }
 
 // FISUB: Subtract Integer
 // ST(0) = ST(0) - [mem_int]
 pub fn fisub(cpu: &mut Cpu, instr: &Instruction) {
-    let addr = calculate_addr(cpu, instr);
-    let val = cpu.load_int_to_f80(addr, instr.memory_size());
-    let mut st0 = cpu.fpu_get(0);
-    st0.sub(val);
-    cpu.fpu_set(0, st0);
+    binary_int(cpu, instr, Op::Sub);
 }
 
 // FISUBR: Subtract Integer Reverse
 // ST(0) = [mem_int] - ST(0)
 pub fn fisubr(cpu: &mut Cpu, instr: &Instruction) {
-    let addr = calculate_addr(cpu, instr);
-    let mut val = cpu.load_int_to_f80(addr, instr.memory_size());
-    let st0 = cpu.fpu_get(0);
-    val.sub(st0);
-    cpu.fpu_set(0, val);
+    binary_int(cpu, instr, Op::SubR);
 }
 
 // FIMUL: Multiply Integer
 // ST(0) = ST(0) * [mem_int]
 pub fn fimul(cpu: &mut Cpu, instr: &Instruction) {
-    let addr = calculate_addr(cpu, instr);
-    let val = cpu.load_int_to_f80(addr, instr.memory_size()).get_f64();
-    let mut st0 = cpu.fpu_get(0);
-    // Note: If F80 doesn't have mul yet, use f64 as intermediary
-    let res_f = st0.get_f64() * val;
-    st0.set_f64(res_f);
-    cpu.fpu_set(0, st0);
+    binary_int(cpu, instr, Op::Mul);
 }
 
 // FIDIV: Divide Integer
 // ST(0) = ST(0) / [mem_int]
 pub fn fidiv(cpu: &mut Cpu, instr: &Instruction) {
-    let addr = calculate_addr(cpu, instr);
-    let val = cpu.load_int_to_f80(addr, instr.memory_size()).get_f64();
-    let mut st0 = cpu.fpu_get(0);
-    if val != 0.0 {
-        st0.set_f64(st0.get_f64() / val);
-    } else {
-        st0.set_f64(f64::INFINITY);
-    }
-    cpu.fpu_set(0, st0);
+    binary_int(cpu, instr, Op::Div);
 }
 
 // FIDIVR: Reverse Integer Divide
 // ST(0) = [mem_int] / ST(0)
 pub fn fidivr(cpu: &mut Cpu, instr: &Instruction) {
-    let addr = calculate_addr(cpu, instr);
-    let val = cpu.load_int_to_f80(addr, instr.memory_size()).get_f64();
-    let mut st0 = cpu.fpu_get(0);
-    let st0_f = st0.get_f64();
-    if st0_f != 0.0 {
-        st0.set_f64(val / st0_f);
-    } else {
-        st0.set_real_indefinite();
-    }
-    cpu.fpu_set(0, st0);
+    binary_int(cpu, instr, Op::DivR);
 }
 
 // FADD: Add Real
 pub fn fadd(cpu: &mut Cpu, instr: &Instruction) {
-    if instr.op0_kind() == OpKind::Memory {
-        let addr = calculate_addr(cpu, instr);
-        let mut val = F80::new();
-        match instr.memory_size() {
-            MemorySize::Float32 => val.set_f64(f32::from_bits(cpu.bus.read_32(addr)) as f64),
-            MemorySize::Float64 => val.set_f64(f64::from_bits(cpu.bus.read_64(addr))),
-            _ => {}
-        }
-        let mut st0 = cpu.fpu_get(0);
-        st0.add(val);
-        cpu.fpu_set(0, st0);
-    } else {
-        let dst_reg = instr.op0_register();
-        let src_reg = instr.op1_register();
-        let idx_src = (src_reg.number() - Register::ST0.number()) as usize;
-        let idx_dst = (dst_reg.number() - Register::ST0.number()) as usize;
-
-        let mut dest = cpu.fpu_get(idx_dst);
-        let src = cpu.fpu_get(idx_src);
-        dest.add(src);
-        cpu.fpu_set(idx_dst, dest);
-    }
+    binary(cpu, instr, Op::Add);
 }
 
 // FADDP: Add and Pop
 pub fn faddp(cpu: &mut Cpu, instr: &Instruction) {
-    let dst_reg = instr.op0_register();
-    let idx = if dst_reg == Register::None || dst_reg == Register::ST1 {
-        1
-    } else {
-        (dst_reg.number() - Register::ST0.number()) as usize
-    };
-
-    let mut sti = cpu.fpu_get(idx);
-    let st0 = cpu.fpu_get(0);
-    sti.add(st0);
-    cpu.fpu_set(idx, sti);
-    cpu.fpu_pop();
+    binary_pop(cpu, instr, Op::Add);
 }
 
 // FSUB: Subtract Real
 // ST(0) = ST(0) - Src  OR  Dest = Dest - ST(0)
 pub fn fsub(cpu: &mut Cpu, instr: &Instruction) {
-    if instr.op0_kind() == OpKind::Memory {
-        let addr = calculate_addr(cpu, instr);
-        let mut val = F80::new();
-        match instr.memory_size() {
-            MemorySize::Float32 => val.set_f64(f32::from_bits(cpu.bus.read_32(addr)) as f64),
-            MemorySize::Float64 => val.set_f64(f64::from_bits(cpu.bus.read_64(addr))),
-            _ => {}
-        }
-        let mut st0 = cpu.fpu_get(0);
-        st0.sub(val);
-        cpu.fpu_set(0, st0);
-    } else {
-        let dst_idx = (instr.op0_register().number() - Register::ST0.number()) as usize;
-        let src_idx = (instr.op1_register().number() - Register::ST0.number()) as usize;
-        let mut dst = cpu.fpu_get(dst_idx);
-        let src = cpu.fpu_get(src_idx);
-        dst.sub(src);
-        cpu.fpu_set(dst_idx, dst);
-    }
+    binary(cpu, instr, Op::Sub);
 }
 
 // FSUBP: Subtract and Pop
 // ST(i) = ST(i) - ST(0); Pop ST(0)
 pub fn fsubp(cpu: &mut Cpu, instr: &Instruction) {
-    let idx = get_pop_dst_index(instr);
-
-    let st0 = cpu.fpu_get(0);
-    let mut sti = cpu.fpu_get(idx);
-    sti.sub(st0);
-    cpu.fpu_set(idx, sti);
-    cpu.fpu_pop();
+    binary_pop(cpu, instr, Op::Sub);
 }
 
 // FSUBR: Reverse Subtract
 // ST(0) = Src - ST(0)  OR  Dest = ST(0) - Dest
 pub fn fsubr(cpu: &mut Cpu, instr: &Instruction) {
-    if instr.op0_kind() == OpKind::Memory {
-        let addr = calculate_addr(cpu, instr);
-        let mut val = F80::new();
-        match instr.memory_size() {
-            MemorySize::Float32 => val.set_f64(f32::from_bits(cpu.bus.read_32(addr)) as f64),
-            MemorySize::Float64 => val.set_f64(f64::from_bits(cpu.bus.read_64(addr))),
-            _ => {}
-        }
-        let st0 = cpu.fpu_get(0);
-        val.sub(st0);
-        cpu.fpu_set(0, val);
-    } else {
-        let dst_idx = (instr.op0_register().number() - Register::ST0.number()) as usize;
-        let src_idx = (instr.op1_register().number() - Register::ST0.number()) as usize;
-        let dst = cpu.fpu_get(dst_idx);
-        let mut src = cpu.fpu_get(src_idx);
-        src.sub(dst);
-        cpu.fpu_set(dst_idx, src);
-    }
+    binary(cpu, instr, Op::SubR);
 }
 
 // FSUBRP: Reverse Subtract and Pop
 // ST(i) = ST(0) - ST(i); Pop ST(0)
 pub fn fsubrp(cpu: &mut Cpu, instr: &Instruction) {
-    let idx = get_pop_dst_index(instr);
-
-    let mut st0 = cpu.fpu_get(0);
-    let sti = cpu.fpu_get(idx);
-    st0.sub(sti); // Result is in st0 (local var)
-    cpu.fpu_set(idx, st0); // Write result to ST(i)
-    cpu.fpu_pop();
+    binary_pop(cpu, instr, Op::SubR);
 }
 
 // FMUL: Multiply Real
 pub fn fmul(cpu: &mut Cpu, instr: &Instruction) {
-    if instr.op0_kind() == OpKind::Memory {
-        let addr = calculate_addr(cpu, instr);
-        let mut val = F80::new();
-        match instr.memory_size() {
-            MemorySize::Float32 => val.set_f64(f32::from_bits(cpu.bus.read_32(addr)) as f64),
-            MemorySize::Float64 => val.set_f64(f64::from_bits(cpu.bus.read_64(addr))),
-            _ => {}
-        }
-        let mut st0 = cpu.fpu_get(0);
-        st0.set_f64(st0.get_f64() * val.get_f64());
-        cpu.fpu_set(0, st0);
-    } else {
-        let dst_idx = (instr.op0_register().number() - Register::ST0.number()) as usize;
-        let src_idx = (instr.op1_register().number() - Register::ST0.number()) as usize;
-        let mut dst = cpu.fpu_get(dst_idx);
-        let src = cpu.fpu_get(src_idx);
-        dst.set_f64(dst.get_f64() * src.get_f64());
-        cpu.fpu_set(dst_idx, dst);
-    }
+    binary(cpu, instr, Op::Mul);
 }
 
 // FMULP: Multiply and Pop
 pub fn fmulp(cpu: &mut Cpu, instr: &Instruction) {
-    let dst_reg = instr.op0_register();
-    let idx = if dst_reg == Register::None || dst_reg == Register::ST1 {
-        1
-    } else {
-        (dst_reg.number() - Register::ST0.number()) as usize
-    };
-    let mut sti = cpu.fpu_get(idx);
-    let st0 = cpu.fpu_get(0);
-    sti.set_f64(sti.get_f64() * st0.get_f64());
-    cpu.fpu_set(idx, sti);
-    cpu.fpu_pop();
+    binary_pop(cpu, instr, Op::Mul);
 }
 
 // FDIV: Floating Point Divide
+// Division by zero sets ZE and gives a signed infinity
 pub fn fdiv(cpu: &mut Cpu, instr: &Instruction) {
-    if instr.op0_kind() == OpKind::Memory {
-        let addr = calculate_addr(cpu, instr);
-        let mut divisor = F80::new();
-        match instr.memory_size() {
-            MemorySize::Float32 => divisor.set_f64(f32::from_bits(cpu.bus.read_32(addr)) as f64),
-            MemorySize::Float64 => divisor.set_f64(f64::from_bits(cpu.bus.read_64(addr))),
-            _ => {}
-        }
-        let mut st0 = cpu.fpu_get(0);
-        let div_f = divisor.get_f64();
-        if div_f != 0.0 {
-            st0.set_f64(st0.get_f64() / div_f);
-        } else {
-            st0.set_real_indefinite();
-        }
-        cpu.fpu_set(0, st0);
-    } else {
-        let dst_idx = (instr.op0_register().number() - Register::ST0.number()) as usize;
-        let src_idx = (instr.op1_register().number() - Register::ST0.number()) as usize;
-        let mut dst = cpu.fpu_get(dst_idx);
-        let src = cpu.fpu_get(src_idx);
-        let src_f = src.get_f64();
-        if src_f != 0.0 {
-            dst.set_f64(dst.get_f64() / src_f);
-        } else {
-            dst.set_real_indefinite();
-        }
-        cpu.fpu_set(dst_idx, dst);
-    }
+    binary(cpu, instr, Op::Div);
 }
 
 // FDIVP: Divide and Pop
 pub fn fdivp(cpu: &mut Cpu, instr: &Instruction) {
-    let dst_reg = instr.op0_register();
-    let idx = if dst_reg == Register::None || dst_reg == Register::ST1 {
-        1
-    } else {
-        (dst_reg.number() - Register::ST0.number()) as usize
-    };
-    let mut sti = cpu.fpu_get(idx);
-    let st0 = cpu.fpu_get(0).get_f64();
-    if st0 != 0.0 {
-        sti.set_f64(sti.get_f64() / st0);
-    } else {
-        sti.set_real_indefinite();
-    }
-    cpu.fpu_set(idx, sti);
-    cpu.fpu_pop();
+    binary_pop(cpu, instr, Op::Div);
 }
 
 // FDIVR: Reverse Divide
+// FDIVR ST(0), ST(i) -> ST(0) = ST(i) / ST(0)
+// FDIVR ST(i), ST(0) -> ST(i) = ST(0) / ST(i)
 pub fn fdivr(cpu: &mut Cpu, instr: &Instruction) {
-    if instr.op0_kind() == OpKind::Memory {
-        // FDIVR [mem] -> ST(0) = [mem] / ST(0)
-        let addr = calculate_addr(cpu, instr);
-        let mut mem_val = F80::new();
-        match instr.memory_size() {
-            MemorySize::Float32 => mem_val.set_f64(f32::from_bits(cpu.bus.read_32(addr)) as f64),
-            MemorySize::Float64 => mem_val.set_f64(f64::from_bits(cpu.bus.read_64(addr))),
-            _ => mem_val.set_f64(1.0),
-        }
-
-        let mut st0 = cpu.fpu_get(0);
-        let st0_f = st0.get_f64();
-
-        if st0_f != 0.0 {
-            st0.set_f64(mem_val.get_f64() / st0_f);
-        } else {
-            st0.set_real_indefinite(); // Handle division by zero
-            cpu.set_fpu_flag(FpuFlags::ZE, true);
-        }
-        cpu.fpu_set(0, st0);
-    } else {
-        let dst_idx = (instr.op0_register().number() - Register::ST0.number()) as usize;
-        let src_idx = (instr.op1_register().number() - Register::ST0.number()) as usize;
-
-        let mut dst = cpu.fpu_get(dst_idx);
-        let src = cpu.fpu_get(src_idx);
-
-        // FDIVR ST(0), ST(i) -> ST(0) = ST(i) / ST(0)
-        // FDIVR ST(i), ST(0) -> ST(i) = ST(0) / ST(i)
-        // In both cases, we divide the "Source" by the "Destination"
-        let dst_f = dst.get_f64();
-        if dst_f != 0.0 {
-            dst.set_f64(src.get_f64() / dst_f);
-        } else {
-            dst.set_real_indefinite();
-            cpu.set_fpu_flag(FpuFlags::ZE, true);
-        }
-        cpu.fpu_set(dst_idx, dst);
-    }
+    binary(cpu, instr, Op::DivR);
 }
 
 // FDIVRP: Reverse Divide and Pop
 // ST(i) = ST(0) / ST(i); Pop ST(0)
 pub fn fdivrp(cpu: &mut Cpu, instr: &Instruction) {
-    let idx = get_pop_dst_index(instr);
-
-    let st0_val = cpu.fpu_get(0).get_f64();
-    let mut sti = cpu.fpu_get(idx);
-    let sti_val = sti.get_f64();
-
-    if sti_val != 0.0 {
-        sti.set_f64(st0_val / sti_val);
-    } else {
-        sti.set_real_indefinite(); // Zero divide
-        cpu.set_fpu_flag(FpuFlags::ZE, true);
-    }
-
-    cpu.fpu_set(idx, sti);
-    cpu.fpu_pop();
+    binary_pop(cpu, instr, Op::DivR);
 }
 
 // --- ADVANCED ARITHMETIC ---
 
 pub fn fprem_internal(cpu: &mut Cpu, ieee: bool) {
-    let st0_obj = cpu.fpu_get(0);
-    let st1_obj = cpu.fpu_get(1);
-
-    let st0 = st0_obj.get_f64();
-    let st1 = st1_obj.get_f64();
-
-    if st1 == 0.0 || st0.is_infinite() {
-        cpu.set_fpu_flag(FpuFlags::IE, true);
-        let mut nan = F80::new();
-        nan.set_real_indefinite();
-        cpu.fpu_set(0, nan);
-        return;
-    }
-
-    // FPREM spec allows partial reduction if exponent difference is large.
-    // However, to avoid complexity and precision issues with large quotients in manual reduction,
-    // we perform a "complete" reduction immediately using the underlying f64 remainder.
-    // This is compliant as long as we clear C2 (indicating completion).
-
-    let (remainder, q_bits) = if ieee {
-        // FPREM1: Round to nearest
-        let quotient = st0 / st1;
-        let q_rnd = quotient.round();
-        let rem = st0 - q_rnd * st1;
-        (rem, q_rnd as i64)
-    } else {
-        // FPREM: Round toward zero (Truncate)
-        // Use f64 standard remainder (%) which is truncating
-        let rem = st0 % st1;
-
-        // Calculate quotient bits (Q2, Q1, Q0) without full quotient overflow
-        // Q = trunc(ST0 / ST1). We need Q % 8.
-        // Q % 8 = trunc( (ST0 % (8 * ST1)) / ST1 )
-        let st0_abs = st0.abs();
-        let st1_abs = st1.abs();
-        let st0_mod8 = st0_abs % (8.0 * st1_abs);
-        let q_mod8 = (st0_mod8 / st1_abs).trunc() as i64;
-
-        let sign_diff = st0.is_sign_negative() ^ st1.is_sign_negative();
-        let q_final = if sign_diff { -q_mod8 } else { q_mod8 };
-
-        (rem, q_final)
-    };
-
-    let mut res = F80::new();
-    res.set_f64(remainder);
-    cpu.fpu_set(0, res);
+    let mut env = rounding_env(cpu);
+    let (remainder, q_bits, complete) = cpu.fpu_get(0).rem_with(cpu.fpu_get(1), ieee, &mut env);
+    cpu.fpu_set(0, remainder);
+    record_exceptions(cpu, &env);
 
     cpu.set_fpu_flag(
         FpuFlags::C0 | FpuFlags::C1 | FpuFlags::C2 | FpuFlags::C3,
         false,
     );
 
+    // C2 set: the exponents were too far apart, run FPREM again
+    if !complete {
+        cpu.set_fpu_flag(FpuFlags::C2, true);
+        return;
+    }
+
     // Set C0, C3, C1 from Q2, Q1, Q0
     if (q_bits & 4) != 0 {
         cpu.set_fpu_flag(FpuFlags::C0, true);
@@ -433,19 +244,10 @@ pub fn fprem1(cpu: &mut Cpu) {
 
 // FRNDINT: Round to Integer
 pub fn frndint(cpu: &mut Cpu) {
-    let mut st0 = cpu.fpu_get(0);
-    let val = st0.get_f64();
-    let rc = (cpu.fpu_control >> 10) & 0x03;
-    let result = match rc {
-        0 => val.round(), // Nearest
-        1 => val.floor(), // Down
-        2 => val.ceil(),  // Up
-        3 => val.trunc(), // Toward Zero
-        _ => val,
-    };
-    st0.set_f64(result);
-    cpu.fpu_set(0, st0);
-    cpu.set_fpu_flag(FpuFlags::C2, false);
+    let mut env = rounding_env(cpu);
+    let result = cpu.fpu_get(0).round_int_with(&mut env);
+    cpu.fpu_set(0, result);
+    record_exceptions(cpu, &env);
 }
 
 // FABS: Absolute Value
@@ -467,102 +269,57 @@ pub fn fchs(cpu: &mut Cpu) {
 // FSCALE: Scale by 2^trunc(ST(1))
 // ST(0) = ST(0) * 2^(trunc(ST(1)))
 pub fn fscale(cpu: &mut Cpu) {
-    let mut st0 = cpu.fpu_get(0);
-    let st1 = cpu.fpu_get(1).get_f64().trunc();
-    let res = st0.get_f64() * 2.0_f64.powf(st1);
-    st0.set_f64(res);
-    cpu.fpu_set(0, st0);
+    let mut env = rounding_env(cpu);
+    let result = cpu.fpu_get(0).scale_with(cpu.fpu_get(1), &mut env);
+    cpu.fpu_set(0, result);
+    record_exceptions(cpu, &env);
 }
 
 // FSQRT: Square Root
 // ST(0) = sqrt(ST(0))
+// -0.0 gives -0.0, anything else negative is an Invalid Operation
 pub fn fsqrt(cpu: &mut Cpu) {
-    let mut st0 = cpu.fpu_get(0);
-    let val = st0.get_f64();
-
-    // x87 handles -0.0 by returning -0.0
-    if st0.is_zero() {
-        // Result is already zero, just preserve the sign (usually +0.0 or -0.0)
-        cpu.fpu_set(0, st0);
-        cpu.set_fpu_flag(FpuFlags::C1, false);
-        return;
-    }
-
-    if !st0.get_sign() {
-        // Case: Positive number
-        st0.set_f64(val.sqrt());
-        cpu.fpu_set(0, st0);
-        cpu.set_fpu_flag(FpuFlags::C1, false); // No rounding-up occurred (simplified)
-    } else {
-        // Case: Negative number (Invalid Operation)
-        cpu.set_fpu_flag(FpuFlags::IE, true); // Set Invalid Operation bit
-
-        // Return "Real Indefinite" (The special NaN for FPU errors)
-        st0.set_real_indefinite();
-        cpu.fpu_set(0, st0);
-    }
+    let mut env = rounding_env(cpu);
+    let result = cpu.fpu_get(0).sqrt_with(&mut env);
+    cpu.fpu_set(0, result);
+    record_exceptions(cpu, &env);
 }
 
 // FXTRACT: Extract Exponent and Significand
-// Separates ST(0) into exponent and significand.
-// ST(0) becomes Exponent (unbiased), Push Significand.
+// ST(0) becomes the exponent (unbiased), then the significand is pushed.
+// Zero gives an exponent of -inf and raises ZE.
 pub fn fxtract(cpu: &mut Cpu) {
-    let val = cpu.fpu_get(0);
-    let f_val = val.get_f64();
-
-    // Handle Zero case
-    if f_val == 0.0 {
-        // ST(0) = -Infinity
-        let mut neg_inf = F80::new();
-        neg_inf.set_f64(f64::NEG_INFINITY);
-        cpu.fpu_set(0, neg_inf);
-
-        // Push 0.0
-        let mut zero = F80::new();
-        zero.set_f64(0.0);
-        cpu.fpu_push(zero);
-        return;
-    }
-
-    let exp = (val.get_exponent() as i32) - 16383;
-    let mut sig = val;
-    sig.set_exponent(16383); // Normalize significand to 1.xx
-
-    // ST(0) becomes Exponent
-    let mut f_exp = F80::new();
-    f_exp.set_f64(exp as f64);
-    cpu.fpu_set(0, f_exp);
-
-    // Push Significand (New ST(0))
-    cpu.fpu_push(sig);
+    let mut env = rounding_env(cpu);
+    let (exponent, significand) = cpu.fpu_get(0).extract_with(&mut env);
+    cpu.fpu_set(0, exponent);
+    cpu.fpu_push(significand);
+    record_exceptions(cpu, &env);
 }
 
 // F2XM1: 2^x - 1
 pub fn f2xm1(cpu: &mut Cpu) {
-    let mut st0 = cpu.fpu_get(0);
-    st0.set_f64(2.0f64.powf(st0.get_f64()) - 1.0);
-    cpu.fpu_set(0, st0);
+    let mut env = rounding_env(cpu);
+    let result = cpu.fpu_get(0).f2xm1_with(&mut env);
+    cpu.fpu_set(0, result);
+    record_exceptions(cpu, &env);
 }
 
 // FYL2X: y * log2(x)
 // ST(1) = ST(1) * log2(ST(0)); Pop ST(0)
 pub fn fyl2x(cpu: &mut Cpu) {
-    let x = cpu.fpu_get(0).get_f64();
-    let mut y = cpu.fpu_get(1);
-    if x > 0.0 {
-        y.set_f64(y.get_f64() * x.log2());
-    } else {
-        y.set_QNaN();
-    }
-    cpu.fpu_set(1, y);
+    let mut env = rounding_env(cpu);
+    let result = cpu.fpu_get(0).yl2x_with(cpu.fpu_get(1), &mut env);
+    cpu.fpu_set(1, result);
+    record_exceptions(cpu, &env);
     cpu.fpu_pop();
 }
 
 // FYL2XP1: y * log2(x + 1)
+// ST(1) = ST(1) * log2(ST(0) + 1); Pop ST(0)
 pub fn fyl2xp1(cpu: &mut Cpu) {
-    let x = cpu.fpu_get(0).get_f64();
-    let mut y = cpu.fpu_get(1);
-    y.set_f64(y.get_f64() * (x + 1.0).log2());
-    cpu.fpu_set(1, y);
+    let mut env = rounding_env(cpu);
+    let result = cpu.fpu_get(0).yl2xp1_with(cpu.fpu_get(1), &mut env);
+    cpu.fpu_set(1, result);
+    record_exceptions(cpu, &env);
     cpu.fpu_pop();
 }
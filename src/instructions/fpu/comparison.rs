@@ -1,51 +1,56 @@
 use iced_x86::{Instruction, Mnemonic, OpKind, MemorySize, Register};
+use std::cmp::Ordering;
 
 use crate::cpu::{Cpu, FpuFlags, CpuFlags, FPU_TAG_EMPTY};
+use crate::f80::{F80, FpEnv};
+use crate::instructions::fpu::{record_exceptions, rounding_env};
 use crate::instructions::utils::calculate_addr;
 
 // Performs the FPU comparison and sets Status Word flags
-// Used by FCOM, FCOMP, FCOMPP
-fn fpu_compare_values(cpu: &mut Cpu, lhs: f64, rhs: f64) {
+// Used by FCOM, FCOMP, FCOMPP. `quiet` is the FUCOM flavour.
+fn fpu_compare_values(cpu: &mut Cpu, env: &mut FpEnv, lhs: F80, rhs: F80, quiet: bool) {
+    let order = lhs.compare_with(rhs, quiet, env);
+    record_exceptions(cpu, env);
+
     // Clear C0, C2, C3
     cpu.set_fpu_flag(FpuFlags::C0 | FpuFlags::C2 | FpuFlags::C3, false);
 
-    if lhs.is_nan() || rhs.is_nan() {
+    match order {
         // Unordered: C3=1, C2=1, C0=1
-        cpu.set_fpu_flag(FpuFlags::C0 | FpuFlags::C2 | FpuFlags::C3, true);
-    } else if lhs == rhs {
+        None => cpu.set_fpu_flag(FpuFlags::C0 | FpuFlags::C2 | FpuFlags::C3, true),
         // Equal: C3=1
-        cpu.set_fpu_flag(FpuFlags::C3, true);
-    } else if lhs < rhs {
+        Some(Ordering::Equal) => cpu.set_fpu_flag(FpuFlags::C3, true),
         // Less Than: C0=1
-        cpu.set_fpu_flag(FpuFlags::C0, true);
+        Some(Ordering::Less) => cpu.set_fpu_flag(FpuFlags::C0, true),
+        // Greater Than: All flags 0
+        Some(Ordering::Greater) => {}
     }
-    // Greater Than: All flags 0
 }
 
 pub fn fcom_variants(cpu: &mut Cpu, instr: &Instruction) {
+    let mut env = rounding_env(cpu);
     let (lhs, rhs) = if instr.mnemonic() == Mnemonic::Fcompp {
         // FCOMPP is always ST(0) vs ST(1)
-        (cpu.fpu_get(0).get_f64(), cpu.fpu_get(1).get_f64())
+        (cpu.fpu_get(0), cpu.fpu_get(1))
     } else {
         match instr.op0_kind() {
             OpKind::Memory => {
                 // Memory Comparison is ALWAYS ST(0) vs Memory
-                let val_0 = cpu.fpu_get(0).get_f64();
+                let val_0 = cpu.fpu_get(0);
                 let addr = calculate_addr(cpu, instr);
                 let val_op = match instr.memory_size() {
-                    MemorySize::Float32 => f32::from_bits(cpu.bus.read_32(addr)) as f64,
-                    MemorySize::Float64 => f64::from_bits(cpu.bus.read_64(addr)),
-                    _ => f64::NAN, 
+                    MemorySize::Float32 => F80::from_f32_bits(cpu.bus.read_32(addr), &mut env),
+                    MemorySize::Float64 => F80::from_f64_bits(cpu.bus.read_64(addr), &mut env),
+                    _ => F80::indefinite(),
                 };
                 (val_0, val_op)
             }
             OpKind::Register => {
                 // Read raw opcode from memory
                 // iced_x86 apparently has a bug with D8 vs DC ambiguity
-                let cs_base = (cpu.cs as u32) << 4;
-                let instr_addr = (cpu.ip as u32).wrapping_sub(instr.len() as u32);
-                let phys_addr = cs_base.wrapping_add(instr_addr) & 0xFFFFF;
-                let opcode_byte = cpu.bus.read_8(phys_addr as usize);
+                let instr_addr = cpu.ip.wrapping_sub(instr.len() as u16);
+                let phys_addr = cpu.get_physical_addr(cpu.cs, instr_addr);
+                let opcode_byte = cpu.bus.read_8(phys_addr);
 
                 // Identify the operand register index (i)
                 // iced_x86 might say "FCOM ST0, ST1" or "FCOM ST1, ST0"
@@ -61,8 +66,8 @@ pub fn fcom_variants(cpu: &mut Cpu, instr: &Instruction) {
                     1 // Default to ST(1) if parsing fails or implicit
                 };
 
-                let val_i = cpu.fpu_get(idx as usize).get_f64();
-                let val_0 = cpu.fpu_get(0).get_f64();
+                let val_i = cpu.fpu_get(idx as usize);
+                let val_0 = cpu.fpu_get(0);
 
                 // Determine direction
                 // If memory has 0xDC, it's Reverse.
@@ -84,12 +89,12 @@ pub fn fcom_variants(cpu: &mut Cpu, instr: &Instruction) {
                 }
             }
             _ => {
-                (cpu.fpu_get(0).get_f64(), cpu.fpu_get(1).get_f64())
+                (cpu.fpu_get(0), cpu.fpu_get(1))
             }
         }
     };
 
-    fpu_compare_values(cpu, lhs, rhs);
+    fpu_compare_values(cpu, &mut env, lhs, rhs, false);
 
     match instr.mnemonic() {
         Mnemonic::Fcomp => { cpu.fpu_pop(); },
@@ -98,15 +103,36 @@ pub fn fcom_variants(cpu: &mut Cpu, instr: &Instruction) {
     }
 }
 
+// FUCOM / FUCOMP / FUCOMPP: Unordered Compare (387+)
+// Same condition codes as FCOM. The difference is that a quiet NaN operand
+// doesn't raise Invalid Operation.
+pub fn fucom_variants(cpu: &mut Cpu, instr: &Instruction) {
+    // FUCOMPP has no operands and always uses ST(1)
+    let idx = if instr.op_count() == 2 {
+        instr.op1_register().number() - Register::ST0.number()
+    } else {
+        1
+    };
+
+    let mut env = rounding_env(cpu);
+    fpu_compare_values(cpu, &mut env, cpu.fpu_get(0), cpu.fpu_get(idx), true);
+
+    match instr.mnemonic() {
+        Mnemonic::Fucomp => { cpu.fpu_pop(); },
+        Mnemonic::Fucompp => { cpu.fpu_pop(); cpu.fpu_pop(); },
+        _ => {}
+    }
+}
+
 pub fn ficom_variants(cpu: &mut Cpu, instr: &Instruction) {
-    let st0 = cpu.fpu_get(0).get_f64();
     let addr = calculate_addr(cpu, instr);
     let val = match instr.memory_size() {
-        MemorySize::Int16 => (cpu.bus.read_16(addr) as i16) as f64,
-        MemorySize::Int32 => (cpu.bus.read_32(addr) as i32) as f64,
-        _ => 0.0,
+        MemorySize::Int16 => cpu.bus.read_16(addr) as i16 as i64,
+        MemorySize::Int32 => cpu.bus.read_32(addr) as i32 as i64,
+        _ => 0,
     };
-    fpu_compare_values(cpu, st0, val);
+    let mut env = rounding_env(cpu);
+    fpu_compare_values(cpu, &mut env, cpu.fpu_get(0), F80::from_i64(val), false);
     if instr.mnemonic() == Mnemonic::Ficomp {
         cpu.fpu_pop();
     }
@@ -150,9 +176,9 @@ pub fn fxam(cpu: &mut Cpu) {
 
 // FTST: Test ST(0) against 0.0
 pub fn ftst(cpu: &mut Cpu) {
-    let st0 = cpu.fpu_get(0).get_f64();
     // Compare ST(0) vs 0.0
-    fpu_compare_values(cpu, st0, 0.0);
+    let mut env = rounding_env(cpu);
+    fpu_compare_values(cpu, &mut env, cpu.fpu_get(0), F80::zero(false), false);
 }
 
 // FCOMI/FUCOMI... (Pentium Pro+)
@@ -163,35 +189,24 @@ pub fn fcomi_variants(cpu: &mut Cpu, instr: &Instruction) {
     let sti = cpu.fpu_get(idx);
     
     // Set ZF, PF, CF based on comparison
-    // ZF=1 if Equal, CF=1 if Less, PF=1 if NaN
-    #[allow(unused_assignments)]
-    let mut zf = false;
-    #[allow(unused_assignments)]
-    let mut pf = false;
-    #[allow(unused_assignments)]
-    let mut cf = false;
-    
-    if st0.is_nan() || sti.is_nan() {
-        zf = true; pf = true; cf = true; // "Unordered"
-    } else if st0.get() == sti.get() {
-        zf = true; pf = false; cf = false; // Equal
-    } else {
-        // For magnitude comparison, get_f64 is sufficient 
-        let a = st0.get_f64();
-        let b = sti.get_f64();
-        if a < b {
-            zf = false; pf = false; cf = true;
-        } else {
-            zf = false; pf = false; cf = false;
-        }
-    }
-    
+    // ZF=1 if Equal, CF=1 if Less, all three if unordered
+    let m = instr.mnemonic();
+    let quiet = m == iced_x86::Mnemonic::Fucomi || m == iced_x86::Mnemonic::Fucomip;
+    let mut env = rounding_env(cpu);
+    let order = st0.compare_with(sti, quiet, &mut env);
+    record_exceptions(cpu, &env);
+    let (zf, pf, cf) = match order {
+        None => (true, true, true),
+        Some(Ordering::Equal) => (true, false, false),
+        Some(Ordering::Less) => (false, false, true),
+        Some(Ordering::Greater) => (false, false, false),
+    };
+
     cpu.set_cpu_flag(CpuFlags::ZF, zf);
     cpu.set_cpu_flag(CpuFlags::PF, pf);
     cpu.set_cpu_flag(CpuFlags::CF, cf);
 
     // Pop if P-variant (FCOMIP / FUCOMIP)
-    let m = instr.mnemonic();
     if m == iced_x86::Mnemonic::Fcomip || m == iced_x86::Mnemonic::Fucomip {
         cpu.fpu_pop();
     }
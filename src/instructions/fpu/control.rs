@@ -1,5 +1,5 @@
 use iced_x86::{Instruction, OpKind, Register};
-use crate::cpu::{Cpu, FPU_TAG_EMPTY, FPU_TAG_VALID, FpuFlags};
+use crate::cpu::{Cpu, FPU_TAG_EMPTY, FPU_TAG_VALID, FpuFlags, FpuModel};
 use crate::f80::F80;
 use crate::instructions::utils::calculate_addr;
 
@@ -41,12 +41,9 @@ pub fn fnstcw(cpu: &mut Cpu, instr: &Instruction) {
 // FNSTSW: Store FPU Status Word (No Wait)
 // Usually: FNSTSW AX  or  FNSTSW [mem]
 pub fn fnstsw(cpu: &mut Cpu, instr: &Instruction) {
-    let flags = cpu.get_fpu_flags();
-    
     // FPU Top is usually stored in bits 11-13 of the Status Word.
     // But we store it separately in our CPU struct, so we need to combine them.
-    let mut raw_bits = flags.bits();
-    raw_bits = (raw_bits & !0x3800) | ((cpu.fpu_top as u16 & 0x07) << 11);
+    let raw_bits = status_word(cpu);
 
     if instr.op0_kind() == OpKind::Register {
         if instr.op0_register() == Register::AX {
@@ -66,6 +63,29 @@ pub fn ffree(cpu: &mut Cpu, instr: &Instruction) {
     cpu.fpu_tags[phys_idx] = crate::cpu::FPU_TAG_EMPTY;
 }
 
+// FFREEP: Free ST(i), then pop (undocumented, 287+)
+// Only the tag of ST(i) changes; TOP is simply incremented.
+pub fn ffreep(cpu: &mut Cpu, instr: &Instruction) {
+    ffree(cpu, instr);
+    fincstp(cpu);
+}
+
+// FNENI: Enable Interrupts (8087 only)
+// Clears the Interrupt Enable Mask in bit 7 of the Control Word.
+// The 287 and later have no such mask and treat it as FNOP.
+pub fn fneni(cpu: &mut Cpu) {
+    if cpu.fpu_model == FpuModel::I8087 {
+        cpu.fpu_control &= !0x0080;
+    }
+}
+
+// FNDISI: Disable Interrupts (8087 only)
+pub fn fndisi(cpu: &mut Cpu) {
+    if cpu.fpu_model == FpuModel::I8087 {
+        cpu.fpu_control |= 0x0080;
+    }
+}
+
 // FINCSTP: Increment Stack Top Pointer
 // This simply rotates the stack pointer. It does NOT push/pop values or change tags.
 pub fn fincstp(cpu: &mut Cpu) {
@@ -77,39 +97,23 @@ pub fn fdecstp(cpu: &mut Cpu) {
     cpu.fpu_top = (cpu.fpu_top.wrapping_sub(1)) & 7;
 }
 
-// FSAVE / FNSAVE: Save FPU State
-// Writes the 94-byte (108-byte in 32-bit mode) FPU Environment to memory.
-// Initializes the FPU (Like FNINIT).
-// This implements the 16-bit Protected/Real mode format (94 bytes).
-//
-// Layout (16-bit Real Mode):
-// 00: Control Word (16)
-// 02: Status Word (16)
-// 04: Tag Word (16)
-// 06: Instruction Pointer (Low)
-// 08: Instruction Pointer (High) & Opcode
-// 0A: Operand Pointer (Low)
-// 0C: Operand Pointer (High)
-// 0E: Register ST(0) ... ST(7) (10 bytes each * 8 = 80 bytes)
-pub fn fnsave(cpu: &mut Cpu, instr: &Instruction) {
-    let addr = calculate_addr(cpu, instr);
-    
-    // Construct Status Word (Flags + Top Ptr)
+// Status Word with the TOP pointer merged back in (bits 11-13)
+fn status_word(cpu: &Cpu) -> u16 {
     let flags = cpu.get_fpu_flags();
-    let sw = (flags.bits() & !0x3800) | ((cpu.fpu_top as u16 & 0x07) << 11);
-    
-    // Construct Tag Word
-    // The x87 Tag Word uses 2 bits per register to indicate status:
-    // 00 = Valid, 01 = Zero, 10 = Special (NaN/Inf), 11 = Empty
-    // It is stored relative to physical registers 0..7
+    (flags.bits() & !0x3800) | ((cpu.fpu_top as u16 & 0x07) << 11)
+}
+
+// Construct Tag Word
+// The x87 Tag Word uses 2 bits per register to indicate status:
+// 00 = Valid, 01 = Zero, 10 = Special (NaN/Inf), 11 = Empty
+// It is stored relative to physical registers 0..7
+fn tag_word(cpu: &Cpu) -> u16 {
     let mut tag_word: u16 = 0;
     for i in 0..8 {
         let tag = if cpu.fpu_tags[i] == FPU_TAG_EMPTY {
             0b11
         } else {
-            // Check value for 0.0 or Special if strictly required, 
-            // but for simple emulation, 00 (Valid) is sufficient for non-empty.
-            // (Real hardware checks the actual float value here)
+            // Real hardware checks the actual float value here
             let val = cpu.fpu_stack[i];
             if val.is_zero() { 0b01 } 
             else if val.is_nan() || val.is_infinite() { 0b10 }
@@ -117,15 +121,61 @@ pub fn fnsave(cpu: &mut Cpu, instr: &Instruction) {
         };
         tag_word |= tag << (i * 2);
     }
+    tag_word
+}
+
+// Write the 14-byte real mode environment (CW, SW, TW, instruction and operand pointers)
+fn store_env(cpu: &mut Cpu, addr: usize) {
+    let sw = status_word(cpu);
+    let tw = tag_word(cpu);
 
-    // Write Environment (14 bytes)
     cpu.bus.write_16(addr, cpu.fpu_control);      // 00: CW
     cpu.bus.write_16(addr + 2, sw);               // 02: SW
-    cpu.bus.write_16(addr + 4, tag_word);         // 04: TW
+    cpu.bus.write_16(addr + 4, tw);               // 04: TW
     cpu.bus.write_16(addr + 6, 0); // IP Offset (Dummy)
     cpu.bus.write_16(addr + 8, 0); // CS Selector (Dummy)
     cpu.bus.write_16(addr + 10, 0); // Operand Offset (Dummy)
     cpu.bus.write_16(addr + 12, 0); // Operand Selector (Dummy)
+}
+
+// Read back the 14-byte environment written by store_env
+fn load_env(cpu: &mut Cpu, addr: usize) {
+    cpu.fpu_control = cpu.bus.read_16(addr);
+    let sw = cpu.bus.read_16(addr + 2);
+    let tag_word = cpu.bus.read_16(addr + 4);
+
+    // Decode Status Word
+    cpu.fpu_top = ((sw >> 11) & 0x07) as usize;
+    // Mask out the TOP bits before setting flags to avoid corruption
+    let flags = FpuFlags::from_bits_truncate(sw & !0x3800);
+    cpu.set_fpu_flags(flags);
+
+    // Decode Tag Word
+    for i in 0..8 {
+        let tag = (tag_word >> (i * 2)) & 0x03;
+        cpu.fpu_tags[i] = if tag == 0b11 { FPU_TAG_EMPTY } else { FPU_TAG_VALID };
+    }
+}
+
+// FSAVE / FNSAVE: Save FPU State
+// Writes the 94-byte (108-byte in 32-bit mode) FPU Environment to memory.
+// Initializes the FPU (Like FNINIT).
+// This implements the 16-bit Protected/Real mode format (94 bytes).
+//
+// Layout (16-bit Real Mode):
+// 00: Control Word (16)
+// 02: Status Word (16)
+// 04: Tag Word (16)
+// 06: Instruction Pointer (Low)
+// 08: Instruction Pointer (High) & Opcode
+// 0A: Operand Pointer (Low)
+// 0C: Operand Pointer (High)
+// 0E: Register ST(0) ... ST(7) (10 bytes each * 8 = 80 bytes)
+pub fn fnsave(cpu: &mut Cpu, instr: &Instruction) {
+    let addr = calculate_addr(cpu, instr);
+
+    // Write Environment (14 bytes)
+    store_env(cpu, addr);
 
     // Write Register Stack (80 bytes) starting at offset 14 (0x0E)
     // Written sequentially: ST(0), ST(1) ... NO! 
@@ -149,30 +199,94 @@ pub fn frstor(cpu: &mut Cpu, instr: &Instruction) {
     let addr = calculate_addr(cpu, instr);
 
     // Load Environment
+    load_env(cpu, addr);
+
+    // Load Registers (Physical 0..7)
+    let mut reg_addr = addr + 14;
+    for i in 0..8 {
+        let mut bytes = [0u8; 10];
+        for b in 0..10 {
+            bytes[b] = cpu.bus.read_8(reg_addr);
+            reg_addr += 1;
+        }
+        cpu.fpu_stack[i].set_bytes(&bytes);
+    }
+}
+
+// FSTENV / FNSTENV: Store the 14-byte environment, then mask all exceptions
+pub fn fnstenv(cpu: &mut Cpu, instr: &Instruction) {
+    let addr = calculate_addr(cpu, instr);
+    store_env(cpu, addr);
+    cpu.fpu_control |= 0x003F;
+}
+
+// FLDENV: Load the 14-byte environment
+pub fn fldenv(cpu: &mut Cpu, instr: &Instruction) {
+    let addr = calculate_addr(cpu, instr);
+    load_env(cpu, addr);
+}
+
+// FXSAVE: Save x87 state in the 512-byte FXSAVE format (P6+)
+// 00: FCW, 02: FSW, 04: abridged tag byte (1 bit per physical register, 1 = valid)
+// 06: FOP, 08: FIP, 0C: FCS, 10: FDP, 14: FDS, 18: MXCSR, 1C: MXCSR_MASK
+// 20: ST(0) ... ST(7), 16 bytes each (10 used)
+// Unlike FSAVE, the FPU is left untouched.
+pub fn fxsave(cpu: &mut Cpu, instr: &Instruction) {
+    let addr = calculate_addr(cpu, instr);
+
+    let mut abridged: u8 = 0;
+    for i in 0..8 {
+        if cpu.fpu_tags[i] != FPU_TAG_EMPTY {
+            abridged |= 1 << i;
+        }
+    }
+
+    let sw = status_word(cpu);
+    cpu.bus.write_16(addr, cpu.fpu_control);
+    cpu.bus.write_16(addr + 2, sw);
+    cpu.bus.write_8(addr + 4, abridged);
+    cpu.bus.write_8(addr + 5, 0);
+    for offset in (6..0x18).step_by(2) {
+        cpu.bus.write_16(addr + offset, 0); // FOP, FIP, FCS, FDP, FDS (Dummy)
+    }
+    cpu.bus.write_32(addr + 0x18, 0x1F80); // MXCSR power-on default
+    cpu.bus.write_32(addr + 0x1C, 0xFFBF); // MXCSR_MASK
+
+    // Registers are stored in stack order here, not physical order
+    for i in 0..8 {
+        let bytes = cpu.fpu_stack[cpu.fpu_get_phys_index(i)].get_bytes();
+        let reg_addr = addr + 0x20 + i * 16;
+        for (b, &byte) in bytes.iter().enumerate() {
+            cpu.bus.write_8(reg_addr + b, byte);
+        }
+        for b in 10..16 {
+            cpu.bus.write_8(reg_addr + b, 0);
+        }
+    }
+}
+
+// FXRSTOR: Restore x87 state from the 512-byte FXSAVE format
+pub fn fxrstor(cpu: &mut Cpu, instr: &Instruction) {
+    let addr = calculate_addr(cpu, instr);
+
     cpu.fpu_control = cpu.bus.read_16(addr);
     let sw = cpu.bus.read_16(addr + 2);
-    let tag_word = cpu.bus.read_16(addr + 4);
+    let abridged = cpu.bus.read_8(addr + 4);
 
-    // Decode Status Word
     cpu.fpu_top = ((sw >> 11) & 0x07) as usize;
-    // Mask out the TOP bits before setting flags to avoid corruption
-    let flags = FpuFlags::from_bits_truncate(sw & !0x3800);
-    cpu.set_fpu_flags(flags);
+    cpu.set_fpu_flags(FpuFlags::from_bits_truncate(sw & !0x3800));
 
-    // Decode Tag Word
     for i in 0..8 {
-        let tag = (tag_word >> (i * 2)) & 0x03;
-        cpu.fpu_tags[i] = if tag == 0b11 { FPU_TAG_EMPTY } else { FPU_TAG_VALID };
+        cpu.fpu_tags[i] = if abridged & (1 << i) != 0 { FPU_TAG_VALID } else { FPU_TAG_EMPTY };
     }
 
-    // Load Registers (Physical 0..7)
-    let mut reg_addr = addr + 14;
     for i in 0..8 {
+        let reg_addr = addr + 0x20 + i * 16;
         let mut bytes = [0u8; 10];
-        for b in 0..10 {
-            bytes[b] = cpu.bus.read_8(reg_addr);
-            reg_addr += 1;
+        for (b, byte) in bytes.iter_mut().enumerate() {
+            *byte = cpu.bus.read_8(reg_addr + b);
         }
-        cpu.fpu_stack[i].set_bytes(&bytes);
+        let phys = cpu.fpu_get_phys_index(i);
+        cpu.fpu_stack[phys].set_bytes(&bytes);
     }
-}
\ No newline at end of file
+}
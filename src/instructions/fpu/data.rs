@@ -1,7 +1,8 @@
-use crate::cpu::Cpu;
-use crate::f80::F80;
+use crate::cpu::{Cpu, CpuFlags};
+use crate::f80::{F80, Rounding};
+use crate::instructions::fpu::{record_exceptions, rounding_env};
 use crate::instructions::utils::calculate_addr;
-use iced_x86::{Instruction, MemorySize, OpKind, Register};
+use iced_x86::{Instruction, MemorySize, Mnemonic, OpKind, Register};
 
 // FLD: Load Floating Point Value
 pub fn fld(cpu: &mut Cpu, instr: &Instruction) {
@@ -11,12 +12,14 @@ pub fn fld(cpu: &mut Cpu, instr: &Instruction) {
 
         match instr.memory_size() {
             MemorySize::Float32 => {
-                let bits = cpu.bus.read_32(addr);
-                f.set_f64(f32::from_bits(bits) as f64);
+                let mut env = rounding_env(cpu);
+                f = F80::from_f32_bits(cpu.bus.read_32(addr), &mut env);
+                record_exceptions(cpu, &env);
             }
             MemorySize::Float64 => {
-                let bits = cpu.bus.read_64(addr);
-                f.set_f64(f64::from_bits(bits));
+                let mut env = rounding_env(cpu);
+                f = F80::from_f64_bits(cpu.bus.read_64(addr), &mut env);
+                record_exceptions(cpu, &env);
             }
             MemorySize::Float80 => {
                 // Load 10 bytes directly from memory without lossy conversion
@@ -49,85 +52,88 @@ pub fn fild(cpu: &mut Cpu, instr: &Instruction) {
     cpu.fpu_push(f);
 }
 
-fn x87_round(f_val: f64, rc: u16) -> f64 {
-    match rc {
-        0 => {
-            // Round to nearest, ties to even
-            let floor_val = f_val.floor();
-            let diff = f_val - floor_val;
-            if diff < 0.5 {
-                floor_val
-            } else if diff > 0.5 {
-                floor_val + 1.0
-            } else {
-                // Tie: round to even
-                if floor_val % 2.0 == 0.0 {
-                    floor_val
-                } else {
-                    floor_val + 1.0
-                }
-            }
+// Converts to an integer under the given rounding and writes it out.
+// Out-of-range values and NaNs store the integer indefinite.
+fn store_int(cpu: &mut Cpu, instr: &Instruction, val: F80, rounding: Option<Rounding>) {
+    let addr = calculate_addr(cpu, instr);
+    let mut env = rounding_env(cpu);
+    if let Some(rounding) = rounding {
+        env.rounding = rounding;
+    }
+
+    match instr.memory_size() {
+        MemorySize::Int16 => {
+            let i_val = val.to_int_with(16, &mut env).map_or(0x8000, |v| v as u16);
+            cpu.bus.write_16(addr, i_val);
+        }
+        MemorySize::Int32 => {
+            let i_val = val.to_int_with(32, &mut env).map_or(0x8000_0000, |v| v as u32);
+            cpu.bus.write_32(addr, i_val);
+        }
+        MemorySize::Int64 => {
+            let i_val = val
+                .to_int_with(64, &mut env)
+                .map_or(0x8000_0000_0000_0000, |v| v as u64);
+            cpu.bus.write_64(addr, i_val);
+        }
+        _ => {
+            cpu.bus.log_string(&format!(
+                "[FPU] FIST Unsupported memory size: {:?}",
+                instr.memory_size()
+            ));
         }
-        1 => f_val.floor(), // Round Down
-        2 => f_val.ceil(),  // Round Up
-        3 => f_val.trunc(), // Truncate
-        _ => unreachable!(),
     }
+    record_exceptions(cpu, &env);
 }
 
 // FISTP: Store Integer and Pop
 pub fn fistp(cpu: &mut Cpu, instr: &Instruction) {
     let val = cpu.fpu_pop();
-    let addr = calculate_addr(cpu, instr);
+    store_int(cpu, instr, val, None);
+}
 
-    // Use the custom rounding logic
-    let rc = (cpu.fpu_control >> 10) & 0x03;
-    let rounded = x87_round(val.get_f64(), rc);
+// FISTTP: Store Integer with Truncation and Pop (SSE3)
+// Always chops towards zero, whatever the rounding control says.
+pub fn fisttp(cpu: &mut Cpu, instr: &Instruction) {
+    let val = cpu.fpu_pop();
+    store_int(cpu, instr, val, Some(Rounding::Zero));
+}
 
+// Writes ST value to a real memory operand, rounding per RC
+fn store_real(cpu: &mut Cpu, instr: &Instruction, val: F80, addr: usize) {
+    let mut env = rounding_env(cpu);
     match instr.memory_size() {
-        MemorySize::Int16 => {
-            cpu.bus.write_16(addr, rounded as i16 as u16);
+        MemorySize::Float32 => {
+            let bits = val.to_f32_bits(&mut env);
+            cpu.bus.write_32(addr, bits);
         }
-        MemorySize::Int32 => {
-            cpu.bus.write_32(addr, rounded as i32 as u32);
+        MemorySize::Float64 => {
+            let bits = val.to_f64_bits(&mut env);
+            cpu.bus.write_64(addr, bits);
         }
-        MemorySize::Int64 => {
-            cpu.bus.write_64(addr, rounded as i64 as u64);
+        MemorySize::Float80 => {
+            let bytes = val.get_bytes();
+            for i in 0..10 {
+                cpu.bus.write_8(addr + i as usize, bytes[i]);
+            }
+        }
+        _ => {
+            cpu.bus.log_string(&format!(
+                "[FPU] FST Unsupported memory size: {:?}",
+                instr.memory_size()
+            ));
         }
-        _ => {}
     }
+    record_exceptions(cpu, &env);
 }
 
-// FSTP: Store Float and Pop
 // FSTP: Store Float and Pop
 pub fn fstp(cpu: &mut Cpu, instr: &Instruction) {
     if instr.op0_kind() == OpKind::Memory {
         let val: F80 = cpu.fpu_pop();
         let addr = calculate_addr(cpu, instr);
         cpu.last_fstp_addr = addr;
-
-        match instr.memory_size() {
-            MemorySize::Float32 => {
-                let bits = (val.get_f64() as f32).to_bits();
-                cpu.bus.write_32(addr, bits);
-            }
-            MemorySize::Float64 => {
-                let bits = val.get_f64().to_bits();
-                cpu.bus.write_64(addr, bits);
-            }
-            MemorySize::Float80 => {
-                let bytes = val.get_bytes();
-                for i in 0..10 {
-                    cpu.bus.write_8(addr + i as usize, bytes[i]);
-                }
-            }
-            _ => {
-                cpu.bus.log_string(&format!(
-                    "[FPU] FSTP Unsupported memory size: {:?}",
-                    instr.memory_size()
-                ));
-            }
-        }
+        store_real(cpu, instr, val, addr);
     } else if instr.op0_kind() == OpKind::Register {
         // FSTP ST(i)
         // Store ST(0) to ST(i), THEN pop.
@@ -138,6 +144,19 @@ pub fn fstp(cpu: &mut Cpu, instr: &Instruction) {
     }
 }
 
+// FBLD: Load 18-digit Packed BCD Integer and Push
+pub fn fbld(cpu: &mut Cpu, instr: &Instruction) {
+    let addr = calculate_addr(cpu, instr);
+    let mut bcd = [0u8; 10];
+    for (i, byte) in bcd.iter_mut().enumerate() {
+        *byte = cpu.bus.read_8(addr + i);
+    }
+
+    let mut f = F80::new();
+    f.from_bcd_packed(&bcd);
+    cpu.fpu_push(f);
+}
+
 // FBSTP: Store BCD Integer and Pop
 pub fn fbstp(cpu: &mut Cpu, instr: &Instruction) {
     let val: F80 = cpu.fpu_pop();
@@ -157,28 +176,7 @@ pub fn fst(cpu: &mut Cpu, instr: &Instruction) {
 
     if instr.op0_kind() == OpKind::Memory {
         let addr = calculate_addr(cpu, instr);
-        match instr.memory_size() {
-            MemorySize::Float32 => {
-                let bits = (st0.get_f64() as f32).to_bits();
-                cpu.bus.write_32(addr, bits);
-            }
-            MemorySize::Float64 => {
-                let bits = st0.get_f64().to_bits();
-                cpu.bus.write_64(addr, bits);
-            }
-            MemorySize::Float80 => {
-                let bytes = st0.get_bytes();
-                for i in 0..10 {
-                    cpu.bus.write_8(addr + i as usize, bytes[i]);
-                }
-            }
-            _ => {
-                cpu.bus.log_string(&format!(
-                    "[FPU] FST Unsupported memory size: {:?}",
-                    instr.memory_size()
-                ));
-            }
-        }
+        store_real(cpu, instr, st0, addr);
     } else if instr.op0_kind() == OpKind::Register {
         // FST ST(i)
         let idx = (instr.op0_register().number() - Register::ST0.number()) as usize;
@@ -215,6 +213,31 @@ pub fn fxch(cpu: &mut Cpu, instr: &Instruction) {
     cpu.set_fpu_flag(crate::cpu::FpuFlags::C1, false);
 }
 
+// FCMOVcc: Copy ST(i) to ST(0) if the EFLAGS condition holds (Pentium Pro+)
+pub fn fcmov(cpu: &mut Cpu, instr: &Instruction) {
+    let cf = cpu.get_cpu_flag(CpuFlags::CF);
+    let zf = cpu.get_cpu_flag(CpuFlags::ZF);
+    let pf = cpu.get_cpu_flag(CpuFlags::PF);
+
+    let condition = match instr.mnemonic() {
+        Mnemonic::Fcmovb => cf,
+        Mnemonic::Fcmove => zf,
+        Mnemonic::Fcmovbe => cf || zf,
+        Mnemonic::Fcmovu => pf,
+        Mnemonic::Fcmovnb => !cf,
+        Mnemonic::Fcmovne => !zf,
+        Mnemonic::Fcmovnbe => !cf && !zf,
+        Mnemonic::Fcmovnu => !pf,
+        _ => false,
+    };
+
+    if condition {
+        let idx = instr.op1_register().number() - Register::ST0.number();
+        let val = cpu.fpu_get(idx);
+        cpu.fpu_set(0, val);
+    }
+}
+
 // FLD1: Push +1.0
 pub fn fld1(cpu: &mut Cpu) {
     let mut f = F80::new();
@@ -263,27 +286,5 @@ pub fn fldln2(cpu: &mut Cpu) {
 // FIST: Store Integer (No Pop)
 pub fn fist(cpu: &mut Cpu, instr: &Instruction) {
     let val = cpu.fpu_get(0);
-    let addr = calculate_addr(cpu, instr);
-
-    // Extract Rounding Control (RC) - Bits 10 and 11
-    let rc = (cpu.fpu_control >> 10) & 0x03;
-
-    // Use the x87-compliant rounding helper
-    let f_val = val.get_f64();
-    let i_val = x87_round(f_val, rc);
-
-    match instr.memory_size() {
-        MemorySize::Int16 => {
-            cpu.bus.write_16(addr, i_val as i16 as u16);
-        }
-        MemorySize::Int32 => {
-            cpu.bus.write_32(addr, i_val as i32 as u32);
-        }
-        _ => {
-            cpu.bus.log_string(&format!(
-                "[FPU] FIST Unsupported memory size: {:?}",
-                instr.memory_size()
-            ));
-        }
-    }
+    store_int(cpu, instr, val, None);
 }
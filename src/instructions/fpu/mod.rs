@@ -1,5 +1,7 @@
-use crate::cpu::Cpu;
-use iced_x86::{Instruction, Mnemonic};
+use crate::cpu::{Cpu, CpuModel, FpuErrorLine, FpuFlags, FpuModel};
+use crate::f80::{FpEnv, FP_DENORMAL, FP_INVALID, FP_ZERO_DIVIDE};
+use crate::interrupts;
+use iced_x86::{Instruction, Mnemonic, OpKind};
 
 pub mod arithmetic;
 pub mod comparison;
@@ -7,7 +9,147 @@ pub mod control;
 pub mod data;
 pub mod transcendental;
 
+// Rounding settings from the current control word
+pub fn rounding_env(cpu: &Cpu) -> FpEnv {
+    FpEnv::from_control_word(cpu.fpu_control)
+}
+
+// Merge the exceptions an operation raised into the status word.
+// C1 tells whether the result was rounded up.
+pub fn record_exceptions(cpu: &mut Cpu, env: &FpEnv) {
+    cpu.set_fpu_flag(FpuFlags::from_bits_truncate(env.exceptions as u16), true);
+    cpu.set_fpu_flag(FpuFlags::C1, env.rounded_up);
+}
+
+// An unmasked invalid, denormal or zero divide exception leaves the destination
+// alone on the 387, so the handler still sees the original operands
+pub fn unmasked_fault(cpu: &Cpu, env: &FpEnv) -> bool {
+    env.exceptions & !(cpu.fpu_control as u8) & (FP_INVALID | FP_DENORMAL | FP_ZERO_DIVIDE) != 0
+}
+
+fn has_handler(cpu: &Cpu, vector: u8) -> bool {
+    let ivt = vector as usize * 4;
+    cpu.bus.read_16(ivt) != 0 || cpu.bus.read_16(ivt + 2) != 0
+}
+
+// The FN* forms don't wait, so they never see a pending error
+fn is_no_wait(instr: &Instruction) -> bool {
+    matches!(
+        instr.mnemonic(),
+        Mnemonic::Fninit | Mnemonic::Fnclex | Mnemonic::Fnstcw | Mnemonic::Fnstsw |
+        Mnemonic::Fnstenv | Mnemonic::Fnsave | Mnemonic::Fneni | Mnemonic::Fndisi |
+        Mnemonic::Fnsetpm
+    )
+}
+
+// ES mirrors "some exception flag is set whose mask bit is clear".
+// Once ES drops (FNCLEX, FNINIT, FLDENV...) the next error can be reported again.
+fn update_error_summary(cpu: &mut Cpu) {
+    let unmasked = cpu.get_fpu_flags().bits() & !cpu.fpu_control & 0x3F;
+    cpu.set_fpu_flag(FpuFlags::ES, unmasked != 0);
+    if unmasked == 0 {
+        cpu.fpu_error_latched = false;
+    }
+}
+
+// Report an unmasked exception left behind by an earlier instruction.
+// Called for FWAIT and every waiting FPU instruction, before it executes.
+// Returns true if the CPU was sent to the error handler; IP then points back at
+// the instruction so it runs again after the handler returns.
+pub fn deliver_pending_error(cpu: &mut Cpu, instr: &Instruction) -> bool {
+    if !cpu.get_fpu_flags().contains(FpuFlags::ES) || cpu.fpu_error_latched {
+        return false;
+    }
+
+    // The 8087 can hold its interrupt request back (IEM, set by FDISI)
+    if cpu.fpu_model == FpuModel::I8087 && cpu.fpu_control & 0x0080 != 0 {
+        return false;
+    }
+
+    let vector = match cpu.fpu_error_line {
+        FpuErrorLine::Nmi => 0x02,
+        FpuErrorLine::Irq13 => 0x75,
+    };
+
+    cpu.fpu_error_latched = true;
+    cpu.bus.log_string(&format!(
+        "[FPU] Unmasked exception, status {:04X} -> INT {:02X}",
+        cpu.get_fpu_flags().bits(),
+        vector
+    ));
+
+    if !has_handler(cpu, vector) {
+        return false;
+    }
+    cpu.ip = cpu.ip.wrapping_sub(instr.len() as u16);
+    match cpu.fpu_error_line {
+        FpuErrorLine::Nmi => interrupts::handle_interrupt(cpu, vector),
+        // The request goes through the slave PIC and is delivered before the retry
+        // if IF and the masks allow it; otherwise the retry simply runs
+        FpuErrorLine::Irq13 => cpu.bus.pic_mut().raise(1 << 13),
+    }
+    true
+}
+
+// First coprocessor generation that implements an instruction
+fn introduced_in(instr: &Instruction) -> FpuModel {
+    match instr.mnemonic() {
+        // FSTSW AX arrived with the 287, the 8087 can only store to memory
+        Mnemonic::Fstsw | Mnemonic::Fnstsw if instr.op0_kind() == OpKind::Register => {
+            FpuModel::I80287
+        }
+        Mnemonic::Fsetpm | Mnemonic::Fnsetpm | Mnemonic::Ffreep => FpuModel::I80287,
+
+        Mnemonic::Fucom | Mnemonic::Fucomp | Mnemonic::Fucompp | Mnemonic::Fprem1 |
+        Mnemonic::Fsin | Mnemonic::Fcos | Mnemonic::Fsincos => FpuModel::I80387,
+
+        Mnemonic::Fcmovb | Mnemonic::Fcmove | Mnemonic::Fcmovbe | Mnemonic::Fcmovu |
+        Mnemonic::Fcmovnb | Mnemonic::Fcmovne | Mnemonic::Fcmovnbe | Mnemonic::Fcmovnu |
+        Mnemonic::Fcomi | Mnemonic::Fcomip | Mnemonic::Fucomi | Mnemonic::Fucomip |
+        Mnemonic::Fisttp | Mnemonic::Fxsave | Mnemonic::Fxrstor => FpuModel::PentiumPro,
+
+        _ => FpuModel::I8087,
+    }
+}
+
+// Opcode the fitted coprocessor doesn't know.
+// The 8086/8088 have no #UD, so the opcode is silently skipped there, like an
+// unrecognised ESC. Later CPUs fault with INT 6 pointing at the instruction.
+fn unavailable(cpu: &mut Cpu, instr: &Instruction) {
+    cpu.bus.log_string(&format!(
+        "[FPU] {:?} not available on {:?}",
+        instr.mnemonic(),
+        cpu.fpu_model
+    ));
+
+    if cpu.model >= CpuModel::I80186 && has_handler(cpu, 6) {
+        cpu.ip = cpu.ip.wrapping_sub(instr.len() as u16);
+        interrupts::handle_interrupt(cpu, 6);
+    }
+}
+
 pub fn handle(cpu: &mut Cpu, instr: &Instruction) {
+    if cpu.fpu_model < introduced_in(instr) {
+        unavailable(cpu, instr);
+        return;
+    }
+
+    if !is_no_wait(instr) && deliver_pending_error(cpu, instr) {
+        return;
+    }
+
+    execute(cpu, instr);
+    update_error_summary(cpu);
+}
+
+// Entry point for the INT 34h-3Dh emulator. The emulation library behaves the same
+// whatever coprocessor is fitted (or none), and leaves errors in the status word.
+pub fn emulate(cpu: &mut Cpu, instr: &Instruction) {
+    execute(cpu, instr);
+    update_error_summary(cpu);
+}
+
+fn execute(cpu: &mut Cpu, instr: &Instruction) {
     match instr.mnemonic() {
         // Source: https://linasm.sourceforge.net/docs/instructions/fpu.php
 
@@ -23,12 +165,22 @@ pub fn handle(cpu: &mut Cpu, instr: &Instruction) {
 
         Mnemonic::Fsave | Mnemonic::Fnsave => control::fnsave(cpu, instr),
         Mnemonic::Frstor => control::frstor(cpu, instr),
+        Mnemonic::Fstenv | Mnemonic::Fnstenv => control::fnstenv(cpu, instr),
+        Mnemonic::Fldenv => control::fldenv(cpu, instr),
+        Mnemonic::Fxsave => control::fxsave(cpu, instr),
+        Mnemonic::Fxrstor => control::fxrstor(cpu, instr),
         Mnemonic::Fincstp => control::fincstp(cpu),
         Mnemonic::Fdecstp => control::fdecstp(cpu),
 
+        // 8087 interrupt mask, ignored from the 287 on
+        Mnemonic::Feni | Mnemonic::Fneni => control::fneni(cpu),
+        Mnemonic::Fdisi | Mnemonic::Fndisi => control::fndisi(cpu),
+
         // No-ops or Wait in HLE
-        Mnemonic::Fnop => {}
+        // FSETPM switches the 287 to protected mode addressing, which we don't model
+        Mnemonic::Fnop | Mnemonic::Fsetpm | Mnemonic::Fnsetpm => {}
         Mnemonic::Ffree => control::ffree(cpu, instr),
+        Mnemonic::Ffreep => control::ffreep(cpu, instr),
 
         // DATA TRANSFER
         // -------------
@@ -40,10 +192,13 @@ pub fn handle(cpu: &mut Cpu, instr: &Instruction) {
         Mnemonic::Fild => data::fild(cpu, instr),
         Mnemonic::Fist => data::fist(cpu, instr),
         Mnemonic::Fistp => data::fistp(cpu, instr),
+        Mnemonic::Fisttp => data::fisttp(cpu, instr),
+        Mnemonic::Fbld => data::fbld(cpu, instr),
 
         // Store Float
         Mnemonic::Fst => data::fst(cpu, instr),
-        Mnemonic::Fstp => data::fstp(cpu, instr),
+        // D9 D8+i is an undocumented alias of FSTP ST(i)
+        Mnemonic::Fstp | Mnemonic::Fstpnce => data::fstp(cpu, instr),
         Mnemonic::Fbstp => data::fbstp(cpu, instr),
 
         // Exchange
@@ -59,8 +214,10 @@ pub fn handle(cpu: &mut Cpu, instr: &Instruction) {
         Mnemonic::Fldln2 => data::fldln2(cpu),
 
         // Conditional Move (Pentium Pro+)
-        // Mnemonic::Fcmovb | Mnemonic::Fcmove | ... => data::fcmov(cpu, instr),
-        // Don't know if we'll ever get there...
+        Mnemonic::Fcmovb | Mnemonic::Fcmove | Mnemonic::Fcmovbe | Mnemonic::Fcmovu |
+        Mnemonic::Fcmovnb | Mnemonic::Fcmovne | Mnemonic::Fcmovnbe | Mnemonic::Fcmovnu => {
+            data::fcmov(cpu, instr)
+        }
 
         // ARITHMETIC
         // ----------
@@ -116,6 +273,11 @@ pub fn handle(cpu: &mut Cpu, instr: &Instruction) {
             comparison::fcom_variants(cpu, instr)
         }
 
+        // Unordered Compare (387+)
+        Mnemonic::Fucom | Mnemonic::Fucomp | Mnemonic::Fucompp => {
+            comparison::fucom_variants(cpu, instr)
+        }
+
         // Integer Compare
         Mnemonic::Ficom | Mnemonic::Ficomp => comparison::ficom_variants(cpu, instr),
 
@@ -77,11 +77,7 @@ where F: Fn(u16, u16) -> u16 {
     }
 
     // Update Flags
-    cpu.set_cpu_flag(CpuFlags::ZF, if is_8bit { (res & 0xFF) == 0 } else { res == 0 });
-    cpu.set_cpu_flag(CpuFlags::SF, if is_8bit { (res & 0x80) != 0 } else { (res & 0x8000) != 0 });
-    cpu.set_cpu_flag(CpuFlags::OF, false);
-    cpu.set_cpu_flag(CpuFlags::CF, false);
-    cpu.update_pf(res);
+    cpu.set_logic_flags(res, is_8bit);
 }
 
 /// TEST: Same as AND, but discards result
@@ -117,11 +113,7 @@ fn test(cpu: &mut Cpu, instr: &Instruction) {
     let res = dest & src;
 
     // Flags Only
-    cpu.set_cpu_flag(CpuFlags::ZF, if is_8bit { (res & 0xFF) == 0 } else { res == 0 });
-    cpu.set_cpu_flag(CpuFlags::SF, if is_8bit { (res & 0x80) != 0 } else { (res & 0x8000) != 0 });
-    cpu.set_cpu_flag(CpuFlags::OF, false);
-    cpu.set_cpu_flag(CpuFlags::CF, false);
-    cpu.update_pf(res);
+    cpu.set_logic_flags(res, is_8bit);
 }
 
 /// NOT: Invert bits (One's Complement)
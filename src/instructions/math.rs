@@ -177,17 +177,11 @@ fn inc(cpu: &mut Cpu, instr: &Instruction) {
     
     let res = if is_8bit {
         let r = (val as u8).wrapping_add(1);
-        cpu.set_cpu_flag(CpuFlags::ZF, r == 0);
-        cpu.set_cpu_flag(CpuFlags::SF, (r & 0x80) != 0);
-        cpu.set_cpu_flag(CpuFlags::OF, val == 0x7F);
-        cpu.update_pf(r as u16);
+        cpu.set_inc_dec_flags(val, r as u16, true, true);
         r as u16
     } else {
         let r = val.wrapping_add(1);
-        cpu.set_cpu_flag(CpuFlags::ZF, r == 0);
-        cpu.set_cpu_flag(CpuFlags::SF, (r & 0x8000) != 0);
-        cpu.set_cpu_flag(CpuFlags::OF, val == 0x7FFF);
-        cpu.update_pf(r);
+        cpu.set_inc_dec_flags(val, r, false, true);
         r
     };
     
@@ -206,21 +200,11 @@ fn dec(cpu: &mut Cpu, instr: &Instruction) {
     let res = if is_8bit {
         let v = val as u8;
         let r = v.wrapping_sub(1);
-        
-        cpu.set_cpu_flag(CpuFlags::ZF, r == 0);
-        cpu.set_cpu_flag(CpuFlags::SF, (r & 0x80) != 0);
-        cpu.set_cpu_flag(CpuFlags::OF, v == 0x80);
-        cpu.set_cpu_flag(CpuFlags::AF, (v & 0x0F) == 0);
-        cpu.update_pf(r as u16);
+        cpu.set_inc_dec_flags(v as u16, r as u16, true, false);
         r as u16
     } else {
         let r = val.wrapping_sub(1);
-        cpu.set_cpu_flag(CpuFlags::ZF, r == 0);
-        cpu.set_cpu_flag(CpuFlags::SF, (r & 0x8000) != 0);
-        cpu.set_cpu_flag(CpuFlags::OF, val == 0x8000);
-        cpu.set_cpu_flag(CpuFlags::AF, (val & 0x0F) == 0);
-        
-        cpu.update_pf(r);
+        cpu.set_inc_dec_flags(val, r, false, false);
         r
     };
     
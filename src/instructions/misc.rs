@@ -1,5 +1,6 @@
 use iced_x86::{Instruction, Mnemonic};
-use crate::cpu::{Cpu, CpuFlags, CpuState};
+use crate::cpu::{Cpu, CpuFlags, CpuState, FpuModel};
+use crate::instructions::fpu;
 use crate::interrupts;
 
 pub fn handle(cpu: &mut Cpu, instr: &Instruction) {
@@ -103,9 +104,14 @@ pub fn handle(cpu: &mut Cpu, instr: &Instruction) {
             let cf = cpu.get_cpu_flag(CpuFlags::CF);
             cpu.set_cpu_flag(CpuFlags::CF, !cf);
         }
-        Mnemonic::Sti => { /* Enable Interrupts */ },
-        Mnemonic::Cli => { /* Disable Interrupts */ },
-        Mnemonic::Wait => { /* Wait for Interrupt */ },
+        Mnemonic::Sti => cpu.set_cpu_flag(CpuFlags::IF, true),
+        Mnemonic::Cli => cpu.set_cpu_flag(CpuFlags::IF, false),
+        Mnemonic::Wait => {
+            // FWAIT is where a pending coprocessor error gets reported
+            if cpu.fpu_model != FpuModel::None {
+                fpu::deliver_pending_error(cpu, instr);
+            }
+        }
         Mnemonic::Nop => { /* No Operation */ },
         
         _ => { cpu.bus.log_string(&format!("[MISC] Unsupported instruction: {:?}", instr.mnemonic())); }
@@ -1,6 +1,7 @@
 use iced_x86::{Instruction, Mnemonic};
-use crate::cpu::{Cpu, CpuFlags};
+use crate::cpu::{Cpu, CpuFlags, FpuModel};
 
+pub mod timing;
 pub mod utils;
 pub mod fpu;
 pub mod math;
@@ -58,10 +59,13 @@ pub fn execute_instruction(cpu: &mut Cpu, instr: &Instruction) {
         Mnemonic::Fbld | Mnemonic::Fbstp |
         Mnemonic::Fxch | Mnemonic::Fld1 | Mnemonic::Fldz | 
         Mnemonic::Fldpi | Mnemonic::Fldl2e | Mnemonic::Fldl2t | 
-        Mnemonic::Fldlg2 | Mnemonic::Fldln2 |
+        Mnemonic::Fldlg2 | Mnemonic::Fldln2 | Mnemonic::Fstpnce |
+        Mnemonic::Fcmovb | Mnemonic::Fcmove | Mnemonic::Fcmovbe | Mnemonic::Fcmovu |
+        Mnemonic::Fcmovnb | Mnemonic::Fcmovne | Mnemonic::Fcmovnbe | Mnemonic::Fcmovnu |
         
         // --- Comparison ---
         Mnemonic::Fcom | Mnemonic::Fcomp | Mnemonic::Fcompp |
+        Mnemonic::Fucom | Mnemonic::Fucomp | Mnemonic::Fucompp |
         Mnemonic::Ficom | Mnemonic::Ficomp |
         Mnemonic::Ftst | Mnemonic::Fxam |
         Mnemonic::Fcomi | Mnemonic::Fcomip | Mnemonic::Fucomi | Mnemonic::Fucomip |
@@ -73,9 +77,15 @@ pub fn execute_instruction(cpu: &mut Cpu, instr: &Instruction) {
         Mnemonic::Fclex | Mnemonic::Fnclex |
         Mnemonic::Fsave | Mnemonic::Fnsave | Mnemonic::Frstor |
         Mnemonic::Fstenv | Mnemonic::Fnstenv | Mnemonic::Fldenv |
-        Mnemonic::Fnop | Mnemonic::Ffree | Mnemonic::Fincstp | 
-        Mnemonic::Fdecstp => {
-            fpu::handle(cpu, instr);
+        Mnemonic::Fnop | Mnemonic::Ffree | Mnemonic::Ffreep | Mnemonic::Fincstp | 
+        Mnemonic::Fdecstp |
+        Mnemonic::Feni | Mnemonic::Fneni | Mnemonic::Fdisi | Mnemonic::Fndisi |
+        Mnemonic::Fsetpm | Mnemonic::Fnsetpm |
+        Mnemonic::Fxsave | Mnemonic::Fxrstor => {
+            // Without a coprocessor nobody answers the ESC opcodes
+            if cpu.fpu_model != FpuModel::None {
+                fpu::handle(cpu, instr);
+            }
         }
 
         // --- Logic / Bitwise ---
@@ -115,6 +125,7 @@ pub fn execute_instruction(cpu: &mut Cpu, instr: &Instruction) {
 
         _ => {
             cpu.bus.log_string(&format!("[CPU] Unhandled: {}", instr));
+            cpu.dump_crash_report(&format!("Unhandled opcode {:?}", instr.mnemonic()));
         }
     }
 
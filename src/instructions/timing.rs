@@ -0,0 +1,1112 @@
+use iced_x86::{Instruction, MemorySize, Mnemonic, OpKind, Register};
+
+use crate::cpu::{Cpu, CpuModel};
+
+/// Cycles that pass per step while the CPU sits in HLT
+pub const HALT_QUANTUM: u32 = 64;
+
+// Instruction timings in CPU clocks.
+// Sources: Intel iAPX 86/88 User's Manual, 80186/80286/80386 Programmer's Reference Manuals.
+// Where the manual gives a range (MUL, DIV, ...) we use the midpoint.
+
+/// Per-model cycle costs. One table per CPU generation keeps the
+/// classification logic shared and the numbers easy to audit.
+pub struct CycleCosts {
+    // MOV
+    pub mov_rr: u32,
+    pub mov_rm: u32,
+    pub mov_mr: u32,
+    pub mov_ri: u32,
+    pub mov_mi: u32,
+    pub mov_acc_mem: u32,
+
+    // ADD/SUB/AND/OR/XOR/ADC/SBB (CMP/TEST use the read-only column for memory destinations)
+    pub alu_rr: u32,
+    pub alu_rm: u32,
+    pub alu_mr: u32,
+    pub alu_ri: u32,
+    pub alu_mi: u32,
+    pub cmp_mr: u32,
+    pub cmp_mi: u32,
+
+    // INC/DEC/NEG/NOT
+    pub unary_r: u32,
+    pub unary_m: u32,
+
+    // Multiply / Divide (register form; memory form adds `muldiv_mem`)
+    pub mul8: u32,
+    pub mul16: u32,
+    pub imul8: u32,
+    pub imul16: u32,
+    pub div8: u32,
+    pub div16: u32,
+    pub idiv8: u32,
+    pub idiv16: u32,
+    pub muldiv_mem: u32,
+
+    // Shifts and rotates
+    pub shift_r1: u32,
+    pub shift_m1: u32,
+    pub shift_rn: u32,
+    pub shift_mn: u32,
+    pub shift_per_bit: u32,
+
+    // Stack
+    pub push_r: u32,
+    pub push_m: u32,
+    pub pop_r: u32,
+    pub pop_m: u32,
+    pub pushf: u32,
+    pub popf: u32,
+    pub pusha: u32,
+    pub popa: u32,
+
+    // Control transfer
+    pub jmp_near: u32,
+    pub jmp_far: u32,
+    pub jmp_r: u32,
+    pub jmp_m: u32,
+    pub jmp_far_m: u32,
+    pub jcc_taken: u32,
+    pub jcc_not_taken: u32,
+    pub loop_taken: u32,
+    pub loop_not_taken: u32,
+    pub jcxz_taken: u32,
+    pub jcxz_not_taken: u32,
+    pub call_near: u32,
+    pub call_far: u32,
+    pub call_r: u32,
+    pub call_m: u32,
+    pub call_far_m: u32,
+    pub ret_near: u32,
+    pub ret_far: u32,
+    pub int: u32,
+    pub iret: u32,
+    pub enter: u32,
+    pub leave: u32,
+
+    // String operations: (single, REP setup, REP per iteration)
+    pub movs: (u32, u32, u32),
+    pub cmps: (u32, u32, u32),
+    pub scas: (u32, u32, u32),
+    pub lods: (u32, u32, u32),
+    pub stos: (u32, u32, u32),
+    pub ins_outs: (u32, u32, u32),
+
+    // Misc
+    pub lea: u32,
+    pub lds_les: u32,
+    pub xchg_rr: u32,
+    pub xchg_rm: u32,
+    pub xlat: u32,
+    pub lahf_sahf: u32,
+    pub cbw: u32,
+    pub cwd: u32,
+    pub io_port: u32,
+    pub flag_op: u32,
+    pub hlt: u32,
+    pub nop: u32,
+    pub bcd_adjust: u32,
+    pub aam: u32,
+    pub aad: u32,
+
+    // FPU (coprocessor of the same generation)
+    pub fpu_load: u32,
+    pub fpu_store: u32,
+    pub fpu_int_load: u32,
+    pub fpu_int_store: u32,
+    pub fpu_bcd: u32,
+    pub fpu_const: u32,
+    pub fpu_add: u32,
+    pub fpu_mul: u32,
+    pub fpu_div: u32,
+    pub fpu_sqrt: u32,
+    pub fpu_compare: u32,
+    pub fpu_simple: u32,
+    pub fpu_transcendental: u32,
+    pub fpu_control: u32,
+    pub fpu_state: u32,
+
+    /// Fallback for anything not classified above
+    pub default: u32,
+
+    /// 8086/8088 compute effective addresses in microcode and charge for it
+    pub charges_ea: bool,
+    /// Extra clocks per 16-bit memory transfer (8088 8-bit bus)
+    pub word_penalty: u32,
+}
+
+pub const COSTS_8086: CycleCosts = CycleCosts {
+    mov_rr: 2,
+    mov_rm: 8,
+    mov_mr: 9,
+    mov_ri: 4,
+    mov_mi: 10,
+    mov_acc_mem: 10,
+    alu_rr: 3,
+    alu_rm: 9,
+    alu_mr: 16,
+    alu_ri: 4,
+    alu_mi: 17,
+    cmp_mr: 9,
+    cmp_mi: 10,
+    unary_r: 3,
+    unary_m: 15,
+    mul8: 74,
+    mul16: 126,
+    imul8: 89,
+    imul16: 141,
+    div8: 85,
+    div16: 153,
+    idiv8: 106,
+    idiv16: 174,
+    muldiv_mem: 6,
+    shift_r1: 2,
+    shift_m1: 15,
+    shift_rn: 8,
+    shift_mn: 20,
+    shift_per_bit: 4,
+    push_r: 11,
+    push_m: 16,
+    pop_r: 8,
+    pop_m: 17,
+    pushf: 10,
+    popf: 8,
+    pusha: 36,
+    popa: 51,
+    jmp_near: 15,
+    jmp_far: 15,
+    jmp_r: 11,
+    jmp_m: 18,
+    jmp_far_m: 24,
+    jcc_taken: 16,
+    jcc_not_taken: 4,
+    loop_taken: 17,
+    loop_not_taken: 5,
+    jcxz_taken: 18,
+    jcxz_not_taken: 6,
+    call_near: 19,
+    call_far: 28,
+    call_r: 16,
+    call_m: 21,
+    call_far_m: 37,
+    ret_near: 8,
+    ret_far: 18,
+    int: 51,
+    iret: 24,
+    enter: 15,
+    leave: 8,
+    movs: (18, 9, 17),
+    cmps: (22, 9, 22),
+    scas: (15, 9, 15),
+    lods: (12, 9, 13),
+    stos: (11, 9, 10),
+    ins_outs: (14, 8, 8),
+    lea: 2,
+    lds_les: 16,
+    xchg_rr: 4,
+    xchg_rm: 17,
+    xlat: 11,
+    lahf_sahf: 4,
+    cbw: 2,
+    cwd: 5,
+    io_port: 10,
+    flag_op: 2,
+    hlt: 2,
+    nop: 3,
+    bcd_adjust: 4,
+    aam: 83,
+    aad: 60,
+    fpu_load: 45,
+    fpu_store: 90,
+    fpu_int_load: 50,
+    fpu_int_store: 90,
+    fpu_bcd: 300,
+    fpu_const: 18,
+    fpu_add: 85,
+    fpu_mul: 140,
+    fpu_div: 200,
+    fpu_sqrt: 183,
+    fpu_compare: 45,
+    fpu_simple: 15,
+    fpu_transcendental: 500,
+    fpu_control: 15,
+    fpu_state: 200,
+    default: 4,
+    charges_ea: true,
+    word_penalty: 0,
+};
+
+pub const COSTS_8088: CycleCosts = CycleCosts {
+    word_penalty: 4,
+    ..COSTS_8086
+};
+
+pub const COSTS_80186: CycleCosts = CycleCosts {
+    mov_rr: 2,
+    mov_rm: 12,
+    mov_mr: 12,
+    mov_ri: 4,
+    mov_mi: 13,
+    mov_acc_mem: 9,
+    alu_rr: 3,
+    alu_rm: 10,
+    alu_mr: 15,
+    alu_ri: 4,
+    alu_mi: 16,
+    cmp_mr: 10,
+    cmp_mi: 10,
+    unary_r: 3,
+    unary_m: 15,
+    mul8: 27,
+    mul16: 36,
+    imul8: 26,
+    imul16: 35,
+    div8: 29,
+    div16: 38,
+    idiv8: 48,
+    idiv16: 57,
+    muldiv_mem: 6,
+    shift_r1: 2,
+    shift_m1: 15,
+    shift_rn: 5,
+    shift_mn: 17,
+    shift_per_bit: 1,
+    push_r: 10,
+    push_m: 16,
+    pop_r: 10,
+    pop_m: 20,
+    pushf: 9,
+    popf: 8,
+    pusha: 36,
+    popa: 51,
+    jmp_near: 14,
+    jmp_far: 14,
+    jmp_r: 11,
+    jmp_m: 17,
+    jmp_far_m: 26,
+    jcc_taken: 13,
+    jcc_not_taken: 4,
+    loop_taken: 15,
+    loop_not_taken: 5,
+    jcxz_taken: 16,
+    jcxz_not_taken: 6,
+    call_near: 15,
+    call_far: 23,
+    call_r: 13,
+    call_m: 19,
+    call_far_m: 38,
+    ret_near: 16,
+    ret_far: 22,
+    int: 47,
+    iret: 28,
+    enter: 15,
+    leave: 8,
+    movs: (14, 8, 8),
+    cmps: (22, 5, 22),
+    scas: (15, 5, 15),
+    lods: (12, 6, 11),
+    stos: (10, 6, 9),
+    ins_outs: (14, 8, 8),
+    lea: 6,
+    lds_les: 18,
+    xchg_rr: 4,
+    xchg_rm: 17,
+    xlat: 11,
+    lahf_sahf: 3,
+    cbw: 2,
+    cwd: 4,
+    io_port: 10,
+    flag_op: 2,
+    hlt: 2,
+    nop: 3,
+    bcd_adjust: 8,
+    aam: 19,
+    aad: 15,
+    charges_ea: false,
+    word_penalty: 0,
+    ..COSTS_8086
+};
+
+pub const COSTS_80286: CycleCosts = CycleCosts {
+    mov_rr: 2,
+    mov_rm: 5,
+    mov_mr: 3,
+    mov_ri: 2,
+    mov_mi: 3,
+    mov_acc_mem: 5,
+    alu_rr: 2,
+    alu_rm: 7,
+    alu_mr: 7,
+    alu_ri: 3,
+    alu_mi: 7,
+    cmp_mr: 6,
+    cmp_mi: 6,
+    unary_r: 2,
+    unary_m: 7,
+    mul8: 13,
+    mul16: 21,
+    imul8: 13,
+    imul16: 21,
+    div8: 14,
+    div16: 22,
+    idiv8: 17,
+    idiv16: 25,
+    muldiv_mem: 3,
+    shift_r1: 2,
+    shift_m1: 7,
+    shift_rn: 5,
+    shift_mn: 8,
+    shift_per_bit: 1,
+    push_r: 3,
+    push_m: 5,
+    pop_r: 5,
+    pop_m: 5,
+    pushf: 3,
+    popf: 5,
+    pusha: 17,
+    popa: 19,
+    jmp_near: 7,
+    jmp_far: 11,
+    jmp_r: 7,
+    jmp_m: 11,
+    jmp_far_m: 15,
+    jcc_taken: 7,
+    jcc_not_taken: 3,
+    loop_taken: 8,
+    loop_not_taken: 4,
+    jcxz_taken: 8,
+    jcxz_not_taken: 4,
+    call_near: 7,
+    call_far: 13,
+    call_r: 7,
+    call_m: 11,
+    call_far_m: 16,
+    ret_near: 11,
+    ret_far: 15,
+    int: 23,
+    iret: 17,
+    enter: 11,
+    leave: 5,
+    movs: (5, 5, 4),
+    cmps: (8, 5, 9),
+    scas: (7, 5, 8),
+    lods: (5, 5, 4),
+    stos: (3, 4, 3),
+    ins_outs: (5, 5, 4),
+    lea: 3,
+    lds_les: 7,
+    xchg_rr: 3,
+    xchg_rm: 5,
+    xlat: 5,
+    lahf_sahf: 2,
+    cbw: 2,
+    cwd: 2,
+    io_port: 5,
+    flag_op: 2,
+    hlt: 2,
+    nop: 3,
+    bcd_adjust: 3,
+    aam: 16,
+    aad: 14,
+    charges_ea: false,
+    word_penalty: 0,
+    ..COSTS_8086
+};
+
+pub const COSTS_80386: CycleCosts = CycleCosts {
+    mov_rr: 2,
+    mov_rm: 4,
+    mov_mr: 2,
+    mov_ri: 2,
+    mov_mi: 2,
+    mov_acc_mem: 4,
+    alu_rr: 2,
+    alu_rm: 6,
+    alu_mr: 7,
+    alu_ri: 2,
+    alu_mi: 7,
+    cmp_mr: 5,
+    cmp_mi: 5,
+    unary_r: 2,
+    unary_m: 6,
+    mul8: 12,
+    mul16: 17,
+    imul8: 12,
+    imul16: 17,
+    div8: 14,
+    div16: 22,
+    idiv8: 19,
+    idiv16: 27,
+    muldiv_mem: 3,
+    shift_r1: 3,
+    shift_m1: 7,
+    shift_rn: 3,
+    shift_mn: 7,
+    shift_per_bit: 0,
+    push_r: 2,
+    push_m: 5,
+    pop_r: 4,
+    pop_m: 5,
+    pushf: 4,
+    popf: 5,
+    pusha: 18,
+    popa: 24,
+    jmp_near: 7,
+    jmp_far: 12,
+    jmp_r: 7,
+    jmp_m: 10,
+    jmp_far_m: 12,
+    jcc_taken: 7,
+    jcc_not_taken: 3,
+    loop_taken: 11,
+    loop_not_taken: 11,
+    jcxz_taken: 9,
+    jcxz_not_taken: 5,
+    call_near: 7,
+    call_far: 17,
+    call_r: 7,
+    call_m: 10,
+    call_far_m: 22,
+    ret_near: 10,
+    ret_far: 18,
+    int: 37,
+    iret: 22,
+    enter: 10,
+    leave: 4,
+    movs: (7, 7, 4),
+    cmps: (10, 5, 9),
+    scas: (7, 5, 8),
+    lods: (5, 5, 6),
+    stos: (4, 5, 5),
+    ins_outs: (15, 13, 6),
+    lea: 2,
+    lds_les: 7,
+    xchg_rr: 3,
+    xchg_rm: 5,
+    xlat: 5,
+    lahf_sahf: 2,
+    cbw: 3,
+    cwd: 2,
+    io_port: 12,
+    flag_op: 2,
+    hlt: 5,
+    nop: 3,
+    bcd_adjust: 4,
+    aam: 17,
+    aad: 19,
+    fpu_load: 14,
+    fpu_store: 11,
+    fpu_int_load: 45,
+    fpu_int_store: 80,
+    fpu_bcd: 266,
+    fpu_const: 20,
+    fpu_add: 23,
+    fpu_mul: 29,
+    fpu_div: 88,
+    fpu_sqrt: 122,
+    fpu_compare: 24,
+    fpu_simple: 6,
+    fpu_transcendental: 300,
+    fpu_control: 5,
+    fpu_state: 100,
+    default: 2,
+    charges_ea: false,
+    word_penalty: 0,
+};
+
+impl CpuModel {
+    pub fn cycle_costs(&self) -> &'static CycleCosts {
+        match self {
+            CpuModel::I8088 => &COSTS_8088,
+            CpuModel::I8086 => &COSTS_8086,
+            CpuModel::I80186 => &COSTS_80186,
+            CpuModel::I80286 => &COSTS_80286,
+            CpuModel::I80386 => &COSTS_80386,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Form {
+    RegReg,
+    RegMem,
+    MemReg,
+    RegImm,
+    MemImm,
+    Reg,
+    Mem,
+    None,
+}
+
+fn is_immediate(kind: OpKind) -> bool {
+    matches!(
+        kind,
+        OpKind::Immediate8
+            | OpKind::Immediate8_2nd
+            | OpKind::Immediate16
+            | OpKind::Immediate32
+            | OpKind::Immediate8to16
+            | OpKind::Immediate8to32
+    )
+}
+
+fn operand_form(instr: &Instruction) -> Form {
+    match instr.op_count() {
+        0 => Form::None,
+        1 => match instr.op0_kind() {
+            OpKind::Memory => Form::Mem,
+            _ => Form::Reg,
+        },
+        _ => {
+            let k0 = instr.op0_kind();
+            let k1 = instr.op1_kind();
+            match (k0, k1) {
+                (OpKind::Memory, OpKind::Register) => Form::MemReg,
+                (OpKind::Register, OpKind::Memory) => Form::RegMem,
+                (OpKind::Memory, k) if is_immediate(k) => Form::MemImm,
+                (OpKind::Register, k) if is_immediate(k) => Form::RegImm,
+                (OpKind::Memory, _) => Form::Mem,
+                _ => Form::RegReg,
+            }
+        }
+    }
+}
+
+fn has_memory_operand(instr: &Instruction) -> bool {
+    (0..instr.op_count()).any(|i| instr.op_kind(i) == OpKind::Memory)
+}
+
+/// Effective address calculation time on the 8086/8088.
+pub fn ea_cycles(instr: &Instruction) -> u32 {
+    let base = instr.memory_base();
+    let index = instr.memory_index();
+    let has_disp = instr.memory_displ_size() != 0;
+
+    let cycles = match (base != Register::None, index != Register::None) {
+        (false, false) => 6, // Direct [disp16]
+        (true, false) | (false, true) => {
+            if has_disp {
+                9
+            } else {
+                5
+            }
+        }
+        (true, true) => {
+            // BP+DI and BX+SI are one clock faster than BP+SI and BX+DI
+            let fast = matches!(
+                (base, index),
+                (Register::BP, Register::DI) | (Register::BX, Register::SI)
+            );
+            match (fast, has_disp) {
+                (true, false) => 7,
+                (false, false) => 8,
+                (true, true) => 11,
+                (false, true) => 12,
+            }
+        }
+    };
+
+    // Segment override prefix
+    if instr.segment_prefix() != Register::None {
+        cycles + 2
+    } else {
+        cycles
+    }
+}
+
+fn is_word_memory(instr: &Instruction) -> bool {
+    !matches!(
+        instr.memory_size(),
+        MemorySize::UInt8 | MemorySize::Int8 | MemorySize::Unknown
+    )
+}
+
+fn is_8bit_operation(instr: &Instruction) -> bool {
+    match instr.op0_kind() {
+        OpKind::Register => instr.op0_register().is_gpr8(),
+        OpKind::Memory => matches!(instr.memory_size(), MemorySize::UInt8 | MemorySize::Int8),
+        _ => false,
+    }
+}
+
+/// Returns the number of clocks `instr` takes on `model`.
+///
+/// `branch_taken` tells conditional transfers which column to use and
+/// `count` carries the iteration count of a REP string instruction or the
+/// bit count of a shift by CL.
+pub fn instruction_cycles(
+    model: CpuModel,
+    instr: &Instruction,
+    branch_taken: bool,
+    count: u32,
+) -> u32 {
+    let c = model.cycle_costs();
+    let form = operand_form(instr);
+    let memory = has_memory_operand(instr) && instr.mnemonic() != Mnemonic::Lea;
+
+    // Number of 16-bit bus transfers, for the 8088 penalty
+    let mut word_transfers = 0;
+    let mem_word = memory && is_word_memory(instr);
+
+    let base = match instr.mnemonic() {
+        Mnemonic::Mov => {
+            let acc_direct = (form == Form::RegMem || form == Form::MemReg)
+                && instr.memory_base() == Register::None
+                && instr.memory_index() == Register::None
+                && matches!(
+                    if form == Form::RegMem {
+                        instr.op0_register()
+                    } else {
+                        instr.op1_register()
+                    },
+                    Register::AL | Register::AX
+                );
+            if mem_word {
+                word_transfers = 1;
+            }
+            if acc_direct {
+                // MOV AL/AX, moffs has no ModRM and no EA calculation
+                return c.mov_acc_mem + word_transfers * c.word_penalty;
+            }
+            match form {
+                Form::RegReg => c.mov_rr,
+                Form::RegMem => c.mov_rm,
+                Form::MemReg => c.mov_mr,
+                Form::RegImm => c.mov_ri,
+                Form::MemImm => c.mov_mi,
+                _ => c.mov_rr,
+            }
+        }
+
+        Mnemonic::Add
+        | Mnemonic::Sub
+        | Mnemonic::Adc
+        | Mnemonic::Sbb
+        | Mnemonic::And
+        | Mnemonic::Or
+        | Mnemonic::Xor => match form {
+            Form::RegReg => c.alu_rr,
+            Form::RegMem => {
+                word_transfers = 1;
+                c.alu_rm
+            }
+            Form::MemReg => {
+                word_transfers = 2;
+                c.alu_mr
+            }
+            Form::RegImm => c.alu_ri,
+            Form::MemImm => {
+                word_transfers = 2;
+                c.alu_mi
+            }
+            _ => c.alu_rr,
+        },
+
+        Mnemonic::Cmp | Mnemonic::Test => match form {
+            Form::RegReg => c.alu_rr,
+            Form::RegMem => {
+                word_transfers = 1;
+                c.alu_rm
+            }
+            Form::MemReg => {
+                word_transfers = 1;
+                c.cmp_mr
+            }
+            Form::RegImm => c.alu_ri,
+            Form::MemImm => {
+                word_transfers = 1;
+                c.cmp_mi
+            }
+            _ => c.alu_rr,
+        },
+
+        Mnemonic::Inc | Mnemonic::Dec | Mnemonic::Neg | Mnemonic::Not => {
+            if memory {
+                word_transfers = 2;
+                c.unary_m
+            } else if model <= CpuModel::I8086
+                && matches!(instr.mnemonic(), Mnemonic::Inc | Mnemonic::Dec)
+                && !instr.op0_register().is_gpr8()
+            {
+                // Short-form INC/DEC r16 (40h-4Fh)
+                2
+            } else {
+                c.unary_r
+            }
+        }
+
+        Mnemonic::Mul | Mnemonic::Imul | Mnemonic::Div | Mnemonic::Idiv => {
+            let byte = is_8bit_operation(instr);
+            let cost = match (instr.mnemonic(), byte) {
+                (Mnemonic::Mul, true) => c.mul8,
+                (Mnemonic::Mul, false) => c.mul16,
+                (Mnemonic::Imul, true) => c.imul8,
+                (Mnemonic::Imul, false) => c.imul16,
+                (Mnemonic::Div, true) => c.div8,
+                (Mnemonic::Div, false) => c.div16,
+                (Mnemonic::Idiv, true) => c.idiv8,
+                _ => c.idiv16,
+            };
+            if memory {
+                word_transfers = 1;
+                cost + c.muldiv_mem
+            } else {
+                cost
+            }
+        }
+
+        Mnemonic::Shl
+        | Mnemonic::Sal
+        | Mnemonic::Shr
+        | Mnemonic::Sar
+        | Mnemonic::Rol
+        | Mnemonic::Ror
+        | Mnemonic::Rcl
+        | Mnemonic::Rcr => {
+            let by_one =
+                instr.op_count() < 2 || (is_immediate(instr.op1_kind()) && instr.immediate8() == 1);
+            if memory {
+                word_transfers = 2;
+            }
+            match (memory, by_one) {
+                (false, true) => c.shift_r1,
+                (true, true) => c.shift_m1,
+                (false, false) => c.shift_rn + c.shift_per_bit * count,
+                (true, false) => c.shift_mn + c.shift_per_bit * count,
+            }
+        }
+
+        Mnemonic::Push => {
+            word_transfers = if memory { 2 } else { 1 };
+            if memory { c.push_m } else { c.push_r }
+        }
+        Mnemonic::Pop => {
+            word_transfers = if memory { 2 } else { 1 };
+            if memory { c.pop_m } else { c.pop_r }
+        }
+        Mnemonic::Pushf => {
+            word_transfers = 1;
+            c.pushf
+        }
+        Mnemonic::Popf => {
+            word_transfers = 1;
+            c.popf
+        }
+        Mnemonic::Pusha => {
+            word_transfers = 8;
+            c.pusha
+        }
+        Mnemonic::Popa => {
+            word_transfers = 8;
+            c.popa
+        }
+
+        Mnemonic::Jmp => match instr.op0_kind() {
+            OpKind::NearBranch16 | OpKind::NearBranch32 => c.jmp_near,
+            OpKind::FarBranch16 | OpKind::FarBranch32 => c.jmp_far,
+            OpKind::Register => c.jmp_r,
+            _ => {
+                if instr.memory_size() == MemorySize::SegPtr16 {
+                    word_transfers = 2;
+                    c.jmp_far_m
+                } else {
+                    word_transfers = 1;
+                    c.jmp_m
+                }
+            }
+        },
+
+        Mnemonic::Call => match instr.op0_kind() {
+            OpKind::NearBranch16 | OpKind::NearBranch32 => {
+                word_transfers = 1;
+                c.call_near
+            }
+            OpKind::FarBranch16 | OpKind::FarBranch32 => {
+                word_transfers = 2;
+                c.call_far
+            }
+            OpKind::Register => {
+                word_transfers = 1;
+                c.call_r
+            }
+            _ => {
+                if instr.memory_size() == MemorySize::SegPtr16 {
+                    word_transfers = 4;
+                    c.call_far_m
+                } else {
+                    word_transfers = 2;
+                    c.call_m
+                }
+            }
+        },
+
+        Mnemonic::Ret => {
+            word_transfers = 1;
+            c.ret_near
+        }
+        Mnemonic::Retf => {
+            word_transfers = 2;
+            c.ret_far
+        }
+
+        Mnemonic::Je
+        | Mnemonic::Jne
+        | Mnemonic::Jb
+        | Mnemonic::Jbe
+        | Mnemonic::Ja
+        | Mnemonic::Jae
+        | Mnemonic::Jl
+        | Mnemonic::Jle
+        | Mnemonic::Jg
+        | Mnemonic::Jge
+        | Mnemonic::Js
+        | Mnemonic::Jns
+        | Mnemonic::Jo
+        | Mnemonic::Jno
+        | Mnemonic::Jp
+        | Mnemonic::Jnp => {
+            if branch_taken {
+                c.jcc_taken
+            } else {
+                c.jcc_not_taken
+            }
+        }
+
+        Mnemonic::Loop | Mnemonic::Loope | Mnemonic::Loopne => {
+            let extra = if instr.mnemonic() == Mnemonic::Loop {
+                0
+            } else {
+                1
+            };
+            if branch_taken {
+                c.loop_taken + extra
+            } else {
+                c.loop_not_taken + extra
+            }
+        }
+        Mnemonic::Jcxz | Mnemonic::Jecxz => {
+            if branch_taken {
+                c.jcxz_taken
+            } else {
+                c.jcxz_not_taken
+            }
+        }
+
+        Mnemonic::Int | Mnemonic::Int3 => {
+            // FLAGS, CS, IP pushed + 2 IVT words read
+            word_transfers = 5;
+            c.int
+        }
+        Mnemonic::Into => {
+            if branch_taken {
+                word_transfers = 5;
+                c.int + 2
+            } else {
+                4
+            }
+        }
+        Mnemonic::Iret => {
+            word_transfers = 3;
+            c.iret
+        }
+        Mnemonic::Enter => c.enter,
+        Mnemonic::Leave => {
+            word_transfers = 1;
+            c.leave
+        }
+
+        Mnemonic::Movsb | Mnemonic::Movsw => string_cost(c.movs, instr, count),
+        Mnemonic::Cmpsb | Mnemonic::Cmpsw => string_cost(c.cmps, instr, count),
+        Mnemonic::Scasb | Mnemonic::Scasw => string_cost(c.scas, instr, count),
+        Mnemonic::Lodsb | Mnemonic::Lodsw => string_cost(c.lods, instr, count),
+        Mnemonic::Stosb | Mnemonic::Stosw => string_cost(c.stos, instr, count),
+        Mnemonic::Insb | Mnemonic::Insw | Mnemonic::Outsb | Mnemonic::Outsw => {
+            string_cost(c.ins_outs, instr, count)
+        }
+
+        Mnemonic::Lea => c.lea,
+        Mnemonic::Lds | Mnemonic::Les => {
+            word_transfers = 2;
+            c.lds_les
+        }
+        Mnemonic::Xchg => {
+            if memory {
+                word_transfers = 2;
+                c.xchg_rm
+            } else if instr.op0_register() == Register::AX || instr.op1_register() == Register::AX {
+                // XCHG AX, r16 short form
+                c.xchg_rr - 1
+            } else {
+                c.xchg_rr
+            }
+        }
+        Mnemonic::Xlatb => c.xlat,
+        Mnemonic::Lahf | Mnemonic::Sahf => c.lahf_sahf,
+        Mnemonic::Cbw => c.cbw,
+        Mnemonic::Cwd => c.cwd,
+        Mnemonic::In | Mnemonic::Out => {
+            // DX-relative form is two clocks cheaper than the immediate form
+            if instr.op0_register() == Register::DX || instr.op1_register() == Register::DX {
+                c.io_port.saturating_sub(2)
+            } else {
+                c.io_port
+            }
+        }
+
+        Mnemonic::Clc
+        | Mnemonic::Stc
+        | Mnemonic::Cmc
+        | Mnemonic::Cld
+        | Mnemonic::Std
+        | Mnemonic::Cli
+        | Mnemonic::Sti => c.flag_op,
+        Mnemonic::Hlt => c.hlt,
+        Mnemonic::Nop | Mnemonic::Wait => c.nop,
+        Mnemonic::Aaa | Mnemonic::Aas | Mnemonic::Daa | Mnemonic::Das => c.bcd_adjust,
+        Mnemonic::Aam => c.aam,
+        Mnemonic::Aad => c.aad,
+
+        m => fpu_cycles(c, m).unwrap_or(c.default),
+    };
+
+    let ea = if memory && c.charges_ea {
+        ea_cycles(instr)
+    } else {
+        0
+    };
+
+    let penalty = if mem_word || !memory {
+        word_transfers * c.word_penalty
+    } else {
+        // Byte-sized memory operand: only the stack side of the transfer is a word
+        0
+    };
+
+    base + ea + penalty
+}
+
+fn string_cost(cost: (u32, u32, u32), instr: &Instruction, count: u32) -> u32 {
+    let (single, rep_setup, per_iteration) = cost;
+    if instr.has_rep_prefix() || instr.has_repne_prefix() {
+        rep_setup + per_iteration * count
+    } else {
+        single
+    }
+}
+
+fn fpu_cycles(c: &CycleCosts, mnemonic: Mnemonic) -> Option<u32> {
+    let cost = match mnemonic {
+        Mnemonic::Fld | Mnemonic::Fxch => c.fpu_load,
+        Mnemonic::Fst | Mnemonic::Fstp => c.fpu_store,
+        Mnemonic::Fild => c.fpu_int_load,
+        Mnemonic::Fist | Mnemonic::Fistp | Mnemonic::Fisttp => c.fpu_int_store,
+        Mnemonic::Fbld | Mnemonic::Fbstp => c.fpu_bcd,
+        Mnemonic::Fld1
+        | Mnemonic::Fldz
+        | Mnemonic::Fldpi
+        | Mnemonic::Fldl2e
+        | Mnemonic::Fldl2t
+        | Mnemonic::Fldlg2
+        | Mnemonic::Fldln2 => c.fpu_const,
+        Mnemonic::Fadd
+        | Mnemonic::Faddp
+        | Mnemonic::Fiadd
+        | Mnemonic::Fsub
+        | Mnemonic::Fsubp
+        | Mnemonic::Fsubr
+        | Mnemonic::Fsubrp
+        | Mnemonic::Fisub
+        | Mnemonic::Fisubr => c.fpu_add,
+        Mnemonic::Fmul | Mnemonic::Fmulp | Mnemonic::Fimul | Mnemonic::Fscale => c.fpu_mul,
+        Mnemonic::Fdiv
+        | Mnemonic::Fdivp
+        | Mnemonic::Fdivr
+        | Mnemonic::Fdivrp
+        | Mnemonic::Fidiv
+        | Mnemonic::Fidivr
+        | Mnemonic::Fprem
+        | Mnemonic::Fprem1 => c.fpu_div,
+        Mnemonic::Fsqrt => c.fpu_sqrt,
+        Mnemonic::Fcom
+        | Mnemonic::Fcomp
+        | Mnemonic::Fcompp
+        | Mnemonic::Ficom
+        | Mnemonic::Ficomp
+        | Mnemonic::Fucom
+        | Mnemonic::Fucomp
+        | Mnemonic::Fucompp
+        | Mnemonic::Fcomi
+        | Mnemonic::Fcomip
+        | Mnemonic::Fucomi
+        | Mnemonic::Fucomip
+        | Mnemonic::Ftst
+        | Mnemonic::Fxam => c.fpu_compare,
+        Mnemonic::Fabs
+        | Mnemonic::Fchs
+        | Mnemonic::Frndint
+        | Mnemonic::Fxtract
+        | Mnemonic::Fincstp
+        | Mnemonic::Fdecstp
+        | Mnemonic::Ffree
+        | Mnemonic::Fnop => c.fpu_simple,
+        Mnemonic::Fsin
+        | Mnemonic::Fcos
+        | Mnemonic::Fsincos
+        | Mnemonic::Fptan
+        | Mnemonic::Fpatan
+        | Mnemonic::F2xm1
+        | Mnemonic::Fyl2x
+        | Mnemonic::Fyl2xp1 => c.fpu_transcendental,
+        Mnemonic::Finit
+        | Mnemonic::Fninit
+        | Mnemonic::Fldcw
+        | Mnemonic::Fstcw
+        | Mnemonic::Fnstcw
+        | Mnemonic::Fstsw
+        | Mnemonic::Fnstsw
+        | Mnemonic::Fclex
+        | Mnemonic::Fnclex => c.fpu_control,
+        Mnemonic::Fsave
+        | Mnemonic::Fnsave
+        | Mnemonic::Frstor
+        | Mnemonic::Fstenv
+        | Mnemonic::Fnstenv
+        | Mnemonic::Fldenv => c.fpu_state,
+        _ => return None,
+    };
+    Some(cost)
+}
+
+/// Cost of a BOP trap into an HLE handler: an INT plus the IRET.
+/// The work done on the host side is free.
+pub fn hle_cycles(model: CpuModel) -> u32 {
+    let c = model.cycle_costs();
+    c.int + c.iret
+}
+
+/// Executes `instr` and charges its cycles. IP must already point past the instruction.
+pub fn execute_timed(cpu: &mut Cpu, instr: &Instruction) {
+    let cx_before = cpu.cx;
+    // The 8086 uses the full CL count; later models mask it to 5 bits
+    let cl_before = if cpu.model >= CpuModel::I80186 {
+        (cpu.cx & 0x1F) as u32
+    } else {
+        (cpu.cx & 0xFF) as u32
+    };
+    let cs_before = cpu.cs;
+
+    super::execute_instruction(cpu, instr);
+
+    let branch_taken = cpu.ip != instr.next_ip() as u16 || cpu.cs != cs_before;
+    let count = if instr.has_rep_prefix() || instr.has_repne_prefix() {
+        cx_before.wrapping_sub(cpu.cx) as u32
+    } else if instr.op_count() == 2 && instr.op1_register() == Register::CL {
+        cl_before
+    } else {
+        0
+    };
+
+    let cycles = instruction_cycles(cpu.model, instr, branch_taken, count);
+    cpu.add_cycles(cycles);
+}
@@ -0,0 +1,85 @@
+use crate::cpu::Cpu;
+
+// Host-side interrupt handlers.
+//
+// Embedding code can attach closures to any vector, or to one AH function of a
+// vector. Each vector keeps a chain: the newest handler sees the call first and
+// either services it or passes it on, down to the built-in HLE handler.
+// Hooked vectors get a BOP stub like the BIOS ones, so DOS programs reach them
+// with a plain INT.
+
+/// What a host handler did with the call
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HleAction {
+    /// The call was serviced; the rest of the chain doesn't see it
+    Handled,
+    /// Pass the call on to the previously registered handler
+    Chain,
+}
+
+pub type HleHandler = Box<dyn FnMut(&mut Cpu) -> HleAction>;
+
+struct Hook {
+    // Only called when AH matches
+    ah: Option<u8>,
+    handler: HleHandler,
+}
+
+pub struct HleHooks {
+    // One chain per vector, oldest first
+    chains: Vec<Vec<Hook>>,
+}
+
+impl HleHooks {
+    pub fn new() -> Self {
+        Self {
+            chains: (0..256).map(|_| Vec::new()).collect(),
+        }
+    }
+
+    pub fn add(&mut self, vector: u8, ah: Option<u8>, handler: HleHandler) {
+        self.chains[vector as usize].push(Hook { ah, handler });
+    }
+
+    pub fn is_hooked(&self, vector: u8) -> bool {
+        !self.chains[vector as usize].is_empty()
+    }
+
+    pub fn hooked_vectors(&self) -> Vec<u8> {
+        (0..=255u8).filter(|&v| self.is_hooked(v)).collect()
+    }
+}
+
+impl Default for HleHooks {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Runs the host handlers for `vector`, newest first.
+/// Returns true if one of them handled the call.
+pub fn dispatch(cpu: &mut Cpu, vector: u8) -> bool {
+    if !cpu.hle_hooks.is_hooked(vector) {
+        return false;
+    }
+
+    // Handlers need the whole CPU, so the chain is lent out while it runs
+    let mut chain = std::mem::take(&mut cpu.hle_hooks.chains[vector as usize]);
+    let ah = (cpu.ax >> 8) as u8;
+
+    let mut handled = false;
+    for hook in chain.iter_mut().rev() {
+        if hook.ah.is_some_and(|f| f != ah) {
+            continue;
+        }
+        if (hook.handler)(cpu) == HleAction::Handled {
+            handled = true;
+            break;
+        }
+    }
+
+    // Keep anything a handler registered in the meantime
+    let added = std::mem::replace(&mut cpu.hle_hooks.chains[vector as usize], chain);
+    cpu.hle_hooks.chains[vector as usize].extend(added);
+    handled
+}
@@ -28,15 +28,15 @@ pub fn handle(cpu: &mut Cpu) {
                 // CGA Graphics Modes (4, 5, 6): Zero out 16KB of B8000 Memory
                 0x04..=0x06 => {
                     for i in 0..16384 {
-                        if i < cpu.bus.vga.vram_text.len() {
-                            cpu.bus.vga.vram_text[i] = 0x00;
+                        if i < cpu.bus.vga().vram_text.len() {
+                            cpu.bus.vga_mut().vram_text[i] = 0x00;
                         }
                     }
                 }
                 // VGA Graphics Mode (13h): Zero out 64KB of A0000 Memory
                 0x13 => {
-                    for i in 0..cpu.bus.vga.vram_graphics.len() {
-                        cpu.bus.vga.vram_graphics[i] = 0x00;
+                    for i in 0..cpu.bus.vga().vram_graphics.len() {
+                        cpu.bus.vga_mut().vram_graphics[i] = 0x00;
                     }
                 }
                 // Fallback / Stubbed modes
@@ -92,7 +92,7 @@ pub fn handle(cpu: &mut Cpu) {
                     cpu.bus
                         .log_string("[BIOS] Switch to Graphics Mode (320x200)");
                     cpu.bus.video_mode = VideoMode::Graphics320x200;
-                    cpu.bus.vga.set_video_mode(VideoMode::Graphics320x200);
+                    cpu.bus.vga_mut().set_video_mode(VideoMode::Graphics320x200);
                 }
                 _ => cpu
                     .bus
@@ -357,12 +357,12 @@ pub fn handle(cpu: &mut Cpu) {
                     // BH = Color Value
                     let reg = (cpu.bx & 0xFF) as u8 & 0x0F;
                     let val = (cpu.bx >> 8) as u8;
-                    cpu.bus.vga.attribute_regs[reg as usize] = val;
+                    cpu.bus.vga_mut().attribute_regs[reg as usize] = val;
                 }
                 0x01 => {
                     // Set Overscan (Border) Color
                     let val = (cpu.bx >> 8) as u8; // BH
-                    cpu.bus.vga.attribute_regs[0x11] = val;
+                    cpu.bus.vga_mut().attribute_regs[0x11] = val;
                 }
                 0x02 => {
                     // Set All Palette Registers + Overscan
@@ -373,17 +373,17 @@ pub fn handle(cpu: &mut Cpu) {
 
                     for i in 0..16 {
                         let val = cpu.bus.read_8(addr + i);
-                        cpu.bus.vga.attribute_regs[i as usize] = val;
+                        cpu.bus.vga_mut().attribute_regs[i as usize] = val;
                     }
                     let border = cpu.bus.read_8(addr + 16);
-                    cpu.bus.vga.attribute_regs[0x11] = border;
+                    cpu.bus.vga_mut().attribute_regs[0x11] = border;
                 }
                 0x07 => {
                     // Read Individual Palette Register
                     // BL = Register
                     // Return: BH = Value
                     let reg = (cpu.bx & 0xFF) as u8 & 0x0F;
-                    let val = cpu.bus.vga.attribute_regs[reg as usize];
+                    let val = cpu.bus.vga().attribute_regs[reg as usize];
                     cpu.set_reg8(Register::BH, val);
                 }
                 0x10 => {
@@ -396,10 +396,10 @@ pub fn handle(cpu: &mut Cpu) {
                     let b = (cpu.cx & 0xFF) as u8; // CL
 
                     let base = (idx as usize) * 3;
-                    if base + 2 < cpu.bus.vga.palette.len() {
-                        cpu.bus.vga.palette[base] = r;
-                        cpu.bus.vga.palette[base + 1] = g;
-                        cpu.bus.vga.palette[base + 2] = b;
+                    if base + 2 < cpu.bus.vga().palette.len() {
+                        cpu.bus.vga_mut().palette[base] = r;
+                        cpu.bus.vga_mut().palette[base + 1] = g;
+                        cpu.bus.vga_mut().palette[base + 2] = b;
                     }
                 }
                 _ => {
@@ -837,7 +837,7 @@ fn write_char_at(cpu: &mut Cpu, col: u8, row: u8, char_code: u8, attr: u8) {
             };
 
             let offset = (row as usize * cols + col as usize) * 2;
-            if offset < cpu.bus.vga.vram_text.len() {
+            if offset < cpu.bus.vga().vram_text.len() {
                 cpu.bus.write_8(ADDR_VGA_TEXT + offset, char_code);
                 cpu.bus.write_8(ADDR_VGA_TEXT + offset + 1, attr);
             }
@@ -866,7 +866,7 @@ fn read_char_at(cpu: &Cpu, col: u8, row: u8, _page: u8) -> (u8, u8) {
             };
 
             let offset = (row as usize * cols + col as usize) * 2;
-            if offset < cpu.bus.vga.vram_text.len() {
+            if offset < cpu.bus.vga().vram_text.len() {
                 let char_code = cpu.bus.read_8(ADDR_VGA_TEXT + offset);
                 let attr = cpu.bus.read_8(ADDR_VGA_TEXT + offset + 1);
                 (char_code, attr)
@@ -904,15 +904,15 @@ fn scroll_area(
     if is_graphics && lines == 0 {
         // Determine which VRAM buffer to clear
         if cpu.bus.video_mode == VideoMode::Graphics320x200 {
-            for i in 0..cpu.bus.vga.vram_graphics.len() {
-                cpu.bus.vga.vram_graphics[i] = 0;
+            for i in 0..cpu.bus.vga().vram_graphics.len() {
+                cpu.bus.vga_mut().vram_graphics[i] = 0;
             }
         } else {
             // CGA Modes use the text buffer range
             for i in 0..16384 {
                 // 16KB CGA Memory
-                if i < cpu.bus.vga.vram_text.len() {
-                    cpu.bus.vga.vram_text[i] = 0;
+                if i < cpu.bus.vga().vram_text.len() {
+                    cpu.bus.vga_mut().vram_text[i] = 0;
                 }
             }
         }
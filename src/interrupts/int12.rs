@@ -1,5 +1,5 @@
 use crate::cpu::Cpu;
 
 pub fn handle(cpu: &mut Cpu) {
-    cpu.ax = 640; // KB
+    cpu.ax = cpu.bus.read_16(0x0413); // KB, from the BDA
 }
\ No newline at end of file
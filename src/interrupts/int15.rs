@@ -4,9 +4,43 @@ use iced_x86::Register;
 pub fn handle(cpu: &mut Cpu) {
     let ah = cpu.get_ah();
     match ah {
+        0x87 => {
+            // Move Extended Memory Block
+            // ES:SI -> GDT: source descriptor at +10h, destination at +18h. CX = words.
+            let gdt = cpu.get_physical_addr(cpu.es, cpu.si);
+            let src = descriptor_base(cpu, gdt + 0x10);
+            let dst = descriptor_base(cpu, gdt + 0x18);
+            let len = cpu.cx as usize * 2;
+            cpu.bus.copy_linear(src, dst, len);
+            cpu.set_reg8(Register::AH, 0);
+            cpu.set_cpu_flag(CpuFlags::CF, false);
+        }
         0x88 => {
-            // Extended Memory (16MB total -> 15MB extended)
-            cpu.ax = 15360;
+            // Extended Memory Size (KB)
+            cpu.ax = cpu.bus.extended_kb();
+            cpu.set_cpu_flag(CpuFlags::CF, false);
+        }
+        0x24 => {
+            // A20 Gate Support
+            let al = cpu.get_al();
+            match al {
+                0x00 | 0x01 => cpu.bus.set_a20(al == 0x01), // Disable / Enable
+                0x02 => {
+                    // Query Status
+                    let on = cpu.bus.a20_enabled();
+                    cpu.set_reg8(Register::AL, on as u8);
+                }
+                0x03 => {
+                    // Query Support: keyboard controller and port 92h
+                    cpu.bx = 0x0003;
+                }
+                _ => {
+                    cpu.set_reg8(Register::AH, 0x86); // Function not supported
+                    cpu.set_cpu_flag(CpuFlags::CF, true);
+                    return;
+                }
+            }
+            cpu.set_reg8(Register::AH, 0);
             cpu.set_cpu_flag(CpuFlags::CF, false);
         }
         0x86 => {
@@ -23,27 +57,42 @@ pub fn handle(cpu: &mut Cpu) {
             let table_off = 0xE800;
             let phys_addr = 0xFE800;
 
-            // Byte 0-1: Length (8)
-            cpu.bus.write_16(phys_addr, 0x0008);
-            // Byte 2: Model (FC = AT)
-            cpu.bus.write_8(phys_addr + 2, 0xFC);
-            // Byte 3: Submodel (01 = AT)
-            cpu.bus.write_8(phys_addr + 3, 0x01);
-            // Byte 4: BIOS Revision (0)
-            cpu.bus.write_8(phys_addr + 4, 0x00);
-            // Byte 5: Feature Info 1 (0)
-            cpu.bus.write_8(phys_addr + 5, 0x00);
-            // Byte 6-9: Reserved/Features
-            cpu.bus.write_8(phys_addr + 6, 0x00);
-            cpu.bus.write_8(phys_addr + 7, 0x00);
+            cpu.bus.write_rom(
+                phys_addr,
+                &[
+                    0x08, 0x00, // Byte 0-1: Length (8)
+                    0xFC, // Byte 2: Model (FC = AT)
+                    0x01, // Byte 3: Submodel (01 = AT)
+                    0x00, // Byte 4: BIOS Revision (0)
+                    0x00, // Byte 5: Feature Info 1 (0)
+                    0x00, 0x00, // Byte 6-9: Reserved/Features
+                ],
+            );
 
             cpu.es = table_seg;
             cpu.bx = table_off;
             cpu.set_reg8(Register::AH, 0);
             cpu.set_cpu_flag(CpuFlags::CF, false);
         }
+        0xE8 if cpu.get_al() == 0x01 => {
+            // Get Memory Size for >64MB Configurations
+            // AX/CX = KB between 1MB and 16MB, BX/DX = 64KB blocks above 16MB
+            let kb = cpu.bus.extended_kb() as u32;
+            let below_16mb = kb.min(15 * 1024);
+            let above_16mb = (kb - below_16mb) / 64;
+            cpu.ax = below_16mb as u16;
+            cpu.cx = below_16mb as u16;
+            cpu.bx = above_16mb as u16;
+            cpu.dx = above_16mb as u16;
+            cpu.set_cpu_flag(CpuFlags::CF, false);
+        }
         _ => cpu
             .bus
             .log_string(&format!("[BIOS] Unhandled INT 15h AH={:02X}", ah)),
     }
 }
+
+// 24-bit base of a protected mode segment descriptor
+fn descriptor_base(cpu: &Cpu, addr: usize) -> usize {
+    cpu.bus.read_16(addr + 2) as usize | (cpu.bus.read_8(addr + 4) as usize) << 16
+}
@@ -1,24 +1,94 @@
+use chrono::{Datelike, NaiveDate, NaiveTime, Timelike};
 use iced_x86::Register;
+use crate::clock;
 use crate::cpu::{Cpu, CpuFlags};
+use crate::devices::rtc::RTC_IRQ;
+
+fn to_bcd(value: u32) -> u8 {
+    (((value / 10) << 4) | (value % 10)) as u8
+}
+
+fn from_bcd(value: u8) -> Option<u32> {
+    let (high, low) = (value >> 4, value & 0x0F);
+    (high < 10 && low < 10).then_some((high * 10 + low) as u32)
+}
+
+// CH:CL:DH as hours, minutes and seconds in BCD
+fn bcd_time(cpu: &Cpu) -> Option<NaiveTime> {
+    let hour = from_bcd(cpu.get_reg8(Register::CH))?;
+    let minute = from_bcd(cpu.get_reg8(Register::CL))?;
+    let second = from_bcd(cpu.get_reg8(Register::DH))?;
+    NaiveTime::from_hms_opt(hour, minute, second)
+}
 
 pub fn handle(cpu: &mut Cpu) {
     let ah = cpu.get_ah();
+    let now = cpu.bus.clock().now();
     match ah {
-        0x00 => {
-            let elapsed_ms = cpu.bus.start_time.elapsed().as_millis();
-            let ticks = (elapsed_ms as u64 * 182) / 10000;
+        0x00 => { // Get Tick Count; AL is set once past midnight
+            let (ticks, midnight) = clock::read_ticks(&mut cpu.bus);
             cpu.cx = (ticks >> 16) as u16;
             cpu.dx = (ticks & 0xFFFF) as u16;
-            cpu.set_reg8(Register::AL, 0);
+            cpu.set_reg8(Register::AL, midnight as u8);
+        }
+        0x01 => { // Set Tick Count
+            let ticks = ((cpu.cx as u32) << 16) | cpu.dx as u32;
+            clock::set_ticks(&mut cpu.bus, ticks);
         }
         0x02 => { // Get Real-Time
-            cpu.cx = 0; cpu.dx = 0;
+            cpu.set_reg8(Register::CH, to_bcd(now.hour()));
+            cpu.set_reg8(Register::CL, to_bcd(now.minute()));
+            cpu.set_reg8(Register::DH, to_bcd(now.second()));
+            cpu.set_reg8(Register::DL, 0); // Standard time
             cpu.set_cpu_flag(CpuFlags::CF, false);
         }
+        0x03 => { // Set Real-Time
+            match bcd_time(cpu) {
+                Some(time) => {
+                    clock::set(&mut cpu.bus, now.date().and_time(time));
+                    cpu.set_cpu_flag(CpuFlags::CF, false);
+                }
+                None => cpu.set_cpu_flag(CpuFlags::CF, true),
+            }
+        }
         0x04 => { // Get Date
-            cpu.cx = 0x2000; cpu.dx = 0x0101;
+            let year = now.year() as u32;
+            cpu.set_reg8(Register::CH, to_bcd(year / 100));
+            cpu.set_reg8(Register::CL, to_bcd(year % 100));
+            cpu.set_reg8(Register::DH, to_bcd(now.month()));
+            cpu.set_reg8(Register::DL, to_bcd(now.day()));
+            cpu.set_cpu_flag(CpuFlags::CF, false);
+        }
+        0x05 => { // Set Date
+            let date = (|| {
+                let year = from_bcd(cpu.get_reg8(Register::CH))? * 100
+                    + from_bcd(cpu.get_reg8(Register::CL))?;
+                let month = from_bcd(cpu.get_reg8(Register::DH))?;
+                let day = from_bcd(cpu.get_reg8(Register::DL))?;
+                NaiveDate::from_ymd_opt(year as i32, month, day)
+            })();
+            match date {
+                Some(date) => {
+                    clock::set(&mut cpu.bus, date.and_time(now.time()));
+                    cpu.set_cpu_flag(CpuFlags::CF, false);
+                }
+                None => cpu.set_cpu_flag(CpuFlags::CF, true),
+            }
+        }
+        0x06 => { // Set Alarm; fails if one is already set. INT 4Ah goes off.
+            match bcd_time(cpu) {
+                Some(time) if !cpu.bus.rtc().alarm_enabled() => {
+                    cpu.bus.rtc_mut().set_alarm(Some(time));
+                    cpu.bus.pic_mut().slave_mask &= !(1 << (RTC_IRQ - 8));
+                    cpu.set_cpu_flag(CpuFlags::CF, false);
+                }
+                _ => cpu.set_cpu_flag(CpuFlags::CF, true),
+            }
+        }
+        0x07 => { // Reset Alarm
+            cpu.bus.rtc_mut().set_alarm(None);
             cpu.set_cpu_flag(CpuFlags::CF, false);
         }
         _ => cpu.bus.log_string(&format!("[BIOS] Unhandled INT 1A AH={:02X}", ah)),
     }
-}
\ No newline at end of file
+}
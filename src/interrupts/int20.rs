@@ -6,6 +6,11 @@ pub fn handle(cpu: &mut Cpu) {
     // This simply signals the main loop to reload the shell.
 
     cpu.bus.log_string("[INT20] Program Terminated.");
+    cpu.exit_code = Some(0);
+    crate::handles::close_all(&mut cpu.bus, cpu.current_psp);
+    crate::fcb::close_all(&mut cpu.bus, cpu.current_psp);
+    crate::mcb::free_owned(&mut cpu.bus, cpu.current_psp);
+    cpu.restore_exit_vectors();
 
     if cpu.restore_process_context() {
         cpu.bus.log_string("[INT20] Returning to Parent Process");
@@ -1,12 +1,28 @@
-use chrono::{Local, Timelike};
+use chrono::{Datelike, NaiveDate, NaiveTime, Timelike};
 use iced_x86::Register;
 
+use super::int24;
 use super::utils::{pattern_to_fcb, read_asciiz_string, read_dta_template};
 use crate::audio::play_sdl_beep;
+use crate::clock;
 use crate::cpu::{Cpu, CpuFlags, CpuState};
+use crate::disk;
+use crate::errors;
+use crate::fcb;
+use crate::handles;
+use crate::mcb;
+use crate::nls;
 use crate::video::print_char;
 
 pub fn handle(cpu: &mut Cpu) {
+    let caller = int24::Caller::save(cpu);
+    dispatch(cpu);
+    if let Some(error) = cpu.bus.errors.pending.take() {
+        int24::raise(cpu, &caller, error);
+    }
+}
+
+fn dispatch(cpu: &mut Cpu) {
     let ah = cpu.get_ah();
     match ah {
         // AH = 0Eh: Select Default Drive
@@ -21,6 +37,11 @@ pub fn handle(cpu: &mut Cpu) {
         0x00 => {
             cpu.bus
                 .log_string("[DOS] Program Terminated (Legacy INT 20h/21h AH=00).");
+            cpu.exit_code = Some(0);
+            handles::close_all(&mut cpu.bus, cpu.current_psp);
+            fcb::close_all(&mut cpu.bus, cpu.current_psp);
+            mcb::free_owned(&mut cpu.bus, cpu.current_psp);
+            cpu.restore_exit_vectors();
 
             if cpu.restore_process_context() {
                 cpu.bus
@@ -90,13 +111,51 @@ pub fn handle(cpu: &mut Cpu) {
                     cpu.bus.write_16(dta_phys + 0x14, entry.dos_date);
                     cpu.bus.write_32(dta_phys + 0x10, entry.size);
                 }
-                Err(_) => {
+                Err(code) => {
                     // Failure: AL=FFh
+                    errors::record(&mut cpu.bus, code);
                     cpu.set_reg8(Register::AL, 0xFF);
                 }
             }
         }
 
+        // FCB file functions; DS:DX = FCB, AL = result
+        0x0F | 0x10 | 0x13..=0x17 | 0x21..=0x24 => {
+            let addr = cpu.get_physical_addr(cpu.ds, cpu.dx);
+            let psp = cpu.current_psp;
+            let bus = &mut cpu.bus;
+            let result = match ah {
+                0x0F => fcb::open(bus, addr, psp),
+                0x10 => fcb::close(bus, addr),
+                0x13 => fcb::delete(bus, addr),
+                0x14 => fcb::read_sequential(bus, addr),
+                0x15 => fcb::write_sequential(bus, addr),
+                0x16 => fcb::create(bus, addr, psp),
+                0x17 => fcb::rename(bus, addr),
+                0x21 => fcb::read_random(bus, addr),
+                0x22 => fcb::write_random(bus, addr),
+                0x23 => fcb::file_size(bus, addr),
+                _ => {
+                    // 24h: Set Random Record leaves AL alone
+                    fcb::set_random(bus, addr);
+                    return;
+                }
+            };
+            cpu.set_reg8(Register::AL, result);
+        }
+
+        // AH = 27h/28h: Random Block Read/Write, CX = records
+        0x27 | 0x28 => {
+            let addr = cpu.get_physical_addr(cpu.ds, cpu.dx);
+            let (result, count) = if ah == 0x27 {
+                fcb::read_block(&mut cpu.bus, addr, cpu.cx)
+            } else {
+                fcb::write_block(&mut cpu.bus, addr, cpu.cx)
+            };
+            cpu.set_reg8(Register::AL, result);
+            cpu.cx = count;
+        }
+
         // AH = 02h: Output Character (DL = Char)
         0x02 => {
             let char_byte = cpu.get_dl();
@@ -148,15 +207,12 @@ pub fn handle(cpu: &mut Cpu) {
             } else {
                 // Retry logic
                 // Calculate Physical Address of the Stack Pointer (SS:SP)
-                let sp = cpu.sp;
-                let ss = cpu.ss;
-                let phys_sp = (ss as usize * 16) + sp as usize; // TODO: Check phys addr calc
+                let phys_sp = cpu.get_physical_addr(cpu.ss, cpu.sp);
 
-                let saved_ip = cpu.bus.read_16(phys_sp & 0xFFFFF);
+                let saved_ip = cpu.bus.read_16(phys_sp);
 
                 // Substract 4 and make the CPU re-execute the trap instruction after returning.
-                cpu.bus
-                    .write_16(phys_sp & 0xFFFFF, saved_ip.wrapping_sub(4));
+                cpu.bus.write_16(phys_sp, saved_ip.wrapping_sub(4));
             }
         }
 
@@ -500,68 +556,96 @@ pub fn handle(cpu: &mut Cpu) {
                         (filename.clone(), cmd_tail.clone())
                     };
 
-                // Allocate memory for new process using heap_pointer
-                // Align to next paragraph if needed (heap_pointer is already paragraph)
-                let load_segment = cpu.heap_pointer;
-
-                if cpu.load_executable(&target_filename, Some(load_segment)) {
-                    let psp_phys = cpu.get_physical_addr(load_segment, 0);
+                // The parent sleeps until the child terminates.
+                // Loading reinstalls the BIOS vectors; the child inherits the parent's
+                // INT 22h-24h instead and its PSP keeps them for the way back.
+                let exit_vectors = cpu.exit_vectors();
+                cpu.save_process_context();
+                let parent_psp = cpu.current_psp;
+                let strategy = cpu.bus.alloc_strategy;
+
+                // Environment first, then the largest block left goes to the program
+                let env_paras = env_block.len().div_ceil(16) as u16;
+                let blocks = mcb::allocate(&mut cpu.bus, env_paras, parent_psp, strategy)
+                    .and_then(|env_seg| {
+                        let largest = mcb::largest_free(&mut cpu.bus);
+                        match mcb::allocate(&mut cpu.bus, largest, parent_psp, mcb::FIRST_FIT) {
+                            Ok(load_segment) => Ok((env_seg, load_segment)),
+                            Err(e) => {
+                                let _ = mcb::free(&mut cpu.bus, env_seg);
+                                Err(e)
+                            }
+                        }
+                    });
+
+                match blocks {
+                    Ok((env_seg, load_segment))
+                        if cpu.load_executable(&target_filename, Some(load_segment)) =>
+                    {
+                        let psp_phys = cpu.get_physical_addr(load_segment, 0);
+                        cpu.set_exit_vectors(exit_vectors);
+                        cpu.save_exit_vectors(load_segment, exit_vectors);
+
+                        // Write Environment Block; the child owns it
+                        mcb::set_owner(&mut cpu.bus, env_seg, load_segment);
+                        let env_phys_dest = cpu.get_physical_addr(env_seg, 0);
+                        for (i, &b) in env_block.iter().enumerate() {
+                            cpu.bus.write_8(env_phys_dest + i, b);
+                        }
 
-                    // Write Environment Block
-                    let env_seg = cpu.heap_pointer;
-                    let env_paras = (env_block.len() + 15) / 16;
-                    // Increment heap
-                    cpu.heap_pointer += env_paras as u16 + 1; // +1 safety
+                        // Update PSP offset 0x2C (Environment Segment)
+                        cpu.bus.write_16(psp_phys + 0x2C, env_seg);
 
-                    let env_phys_dest = cpu.get_physical_addr(env_seg, 0);
-                    for (i, &b) in env_block.iter().enumerate() {
-                        cpu.bus.write_8(env_phys_dest + i, b);
-                    }
+                        // Update PSP offset 0x16 (Parent PSP Segment)
+                        cpu.bus.write_16(psp_phys + 0x16, parent_psp);
 
-                    // Now load program at *new* heap pointer
-                    let new_env_seg = 0x0C00;
-                    let new_env_phys = cpu.get_physical_addr(new_env_seg, 0);
-                    for (i, &b) in env_block.iter().enumerate() {
-                        cpu.bus.write_8(new_env_phys + i, b);
+                        // Write Command Tail to 80h
+                        // target_cmd_tail_bytes does NOT include CR, logic below adds it.
+                        cpu.bus
+                            .write_8(psp_phys + 0x80, target_cmd_tail_bytes.len() as u8);
+                        for (i, &b) in target_cmd_tail_bytes.iter().enumerate() {
+                            cpu.bus.write_8(psp_phys + 0x81 + i, b);
+                        }
+                        // Ensure CR at end
+                        cpu.bus
+                            .write_8(psp_phys + 0x81 + target_cmd_tail_bytes.len(), 0x0D);
+
+                        // We do NOT set CF=0 because we don't return to the caller yet!
+                        // The caller is suspended. The IRET that ends this call enters the child.
+                        let flags = cpu.get_cpu_flags().bits();
+                        cpu.push(flags);
+                        cpu.push(cpu.cs);
+                        cpu.push(cpu.ip);
                     }
-
-                    // Update PSP offset 0x2C (Environment Segment)
-                    cpu.bus.write_16(psp_phys + 0x2C, new_env_seg);
-
-                    // Update PSP offset 0x16 (Parent PSP Segment)
-                    let parent_psp = cpu.current_psp;
-                    cpu.bus.write_16(psp_phys + 0x16, parent_psp);
-
-                    // Write Command Tail to 80h
-                    // target_cmd_tail_bytes does NOT include CR, logic below adds it.
-                    cpu.bus
-                        .write_8(psp_phys + 0x80, target_cmd_tail_bytes.len() as u8);
-                    for (i, &b) in target_cmd_tail_bytes.iter().enumerate() {
-                        cpu.bus.write_8(psp_phys + 0x81 + i, b);
+                    blocks => {
+                        // Fail
+                        let error = match blocks {
+                            Ok((env_seg, load_segment)) => {
+                                let _ = mcb::free(&mut cpu.bus, load_segment);
+                                let _ = mcb::free(&mut cpu.bus, env_seg);
+                                if cpu.bus.disk.resolve_path(&target_filename).is_some() {
+                                    mcb::ERR_INSUFFICIENT_MEMORY
+                                } else {
+                                    0x02 // File not found
+                                }
+                            }
+                            Err((error, _)) => error,
+                        };
+                        cpu.set_exit_vectors(exit_vectors);
+                        cpu.restore_process_context(); // Restore parent immediately
+                        finish(cpu, Err(error));
                     }
-                    // Ensure CR at end
-                    cpu.bus
-                        .write_8(psp_phys + 0x81 + target_cmd_tail_bytes.len(), 0x0D);
-
-                    // We do NOT set CF=0 because we don't return to the caller yet!
-                    // The caller is suspended.
-                } else {
-                    // Fail
-                    cpu.restore_process_context(); // Restore parent immediately
-                    cpu.set_cpu_flag(CpuFlags::CF, true);
-                    cpu.set_reg16(Register::AX, 0x02); // File not found
                 }
             } else {
                 cpu.bus.log_string("[DOS] EXEC Unsupported Mode");
-                cpu.set_cpu_flag(CpuFlags::CF, true);
-                cpu.set_reg16(Register::AX, 0x01); // Invalid function
+                finish(cpu, Err(0x01)); // Invalid function
             }
         }
 
         // AH = 2Ch: Get System Time
         // Returns: CH=Hour, CL=Minute, DH=Second, DL=1/100s
         0x2C => {
-            let now = Local::now();
+            let now = cpu.bus.clock().now();
 
             let hour = now.hour() as u8;
             let minute = now.minute() as u8;
@@ -575,6 +659,54 @@ pub fn handle(cpu: &mut Cpu) {
             cpu.set_reg8(Register::DL, hundredths);
         }
 
+        // AH = 2Dh: Set Time (CH:CL:DH.DL); AL = FFh if invalid
+        0x2D => {
+            let time = NaiveTime::from_hms_milli_opt(
+                cpu.get_reg8(Register::CH) as u32,
+                cpu.get_reg8(Register::CL) as u32,
+                cpu.get_reg8(Register::DH) as u32,
+                cpu.get_dl() as u32 * 10,
+            )
+            .filter(|_| cpu.get_dl() < 100);
+            let result = match time {
+                Some(time) => {
+                    let date = cpu.bus.clock().now().date();
+                    clock::set(&mut cpu.bus, date.and_time(time));
+                    0x00
+                }
+                None => 0xFF,
+            };
+            cpu.set_reg8(Register::AL, result);
+        }
+
+        // AH = 2Ah: Get Date; AL = day of the week, 0 = Sunday
+        0x2A => {
+            let now = cpu.bus.clock().now();
+            cpu.cx = now.year() as u16;
+            cpu.set_reg8(Register::DH, now.month() as u8);
+            cpu.set_reg8(Register::DL, now.day() as u8);
+            cpu.set_reg8(Register::AL, now.weekday().num_days_from_sunday() as u8);
+        }
+
+        // AH = 2Bh: Set Date (CX = year, DH = month, DL = day), 1980 to 2099
+        0x2B => {
+            let date = NaiveDate::from_ymd_opt(
+                cpu.cx as i32,
+                cpu.get_reg8(Register::DH) as u32,
+                cpu.get_dl() as u32,
+            )
+            .filter(|_| (1980..=2099).contains(&cpu.cx));
+            let result = match date {
+                Some(date) => {
+                    let time = cpu.bus.clock().now().time();
+                    clock::set(&mut cpu.bus, date.and_time(time));
+                    0x00
+                }
+                None => 0xFF,
+            };
+            cpu.set_reg8(Register::AL, result);
+        }
+
         // AH=2Fh: Get DTA Address
         0x2F => {
             cpu.es = cpu.bus.dta_segment;
@@ -600,26 +732,19 @@ pub fn handle(cpu: &mut Cpu) {
                 "[DOS] TSR Terminate (AH=31h) Code={:02X} Paras={:04X} PSP={:04X}",
                 return_code, paras_to_keep, tsr_psp
             ));
+            cpu.exit_code = Some(return_code);
 
-            // Calculate where the resident block ends
-            let resident_end = tsr_psp.wrapping_add(paras_to_keep);
+            // The PSP block shrinks to what stays resident; other blocks are kept.
+            // DOS never leaves less than the PSP itself.
+            if let Err((error, _)) = mcb::resize(&mut cpu.bus, tsr_psp, paras_to_keep.max(6)) {
+                cpu.bus
+                    .log_string(&format!("[DOS] TSR: Resize failed, error {:02X}", error));
+            }
+            // A TSR keeps its memory, but INT 22h-24h still go back to the parent's
+            cpu.restore_exit_vectors();
 
             if cpu.restore_process_context() {
-                cpu.bus.log_string(&format!(
-                    "[DOS] TSR: Returning to Parent. Resident End={:04X}",
-                    resident_end
-                ));
-
-                // TSR Logic: Ensure the heap pointer respects the resident memory.
-                // If the parent's heap pointer is "behind" the resident block, bump it forward.
-                if cpu.heap_pointer < resident_end {
-                    cpu.bus.log_string(&format!(
-                        "[DOS] TSR: Bumping Heap Pointer from {:04X} to {:04X}",
-                        cpu.heap_pointer, resident_end
-                    ));
-                    cpu.heap_pointer = resident_end;
-                }
-
+                cpu.bus.log_string("[DOS] TSR: Returning to Parent");
                 cpu.ax = return_code as u16; // Set return code (AL)
                 cpu.set_cpu_flag(CpuFlags::CF, false);
             } else {
@@ -674,18 +799,37 @@ pub fn handle(cpu: &mut Cpu) {
             }
         }
 
+        // AH=38h: Get/Set Country Information
+        // AL = country code, or FFh with it in BX; 00h for the current one.
+        // DX = FFFFh sets the country, otherwise DS:DX gets its information.
+        0x38 => {
+            let code = match cpu.get_al() {
+                0x00 => cpu.bus.nls.country,
+                0xFF => cpu.bx,
+                al => al as u16,
+            };
+            let result = if cpu.dx == 0xFFFF {
+                nls::set_country(&mut cpu.bus, code)
+            } else {
+                let addr = cpu.get_physical_addr(cpu.ds, cpu.dx);
+                nls::write_country_info(&mut cpu.bus, addr, code)
+            };
+            if result.is_ok() {
+                cpu.bx = code;
+            }
+            finish_unit(cpu, result);
+        }
+
         // AH=39h: Create Directory (MKDIR)
         0x39 => {
             // TODO: Implement MKDIR
-            cpu.set_cpu_flag(CpuFlags::CF, true);
-            cpu.ax = 0x03; // Path not found (stub)
+            finish(cpu, Err(0x03)); // Path not found (stub)
         }
 
         // AH=3Ah: Remove Directory (RMDIR)
         0x3A => {
             // TODO: Implement RMDIR
-            cpu.set_cpu_flag(CpuFlags::CF, true);
-            cpu.ax = 0x03;
+            finish(cpu, Err(0x03));
         }
 
         // AH=3Bh: Set Current Directory (CHDIR)
@@ -695,8 +839,7 @@ pub fn handle(cpu: &mut Cpu) {
             if cpu.bus.disk.set_current_directory(&path) {
                 cpu.set_cpu_flag(CpuFlags::CF, false);
             } else {
-                cpu.set_cpu_flag(CpuFlags::CF, true);
-                cpu.ax = 0x03; // Path not found
+                finish(cpu, Err(0x03)); // Path not found
             }
         }
 
@@ -704,18 +847,9 @@ pub fn handle(cpu: &mut Cpu) {
         0x3C => {
             let addr = cpu.get_physical_addr(cpu.ds, cpu.dx);
             let filename = read_asciiz_string(&cpu.bus, addr);
-            // Attributes in CX are ignored for now (TODO)
-            match cpu.bus.disk.open_file(&filename, 0x02) {
-                // 0x02 = Read/Write + Create
-                Ok(handle) => {
-                    cpu.ax = handle;
-                    cpu.set_cpu_flag(CpuFlags::CF, false);
-                }
-                Err(code) => {
-                    cpu.ax = code as u16;
-                    cpu.set_cpu_flag(CpuFlags::CF, true);
-                }
-            }
+            let result = cpu.bus.disk.create_file(&filename, 0x02, cpu.cx, false);
+            let result = result.and_then(|sft| attach_handle(cpu, sft));
+            finish(cpu, result);
         }
 
         // AH=3Dh: Open File
@@ -729,28 +863,24 @@ pub fn handle(cpu: &mut Cpu) {
                 filename, mode
             ));
 
-            match cpu.bus.disk.open_file(&filename, mode) {
-                Ok(handle) => {
-                    cpu.ax = handle;
-                    cpu.bus
-                        .log_string(&format!("[DEBUG] Open Success, Handle={:04X}", handle));
-                    // In real CPU, clear CF here
-                    cpu.set_cpu_flag(CpuFlags::CF, false);
-                }
-                Err(code) => {
-                    cpu.ax = code as u16;
-                    // In real CPU, set CF here
-                    cpu.bus
-                        .log_string(&format!("[DEBUG] Open Failed, Error={:04X}", code));
-                    cpu.set_cpu_flag(CpuFlags::CF, true);
-                }
+            let result = open_handle(cpu, &filename, mode);
+            match result {
+                Ok(handle) => cpu
+                    .bus
+                    .log_string(&format!("[DEBUG] Open Success, Handle={:04X}", handle)),
+                Err(code) => cpu
+                    .bus
+                    .log_string(&format!("[DEBUG] Open Failed, Error={:04X}", code)),
             }
+            finish(cpu, result);
         }
 
         // AH = 3Eh: Close File
         0x3E => {
-            let handle = cpu.bx;
-            cpu.bus.disk.close_file(handle);
+            match handles::close(&mut cpu.bus, cpu.current_psp, cpu.bx) {
+                Ok(()) => cpu.set_cpu_flag(CpuFlags::CF, false),
+                Err(e) => finish(cpu, Err(e)),
+            }
         }
 
         // AH = 3Fh: Read from File (or Stdin)
@@ -764,22 +894,23 @@ pub fn handle(cpu: &mut Cpu) {
                 handle, count
             ));
 
-            if handle == 0 {
-                // STDIN
-                let mut read_count = 0;
-                for _ in 0..count {
-                    if let Some(key) = cpu.bus.keyboard_buffer.pop_front() {
-                        cpu.bus.write_8(buf_addr, (key & 0xFF) as u8);
-                        buf_addr += 1;
-                        read_count += 1;
-                    } else {
-                        break;
+            match handles::sft_index(&cpu.bus, cpu.current_psp, handle) {
+                Ok(disk::SFT_CON) => {
+                    // STDIN
+                    let mut read_count = 0;
+                    for _ in 0..count {
+                        if let Some(key) = cpu.bus.keyboard_buffer.pop_front() {
+                            cpu.bus.write_8(buf_addr, (key & 0xFF) as u8);
+                            buf_addr += 1;
+                            read_count += 1;
+                        } else {
+                            break;
+                        }
                     }
+                    cpu.ax = read_count as u16;
+                    cpu.set_cpu_flag(CpuFlags::CF, false);
                 }
-                cpu.ax = read_count as u16;
-                cpu.set_cpu_flag(CpuFlags::CF, false);
-            } else {
-                match cpu.bus.disk.read_file(handle, count) {
+                Ok(sft) => match cpu.bus.disk.read_file(sft, count) {
                     Ok(bytes) => {
                         for b in &bytes {
                             cpu.bus.write_8(buf_addr, *b);
@@ -791,10 +922,10 @@ pub fn handle(cpu: &mut Cpu) {
                     Err(e) => {
                         cpu.bus
                             .log_string(&format!("[DEBUG] Read Failed, Error={:04X}", e));
-                        cpu.ax = e;
-                        cpu.set_cpu_flag(CpuFlags::CF, true);
+                        finish(cpu, Err(e as u8));
                     }
-                }
+                },
+                Err(e) => finish(cpu, Err(e)),
             }
         }
 
@@ -814,29 +945,28 @@ pub fn handle(cpu: &mut Cpu) {
                 data.push(cpu.bus.read_8(buf_addr + i));
             }
 
-            if handle == 1 || handle == 2 {
-                // STDOUT/STDERR
-                for &byte in &data {
-                    if byte == 0x07 {
-                        play_sdl_beep(&mut cpu.bus);
+            let result = match handles::sft_index(&cpu.bus, cpu.current_psp, handle) {
+                Ok(disk::SFT_CON) => {
+                    // STDOUT/STDERR
+                    for &byte in &data {
+                        if byte == 0x07 {
+                            play_sdl_beep(&mut cpu.bus);
+                        }
                     }
-                }
-                let s = String::from_utf8_lossy(&data);
-                // Log what is being printed to stdout
-                cpu.bus.log_string(&format!("[STDOUT] {}", s.trim()));
+                    let s = String::from_utf8_lossy(&data);
+                    // Log what is being printed to stdout
+                    cpu.bus.log_string(&format!("[STDOUT] {}", s.trim()));
 
-                let visual_s = s.replace('\x07', "");
-                crate::video::print_string(cpu, &visual_s);
-                cpu.ax = count as u16;
-            } else {
-                match &mut cpu.bus.disk.write_file(handle, &data) {
-                    Ok(written) => cpu.ax = *written,
-                    Err(_) => {
-                        cpu.bus.log_string("[DEBUG] Write Failed");
-                        cpu.ax = 0
-                    }
+                    let visual_s = s.replace('\x07', "");
+                    crate::video::print_string(cpu, &visual_s);
+                    Ok(count as u16)
                 }
-            }
+                Ok(sft) => cpu.bus.disk.write_file(sft, &data).inspect_err(|_| {
+                    cpu.bus.log_string("[DEBUG] Write Failed");
+                }),
+                Err(e) => Err(e),
+            };
+            finish(cpu, result);
         }
 
         // AH = 42h: Move File Pointer
@@ -847,28 +977,44 @@ pub fn handle(cpu: &mut Cpu) {
             let offset = ((offset_high << 16) | offset_low) as i32;
             let whence = cpu.get_al();
 
-            match cpu.bus.disk.seek_file(handle, offset as i64, whence) {
+            let result = handles::sft_index(&cpu.bus, cpu.current_psp, handle)
+                .map_err(u16::from)
+                .and_then(|sft| cpu.bus.disk.seek_file(sft, offset as i64, whence));
+            match result {
                 Ok(new_pos) => {
                     cpu.dx = ((new_pos >> 16) & 0xFFFF) as u16;
                     cpu.ax = (new_pos & 0xFFFF) as u16;
                     cpu.set_cpu_flag(CpuFlags::CF, false);
                 }
-                Err(e) => {
-                    cpu.ax = e;
-                    cpu.set_cpu_flag(CpuFlags::CF, true);
-                }
+                Err(e) => finish(cpu, Err(e as u8)),
             }
         }
 
+        // AH=41h: Delete File
+        0x41 => {
+            let addr = cpu.get_physical_addr(cpu.ds, cpu.dx);
+            let filename = read_asciiz_string(&cpu.bus, addr);
+            let result = cpu.bus.disk.delete_file(&filename);
+            finish_unit(cpu, result);
+        }
+
         // AH=43h: Get/Set File Attributes
         0x43 => {
-            let al = cpu.get_reg8(Register::AL);
-            if al == 0x00 {
-                cpu.set_reg16(Register::CX, 0x20); // Archive
-                cpu.set_cpu_flag(CpuFlags::CF, false);
-            } else {
-                cpu.set_cpu_flag(CpuFlags::CF, false);
-            }
+            let addr = cpu.get_physical_addr(cpu.ds, cpu.dx);
+            let filename = read_asciiz_string(&cpu.bus, addr);
+            let result = match cpu.get_al() {
+                0x00 => cpu
+                    .bus
+                    .disk
+                    .get_file_attribute(&filename)
+                    .inspect(|&attributes| cpu.cx = attributes),
+                0x01 => {
+                    let ax = cpu.ax;
+                    cpu.bus.disk.set_file_attribute(&filename, cpu.cx).map(|()| ax)
+                }
+                _ => Err(0x01),
+            };
+            finish(cpu, result);
         }
 
         // AH = 44h: IOCTL (I/O Control)
@@ -886,7 +1032,14 @@ pub fn handle(cpu: &mut Cpu) {
                 0x00 => {
                     // Bit 7=1 (Char Dev), Bit 6=0 (EOF), Bit 0=1 (Console Input)
                     // For STDIN(0), STDOUT(1), STDERR(2), return 0x80D3 or similar.
-                    if bx <= 2 {
+                    let sft = match handles::sft_index(&cpu.bus, cpu.current_psp, bx) {
+                        Ok(sft) => sft,
+                        Err(e) => {
+                            finish(cpu, Err(e));
+                            return;
+                        }
+                    };
+                    if sft == disk::SFT_CON {
                         // 1000 0000 1101 0011 = 80D3
                         // Bit 7: Char device
                         // Bit 6: EOF (0) - meaningful for files?
@@ -897,6 +1050,8 @@ pub fn handle(cpu: &mut Cpu) {
                         // Bit 1: Stdout
                         // Bit 0: Stdin
                         cpu.dx = 0x80D3;
+                    } else if sft == disk::SFT_AUX || sft == disk::SFT_PRN {
+                        cpu.dx = 0x80C0; // Char device, not EOF
                     } else {
                         // File: Bit 7=0 (Block Dev), Bits 0-5 = Drive #
                         cpu.dx = 0x0002; // Drive C
@@ -917,6 +1072,20 @@ pub fn handle(cpu: &mut Cpu) {
                 }
             }
         }
+        // AH = 45h: Duplicate Handle (DUP)
+        0x45 => {
+            let result = handles::dup(&mut cpu.bus, cpu.current_psp, cpu.bx);
+            finish(cpu, result);
+        }
+
+        // AH = 46h: Force Duplicate Handle (DUP2): CX becomes a copy of BX
+        0x46 => {
+            match handles::force_dup(&mut cpu.bus, cpu.current_psp, cpu.bx, cpu.cx) {
+                Ok(()) => cpu.set_cpu_flag(CpuFlags::CF, false),
+                Err(e) => finish(cpu, Err(e)),
+            }
+        }
+
         // AH=47h: Get Current Directory
         0x47 => {
             let dl = cpu.get_dl(); // Drive (0=Default, 1=A, ...)
@@ -949,29 +1118,25 @@ pub fn handle(cpu: &mut Cpu) {
         // Return: AX = Segment, or CF=1 + AX=Error, BX=Max Available
         0x48 => {
             let requested_paras = cpu.bx;
-            let available_paras = if cpu.heap_pointer < 0xA000 {
-                0xA000 - cpu.heap_pointer
-            } else {
-                0
-            };
-
-            cpu.bus.log_string(&format!(
-                "[DEBUG] Alloc Mem: Request {:04X} paras. Heap at {:04X}, Avail {:04X}",
-                requested_paras, cpu.heap_pointer, available_paras
-            ));
-
-            if requested_paras > available_paras {
-                cpu.ax = 0x0008; // Insufficient memory
-                cpu.bx = available_paras;
-                cpu.set_cpu_flag(CpuFlags::CF, true);
-                cpu.bus
-                    .log_string("[DEBUG] Alloc Failed: Insufficient Memory");
-            } else {
-                cpu.ax = cpu.heap_pointer;
-                cpu.heap_pointer += requested_paras;
-                cpu.set_cpu_flag(CpuFlags::CF, false);
-                cpu.bus
-                    .log_string(&format!("[DEBUG] Alloc Success: {:04X}", cpu.ax));
+            let owner = cpu.current_psp;
+            let strategy = cpu.bus.alloc_strategy;
+            match mcb::allocate(&mut cpu.bus, requested_paras, owner, strategy) {
+                Ok(segment) => {
+                    cpu.bus.log_string(&format!(
+                        "[DOS] Alloc Mem: {:04X} paras at {:04X}",
+                        requested_paras, segment
+                    ));
+                    cpu.ax = segment;
+                    cpu.set_cpu_flag(CpuFlags::CF, false);
+                }
+                Err((error, largest)) => {
+                    cpu.bus.log_string(&format!(
+                        "[DOS] Alloc Mem: {:04X} paras failed, largest {:04X}",
+                        requested_paras, largest
+                    ));
+                    cpu.bx = largest;
+                    finish(cpu, Err(error));
+                }
             }
         }
 
@@ -979,35 +1144,33 @@ pub fn handle(cpu: &mut Cpu) {
         // ES = Segment of the block to be freed
         0x49 => {
             let segment_to_free = cpu.es;
-
-            // TODO: Replace this stub by actually marking the memory block in the MCB chain as free.
-
             cpu.bus.log_string(&format!(
                 "[DOS] Freeing Memory Block at {:04X}",
                 segment_to_free
             ));
 
-            // Return Success
-            cpu.set_cpu_flag(CpuFlags::CF, false);
-            cpu.ax = 0;
+            let result = mcb::free(&mut cpu.bus, segment_to_free);
+            finish_unit(cpu, result);
         }
 
         // AH = 4Ah: Resize Memory Block
+        // ES = Segment of the block, BX = New size in paragraphs
+        // Return: CF=1 + AX=Error, BX=Max size for this block
         0x4A => {
             let requested_size = cpu.get_reg16(Register::BX);
-            let max_available = 0x9000; // Simulated available paragraphs
-
             cpu.bus.log_string(&format!(
-                "[DEBUG] INT 21,4A Resize: Req {:04X}, Max {:04X}",
-                requested_size, max_available
+                "[DOS] Resize {:04X} to {:04X} paras",
+                cpu.es, requested_size
             ));
 
-            if requested_size > max_available {
-                cpu.set_reg16(Register::BX, max_available);
-                cpu.set_reg16(Register::AX, 0x0008);
-                cpu.set_cpu_flag(CpuFlags::CF, true);
-            } else {
-                cpu.set_cpu_flag(CpuFlags::CF, false);
+            match mcb::resize(&mut cpu.bus, cpu.es, requested_size) {
+                Ok(()) => cpu.set_cpu_flag(CpuFlags::CF, false),
+                Err((error, max_available)) => {
+                    if error == mcb::ERR_INSUFFICIENT_MEMORY {
+                        cpu.bx = max_available;
+                    }
+                    finish(cpu, Err(error));
+                }
             }
         }
 
@@ -1018,21 +1181,7 @@ pub fn handle(cpu: &mut Cpu) {
                 "[DOS] Program Terminated (INT 21h, 4Ch). ExitCode={:02X}",
                 exit_code
             ));
-
-            // Try to restore parent process
-            if cpu.restore_process_context() {
-                cpu.bus.log_string("[DOS] Returning to Parent Process");
-                // TODO: Set Return Code in Parent's AX?
-                // DOS convention: AL = Return Code.
-                // Since we restored the parent context, we should probably update AL in the restored context.
-                // But `restore_process_context` already overwrote registers from stack.
-                // We should update AX *after* restore.
-                cpu.ax = exit_code as u16;
-                // Clear CF to indicate success? Usually EXEC returns with Carry Clear.
-                cpu.set_cpu_flag(CpuFlags::CF, false);
-            } else {
-                cpu.state = CpuState::RebootShell;
-            }
+            terminate(cpu, exit_code);
         }
 
         // AH=4Eh (Find First) / AH=4Fh (Find Next)
@@ -1222,15 +1371,255 @@ pub fn handle(cpu: &mut Cpu) {
                         "[DOS] FindFirst/Next Failed: Pattern='{}' Index={} Error={:02X}",
                         search_pattern, index, code
                     ));
-                    cpu.set_reg16(Register::AX, code as u16);
-                    cpu.set_cpu_flag(CpuFlags::CF, true);
+                    finish(cpu, Err(code));
+                }
+            }
+        }
+
+        // AH=56h: Rename File, DS:DX to ES:DI
+        0x56 => {
+            let addr = cpu.get_physical_addr(cpu.ds, cpu.dx);
+            let old_name = read_asciiz_string(&cpu.bus, addr);
+            let di = cpu.get_reg16(Register::DI);
+            let addr = cpu.get_physical_addr(cpu.es, di);
+            let new_name = read_asciiz_string(&cpu.bus, addr);
+            let result = cpu.bus.disk.rename_file(&old_name, &new_name);
+            finish_unit(cpu, result);
+        }
+
+        // AH=57h: Get/Set File Date and Time (CX=time, DX=date)
+        0x57 => {
+            let result = handles::sft_index(&cpu.bus, cpu.current_psp, cpu.bx).and_then(|sft| {
+                match cpu.get_al() {
+                    0x00 => cpu.bus.disk.file_time(sft).map(|(time, date)| {
+                        cpu.cx = time;
+                        cpu.dx = date;
+                    }),
+                    0x01 => cpu.bus.disk.set_file_time(sft, cpu.cx, cpu.dx),
+                    _ => Err(0x01),
+                }
+            });
+            finish_unit(cpu, result);
+        }
+
+        // AH = 58h: Get/Set Memory Allocation Strategy and UMB Link State
+        0x58 => {
+            let al = cpu.get_al();
+            let bx = cpu.bx;
+            let result = match al {
+                0x00 => {
+                    cpu.ax = cpu.bus.alloc_strategy as u16;
+                    Ok(())
+                }
+                // Fit in the low bits; 40h/80h ask for upper memory only/first
+                0x01 if bx & 0xFF3C == 0 && bx & 0x03 <= 0x02 && bx & 0xC0 != 0xC0 => {
+                    cpu.bus.alloc_strategy = bx as u8;
+                    Ok(())
+                }
+                0x02 => {
+                    cpu.set_reg8(Register::AL, cpu.bus.umb_linked as u8);
+                    Ok(())
+                }
+                // Fails when there are no UMBs to link
+                0x03 if bx <= 1 => mcb::link_upper(&mut cpu.bus, bx == 1),
+                _ => Err(mcb::ERR_INVALID_FUNCTION),
+            };
+            finish_unit(cpu, result);
+        }
+
+        // AH=59h: Get Extended Error Information
+        // AX = code, BH = class, BL = suggested action, CH = locus
+        0x59 => {
+            let error = cpu.bus.errors.last;
+            cpu.ax = error.code as u16;
+            cpu.bx = (error.class as u16) << 8 | error.action as u16;
+            cpu.set_reg8(Register::CH, error.locus);
+        }
+
+        // AH=5Ah: Create Temporary File
+        // DS:DX is the directory, ending in a backslash; the name is appended
+        0x5A => {
+            let addr = cpu.get_physical_addr(cpu.ds, cpu.dx);
+            let dir = read_asciiz_string(&cpu.bus, addr);
+            let result = cpu
+                .bus
+                .disk
+                .create_temp_file(&dir, cpu.cx)
+                .and_then(|(sft, name)| {
+                    let handle = attach_handle(cpu, sft)?;
+                    let mut path = dir.clone();
+                    if !path.is_empty() && !path.ends_with('\\') && !path.ends_with(':') {
+                        path.push('\\');
+                    }
+                    path.push_str(&name);
+                    for (i, byte) in path.bytes().chain([0]).enumerate() {
+                        cpu.bus.write_8(addr + i, byte);
+                    }
+                    Ok(handle)
+                });
+            finish(cpu, result);
+        }
+
+        // AH=5Bh: Create New File, failing if it exists
+        0x5B => {
+            let addr = cpu.get_physical_addr(cpu.ds, cpu.dx);
+            let filename = read_asciiz_string(&cpu.bus, addr);
+            let result = cpu.bus.disk.create_file(&filename, 0x02, cpu.cx, true);
+            let result = result.and_then(|sft| attach_handle(cpu, sft));
+            finish(cpu, result);
+        }
+
+        // AH = 65h: Get Extended Country Information, and upper-casing
+        0x65 => {
+            let al = cpu.get_al();
+            let result = match al {
+                // BX = code page, DX = country (FFFFh for current), ES:DI = buffer of CX bytes
+                0x01..=0x07 => {
+                    let di = cpu.get_reg16(Register::DI);
+                    let addr = cpu.get_physical_addr(cpu.es, di);
+                    nls::write_extended_info(&mut cpu.bus, al, cpu.dx, cpu.bx, addr, cpu.cx)
+                        .map(|len| cpu.cx = len)
                 }
+                // Character in DL
+                0x20 | 0xA0 => {
+                    let c = nls::to_upper(&cpu.bus, cpu.get_reg8(Register::DL));
+                    cpu.set_reg8(Register::DL, c);
+                    Ok(())
+                }
+                // CX bytes at DS:DX, or an ASCIZ string there
+                0x21 | 0xA1 | 0x22 | 0xA2 => {
+                    let addr = cpu.get_physical_addr(cpu.ds, cpu.dx);
+                    let len = if al & 0x0F == 0x01 { cpu.cx as usize } else { usize::MAX };
+                    for i in 0..len {
+                        let c = cpu.bus.read_8(addr + i);
+                        if len == usize::MAX && c == 0 {
+                            break;
+                        }
+                        cpu.bus.write_8(addr + i, nls::to_upper(&cpu.bus, c));
+                    }
+                    Ok(())
+                }
+                // Yes/No character in DL: AX = 0 no, 1 yes, 2 neither
+                0x23 => {
+                    cpu.ax = nls::yes_no(&cpu.bus, cpu.get_reg8(Register::DL));
+                    Ok(())
+                }
+                _ => Err(nls::ERR_INVALID_FUNCTION),
+            };
+            finish_unit(cpu, result);
+        }
+
+        // AH = 66h: Get/Set Global Code Page
+        0x66 => {
+            let result = match cpu.get_al() {
+                0x01 => {
+                    cpu.bx = cpu.bus.nls.code_page;
+                    cpu.dx = cpu.bus.nls.system_code_page;
+                    Ok(())
+                }
+                0x02 => nls::set_code_page(&mut cpu.bus, cpu.bx),
+                _ => Err(nls::ERR_INVALID_FUNCTION),
+            };
+            finish_unit(cpu, result);
+        }
+
+        // AH = 67h: Set Handle Count
+        0x67 => {
+            match handles::set_count(&mut cpu.bus, cpu.current_psp, cpu.bx) {
+                Ok(()) => cpu.set_cpu_flag(CpuFlags::CF, false),
+                Err(e) => finish(cpu, Err(e)),
             }
         }
 
+        // AH = 68h/6Ah: Commit File
+        0x68 | 0x6A => {
+            let result = handles::sft_index(&cpu.bus, cpu.current_psp, cpu.bx)
+                .and_then(|sft| cpu.bus.disk.commit_file(sft));
+            finish_unit(cpu, result);
+        }
+
+        // AX = 6C00h: Extended Open/Create
+        // BL=mode, CX=attributes, DX=action, DS:SI=filename; CX returns what was done
+        0x6C => {
+            let si = cpu.get_reg16(Register::SI);
+            let addr = cpu.get_physical_addr(cpu.ds, si);
+            let filename = read_asciiz_string(&cpu.bus, addr);
+            let result = if cpu.get_al() != 0x00 {
+                Err(0x01)
+            } else {
+                let mode = cpu.get_reg8(Register::BL);
+                cpu.bus
+                    .disk
+                    .extended_open(&filename, mode, cpu.cx, cpu.dx)
+                    .and_then(|(sft, action)| {
+                        let handle = attach_handle(cpu, sft)?;
+                        cpu.cx = action;
+                        Ok(handle)
+                    })
+            };
+            finish(cpu, result);
+        }
+
         _ => {
             cpu.bus
                 .log_string(&format!("[DOS] Unhandled INT 21h AH={:02X}", ah));
         }
     }
 }
+
+// Returns a value in AX with CF clear, or an error code in AX with CF set.
+// Errors are recorded for 59h, critical ones for INT 24h.
+fn finish(cpu: &mut Cpu, result: Result<u16, u8>) {
+    match result {
+        Ok(value) => {
+            cpu.ax = value;
+            cpu.set_cpu_flag(CpuFlags::CF, false);
+        }
+        Err(code) => {
+            errors::record(&mut cpu.bus, code);
+            cpu.ax = code as u16;
+            cpu.set_cpu_flag(CpuFlags::CF, true);
+        }
+    }
+}
+
+// Clears CF, leaving AX alone, or returns an error code in AX with CF set
+fn finish_unit(cpu: &mut Cpu, result: Result<(), u8>) {
+    match result {
+        Ok(()) => cpu.set_cpu_flag(CpuFlags::CF, false),
+        Err(code) => finish(cpu, Err(code)),
+    }
+}
+
+/// Ends the current program, as 4Ch does, and goes back to its parent
+pub fn terminate(cpu: &mut Cpu, exit_code: u8) {
+    cpu.exit_code = Some(exit_code);
+    handles::close_all(&mut cpu.bus, cpu.current_psp);
+    fcb::close_all(&mut cpu.bus, cpu.current_psp);
+    mcb::free_owned(&mut cpu.bus, cpu.current_psp);
+    cpu.restore_exit_vectors();
+
+    // Try to restore parent process
+    if cpu.restore_process_context() {
+        cpu.bus.log_string("[DOS] Returning to Parent Process");
+        // EXEC returns with carry clear and the child's exit code in AL
+        cpu.ax = exit_code as u16;
+        cpu.set_cpu_flag(CpuFlags::CF, false);
+    } else {
+        cpu.state = CpuState::RebootShell;
+    }
+}
+
+// Opens `filename` and gives it a handle in the current process
+fn open_handle(cpu: &mut Cpu, filename: &str, mode: u8) -> Result<u16, u8> {
+    let sft = cpu.bus.disk.open_file(filename, mode)?;
+    attach_handle(cpu, sft)
+}
+
+// Gives a newly opened SFT entry a handle in the current process
+fn attach_handle(cpu: &mut Cpu, sft: u8) -> Result<u16, u8> {
+    handles::allocate(&mut cpu.bus, cpu.current_psp, sft).inspect_err(|_| {
+        // Nothing refers to the new entry yet, so this closes it
+        cpu.bus.disk.release(sft);
+    })
+}
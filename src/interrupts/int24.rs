@@ -0,0 +1,249 @@
+use iced_x86::Register;
+
+use crate::bus::{Bus, HLE_TRAP_BASE};
+use crate::cpu::{Cpu, CpuFlags};
+use crate::errors::{CriticalError, FAIL_ON_INT24, NOT_READY, SHARING_VIOLATION, WRITE_PROTECTED};
+use crate::interrupts::int21;
+use crate::video::print_string;
+
+// Critical error handler (INT 24h).
+//
+// When an INT 21h call runs into a critical error, DOS calls the INT 24h
+// handler before going back to the program, with:
+//
+//   AH     bit 0 write, bits 1-2 disk area, bits 3-5 Fail/Retry/Ignore allowed
+//   AL     drive, 0=A
+//   DI     error: the extended error code less 13h
+//   BP:SI  header of the drive's device driver
+//
+// Above its return frame the stack holds the program's AX, BX, CX, DX, SI,
+// DI, BP, DS and ES and then the frame of the INT 21h call, so a handler can
+// unwind straight to the program. One that returns says what to do in AL.
+//
+// Here the handler returns to a BOP stub that carries out the answer and
+// goes on to the program with an IRET. The default handler asks the user.
+
+/// Answers, in AL
+pub const IGNORE: u8 = 0;
+pub const RETRY: u8 = 1;
+pub const ABORT: u8 = 2;
+pub const FAIL: u8 = 3;
+
+// AH bits
+const WRITE: u8 = 0x01;
+const AREA_DIRECTORY: u8 = 0x04;
+const AREA_DATA: u8 = 0x06;
+const ALLOW_FAIL: u8 = 0x08;
+const ALLOW_RETRY: u8 = 0x10;
+const ALLOW_IGNORE: u8 = 0x20;
+
+const ROM_SEGMENT: u16 = 0xF000;
+// F000:0F28  FE 39 02  Back from the handler
+//            CF        IRET to the program
+const RETURN_STUB: u16 = 0x0F28;
+const DEVICE_HEADER: u16 = 0x0F30;
+
+pub fn install(bus: &mut Bus) {
+    let rom = (ROM_SEGMENT as usize) << 4;
+    bus.write_rom(
+        rom + RETURN_STUB as usize,
+        &[0xFE, 0x39, crate::interrupts::HLE_CALL_CRITICAL_RETURN, 0xCF],
+    );
+
+    // A block device with one unit stands in for the drivers of all drives
+    let routine = DEVICE_HEADER + 0x12;
+    let mut header = Vec::new();
+    header.extend(0xFFFF_FFFFu32.to_le_bytes()); // Next driver: none
+    header.extend(0x0000u16.to_le_bytes()); // Block device
+    header.extend(routine.to_le_bytes()); // Strategy routine
+    header.extend(routine.to_le_bytes()); // Interrupt routine
+    header.push(1); // Units
+    header.extend([0; 7]);
+    header.push(0xCB); // RETF
+    bus.write_rom(rom + DEVICE_HEADER as usize, &header);
+}
+
+/// The program's registers as its INT 21h call found them
+#[derive(Debug, Clone, Copy)]
+pub struct Caller([u16; 9]);
+
+impl Caller {
+    pub fn save(cpu: &Cpu) -> Self {
+        Self([cpu.ax, cpu.bx, cpu.cx, cpu.dx, cpu.si, cpu.di, cpu.bp, cpu.ds, cpu.es])
+    }
+
+    fn restore(&self, cpu: &mut Cpu) {
+        [cpu.ax, cpu.bx, cpu.cx, cpu.dx, cpu.si, cpu.di, cpu.bp, cpu.ds, cpu.es] = self.0;
+    }
+
+    fn function(&self) -> u8 {
+        (self.0[0] >> 8) as u8
+    }
+}
+
+/// Sends a critical error the INT 21h call ran into to the INT 24h handler.
+/// The stack holds the call's IRET frame.
+pub fn raise(cpu: &mut Cpu, caller: &Caller, mut error: CriticalError) {
+    let function = caller.function();
+    // Calls the handler makes itself just fail
+    if cpu.bus.errors.active.is_some() {
+        fail(cpu, function);
+        return;
+    }
+    error.info = info(function, caller.0[0] as u8);
+    cpu.bus.errors.active = Some(error);
+    caller.restore(cpu);
+
+    let frame = cpu.get_physical_addr(cpu.ss, cpu.sp);
+    let flags = cpu.bus.read_16(frame + 4);
+    for &word in caller.0.iter().rev() {
+        cpu.push(word);
+    }
+    // Where the handler returns to, then where the HLE return goes: the handler
+    cpu.push(flags);
+    cpu.push(ROM_SEGMENT);
+    cpu.push(RETURN_STUB);
+    let handler_flags = flags & !(CpuFlags::IF.bits() | CpuFlags::TF.bits());
+    cpu.push(handler_flags);
+    cpu.push(cpu.bus.read_16(0x24 * 4 + 2));
+    cpu.push(cpu.bus.read_16(0x24 * 4));
+
+    cpu.set_reg8(Register::AH, error.info);
+    cpu.set_reg8(Register::AL, error.drive);
+    cpu.di = (error.code - WRITE_PROTECTED) as u16;
+    cpu.bp = ROM_SEGMENT;
+    cpu.si = DEVICE_HEADER;
+}
+
+/// Runs when the handler returns: carries out its answer
+pub fn resume(cpu: &mut Cpu) {
+    let answer = cpu.get_al();
+    let caller = Caller(std::array::from_fn(|_| cpu.pop()));
+    caller.restore(cpu);
+    let function = caller.function();
+
+    let allowed = cpu.bus.errors.active.take().map_or(0, |error| error.info);
+    match answer {
+        IGNORE if allowed & ALLOW_IGNORE != 0 => ignore(cpu, function),
+        RETRY => int21::handle(cpu),
+        ABORT => int21::terminate(cpu, 0),
+        _ => fail(cpu, function),
+    }
+
+    // The call's frame takes the carry, unless the retry went back to the handler
+    if cpu.bus.errors.active.is_none() {
+        let flags_addr = cpu.get_physical_addr(cpu.ss, cpu.sp.wrapping_add(4));
+        let flags = cpu.bus.read_16(flags_addr) & !CpuFlags::CF.bits();
+        let carry = cpu.get_cpu_flag(CpuFlags::CF) as u16 * CpuFlags::CF.bits();
+        cpu.bus.write_16(flags_addr, flags | carry);
+    }
+    // The BOP returns to the IRET after it
+    cpu.push(ROM_SEGMENT);
+    cpu.push(RETURN_STUB + 3);
+}
+
+/// The default handler: asks the user
+pub fn handle(cpu: &mut Cpu) {
+    let Some(mut error) = cpu.bus.errors.active else {
+        cpu.set_reg8(Register::AL, FAIL);
+        return;
+    };
+    if !error.prompted {
+        let operation = if error.info & WRITE != 0 { "writing" } else { "reading" };
+        let choices = if error.info & ALLOW_IGNORE != 0 {
+            "Abort, Retry, Ignore, Fail?"
+        } else {
+            "Abort, Retry, Fail?"
+        };
+        let message = format!(
+            "\r\n{} {} drive {}\r\n{}",
+            describe(error.code),
+            operation,
+            (b'A' + error.drive) as char,
+            choices
+        );
+        print_string(cpu, &message);
+        error.prompted = true;
+        cpu.bus.errors.active = Some(error);
+    }
+
+    let key = cpu
+        .bus
+        .keyboard_buffer
+        .pop_front()
+        .map(|key| (key as u8).to_ascii_uppercase());
+    let answer = match key {
+        Some(b'A') => ABORT,
+        Some(b'R') => RETRY,
+        Some(b'F') => FAIL,
+        Some(b'I') if error.info & ALLOW_IGNORE != 0 => IGNORE,
+        _ => {
+            // Come back to this trap until there's an answer
+            let trap = (HLE_TRAP_BASE + 0x24 * 4) as u16;
+            cpu.push(cpu.get_cpu_flags().bits());
+            cpu.push(ROM_SEGMENT);
+            cpu.push(trap);
+            return;
+        }
+    };
+    print_string(cpu, &format!("{}\r\n", key.unwrap_or_default() as char));
+    cpu.set_reg8(Register::AL, answer);
+}
+
+// AH for the handler. Writes of file data may be ignored; the rest can only
+// be retried or failed.
+fn info(function: u8, al: u8) -> u8 {
+    let data_write = matches!(function, 0x15 | 0x22 | 0x28 | 0x40);
+    let data_read = matches!(function, 0x14 | 0x21 | 0x27 | 0x3F);
+    let directory_write = matches!(
+        function,
+        0x13 | 0x16 | 0x17 | 0x39 | 0x3A | 0x3C | 0x41 | 0x56 | 0x5A | 0x5B
+    ) || (function == 0x43 && al == 0x01);
+
+    let mut info = ALLOW_FAIL | ALLOW_RETRY;
+    if data_write {
+        info |= ALLOW_IGNORE;
+    }
+    if data_write || directory_write {
+        info |= WRITE;
+    }
+    info | if data_write || data_read { AREA_DATA } else { AREA_DIRECTORY }
+}
+
+fn is_fcb_call(function: u8) -> bool {
+    matches!(function, 0x0F..=0x17 | 0x21..=0x24 | 0x27 | 0x28)
+}
+
+// The call fails: FCB calls with AL=FFh, the others with an error in AX
+fn fail(cpu: &mut Cpu, function: u8) {
+    if is_fcb_call(function) {
+        cpu.set_reg8(Register::AL, 0xFF);
+    } else {
+        cpu.ax = FAIL_ON_INT24 as u16;
+        cpu.set_cpu_flag(CpuFlags::CF, true);
+    }
+}
+
+// The write is taken as done, though nothing was written
+fn ignore(cpu: &mut Cpu, function: u8) {
+    if is_fcb_call(function) {
+        cpu.set_reg8(Register::AL, 0x00);
+    } else {
+        cpu.ax = cpu.cx;
+        cpu.set_cpu_flag(CpuFlags::CF, false);
+    }
+}
+
+fn describe(code: u8) -> &'static str {
+    match code {
+        WRITE_PROTECTED => "Write protect error",
+        0x14 => "Invalid unit",
+        NOT_READY => "Not ready",
+        0x17 => "Data error",
+        0x19 => "Seek error",
+        0x1B => "Sector not found",
+        SHARING_VIOLATION => "Sharing violation",
+        0x21 => "Lock violation",
+        _ => "General failure",
+    }
+}
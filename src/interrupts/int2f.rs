@@ -2,6 +2,14 @@ use crate::cpu::Cpu;
 use crate::interrupts::utils::read_asciiz_string;
 
 pub fn handle(cpu: &mut Cpu) {
+    match cpu.get_ah() {
+        0x43 => crate::interrupts::xms::handle_multiplex(cpu),
+        _ => shell_command(cpu),
+    }
+}
+
+// The shell's command line hook (BOP 2Fh with DS:DX = command)
+fn shell_command(cpu: &mut Cpu) {
     // Safety: Clear buffer so we don't repeat commands
     cpu.bus.keyboard_buffer.clear();
 
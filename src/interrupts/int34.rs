@@ -0,0 +1,66 @@
+use crate::cpu::Cpu;
+use crate::instructions::fpu;
+use iced_x86::{Decoder, DecoderOptions, Mnemonic};
+
+// Floating point emulator interrupts (Microsoft / Borland convention).
+// Compilers emit INT 34h-3Bh in place of WAIT + ESC 0-7 (D8-DF), so the runtime can
+// either patch in the real opcode or interpret it:
+//   INT 34h..3Bh  <modrm> [disp]          = D8..DF <modrm> [disp]
+//   INT 3Ch <esc> <modrm> [disp]          = segment override; bits 7-6 of <esc>
+//                                           pick DS/SS/CS/ES, bits 5-0 are the opcode
+//   INT 3Dh                               = FWAIT
+pub fn handle(cpu: &mut Cpu, vector: u8) {
+    // The INT pushed the address of the bytes that follow it
+    let ret_ip_addr = cpu.get_physical_addr(cpu.ss, cpu.sp);
+    let ret_ip = cpu.bus.read_16(ret_ip_addr);
+    let ret_cs = cpu.bus.read_16(cpu.get_physical_addr(cpu.ss, cpu.sp.wrapping_add(2)));
+
+    // Rebuild the instruction: synthesized opcode bytes, then whatever follows in memory
+    let mut code = [0u8; 16];
+    let (synthesized, consumed) = match vector {
+        0x34..=0x3B => {
+            code[0] = 0xD8 + (vector - 0x34);
+            (1, 0)
+        }
+        0x3C => {
+            let esc = cpu.bus.read_8(cpu.get_physical_addr(ret_cs, ret_ip));
+            code[0] = match esc >> 6 {
+                0 => 0x3E, // DS:
+                1 => 0x36, // SS:
+                2 => 0x2E, // CS:
+                _ => 0x26, // ES:
+            };
+            code[1] = esc | 0xC0;
+            (2, 1)
+        }
+        // FWAIT: emulated instructions complete immediately
+        _ => return,
+    };
+
+    for (i, byte) in code.iter_mut().enumerate().skip(synthesized) {
+        let offset = ret_ip.wrapping_add((i - synthesized + consumed) as u16);
+        *byte = cpu.bus.read_8(cpu.get_physical_addr(ret_cs, offset));
+    }
+
+    let instr = Decoder::new(16, &code, DecoderOptions::NONE).decode();
+    if instr.mnemonic() == Mnemonic::INVALID {
+        cpu.bus.log_string(&format!(
+            "[FPEMU] INT {:02X} at {:04X}:{:04X}: can't decode {:02X?}",
+            vector,
+            ret_cs,
+            ret_ip.wrapping_sub(2),
+            &code[..4]
+        ));
+        return;
+    }
+
+    // CS overrides refer to the caller's code segment, not the BIOS stub we're running in
+    let stub_cs = cpu.cs;
+    cpu.cs = ret_cs;
+    fpu::emulate(cpu, &instr);
+    cpu.cs = stub_cs;
+
+    // Resume after the operand bytes
+    let skipped = instr.len() - synthesized + consumed;
+    cpu.bus.write_16(ret_ip_addr, ret_ip.wrapping_add(skipped as u16));
+}
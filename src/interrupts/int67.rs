@@ -0,0 +1,582 @@
+use crate::bus::Bus;
+use crate::cpu::Cpu;
+use crate::devices::ems::*;
+use iced_x86::Register;
+
+// LIM EMS 4.0 driver (INT 67h) for the board in devices/ems.rs.
+//
+// The driver lives in its own ROM segment that starts with a character device
+// header named EMMXXXX0: programs check for EMS by looking for that name at
+// offset 0Ah of the INT 67h segment. The vector points at a BOP stub in the
+// same segment. Alternate map register sets and DMA sets aren't offered.
+
+pub const DRIVER_SEGMENT: u16 = 0xF150;
+const ENTRY_OFFSET: u16 = 0x0012;
+// Where code called through function 56h returns to
+const CALL_RETURN_OFFSET: u16 = 0x0016;
+
+/// Bytes in a saved page map (functions 4Eh, 59h): handle and logical page per window
+const MAP_SIZE: u8 = (PHYSICAL_PAGES * 4) as u8;
+
+pub fn install_driver(bus: &mut Bus) {
+    let ivt = 0x67 * 4;
+    if bus.ems().total_pages() == 0 {
+        bus.write_16(ivt, 0);
+        bus.write_16(ivt + 2, 0);
+        return;
+    }
+
+    let mut driver = Vec::new();
+    driver.extend(0xFFFF_FFFFu32.to_le_bytes()); // Next driver: none
+    driver.extend(0xC000u16.to_le_bytes()); // Character device, IOCTL
+    driver.extend(0x0019u16.to_le_bytes()); // Strategy routine
+    driver.extend(0x0019u16.to_le_bytes()); // Interrupt routine
+    driver.extend(b"EMMXXXX0");
+    driver.extend([0xFE, 0x38, 0x67, 0xCF]); // 0012: INT 67h entry, IRET
+    driver.extend([0xFE, 0x39, crate::interrupts::HLE_CALL_EMS_RETURN]); // 0016: back from 56h
+    driver.push(0xCB); // 0019: RETF
+    bus.write_rom((DRIVER_SEGMENT as usize) << 4, &driver);
+
+    bus.write_16(ivt, ENTRY_OFFSET);
+    bus.write_16(ivt + 2, DRIVER_SEGMENT);
+}
+
+fn status(cpu: &mut Cpu, result: Result<(), u8>) {
+    cpu.set_reg8(Register::AH, result.err().unwrap_or(0));
+}
+
+pub fn handle(cpu: &mut Cpu) {
+    let ah = cpu.get_ah();
+    let al = cpu.get_al();
+    let result = match ah {
+        0x40 => Ok(()), // Get Status
+        0x41 => {
+            // Get Page Frame Segment
+            cpu.bx = cpu.bus.ems().frame_segment;
+            Ok(())
+        }
+        0x42 => {
+            // Get Unallocated Page Count
+            cpu.bx = cpu.bus.ems().free_pages();
+            cpu.dx = cpu.bus.ems().total_pages();
+            Ok(())
+        }
+        0x43 => {
+            // Allocate Pages: BX = count
+            if cpu.bx == 0 {
+                Err(ERR_ZERO_PAGES)
+            } else {
+                allocate(cpu)
+            }
+        }
+        0x44 => {
+            // Map/Unmap Handle Page: AL = window, BX = logical page (FFFF unmaps)
+            let logical = (cpu.bx != 0xFFFF).then_some(cpu.bx);
+            cpu.bus.ems_mut().map_page(al as usize, cpu.dx, logical)
+        }
+        0x45 => cpu.bus.ems_mut().deallocate(cpu.dx), // Deallocate Pages
+        0x46 => {
+            // Get Version: 4.0
+            cpu.set_reg8(Register::AL, 0x40);
+            Ok(())
+        }
+        0x47 => cpu.bus.ems_mut().save_map(cpu.dx), // Save Page Map
+        0x48 => cpu.bus.ems_mut().restore_map(cpu.dx), // Restore Page Map
+        0x4B => {
+            // Get Handle Count
+            cpu.bx = cpu.bus.ems().active_handles().len() as u16;
+            Ok(())
+        }
+        0x4C => {
+            // Get Handle Pages
+            cpu.bus.ems().handle_pages(cpu.dx).map(|pages| cpu.bx = pages)
+        }
+        0x4D => {
+            // Get All Handle Pages: ES:DI = array of (handle, pages)
+            let handles = cpu.bus.ems().active_handles();
+            let mut addr = cpu.get_physical_addr(cpu.es, cpu.di);
+            for &(handle, pages) in &handles {
+                cpu.bus.write_16(addr, handle);
+                cpu.bus.write_16(addr + 2, pages);
+                addr += 4;
+            }
+            cpu.bx = handles.len() as u16;
+            Ok(())
+        }
+        0x4E => page_map(cpu, al),
+        0x4F => partial_page_map(cpu, al),
+        0x50 => {
+            // Map/Unmap Multiple Pages: CX pairs of (logical, window or segment) at DS:SI
+            let pairs = read_pairs(cpu, cpu.ds, cpu.si, cpu.cx as usize);
+            map_pairs(cpu, al, cpu.dx, &pairs)
+        }
+        0x51 => {
+            // Reallocate Pages: BX = new count
+            let result = cpu.bus.ems_mut().reallocate(cpu.dx, cpu.bx);
+            if let Ok(pages) = cpu.bus.ems().handle_pages(cpu.dx) {
+                cpu.bx = pages;
+            }
+            result
+        }
+        0x52 => match al {
+            // Get/Set Handle Attribute: only volatile handles
+            0x00 | 0x02 => cpu.bus.ems().handle_pages(cpu.dx).map(|_| {
+                cpu.set_reg8(Register::AL, 0x00);
+            }),
+            0x01 if cpu.get_reg8(Register::BL) == 0 => {
+                cpu.bus.ems().handle_pages(cpu.dx).map(|_| ())
+            }
+            0x01 => Err(ERR_NOT_SUPPORTED),
+            _ => Err(ERR_SUBFUNCTION),
+        },
+        0x53 => match al {
+            // Get/Set Handle Name
+            0x00 => cpu.bus.ems().name(cpu.dx).map(|name| {
+                let addr = cpu.get_physical_addr(cpu.es, cpu.di);
+                for (i, &b) in name.iter().enumerate() {
+                    cpu.bus.write_8(addr + i, b);
+                }
+            }),
+            0x01 => {
+                let name = read_name(cpu, cpu.ds, cpu.si);
+                cpu.bus.ems_mut().set_name(cpu.dx, name)
+            }
+            _ => Err(ERR_SUBFUNCTION),
+        },
+        0x54 => handle_directory(cpu, al),
+        0x55 => {
+            // Alter Page Map and Jump
+            let params = cpu.get_physical_addr(cpu.ds, cpu.si);
+            let (target_off, target_seg) = read_far_ptr(cpu, params);
+            let pairs = read_map_list(cpu, params + 4);
+            map_pairs(cpu, al, cpu.dx, &pairs).map(|()| {
+                // Return from the interrupt straight into the target
+                let frame = cpu.get_physical_addr(cpu.ss, cpu.sp);
+                cpu.bus.write_16(frame, target_off);
+                cpu.bus.write_16(frame + 2, target_seg);
+            })
+        }
+        0x56 => alter_map_and_call(cpu, al),
+        0x57 => move_region(cpu, al),
+        0x58 => {
+            // Get Mappable Physical Address Array: (segment, window) pairs
+            if al == 0x00 {
+                let frame = cpu.bus.ems().frame_segment;
+                let addr = cpu.get_physical_addr(cpu.es, cpu.di);
+                for window in 0..PHYSICAL_PAGES {
+                    let segment = frame + (window * PAGE_SIZE / 16) as u16;
+                    cpu.bus.write_16(addr + window * 4, segment);
+                    cpu.bus.write_16(addr + window * 4 + 2, window as u16);
+                }
+            }
+            if al <= 0x01 {
+                cpu.cx = PHYSICAL_PAGES as u16;
+                Ok(())
+            } else {
+                Err(ERR_SUBFUNCTION)
+            }
+        }
+        0x59 => match al {
+            0x00 => {
+                // Get Hardware Configuration
+                let addr = cpu.get_physical_addr(cpu.es, cpu.di);
+                cpu.bus.write_16(addr, (PAGE_SIZE / 16) as u16); // Raw page size, paragraphs
+                cpu.bus.write_16(addr + 2, 0); // Alternate register sets
+                cpu.bus.write_16(addr + 4, MAP_SIZE as u16); // Context save area size
+                cpu.bus.write_16(addr + 6, 0); // DMA register sets
+                cpu.bus.write_16(addr + 8, 0); // DMA channel operation
+                Ok(())
+            }
+            0x01 => {
+                // Get Unallocated Raw Page Count
+                cpu.bx = cpu.bus.ems().free_pages();
+                cpu.dx = cpu.bus.ems().total_pages();
+                Ok(())
+            }
+            _ => Err(ERR_SUBFUNCTION),
+        },
+        0x5A if al <= 0x01 => allocate(cpu), // Allocate Standard/Raw Pages, zero allowed
+        0x5A => Err(ERR_SUBFUNCTION),
+        0x5B => alternate_map_set(cpu, al),
+        _ => {
+            cpu.bus
+                .log_string(&format!("[EMS] Unhandled INT 67h AH={:02X}", ah));
+            Err(ERR_UNDEFINED_FUNCTION)
+        }
+    };
+    status(cpu, result);
+}
+
+/// Runs when code called through function 56h returns: puts back the old map
+pub fn call_return(cpu: &mut Cpu) {
+    if let Some((handle, pairs)) = cpu.bus.ems_mut().call_stack.pop() {
+        for (logical, window) in pairs {
+            let logical = (logical != 0xFFFF).then_some(logical);
+            let _ = cpu
+                .bus
+                .ems_mut()
+                .map_page(window as usize, handle, logical);
+        }
+    }
+    cpu.set_reg8(Register::AH, 0);
+}
+
+fn allocate(cpu: &mut Cpu) -> Result<(), u8> {
+    let handle = cpu.bus.ems_mut().allocate(cpu.bx)?;
+    cpu.dx = handle;
+    Ok(())
+}
+
+fn read_far_ptr(cpu: &Cpu, addr: usize) -> (u16, u16) {
+    (cpu.bus.read_16(addr), cpu.bus.read_16(addr + 2))
+}
+
+fn read_pairs(cpu: &Cpu, segment: u16, offset: u16, count: usize) -> Vec<(u16, u16)> {
+    let addr = cpu.get_physical_addr(segment, offset);
+    (0..count)
+        .map(|i| (cpu.bus.read_16(addr + i * 4), cpu.bus.read_16(addr + i * 4 + 2)))
+        .collect()
+}
+
+// A length byte followed by a far pointer to that many (logical, window) pairs
+fn read_map_list(cpu: &Cpu, addr: usize) -> Vec<(u16, u16)> {
+    let len = cpu.bus.read_8(addr) as usize;
+    let (offset, segment) = read_far_ptr(cpu, addr + 1);
+    read_pairs(cpu, segment, offset, len)
+}
+
+fn read_name(cpu: &Cpu, segment: u16, offset: u16) -> [u8; 8] {
+    let addr = cpu.get_physical_addr(segment, offset);
+    std::array::from_fn(|i| cpu.bus.read_8(addr + i))
+}
+
+// Window number from a window number (AL=00) or a segment address (AL=01)
+fn window(cpu: &Cpu, by_segment: bool, value: u16) -> Result<usize, u8> {
+    if !by_segment {
+        return Ok(value as usize);
+    }
+    let offset = (value as usize).wrapping_sub(cpu.bus.ems().frame_segment as usize) * 16;
+    if offset.is_multiple_of(PAGE_SIZE) && offset / PAGE_SIZE < PHYSICAL_PAGES {
+        Ok(offset / PAGE_SIZE)
+    } else {
+        Err(ERR_PHYSICAL_PAGE)
+    }
+}
+
+fn map_pairs(cpu: &mut Cpu, al: u8, handle: u16, pairs: &[(u16, u16)]) -> Result<(), u8> {
+    if al > 0x01 {
+        return Err(ERR_SUBFUNCTION);
+    }
+    for &(logical, target) in pairs {
+        let window = window(cpu, al == 0x01, target)?;
+        let logical = (logical != 0xFFFF).then_some(logical);
+        cpu.bus.ems_mut().map_page(window, handle, logical)?;
+    }
+    Ok(())
+}
+
+fn write_map(cpu: &mut Cpu, addr: usize, map: &PageMap) {
+    for (window, entry) in map.iter().enumerate() {
+        let (handle, logical) = entry.unwrap_or((0xFFFF, 0xFFFF));
+        cpu.bus.write_16(addr + window * 4, handle);
+        cpu.bus.write_16(addr + window * 4 + 2, logical);
+    }
+}
+
+fn read_map(cpu: &Cpu, addr: usize) -> PageMap {
+    std::array::from_fn(|window| {
+        let handle = cpu.bus.read_16(addr + window * 4);
+        let logical = cpu.bus.read_16(addr + window * 4 + 2);
+        (handle != 0xFFFF).then_some((handle, logical))
+    })
+}
+
+// Function 4Eh: Get/Set Page Map
+fn page_map(cpu: &mut Cpu, al: u8) -> Result<(), u8> {
+    match al {
+        0x00 | 0x02 => {
+            let map = cpu.bus.ems().page_map();
+            let dest = cpu.get_physical_addr(cpu.es, cpu.di);
+            write_map(cpu, dest, &map);
+            if al == 0x02 {
+                let map = read_map(cpu, cpu.get_physical_addr(cpu.ds, cpu.si));
+                cpu.bus.ems_mut().set_page_map(&map);
+            }
+            Ok(())
+        }
+        0x01 => {
+            let map = read_map(cpu, cpu.get_physical_addr(cpu.ds, cpu.si));
+            cpu.bus.ems_mut().set_page_map(&map);
+            Ok(())
+        }
+        0x03 => {
+            cpu.set_reg8(Register::AL, MAP_SIZE);
+            Ok(())
+        }
+        _ => Err(ERR_SUBFUNCTION),
+    }
+}
+
+// Function 4Fh: Get/Set Partial Page Map.
+// Saved as a count followed by (segment, handle, logical page) per window.
+fn partial_page_map(cpu: &mut Cpu, al: u8) -> Result<(), u8> {
+    match al {
+        0x00 => {
+            let list = cpu.get_physical_addr(cpu.ds, cpu.si);
+            let count = cpu.bus.read_16(list) as usize;
+            let map = cpu.bus.ems().page_map();
+            let dest = cpu.get_physical_addr(cpu.es, cpu.di);
+            cpu.bus.write_16(dest, count as u16);
+            for i in 0..count {
+                let segment = cpu.bus.read_16(list + 2 + i * 2);
+                let window = window(cpu, true, segment)?;
+                let (handle, logical) = map[window].unwrap_or((0xFFFF, 0xFFFF));
+                let entry = dest + 2 + i * 6;
+                cpu.bus.write_16(entry, segment);
+                cpu.bus.write_16(entry + 2, handle);
+                cpu.bus.write_16(entry + 4, logical);
+            }
+            Ok(())
+        }
+        0x01 => {
+            let src = cpu.get_physical_addr(cpu.ds, cpu.si);
+            let count = cpu.bus.read_16(src) as usize;
+            for i in 0..count {
+                let entry = src + 2 + i * 6;
+                let window = window(cpu, true, cpu.bus.read_16(entry))?;
+                let handle = cpu.bus.read_16(entry + 2);
+                let logical = cpu.bus.read_16(entry + 4);
+                if handle == 0xFFFF {
+                    cpu.bus.ems_mut().map_page(window, 0, None)?;
+                } else {
+                    cpu.bus.ems_mut().map_page(window, handle, Some(logical))?;
+                }
+            }
+            Ok(())
+        }
+        0x02 => {
+            // Size for BX windows
+            cpu.set_reg8(Register::AL, (2 + cpu.bx as usize * 6) as u8);
+            Ok(())
+        }
+        _ => Err(ERR_SUBFUNCTION),
+    }
+}
+
+// Function 54h: Get Handle Directory
+fn handle_directory(cpu: &mut Cpu, al: u8) -> Result<(), u8> {
+    match al {
+        0x00 => {
+            // ES:DI = (handle, name) entries, AL = count
+            let handles = cpu.bus.ems().active_handles();
+            let addr = cpu.get_physical_addr(cpu.es, cpu.di);
+            for (i, &(handle, _)) in handles.iter().enumerate() {
+                let name = cpu.bus.ems().name(handle)?;
+                cpu.bus.write_16(addr + i * 10, handle);
+                for (j, &b) in name.iter().enumerate() {
+                    cpu.bus.write_8(addr + i * 10 + 2 + j, b);
+                }
+            }
+            cpu.set_reg8(Register::AL, handles.len() as u8);
+            Ok(())
+        }
+        0x01 => {
+            // Search for the name at DS:SI
+            let name = read_name(cpu, cpu.ds, cpu.si);
+            let handle = cpu.bus.ems().find_name(&name).ok_or(ERR_NAME_NOT_FOUND)?;
+            cpu.dx = handle;
+            Ok(())
+        }
+        0x02 => {
+            cpu.bx = MAX_HANDLES as u16;
+            Ok(())
+        }
+        _ => Err(ERR_SUBFUNCTION),
+    }
+}
+
+// Function 56h: Alter Page Map and Call.
+// The target is entered with a far return address pointing at the driver's
+// return stub, which puts the old map back and returns to the caller.
+fn alter_map_and_call(cpu: &mut Cpu, al: u8) -> Result<(), u8> {
+    if al == 0x02 {
+        cpu.bx = 10; // Extra stack the call uses
+        return Ok(());
+    }
+    if al > 0x01 {
+        return Err(ERR_SUBFUNCTION);
+    }
+
+    let params = cpu.get_physical_addr(cpu.ds, cpu.si);
+    let (target_off, target_seg) = read_far_ptr(cpu, params);
+    let new_map = read_map_list(cpu, params + 4);
+    let old_map = read_map_list(cpu, params + 9);
+    let old_windows = old_map
+        .iter()
+        .map(|&(logical, target)| Ok((logical, window(cpu, al == 0x01, target)? as u16)))
+        .collect::<Result<Vec<_>, u8>>()?;
+    map_pairs(cpu, al, cpu.dx, &new_map)?;
+    cpu.bus.ems_mut().call_stack.push((cpu.dx, old_windows));
+
+    // IRET frame (IP, CS, FLAGS) becomes: target IP, CS, FLAGS, return stub, caller
+    let frame = cpu.get_physical_addr(cpu.ss, cpu.sp);
+    let ret_ip = cpu.bus.read_16(frame);
+    let ret_cs = cpu.bus.read_16(frame + 2);
+    let flags = cpu.bus.read_16(frame + 4);
+    cpu.sp = cpu.sp.wrapping_sub(8);
+    let frame = cpu.get_physical_addr(cpu.ss, cpu.sp);
+    for (i, word) in [target_off, target_seg, flags, CALL_RETURN_OFFSET, DRIVER_SEGMENT, ret_ip, ret_cs]
+        .into_iter()
+        .enumerate()
+    {
+        cpu.bus.write_16(frame + i * 2, word);
+    }
+    Ok(())
+}
+
+#[derive(Clone, Copy)]
+enum Location {
+    Conventional(usize),
+    Expanded { handle: u16, page: u16, offset: usize },
+}
+
+fn read_location(cpu: &Cpu, addr: usize, len: usize) -> Result<Location, u8> {
+    let kind = cpu.bus.read_8(addr);
+    let handle = cpu.bus.read_16(addr + 1);
+    let offset = cpu.bus.read_16(addr + 3) as usize;
+    let segment_or_page = cpu.bus.read_16(addr + 5);
+    match kind {
+        0x00 => {
+            let start = (segment_or_page as usize) * 16 + offset;
+            if start + len > 0x100000 {
+                return Err(ERR_WRAPS);
+            }
+            Ok(Location::Conventional(start))
+        }
+        0x01 => {
+            if offset >= PAGE_SIZE {
+                return Err(ERR_OFFSET);
+            }
+            let ems = cpu.bus.ems();
+            ems.storage_index(handle, segment_or_page, offset)?;
+            if len > 0 && ems.storage_index(handle, segment_or_page, offset + len - 1).is_err() {
+                return Err(ERR_LENGTH);
+            }
+            Ok(Location::Expanded {
+                handle,
+                page: segment_or_page,
+                offset,
+            })
+        }
+        _ => Err(ERR_MEMORY_TYPE),
+    }
+}
+
+impl Location {
+    // Linear position, for overlap checks within the same kind of memory
+    fn span(&self, len: usize) -> (Option<u16>, std::ops::Range<usize>) {
+        match *self {
+            Location::Conventional(start) => (None, start..start + len),
+            Location::Expanded { handle, page, offset } => {
+                let start = page as usize * PAGE_SIZE + offset;
+                (Some(handle), start..start + len)
+            }
+        }
+    }
+
+    fn read(&self, cpu: &Cpu, len: usize) -> Vec<u8> {
+        match *self {
+            Location::Conventional(start) => (start..start + len).map(|a| cpu.bus.read_8(a)).collect(),
+            Location::Expanded { handle, page, offset } => {
+                let ems = cpu.bus.ems();
+                (offset..offset + len)
+                    .map(|o| ems.storage()[ems.storage_index(handle, page, o).unwrap_or(0)])
+                    .collect()
+            }
+        }
+    }
+
+    fn write(&self, cpu: &mut Cpu, bytes: &[u8]) {
+        match *self {
+            Location::Conventional(start) => {
+                for (i, &b) in bytes.iter().enumerate() {
+                    cpu.bus.write_8(start + i, b);
+                }
+            }
+            Location::Expanded { handle, page, offset } => {
+                let ems = cpu.bus.ems_mut();
+                for (i, &b) in bytes.iter().enumerate() {
+                    if let Ok(index) = ems.storage_index(handle, page, offset + i) {
+                        ems.storage_mut()[index] = b;
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Function 57h: Move (AL=00) / Exchange (AL=01) Memory Region
+fn move_region(cpu: &mut Cpu, al: u8) -> Result<(), u8> {
+    if al > 0x01 {
+        return Err(ERR_SUBFUNCTION);
+    }
+    let params = cpu.get_physical_addr(cpu.ds, cpu.si);
+    let len = cpu.bus.read_32(params) as usize;
+    if len > 0x100000 {
+        return Err(ERR_REGION_TOO_BIG);
+    }
+    let src = read_location(cpu, params + 4, len)?;
+    let dst = read_location(cpu, params + 0x0B, len)?;
+
+    let (src_handle, src_span) = src.span(len);
+    let (dst_handle, dst_span) = dst.span(len);
+    let overlap = src_handle == dst_handle
+        && std::mem::discriminant(&src) == std::mem::discriminant(&dst)
+        && src_span.start < dst_span.end
+        && dst_span.start < src_span.end;
+
+    let src_bytes = src.read(cpu, len);
+    if al == 0x01 {
+        if overlap {
+            return Err(ERR_OVERLAP_EXCHANGE);
+        }
+        let dst_bytes = dst.read(cpu, len);
+        src.write(cpu, &dst_bytes);
+    }
+    dst.write(cpu, &src_bytes);
+    if overlap { Err(OVERLAP_MOVED) } else { Ok(()) }
+}
+
+// Function 5Bh: Alternate Map Register Set. Only set 0, the normal map, exists.
+fn alternate_map_set(cpu: &mut Cpu, al: u8) -> Result<(), u8> {
+    let bl = cpu.get_reg8(Register::BL);
+    match al {
+        0x00 => {
+            // Get: BL = 0, ES:DI = no save area
+            cpu.set_reg8(Register::BL, 0);
+            cpu.es = 0;
+            cpu.di = 0;
+            Ok(())
+        }
+        0x01 if bl == 0 => {
+            // Set: with set 0, ES:DI holds a map to load (if any)
+            if cpu.es != 0 || cpu.di != 0 {
+                let map = read_map(cpu, cpu.get_physical_addr(cpu.es, cpu.di));
+                cpu.bus.ems_mut().set_page_map(&map);
+            }
+            Ok(())
+        }
+        0x02 => {
+            cpu.dx = MAP_SIZE as u16;
+            Ok(())
+        }
+        0x03 => {
+            // Allocate: none available
+            cpu.set_reg8(Register::BL, 0);
+            Ok(())
+        }
+        0x01 | 0x04 if bl != 0 => Err(ERR_ALT_MAP_SET),
+        0x04 => Ok(()),
+        _ => Err(ERR_SUBFUNCTION),
+    }
+}
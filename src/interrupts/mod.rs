@@ -9,8 +9,13 @@ pub mod int16;
 pub mod int1a;
 pub mod int20;
 pub mod int21;
+pub mod int24;
 pub mod int2f;
 pub mod int33;
+pub mod int34;
+pub mod int67;
+pub mod xms;
+pub mod hooks;
 pub mod utils;
 
 /// Called when the CPU encounters "INT XX" instruction.
@@ -41,7 +46,40 @@ pub fn handle_interrupt(cpu: &mut Cpu, vector: u8) {
     cpu.set_cpu_flag(CpuFlags::TF, false);
 }
 
+/// Vectors the BIOS points at HLE traps
+pub const BIOS_VECTORS: &[u8] = &[
+    0x08, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x1A, 0x20, 0x21, 0x24, 0x2F, 0x33,
+    // Floating point emulator
+    0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3A, 0x3B, 0x3C, 0x3D,
+];
+
+/// Far-call BOP services (FE 39 XX)
+pub const HLE_CALL_XMS: u8 = 0x00;
+pub const HLE_CALL_EMS_RETURN: u8 = 0x01;
+pub const HLE_CALL_CRITICAL_RETURN: u8 = 0x02;
+
+/// Called when the CPU executes a far-call BOP at a driver entry point
+pub fn handle_hle_call(cpu: &mut Cpu, service: u8) {
+    match service {
+        HLE_CALL_XMS => xms::handle(cpu),
+        HLE_CALL_EMS_RETURN => int67::call_return(cpu),
+        HLE_CALL_CRITICAL_RETURN => int24::resume(cpu),
+        _ => {
+            cpu.bus.log_string(&format!(
+                "[CPU] Unhandled HLE Call Service {:02X}",
+                service
+            ));
+        }
+    }
+}
+
+/// Called when the CPU executes a BOP trap.
+/// Host handlers registered for the vector get the first go.
 pub fn handle_hle(cpu: &mut Cpu, vector: u8) {
+    if hooks::dispatch(cpu, vector) {
+        return;
+    }
+
     match vector {
         0x00 => int00::handle(cpu),
         0x08 => int08::handle(cpu),
@@ -53,6 +91,7 @@ pub fn handle_hle(cpu: &mut Cpu, vector: u8) {
         0x1A => int1a::handle(cpu),
         0x20 => int20::handle(cpu),
         0x21 => int21::handle(cpu),
+        0x24 => int24::handle(cpu),
         0x28 => { /* Idle Interrupt - Do nothing */ }
         0x2A => { /* DOS Timer Tick - Do nothing for now */ }
         0x13 => {
@@ -69,10 +108,11 @@ pub fn handle_hle(cpu: &mut Cpu, vector: u8) {
         } // IO Error, Selected, Out of Paper
         0x2F => int2f::handle(cpu),
         0x33 => int33::handle(cpu),
-        0x34 | 0x35 | 0x36 | 0x37 | 0x38 | 0x39 | 0x3A | 0x3B | 0x3C | 0x3D | 0x3E | 0x3F => {
-            /* FPU Vector - IRET */
-            // TODO: Implement FPU
+        0x34..=0x3D => int34::handle(cpu, vector),
+        0x3E | 0x3F => {
+            /* Borland emulator shortcuts / overlay manager - IRET */
         }
+        0x67 => int67::handle(cpu),
         0x4C => {
             cpu.bus
                 .log_string("[DOS] Program Exited. Rebooting Shell...");
@@ -0,0 +1,307 @@
+use crate::bus::Bus;
+use crate::cpu::Cpu;
+use crate::mcb;
+use crate::memory;
+use crate::xms::*;
+use iced_x86::Register;
+
+// XMS 3.0 driver, the HIMEM.SYS equivalent.
+//
+// Programs find it with INT 2Fh AX=4300h/4310h and then far call its entry
+// point with the function in AH. The entry is a far-call BOP in the BIOS
+// segment, behind the short jump XMS hookers expect to patch.
+// Functions 88h-8Fh need 32-bit registers and aren't offered.
+
+pub const ENTRY_SEGMENT: u16 = 0xF000;
+pub const ENTRY_OFFSET: u16 = 0x1400;
+
+pub fn install_entry(bus: &mut Bus) {
+    let phys = ((ENTRY_SEGMENT as usize) << 4) + ENTRY_OFFSET as usize;
+    bus.write_rom(
+        phys,
+        &[
+            0xEB, 0x03, // JMP SHORT +3
+            0x90, 0x90, 0x90, // NOP NOP NOP (room for a hook's far jump)
+            0xFE, 0x39, crate::interrupts::HLE_CALL_XMS, // BOP far call, RETF
+        ],
+    );
+}
+
+/// INT 2Fh AH=43h: installation check and entry point
+pub fn handle_multiplex(cpu: &mut Cpu) {
+    match cpu.get_al() {
+        0x00 => cpu.set_reg8(Register::AL, 0x80), // Installed
+        0x10 => {
+            cpu.es = ENTRY_SEGMENT;
+            cpu.bx = ENTRY_OFFSET;
+        }
+        al => cpu
+            .bus
+            .log_string(&format!("[XMS] Unhandled INT 2Fh AX=43{:02X}", al)),
+    }
+}
+
+fn success(cpu: &mut Cpu) {
+    cpu.ax = 0x0001;
+    cpu.set_reg8(Register::BL, 0x00);
+}
+
+fn fail(cpu: &mut Cpu, code: u8) {
+    cpu.ax = 0x0000;
+    cpu.set_reg8(Register::BL, code);
+}
+
+fn finish(cpu: &mut Cpu, result: Result<(), u8>) {
+    match result {
+        Ok(()) => success(cpu),
+        Err(code) => fail(cpu, code),
+    }
+}
+
+pub fn handle(cpu: &mut Cpu) {
+    let ah = cpu.get_ah();
+    match ah {
+        0x00 => {
+            // Get XMS Version
+            cpu.ax = 0x0300;
+            cpu.bx = 0x0300; // Driver revision
+            cpu.dx = (cpu.bus.extended_kb() >= 64) as u16; // HMA exists
+        }
+        0x01 => {
+            // Request HMA
+            let result = if cpu.bus.extended_kb() < 64 {
+                Err(ERR_HMA_MISSING)
+            } else if cpu.bus.xms.hma_allocated {
+                Err(ERR_HMA_IN_USE)
+            } else {
+                cpu.bus.xms.hma_allocated = true;
+                Ok(())
+            };
+            finish(cpu, result);
+        }
+        0x02 => {
+            // Release HMA
+            let result = if cpu.bus.extended_kb() < 64 {
+                Err(ERR_HMA_MISSING)
+            } else if !cpu.bus.xms.hma_allocated {
+                Err(ERR_HMA_NOT_ALLOCATED)
+            } else {
+                cpu.bus.xms.hma_allocated = false;
+                Ok(())
+            };
+            finish(cpu, result);
+        }
+        0x03 => {
+            // Global Enable A20
+            cpu.bus.set_a20(true);
+            success(cpu);
+        }
+        0x04 => {
+            // Global Disable A20. Stays on while a local enable is outstanding.
+            if cpu.bus.xms.a20_local_count > 0 {
+                fail(cpu, ERR_A20_STILL_ENABLED);
+            } else {
+                cpu.bus.set_a20(false);
+                success(cpu);
+            }
+        }
+        0x05 => {
+            // Local Enable A20
+            cpu.bus.xms.a20_local_count = cpu.bus.xms.a20_local_count.saturating_add(1);
+            cpu.bus.set_a20(true);
+            success(cpu);
+        }
+        0x06 => {
+            // Local Disable A20
+            let xms = &mut cpu.bus.xms;
+            xms.a20_local_count = xms.a20_local_count.saturating_sub(1);
+            if xms.a20_local_count == 0 {
+                cpu.bus.set_a20(false);
+                success(cpu);
+            } else {
+                fail(cpu, ERR_A20_STILL_ENABLED);
+            }
+        }
+        0x07 => {
+            // Query A20
+            cpu.ax = cpu.bus.a20_enabled() as u16;
+            cpu.set_reg8(Register::BL, 0x00);
+        }
+        0x08 => {
+            // Query Free Extended Memory (KB)
+            let (largest, total) = cpu.bus.xms.free_kb();
+            cpu.ax = largest;
+            cpu.dx = total;
+            let code = if total == 0 { ERR_OUT_OF_MEMORY } else { 0x00 };
+            cpu.set_reg8(Register::BL, code);
+        }
+        0x09 => {
+            // Allocate Extended Memory Block: DX = KB
+            match cpu.bus.xms.allocate(cpu.dx) {
+                Ok(handle) => {
+                    success(cpu);
+                    cpu.dx = handle;
+                }
+                Err(code) => {
+                    fail(cpu, code);
+                    cpu.dx = 0;
+                }
+            }
+        }
+        0x0A => {
+            // Free Extended Memory Block
+            let result = cpu.bus.xms.free(cpu.dx);
+            finish(cpu, result);
+        }
+        0x0B => {
+            // Move Extended Memory Block
+            let result = move_block(cpu);
+            finish(cpu, result);
+        }
+        0x0C => {
+            // Lock Extended Memory Block: returns DX:BX = physical address
+            match cpu.bus.xms.lock(cpu.dx) {
+                Ok(addr) => {
+                    cpu.ax = 0x0001;
+                    cpu.dx = (addr >> 16) as u16;
+                    cpu.bx = addr as u16;
+                }
+                Err(code) => fail(cpu, code),
+            }
+        }
+        0x0D => {
+            // Unlock Extended Memory Block
+            let result = cpu.bus.xms.unlock(cpu.dx);
+            finish(cpu, result);
+        }
+        0x0E => {
+            // Get EMB Handle Information
+            match cpu.bus.xms.block(cpu.dx).copied() {
+                Some(emb) => {
+                    cpu.ax = 0x0001;
+                    cpu.set_reg8(Register::BH, emb.locks);
+                    cpu.set_reg8(Register::BL, cpu.bus.xms.free_handles());
+                    cpu.dx = emb.size_kb;
+                }
+                None => fail(cpu, ERR_INVALID_HANDLE),
+            }
+        }
+        0x0F => {
+            // Reallocate Extended Memory Block: BX = new size in KB
+            let result = cpu.bus.xms.resize(cpu.dx, cpu.bx).map(|moved| {
+                if let Some((from, to, len)) = moved {
+                    cpu.bus.copy_linear(from, to, len);
+                }
+            });
+            finish(cpu, result);
+        }
+        0x10 => {
+            // Request Upper Memory Block: DX = size in paragraphs
+            match mcb::allocate_upper(&mut cpu.bus, cpu.dx, mcb::OWNER_DOS) {
+                Ok(segment) => {
+                    cpu.bus.xms.umbs.push(segment);
+                    cpu.ax = 0x0001;
+                    cpu.bx = segment;
+                }
+                Err((_, 0)) => {
+                    fail(cpu, ERR_NO_UMB);
+                    cpu.dx = 0;
+                }
+                Err((_, largest)) => {
+                    fail(cpu, ERR_SMALLER_UMB);
+                    cpu.dx = largest;
+                }
+            }
+        }
+        0x11 => {
+            // Release Upper Memory Block: DX = segment
+            let segment = cpu.dx;
+            let result = match cpu.bus.xms.umbs.iter().position(|&s| s == segment) {
+                Some(index) => {
+                    cpu.bus.xms.umbs.remove(index);
+                    mcb::free(&mut cpu.bus, segment).map_err(|_| ERR_INVALID_UMB)
+                }
+                None => Err(ERR_INVALID_UMB),
+            };
+            finish(cpu, result);
+        }
+        0x12 => {
+            // Reallocate Upper Memory Block: DX = segment, BX = new size in paragraphs
+            if !cpu.bus.xms.umbs.contains(&cpu.dx) {
+                fail(cpu, ERR_INVALID_UMB);
+            } else if let Err((_, largest)) = mcb::resize(&mut cpu.bus, cpu.dx, cpu.bx) {
+                fail(cpu, ERR_SMALLER_UMB);
+                cpu.dx = largest;
+            } else {
+                success(cpu);
+            }
+        }
+        _ => {
+            cpu.bus
+                .log_string(&format!("[XMS] Unhandled function AH={:02X}", ah));
+            fail(cpu, ERR_NOT_IMPLEMENTED);
+        }
+    }
+}
+
+// Physical address of `len` bytes at `offset` into `handle`.
+// Handle 0 means conventional memory, with a segment:offset for the offset.
+fn resolve(
+    cpu: &Cpu,
+    handle: u16,
+    offset: u32,
+    len: usize,
+    bad_handle: u8,
+    bad_offset: u8,
+) -> Result<usize, u8> {
+    if handle == 0 {
+        let addr = ((offset >> 16) as usize) * 16 + (offset & 0xFFFF) as usize;
+        if addr + len > memory::ADDRESS_SPACE {
+            return Err(ERR_INVALID_LENGTH);
+        }
+        return Ok(addr);
+    }
+
+    let emb = cpu.bus.xms.block(handle).ok_or(bad_handle)?;
+    let offset = offset as usize;
+    if offset > emb.size_bytes() {
+        return Err(bad_offset);
+    }
+    if offset + len > emb.size_bytes() {
+        return Err(ERR_INVALID_LENGTH);
+    }
+    Ok(emb.addr + offset)
+}
+
+// Runs the move described by the structure at DS:SI
+fn move_block(cpu: &mut Cpu) -> Result<(), u8> {
+    let params = cpu.get_physical_addr(cpu.ds, cpu.si);
+    let len = cpu.bus.read_32(params) as usize;
+    let src_handle = cpu.bus.read_16(params + 4);
+    let src_offset = cpu.bus.read_32(params + 6);
+    let dst_handle = cpu.bus.read_16(params + 10);
+    let dst_offset = cpu.bus.read_32(params + 12);
+
+    if !len.is_multiple_of(2) {
+        return Err(ERR_INVALID_LENGTH);
+    }
+    let src = resolve(
+        cpu,
+        src_handle,
+        src_offset,
+        len,
+        ERR_INVALID_SOURCE_HANDLE,
+        ERR_INVALID_SOURCE_OFFSET,
+    )?;
+    let dst = resolve(
+        cpu,
+        dst_handle,
+        dst_offset,
+        len,
+        ERR_INVALID_DEST_HANDLE,
+        ERR_INVALID_DEST_OFFSET,
+    )?;
+
+    cpu.bus.copy_linear(src, dst, len);
+    Ok(())
+}
@@ -0,0 +1,136 @@
+use crate::cpu::CpuFlags;
+
+// Lazy evaluation of the arithmetic flags.
+//
+// Most flag results are overwritten by the next ALU instruction before anyone
+// looks at them. Instead of computing CF/PF/AF/ZF/SF/OF after every operation,
+// the ALU records the operation, its operands and its result here. The flags are
+// only worked out when something reads them (Jcc, PUSHF, LAHF, INT, ...).
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlagOp {
+    /// Nothing pending, the flags register is up to date
+    None,
+    Add8,
+    Add16,
+    Adc8,
+    Adc16,
+    Sub8,
+    Sub16,
+    Sbb8,
+    Sbb16,
+    Inc8,
+    Inc16,
+    Dec8,
+    Dec16,
+    /// AND/OR/XOR/TEST: CF and OF cleared, AF untouched
+    Logic8,
+    Logic16,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LazyFlags {
+    pub op: FlagOp,
+    pub dest: u16,
+    pub src: u16,
+    pub result: u16,
+    pub carry_in: bool,
+}
+
+impl Default for LazyFlags {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LazyFlags {
+    pub const fn new() -> Self {
+        Self {
+            op: FlagOp::None,
+            dest: 0,
+            src: 0,
+            result: 0,
+            carry_in: false,
+        }
+    }
+
+    #[inline]
+    pub fn is_pending(&self) -> bool {
+        self.op != FlagOp::None
+    }
+
+    /// The flags the pending operation defines. Everything else comes from the flags register.
+    pub fn mask(op: FlagOp) -> CpuFlags {
+        let all =
+            CpuFlags::CF | CpuFlags::PF | CpuFlags::AF | CpuFlags::ZF | CpuFlags::SF | CpuFlags::OF;
+        match op {
+            FlagOp::None => CpuFlags::empty(),
+            FlagOp::Inc8 | FlagOp::Inc16 => {
+                CpuFlags::PF | CpuFlags::ZF | CpuFlags::SF | CpuFlags::OF
+            }
+            FlagOp::Dec8 | FlagOp::Dec16 => all - CpuFlags::CF,
+            FlagOp::Logic8 | FlagOp::Logic16 => all - CpuFlags::AF,
+            _ => all,
+        }
+    }
+
+    /// Returns `flags` with the bits defined by the pending operation filled in
+    pub fn resolve(&self, flags: CpuFlags) -> CpuFlags {
+        if self.op == FlagOp::None {
+            return flags;
+        }
+
+        let byte = matches!(
+            self.op,
+            FlagOp::Add8
+                | FlagOp::Adc8
+                | FlagOp::Sub8
+                | FlagOp::Sbb8
+                | FlagOp::Inc8
+                | FlagOp::Dec8
+                | FlagOp::Logic8
+        );
+        let (width_mask, sign_bit) = if byte {
+            (0xFFu32, 0x80u32)
+        } else {
+            (0xFFFF, 0x8000)
+        };
+
+        let dest = self.dest as u32 & width_mask;
+        let src = self.src as u32 & width_mask;
+        let result = self.result as u32 & width_mask;
+        let cin = self.carry_in as u32;
+
+        let dest_sign = dest & sign_bit != 0;
+        let src_sign = src & sign_bit != 0;
+        let res_sign = result & sign_bit != 0;
+        let half_carry = (dest ^ src ^ result) & 0x10 != 0;
+
+        let (cf, af, of) = match self.op {
+            FlagOp::Add8 | FlagOp::Add16 | FlagOp::Adc8 | FlagOp::Adc16 => (
+                dest + src + cin > width_mask,
+                half_carry,
+                dest_sign == src_sign && res_sign != dest_sign,
+            ),
+            FlagOp::Sub8 | FlagOp::Sub16 | FlagOp::Sbb8 | FlagOp::Sbb16 => (
+                dest < src + cin,
+                half_carry,
+                dest_sign != src_sign && res_sign != dest_sign,
+            ),
+            FlagOp::Inc8 | FlagOp::Inc16 => (false, false, dest == sign_bit - 1),
+            FlagOp::Dec8 | FlagOp::Dec16 => (false, dest & 0x0F == 0, dest == sign_bit),
+            _ => (false, false, false),
+        };
+
+        let mut computed = CpuFlags::empty();
+        computed.set(CpuFlags::CF, cf);
+        computed.set(CpuFlags::PF, (result & 0xFF).count_ones().is_multiple_of(2));
+        computed.set(CpuFlags::AF, af);
+        computed.set(CpuFlags::ZF, result == 0);
+        computed.set(CpuFlags::SF, res_sign);
+        computed.set(CpuFlags::OF, of);
+
+        let mask = Self::mask(self.op);
+        (flags - mask) | (computed & mask)
+    }
+}
@@ -1,12 +1,25 @@
 pub mod audio;
 pub mod bus;
+pub mod clock;
 pub mod command;
 pub mod cpu;
+pub mod crash;
+pub mod decode_cache;
+pub mod devices;
 pub mod disk;
+pub mod errors;
 pub mod f80;
+pub mod fcb;
+pub mod handles;
 pub mod keyboard;
+pub mod lazy_flags;
 pub mod instructions;
 pub mod interrupts;
+pub mod machine;
+pub mod mcb;
+pub mod memory;
+pub mod nls;
 pub mod recorder;
 pub mod shell;
 pub mod video;
+pub mod xms;
@@ -0,0 +1,333 @@
+use std::path::PathBuf;
+
+use crate::audio::{AudioSink, pump_audio};
+use crate::clock::{self, StartTime};
+use crate::cpu::{ClockSpeed, Cpu, CpuModel, CpuState, FpuErrorLine, FpuModel};
+use crate::devices::Device;
+use crate::keyboard;
+use crate::nls;
+use crate::shell;
+use crate::video::{self, VideoMode};
+
+// Embedding API.
+//
+// `MachineBuilder` does the wiring: CPU setup, sinks, devices and the shell.
+// The resulting `Machine` runs the emulation loop. The SDL front end in main.rs
+// drives one and adds the window; other crates and tests can drive one without
+// SDL to type commands, run programs and inspect the screen.
+
+/// Frames per emulated second handed to the video sink
+const FRAME_RATE: u64 = 60;
+
+/// Receives every rendered frame: 640x400 RGB24, row by row
+pub trait VideoSink {
+    fn present(&mut self, frame: &[u8]);
+}
+
+impl<F: FnMut(&[u8])> VideoSink for F {
+    fn present(&mut self, frame: &[u8]) {
+        self(frame)
+    }
+}
+
+pub struct MachineBuilder {
+    root: PathBuf,
+    write_protect: bool,
+    memory_kb: u16,
+    extended_kb: u16,
+    ems: (u16, u16),
+    upper_memory: bool,
+    start_time: StartTime,
+    nvram_file: Option<PathBuf>,
+    country: u16,
+    code_page: Option<u16>,
+    font_dir: Option<PathBuf>,
+    model: CpuModel,
+    clock: Option<ClockSpeed>,
+    fpu: Option<FpuModel>,
+    fpu_error: Option<FpuErrorLine>,
+    devices: Vec<Box<dyn Device>>,
+    audio: Option<Box<dyn AudioSink>>,
+    video: Option<Box<dyn VideoSink>>,
+}
+
+impl MachineBuilder {
+    /// `root` becomes drive C:
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            write_protect: false,
+            memory_kb: 640,
+            extended_kb: crate::bus::DEFAULT_EXTENDED_KB,
+            ems: (crate::bus::DEFAULT_EMS_PAGES, crate::bus::DEFAULT_EMS_FRAME),
+            upper_memory: true,
+            start_time: StartTime::Host,
+            nvram_file: None,
+            country: 1,
+            code_page: None,
+            font_dir: None,
+            model: CpuModel::I80386,
+            clock: None,
+            fpu: None,
+            fpu_error: None,
+            devices: Vec::new(),
+            audio: None,
+            video: None,
+        }
+    }
+
+    /// Makes C: read-only, like a write-protected disk: changes to it go to INT 24h
+    pub fn write_protect(mut self, enabled: bool) -> Self {
+        self.write_protect = enabled;
+        self
+    }
+
+    /// Conventional memory in KB, up to 640
+    pub fn memory_kb(mut self, kb: u16) -> Self {
+        self.memory_kb = kb.clamp(64, 640);
+        self
+    }
+
+    /// Memory above 1MB in KB, HMA included. The XMS driver hands it out.
+    pub fn extended_kb(mut self, kb: u16) -> Self {
+        self.extended_kb = kb;
+        self
+    }
+
+    /// Expanded memory in 16KB pages, banked in at `frame_segment`. No pages, no EMS.
+    /// `build` panics if the frame isn't a 16KB aligned segment from C800 to E000.
+    pub fn expanded_memory(mut self, pages: u16, frame_segment: u16) -> Self {
+        self.ems = (pages, frame_segment);
+        self
+    }
+
+    /// Upper memory blocks in the free space between C800 and F000, on by default
+    pub fn upper_memory(mut self, enabled: bool) -> Self {
+        self.upper_memory = enabled;
+        self
+    }
+
+    /// Date and time at power-on; the host's local time by default
+    pub fn start_time(mut self, start: StartTime) -> Self {
+        self.start_time = start;
+        self
+    }
+
+    /// Host file holding the CMOS NVRAM, created if missing. Without one the
+    /// settings last as long as the machine.
+    /// `build` panics if the file can't be read or written, or isn't 128 bytes.
+    pub fn nvram_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.nvram_file = Some(path.into());
+        self
+    }
+
+    /// Country code for date, time and currency formats; 1 (United States) by default.
+    /// `build` panics on a country it has no data for.
+    pub fn country(mut self, code: u16) -> Self {
+        self.country = code;
+        self
+    }
+
+    /// Code page: 437, 850, 852 or 866. Defaults to the country's.
+    pub fn code_page(mut self, id: u16) -> Self {
+        self.code_page = Some(id);
+        self
+    }
+
+    /// Where fonts overriding the bundled ones are, as CPnnn.F16 and CPnnn.F08
+    pub fn font_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.font_dir = Some(dir.into());
+        self
+    }
+
+    /// Clock, FPU and FPU error line default to what usually came with the model
+    pub fn cpu(mut self, model: CpuModel) -> Self {
+        self.model = model;
+        self
+    }
+
+    pub fn clock(mut self, clock: ClockSpeed) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    pub fn fpu(mut self, fpu: FpuModel) -> Self {
+        self.fpu = Some(fpu);
+        self
+    }
+
+    pub fn fpu_error(mut self, line: FpuErrorLine) -> Self {
+        self.fpu_error = Some(line);
+        self
+    }
+
+    /// Attaches an I/O device. Its ports take precedence over the built-in hardware.
+    pub fn device(mut self, device: impl Device + 'static) -> Self {
+        self.devices.push(Box::new(device));
+        self
+    }
+
+    pub fn audio_sink(mut self, sink: impl AudioSink + 'static) -> Self {
+        self.audio = Some(Box::new(sink));
+        self
+    }
+
+    pub fn video_sink(mut self, sink: impl VideoSink + 'static) -> Self {
+        self.video = Some(Box::new(sink));
+        self
+    }
+
+    /// Powers the machine on; it sits at the shell prompt
+    pub fn build(self) -> Machine {
+        self.try_build().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Like `build`, but a bad EMS frame, NVRAM file or country comes back as an error
+    pub fn try_build(self) -> Result<Machine, String> {
+        let mut cpu = Cpu::new(self.root);
+        cpu.bus.disk.set_write_protected(self.write_protect);
+        cpu.model = self.model;
+        cpu.clock = self
+            .clock
+            .unwrap_or(ClockSpeed::Hz(self.model.nominal_hz()));
+        cpu.fpu_model = self.fpu.unwrap_or(FpuModel::for_cpu(self.model));
+        cpu.fpu_error_line = self.fpu_error.unwrap_or(FpuErrorLine::for_cpu(self.model));
+        cpu.bus.write_16(0x0413, self.memory_kb);
+        cpu.bus.set_extended_memory(self.extended_kb);
+        cpu.bus.set_expanded_memory(self.ems.0, self.ems.1)?;
+        cpu.bus.set_upper_memory(self.upper_memory);
+        clock::start(&mut cpu.bus, self.start_time);
+        if let Some(path) = self.nvram_file {
+            cpu.bus
+                .rtc_mut()
+                .set_nvram_file(path.clone())
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+        }
+        cpu.bus.nls.font_dir = self.font_dir;
+        nls::start(&mut cpu.bus, self.country, self.code_page)?;
+        for device in self.devices {
+            cpu.bus.io.attach(device);
+        }
+        cpu.bus.audio_device = self.audio;
+        if let Some(device) = &mut cpu.bus.audio_device {
+            device.resume();
+        }
+        cpu.load_shell();
+
+        let frame_cycles = cpu.clock.effective_hz(cpu.model) / FRAME_RATE;
+        Ok(Machine {
+            cpu,
+            video: self.video,
+            frame_cycles,
+            next_frame: frame_cycles,
+        })
+    }
+}
+
+pub struct Machine {
+    pub cpu: Cpu,
+    video: Option<Box<dyn VideoSink>>,
+    frame_cycles: u64,
+    next_frame: u64, // Cycle count at which the next frame is due
+}
+
+impl Machine {
+    /// Executes one instruction, or one shell action (command, program exit)
+    pub fn step(&mut self) {
+        let cpu = &mut self.cpu;
+        if shell::run_pending_command(cpu) {
+            return;
+        }
+        if cpu.state == CpuState::RebootShell {
+            shell::reload(cpu);
+            return;
+        }
+        if shell::check_psp_exit(cpu) {
+            return;
+        }
+        cpu.step();
+
+        if cpu.cycles >= self.next_frame {
+            self.next_frame = cpu.cycles + self.frame_cycles;
+            self.end_frame();
+        }
+    }
+
+    /// Runs until `done` returns true, checking it before every step.
+    /// Gives up after `max_cycles` emulated cycles or when the machine is switched off.
+    /// Returns whether `done` was satisfied.
+    pub fn run_until(&mut self, max_cycles: u64, mut done: impl FnMut(&Machine) -> bool) -> bool {
+        let limit = self.cpu.cycles.saturating_add(max_cycles);
+        while self.cpu.cycles < limit {
+            if done(self) {
+                return true;
+            }
+            if self.is_powered_off() {
+                return false;
+            }
+            self.step();
+        }
+        done(self)
+    }
+
+    /// Types `text` into the keyboard buffer. CR or LF press Enter.
+    /// Characters without a key on a US keyboard are dropped.
+    pub fn send_keys(&mut self, text: &str) {
+        for c in text.chars() {
+            if let Some(code) = keyboard::map_char_to_pc(c) {
+                self.cpu.bus.keyboard_buffer.push_back(code);
+            }
+        }
+    }
+
+    /// Text mode screen contents, one line per row with trailing blanks removed.
+    /// Non-ASCII characters come back as '?'. Empty in graphics modes.
+    pub fn screen_text(&self) -> String {
+        let bus = &self.cpu.bus;
+        let vram = &bus.vga().vram_text;
+        let cols = match bus.video_mode {
+            VideoMode::Text80x25 | VideoMode::Text80x25Color => 80,
+            VideoMode::Text40x25 | VideoMode::Text40x25Color => 40,
+            _ => return String::new(),
+        };
+
+        let mut lines = Vec::with_capacity(video::MAX_ROWS as usize);
+        for row in 0..video::MAX_ROWS as usize {
+            let line: String = (0..cols)
+                .map(|col| match vram[(row * cols + col) * 2] {
+                    0 => ' ',
+                    b @ 0x20..=0x7E => b as char,
+                    _ => '?',
+                })
+                .collect();
+            lines.push(line.trim_end().to_string());
+        }
+        lines.join("\n")
+    }
+
+    /// Renders the current screen: 640x400 RGB24, as the video sink sees it
+    pub fn framebuffer(&self) -> Vec<u8> {
+        let mut frame = vec![0; (video::SCREEN_WIDTH * video::SCREEN_HEIGHT * 3) as usize];
+        video::render_screen(&mut frame, &self.cpu.bus);
+        frame
+    }
+
+    /// Return code of the last program that terminated, if any has
+    pub fn exit_code(&self) -> Option<u8> {
+        self.cpu.exit_code
+    }
+
+    /// The shell's EXIT command was run
+    pub fn is_powered_off(&self) -> bool {
+        self.cpu.state == CpuState::PoweredOff
+    }
+
+    // Hands audio and video their share of the frame that just ended
+    fn end_frame(&mut self) {
+        pump_audio(&mut self.cpu.bus);
+        if let Some(mut sink) = self.video.take() {
+            sink.present(&self.framebuffer());
+            self.video = Some(sink);
+        }
+    }
+}
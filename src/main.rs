@@ -1,29 +1,17 @@
+use chrono::{Local, NaiveDate, NaiveDateTime, TimeDelta};
 use clap::Parser;
-use iced_x86::{Decoder, DecoderOptions, Mnemonic};
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
 use sdl2::pixels::PixelFormatEnum;
-use std::io::Write;
-use std::time::Duration;
-
-use crate::audio::pump_audio;
-use crate::command::CommandDispatcher;
-use crate::cpu::{Cpu, CpuFlags, CpuState};
-use crate::recorder::ScreenRecorder;
-use crate::video::VideoMode;
-
-mod audio;
-mod bus;
-mod command;
-mod cpu;
-mod disk;
-mod f80;
-mod instructions;
-mod interrupts;
-mod keyboard;
-mod recorder;
-mod shell;
-mod video;
+use std::time::{Duration, Instant};
+
+use rust_dos::bus;
+use rust_dos::clock::StartTime;
+use rust_dos::cpu::{ClockSpeed, CpuModel, FpuErrorLine, FpuModel};
+use rust_dos::keyboard;
+use rust_dos::machine::MachineBuilder;
+use rust_dos::recorder::ScreenRecorder;
+use rust_dos::video::{self, VideoMode};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -34,11 +22,91 @@ struct Args {
     /// Root directory for Drive C:
     #[arg(short, long, default_value = ".")]
     dir: String,
+
+    /// Treat drive C: as write-protected
+    #[arg(long)]
+    write_protect: bool,
+
+    /// CPU model: 8088, 8086, 186, 286 or 386
+    #[arg(long, default_value = "386")]
+    cpu: CpuModel,
+
+    /// Clock speed in MHz (e.g. 4.77, 8, 33) or "max". Defaults to the CPU's usual speed.
+    #[arg(long)]
+    clock: Option<ClockSpeed>,
+
+    /// Coprocessor: none, 8087, 287, 387 or p6. Defaults to the one usually paired with the CPU.
+    #[arg(long)]
+    fpu: Option<FpuModel>,
+
+    /// Where unmasked FPU exceptions are signalled: nmi (PC/XT) or irq13 (AT). Defaults by CPU.
+    #[arg(long)]
+    fpu_error: Option<FpuErrorLine>,
+
+    /// Memory above 1MB in KB, handed out through XMS
+    #[arg(long, default_value_t = bus::DEFAULT_EXTENDED_KB)]
+    extended_kb: u16,
+
+    /// Expanded memory in 16KB pages (0 disables EMS)
+    #[arg(long, default_value_t = bus::DEFAULT_EMS_PAGES)]
+    ems_pages: u16,
+
+    /// Segment of the 64KB EMS page frame, in hex
+    #[arg(long, default_value = "E000", value_parser = parse_segment)]
+    ems_frame: u16,
+
+    /// Leave C800-EFFF unmapped instead of offering it as upper memory blocks
+    #[arg(long)]
+    no_umb: bool,
+
+    /// Start the clock at this date (YYYY-MM-DD) or date and time (YYYY-MM-DDTHH:MM:SS)
+    /// instead of the host's
+    #[arg(long, value_parser = parse_date, conflicts_with = "date_offset")]
+    date: Option<NaiveDateTime>,
+
+    /// Start the clock this many days away from the host's date; negative for the past
+    #[arg(long, allow_negative_numbers = true)]
+    date_offset: Option<i64>,
+
+    /// Keep the CMOS settings in this file from one run to the next
+    #[arg(long)]
+    nvram: Option<std::path::PathBuf>,
+
+    /// Country code for date, time and currency formats, as in COUNTRY=
+    #[arg(long, default_value_t = 1)]
+    country: u16,
+
+    /// Code page (437, 850, 852 or 866); defaults to the country's
+    #[arg(long)]
+    code_page: Option<u16>,
+
+    /// Directory with fonts to use instead of the bundled ones, as CPnnn.F16 and CPnnn.F08
+    #[arg(long)]
+    font_dir: Option<std::path::PathBuf>,
 }
 
+fn parse_segment(s: &str) -> Result<u16, String> {
+    u16::from_str_radix(s.trim_end_matches(['h', 'H']), 16)
+        .map_err(|_| format!("Not a hex segment: {}", s))
+}
+
+// A date alone keeps the host's time of day
+fn parse_date(s: &str) -> Result<NaiveDateTime, String> {
+    NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S")
+        .or_else(|_| {
+            NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                .map(|date| date.and_time(Local::now().time()))
+        })
+        .map_err(|_| format!("Not a date: {}", s))
+}
+
+// Never try to catch up more than this much emulated time in one frame
+const MAX_FRAME_TIME: Duration = Duration::from_millis(50);
+// Wall time spent executing per frame when running unthrottled
+const MAX_SPEED_SLICE: Duration = Duration::from_millis(15);
+
 fn main() -> Result<(), String> {
     let args = Args::parse();
-    let mut debug_mode = false;
 
     let mut cursor_visible = true;
     let mut last_blink = std::time::Instant::now();
@@ -60,7 +128,6 @@ fn main() -> Result<(), String> {
     let audio_device = audio_subsystem
         .open_queue::<i16, _>(None, &desired_spec)
         .map_err(|e| e.to_string())?;
-    audio_device.resume();
 
     let window = video_subsystem
         .window(
@@ -83,13 +150,43 @@ fn main() -> Result<(), String> {
         )
         .map_err(|e| e.to_string())?;
 
-    let root_path = std::path::PathBuf::from(&args.dir);
-    let mut cpu = Cpu::new(root_path);
-    cpu.bus.audio_device = Some(audio_device);
+    let start = match (args.date, args.date_offset) {
+        (Some(date), _) => StartTime::Fixed(date),
+        (None, Some(days)) => StartTime::Offset(TimeDelta::days(days)),
+        (None, None) => StartTime::Host,
+    };
+    let mut builder = MachineBuilder::new(&args.dir)
+        .write_protect(args.write_protect)
+        .cpu(args.cpu)
+        .extended_kb(args.extended_kb)
+        .expanded_memory(args.ems_pages, args.ems_frame)
+        .upper_memory(!args.no_umb)
+        .start_time(start)
+        .country(args.country)
+        .audio_sink(audio_device);
+    if let Some(clock) = args.clock {
+        builder = builder.clock(clock);
+    }
+    if let Some(fpu) = args.fpu {
+        builder = builder.fpu(fpu);
+    }
+    if let Some(line) = args.fpu_error {
+        builder = builder.fpu_error(line);
+    }
+    if let Some(path) = args.nvram {
+        builder = builder.nvram_file(path);
+    }
+    if let Some(id) = args.code_page {
+        builder = builder.code_page(id);
+    }
+    if let Some(dir) = args.font_dir {
+        builder = builder.font_dir(dir);
+    }
+    // Loads the shell; the machine starts at the prompt
+    let mut machine = builder.try_build()?;
     let mut event_pump = sdl_context.event_pump()?;
 
-    // Load Shell Code into Memory
-    cpu.load_shell();
+    let mut last_frame = Instant::now();
 
     // Main Loop
     'running: loop {
@@ -103,7 +200,8 @@ fn main() -> Result<(), String> {
                 } => {
                     // Update BDA Shift Flags (0x0417)
                     // This lets INT 16h AH=02 report modifier state correctly
-                    let mut flags = cpu.bus.read_8(0x0417);
+                    let bus = &mut machine.cpu.bus;
+                    let mut flags = bus.read_8(0x0417);
                     match keycode {
                         Keycode::RShift => flags |= 0x01,
                         Keycode::LShift => flags |= 0x02,
@@ -112,7 +210,7 @@ fn main() -> Result<(), String> {
                         Keycode::CapsLock => flags ^= 0x40, // Toggle on press
                         _ => {}
                     }
-                    cpu.bus.write_8(0x0417, flags);
+                    bus.write_8(0x0417, flags);
 
                     // Recorder Toggle
                     if keycode == Keycode::PrintScreen {
@@ -122,17 +220,18 @@ fn main() -> Result<(), String> {
 
                     // Debug Toggle (F12 reserved for Emulator)
                     if keycode == Keycode::F12 {
-                        debug_mode = !debug_mode;
+                        let cpu = &mut machine.cpu;
+                        cpu.trace_enabled = !cpu.trace_enabled;
                         cpu.bus.log_string(&format!(
                             "[DEBUG] Tracing: {}",
-                            if debug_mode { "ON" } else { "OFF" }
+                            if cpu.trace_enabled { "ON" } else { "OFF" }
                         ));
                         continue;
                     }
 
                     // Map Key to PC Scancode/ASCII
                     if let Some(code) = keyboard::map_sdl_to_pc(keycode, keymod) {
-                        cpu.bus.keyboard_buffer.push_back(code);
+                        bus.keyboard_buffer.push_back(code);
                     }
                 }
                 // KeyUp only matters for modifiers
@@ -141,7 +240,8 @@ fn main() -> Result<(), String> {
                     ..
                 } => {
                     // Update BDA Shift Flags (Clear bits)
-                    let mut flags = cpu.bus.read_8(0x0417);
+                    let bus = &mut machine.cpu.bus;
+                    let mut flags = bus.read_8(0x0417);
                     match keycode {
                         Keycode::RShift => flags &= !0x01,
                         Keycode::LShift => flags &= !0x02,
@@ -149,7 +249,7 @@ fn main() -> Result<(), String> {
                         Keycode::LAlt | Keycode::RAlt => flags &= !0x08,
                         _ => {}
                     }
-                    cpu.bus.write_8(0x0417, flags);
+                    bus.write_8(0x0417, flags);
                 }
 
                 _ => {}
@@ -157,183 +257,37 @@ fn main() -> Result<(), String> {
         }
 
         // Execute instructions
-        for _ in 0..30_000 {
-            let prev_ip = cpu.ip;
-
-            // --- HANDLE PENDING COMMANDS (Outside Interrupts) ---
-            if let Some(cmd) = cpu.pending_command.take() {
-                // We have a command from the shell!
-                cpu.bus
-                    .log_string(&format!("[MAIN] Processing Command: {}", cmd));
-
-                let (command, args) = match cmd.split_once(' ') {
-                    Some((c, a)) => (c, a.trim()),
-                    None => (cmd.as_str(), ""),
-                };
-
-                let dispatcher = CommandDispatcher::new();
-
-                // Dispatch logic
-                if dispatcher.dispatch(&mut cpu, command, args) {
-                    // Built-in command executed. CPU continues shell loop.
-                } else {
-                    // Load Program
-                    let filename = command.to_string();
-                    let loaded = if !filename.contains('.') {
-                        cpu.load_executable(&format!("{}.com", command), None)
-                            || cpu.load_executable(&format!("{}.exe", command), None)
-                    } else {
-                        cpu.load_executable(&filename, None)
-                    };
-
-                    if !loaded {
-                        crate::video::print_string(&mut cpu, "Bad command or file name.\r\n");
-                    }
-                    // If loaded, load_executable() reset CS:IP.
-                    // The CPU will naturally start executing the new program next cycle.
-                }
-
-                // Skip the rest of this cycle to ensure clean state
-                continue;
-            }
-
-            // --- HANDLE STATE CHANGES ---
-            if cpu.state == CpuState::RebootShell {
-                cpu.load_shell();
-                cpu.state = CpuState::Running;
-
-                //TODO: Replace this hack with a proper fix
-                //Add a newline to make sure the prompt starts on a new line.
-                let col = cpu.bus.read_8(0x0450);
-                if col != 0 {
-                    video::print_string(&mut cpu, "\r\n");
-                }
-
-                break; // Break inner loop to refresh SDL
+        // Throttled clocks get as many cycles as the real time since the last frame is worth.
+        // Unthrottled runs for a fixed slice of wall time instead.
+        let frame_start = Instant::now();
+        let elapsed = frame_start.duration_since(last_frame).min(MAX_FRAME_TIME);
+        last_frame = frame_start;
+        let clock = machine.cpu.clock;
+        let target_cycles = match clock {
+            ClockSpeed::Hz(hz) => {
+                machine.cpu.cycles + (hz as u128 * elapsed.as_micros() / 1_000_000) as u64
             }
-
-            // Handle "IP = 0" as an explicit exit (Standard COM behavior)
-            // If the program jumps to the start of the segment, it wants to exit.
-            if cpu.ip == 0x0000 && cpu.cs == 0x1000 {
-                cpu.bus
-                    .log_string("[DOS] Program jumped to offset 0000h. Exiting to Shell.");
-                // Flush log on exit so we don't lose tail data
-                let _ = cpu.bus.log_file.as_mut().unwrap().flush();
-                cpu.load_shell();
-                cpu.state = CpuState::Running;
-                shell::show_prompt(&mut cpu);
+            ClockSpeed::Max => u64::MAX,
+        };
+        let mut executed: u32 = 0;
+
+        while machine.cpu.cycles < target_cycles {
+            executed = executed.wrapping_add(1);
+            if clock == ClockSpeed::Max
+                && executed.is_multiple_of(1024)
+                && frame_start.elapsed() >= MAX_SPEED_SLICE
+            {
                 break;
             }
 
-            // Current instruction
-            let phys_ip = cpu.get_physical_addr(cpu.cs, cpu.ip);
-            // Look ahead one instruction
-            let b0 = cpu.bus.read_8(phys_ip);
-            let b1 = cpu.bus.read_8(cpu.get_physical_addr(cpu.cs, cpu.ip + 1));
-            let bytes = &cpu.bus.ram[phys_ip..];
-
-            // If we are about to execute 00 00, stop immediately.
-            if bytes.len() >= 2 && bytes[0] == 0x00 && bytes[1] == 0x00 {
-                // panic!(
-                //     "[CRITICAL] CPU hit 00 00 (Empty RAM) at {:04X}:{:04X}",
-                //     cpu.cs, cpu.ip
-                // );
-            }
-
-            // Check for "BOP" (BIOS Operation) -> FE 38 XX
-            if b0 == 0xFE && b1 == 0x38 {
-                let vector = cpu.bus.read_8(cpu.get_physical_addr(cpu.cs, cpu.ip + 2));
-
-                // Run the HLE handler directly
-                crate::interrupts::handle_hle(&mut cpu, vector);
-
-                // Do not call real IRET, just simulate it
-                cpu.ip = cpu.pop();
-                cpu.cs = cpu.pop();
-
-                let hle_cf = cpu.get_cpu_flag(CpuFlags::CF);
-                let hle_zf = cpu.get_cpu_flag(CpuFlags::ZF);
-                let flags_to_restore = CpuFlags::from_bits_truncate(cpu.pop());
-
-                cpu.set_cpu_flags(flags_to_restore);
-                cpu.set_cpu_flag(CpuFlags::DF, false);
-                cpu.set_cpu_flag(CpuFlags::CF, hle_cf);
-                cpu.set_cpu_flag(CpuFlags::ZF, hle_zf);
-
-                continue; // Done for this cycle
-            }
-
-            let mut decoder = Decoder::with_ip(16, bytes, cpu.ip as u64, DecoderOptions::NONE);
-            let instr = decoder.decode();
-
-            if debug_mode || cpu.debug_qb_print {
-                // Filter out the 'Wait for Key' interrupt loop to save disk space
-                if !((instr.mnemonic() == Mnemonic::Int && instr.immediate8() == 0x16)
-                    || (instr.mnemonic() == Mnemonic::Jmp && instr.near_branch16() == 0x10E))
-                {
-                    // Skip BIOS area noise
-                    if cpu.cs < 0xF000 {
-                        // Format the instruction string manually since we can't capture stdout
-                        // (Assuming you want the same format as print_debug_trace)
-                        let instr_text = format!("{}", instr);
-                        let log_line = format!(
-                            "{:04X}:{:04X}  AX:{:04X} BX:{:04X} CX:{:04X} DX:{:04X} SP:{:04X}  {}",
-                            cpu.cs,
-                            cpu.ip,
-                            cpu.get_reg16(iced_x86::Register::AX),
-                            cpu.get_reg16(iced_x86::Register::BX),
-                            cpu.get_reg16(iced_x86::Register::CX),
-                            cpu.get_reg16(iced_x86::Register::DX),
-                            cpu.sp,
-                            instr_text
-                        );
-
-                        // Write to file, ignore errors to keep emulation fast
-                        let _ = cpu.bus.log_string(&log_line);
-
-                        if instr.mnemonic() == Mnemonic::Int {
-                            let vector = instr.immediate8();
-                            // Read IVT (Vector * 4) to find where this points
-                            let ivt_addr = (vector as usize) * 4;
-                            let target_cs = cpu.bus.read_16(ivt_addr + 2);
-                            let target_ip = cpu.bus.read_16(ivt_addr);
-
-                            if target_cs == 0xF000 {
-                                let log = format!(
-                                    "[CPU-DEBUG] Hooked INT {:02X} detected -> Points to F000:{:04X}",
-                                    vector, target_ip
-                                );
-                                cpu.bus.log_string(&log);
-                            }
-                        }
-                    }
-                }
+            // One instruction, BOP, shell command or program exit.
+            // The machine pumps audio as emulated frames go by.
+            machine.step();
+            if machine.is_powered_off() {
+                break 'running;
             }
-
-            cpu.trace_qb_conversion(&instr);
-
-            cpu.ip = instr.next_ip() as u16;
-
-            // Check State
-            if cpu.state == CpuState::RebootShell {
-                cpu.load_shell(); // Reloads assembly into RAM, resets IP/SP
-                cpu.state = CpuState::Running;
-                shell::show_prompt(&mut cpu);
-                break; // Break inner execution batch
-            }
-
-            // Yield if we are in a tight loop
-            if cpu.ip == prev_ip {
-                std::thread::yield_now();
-            }
-
-            // Make it so
-            instructions::execute_instruction(&mut cpu, &instr);
         }
 
-        // Update Audio
-        pump_audio(&mut cpu.bus);
-
         // Update Cursor Blink
         if last_blink.elapsed() >= blink_interval {
             cursor_visible = !cursor_visible;
@@ -344,11 +298,12 @@ fn main() -> Result<(), String> {
         // Note: We redraw every frame here for simplicity, even if VRAM isn't dirty
         texture.with_lock(None, |buffer: &mut [u8], _pitch: usize| {
             // Draw the base screen (text characters)
-            video::render_screen(buffer, &cpu.bus);
+            let bus = &machine.cpu.bus;
+            video::render_screen(buffer, bus);
 
             // Draw the Cursor (Overlay)
             // Only draw the hardware cursor in Text Modes!
-            let current_mode = cpu.bus.video_mode;
+            let current_mode = bus.video_mode;
             let is_text_mode = matches!(
                 current_mode,
                 VideoMode::Text80x25
@@ -358,11 +313,11 @@ fn main() -> Result<(), String> {
             );
             if is_text_mode {
                 // Read Cursor Position from BDA
-                let cursor_col = cpu.bus.read_8(0x0450) as usize;
-                let cursor_row = cpu.bus.read_8(0x0451) as usize;
+                let cursor_col = bus.read_8(0x0450) as usize;
+                let cursor_row = bus.read_8(0x0451) as usize;
 
                 // Read Cursor Shape from BDA
-                let cursor_shape = cpu.bus.read_16(0x0460);
+                let cursor_shape = bus.read_16(0x0460);
                 let start_scan = (cursor_shape >> 8) as u8;
                 let end_scan = (cursor_shape & 0xFF) as u8;
 
@@ -437,7 +392,9 @@ fn main() -> Result<(), String> {
         canvas.copy(&texture, None, None)?;
         canvas.present();
 
-        std::thread::sleep(Duration::from_millis(16));
+        if clock != ClockSpeed::Max {
+            std::thread::sleep(Duration::from_millis(16));
+        }
     }
 
     Ok(())
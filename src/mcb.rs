@@ -0,0 +1,387 @@
+use crate::bus::Bus;
+
+// DOS memory arenas (INT 21h 48h/49h/4Ah/58h).
+//
+// Conventional memory above the shell is a chain of memory control blocks,
+// laid out as in DOS: each block is preceded by a one paragraph header
+//
+//   00  'M', or 'Z' for the last block
+//   01  Owner PSP segment, 0 when free
+//   03  Size in paragraphs, not counting the header
+//   08  Program name (DOS 4+), padded with zeros
+//
+// Free neighbours are only joined when the chain is searched, like DOS does.
+//
+// With upper memory, the last paragraph below the top of conventional memory
+// holds a DOS-owned "SC" block spanning the gap up to the first UMB, and every
+// UMB but the last ends with one spanning the gap to the next. The
+// conventional arena ends with 'Z' until INT 21h 5803h links the UMBs in.
+
+/// Header of the first block; the first program's PSP lands at 1000h
+pub const FIRST_MCB: u16 = 0x0FFF;
+
+/// Owner of blocks that belong to DOS itself
+pub const OWNER_DOS: u16 = 0x0008;
+
+// Allocation strategies (INT 21h 5801h), low bits
+pub const FIRST_FIT: u8 = 0x00;
+pub const BEST_FIT: u8 = 0x01;
+pub const LAST_FIT: u8 = 0x02;
+// High bits, honoured while the UMBs are linked
+pub const UPPER_ONLY: u8 = 0x40;
+pub const UPPER_FIRST: u8 = 0x80;
+
+// DOS error codes, returned in AX
+pub const ERR_INVALID_FUNCTION: u8 = 0x01;
+pub const ERR_ARENA_TRASHED: u8 = 0x07;
+pub const ERR_INSUFFICIENT_MEMORY: u8 = 0x08;
+pub const ERR_INVALID_BLOCK: u8 = 0x09;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mcb {
+    /// Segment of the header itself; the block starts one paragraph later
+    pub segment: u16,
+    pub last: bool,
+    pub owner: u16,
+    pub size: u16,
+}
+
+impl Mcb {
+    pub fn read(bus: &Bus, segment: u16) -> Result<Mcb, u8> {
+        let addr = (segment as usize) << 4;
+        let last = match bus.read_8(addr) {
+            b'M' => false,
+            b'Z' => true,
+            _ => return Err(ERR_ARENA_TRASHED),
+        };
+        Ok(Mcb {
+            segment,
+            last,
+            owner: bus.read_16(addr + 1),
+            size: bus.read_16(addr + 3),
+        })
+    }
+
+    fn write(&self, bus: &mut Bus) {
+        let addr = (self.segment as usize) << 4;
+        bus.write_8(addr, if self.last { b'Z' } else { b'M' });
+        bus.write_16(addr + 1, self.owner);
+        bus.write_16(addr + 3, self.size);
+    }
+
+    /// First segment of the block's memory
+    pub fn data(&self) -> u16 {
+        self.segment.wrapping_add(1)
+    }
+
+    /// Segment just past the block, where the next header is
+    pub fn end(&self) -> u16 {
+        self.data().wrapping_add(self.size)
+    }
+
+    pub fn is_free(&self) -> bool {
+        self.owner == 0
+    }
+
+    pub fn name(&self, bus: &Bus) -> String {
+        let addr = ((self.segment as usize) << 4) + 8;
+        (0..8)
+            .map(|i| bus.read_8(addr + i))
+            .take_while(|&b| b != 0)
+            .map(|b| b as char)
+            .collect()
+    }
+}
+
+// Header of the block bridging conventional memory and the UMBs
+fn link_segment(bus: &Bus) -> u16 {
+    bus.memory_top() - 1
+}
+
+/// Starts over with one free block covering conventional memory, and one per UMB
+pub fn init(bus: &mut Bus) {
+    let top = bus.memory_top();
+    let upper = bus.upper_memory().to_vec();
+    bus.xms.umbs.clear();
+    let mut block = Mcb {
+        segment: FIRST_MCB,
+        last: true,
+        owner: 0,
+        size: top - FIRST_MCB - 1,
+    };
+    if upper.is_empty() {
+        block.write(bus);
+        set_name(bus, block.data(), "");
+        return;
+    }
+
+    block.size -= 1;
+    block.last = !bus.umb_linked;
+    block.write(bus);
+    set_name(bus, block.data(), "");
+
+    // System blocks cover the gaps: below A000 up to the first UMB, then between UMBs
+    let mut gap = link_segment(bus);
+    for (i, range) in upper.iter().enumerate() {
+        let system = Mcb {
+            segment: gap,
+            last: false,
+            owner: OWNER_DOS,
+            size: range.start - gap - 1,
+        };
+        system.write(bus);
+        set_name(bus, system.data(), "SC");
+
+        let last = i + 1 == upper.len();
+        let umb = Mcb {
+            segment: range.start,
+            last,
+            owner: 0,
+            size: range.end - range.start - if last { 1 } else { 2 },
+        };
+        umb.write(bus);
+        set_name(bus, umb.data(), "");
+        gap = umb.end();
+    }
+}
+
+// Follows headers from `segment` up to the 'Z' block, which must end by `limit`
+fn walk(bus: &Bus, mut segment: u16, limit: u16) -> Result<Vec<Mcb>, u8> {
+    let mut blocks = Vec::new();
+    loop {
+        let block = Mcb::read(bus, segment)?;
+        // A block running past the end of memory means the chain is corrupt
+        if block.end() > limit || block.end() < block.data() {
+            return Err(ERR_ARENA_TRASHED);
+        }
+        blocks.push(block);
+        if block.last {
+            return Ok(blocks);
+        }
+        segment = block.end();
+    }
+}
+
+// End of the upper arena, or the top of conventional memory without UMBs
+fn upper_end(bus: &Bus) -> u16 {
+    bus.upper_memory().last().map_or(bus.memory_top(), |r| r.end)
+}
+
+/// The chain as DOS sees it, in address order. It runs on into upper memory
+/// while the UMBs are linked.
+pub fn chain(bus: &Bus) -> Result<Vec<Mcb>, u8> {
+    let limit = if bus.umb_linked { upper_end(bus) } else { bus.memory_top() };
+    walk(bus, FIRST_MCB, limit)
+}
+
+// Every block in both arenas, linked or not
+fn all_blocks(bus: &Bus) -> Result<Vec<Mcb>, u8> {
+    let mut blocks = chain(bus)?;
+    if !bus.umb_linked && !bus.upper_memory().is_empty() {
+        blocks.extend(walk(bus, link_segment(bus), upper_end(bus))?);
+    }
+    Ok(blocks)
+}
+
+// Joins runs of free blocks, returning the tidied chain
+fn coalesce(bus: &mut Bus) -> Result<Vec<Mcb>, u8> {
+    let mut blocks = all_blocks(bus)?;
+    let mut i = 0;
+    while i + 1 < blocks.len() {
+        if blocks[i].is_free() && blocks[i + 1].is_free() && blocks[i].end() == blocks[i + 1].segment {
+            let next = blocks.remove(i + 1);
+            blocks[i].size += next.size + 1;
+            blocks[i].last = next.last;
+            blocks[i].write(bus);
+        } else {
+            i += 1;
+        }
+    }
+    Ok(blocks)
+}
+
+// Splits `block` so it keeps `paras`, turning the rest into a free block
+fn split(bus: &mut Bus, block: &mut Mcb, paras: u16) {
+    if block.size == paras {
+        return;
+    }
+    let rest = Mcb {
+        segment: block.data() + paras,
+        last: block.last,
+        owner: 0,
+        size: block.size - paras - 1,
+    };
+    rest.write(bus);
+    set_name(bus, rest.data(), "");
+    block.size = paras;
+    block.last = false;
+    block.write(bus);
+}
+
+// The block whose memory starts at `segment`
+fn find(blocks: &[Mcb], segment: u16) -> Result<usize, u8> {
+    blocks
+        .iter()
+        .position(|b| b.data() == segment && !b.is_free())
+        .ok_or(ERR_INVALID_BLOCK)
+}
+
+// Free blocks DOS may hand out: conventional ones, plus upper ones while linked
+fn free_blocks(bus: &mut Bus) -> Result<Vec<Mcb>, u8> {
+    let top = bus.memory_top();
+    let linked = bus.umb_linked;
+    let blocks = coalesce(bus)?;
+    Ok(blocks
+        .into_iter()
+        .filter(|b| b.is_free() && (linked || b.segment < top))
+        .collect())
+}
+
+/// Size of the largest free block, in paragraphs
+pub fn largest_free(bus: &mut Bus) -> u16 {
+    free_blocks(bus)
+        .ok()
+        .and_then(|blocks| blocks.iter().map(|b| b.size).max())
+        .unwrap_or(0)
+}
+
+// Carves `paras` out of one of `candidates`, picked by the fit in `strategy`
+fn take(bus: &mut Bus, candidates: &[Mcb], paras: u16, owner: u16, strategy: u8) -> Result<u16, (u8, u16)> {
+    let mut fits = candidates.iter().filter(|b| b.size >= paras);
+    let found = match strategy & 0x03 {
+        BEST_FIT => fits.min_by_key(|b| b.size),
+        LAST_FIT => fits.next_back(),
+        _ => fits.next(),
+    };
+    let Some(&found) = found else {
+        let largest = candidates.iter().map(|b| b.size).max();
+        return Err((ERR_INSUFFICIENT_MEMORY, largest.unwrap_or(0)));
+    };
+
+    let mut block = found;
+    if strategy & 0x03 == LAST_FIT && block.size > paras {
+        // Take the top of the block, leaving the bottom free
+        let free_size = block.size - paras - 1;
+        split(bus, &mut block, free_size);
+        block = Mcb::read(bus, block.end()).map_err(|e| (e, 0))?;
+    } else {
+        split(bus, &mut block, paras);
+    }
+    // Blocks taken outside of any process (owner 0) would look free
+    block.owner = if owner == 0 { OWNER_DOS } else { owner };
+    block.write(bus);
+    set_name(bus, block.data(), "");
+    Ok(block.data())
+}
+
+/// Allocates `paras` paragraphs for `owner` using `strategy` (INT 21h 48h).
+/// Returns the block's segment, or the error and the largest free block.
+pub fn allocate(bus: &mut Bus, paras: u16, owner: u16, strategy: u8) -> Result<u16, (u8, u16)> {
+    let top = bus.memory_top();
+    let blocks = free_blocks(bus).map_err(|e| (e, 0))?;
+    if bus.umb_linked && strategy & (UPPER_ONLY | UPPER_FIRST) != 0 {
+        let upper: Vec<Mcb> = blocks.iter().filter(|b| b.segment >= top).copied().collect();
+        let result = take(bus, &upper, paras, owner, strategy);
+        if result.is_ok() || strategy & UPPER_ONLY != 0 {
+            return result;
+        }
+    }
+    take(bus, &blocks, paras, owner, strategy)
+}
+
+/// Allocates from upper memory whether or not it is linked (XMS function 10h)
+pub fn allocate_upper(bus: &mut Bus, paras: u16, owner: u16) -> Result<u16, (u8, u16)> {
+    let top = bus.memory_top();
+    let blocks = coalesce(bus).map_err(|e| (e, 0))?;
+    let upper: Vec<Mcb> = blocks
+        .into_iter()
+        .filter(|b| b.is_free() && b.segment >= top)
+        .collect();
+    take(bus, &upper, paras, owner, FIRST_FIT)
+}
+
+/// Gives a block back (INT 21h 49h)
+pub fn free(bus: &mut Bus, segment: u16) -> Result<(), u8> {
+    let blocks = all_blocks(bus)?;
+    let mut block = blocks[find(&blocks, segment)?];
+    block.owner = 0;
+    block.write(bus);
+    Ok(())
+}
+
+/// Grows or shrinks a block in place (INT 21h 4Ah).
+/// On failure, returns the error and the most the block could hold.
+pub fn resize(bus: &mut Bus, segment: u16, paras: u16) -> Result<(), (u8, u16)> {
+    let blocks = all_blocks(bus).map_err(|e| (e, 0))?;
+    let index = find(&blocks, segment).map_err(|e| (e, 0))?;
+    let mut block = blocks[index];
+
+    // Room to grow: the free blocks right after this one
+    let mut available = block.size;
+    let mut last = block.last;
+    let mut end = block.end();
+    for next in &blocks[index + 1..] {
+        if !next.is_free() || next.segment != end {
+            break;
+        }
+        available += next.size + 1;
+        last = next.last;
+        end = next.end();
+    }
+    if paras > available {
+        return Err((ERR_INSUFFICIENT_MEMORY, available));
+    }
+
+    if paras > block.size {
+        block.size = available;
+        block.last = last;
+        block.write(bus);
+    }
+    split(bus, &mut block, paras);
+    Ok(())
+}
+
+/// Frees everything `owner` holds, when a process terminates
+pub fn free_owned(bus: &mut Bus, owner: u16) {
+    let Ok(blocks) = all_blocks(bus) else {
+        bus.log_string("[DOS] Memory arena trashed, blocks not freed");
+        return;
+    };
+    for mut block in blocks.into_iter().filter(|b| b.owner == owner) {
+        block.owner = 0;
+        block.write(bus);
+    }
+}
+
+/// Links the UMBs into the chain or cuts them off again (INT 21h 5803h)
+pub fn link_upper(bus: &mut Bus, linked: bool) -> Result<(), u8> {
+    if bus.upper_memory().is_empty() {
+        return Err(ERR_INVALID_FUNCTION);
+    }
+    let link = link_segment(bus);
+    let blocks = chain(bus)?;
+    let Some(mut block) = blocks.into_iter().find(|b| b.end() == link) else {
+        return Err(ERR_ARENA_TRASHED);
+    };
+    block.last = !linked;
+    block.write(bus);
+    bus.umb_linked = linked;
+    Ok(())
+}
+
+/// Hands the block at `segment` to another owner
+pub fn set_owner(bus: &mut Bus, segment: u16, owner: u16) {
+    bus.write_16(((segment as usize - 1) << 4) + 1, owner);
+}
+
+/// Records the program name in the header of the block at `segment`
+pub fn set_name(bus: &mut Bus, segment: u16, name: &str) {
+    let addr = (segment as usize - 1) << 4;
+    let mut bytes = [0u8; 8];
+    for (dst, src) in bytes.iter_mut().zip(name.bytes()) {
+        *dst = src.to_ascii_uppercase();
+    }
+    for (i, &b) in bytes.iter().enumerate() {
+        bus.write_8(addr + 8 + i, b);
+    }
+}
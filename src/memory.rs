@@ -0,0 +1,95 @@
+use crate::devices::DeviceId;
+
+// Physical memory map.
+//
+// The first megabyte is split into 2KB pages (the granularity of option ROMs),
+// each tagged with what backs it. `Bus::read_8`/`write_8` look the page up in a
+// flat table, so plain RAM costs one index and a compare on top of the access.
+//
+// Default PC layout:
+//   00000-9FFFF  RAM (conventional memory)
+//   A0000-AFFFF  VGA graphics window
+//   B0000-B7FFF  open bus (no monochrome adapter)
+//   B8000-BFFFF  VGA text / CGA window
+//   C0000-C7FFF  VGA BIOS
+//   C8000-EFFFF  open bus, option ROM slots
+//   F0000-FFFFF  system BIOS (signature tables, HLE traps)
+//   100000-10FFEF High Memory Area, reachable from FFFF:0010 up with A20 on
+
+/// End of what real mode can address: 1MB plus the HMA, rounded to a page
+pub const ADDRESS_SPACE: usize = 0x110000;
+pub const HMA_START: usize = 0x100000;
+
+/// Address masks for the A20 gate. With A20 off, FFFF:0010 wraps to 0000:0000.
+pub const A20_OFF_MASK: usize = 0x0FFFFF;
+pub const A20_ON_MASK: usize = 0x1FFFFF;
+
+pub const PAGE_SHIFT: usize = 11;
+pub const PAGE_SIZE: usize = 1 << PAGE_SHIFT;
+
+/// Option ROMs are scanned for on 2KB boundaries in this range
+pub const OPTION_ROM_START: usize = 0xC8000;
+pub const OPTION_ROM_END: usize = 0xF0000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+    Ram,
+    /// Reads come from RAM, guest writes are dropped
+    Rom,
+    /// Handled by a device in the port registry
+    Mmio(DeviceId),
+    /// Nothing there: reads float high, writes vanish
+    Unmapped,
+}
+
+pub struct MemoryMap {
+    pages: Vec<Region>,
+}
+
+impl MemoryMap {
+    /// All RAM
+    pub fn new(size: usize) -> Self {
+        Self {
+            pages: vec![Region::Ram; size >> PAGE_SHIFT],
+        }
+    }
+
+    /// Maps `len` bytes at `start`; both must be page aligned
+    pub fn map(&mut self, start: usize, len: usize, region: Region) {
+        assert!(
+            start.is_multiple_of(PAGE_SIZE) && len.is_multiple_of(PAGE_SIZE),
+            "Unaligned memory region {:05X}+{:X}",
+            start,
+            len
+        );
+        let first = start >> PAGE_SHIFT;
+        let last = ((start + len) >> PAGE_SHIFT).min(self.pages.len());
+        self.pages[first..last].fill(region);
+    }
+
+    #[inline]
+    pub fn region(&self, addr: usize) -> Region {
+        self.pages
+            .get(addr >> PAGE_SHIFT)
+            .copied()
+            .unwrap_or(Region::Unmapped)
+    }
+}
+
+/// Checks an option ROM image: 55AA signature, length in 512-byte blocks at
+/// offset 2, and all bytes of that length summing to zero.
+/// Returns the length in bytes.
+pub fn validate_option_rom(image: &[u8]) -> Result<usize, String> {
+    if image.len() < 3 || image[0] != 0x55 || image[1] != 0xAA {
+        return Err("missing 55AA signature".to_string());
+    }
+    let len = image[2] as usize * 512;
+    if len == 0 || len > image.len() {
+        return Err(format!("header claims {} bytes, image has {}", len, image.len()));
+    }
+    let sum = image[..len].iter().fold(0u8, |acc, &b| acc.wrapping_add(b));
+    if sum != 0 {
+        return Err(format!("bad checksum {:02X}", sum));
+    }
+    Ok(len)
+}
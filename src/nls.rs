@@ -0,0 +1,497 @@
+use std::path::PathBuf;
+
+use chrono::{NaiveDate, NaiveTime, Timelike};
+
+use crate::bus::Bus;
+use crate::video::Font;
+
+// National language support (INT 21h 38h, 65h and 66h).
+//
+// The country decides how dates, times and money are written; the code page
+// decides what the bytes 80h-FFh stand for, and with them the upper-casing
+// and collating tables and the text mode font. Every code page's tables sit
+// in the BIOS segment, where INT 21h 65h hands out far pointers to them:
+//
+//   F000:0000 + 200h * n  Case map routine: far call, AL in and out
+//                 + 010h  Upper-case table for 80h-FFh, after its size word
+//                 + 0A0h  Collating table for 00h-FFh, after its size word
+//   F000:0800             Filename terminator table
+//   F000:0820             DBCS lead byte ranges: none
+//
+// Every supported code page has a bundled font. A font directory overrides
+// them with raw bitmaps named CPnnn.F16 (8x16) and CPnnn.F08 (8x8).
+
+/// DOS reports countries and code pages it has no data for as a missing file
+pub const ERR_FILE_NOT_FOUND: u8 = 0x02;
+pub const ERR_INVALID_FUNCTION: u8 = 0x01;
+
+const TABLE_SEGMENT: u16 = 0xF000;
+const BLOCK_SIZE: u16 = 0x0200;
+const UPPERCASE_OFFSET: u16 = 0x0010;
+const COLLATING_OFFSET: u16 = 0x00A0;
+const TERMINATORS: u16 = 0x0800;
+const DBCS: u16 = 0x0820;
+
+/// Date order in the country information
+pub const MDY: u16 = 0;
+pub const DMY: u16 = 1;
+pub const YMD: u16 = 2;
+
+pub struct Country {
+    pub code: u16,
+    /// The code page the country starts out with
+    pub code_page: u16,
+    pub date_format: u16,
+    pub currency: &'static str,
+    pub thousands: char,
+    pub decimal: char,
+    pub date_separator: char,
+    pub time_separator: char,
+    pub list_separator: char,
+    /// Bit 0: symbol after the amount, bit 1: space between, bit 2: symbol
+    /// replaces the decimal separator
+    pub currency_format: u8,
+    pub currency_digits: u8,
+    pub clock_24h: bool,
+    pub yes: char,
+    pub no: char,
+}
+
+// Separators: thousands, decimal, date, time, list
+#[allow(clippy::too_many_arguments)]
+const fn country(
+    code: u16,
+    code_page: u16,
+    date_format: u16,
+    currency: &'static str,
+    [thousands, decimal, date_separator, time_separator, list_separator]: [char; 5],
+    currency_format: u8,
+    currency_digits: u8,
+    clock_24h: bool,
+    [yes, no]: [char; 2],
+) -> Country {
+    Country {
+        code,
+        code_page,
+        date_format,
+        currency,
+        thousands,
+        decimal,
+        date_separator,
+        time_separator,
+        list_separator,
+        currency_format,
+        currency_digits,
+        clock_24h,
+        yes,
+        no,
+    }
+}
+
+/// Countries by their telephone codes, as in COUNTRY.SYS
+pub const COUNTRIES: &[Country] = &[
+    country(1, 437, MDY, "$", [',', '.', '-', ':', ','], 0, 2, false, ['Y', 'N']),
+    country(2, 850, YMD, "$", [' ', ',', '-', ':', ';'], 3, 2, true, ['O', 'N']),
+    country(3, 850, DMY, "$", [',', '.', '/', ':', ','], 0, 2, false, ['S', 'N']),
+    country(7, 866, DMY, "р.", [' ', ',', '.', ':', ';'], 3, 2, true, ['Д', 'Н']),
+    country(31, 850, DMY, "ƒ", ['.', ',', '-', ':', ';'], 2, 2, true, ['J', 'N']),
+    country(32, 850, DMY, "BEF", ['.', ',', '/', ':', ';'], 3, 2, true, ['J', 'N']),
+    country(33, 850, DMY, "F", [' ', ',', '.', ':', ';'], 3, 2, true, ['O', 'N']),
+    country(34, 850, DMY, "Pts", ['.', ',', '/', ':', ';'], 3, 0, true, ['S', 'N']),
+    country(36, 852, YMD, "Ft", [' ', ',', '.', ':', ';'], 3, 2, true, ['I', 'N']),
+    country(39, 850, DMY, "L.", ['.', ',', '/', '.', ';'], 2, 0, true, ['S', 'N']),
+    country(41, 850, DMY, "Fr", ['\'', '.', '.', ',', ';'], 2, 2, true, ['J', 'N']),
+    country(42, 852, DMY, "Kč", [' ', ',', '.', ':', ';'], 3, 2, true, ['A', 'N']),
+    country(44, 437, DMY, "£", [',', '.', '/', ':', ','], 0, 2, true, ['Y', 'N']),
+    country(45, 850, DMY, "kr", ['.', ',', '-', '.', ';'], 2, 2, true, ['J', 'N']),
+    country(46, 850, YMD, "Kr", [' ', ',', '-', '.', ';'], 3, 2, true, ['J', 'N']),
+    country(47, 850, DMY, "Kr", ['.', ',', '.', ':', ';'], 2, 2, true, ['J', 'N']),
+    country(48, 852, YMD, "Zł", [' ', ',', '-', ':', ';'], 3, 2, true, ['T', 'N']),
+    country(49, 850, DMY, "DM", ['.', ',', '.', ':', ';'], 3, 2, true, ['J', 'N']),
+    country(61, 437, DMY, "$", [',', '.', '-', ':', ','], 0, 2, false, ['Y', 'N']),
+    country(351, 850, DMY, "Esc.", ['.', ',', '-', ':', ';'], 3, 2, true, ['S', 'N']),
+    country(358, 850, DMY, "mk", [' ', ',', '.', '.', ';'], 3, 2, true, ['K', 'E']),
+];
+
+impl Country {
+    pub fn find(code: u16) -> Option<&'static Country> {
+        COUNTRIES.iter().find(|country| country.code == code)
+    }
+
+    /// The date with a four-digit year, in the country's order
+    pub fn format_date(&self, date: NaiveDate) -> String {
+        let order = match self.date_format {
+            MDY => "%m{}%d{}%Y",
+            DMY => "%d{}%m{}%Y",
+            _ => "%Y{}%m{}%d",
+        };
+        let sep = self.date_separator.to_string();
+        date.format(&order.replace("{}", &sep)).to_string()
+    }
+
+    /// Hours and minutes, with an a/p suffix on a 12-hour clock
+    pub fn format_time(&self, time: NaiveTime) -> String {
+        let sep = self.time_separator;
+        if self.clock_24h {
+            format!("{:2}{}{:02}", time.hour(), sep, time.minute())
+        } else {
+            let (pm, hour) = time.hour12();
+            let suffix = if pm { 'p' } else { 'a' };
+            format!("{:2}{}{:02}{}", hour, sep, time.minute(), suffix)
+        }
+    }
+
+    /// Day, month and year from the parts of a date typed in the country's order
+    pub fn date_parts(&self, parts: [u32; 3]) -> (u32, u32, u32) {
+        let [a, b, c] = parts;
+        match self.date_format {
+            MDY => (b, a, c),
+            DMY => (a, b, c),
+            _ => (c, b, a),
+        }
+    }
+
+    /// The 34-byte country information of INT 21h 38h
+    pub fn info(&self, code_page: &CodePage, case_map: u32) -> [u8; 34] {
+        let mut info = [0; 34];
+        info[0x00..0x02].copy_from_slice(&self.date_format.to_le_bytes());
+        for (i, c) in self.currency.chars().take(4).enumerate() {
+            info[0x02 + i] = code_page.encode(c).unwrap_or(b'?');
+        }
+        let separators = [
+            (0x07, self.thousands),
+            (0x09, self.decimal),
+            (0x0B, self.date_separator),
+            (0x0D, self.time_separator),
+            (0x16, self.list_separator),
+        ];
+        for (offset, c) in separators {
+            info[offset] = code_page.encode(c).unwrap_or(b' ');
+        }
+        info[0x0F] = self.currency_format;
+        info[0x10] = self.currency_digits;
+        info[0x11] = self.clock_24h as u8;
+        info[0x12..0x16].copy_from_slice(&case_map.to_le_bytes());
+        info
+    }
+}
+
+/// Bytes 80h-FFh of a code page as Unicode, and what each one upper-cases to
+/// and sorts as. Accented letters the code page has no capital for lose
+/// their accents, as in MS-DOS's tables.
+pub struct CodePage {
+    pub id: u16,
+    chars: &'static str,
+    upper: &'static str,
+    sort: &'static str,
+}
+
+pub const CODE_PAGES: &[CodePage] = &[
+    CodePage {
+        id: 437,
+        chars: concat!(
+            "ÇüéâäàåçêëèïîìÄÅÉæÆôöòûùÿÖÜ¢£¥₧ƒ",
+            "áíóúñÑªº¿⌐¬½¼¡«»░▒▓│┤╡╢╖╕╣║╗╝╜╛┐",
+            "└┴┬├─┼╞╟╚╔╩╦╠═╬╧╨╤╥╙╘╒╓╫╪┘┌█▄▌▐▀",
+            "αßΓπΣσµτΦΘΩδ∞φε∩≡±≥≤⌠⌡÷≈°∙·√ⁿ²■\u{a0}",
+        ),
+        upper: concat!(
+            "ÇÜÉAÄAÅÇEEEIIIÄÅÉÆÆOÖOUUYÖÜ¢£¥₧ƒ",
+            "AIOUÑÑªº¿⌐¬½¼¡«»░▒▓│┤╡╢╖╕╣║╗╝╜╛┐",
+            "└┴┬├─┼╞╟╚╔╩╦╠═╬╧╨╤╥╙╘╒╓╫╪┘┌█▄▌▐▀",
+            "αßΓπΣσµτΦΘΩδ∞φε∩≡±≥≤⌠⌡÷≈°∙·√ⁿ²■\u{a0}",
+        ),
+        sort: concat!(
+            "CUEAAAACEEEIIIAAEAAOOOUUYOU¢£¥₧F",
+            "AIOUNNªº¿⌐¬½¼¡«»░▒▓│┤╡╢╖╕╣║╗╝╜╛┐",
+            "└┴┬├─┼╞╟╚╔╩╦╠═╬╧╨╤╥╙╘╒╓╫╪┘┌█▄▌▐▀",
+            "αSΓπΣσµτΦΘΩδ∞φε∩≡±≥≤⌠⌡÷≈°∙·√ⁿ²■\u{a0}",
+        ),
+    },
+    CodePage {
+        id: 850,
+        chars: concat!(
+            "ÇüéâäàåçêëèïîìÄÅÉæÆôöòûùÿÖÜø£Ø×ƒ",
+            "áíóúñÑªº¿®¬½¼¡«»░▒▓│┤ÁÂÀ©╣║╗╝¢¥┐",
+            "└┴┬├─┼ãÃ╚╔╩╦╠═╬¤ðÐÊËÈıÍÎÏ┘┌█▄¦Ì▀",
+            "ÓßÔÒõÕµþÞÚÛÙýÝ¯´\u{ad}±‗¾¶§÷¸°¨·¹³²■\u{a0}",
+        ),
+        upper: concat!(
+            "ÇÜÉÂÄÀÅÇÊËÈÏÎÌÄÅÉÆÆÔÖÒÛÙYÖÜØ£Ø×ƒ",
+            "ÁÍÓÚÑÑªº¿®¬½¼¡«»░▒▓│┤ÁÂÀ©╣║╗╝¢¥┐",
+            "└┴┬├─┼ÃÃ╚╔╩╦╠═╬¤ÐÐÊËÈIÍÎÏ┘┌█▄¦Ì▀",
+            "ÓßÔÒÕÕµÞÞÚÛÙÝÝ¯´\u{ad}±‗¾¶§÷¸°¨·¹³²■\u{a0}",
+        ),
+        sort: concat!(
+            "CUEAAAACEEEIIIAAEAAOOOUUYOUO£O×F",
+            "AIOUNNªº¿®¬½¼¡«»░▒▓│┤AAA©╣║╗╝¢¥┐",
+            "└┴┬├─┼AA╚╔╩╦╠═╬¤DDEEEIIII┘┌█▄¦I▀",
+            "OSOOOOµTTUUUYY¯´\u{ad}±‗¾¶§÷¸°¨·¹³²■\u{a0}",
+        ),
+    },
+    CodePage {
+        id: 852,
+        chars: concat!(
+            "ÇüéâäůćçłëŐőîŹÄĆÉĹĺôöĽľŚśÖÜŤťŁ×č",
+            "áíóúĄąŽžĘę¬źČş«»░▒▓│┤ÁÂĚŞ╣║╗╝Żż┐",
+            "└┴┬├─┼Ăă╚╔╩╦╠═╬¤đĐĎËďŇÍÎě┘┌█▄ŢŮ▀",
+            "ÓßÔŃńňŠšŔÚŕŰýÝţ´\u{ad}˝˛ˇ˘§÷¸°¨˙űŘř■\u{a0}",
+        ),
+        upper: concat!(
+            "ÇÜÉÂÄŮĆÇŁËŐŐÎŹÄĆÉĹĹÔÖĽĽŚŚÖÜŤŤŁ×Č",
+            "ÁÍÓÚĄĄŽŽĘĘ¬ŹČŞ«»░▒▓│┤ÁÂĚŞ╣║╗╝ŻŻ┐",
+            "└┴┬├─┼ĂĂ╚╔╩╦╠═╬¤ĐĐĎËĎŇÍÎĚ┘┌█▄ŢŮ▀",
+            "ÓßÔŃŃŇŠŠŔÚŔŰÝÝŢ´\u{ad}˝˛ˇ˘§÷¸°¨˙ŰŘŘ■\u{a0}",
+        ),
+        sort: concat!(
+            "CUEAAUCCLEOOIZACELLOOLLSSOUTTL×C",
+            "AIOUAAZZEE¬ZCS«»░▒▓│┤AAES╣║╗╝ZZ┐",
+            "└┴┬├─┼AA╚╔╩╦╠═╬¤DDDEDNIIE┘┌█▄TU▀",
+            "OSONNNSSRURUYYT´\u{ad}˝˛ˇ˘§÷¸°¨˙URR■\u{a0}",
+        ),
+    },
+    CodePage {
+        id: 866,
+        chars: concat!(
+            "АБВГДЕЖЗИЙКЛМНОПРСТУФХЦЧШЩЪЫЬЭЮЯ",
+            "абвгдежзийклмноп░▒▓│┤╡╢╖╕╣║╗╝╜╛┐",
+            "└┴┬├─┼╞╟╚╔╩╦╠═╬╧╨╤╥╙╘╒╓╫╪┘┌█▄▌▐▀",
+            "рстуфхцчшщъыьэюяЁёЄєЇїЎў°∙·√№¤■\u{a0}",
+        ),
+        upper: concat!(
+            "АБВГДЕЖЗИЙКЛМНОПРСТУФХЦЧШЩЪЫЬЭЮЯ",
+            "АБВГДЕЖЗИЙКЛМНОП░▒▓│┤╡╢╖╕╣║╗╝╜╛┐",
+            "└┴┬├─┼╞╟╚╔╩╦╠═╬╧╨╤╥╙╘╒╓╫╪┘┌█▄▌▐▀",
+            "РСТУФХЦЧШЩЪЫЬЭЮЯЁЁЄЄЇЇЎЎ°∙·√№¤■\u{a0}",
+        ),
+        sort: concat!(
+            "АБВГДЕЖЗИЙКЛМНОПРСТУФХЦЧШЩЪЫЬЭЮЯ",
+            "АБВГДЕЖЗИЙКЛМНОП░▒▓│┤╡╢╖╕╣║╗╝╜╛┐",
+            "└┴┬├─┼╞╟╚╔╩╦╠═╬╧╨╤╥╙╘╒╓╫╪┘┌█▄▌▐▀",
+            "РСТУФХЦЧШЩЪЫЬЭЮЯЁЁЄЄЇЇЎЎ°∙·√№¤■\u{a0}",
+        ),
+    },
+];
+
+impl CodePage {
+    pub fn find(id: u16) -> Option<&'static CodePage> {
+        CODE_PAGES.iter().find(|code_page| code_page.id == id)
+    }
+
+    /// The byte standing for `c`, if the code page has one
+    pub fn encode(&self, c: char) -> Option<u8> {
+        if c.is_ascii() {
+            return Some(c as u8);
+        }
+        let index = self.chars.chars().position(|x| x == c)?;
+        Some(0x80 + index as u8)
+    }
+
+    // Bytes 80h-FFh run through one of the mapping strings
+    fn map(&self, mapping: &str) -> [u8; 128] {
+        let mut table = [0; 128];
+        for (i, c) in mapping.chars().enumerate() {
+            table[i] = self.encode(c).unwrap_or(0x80 + i as u8);
+        }
+        table
+    }
+
+    pub fn to_upper(&self, byte: u8) -> u8 {
+        match byte {
+            0x00..=0x7F => byte.to_ascii_uppercase(),
+            _ => self.map(self.upper)[byte as usize - 0x80],
+        }
+    }
+
+    /// Sort weights for every byte: case and accents don't count
+    pub fn collating_table(&self) -> [u8; 256] {
+        let mut table = [0; 256];
+        for (i, weight) in table.iter_mut().enumerate().take(0x80) {
+            *weight = (i as u8).to_ascii_uppercase();
+        }
+        table[0x80..].copy_from_slice(&self.map(self.sort));
+        table
+    }
+
+    fn block(&self) -> u16 {
+        let index = CODE_PAGES.iter().position(|cp| cp.id == self.id).unwrap_or(0);
+        index as u16 * BLOCK_SIZE
+    }
+}
+
+fn far_pointer(offset: u16) -> u32 {
+    (TABLE_SEGMENT as u32) << 16 | offset as u32
+}
+
+fn table_addr(offset: u16) -> usize {
+    ((TABLE_SEGMENT as usize) << 4) + offset as usize
+}
+
+/// The country and code page in effect
+pub struct Nls {
+    pub country: u16,
+    pub code_page: u16,
+    /// The code page at startup, which INT 21h 66h reports alongside
+    pub system_code_page: u16,
+    /// Where code page fonts are looked for
+    pub font_dir: Option<PathBuf>,
+}
+
+impl Nls {
+    pub fn new() -> Self {
+        Self {
+            country: 1,
+            code_page: 437,
+            system_code_page: 437,
+            font_dir: None,
+        }
+    }
+
+    pub fn country(&self) -> &'static Country {
+        Country::find(self.country).unwrap_or(&COUNTRIES[0])
+    }
+
+    pub fn code_page(&self) -> &'static CodePage {
+        CodePage::find(self.code_page).unwrap_or(&CODE_PAGES[0])
+    }
+}
+
+impl Default for Nls {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Puts every code page's tables in the BIOS segment
+pub fn install(bus: &mut Bus) {
+    for code_page in CODE_PAGES {
+        let block = code_page.block();
+        let uppercase = block + UPPERCASE_OFFSET;
+        // CMP AL, 80h; JB done; PUSH BX; MOV BX, table - 80h; CS: XLAT; POP BX; done: RETF.
+        // BX + AL wraps within the segment, so the base may be "negative"
+        let [low, high] = (uppercase + 2).wrapping_sub(0x80).to_le_bytes();
+        bus.write_rom(
+            table_addr(block),
+            &[0x3C, 0x80, 0x72, 0x07, 0x53, 0xBB, low, high, 0x2E, 0xD7, 0x5B, 0xCB],
+        );
+
+        let mut table = vec![0x80, 0x00];
+        table.extend((0x80..=0xFF).map(|byte| code_page.to_upper(byte)));
+        bus.write_rom(table_addr(uppercase), &table);
+
+        let mut table = vec![0x00, 0x01];
+        table.extend(code_page.collating_table());
+        bus.write_rom(table_addr(block + COLLATING_OFFSET), &table);
+    }
+
+    // Size, then: permissible range 00h-FFh, excluded range 00h-20h, and the
+    // characters that end a filename
+    let mut table = vec![0x16, 0x00, 0x01, 0x00, 0xFF, 0x00, 0x00, 0x20, 0x02, 0x0E];
+    table.extend(b".\"/\\[]:|<>+=;,");
+    bus.write_rom(table_addr(TERMINATORS), &table);
+    bus.write_rom(table_addr(DBCS), &[0x00, 0x00, 0x00, 0x00]);
+}
+
+/// Sets the country and the code page at startup: the country's own unless
+/// one is given
+pub fn start(bus: &mut Bus, country: u16, code_page: Option<u16>) -> Result<(), String> {
+    let country = Country::find(country).ok_or(format!("Unknown country code {}", country))?;
+    let code_page = code_page.unwrap_or(country.code_page);
+    bus.nls.country = country.code;
+    set_code_page(bus, code_page).map_err(|_| format!("Unsupported code page {}", code_page))?;
+    bus.nls.system_code_page = code_page;
+    Ok(())
+}
+
+pub fn set_country(bus: &mut Bus, code: u16) -> Result<(), u8> {
+    let country = Country::find(code).ok_or(ERR_FILE_NOT_FOUND)?;
+    bus.nls.country = country.code;
+    Ok(())
+}
+
+/// Switches code pages, and the font with them: from the font directory if
+/// it has one for the code page, otherwise the bundled one.
+pub fn set_code_page(bus: &mut Bus, id: u16) -> Result<(), u8> {
+    CodePage::find(id).ok_or(ERR_FILE_NOT_FOUND)?;
+    bus.nls.code_page = id;
+    let font = bus.nls.font_dir.as_deref().and_then(|dir| Font::load(dir, id));
+    let font = font.or_else(|| Font::bundled(id));
+    if font.is_none() {
+        bus.log_string(&format!("[NLS] No font for code page {}, using the built-in one", id));
+    }
+    bus.vga_mut().font = font.unwrap_or_else(Font::builtin);
+    Ok(())
+}
+
+/// INT 21h 38h: the country information of `code` at `addr`
+pub fn write_country_info(bus: &mut Bus, addr: usize, code: u16) -> Result<(), u8> {
+    let country = Country::find(code).ok_or(ERR_FILE_NOT_FOUND)?;
+    let code_page = bus.nls.code_page();
+    let info = country.info(code_page, far_pointer(code_page.block()));
+    for (i, &byte) in info.iter().enumerate() {
+        bus.write_8(addr + i, byte);
+    }
+    Ok(())
+}
+
+/// INT 21h 65h AL=01h-07h: `id` for a country and code page (FFFFh for the
+/// current ones) into the `size` bytes at `addr`. Returns the bytes written.
+pub fn write_extended_info(
+    bus: &mut Bus,
+    id: u8,
+    country: u16,
+    code_page: u16,
+    addr: usize,
+    size: u16,
+) -> Result<u16, u8> {
+    let country = match country {
+        0xFFFF => bus.nls.country(),
+        code => Country::find(code).ok_or(ERR_FILE_NOT_FOUND)?,
+    };
+    let code_page = match code_page {
+        0xFFFF => bus.nls.code_page(),
+        id => CodePage::find(id).ok_or(ERR_FILE_NOT_FOUND)?,
+    };
+    if size < 5 {
+        return Err(ERR_INVALID_FUNCTION);
+    }
+    let block = code_page.block();
+    let mut data = vec![id];
+    match id {
+        0x01 => {
+            data.extend(0x26u16.to_le_bytes());
+            data.extend(country.code.to_le_bytes());
+            data.extend(code_page.id.to_le_bytes());
+            data.extend(country.info(code_page, far_pointer(block)));
+        }
+        0x02 | 0x04 => data.extend(far_pointer(block + UPPERCASE_OFFSET).to_le_bytes()),
+        0x05 => data.extend(far_pointer(TERMINATORS).to_le_bytes()),
+        0x06 => data.extend(far_pointer(block + COLLATING_OFFSET).to_le_bytes()),
+        0x07 => data.extend(far_pointer(DBCS).to_le_bytes()),
+        _ => return Err(ERR_INVALID_FUNCTION),
+    }
+    data.truncate(size as usize);
+    for (i, &byte) in data.iter().enumerate() {
+        bus.write_8(addr + i, byte);
+    }
+    Ok(data.len() as u16)
+}
+
+/// Upper-cases a character in the current code page
+pub fn to_upper(bus: &Bus, byte: u8) -> u8 {
+    bus.nls.code_page().to_upper(byte)
+}
+
+/// INT 21h 65h AL=23h: 1 for the country's yes, 0 for its no, 2 for neither
+pub fn yes_no(bus: &Bus, byte: u8) -> u16 {
+    let country = bus.nls.country();
+    let code_page = bus.nls.code_page();
+    let answer = Some(code_page.to_upper(byte));
+    if answer == code_page.encode(country.yes) {
+        1
+    } else if answer == code_page.encode(country.no) {
+        0
+    } else {
+        2
+    }
+}
@@ -1,5 +1,8 @@
-use crate::cpu::Cpu;
+use crate::command::CommandDispatcher;
+use crate::cpu::{Cpu, CpuState};
+use crate::interrupts::int20;
 use crate::video;
+use std::io::Write;
 
 /// A Tiny "OS" written in Machine Code. Reads keys into a buffer at offset 0x0200
 /// On Enter, calls INT 20h (Our Rust Shell). Handles backspace visually and in buffer
@@ -107,3 +110,65 @@ pub fn show_prompt(cpu: &mut Cpu) {
         video::print_string(cpu, &format!("C:\\{}>", cwd));
     }
 }
+
+/// Runs the command line queued by the shell's INT 2Fh, if any.
+/// Built-ins run right away; anything else is loaded as a .COM or .EXE.
+/// Returns true if there was a command.
+pub fn run_pending_command(cpu: &mut Cpu) -> bool {
+    let Some(cmd) = cpu.pending_command.take() else {
+        return false;
+    };
+    cpu.bus
+        .log_string(&format!("[SHELL] Processing Command: {}", cmd));
+
+    let (command, args) = match cmd.split_once(' ') {
+        Some((c, a)) => (c, a.trim()),
+        None => (cmd.as_str(), ""),
+    };
+
+    let dispatcher = CommandDispatcher::new();
+    if !dispatcher.dispatch(cpu, command, args) {
+        let loaded = if !command.contains('.') {
+            cpu.load_executable(&format!("{}.com", command), None)
+                || cpu.load_executable(&format!("{}.exe", command), None)
+        } else {
+            cpu.load_executable(command, None)
+        };
+
+        if !loaded {
+            video::print_string(cpu, "Bad command or file name.\r\n");
+        }
+        // If loaded, load_executable() reset CS:IP and the program starts next step
+    }
+    true
+}
+
+/// Brings the shell back after a program terminated
+pub fn reload(cpu: &mut Cpu) {
+    cpu.load_shell();
+    cpu.state = CpuState::Running;
+
+    //TODO: Replace this hack with a proper fix
+    //Add a newline to make sure the prompt starts on a new line.
+    let col = cpu.bus.read_8(0x0450);
+    if col != 0 {
+        video::print_string(cpu, "\r\n");
+    }
+}
+
+/// Handles "IP = 0" as an explicit exit (Standard COM behavior).
+/// If the program jumps to the start of its PSP, where INT 20h sits, it wants to exit.
+pub fn check_psp_exit(cpu: &mut Cpu) -> bool {
+    if cpu.ip != 0x0000 || cpu.current_psp == 0 || cpu.cs != cpu.current_psp {
+        return false;
+    }
+    cpu.bus
+        .log_string("[DOS] Program jumped to offset 0000h. Exiting to Shell.");
+    // Flush log on exit so we don't lose tail data
+    if let Some(log) = cpu.bus.log_file.as_mut() {
+        let _ = log.flush();
+    }
+    // Back to the parent, or the shell, as INT 20h would
+    int20::handle(cpu);
+    true
+}
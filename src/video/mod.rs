@@ -1,3 +1,6 @@
+use std::borrow::Cow;
+use std::path::Path;
+
 use crate::bus::Bus;
 use crate::cpu::Cpu;
 
@@ -9,7 +12,6 @@ pub const SCREEN_HEIGHT: u32 = 400;
 // Memory Map Addresses
 pub const ADDR_VGA_GRAPHICS: usize = 0xA0000;
 pub const ADDR_VGA_TEXT: usize = 0xB8000;
-pub const SIZE_GRAPHICS: usize = 64000; // 320 * 200
 pub const SIZE_TEXT: usize = 32 * 1024; // 32kB to cover CGA modes too
 pub const BDA_CURSOR_POS: usize = 0x0450; // Base for Page 0. Page n = 0x450 + n*2
 pub const BDA_CURSOR_MODE: usize = 0x0460;
@@ -19,6 +21,69 @@ pub const MAX_ROWS: u8 = 25;
 static FONT_8X16: &[u8] = include_bytes!("assets/IBM_VGA_8x16.bin");
 static FONT_8X8: &[u8] = include_bytes!("assets/IBM_VGA_8x8.bin");
 
+/// Glyphs shipped for each supported code page, 8x16 then 8x8. Only the
+/// characters 80h-FFh differ from the IBM VGA font.
+static BUNDLED: [(u16, &[u8], &[u8]); 4] = [
+    (437, FONT_8X16, FONT_8X8),
+    (
+        850,
+        include_bytes!("assets/CP850_8x16.bin"),
+        include_bytes!("assets/CP850_8x8.bin"),
+    ),
+    (
+        852,
+        include_bytes!("assets/CP852_8x16.bin"),
+        include_bytes!("assets/CP852_8x8.bin"),
+    ),
+    (
+        866,
+        include_bytes!("assets/CP866_8x16.bin"),
+        include_bytes!("assets/CP866_8x8.bin"),
+    ),
+];
+
+/// Text mode glyphs for the 256 characters of a code page
+pub struct Font {
+    pub glyphs_8x16: Cow<'static, [u8]>,
+    pub glyphs_8x8: Cow<'static, [u8]>,
+}
+
+impl Font {
+    /// The IBM VGA font, code page 437
+    pub fn builtin() -> Self {
+        Self {
+            glyphs_8x16: Cow::Borrowed(FONT_8X16),
+            glyphs_8x8: Cow::Borrowed(FONT_8X8),
+        }
+    }
+
+    /// The font shipped for `code_page`, if there is one
+    pub fn bundled(code_page: u16) -> Option<Self> {
+        let &(_, glyphs_8x16, glyphs_8x8) = BUNDLED.iter().find(|(id, _, _)| *id == code_page)?;
+        Some(Self {
+            glyphs_8x16: Cow::Borrowed(glyphs_8x16),
+            glyphs_8x8: Cow::Borrowed(glyphs_8x8),
+        })
+    }
+
+    /// Glyphs for `code_page` from raw bitmaps in `dir`: CPnnn.F16, and
+    /// CPnnn.F08 for the 40-column modes, which otherwise keep the bundled ones
+    pub fn load(dir: &Path, code_page: u16) -> Option<Self> {
+        let read = |extension: &str, size: usize| {
+            std::fs::read(dir.join(format!("CP{}.{}", code_page, extension)))
+                .ok()
+                .filter(|glyphs| glyphs.len() == size)
+        };
+        let glyphs_8x16 = read("F16", FONT_8X16.len())?;
+        let bundled = Self::bundled(code_page).unwrap_or_else(Self::builtin);
+        let glyphs_8x8 = read("F08", FONT_8X8.len()).map_or(bundled.glyphs_8x8, Cow::Owned);
+        Some(Self {
+            glyphs_8x16: Cow::Owned(glyphs_8x16),
+            glyphs_8x8,
+        })
+    }
+}
+
 #[derive(PartialEq, Clone, Copy, Debug)]
 pub enum VideoMode {
     Text40x25 = 0x00,
@@ -34,15 +99,15 @@ pub enum VideoMode {
 
 pub fn render_screen(canvas: &mut [u8], bus: &Bus) {
     match bus.video_mode {
-        VideoMode::Graphics320x200 => render_graphics_mode(canvas, &bus.vga.vram_graphics, bus),
+        VideoMode::Graphics320x200 => render_graphics_mode(canvas, &bus.vga().vram_graphics, bus),
         VideoMode::Cga320x200Color | VideoMode::Cga320x200 => {
-            render_cga_mode4(canvas, &bus.vga.vram_text, &bus)
+            render_cga_mode4(canvas, &bus.vga().vram_text, &bus)
         }
-        VideoMode::Cga640x200 => render_cga_mode6(canvas, &bus.vga.vram_text),
-        VideoMode::Text80x25 => render_text_mode_80x25(canvas, &bus.vga.vram_text, bus),
-        VideoMode::Text80x25Color => render_text_mode_80x25(canvas, &bus.vga.vram_text, bus),
-        VideoMode::Text40x25 => render_text_mode_40x25(canvas, &bus.vga.vram_text, bus),
-        VideoMode::Text40x25Color => render_text_mode_40x25(canvas, &bus.vga.vram_text, bus),
+        VideoMode::Cga640x200 => render_cga_mode6(canvas, &bus.vga().vram_text),
+        VideoMode::Text80x25 => render_text_mode_80x25(canvas, &bus.vga().vram_text, bus),
+        VideoMode::Text80x25Color => render_text_mode_80x25(canvas, &bus.vga().vram_text, bus),
+        VideoMode::Text40x25 => render_text_mode_40x25(canvas, &bus.vga().vram_text, bus),
+        VideoMode::Text40x25Color => render_text_mode_40x25(canvas, &bus.vga().vram_text, bus),
     }
 }
 
@@ -63,7 +128,7 @@ pub fn render_graphics_mode(canvas: &mut [u8], vram: &[u8], bus: &Bus) {
             } else {
                 0
             };
-            let rgb = bus.vga.get_rgb(color_idx);
+            let rgb = bus.vga().get_rgb(color_idx);
 
             // Scale 2x horizontally and 2x vertically
             for dy in 0..2 {
@@ -91,20 +156,20 @@ fn render_cga_mode4(canvas: &mut [u8], vram: &[u8], bus: &Bus) {
     let bg_color_idx = cga_reg & 0x0F;
     let palette_id = (cga_reg & 0x20) != 0;
     // Get RGB values using the bus
-    let bg_rgb_val = bus.vga.get_rgb(bg_color_idx);
+    let bg_rgb_val = bus.vga().get_rgb(bg_color_idx);
 
     // Hardcoded Indices
     let p0 = [
         bg_rgb_val,
-        bus.vga.get_rgb(2),
-        bus.vga.get_rgb(4),
-        bus.vga.get_rgb(6),
+        bus.vga().get_rgb(2),
+        bus.vga().get_rgb(4),
+        bus.vga().get_rgb(6),
     ];
     let p1 = [
         bg_rgb_val,
-        bus.vga.get_rgb(3),
-        bus.vga.get_rgb(5),
-        bus.vga.get_rgb(7),
+        bus.vga().get_rgb(3),
+        bus.vga().get_rgb(5),
+        bus.vga().get_rgb(7),
     ];
 
     let current_pal = if palette_id { p1 } else { p0 };
@@ -194,11 +259,11 @@ pub fn render_text_mode_80x25(canvas: &mut [u8], vram: &[u8], bus: &Bus) {
     for row in 0..25 {
         for col in 0..80 {
             let offset = (row * 80 + col) * 2;
-            let char_code = vram[offset] as usize; // Direct index into CP437
+            let char_code = vram[offset] as usize; // Direct index into the font
             let attr = vram[offset + 1];
 
-            let fg = bus.vga.get_rgb(attr & 0x0F);
-            let bg = bus.vga.get_rgb((attr >> 4) & 0x0F);
+            let fg = bus.vga().get_rgb(attr & 0x0F);
+            let bg = bus.vga().get_rgb((attr >> 4) & 0x0F);
 
             // Calculate start index in the font array
             // Each character is 16 bytes long in the 8x16 font
@@ -207,7 +272,7 @@ pub fn render_text_mode_80x25(canvas: &mut [u8], vram: &[u8], bus: &Bus) {
             // Draw 8x16 Block
             for y in 0..16 {
                 // Get the byte for this row of the character
-                let glyph_row = FONT_8X16[glyph_start + y];
+                let glyph_row = bus.vga().font.glyphs_8x16[glyph_start + y];
 
                 for x in 0..8 {
                     // Check bit (most significant bit is left-most pixel)
@@ -241,14 +306,14 @@ fn render_text_mode_40x25(canvas: &mut [u8], vram: &[u8], bus: &Bus) {
             let char_code = vram[offset] as usize;
             let attr = vram[offset + 1];
 
-            let fg = bus.vga.get_rgb(attr & 0x0F);
-            let bg = bus.vga.get_rgb((attr >> 4) & 0x0F);
+            let fg = bus.vga().get_rgb(attr & 0x0F);
+            let bg = bus.vga().get_rgb((attr >> 4) & 0x0F);
 
             // Each character is 8 bytes long in the 8x8 font
             let glyph_start = char_code * 8;
 
             for y in 0..8 {
-                let glyph_row = FONT_8X8[glyph_start + y];
+                let glyph_row = bus.vga().font.glyphs_8x8[glyph_start + y];
 
                 for x in 0..8 {
                     let on = (glyph_row >> (7 - x)) & 1 == 1;
@@ -292,14 +357,14 @@ pub fn print_char(bus: &mut Bus, ascii: u8) {
                 bus.cursor_x -= 1;
                 // Visually clear the character
                 let offset = (bus.cursor_y * 80 + bus.cursor_x) * 2;
-                bus.vga.vram_text[offset] = 0x20; // Space
+                bus.vga_mut().vram_text[offset] = 0x20; // Space
             }
         }
         _ => {
             // Print standard character
             let offset = (bus.cursor_y * 80 + bus.cursor_x) * 2;
-            bus.vga.vram_text[offset] = ascii;
-            bus.vga.vram_text[offset + 1] = 0x07; // Light Gray Attribute
+            bus.vga_mut().vram_text[offset] = ascii;
+            bus.vga_mut().vram_text[offset + 1] = 0x07; // Light Gray Attribute
             bus.cursor_x += 1;
         }
     }
@@ -338,8 +403,8 @@ pub fn print_string(cpu: &mut Cpu, s: &str) {
                     // Visual Erase (Space + Light Gray)
                     let offset = (row * max_cols + col) * 2;
                     if offset < SIZE_TEXT {
-                        cpu.bus.vga.vram_text[offset] = 0x20;
-                        cpu.bus.vga.vram_text[offset + 1] = 0x07;
+                        cpu.bus.vga_mut().vram_text[offset] = 0x20;
+                        cpu.bus.vga_mut().vram_text[offset + 1] = 0x07;
                     }
                 }
             }
@@ -347,8 +412,8 @@ pub fn print_string(cpu: &mut Cpu, s: &str) {
                 // Printable Character
                 let offset = (row * max_cols + col) * 2;
                 if offset < SIZE_TEXT {
-                    cpu.bus.vga.vram_text[offset] = c as u8;
-                    cpu.bus.vga.vram_text[offset + 1] = 0x07; // Attribute: Light Gray
+                    cpu.bus.vga_mut().vram_text[offset] = c as u8;
+                    cpu.bus.vga_mut().vram_text[offset + 1] = 0x07; // Attribute: Light Gray
                 }
                 col += 1;
             }
@@ -370,15 +435,16 @@ pub fn print_string(cpu: &mut Cpu, s: &str) {
             // We can't use `copy_within` easily on Vec<u8> across overlapping ranges in simple rust
             // without unsafe or a temp buffer, but a simple loop works fine for 4KB.
             for i in 0..(screen_size - row_size) {
-                cpu.bus.vga.vram_text[i] = cpu.bus.vga.vram_text[i + row_size];
+                let vram = &mut cpu.bus.vga_mut().vram_text;
+                vram[i] = vram[i + row_size];
             }
 
             // Clear bottom row
             for i in (screen_size - row_size)..screen_size {
                 if i % 2 == 0 {
-                    cpu.bus.vga.vram_text[i] = 0x20; // Space
+                    cpu.bus.vga_mut().vram_text[i] = 0x20; // Space
                 } else {
-                    cpu.bus.vga.vram_text[i] = 0x07; // Color
+                    cpu.bus.vga_mut().vram_text[i] = 0x07; // Color
                 }
             }
 
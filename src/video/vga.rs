@@ -1,4 +1,5 @@
-use crate::bus::Device;
+use crate::devices::{Device, Effects};
+use super::{ADDR_VGA_GRAPHICS, ADDR_VGA_TEXT, Font};
 use std::cell::Cell;
 
 pub struct VgaCard {
@@ -22,6 +23,12 @@ pub struct VgaCard {
     pub attribute_index: u8,
     pub attribute_regs: [u8; 21],  // 0-0xF: Palette, 0x10-0x14: Control
     pub attribute_flip_flop: bool, // false = Address, true = Data
+
+    // Character generator: glyphs for the current code page
+    pub font: Font,
+
+    // Mode registers written since the bus last looked
+    effects: Effects,
 }
 
 impl VgaCard {
@@ -95,6 +102,8 @@ impl VgaCard {
             attribute_index: 0,
             attribute_regs: [0; 21],
             attribute_flip_flop: false,
+            font: Font::builtin(),
+            effects: Effects::empty(),
         }
     }
 
@@ -283,6 +292,23 @@ impl VgaCard {
 }
 
 impl Device for VgaCard {
+    // A0000-AFFFF: graphics planes, B8000-BFFFF: text / CGA
+    fn mem_read(&self, addr: usize) -> u8 {
+        if addr >= ADDR_VGA_TEXT {
+            self.vram_text[addr - ADDR_VGA_TEXT]
+        } else {
+            self.vram_graphics[addr - ADDR_VGA_GRAPHICS]
+        }
+    }
+
+    fn mem_write(&mut self, addr: usize, value: u8) {
+        if addr >= ADDR_VGA_TEXT {
+            self.vram_text[addr - ADDR_VGA_TEXT] = value;
+        } else {
+            self.write_graphics(addr - ADDR_VGA_GRAPHICS, value);
+        }
+    }
+
     fn ports(&self) -> Vec<u16> {
         vec![
             0x3C2, // Misc Output (Write) / Input Status 0 (Read)
@@ -378,6 +404,14 @@ impl Device for VgaCard {
     }
 
     fn io_write(&mut self, port: u16, value: u8) {
+        // Misc Output, Sequencer Memory Mode and Graphics Mode decide the video mode
+        if port == 0x3C2
+            || (port == 0x3C5 && self.sequencer_index == 0x04)
+            || (port == 0x3CF && self.graphics_index == 0x05)
+        {
+            self.effects |= Effects::VIDEO_MODE;
+        }
+
         match port {
             0x3C0 => {
                 if !self.attribute_flip_flop {
@@ -457,4 +491,8 @@ impl Device for VgaCard {
             _ => {}
         }
     }
+
+    fn take_effects(&mut self) -> Effects {
+        std::mem::take(&mut self.effects)
+    }
 }
@@ -0,0 +1,192 @@
+use std::ops::Range;
+
+// Extended memory manager behind the XMS driver (interrupts/xms.rs).
+//
+// Extended memory blocks (EMBs) are carved out of the RAM above the HMA,
+// first fit, in 1KB units. Blocks never move while locked; an unlocked block
+// may be relocated when it has to grow.
+
+/// Handles a real HIMEM hands out by default
+pub const MAX_HANDLES: usize = 32;
+
+// XMS error codes, returned in BL
+pub const ERR_NOT_IMPLEMENTED: u8 = 0x80;
+pub const ERR_HMA_MISSING: u8 = 0x90;
+pub const ERR_HMA_IN_USE: u8 = 0x91;
+pub const ERR_HMA_NOT_ALLOCATED: u8 = 0x93;
+pub const ERR_A20_STILL_ENABLED: u8 = 0x94;
+pub const ERR_OUT_OF_MEMORY: u8 = 0xA0;
+pub const ERR_OUT_OF_HANDLES: u8 = 0xA1;
+pub const ERR_INVALID_HANDLE: u8 = 0xA2;
+pub const ERR_INVALID_SOURCE_HANDLE: u8 = 0xA3;
+pub const ERR_INVALID_SOURCE_OFFSET: u8 = 0xA4;
+pub const ERR_INVALID_DEST_HANDLE: u8 = 0xA5;
+pub const ERR_INVALID_DEST_OFFSET: u8 = 0xA6;
+pub const ERR_INVALID_LENGTH: u8 = 0xA7;
+pub const ERR_NOT_LOCKED: u8 = 0xAA;
+pub const ERR_LOCKED: u8 = 0xAB;
+pub const ERR_LOCK_OVERFLOW: u8 = 0xAC;
+pub const ERR_SMALLER_UMB: u8 = 0xB0;
+pub const ERR_NO_UMB: u8 = 0xB1;
+pub const ERR_INVALID_UMB: u8 = 0xB2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Emb {
+    /// Physical address of the first byte
+    pub addr: usize,
+    pub size_kb: u16,
+    pub locks: u8,
+}
+
+impl Emb {
+    pub fn size_bytes(&self) -> usize {
+        self.size_kb as usize * 1024
+    }
+
+    fn end(&self) -> usize {
+        self.addr + self.size_bytes()
+    }
+}
+
+pub struct Xms {
+    pool: Range<usize>,
+    handles: Vec<Option<Emb>>, // Handle n is slot n - 1
+    pub hma_allocated: bool,
+    pub a20_local_count: u16, // Nesting of local A20 enables
+    pub umbs: Vec<u16>,       // Segments of the UMBs handed out
+}
+
+impl Xms {
+    /// Manages the physical range `pool`
+    pub fn new(pool: Range<usize>) -> Self {
+        Self {
+            pool,
+            handles: vec![None; MAX_HANDLES],
+            hma_allocated: false,
+            a20_local_count: 0,
+            umbs: Vec::new(),
+        }
+    }
+
+    pub fn block(&self, handle: u16) -> Option<&Emb> {
+        let slot = (handle as usize).checked_sub(1)?;
+        self.handles.get(slot)?.as_ref()
+    }
+
+    fn block_mut(&mut self, handle: u16) -> Result<&mut Emb, u8> {
+        let slot = (handle as usize)
+            .checked_sub(1)
+            .ok_or(ERR_INVALID_HANDLE)?;
+        self.handles
+            .get_mut(slot)
+            .and_then(Option::as_mut)
+            .ok_or(ERR_INVALID_HANDLE)
+    }
+
+    pub fn free_handles(&self) -> u8 {
+        self.handles.iter().filter(|h| h.is_none()).count() as u8
+    }
+
+    /// Free gaps in the pool, lowest address first, skipping `except`
+    fn gaps(&self, except: Option<u16>) -> Vec<Range<usize>> {
+        let mut used: Vec<Range<usize>> = self
+            .handles
+            .iter()
+            .enumerate()
+            .filter(|(slot, _)| except != Some(*slot as u16 + 1))
+            .filter_map(|(_, emb)| emb.map(|e| e.addr..e.end()))
+            .collect();
+        used.sort_by_key(|r| r.start);
+
+        let mut gaps = Vec::new();
+        let mut next = self.pool.start;
+        for range in used {
+            if range.start > next {
+                gaps.push(next..range.start);
+            }
+            next = next.max(range.end);
+        }
+        if self.pool.end > next {
+            gaps.push(next..self.pool.end);
+        }
+        gaps
+    }
+
+    /// Largest free block and total free memory, in KB
+    pub fn free_kb(&self) -> (u16, u16) {
+        let gaps = self.gaps(None);
+        let largest = gaps.iter().map(|g| g.len() / 1024).max().unwrap_or(0);
+        let total: usize = gaps.iter().map(|g| g.len() / 1024).sum();
+        (largest.min(0xFFFF) as u16, total.min(0xFFFF) as u16)
+    }
+
+    fn find_gap(&self, size_kb: u16, except: Option<u16>) -> Option<usize> {
+        let len = size_kb as usize * 1024;
+        self.gaps(except)
+            .into_iter()
+            .find(|g| g.len() >= len)
+            .map(|g| g.start)
+    }
+
+    pub fn allocate(&mut self, size_kb: u16) -> Result<u16, u8> {
+        let slot = self
+            .handles
+            .iter()
+            .position(Option::is_none)
+            .ok_or(ERR_OUT_OF_HANDLES)?;
+        let addr = self.find_gap(size_kb, None).ok_or(ERR_OUT_OF_MEMORY)?;
+        self.handles[slot] = Some(Emb {
+            addr,
+            size_kb,
+            locks: 0,
+        });
+        Ok(slot as u16 + 1)
+    }
+
+    pub fn free(&mut self, handle: u16) -> Result<(), u8> {
+        if self.block_mut(handle)?.locks > 0 {
+            return Err(ERR_LOCKED);
+        }
+        self.handles[handle as usize - 1] = None;
+        Ok(())
+    }
+
+    /// Returns the block's physical address
+    pub fn lock(&mut self, handle: u16) -> Result<usize, u8> {
+        let emb = self.block_mut(handle)?;
+        emb.locks = emb.locks.checked_add(1).ok_or(ERR_LOCK_OVERFLOW)?;
+        Ok(emb.addr)
+    }
+
+    pub fn unlock(&mut self, handle: u16) -> Result<(), u8> {
+        let emb = self.block_mut(handle)?;
+        emb.locks = emb.locks.checked_sub(1).ok_or(ERR_NOT_LOCKED)?;
+        Ok(())
+    }
+
+    /// Grows or shrinks a block. Returns the move the contents need, if the
+    /// block had to be relocated: (old address, new address, bytes).
+    pub fn resize(&mut self, handle: u16, size_kb: u16) -> Result<Option<(usize, usize, usize)>, u8> {
+        let emb = *self.block_mut(handle)?;
+        if emb.locks > 0 {
+            return Err(ERR_LOCKED);
+        }
+
+        let len = size_kb as usize * 1024;
+        let fits_in_place = self
+            .gaps(Some(handle))
+            .iter()
+            .any(|g| g.start <= emb.addr && emb.addr + len <= g.end);
+        let addr = if fits_in_place {
+            emb.addr
+        } else {
+            self.find_gap(size_kb, Some(handle))
+                .ok_or(ERR_OUT_OF_MEMORY)?
+        };
+
+        let block = self.block_mut(handle)?;
+        block.addr = addr;
+        block.size_kb = size_kb;
+        Ok((addr != emb.addr).then(|| (emb.addr, addr, emb.size_bytes().min(len))))
+    }
+}
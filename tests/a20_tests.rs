@@ -0,0 +1,123 @@
+use iced_x86::Register;
+use rust_dos::bus::Bus;
+use rust_dos::cpu::{Cpu, CpuFlags};
+use std::path::PathBuf;
+
+#[test]
+fn test_a20_wraparound_and_hma() {
+    let mut cpu = Cpu::new(PathBuf::from("."));
+    assert!(!cpu.bus.a20_enabled());
+
+    // A20 off: FFFF:0010 is 0000:0000
+    assert_eq!(cpu.get_physical_addr(0xFFFF, 0x0010), 0x00000);
+    assert_eq!(cpu.get_physical_addr(0xFFFF, 0xFFFF), 0x0FFEF);
+    cpu.bus.write_8(cpu.get_physical_addr(0xFFFF, 0x0020), 0x5A);
+    assert_eq!(cpu.bus.read_8(0x00010), 0x5A);
+    // Word access straddling the top of memory wraps too
+    cpu.bus.write_8(0x00000, 0x12);
+    assert_eq!(cpu.bus.read_16(0xFFFFF), 0x1200);
+
+    // A20 on: the HMA is separate memory
+    cpu.bus.set_a20(true);
+    let hma = cpu.get_physical_addr(0xFFFF, 0x0020);
+    assert_eq!(hma, 0x100010);
+    assert_eq!(cpu.bus.read_8(hma), 0x00);
+    cpu.bus.write_8(hma, 0xA5);
+    assert_eq!(cpu.bus.read_8(hma), 0xA5);
+    assert_eq!(cpu.bus.read_8(0x00010), 0x5A);
+    cpu.bus.write_16(cpu.get_physical_addr(0xFFFF, 0xFFFE), 0xBEEF);
+    assert_eq!(cpu.bus.read_16(0x10FFEE), 0xBEEF);
+
+    // And it's hidden again when the gate closes
+    cpu.bus.set_a20(false);
+    assert_eq!(cpu.bus.read_8(cpu.get_physical_addr(0xFFFF, 0x0020)), 0x5A);
+}
+
+#[test]
+fn test_a20_keyboard_controller() {
+    let mut bus = Bus::new(PathBuf::from("."));
+
+    // Status: input buffer empty, system flag set
+    assert_eq!(bus.io_read(0x64) & 0x02, 0x00);
+    assert_ne!(bus.io_read(0x64) & 0x04, 0x00);
+
+    // The HIMEM sequence: write output port with bit 1 set
+    bus.io_write(0x64, 0xD1);
+    bus.io_write(0x60, 0xDF);
+    assert!(bus.a20_enabled());
+
+    // Read the output port back
+    bus.io_write(0x64, 0xD0);
+    assert_eq!(bus.io_read(0x64) & 0x01, 0x01);
+    assert_eq!(bus.io_read(0x60), 0xDF);
+    assert_eq!(bus.io_read(0x64) & 0x01, 0x00);
+
+    bus.io_write(0x64, 0xD1);
+    bus.io_write(0x60, 0xDD);
+    assert!(!bus.a20_enabled());
+
+    // Single byte commands
+    bus.io_write(0x64, 0xDF);
+    assert!(bus.a20_enabled());
+    bus.io_write(0x64, 0xDD);
+    assert!(!bus.a20_enabled());
+
+    // Without a pending command, port 60h talks to the keyboard
+    bus.io_write(0x60, 0xF4);
+    assert_eq!(bus.io_read(0x60), 0xFA);
+    assert!(!bus.a20_enabled());
+}
+
+#[test]
+fn test_a20_fast_gate_port_92() {
+    let mut bus = Bus::new(PathBuf::from("."));
+
+    let port92 = bus.io_read(0x92);
+    bus.io_write(0x92, port92 | 0x02);
+    assert!(bus.a20_enabled());
+    assert_eq!(bus.io_read(0x92) & 0x02, 0x02);
+
+    // Either gate keeps A20 open
+    bus.io_write(0x64, 0xDF);
+    bus.io_write(0x92, 0x00);
+    assert!(bus.a20_enabled());
+    bus.io_write(0x64, 0xDD);
+    assert!(!bus.a20_enabled());
+
+    // The reset bit doesn't stick
+    bus.io_write(0x92, 0x01);
+    assert_eq!(bus.io_read(0x92), 0x00);
+}
+
+#[test]
+fn test_int15_a20_functions() {
+    let mut cpu = Cpu::new(PathBuf::from("."));
+
+    let call = |cpu: &mut Cpu, ax: u16| {
+        cpu.ax = ax;
+        rust_dos::interrupts::int15::handle(cpu);
+        assert!(!cpu.get_cpu_flag(CpuFlags::CF), "AX={:04X}", ax);
+        assert_eq!(cpu.get_reg8(Register::AH), 0x00);
+    };
+
+    call(&mut cpu, 0x2403);
+    assert_eq!(cpu.bx & 0x0003, 0x0003);
+
+    call(&mut cpu, 0x2402);
+    assert_eq!(cpu.get_reg8(Register::AL), 0x00);
+
+    call(&mut cpu, 0x2401);
+    assert!(cpu.bus.a20_enabled());
+    call(&mut cpu, 0x2402);
+    assert_eq!(cpu.get_reg8(Register::AL), 0x01);
+
+    // Disabling closes the fast gate too
+    cpu.bus.io_write(0x92, 0x02);
+    call(&mut cpu, 0x2400);
+    assert!(!cpu.bus.a20_enabled());
+
+    cpu.ax = 0x2404;
+    rust_dos::interrupts::int15::handle(&mut cpu);
+    assert!(cpu.get_cpu_flag(CpuFlags::CF));
+    assert_eq!(cpu.get_reg8(Register::AH), 0x86);
+}
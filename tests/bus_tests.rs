@@ -1,5 +1,8 @@
 use rust_dos::bus::Bus;
-use rust_dos::video::{ADDR_VGA_GRAPHICS, ADDR_VGA_TEXT};
+use rust_dos::cpu::Cpu;
+use rust_dos::devices::{self, Device, TimeSlice};
+use rust_dos::memory::{self, Region};
+use rust_dos::video::{VideoMode, ADDR_VGA_GRAPHICS, ADDR_VGA_TEXT};
 
 #[test]
 fn test_ram_access() {
@@ -23,17 +26,17 @@ fn test_vram_mapping() {
     bus.write_8(text_addr, 0x41); // 'A'
 
     // Verify it landed in the dedicated vram_text vector
-    assert_eq!(bus.vga.vram_text[0], 0x41);
+    assert_eq!(bus.vga().vram_text[0], 0x41);
     // Verify read_8 maps correctly
     assert_eq!(bus.read_8(text_addr), 0x41);
     // Verify it DID NOT go to RAM or Graphics VRAM
     assert_eq!(bus.ram[text_addr], 0x00);
-    assert_eq!(bus.vga.read_graphics(0), 0x00);
+    assert_eq!(bus.vga().read_graphics(0), 0x00);
 
     // Test Graphics Mode VRAM (0xA0000)
     let graph_addr = ADDR_VGA_GRAPHICS; // 0xA0000
     // Set Mode 13h so write_8 works. We must use the helper to set Registers (Chain 4, etc.)
-    bus.vga
+    bus.vga_mut()
         .set_video_mode(rust_dos::video::VideoMode::Graphics320x200);
     bus.video_mode = rust_dos::video::VideoMode::Graphics320x200;
 
@@ -43,7 +46,7 @@ fn test_vram_mapping() {
 
     bus.write_8(graph_addr, 0xFF);
 
-    assert_eq!(bus.vga.read_graphics(0), 0xFF);
+    assert_eq!(bus.vga().read_graphics(0), 0xFF);
     assert_eq!(bus.read_8(graph_addr), 0xFF);
     assert_eq!(bus.ram[graph_addr], 0x00);
 }
@@ -74,22 +77,22 @@ fn test_pit_channel_2_latch_logic() {
     // It has a MSB/LSB flip-flop.
 
     // 1. Initialize Divisor to known state (0xFFFF)
-    bus.pit_divisor = 0xFFFF;
-    bus.pit_write_msb = false; // Expecting LSB next
+    bus.pit_mut().ch2_divisor = 0xFFFF;
+    bus.pit_mut().ch2_write_msb = false; // Expecting LSB next
 
     // 2. Write LSB (0x12)
     bus.io_write(0x42, 0x12);
     // Divisor should now be 0xFF12 (LSB changed, MSB kept from init)
-    assert_eq!(bus.pit_divisor, 0xFF12);
+    assert_eq!(bus.pit().ch2_divisor, 0xFF12);
     // Toggle should have flipped
-    assert_eq!(bus.pit_write_msb, true);
+    assert_eq!(bus.pit().ch2_write_msb, true);
 
     // 3. Write MSB (0x34)
     bus.io_write(0x42, 0x34);
     // Divisor should now be 0x3412
-    assert_eq!(bus.pit_divisor, 0x3412);
+    assert_eq!(bus.pit().ch2_divisor, 0x3412);
     // Toggle should have flipped back
-    assert_eq!(bus.pit_write_msb, false);
+    assert_eq!(bus.pit().ch2_write_msb, false);
 }
 
 #[test]
@@ -102,12 +105,12 @@ fn test_pit_channel_0_reset_bug() {
     // --------------------------------------------------------
 
     // 1. Put Channel 0 into "MSB expected" state
-    bus.pit0_divisor = 0xFFFF;
-    bus.pit0_write_msb = false;
+    bus.pit_mut().ch0_divisor = 0xFFFF;
+    bus.pit_mut().ch0_write_msb = false;
 
     bus.io_write(0x40, 0xAA); // Write LSB
     assert!(
-        bus.pit0_write_msb,
+        bus.pit().ch0_write_msb,
         "PIT0 toggle should be TRUE (expecting MSB)"
     );
 
@@ -124,7 +127,7 @@ fn test_pit_channel_0_reset_bug() {
     // If bug exists: This remains TRUE
     // If fixed: This becomes FALSE
     assert_eq!(
-        bus.pit0_write_msb, false,
+        bus.pit().ch0_write_msb, false,
         "PIT Channel 0 latch failed to reset after Command 0x36!"
     );
 
@@ -134,11 +137,11 @@ fn test_pit_channel_0_reset_bug() {
     // If reset failed (state=MSB), result is 0xBBAA.
     bus.io_write(0x40, 0xBB);
 
-    if bus.pit0_divisor == 0xBBAA {
+    if bus.pit().ch0_divisor == 0xBBAA {
         panic!("PIT Channel 0 Bug confirmed: Wrote MSB (0xBBAA) instead of LSB (0xFFBB)");
     }
 
-    assert_eq!(bus.pit0_divisor, 0xFFBB);
+    assert_eq!(bus.pit().ch0_divisor, 0xFFBB);
 }
 
 #[test]
@@ -152,16 +155,175 @@ fn test_speaker_io_port_61() {
 
     // 1. Write 0x00 (Both off)
     bus.io_write(0x61, 0x00);
-    assert_eq!(bus.speaker_on, false);
+    assert_eq!(bus.speaker().on, false);
     assert_eq!(bus.io_read(0x61), 0x00);
 
     // 2. Write 0x03 (Both on)
     bus.io_write(0x61, 0x03);
-    assert_eq!(bus.speaker_on, true);
+    assert_eq!(bus.speaker().on, true);
     // Reading 0x61 should reflect the state (masked)
     assert_eq!(bus.io_read(0x61) & 0x03, 0x03);
 
     // 3. Write 0x02 (Bit 0 off)
     bus.io_write(0x61, 0x02);
-    assert_eq!(bus.speaker_on, false);
+    assert_eq!(bus.speaker().on, false);
+}
+
+// Answers a port with a fixed value and raises IRQ 5 whenever time passes
+struct Probe {
+    port: u16,
+    value: u8,
+    written: Vec<u8>,
+}
+
+impl Device for Probe {
+    fn ports(&self) -> Vec<u16> {
+        vec![self.port]
+    }
+
+    fn io_read(&mut self, _port: u16) -> u8 {
+        self.value
+    }
+
+    fn io_write(&mut self, _port: u16, value: u8) {
+        self.written.push(value);
+    }
+
+    fn step(&mut self, slice: &mut TimeSlice) {
+        slice.raise_irq(5);
+    }
+}
+
+#[test]
+fn test_port_registry_attach_and_detach() {
+    let mut bus = Bus::new(std::path::PathBuf::from("."));
+
+    // Unclaimed ports float high
+    assert_eq!(bus.io_read(0x300), 0xFF);
+    assert_eq!(bus.io.owner(0x300), None);
+
+    let id = bus.io.attach(Box::new(Probe { port: 0x300, value: 0x42, written: Vec::new() }));
+    assert_eq!(bus.io.owner(0x300), Some(id));
+    assert_eq!(bus.io_read(0x300), 0x42);
+    bus.io_write(0x300, 0x99);
+    assert_eq!(bus.io.get::<Probe>(id).unwrap().written, vec![0x99]);
+
+    // Devices are stepped as time passes; their IRQs reach the PIC
+    bus.tick_devices(1_000, 1_000_000);
+    assert_ne!(bus.pic().pending & 0x20, 0);
+
+    // A later device can take over a built-in port, and give it back
+    let over = bus.io.attach(Box::new(Probe { port: 0x61, value: 0x5A, written: Vec::new() }));
+    assert_eq!(bus.io_read(0x61), 0x5A);
+    assert!(bus.io.detach(over).is_some());
+    assert_eq!(bus.io.owner(0x61), Some(devices::SPEAKER));
+    assert_eq!(bus.io_read(0x61), 0x00);
+
+    assert!(bus.io.detach(id).is_some());
+    assert!(bus.io.detach(id).is_none());
+    assert_eq!(bus.io_read(0x300), 0xFF);
+
+    // The built-in devices can't be unplugged
+    assert!(bus.io.detach(devices::PIC).is_none());
+    assert!(bus.io.detach(devices::RTC).is_none());
+    assert_eq!(bus.io.owner(0x20), Some(devices::PIC));
+    let mask = bus.io_read(0x21);
+    assert_eq!(bus.pic().mask, mask);
+}
+
+#[test]
+fn test_vga_mode_registers_switch_video_mode() {
+    let mut bus = Bus::new(std::path::PathBuf::from("."));
+    assert_ne!(bus.video_mode, VideoMode::Graphics320x200);
+
+    // Chain 4 alone isn't mode 13h
+    bus.io_write(0x3C4, 0x04);
+    bus.io_write(0x3C5, 0x0E);
+    assert_ne!(bus.video_mode, VideoMode::Graphics320x200);
+
+    // 256 colours in the Graphics Mode register completes it
+    bus.io_write(0x3CE, 0x05);
+    bus.io_write(0x3CF, 0x40);
+    assert_eq!(bus.video_mode, VideoMode::Graphics320x200);
+}
+
+#[test]
+fn test_pic_mask_readback() {
+    let mut bus = Bus::new(std::path::PathBuf::from("."));
+    bus.io_write(0x21, 0xB8);
+    assert_eq!(bus.io_read(0x21), 0xB8);
+    assert_eq!(bus.pic().mask, 0xB8);
+
+    // A masked request stays pending
+    bus.pic_mut().raise(0x08);
+    assert!(!bus.pic().has_request());
+    bus.pic_mut().raise(0x01);
+    assert_eq!(bus.pic_mut().acknowledge(), Some(0));
+    assert_eq!(bus.pic_mut().acknowledge(), None);
+    assert_eq!(bus.pic().pending, 0x08);
+}
+
+#[test]
+fn test_rom_and_open_bus() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+    let bus = &mut cpu.bus;
+
+    // Guest writes to the BIOS and VGA BIOS are ignored
+    let trap = 0xF1000 + 0x21 * 4;
+    assert_eq!(bus.read_8(trap), 0xFE);
+    assert!(!bus.write_8(trap, 0x90));
+    assert_eq!(bus.read_8(trap), 0xFE);
+    let vga_bios = bus.read_8(0xC0000);
+    bus.write_8(0xC0000, !vga_bios);
+    assert_eq!(bus.read_8(0xC0000), vga_bios);
+
+    // The host can still patch ROM
+    bus.write_rom(0xF0F00, &[0xCD, 0x02]);
+    assert_eq!(bus.read_16(0xF0F00), 0x02CD);
+
+    // Nothing at B0000 or in the empty option ROM slots
+    for addr in [0xB0000, 0xB7FFF, 0xC8000, 0xEFFFF] {
+        bus.write_8(addr, 0x00);
+        assert_eq!(bus.read_8(addr), 0xFF, "{:05X}", addr);
+    }
+    assert_eq!(bus.memory.region(0xD0000), Region::Unmapped);
+    assert_eq!(bus.memory.region(0xA0000), Region::Mmio(devices::VGA));
+}
+
+// 512 byte option ROM with a valid header and checksum
+fn option_rom(fill: u8) -> Vec<u8> {
+    let mut image = vec![fill; 512];
+    image[..3].copy_from_slice(&[0x55, 0xAA, 0x01]);
+    let sum = image[..511].iter().fold(0u8, |acc, &b| acc.wrapping_add(b));
+    image[511] = sum.wrapping_neg();
+    image
+}
+
+#[test]
+fn test_option_rom_load() {
+    let mut bus = Bus::new(std::path::PathBuf::from("."));
+    let image = option_rom(0xCB);
+    assert_eq!(memory::validate_option_rom(&image), Ok(512));
+
+    bus.load_option_rom(0xC8000, &image).unwrap();
+    assert_eq!(bus.memory.region(0xC8000), Region::Rom);
+    assert_eq!(bus.read_16(0xC8000), 0xAA55);
+    assert_eq!(bus.read_8(0xC8010), 0xCB);
+    // The rest of the 2KB page reads as erased ROM
+    assert_eq!(bus.read_8(0xC8200), 0xFF);
+    bus.write_8(0xC8010, 0x00);
+    assert_eq!(bus.read_8(0xC8010), 0xCB);
+
+    // Occupied, misaligned or outside the slot range
+    assert!(bus.load_option_rom(0xC8000, &image).is_err());
+    assert!(bus.load_option_rom(0xC8200, &image).is_err());
+    assert!(bus.load_option_rom(0xF0000, &image).is_err());
+
+    // Broken images
+    let mut bad = image.clone();
+    bad[100] ^= 1;
+    assert!(bus.load_option_rom(0xD0000, &bad).is_err());
+    assert!(bus.load_option_rom(0xD0000, &[0x55, 0xAA, 0x04]).is_err());
+    assert!(bus.load_option_rom(0xD0000, &[0x00; 512]).is_err());
+    assert_eq!(bus.read_8(0xD0000), 0xFF);
 }
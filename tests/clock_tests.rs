@@ -0,0 +1,161 @@
+use chrono::{NaiveDate, NaiveDateTime, TimeDelta};
+use iced_x86::Register;
+use rust_dos::bus::PIT_FREQUENCY;
+use rust_dos::clock::{self, StartTime};
+use rust_dos::cpu::{Cpu, CpuFlags};
+use rust_dos::interrupts::{int08, int1a, int21};
+use rust_dos::machine::MachineBuilder;
+use rust_dos::shell;
+use std::path::PathBuf;
+
+fn at(y: i32, mo: u32, d: u32, h: u32, mi: u32, s: u32) -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(y, mo, d)
+        .unwrap()
+        .and_hms_opt(h, mi, s)
+        .unwrap()
+}
+
+fn cpu_at(start: NaiveDateTime) -> Cpu {
+    let mut cpu = Cpu::new(PathBuf::from("."));
+    clock::start(&mut cpu.bus, StartTime::Fixed(start));
+    cpu
+}
+
+fn bios_call(cpu: &mut Cpu, ah: u8, cx: u16, dx: u16) -> bool {
+    cpu.ax = (ah as u16) << 8;
+    cpu.cx = cx;
+    cpu.dx = dx;
+    int1a::handle(cpu);
+    !cpu.get_cpu_flag(CpuFlags::CF)
+}
+
+fn dos_call(cpu: &mut Cpu, ah: u8, cx: u16, dx: u16) -> u8 {
+    cpu.ax = (ah as u16) << 8;
+    cpu.cx = cx;
+    cpu.dx = dx;
+    int21::handle(cpu);
+    cpu.get_reg8(Register::AL)
+}
+
+#[test]
+fn test_dos_date_and_time() {
+    let mut cpu = cpu_at(at(1999, 12, 31, 23, 59, 58));
+
+    // Friday
+    assert_eq!(dos_call(&mut cpu, 0x2A, 0, 0), 5);
+    assert_eq!((cpu.cx, cpu.dx), (1999, 0x0C1F));
+    dos_call(&mut cpu, 0x2C, 0, 0);
+    assert_eq!((cpu.cx, cpu.dx), (0x173B, 0x3A00));
+
+    // Pinned dates stay put until emulated time moves them
+    cpu.bus.tick_devices(3 * PIT_FREQUENCY, PIT_FREQUENCY);
+    assert_eq!(dos_call(&mut cpu, 0x2A, 0, 0), 6);
+    assert_eq!((cpu.cx, cpu.dx), (2000, 0x0101));
+    dos_call(&mut cpu, 0x2C, 0, 0);
+    assert_eq!((cpu.cx, cpu.dx), (0x0000, 0x0100));
+
+    assert_eq!(dos_call(&mut cpu, 0x2B, 1995, 0x060F), 0x00);
+    assert_eq!(dos_call(&mut cpu, 0x2D, 0x0D2D, 0x1E32), 0x00);
+    assert_eq!(
+        cpu.bus.clock().now(),
+        at(1995, 6, 15, 13, 45, 30) + TimeDelta::milliseconds(500)
+    );
+
+    assert_eq!(dos_call(&mut cpu, 0x2B, 1979, 0x0101), 0xFF);
+    assert_eq!(dos_call(&mut cpu, 0x2B, 1995, 0x021E), 0xFF);
+    assert_eq!(dos_call(&mut cpu, 0x2D, 0x1800, 0x0000), 0xFF);
+    assert_eq!(dos_call(&mut cpu, 0x2D, 0x0000, 0x0064), 0xFF);
+    assert_eq!(
+        cpu.bus.clock().now().date(),
+        NaiveDate::from_ymd_opt(1995, 6, 15).unwrap()
+    );
+}
+
+#[test]
+fn test_bios_rtc_in_bcd() {
+    let mut cpu = cpu_at(at(1989, 7, 4, 9, 5, 7));
+
+    assert!(bios_call(&mut cpu, 0x02, 0, 0));
+    assert_eq!((cpu.cx, cpu.dx), (0x0905, 0x0700));
+    assert!(bios_call(&mut cpu, 0x04, 0, 0));
+    assert_eq!((cpu.cx, cpu.dx), (0x1989, 0x0704));
+
+    assert!(bios_call(&mut cpu, 0x03, 0x2359, 0x5900));
+    assert!(bios_call(&mut cpu, 0x05, 0x2001, 0x0228));
+    assert_eq!(cpu.bus.clock().now(), at(2001, 2, 28, 23, 59, 59));
+
+    // Not BCD, or not a date
+    assert!(!bios_call(&mut cpu, 0x03, 0x1A00, 0x0000));
+    assert!(!bios_call(&mut cpu, 0x05, 0x2001, 0x0230));
+
+    // One alarm at a time
+    assert!(bios_call(&mut cpu, 0x06, 0x0630, 0x0000));
+    assert!(!bios_call(&mut cpu, 0x06, 0x0700, 0x0000));
+    assert!(bios_call(&mut cpu, 0x07, 0, 0));
+    assert!(bios_call(&mut cpu, 0x06, 0x0700, 0x0000));
+}
+
+#[test]
+fn test_tick_count_follows_the_clock() {
+    let mut cpu = cpu_at(at(2024, 2, 28, 12, 0, 0));
+    // Noon is half a day of ticks
+    assert_eq!(cpu.bus.read_32(0x046C), 786521);
+    bios_call(&mut cpu, 0x00, 0, 0);
+    assert_eq!(((cpu.cx as u32) << 16) | cpu.dx as u32, 786521);
+
+    // Setting the tick count sets the time of day
+    assert!(bios_call(&mut cpu, 0x01, 0x0018, 0x00AF));
+    let now = cpu.bus.clock().now();
+    assert_eq!(now.date(), NaiveDate::from_ymd_opt(2024, 2, 28).unwrap());
+    assert_eq!(now.format("%H:%M:%S").to_string(), "23:59:59");
+    assert_eq!(cpu.bus.read_32(0x046C), 0x1800AF);
+
+    // The timer interrupt brings the BDA up to date and flags midnight once
+    cpu.bus.tick_devices(PIT_FREQUENCY, PIT_FREQUENCY);
+    int08::handle(&mut cpu);
+    assert!(cpu.bus.read_32(0x046C) < 20);
+    assert_eq!(cpu.bus.read_8(0x0470), 1);
+    bios_call(&mut cpu, 0x00, 0, 0);
+    assert_eq!(cpu.get_reg8(Register::AL), 1);
+    bios_call(&mut cpu, 0x00, 0, 0);
+    assert_eq!(cpu.get_reg8(Register::AL), 0);
+    assert_eq!(dos_call(&mut cpu, 0x2A, 0, 0), 4);
+    assert_eq!(cpu.dx, 0x021D);
+}
+
+#[test]
+fn test_date_and_time_commands() {
+    let mut machine = MachineBuilder::new(".")
+        .start_time(StartTime::Fixed(at(1998, 3, 1, 8, 30, 0)))
+        .build();
+    let cpu = &mut machine.cpu;
+    cpu.pending_command = Some("DATE".to_string());
+    shell::run_pending_command(cpu);
+    assert!(machine.screen_text().contains("Current date is Sun 03-01-1998"));
+
+    let cpu = &mut machine.cpu;
+    cpu.pending_command = Some("DATE 12-31-99".to_string());
+    shell::run_pending_command(cpu);
+    cpu.pending_command = Some("TIME 23:15:02.50".to_string());
+    shell::run_pending_command(cpu);
+    assert_eq!(
+        cpu.bus.clock().now(),
+        at(1999, 12, 31, 23, 15, 2) + TimeDelta::milliseconds(500)
+    );
+    cpu.pending_command = Some("TIME".to_string());
+    shell::run_pending_command(cpu);
+    assert!(machine.screen_text().contains("Current time is 23:15:02.50"));
+
+    let cpu = &mut machine.cpu;
+    cpu.pending_command = Some("DATE 13-01-1999".to_string());
+    shell::run_pending_command(cpu);
+    cpu.pending_command = Some("TIME 25:00".to_string());
+    shell::run_pending_command(cpu);
+    let screen = machine.screen_text();
+    assert!(screen.contains("Invalid date"));
+    assert!(screen.contains("Invalid time"));
+    assert_eq!(
+        machine.cpu.bus.clock().now().date(),
+        NaiveDate::from_ymd_opt(1999, 12, 31).unwrap()
+    );
+}
@@ -0,0 +1,12 @@
+use std::fs;
+use std::path::PathBuf;
+
+/// A fresh, empty target/test_<name> to root a drive at
+pub fn setup(name: &str) -> PathBuf {
+    let root_path = PathBuf::from(format!("target/test_{}", name));
+    if root_path.exists() {
+        fs::remove_dir_all(&root_path).unwrap();
+    }
+    fs::create_dir_all(&root_path).unwrap();
+    root_path
+}
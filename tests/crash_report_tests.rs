@@ -0,0 +1,117 @@
+use rust_dos::cpu::Cpu;
+use rust_dos::crash::{self, TRACE_LOG_LEN};
+
+fn load(cpu: &mut Cpu, code: &[u8]) {
+    cpu.cs = 0x2000;
+    cpu.ip = 0x0000;
+    cpu.ss = 0x1000;
+    cpu.sp = 0x0100;
+    for (i, &byte) in code.iter().enumerate() {
+        cpu.bus.write_8(0x20000 + i, byte);
+    }
+}
+
+#[test]
+fn test_history_records_bytes_and_registers() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+    // MOV AX,1234h / MOV BX,AX / INC BX
+    load(&mut cpu, &[0xB8, 0x34, 0x12, 0x89, 0xC3, 0x43]);
+    for _ in 0..3 {
+        cpu.step();
+    }
+
+    assert_eq!(cpu.trace_log.len(), 3);
+    let last = cpu.trace_log.back().unwrap();
+    assert_eq!((last.cs, last.ip), (0x2000, 0x0005));
+    assert_eq!(last.bytes(), &[0x43]);
+    // Registers are captured before the instruction runs
+    assert_eq!(last.ax, 0x1234);
+    assert_eq!(last.bx, 0x1234);
+    assert_eq!(cpu.bx, 0x1235);
+
+    let first = cpu.trace_log.front().unwrap();
+    assert_eq!(first.bytes(), &[0xB8, 0x34, 0x12]);
+}
+
+#[test]
+fn test_history_is_bounded() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+    // JMP $ forever
+    load(&mut cpu, &[0xEB, 0xFE]);
+    for _ in 0..TRACE_LOG_LEN * 3 {
+        cpu.step();
+    }
+    assert_eq!(cpu.trace_log.len(), TRACE_LOG_LEN);
+}
+
+#[test]
+fn test_report_contents() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+    // MOV CX,0BEEFh / PUSH CX
+    load(&mut cpu, &[0xB9, 0xEF, 0xBE, 0x51]);
+    cpu.step();
+    cpu.step();
+
+    let report = crash::report(&mut cpu, "Test");
+    assert!(report.contains("CRASH REPORT: Test at 2000:0003"));
+    assert!(report.contains("CX=BEEF"));
+    assert!(report.contains("1000:00FE  BEEF"), "Stack snapshot starts at SS:SP");
+    assert!(report.contains("2000:0000  B9EFBE"));
+    assert!(report.to_lowercase().contains("push cx"));
+}
+
+#[test]
+fn test_unhandled_opcode_dumps_report() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+    // SALC
+    load(&mut cpu, &[0xD6]);
+    cpu.step();
+    assert_eq!(cpu.last_crash, Some((0x2000, 0x0000)));
+}
+
+#[test]
+fn test_zeroed_memory_reported_on_entry() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+    // NOP, then empty RAM
+    load(&mut cpu, &[0x90, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+    cpu.ds = 0x3000;
+    cpu.step();
+    assert_eq!(cpu.last_crash, None);
+
+    cpu.step();
+    assert_eq!(cpu.last_crash, Some((0x2000, 0x0001)), "Reported at the first ADD [BX+SI],AL");
+
+    cpu.last_crash = None;
+    cpu.step();
+    cpu.step();
+    assert_eq!(cpu.last_crash, None, "Not again while still inside the run");
+}
+
+#[test]
+fn test_panic_dumps_report_and_propagates() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+    load(&mut cpu, &[0x90]);
+    cpu.step();
+
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        crash::guard(&mut cpu, |_| panic!("boom"))
+    }));
+    assert!(result.is_err());
+    assert_eq!(cpu.last_crash, Some((0x2000, 0x0000)));
+}
+
+#[test]
+fn test_history_reads_hma_when_a20_is_on() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+    cpu.bus.set_a20(true);
+    // INC AX at FFFF:0010, which is 100000h rather than 0
+    cpu.cs = 0xFFFF;
+    cpu.ip = 0x0010;
+    cpu.bus.write_8(0x00000, 0x90);
+    cpu.bus.write_8(0x100000, 0x40);
+    cpu.step();
+
+    assert_eq!(cpu.ax, 1);
+    let last = cpu.trace_log.back().unwrap();
+    assert_eq!(last.bytes(), &[0x40], "History must read the HMA, not wrap to 0");
+}
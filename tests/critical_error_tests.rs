@@ -0,0 +1,308 @@
+use iced_x86::Register;
+use rust_dos::cpu::{Cpu, CpuFlags, CpuState};
+use rust_dos::interrupts::int21;
+use rust_dos::machine::MachineBuilder;
+use std::fs;
+use std::path::PathBuf;
+
+mod common;
+
+const SEGMENT: u16 = 0x3000;
+const CALL: u16 = 0x0100;
+const HANDLER: u16 = 0x0300;
+const NAME: u16 = 0x0200;
+// Where the handler leaves the AX and DI it was called with
+const SEEN: usize = 0x30400;
+
+fn addr(offset: u16) -> usize {
+    ((SEGMENT as usize) << 4) + offset as usize
+}
+
+fn write_name(cpu: &mut Cpu, name: &str) {
+    for (i, byte) in name.bytes().chain([0]).enumerate() {
+        cpu.bus.write_8(addr(NAME) + i, byte);
+    }
+}
+
+// A program whose INT 24h handler notes AX and DI and gives `answer`
+fn cpu_with_handler(answer: u8) -> Cpu {
+    let mut cpu = Cpu::new(PathBuf::from("."));
+    cpu.bus.write_16(SEEN, 0xFFFF);
+    let handler = [
+        0xA3, 0x00, 0x04, // MOV [0400h], AX
+        0x89, 0x3E, 0x02, 0x04, // MOV [0402h], DI
+        0x89, 0x36, 0x04, 0x04, // MOV [0404h], SI
+        0x89, 0x2E, 0x06, 0x04, // MOV [0406h], BP
+        0xB0, answer, // MOV AL, answer
+        0xCF, // IRET
+    ];
+    for (i, &byte) in handler.iter().enumerate() {
+        cpu.bus.write_8(addr(HANDLER) + i, byte);
+    }
+    cpu.bus.write_16(0x24 * 4, HANDLER);
+    cpu.bus.write_16(0x24 * 4 + 2, SEGMENT);
+    cpu
+}
+
+// Runs INT 21h with the given AX, BX and CX (DS:DX = the name) until it
+// returns to the program, calling `in_handler` each time the handler starts.
+// Returns CF.
+fn int21(cpu: &mut Cpu, ax: u16, bx: u16, cx: u16, mut in_handler: impl FnMut(&mut Cpu)) -> bool {
+    cpu.bus.write_8(addr(CALL), 0xCD);
+    cpu.bus.write_8(addr(CALL) + 1, 0x21);
+    cpu.cs = SEGMENT;
+    cpu.ip = CALL;
+    cpu.ds = SEGMENT;
+    cpu.es = SEGMENT;
+    cpu.ss = 0x2000;
+    cpu.sp = 0xFFFE;
+    cpu.ax = ax;
+    cpu.bx = bx;
+    cpu.cx = cx;
+    cpu.dx = NAME;
+    for _ in 0..1000 {
+        cpu.step();
+        if cpu.cs == SEGMENT && cpu.ip == HANDLER {
+            in_handler(cpu);
+        }
+        if cpu.state != CpuState::Running {
+            return cpu.get_cpu_flag(CpuFlags::CF);
+        }
+        if (cpu.cs, cpu.ip) == (SEGMENT, CALL + 2) {
+            assert_eq!(cpu.sp, 0xFFFE);
+            return cpu.get_cpu_flag(CpuFlags::CF);
+        }
+    }
+    panic!("INT 21h didn't return");
+}
+
+fn extended_error(cpu: &mut Cpu) -> (u16, u16, u8) {
+    cpu.ax = 0x5900;
+    cpu.bx = 0;
+    int21::handle(cpu);
+    (cpu.ax, cpu.bx, cpu.get_reg8(Register::CH))
+}
+
+#[test]
+fn test_extended_error_information() {
+    let mut cpu = Cpu::new(PathBuf::from("."));
+    cpu.ds = SEGMENT;
+    write_name(&mut cpu, "NO_SUCH.TXT");
+    cpu.ax = 0x3D00;
+    cpu.dx = NAME;
+    int21::handle(&mut cpu);
+    assert!(cpu.get_cpu_flag(CpuFlags::CF));
+    // File not found: class not found, ask the user, block device
+    assert_eq!(extended_error(&mut cpu), (0x02, 0x0803, 0x02));
+
+    cpu.ax = 0x4800;
+    cpu.bx = 0xFFFF;
+    int21::handle(&mut cpu);
+    assert!(cpu.get_cpu_flag(CpuFlags::CF));
+    // Insufficient memory: out of resource, abort, memory
+    assert_eq!(extended_error(&mut cpu), (0x08, 0x0104, 0x05));
+
+    // FCB calls fail with AL=FFh, but keep the reason
+    let fcb = addr(0x0280);
+    cpu.bus.write_8(fcb, 0);
+    for (i, &byte) in b"NO_SUCH TXT".iter().enumerate() {
+        cpu.bus.write_8(fcb + 1 + i, byte);
+    }
+    cpu.ax = 0x0F00;
+    cpu.dx = 0x0280;
+    int21::handle(&mut cpu);
+    assert_eq!(cpu.get_reg8(Register::AL), 0xFF);
+    assert_eq!(extended_error(&mut cpu).0, 0x02);
+}
+
+#[test]
+fn test_write_protect_fail_and_retry() {
+    let mut cpu = cpu_with_handler(3);
+    cpu.bus.disk.set_write_protected(true);
+    write_name(&mut cpu, "TARGET\\CRIT_WP.TMP");
+
+    // Fail: the call returns error 53h
+    assert!(int21(&mut cpu, 0x3C00, 0, 0, |_| {}));
+    assert_eq!(cpu.ax, 0x53);
+    // Directory write, Fail and Retry allowed; drive C:; write protect
+    assert_eq!(cpu.bus.read_16(SEEN), 0x1D02);
+    assert_eq!(cpu.bus.read_16(SEEN + 2), 0x00);
+    assert_eq!((cpu.bus.read_16(SEEN + 6), cpu.bus.read_16(SEEN + 4)), (0xF000, 0x0F30));
+    // 59h still says why
+    assert_eq!(extended_error(&mut cpu), (0x13, 0x0B07, 0x02));
+
+    // Retry after the disk was made writable
+    let path = PathBuf::from("target/CRIT_WP.TMP");
+    let _ = fs::remove_file(&path);
+    let mut cpu = cpu_with_handler(1);
+    cpu.bus.disk.set_write_protected(true);
+    write_name(&mut cpu, "TARGET\\CRIT_WP.TMP");
+    let mut calls = 0;
+    let failed = int21(&mut cpu, 0x3C00, 0, 0, |cpu| {
+        calls += 1;
+        cpu.bus.disk.set_write_protected(false);
+    });
+    assert!(!failed);
+    assert_eq!(calls, 1);
+    assert!(cpu.ax >= 5);
+    assert!(path.exists());
+    cpu.bx = cpu.ax;
+    cpu.ax = 0x3E00;
+    int21::handle(&mut cpu);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_ignore_and_abort() {
+    let path = PathBuf::from("target/crit_ignore.tmp");
+    fs::write(&path, b"").unwrap();
+
+    // Writes to open files may be ignored: reported as done
+    let mut cpu = cpu_with_handler(0);
+    write_name(&mut cpu, "TARGET\\CRIT_IGNORE.TMP");
+    assert!(!int21(&mut cpu, 0x3D02, 0, 0, |_| {}));
+    let handle = cpu.ax;
+    cpu.bus.disk.set_write_protected(true);
+    assert!(!int21(&mut cpu, 0x4000, handle, 16, |_| {}));
+    assert_eq!(cpu.ax, 16);
+    assert_eq!(cpu.bus.read_16(SEEN) >> 8, 0x3F); // Data write, Ignore allowed
+    assert_eq!(fs::metadata(&path).unwrap().len(), 0);
+
+    // Ignore where it isn't allowed is Fail
+    assert!(int21(&mut cpu, 0x4100, 0, 0, |_| {}));
+    assert_eq!(cpu.ax, 0x53);
+    assert!(path.exists());
+
+    // Abort ends the program
+    let mut cpu = cpu_with_handler(2);
+    cpu.bus.disk.set_write_protected(true);
+    write_name(&mut cpu, "TARGET\\CRIT_IGNORE.TMP");
+    int21(&mut cpu, 0x4100, 0, 0, |_| {});
+    assert_eq!(cpu.state, CpuState::RebootShell);
+    assert_eq!(cpu.exit_code, Some(0));
+    assert!(path.exists());
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_sharing_violation_and_empty_drive() {
+    let path = PathBuf::from("target/crit_share.tmp");
+    fs::write(&path, b"shared").unwrap();
+    let mut cpu = cpu_with_handler(3);
+    write_name(&mut cpu, "TARGET\\CRIT_SHARE.TMP");
+
+    // Open denying writes; others may still read
+    assert!(!int21(&mut cpu, 0x3D20, 0, 0, |_| {}));
+    assert!(!int21(&mut cpu, 0x3D40, 0, 0, |_| {}));
+    assert_eq!(cpu.bus.read_16(SEEN), 0xFFFF);
+    assert!(int21(&mut cpu, 0x3D02, 0, 0, |_| {}));
+    assert_eq!(cpu.ax, 0x53);
+    // Directory read on C:, sharing violation
+    assert_eq!(cpu.bus.read_16(SEEN), 0x1C02);
+    assert_eq!(cpu.bus.read_16(SEEN + 2), 0x0D);
+    assert_eq!(extended_error(&mut cpu), (0x20, 0x0A02, 0x02));
+
+    // A: has no disk in it
+    write_name(&mut cpu, "A:\\GAME.EXE");
+    assert!(int21(&mut cpu, 0x3D00, 0, 0, |_| {}));
+    assert_eq!(cpu.bus.read_16(SEEN), 0x1C00);
+    assert_eq!(cpu.bus.read_16(SEEN + 2), 0x02);
+
+    // FCB calls get the handler too, and fail with AL=FFh
+    cpu.bus.disk.set_write_protected(true);
+    let fcb = addr(NAME);
+    cpu.bus.write_8(fcb, 3);
+    for (i, &byte) in b"CRIT_FCBTMP".iter().enumerate() {
+        cpu.bus.write_8(fcb + 1 + i, byte);
+    }
+    int21(&mut cpu, 0x1600, 0, 0, |_| {});
+    assert_eq!(cpu.get_reg8(Register::AL), 0xFF);
+    assert_eq!(cpu.bus.read_16(SEEN), 0x1D02);
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_default_handler_asks() {
+    let root_path = common::setup("crit_def");
+    let mut machine = MachineBuilder::new(root_path.clone()).write_protect(true).build();
+    let cpu = &mut machine.cpu;
+    write_name(cpu, "CRIT_DEF.TMP");
+    cpu.bus.write_8(addr(CALL), 0xCD);
+    cpu.bus.write_8(addr(CALL) + 1, 0x21);
+    cpu.cs = SEGMENT;
+    cpu.ip = CALL;
+    cpu.ds = SEGMENT;
+    cpu.ss = 0x2000;
+    cpu.sp = 0xFFFE;
+    cpu.ax = 0x4100;
+    cpu.dx = NAME;
+
+    // It waits for a key
+    for _ in 0..100 {
+        cpu.step();
+    }
+    assert_ne!((cpu.cs, cpu.ip), (SEGMENT, CALL + 2));
+    let screen = machine.screen_text();
+    assert!(screen.contains("Write protect error writing drive C"));
+    assert!(screen.contains("Abort, Retry, Fail?"));
+
+    // Ignore isn't one of the choices here
+    let cpu = &mut machine.cpu;
+    cpu.bus.keyboard_buffer.push_back(0x1769);
+    for _ in 0..100 {
+        cpu.step();
+    }
+    assert_ne!((cpu.cs, cpu.ip), (SEGMENT, CALL + 2));
+    cpu.bus.keyboard_buffer.push_back(0x2166);
+    for _ in 0..100 {
+        cpu.step();
+        if (cpu.cs, cpu.ip) == (SEGMENT, CALL + 2) {
+            break;
+        }
+    }
+    assert_eq!((cpu.cs, cpu.ip), (SEGMENT, CALL + 2));
+    assert_eq!(cpu.sp, 0xFFFE);
+    assert!(cpu.get_cpu_flag(CpuFlags::CF));
+    assert_eq!(cpu.ax, 0x53);
+
+    fs::remove_dir_all(&root_path).unwrap();
+}
+
+#[test]
+fn test_exit_restores_handler_vectors() {
+    let root_path = common::setup("exit_vectors");
+    // Notes the INT 24h it inherited at 3000:0410, installs its own and exits
+    let child = [
+        0x31, 0xC0, // XOR AX, AX
+        0x8E, 0xD8, // MOV DS, AX
+        0xA1, 0x90, 0x00, // MOV AX, [0090h]
+        0xBB, 0x00, 0x30, // MOV BX, 3000h
+        0x8E, 0xC3, // MOV ES, BX
+        0x26, 0xA3, 0x10, 0x04, // MOV ES:[0410h], AX
+        0xC7, 0x06, 0x90, 0x00, 0x34, 0x12, // MOV WORD [0090h], 1234h
+        0x8C, 0x0E, 0x92, 0x00, // MOV [0092h], CS
+        0xB8, 0x00, 0x4C, // MOV AX, 4C00h
+        0xCD, 0x21, // INT 21h
+    ];
+    fs::write(root_path.join("CHILD.COM"), child).unwrap();
+
+    let mut cpu = Cpu::new(root_path.clone());
+    cpu.current_psp = 0x1000;
+    cpu.bus.write_16(0x24 * 4, HANDLER);
+    cpu.bus.write_16(0x24 * 4 + 2, SEGMENT);
+    write_name(&mut cpu, "CHILD.COM");
+    // Parameter block: inherit the environment, no command line
+    for i in 0..14 {
+        cpu.bus.write_8(addr(0x0280) + i, 0);
+    }
+
+    assert!(!int21(&mut cpu, 0x4B00, 0x0280, 0, |_| {}));
+    assert_eq!(cpu.current_psp, 0x1000);
+    assert_eq!(cpu.bus.read_16(addr(0x0410)), HANDLER, "The child inherits INT 24h");
+    assert_eq!(cpu.bus.read_16(0x24 * 4), HANDLER);
+    assert_eq!(cpu.bus.read_16(0x24 * 4 + 2), SEGMENT);
+
+    fs::remove_dir_all(&root_path).unwrap();
+}
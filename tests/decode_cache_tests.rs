@@ -0,0 +1,74 @@
+use rust_dos::cpu::Cpu;
+use std::path::PathBuf;
+
+fn setup(ip: u16, code: &[u8]) -> Cpu {
+    let mut cpu = Cpu::new(PathBuf::from("."));
+    cpu.cs = 0x1000;
+    cpu.ds = 0x1000;
+    cpu.ss = 0x2000;
+    cpu.sp = 0xFFFE;
+    cpu.ip = ip;
+    let base = cpu.get_physical_addr(cpu.cs, cpu.ip);
+    for (i, &b) in code.iter().enumerate() {
+        cpu.bus.write_8(base + i, b);
+    }
+    cpu
+}
+
+#[test]
+fn test_cached_instructions_are_reused() {
+    // 0100: MOV AX, 1111h
+    // 0103: JMP 0100
+    let mut cpu = setup(0x100, &[0xB8, 0x11, 0x11, 0xEB, 0xFB]);
+    for _ in 0..10 {
+        cpu.step();
+    }
+    assert_eq!(cpu.ax, 0x1111);
+    assert_eq!(cpu.bus.decode_cache.misses, 2);
+    assert_eq!(cpu.bus.decode_cache.hits, 8);
+}
+
+#[test]
+fn test_self_modifying_code() {
+    // 0100: MOV AX, 1111h
+    // 0103: MOV BYTE [0101h], 22h   ; patch the immediate above
+    // 0108: JMP 0100
+    let mut cpu = setup(
+        0x100,
+        &[0xB8, 0x11, 0x11, 0xC6, 0x06, 0x01, 0x01, 0x22, 0xEB, 0xF6],
+    );
+    cpu.step();
+    assert_eq!(cpu.ax, 0x1111);
+    cpu.step(); // Patch
+    cpu.step(); // Jump back
+    cpu.step(); // Re-execute the patched MOV
+    assert_eq!(cpu.ax, 0x1122, "Stale decoded instruction was executed");
+}
+
+#[test]
+fn test_instruction_straddling_pages() {
+    // MOV AX, 1234h at 1000:00FE spans physical 100FE-10100 (two cache pages)
+    let mut cpu = setup(0x00FE, &[0xB8, 0x34, 0x12]);
+    cpu.step();
+    assert_eq!(cpu.ax, 0x1234);
+
+    // Patch the high byte, which lives in the second page
+    cpu.bus.write_8(0x10100, 0x56);
+    cpu.ip = 0x00FE;
+    cpu.step();
+    assert_eq!(cpu.ax, 0x5634);
+}
+
+#[test]
+fn test_same_bytes_through_different_segments() {
+    // JMP SHORT +0 at physical 10100h, reached as 1000:0100 and 1010:0000.
+    // next_ip must follow the segment used.
+    let mut cpu = setup(0x100, &[0xEB, 0x00]);
+    cpu.step();
+    assert_eq!(cpu.ip, 0x102);
+
+    cpu.cs = 0x1010;
+    cpu.ip = 0x0000;
+    cpu.step();
+    assert_eq!(cpu.ip, 0x0002);
+}
@@ -0,0 +1,347 @@
+use iced_x86::Register;
+use rust_dos::bus::Bus;
+use rust_dos::cpu::{Cpu, CpuState};
+use rust_dos::devices::ems;
+use rust_dos::interrupts::int67;
+use std::path::PathBuf;
+
+fn run(cpu: &mut Cpu, code: &[u8]) {
+    cpu.cs = 0x2000;
+    cpu.ip = 0x0000;
+    cpu.ss = 0x1000;
+    cpu.sp = 0x0100;
+    cpu.state = CpuState::Running;
+    for (i, &byte) in code.iter().enumerate() {
+        cpu.bus.write_8(0x20000 + i, byte);
+    }
+    for _ in 0..100 {
+        if cpu.state == CpuState::Halted {
+            break;
+        }
+        cpu.step();
+    }
+    assert_eq!(cpu.state, CpuState::Halted);
+}
+
+// INT 67h with AX/BX/DX, then HLT
+fn ems_call(cpu: &mut Cpu, ax: u16, bx: u16, dx: u16) -> u8 {
+    let mut code = vec![0xB8];
+    code.extend(ax.to_le_bytes());
+    code.push(0xBB);
+    code.extend(bx.to_le_bytes());
+    code.push(0xBA);
+    code.extend(dx.to_le_bytes());
+    code.extend([0xCD, 0x67, 0xF4]);
+    run(cpu, &code);
+    assert_eq!(cpu.sp, 0x0100);
+    cpu.get_reg8(Register::AH)
+}
+
+#[test]
+fn test_ems_detection() {
+    let mut cpu = Cpu::new(PathBuf::from("."));
+    cpu.load_shell();
+
+    // The classic check: "EMMXXXX0" at offset 0Ah of the INT 67h segment
+    let segment = cpu.bus.read_16(0x67 * 4 + 2);
+    let name: Vec<u8> = (0..8)
+        .map(|i| cpu.bus.read_8(((segment as usize) << 4) + 0x0A + i))
+        .collect();
+    assert_eq!(&name, b"EMMXXXX0");
+
+    assert_eq!(ems_call(&mut cpu, 0x4000, 0, 0), 0);
+    assert_eq!(ems_call(&mut cpu, 0x4600, 0, 0), 0);
+    assert_eq!(cpu.get_reg8(Register::AL), 0x40);
+    assert_eq!(ems_call(&mut cpu, 0x4100, 0, 0), 0);
+    assert_eq!(cpu.bx, 0xE000);
+    assert_eq!(ems_call(&mut cpu, 0x4200, 0, 0), 0);
+    assert_eq!((cpu.bx, cpu.dx), (256, 256));
+}
+
+#[test]
+fn test_ems_configuration() {
+    let mut bus = Bus::new(PathBuf::from("."));
+
+    assert!(bus.set_expanded_memory(64, 0xD000).is_ok());
+    assert_eq!(bus.ems().total_pages(), 64);
+    assert_eq!(bus.read_8(0xE0000), 0xFF);
+
+    // Must be 16KB aligned, clear of the BIOS and VGA, and not over an option ROM
+    assert!(bus.set_expanded_memory(64, 0xD100).is_err());
+    assert!(bus.set_expanded_memory(64, 0xF000).is_err());
+    assert!(bus.set_expanded_memory(64, 0xB800).is_err());
+
+    let mut cpu = Cpu::new(PathBuf::from("."));
+    cpu.bus.set_expanded_memory(0, 0xE000).unwrap();
+    cpu.load_shell();
+    assert_eq!(cpu.bus.read_32(0x67 * 4), 0);
+}
+
+#[test]
+fn test_ems_page_banking() {
+    let mut cpu = Cpu::new(PathBuf::from("."));
+    cpu.load_shell();
+
+    assert_eq!(ems_call(&mut cpu, 0x4300, 0, 0), ems::ERR_ZERO_PAGES);
+    assert_eq!(ems_call(&mut cpu, 0x4300, 300, 0), ems::ERR_MORE_THAN_TOTAL);
+    assert_eq!(ems_call(&mut cpu, 0x4300, 4, 0), 0);
+    let handle = cpu.dx;
+    assert_eq!(ems_call(&mut cpu, 0x4C00, 0, handle), 0);
+    assert_eq!(cpu.bx, 4);
+
+    // Nothing mapped yet
+    assert_eq!(cpu.bus.read_8(0xE0000), 0xFF);
+
+    // Logical 0 -> window 0, logical 1 -> window 3
+    assert_eq!(ems_call(&mut cpu, 0x4400, 0, handle), 0);
+    assert_eq!(ems_call(&mut cpu, 0x4403, 1, handle), 0);
+    cpu.bus.write_8(0xE0000, 0xAA);
+    cpu.bus.write_8(0xEC000, 0xBB);
+
+    // Swap them around
+    assert_eq!(ems_call(&mut cpu, 0x4400, 1, handle), 0);
+    assert_eq!(cpu.bus.read_8(0xE0000), 0xBB);
+    assert_eq!(ems_call(&mut cpu, 0x4401, 0, handle), 0);
+    assert_eq!(cpu.bus.read_8(0xE4000), 0xAA);
+
+    // Errors
+    assert_eq!(ems_call(&mut cpu, 0x4404, 0, handle), ems::ERR_PHYSICAL_PAGE);
+    assert_eq!(ems_call(&mut cpu, 0x4400, 4, handle), ems::ERR_LOGICAL_PAGE);
+    assert_eq!(ems_call(&mut cpu, 0x4400, 0, 0x55), ems::ERR_INVALID_HANDLE);
+
+    // Save and restore the mapping around a change
+    assert_eq!(ems_call(&mut cpu, 0x4700, 0, handle), 0);
+    assert_eq!(ems_call(&mut cpu, 0x4700, 0, handle), ems::ERR_ALREADY_SAVED);
+    assert_eq!(ems_call(&mut cpu, 0x4400, 0xFFFF, handle), 0);
+    assert_eq!(cpu.bus.read_8(0xE0000), 0xFF);
+    assert_eq!(ems_call(&mut cpu, 0x4500, 0, handle), ems::ERR_CONTEXT_SAVED);
+    assert_eq!(ems_call(&mut cpu, 0x4800, 0, handle), 0);
+    assert_eq!(cpu.bus.read_8(0xE0000), 0xBB);
+
+    // Shrinking unmaps what's gone; freeing returns the pages
+    assert_eq!(ems_call(&mut cpu, 0x5100, 1, handle), 0);
+    assert_eq!(cpu.bus.read_8(0xE0000), 0xFF);
+    assert_eq!(cpu.bus.read_8(0xE4000), 0xAA);
+    assert_eq!(ems_call(&mut cpu, 0x4500, 0, handle), 0);
+    assert_eq!(ems_call(&mut cpu, 0x4200, 0, 0), 0);
+    assert_eq!(cpu.bx, 256);
+    assert_eq!(cpu.bus.read_8(0xE4000), 0xFF);
+}
+
+#[test]
+fn test_ems_code_runs_from_page_frame() {
+    let mut cpu = Cpu::new(PathBuf::from("."));
+    cpu.load_shell();
+    let handle = cpu.bus.ems_mut().allocate(2).unwrap();
+
+    // Each page holds a routine: MOV AX,imm / RETF
+    for (page, value) in [(0u16, 0x1111u16), (1, 0x2222)] {
+        cpu.bus.ems_mut().map_page(0, handle, Some(page)).unwrap();
+        let [lo, hi] = value.to_le_bytes();
+        for (i, b) in [0xB8, lo, hi, 0xCB].into_iter().enumerate() {
+            cpu.bus.write_8(0xE0000 + i, b);
+        }
+    }
+
+    // CALL FAR E000:0000 / HLT, with each page in turn
+    let code = [0x9A, 0x00, 0x00, 0x00, 0xE0, 0xF4];
+    cpu.bus.ems_mut().map_page(0, handle, Some(0)).unwrap();
+    run(&mut cpu, &code);
+    assert_eq!(cpu.ax, 0x1111);
+    cpu.bus.ems_mut().map_page(0, handle, Some(1)).unwrap();
+    run(&mut cpu, &code);
+    assert_eq!(cpu.ax, 0x2222);
+}
+
+#[test]
+fn test_ems_page_maps_and_names() {
+    let mut cpu = Cpu::new(PathBuf::from("."));
+    cpu.load_shell();
+    let handle = cpu.bus.ems_mut().allocate(2).unwrap();
+
+    // Map two pages by segment (50h, AL=01): (logical, segment) pairs at 3000:0000
+    for (i, word) in [0u16, 0xE400, 1, 0xE800].into_iter().enumerate() {
+        cpu.bus.write_16(0x30000 + i * 2, word);
+    }
+    cpu.ds = 0x3000;
+    cpu.si = 0;
+    cpu.cx = 2;
+    assert_eq!(ems_call(&mut cpu, 0x5001, 0, handle), 0);
+    cpu.bus.write_8(0xE4000, 0x01);
+    cpu.bus.write_8(0xE8000, 0x02);
+
+    // Save the whole map to 3000:0100, clear it, load it back
+    assert_eq!(ems_call(&mut cpu, 0x4E03, 0, 0), 0);
+    assert_eq!(cpu.get_reg8(Register::AL), 16);
+    cpu.es = 0x3000;
+    cpu.di = 0x0100;
+    assert_eq!(ems_call(&mut cpu, 0x4E00, 0, 0), 0);
+    assert_eq!(ems_call(&mut cpu, 0x4401, 0xFFFF, handle), 0);
+    assert_eq!(cpu.bus.read_8(0xE4000), 0xFF);
+    cpu.si = 0x0100;
+    assert_eq!(ems_call(&mut cpu, 0x4E01, 0, 0), 0);
+    assert_eq!(cpu.bus.read_8(0xE4000), 0x01);
+
+    // Mappable address array
+    cpu.di = 0x0200;
+    assert_eq!(ems_call(&mut cpu, 0x5800, 0, 0), 0);
+    assert_eq!(cpu.cx, 4);
+    assert_eq!(cpu.bus.read_16(0x30200 + 12), 0xEC00);
+    assert_eq!(cpu.bus.read_16(0x30200 + 14), 3);
+
+    // Handle names
+    for (i, b) in b"OVERLAYS".iter().enumerate() {
+        cpu.bus.write_8(0x30300 + i, *b);
+    }
+    cpu.si = 0x0300;
+    assert_eq!(ems_call(&mut cpu, 0x5301, 0, handle), 0);
+    let other = cpu.bus.ems_mut().allocate(1).unwrap();
+    assert_eq!(ems_call(&mut cpu, 0x5301, 0, other), ems::ERR_NAME_EXISTS);
+    assert_eq!(ems_call(&mut cpu, 0x5401, 0, 0), 0);
+    assert_eq!(cpu.dx, handle);
+    assert_eq!(ems_call(&mut cpu, 0x5400, 0, 0), 0);
+    assert_eq!(cpu.get_reg8(Register::AL), 3); // OS handle, ours and the other
+
+    assert_eq!(ems_call(&mut cpu, 0x5900, 0, 0), 0);
+    assert_eq!(cpu.bus.read_16(0x30200), 0x0400);
+    assert_eq!(ems_call(&mut cpu, 0x5A00, 0, 0), 0); // Zero pages is fine here
+    let empty = cpu.dx;
+    assert_eq!(ems_call(&mut cpu, 0x4C00, 0, empty), 0);
+    assert_eq!(cpu.bx, 0);
+}
+
+#[test]
+fn test_ems_move_and_exchange() {
+    let mut cpu = Cpu::new(PathBuf::from("."));
+    cpu.load_shell();
+    let handle = cpu.bus.ems_mut().allocate(2).unwrap();
+    for (i, b) in b"CONVENTIONAL".iter().enumerate() {
+        cpu.bus.write_8(0x40000 + i, *b);
+    }
+
+    // Region descriptor at 3000:0000
+    let describe = |cpu: &mut Cpu, len: u32, src: (u8, u16, u16, u16), dst: (u8, u16, u16, u16)| {
+        cpu.bus.write_32(0x30000, len);
+        for (base, (kind, handle, offset, seg)) in [(0x30004, src), (0x3000B, dst)] {
+            cpu.bus.write_8(base, kind);
+            cpu.bus.write_16(base + 1, handle);
+            cpu.bus.write_16(base + 3, offset);
+            cpu.bus.write_16(base + 5, seg);
+        }
+        cpu.ds = 0x3000;
+        cpu.si = 0;
+    };
+
+    // Conventional 4000:0000 -> logical page 0 offset 3FFCh, straddling into page 1
+    describe(&mut cpu, 12, (0, 0, 0, 0x4000), (1, handle, 0x3FFC, 0));
+    assert_eq!(ems_call(&mut cpu, 0x5700, 0, 0), 0);
+    cpu.bus.ems_mut().map_page(0, handle, Some(1)).unwrap();
+    assert_eq!(cpu.bus.read_8(0xE0000), b'E');
+
+    // Exchange it with 5000:0000
+    for i in 0..12 {
+        cpu.bus.write_8(0x50000 + i, b'x');
+    }
+    describe(&mut cpu, 12, (1, handle, 0x3FFC, 0), (0, 0, 0, 0x5000));
+    assert_eq!(ems_call(&mut cpu, 0x5701, 0, 0), 0);
+    assert_eq!(cpu.bus.read_8(0x50000), b'C');
+    assert_eq!(cpu.bus.read_8(0xE0000), b'x');
+
+    // Errors
+    describe(&mut cpu, 16, (1, handle, 0x3FFC, 1), (0, 0, 0, 0x5000));
+    assert_eq!(ems_call(&mut cpu, 0x5700, 0, 0), ems::ERR_LENGTH);
+    describe(&mut cpu, 4, (1, handle, 0x4000, 0), (0, 0, 0, 0x5000));
+    assert_eq!(ems_call(&mut cpu, 0x5700, 0, 0), ems::ERR_OFFSET);
+    describe(&mut cpu, 8, (0, 0, 0, 0x5000), (0, 0, 4, 0x5000));
+    assert_eq!(ems_call(&mut cpu, 0x5700, 0, 0), ems::OVERLAP_MOVED);
+    assert_eq!(cpu.bus.read_8(0x50004), b'C');
+    assert_eq!(ems_call(&mut cpu, 0x5701, 0, 0), ems::ERR_OVERLAP_EXCHANGE);
+}
+
+#[test]
+fn test_ems_alter_map_and_call() {
+    let mut cpu = Cpu::new(PathBuf::from("."));
+    cpu.load_shell();
+    let handle = cpu.bus.ems_mut().allocate(2).unwrap();
+    cpu.bus.ems_mut().map_page(0, handle, Some(1)).unwrap();
+    cpu.bus.write_8(0xE0000, 0x11);
+    cpu.bus.ems_mut().map_page(0, handle, Some(0)).unwrap();
+    cpu.bus.write_8(0xE0000, 0x22);
+
+    // Target 4000:0000: MOV CL,[E000:0000] via ES / RETF
+    // MOV AX,E000 / MOV ES,AX / ES: MOV CL,[0000] / RETF
+    let target = [0xB8, 0x00, 0xE0, 0x8E, 0xC0, 0x26, 0x8A, 0x0E, 0x00, 0x00, 0xCB];
+    for (i, b) in target.into_iter().enumerate() {
+        cpu.bus.write_8(0x40000 + i, b);
+    }
+
+    // Parameters at 3000:0000; new map at 3000:0020 (page 1 -> window 0),
+    // old map at 3000:0030 (page 0 -> window 0)
+    let params = 0x30000;
+    cpu.bus.write_16(params, 0x0000);
+    cpu.bus.write_16(params + 2, 0x4000);
+    cpu.bus.write_8(params + 4, 1);
+    cpu.bus.write_16(params + 5, 0x0020);
+    cpu.bus.write_16(params + 7, 0x3000);
+    cpu.bus.write_8(params + 9, 1);
+    cpu.bus.write_16(params + 10, 0x0030);
+    cpu.bus.write_16(params + 12, 0x3000);
+    cpu.bus.write_16(params + 0x20, 1);
+    cpu.bus.write_16(params + 0x22, 0);
+    cpu.bus.write_16(params + 0x30, 0);
+    cpu.bus.write_16(params + 0x32, 0);
+    cpu.ds = 0x3000;
+    cpu.si = 0;
+
+    assert_eq!(ems_call(&mut cpu, 0x5600, 0, handle), 0);
+    assert_eq!(cpu.get_reg8(Register::CL), 0x11); // Target saw page 1
+    assert_eq!(cpu.bus.read_8(0xE0000), 0x22); // Page 0 is back
+    assert!(cpu.bus.ems().call_stack.is_empty());
+
+    assert_eq!(ems_call(&mut cpu, 0x5602, 0, 0), 0);
+    assert!(cpu.bx >= 4);
+
+    // Alter map and jump: the target's RETF goes nowhere useful, so jump to a HLT
+    cpu.bus.write_8(0x40100, 0xF4);
+    cpu.bus.write_16(params, 0x0100);
+    run(
+        &mut cpu,
+        &[0xB8, 0x00, 0x55, 0xBA, handle as u8, (handle >> 8) as u8, 0xCD, 0x67],
+    );
+    assert_eq!(cpu.cs, 0x4000);
+    assert_eq!(cpu.bus.read_8(0xE0000), 0x11);
+    assert_eq!(cpu.get_reg8(Register::AH), 0);
+    assert_eq!(int67::DRIVER_SEGMENT, cpu.bus.read_16(0x67 * 4 + 2));
+}
+
+#[test]
+fn test_ems_instruction_running_into_page_frame() {
+    let mut bus = Bus::new(PathBuf::from("."));
+    // Upper memory fills D0000-DFFFF, right below the frame
+    bus.set_upper_memory(true);
+    let handle = bus.ems_mut().allocate(1).unwrap();
+    bus.ems_mut().map_page(0, handle, Some(0)).unwrap();
+
+    // MOV AX, 1234h whose last byte is in the frame
+    bus.write_8(0xDFFFE, 0xB8);
+    bus.write_8(0xDFFFF, 0x34);
+    bus.write_8(0xE0000, 0x12);
+    assert_eq!(bus.fetch_instruction(0xDFFFE, 0xFFFE).immediate16(), 0x1234);
+}
+
+#[test]
+fn test_instruction_fetch_at_the_ends_of_memory() {
+    let mut bus = Bus::new(PathBuf::from("."));
+
+    // A20 off: an instruction crossing FFFFF wraps to 00000
+    bus.write_rom(0xFFFFE, &[0xB8, 0x34]);
+    bus.write_8(0x00000, 0x12);
+    assert_eq!(bus.fetch_instruction(0xFFFFE, 0xFFFE).immediate16(), 0x1234);
+
+    // A20 on: past the HMA is open bus, whatever extended memory holds
+    bus.set_a20(true);
+    bus.write_8(0x10FFFE, 0xB8);
+    bus.write_8(0x10FFFF, 0x34);
+    bus.ram[0x110000] = 0x12;
+    assert_eq!(bus.fetch_instruction(0x10FFFE, 0xFFFE).immediate16(), 0xFF34);
+}
@@ -49,4 +49,228 @@ fn test_f80_extreme_garbage_values() {
     
     // Should not panic, result remains A
     assert_eq!(res.get_exponent(), 0x7FFE);
-}
\ No newline at end of file
+}
+// --- Software floating point ---
+
+use rust_dos::f80::{FP_DENORMAL, FP_INVALID, FP_OVERFLOW, FP_PRECISION, FP_UNDERFLOW, FP_ZERO_DIVIDE};
+use rust_dos::f80::{FpEnv, Precision, Rounding};
+
+fn from_f64(v: f64) -> F80 {
+    F80::from_f64_bits(v.to_bits(), &mut FpEnv::default())
+}
+
+fn env_with(rounding: Rounding, precision: Precision) -> FpEnv {
+    FpEnv {
+        rounding,
+        precision,
+        ..FpEnv::default()
+    }
+}
+
+#[test]
+fn test_f80_div_one_third_extended() {
+    let mut env = FpEnv::default();
+    let third = from_f64(1.0).div_with(from_f64(3.0), &mut env);
+
+    // What a 387 leaves in ST(0) after FLD1 / FDIV 3
+    assert_eq!(third.get(), 0x3FFD_AAAA_AAAA_AAAA_AAAB);
+    assert_eq!(env.exceptions, FP_PRECISION);
+    assert!(env.rounded_up);
+}
+
+#[test]
+fn test_f80_div_rounding_modes() {
+    let one = from_f64(1.0);
+    let three = from_f64(3.0);
+
+    let mut env = env_with(Rounding::Down, Precision::Extended);
+    assert_eq!(one.div_with(three, &mut env).get(), 0x3FFD_AAAA_AAAA_AAAA_AAAA);
+    assert!(!env.rounded_up);
+
+    let mut env = env_with(Rounding::Zero, Precision::Extended);
+    assert_eq!(one.div_with(three, &mut env).get(), 0x3FFD_AAAA_AAAA_AAAA_AAAA);
+
+    let mut env = env_with(Rounding::Up, Precision::Extended);
+    assert_eq!(one.div_with(three, &mut env).get(), 0x3FFD_AAAA_AAAA_AAAA_AAAB);
+
+    // Rounding down a negative quotient moves away from zero
+    let mut env = env_with(Rounding::Down, Precision::Extended);
+    let neg = from_f64(-1.0).div_with(three, &mut env);
+    assert_eq!(neg.get(), 0xBFFD_AAAA_AAAA_AAAA_AAAB);
+}
+
+#[test]
+fn test_f80_precision_control() {
+    let one = from_f64(1.0);
+    let three = from_f64(3.0);
+
+    let mut env = env_with(Rounding::Nearest, Precision::Single);
+    assert_eq!(one.div_with(three, &mut env).get(), 0x3FFD_AAAA_AB00_0000_0000);
+
+    let mut env = env_with(Rounding::Nearest, Precision::Double);
+    assert_eq!(one.div_with(three, &mut env).get(), 0x3FFD_AAAA_AAAA_AAAA_A800);
+
+    // PC limits the significand only, the exponent range stays extended
+    let mut env = env_with(Rounding::Nearest, Precision::Single);
+    let huge = from_f64(f64::MAX).mul_with(from_f64(f64::MAX), &mut env);
+    assert!(!huge.is_infinite());
+    assert_eq!(env.exceptions & FP_OVERFLOW, 0);
+}
+
+#[test]
+fn test_f80_sqrt_two() {
+    let mut env = FpEnv::default();
+    let root = from_f64(2.0).sqrt_with(&mut env);
+    assert_eq!(root.get(), 0x3FFF_B504_F333_F9DE_6484);
+    assert_eq!(env.exceptions, FP_PRECISION);
+
+    // Exact roots stay exact
+    let mut env = FpEnv::default();
+    assert_eq!(from_f64(144.0).sqrt_with(&mut env).get_f64(), 12.0);
+    assert_eq!(env.exceptions, 0);
+
+    let mut env = FpEnv::default();
+    assert_eq!(from_f64(-4.0).sqrt_with(&mut env).get(), F80::indefinite().get());
+    assert_eq!(env.exceptions, FP_INVALID);
+}
+
+#[test]
+fn test_f80_mul_keeps_64_bits() {
+    // 3037000499^2 needs 63 significant bits, more than an f64 holds
+    let mut env = FpEnv::default();
+    let a = F80::from_i64(3_037_000_499);
+    let sq = a.mul_with(a, &mut env);
+    assert_eq!(env.exceptions, 0);
+    assert_eq!(sq.to_int_with(64, &mut env), Some(9_223_372_030_926_249_001));
+}
+
+#[test]
+fn test_f80_special_cases() {
+    let mut env = FpEnv::default();
+    let inf = from_f64(1.0).div_with(from_f64(0.0), &mut env);
+    assert_eq!(inf.get(), F80::infinity(false).get());
+    assert_eq!(env.exceptions, FP_ZERO_DIVIDE);
+
+    let mut env = FpEnv::default();
+    let nan = from_f64(0.0).div_with(from_f64(0.0), &mut env);
+    assert_eq!(nan.get(), F80::indefinite().get());
+    assert_eq!(env.exceptions, FP_INVALID);
+
+    let mut env = FpEnv::default();
+    let nan = F80::infinity(false).sub_with(F80::infinity(false), &mut env);
+    assert_eq!(nan.get(), F80::indefinite().get());
+    assert_eq!(env.exceptions, FP_INVALID);
+
+    // x - x is +0 except when rounding down
+    let mut env = env_with(Rounding::Down, Precision::Extended);
+    let zero = from_f64(5.0).sub_with(from_f64(5.0), &mut env);
+    assert_eq!(zero.get(), F80::zero(true).get());
+}
+
+#[test]
+fn test_f80_overflow_and_underflow() {
+    let mut max = F80::new();
+    max.set(0x7FFE_FFFF_FFFF_FFFF_FFFF);
+
+    let mut env = FpEnv::default();
+    let inf = max.mul_with(from_f64(2.0), &mut env);
+    assert_eq!(inf.get(), F80::infinity(false).get());
+    assert_eq!(env.exceptions, FP_OVERFLOW | FP_PRECISION);
+
+    // Chopping clamps to the largest finite value instead
+    let mut env = env_with(Rounding::Zero, Precision::Extended);
+    assert_eq!(max.mul_with(from_f64(2.0), &mut env).get(), max.get());
+
+    // Halving the smallest normal is exact: a denormal, but no UE while masked
+    let mut min_normal = F80::new();
+    min_normal.set(0x0001_8000_0000_0000_0000);
+    let mut env = FpEnv::default();
+    let denormal = min_normal.mul_with(from_f64(0.5), &mut env);
+    assert_eq!(denormal.get(), 0x0000_4000_0000_0000_0000);
+    assert_eq!(env.exceptions, 0);
+
+    // Losing bits on the way down raises UE and PE
+    let mut odd = F80::new();
+    odd.set(0x0001_8000_0000_0000_0001);
+    let mut env = FpEnv::default();
+    odd.mul_with(from_f64(0.5), &mut env);
+    assert_eq!(env.exceptions, FP_UNDERFLOW | FP_PRECISION);
+}
+
+#[test]
+fn test_f80_ieee_conversions() {
+    // Smallest f64 denormal loads exactly and flags DE
+    let mut env = FpEnv::default();
+    let tiny = F80::from_f64_bits(1, &mut env);
+    assert_eq!(tiny.get(), 0x3BCD_8000_0000_0000_0000);
+    assert_eq!(env.exceptions, FP_DENORMAL);
+
+    let third = from_f64(1.0).div_with(from_f64(3.0), &mut FpEnv::default());
+
+    let mut env = FpEnv::default();
+    assert_eq!(third.to_f32_bits(&mut env), 0x3EAA_AAAB);
+    assert_eq!(env.exceptions, FP_PRECISION);
+
+    let mut env = env_with(Rounding::Zero, Precision::Extended);
+    assert_eq!(third.to_f32_bits(&mut env), 0x3EAA_AAAA);
+
+    let mut env = FpEnv::default();
+    assert_eq!(f64::from_bits(third.to_f64_bits(&mut env)), 1.0 / 3.0);
+
+    // Too large for a float: infinity with OE
+    let mut env = FpEnv::default();
+    assert_eq!(from_f64(1e300).to_f32_bits(&mut env), 0x7F80_0000);
+    assert_eq!(env.exceptions, FP_OVERFLOW | FP_PRECISION);
+}
+
+#[test]
+fn test_f80_integer_conversion() {
+    let mut env = FpEnv::default();
+    assert_eq!(from_f64(2.5).to_int_with(16, &mut env), Some(2));
+    assert_eq!(from_f64(3.5).to_int_with(16, &mut env), Some(4));
+    assert_eq!(from_f64(-2.5).to_int_with(16, &mut env), Some(-2));
+
+    let mut env = env_with(Rounding::Down, Precision::Extended);
+    assert_eq!(from_f64(-2.5).to_int_with(16, &mut env), Some(-3));
+
+    let mut env = FpEnv::default();
+    assert_eq!(from_f64(-32768.0).to_int_with(16, &mut env), Some(-32768));
+    assert_eq!(from_f64(40000.0).to_int_with(16, &mut env), None);
+    assert_eq!(env.exceptions & FP_INVALID, FP_INVALID);
+
+    let mut env = env_with(Rounding::Up, Precision::Extended);
+    assert_eq!(from_f64(0.25).round_int_with(&mut env).get_f64(), 1.0);
+    assert!(env.rounded_up);
+}
+
+#[test]
+fn test_f80_partial_remainder() {
+    let mut env = FpEnv::default();
+    let (rem, q, complete) = from_f64(10.0).rem_with(from_f64(3.0), false, &mut env);
+    assert!(complete);
+    assert_eq!(rem.get_f64(), 1.0);
+    assert_eq!(q, 3);
+
+    // IEEE remainder rounds the quotient to nearest
+    let (rem, _, _) = from_f64(10.0).rem_with(from_f64(3.0), true, &mut env);
+    assert_eq!(rem.get_f64(), 1.0);
+    let (rem, _, _) = from_f64(11.0).rem_with(from_f64(3.0), true, &mut env);
+    assert_eq!(rem.get_f64(), -1.0);
+
+    // Exponents more than 63 apart need several passes
+    let mut x = from_f64(1e30);
+    let y = from_f64(3.0);
+    let mut passes = 0;
+    loop {
+        let (rem, _, complete) = x.rem_with(y, false, &mut env);
+        x = rem;
+        passes += 1;
+        if complete {
+            break;
+        }
+    }
+    assert!(passes > 1);
+    // 1e30 = 3 * 333...333 + 1
+    assert_eq!(x.get_f64(), 1.0);
+    assert_eq!(env.exceptions & FP_PRECISION, 0);
+}
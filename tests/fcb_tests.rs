@@ -0,0 +1,242 @@
+use iced_x86::Register;
+use rust_dos::cpu::Cpu;
+use rust_dos::fcb;
+use rust_dos::handles;
+use rust_dos::interrupts::int21;
+use std::fs;
+use std::path::PathBuf;
+
+const SEGMENT: u16 = 0x4000;
+const FCB: u16 = 0x0100;
+const DTA: u16 = 0x0200;
+const PARENT: u16 = 0x2000;
+const CHILD: u16 = 0x3000;
+
+fn setup(name: &str) -> (Cpu, PathBuf) {
+    let root_path = PathBuf::from(format!("target/test_fcb_{}", name));
+    if root_path.exists() {
+        fs::remove_dir_all(&root_path).unwrap();
+    }
+    fs::create_dir_all(&root_path).unwrap();
+
+    let mut cpu = Cpu::new(root_path.clone());
+    cpu.bus.dta_segment = SEGMENT;
+    cpu.bus.dta_offset = DTA;
+    (cpu, root_path)
+}
+
+fn addr(offset: u16) -> usize {
+    ((SEGMENT as usize) << 4) + offset as usize
+}
+
+// A cleared FCB for `name`, blank-padded to 8.3
+fn set_fcb(cpu: &mut Cpu, name: &[u8; 11]) {
+    for i in 0..0x25 {
+        cpu.bus.write_8(addr(FCB) + i, 0);
+    }
+    for (i, &b) in name.iter().enumerate() {
+        cpu.bus.write_8(addr(FCB) + 1 + i, b);
+    }
+}
+
+fn fcb_call(cpu: &mut Cpu, ah: u8, cx: u16) -> u8 {
+    cpu.ds = SEGMENT;
+    cpu.dx = FCB;
+    cpu.cx = cx;
+    cpu.ax = (ah as u16) << 8;
+    int21::handle(cpu);
+    cpu.get_reg8(Register::AL)
+}
+
+fn fill_dta(cpu: &mut Cpu, data: &[u8]) {
+    for (i, &b) in data.iter().enumerate() {
+        cpu.bus.write_8(addr(DTA) + i, b);
+    }
+}
+
+fn dta(cpu: &Cpu, len: usize) -> Vec<u8> {
+    (0..len).map(|i| cpu.bus.read_8(addr(DTA) + i)).collect()
+}
+
+#[test]
+fn test_fcb_sequential_io() {
+    let (mut cpu, root_path) = setup("sequential");
+
+    set_fcb(&mut cpu, b"DATA    TXT");
+    assert_eq!(fcb_call(&mut cpu, 0x0F, 0), fcb::FAILURE);
+    assert_eq!(fcb_call(&mut cpu, 0x16, 0), fcb::SUCCESS);
+    // Opening fills in the drive, record size and file size
+    assert_eq!(cpu.bus.read_8(addr(FCB)), 3);
+    assert_eq!(cpu.bus.read_16(addr(FCB) + 0x0E), 128);
+
+    // Two 4-byte records
+    cpu.bus.write_16(addr(FCB) + 0x0E, 4);
+    fill_dta(&mut cpu, b"ABCD");
+    assert_eq!(fcb_call(&mut cpu, 0x15, 0), fcb::SUCCESS);
+    fill_dta(&mut cpu, b"EFGH");
+    assert_eq!(fcb_call(&mut cpu, 0x15, 0), fcb::SUCCESS);
+    assert_eq!(cpu.bus.read_8(addr(FCB) + 0x20), 2);
+    assert_eq!(cpu.bus.read_32(addr(FCB) + 0x10), 8);
+    assert_eq!(fcb_call(&mut cpu, 0x10, 0), fcb::SUCCESS);
+    assert_eq!(fcb_call(&mut cpu, 0x10, 0), fcb::FAILURE);
+    assert_eq!(fs::read(root_path.join("DATA.TXT")).unwrap(), b"ABCDEFGH");
+
+    // Read back in 3-byte records: the last is short and zero-padded
+    fs::write(root_path.join("DATA.TXT"), b"ABCDEFG").unwrap();
+    set_fcb(&mut cpu, b"DATA    TXT");
+    assert_eq!(fcb_call(&mut cpu, 0x0F, 0), fcb::SUCCESS);
+    assert_eq!(cpu.bus.read_32(addr(FCB) + 0x10), 7);
+    cpu.bus.write_16(addr(FCB) + 0x0E, 3);
+    assert_eq!(fcb_call(&mut cpu, 0x14, 0), fcb::SUCCESS);
+    assert_eq!(dta(&cpu, 3), b"ABC");
+    assert_eq!(fcb_call(&mut cpu, 0x14, 0), fcb::SUCCESS);
+    assert_eq!(fcb_call(&mut cpu, 0x14, 0), fcb::PARTIAL_RECORD);
+    assert_eq!(dta(&cpu, 3), b"G\0\0");
+    assert_eq!(fcb_call(&mut cpu, 0x14, 0), fcb::END_OF_FILE);
+    fcb_call(&mut cpu, 0x10, 0);
+
+    fs::remove_dir_all(&root_path).unwrap();
+}
+
+#[test]
+fn test_fcb_random_and_block_io() {
+    let (mut cpu, root_path) = setup("random");
+    fs::write(root_path.join("DATA.BIN"), b"0123456789").unwrap();
+
+    set_fcb(&mut cpu, b"DATA    BIN");
+    assert_eq!(fcb_call(&mut cpu, 0x0F, 0), fcb::SUCCESS);
+    cpu.bus.write_16(addr(FCB) + 0x0E, 2);
+
+    // Random read of record 3 makes it the current record
+    cpu.bus.write_32(addr(FCB) + 0x21, 3);
+    assert_eq!(fcb_call(&mut cpu, 0x21, 0), fcb::SUCCESS);
+    assert_eq!(dta(&cpu, 2), b"67");
+    assert_eq!(cpu.bus.read_8(addr(FCB) + 0x20), 3);
+    assert_eq!(cpu.bus.read_32(addr(FCB) + 0x21), 3);
+
+    fill_dta(&mut cpu, b"xy");
+    assert_eq!(fcb_call(&mut cpu, 0x22, 0), fcb::SUCCESS);
+    assert_eq!(fs::read(root_path.join("DATA.BIN")).unwrap(), b"012345xy89");
+
+    // Set Random Record follows the sequential position
+    fcb_call(&mut cpu, 0x14, 0);
+    fcb_call(&mut cpu, 0x24, 0);
+    assert_eq!(cpu.bus.read_32(addr(FCB) + 0x21), 4);
+
+    // A block read stops at the end and moves both positions
+    cpu.bus.write_32(addr(FCB) + 0x21, 2);
+    assert_eq!(fcb_call(&mut cpu, 0x27, 5), fcb::END_OF_FILE);
+    assert_eq!(cpu.cx, 3);
+    assert_eq!(dta(&cpu, 6), b"45xy89");
+    assert_eq!(cpu.bus.read_32(addr(FCB) + 0x21), 5);
+    assert_eq!(cpu.bus.read_8(addr(FCB) + 0x20), 5);
+
+    // Block write appends; with no records it sets the size
+    fill_dta(&mut cpu, b"ABCD");
+    assert_eq!(fcb_call(&mut cpu, 0x28, 2), fcb::SUCCESS);
+    assert_eq!(cpu.cx, 2);
+    assert_eq!(cpu.bus.read_32(addr(FCB) + 0x10), 14);
+    cpu.bus.write_32(addr(FCB) + 0x21, 2);
+    assert_eq!(fcb_call(&mut cpu, 0x28, 0), fcb::SUCCESS);
+    fcb_call(&mut cpu, 0x10, 0);
+    assert_eq!(fs::read(root_path.join("DATA.BIN")).unwrap(), b"0123");
+
+    // File size in records, rounded up
+    set_fcb(&mut cpu, b"DATA    BIN");
+    cpu.bus.write_16(addr(FCB) + 0x0E, 3);
+    assert_eq!(fcb_call(&mut cpu, 0x23, 0), fcb::SUCCESS);
+    assert_eq!(cpu.bus.read_32(addr(FCB) + 0x21), 2);
+    set_fcb(&mut cpu, b"NOPE    BIN");
+    assert_eq!(fcb_call(&mut cpu, 0x23, 0), fcb::FAILURE);
+
+    fs::remove_dir_all(&root_path).unwrap();
+}
+
+#[test]
+fn test_fcb_rename_and_delete_wildcards() {
+    let (mut cpu, root_path) = setup("rename");
+    for name in ["A.TXT", "B.TXT", "C.DAT"] {
+        fs::write(root_path.join(name), name).unwrap();
+    }
+
+    // ????????.TXT -> ????????.BAK
+    set_fcb(&mut cpu, b"????????TXT");
+    for (i, &b) in b"????????BAK".iter().enumerate() {
+        cpu.bus.write_8(addr(FCB) + 0x11 + i, b);
+    }
+    assert_eq!(fcb_call(&mut cpu, 0x17, 0), fcb::SUCCESS);
+    assert_eq!(fs::read(root_path.join("A.BAK")).unwrap(), b"A.TXT");
+    assert!(root_path.join("B.BAK").exists());
+    assert!(!root_path.join("A.TXT").exists());
+    assert_eq!(fcb_call(&mut cpu, 0x17, 0), fcb::FAILURE);
+
+    set_fcb(&mut cpu, b"????????BAK");
+    assert_eq!(fcb_call(&mut cpu, 0x13, 0), fcb::SUCCESS);
+    assert!(!root_path.join("A.BAK").exists());
+    assert!(!root_path.join("B.BAK").exists());
+    assert!(root_path.join("C.DAT").exists());
+    assert_eq!(fcb_call(&mut cpu, 0x13, 0), fcb::FAILURE);
+
+    fs::remove_dir_all(&root_path).unwrap();
+}
+
+#[test]
+fn test_extended_fcb() {
+    let (mut cpu, root_path) = setup("extended");
+
+    // FFh, 5 reserved bytes, attribute, then the FCB itself at +7
+    set_fcb(&mut cpu, b"\0\0\0\0\0\0\0\0\0\0\0");
+    cpu.bus.write_8(addr(FCB), 0xFF);
+    cpu.bus.write_8(addr(FCB) + 6, 0x01);
+    for (i, &b) in b"RO      TXT".iter().enumerate() {
+        cpu.bus.write_8(addr(FCB) + 8 + i, b);
+    }
+    assert_eq!(fcb_call(&mut cpu, 0x16, 0), fcb::SUCCESS);
+    assert_eq!(cpu.bus.read_8(addr(FCB) + 7), 3);
+    assert_eq!(cpu.bus.read_16(addr(FCB) + 7 + 0x0E), 128);
+    assert_eq!(fcb_call(&mut cpu, 0x10, 0), fcb::SUCCESS);
+    assert!(
+        fs::metadata(root_path.join("RO.TXT"))
+            .unwrap()
+            .permissions()
+            .readonly()
+    );
+
+    // Read-only files still open, for reading
+    assert_eq!(fcb_call(&mut cpu, 0x0F, 0), fcb::SUCCESS);
+    assert_eq!(fcb_call(&mut cpu, 0x14, 0), fcb::END_OF_FILE);
+    assert_eq!(fcb_call(&mut cpu, 0x15, 0), fcb::DISK_FULL);
+    fcb_call(&mut cpu, 0x10, 0);
+
+    fs::remove_dir_all(&root_path).unwrap();
+}
+
+#[test]
+fn test_fcb_files_close_when_the_process_ends() {
+    let (mut cpu, root_path) = setup("terminate");
+    fs::write(root_path.join("DATA.TXT"), b"fcb").unwrap();
+    handles::init_psp(&mut cpu.bus, PARENT, 0);
+    cpu.current_psp = PARENT;
+    let sft_of = |cpu: &Cpu| cpu.bus.read_8(addr(FCB) + 0x18);
+
+    set_fcb(&mut cpu, b"DATA    TXT");
+    assert_eq!(fcb_call(&mut cpu, 0x0F, 0), fcb::SUCCESS);
+    let parent_sft = sft_of(&cpu);
+
+    // The child opens the same file and exits without closing it
+    cpu.save_process_context();
+    handles::init_psp(&mut cpu.bus, CHILD, PARENT);
+    cpu.current_psp = CHILD;
+    set_fcb(&mut cpu, b"DATA    TXT");
+    assert_eq!(fcb_call(&mut cpu, 0x0F, 0), fcb::SUCCESS);
+    let child_sft = sft_of(&cpu);
+    assert_eq!(cpu.bus.disk.sft_entry(child_sft).unwrap().fcb_owner, Some(CHILD));
+    cpu.ax = 0x4C00;
+    int21::handle(&mut cpu);
+
+    assert_eq!(cpu.current_psp, PARENT);
+    assert!(cpu.bus.disk.sft_entry(child_sft).is_none());
+    assert_eq!(cpu.bus.disk.sft_entry(parent_sft).unwrap().refs, 1);
+
+    fs::remove_dir_all(&root_path).unwrap();
+}
@@ -0,0 +1,192 @@
+use iced_x86::Register;
+use rust_dos::cpu::{Cpu, CpuFlags};
+use rust_dos::disk;
+use rust_dos::handles;
+use rust_dos::interrupts::int21;
+use std::fs;
+use std::path::PathBuf;
+
+const PSP: u16 = 0x2000;
+const BUFFER: u16 = 0x4000;
+
+fn setup(name: &str) -> (Cpu, PathBuf) {
+    let root_path = PathBuf::from(format!("target/test_file_mgmt_{}", name));
+    if root_path.exists() {
+        fs::remove_dir_all(&root_path).unwrap();
+    }
+    fs::create_dir_all(root_path.join("SUB")).unwrap();
+    fs::write(root_path.join("DATA.TXT"), b"0123456789").unwrap();
+
+    let mut cpu = Cpu::new(root_path.clone());
+    handles::init_psp(&mut cpu.bus, PSP, 0);
+    cpu.current_psp = PSP;
+    (cpu, root_path)
+}
+
+// Puts `name` at BUFFER:offset
+fn put_name(cpu: &mut Cpu, offset: u16, name: &str) {
+    let addr = ((BUFFER as usize) << 4) + offset as usize;
+    for (i, b) in name.bytes().chain([0]).enumerate() {
+        cpu.bus.write_8(addr + i, b);
+    }
+}
+
+fn dos_call(cpu: &mut Cpu, ax: u16, bx: u16, cx: u16, dx: u16) -> Result<u16, u16> {
+    cpu.ax = ax;
+    cpu.bx = bx;
+    cpu.cx = cx;
+    cpu.dx = dx;
+    int21::handle(cpu);
+    if cpu.get_cpu_flag(CpuFlags::CF) {
+        Err(cpu.ax)
+    } else {
+        Ok(cpu.ax)
+    }
+}
+
+// AH=3Ch..5Bh calls taking a filename in DS:DX
+fn path_call(cpu: &mut Cpu, ax: u16, name: &str, cx: u16) -> Result<u16, u16> {
+    put_name(cpu, 0, name);
+    cpu.ds = BUFFER;
+    dos_call(cpu, ax, 0, cx, 0)
+}
+
+fn rename(cpu: &mut Cpu, from: &str, to: &str) -> Result<u16, u16> {
+    put_name(cpu, 0, from);
+    put_name(cpu, 0x80, to);
+    cpu.ds = BUFFER;
+    cpu.es = BUFFER;
+    cpu.set_reg16(Register::DI, 0x80);
+    dos_call(cpu, 0x5600, 0, 0, 0)
+}
+
+fn extended_open(cpu: &mut Cpu, name: &str, mode: u16, action: u16) -> Result<u16, u16> {
+    put_name(cpu, 0, name);
+    cpu.ds = BUFFER;
+    cpu.set_reg16(Register::SI, 0);
+    dos_call(cpu, 0x6C00, mode, 0, action)
+}
+
+#[test]
+fn test_delete_rename_and_attributes() {
+    let (mut cpu, root_path) = setup("delete");
+
+    assert_eq!(path_call(&mut cpu, 0x4300, "DATA.TXT", 0), Ok(0x20));
+    assert_eq!(cpu.cx, 0x20);
+    assert_eq!(path_call(&mut cpu, 0x4300, "SUB", 0), Ok(0x10));
+    assert_eq!(path_call(&mut cpu, 0x4300, "NOPE.TXT", 0), Err(2));
+    assert_eq!(path_call(&mut cpu, 0x4300, "NODIR\\DATA.TXT", 0), Err(3));
+
+    // Read-only files can't be deleted, renamed over or opened for writing
+    assert!(path_call(&mut cpu, 0x4301, "DATA.TXT", 0x21).is_ok());
+    assert_eq!(path_call(&mut cpu, 0x4300, "DATA.TXT", 0), Ok(0x21));
+    assert_eq!(path_call(&mut cpu, 0x3D01, "DATA.TXT", 0), Err(5));
+    assert_eq!(path_call(&mut cpu, 0x4100, "DATA.TXT", 0), Err(5));
+    assert_eq!(path_call(&mut cpu, 0x4301, "DATA.TXT", 0x10), Err(5));
+    assert!(path_call(&mut cpu, 0x4301, "DATA.TXT", 0x20).is_ok());
+
+    // Rename moves within the drive, but never over another file
+    fs::write(root_path.join("OTHER.TXT"), b"x").unwrap();
+    assert_eq!(rename(&mut cpu, "DATA.TXT", "OTHER.TXT"), Err(5));
+    assert_eq!(rename(&mut cpu, "NOPE.TXT", "NEW.TXT"), Err(2));
+    assert_eq!(rename(&mut cpu, "DATA.TXT", "NODIR\\NEW.TXT"), Err(3));
+    assert_eq!(rename(&mut cpu, "DATA.TXT", "Z:\\NEW.TXT"), Err(0x11));
+    assert!(rename(&mut cpu, "DATA.TXT", "SUB\\MOVED.TXT").is_ok());
+    assert_eq!(
+        fs::read(root_path.join("SUB").join("MOVED.TXT")).unwrap(),
+        b"0123456789"
+    );
+
+    assert!(path_call(&mut cpu, 0x4100, "SUB\\MOVED.TXT", 0).is_ok());
+    assert!(!root_path.join("SUB").join("MOVED.TXT").exists());
+    assert_eq!(path_call(&mut cpu, 0x4100, "SUB\\MOVED.TXT", 0), Err(2));
+    assert_eq!(path_call(&mut cpu, 0x4100, "SUB", 0), Err(5));
+
+    fs::remove_dir_all(&root_path).unwrap();
+}
+
+#[test]
+fn test_create_variants() {
+    let (mut cpu, root_path) = setup("create");
+
+    // Opening no longer creates; 3Ch truncates, 5Bh refuses to
+    assert_eq!(path_call(&mut cpu, 0x3D02, "NEW.TXT", 0), Err(2));
+    let handle = path_call(&mut cpu, 0x3C00, "DATA.TXT", 0).unwrap();
+    assert_eq!(fs::read(root_path.join("DATA.TXT")).unwrap(), b"");
+    dos_call(&mut cpu, 0x3E00, handle, 0, 0).unwrap();
+    assert_eq!(path_call(&mut cpu, 0x5B00, "DATA.TXT", 0), Err(0x50));
+    assert!(path_call(&mut cpu, 0x5B00, "NEW.TXT", 0).is_ok());
+    assert!(root_path.join("NEW.TXT").exists());
+    assert_eq!(path_call(&mut cpu, 0x3C00, "NODIR\\X.TXT", 0), Err(3));
+
+    // 5Ah completes the caller's path with a fresh name
+    let handle = path_call(&mut cpu, 0x5A00, "SUB\\", 0).unwrap();
+    let addr = (BUFFER as usize) << 4;
+    let path: String = (0..)
+        .map(|i| cpu.bus.read_8(addr + i))
+        .take_while(|&b| b != 0)
+        .map(|b| b as char)
+        .collect();
+    assert_eq!(path.len(), 12);
+    assert!(root_path.join(path.replace('\\', "/")).exists());
+    assert!(dos_call(&mut cpu, 0x6800, handle, 0, 0).is_ok());
+    assert!(dos_call(&mut cpu, 0x6A00, handle, 0, 0).is_ok());
+    assert_eq!(dos_call(&mut cpu, 0x6800, 19, 0, 0), Err(6));
+
+    fs::remove_dir_all(&root_path).unwrap();
+}
+
+#[test]
+fn test_extended_open_actions() {
+    let (mut cpu, root_path) = setup("extended");
+
+    // Open if present, fail otherwise
+    let handle = extended_open(&mut cpu, "DATA.TXT", 0x00, 0x01).unwrap();
+    assert_eq!(cpu.cx, 1);
+    dos_call(&mut cpu, 0x3E00, handle, 0, 0).unwrap();
+    assert_eq!(extended_open(&mut cpu, "NEW.TXT", 0x00, 0x01), Err(2));
+    assert_eq!(extended_open(&mut cpu, "DATA.TXT", 0x02, 0x10), Err(0x50));
+
+    // Create if missing, replace if present
+    extended_open(&mut cpu, "NEW.TXT", 0x02, 0x10).unwrap();
+    assert_eq!(cpu.cx, 2);
+    extended_open(&mut cpu, "DATA.TXT", 0x02, 0x12).unwrap();
+    assert_eq!(cpu.cx, 3);
+    assert_eq!(fs::read(root_path.join("DATA.TXT")).unwrap(), b"");
+
+    // The mode's no-inherit bit is kept
+    let handle = extended_open(&mut cpu, "NEW.TXT", 0x80, 0x01).unwrap();
+    let sft = handles::sft_index(&cpu.bus, PSP, handle).unwrap();
+    assert_eq!(cpu.bus.disk.sft_entry(sft).unwrap().mode, 0x80);
+
+    assert_eq!(extended_open(&mut cpu, "DATA.TXT", 0x00, 0x03), Err(1));
+    assert_eq!(extended_open(&mut cpu, "NODIR\\X.TXT", 0x02, 0x10), Err(3));
+
+    fs::remove_dir_all(&root_path).unwrap();
+}
+
+#[test]
+fn test_file_date_and_time() {
+    let (mut cpu, root_path) = setup("datetime");
+
+    let handle = path_call(&mut cpu, 0x3D02, "DATA.TXT", 0).unwrap();
+    // 1995-06-15 13:45:30
+    let (time, date) = (13 << 11 | 45 << 5 | 15, 15 << 9 | 6 << 5 | 15);
+    assert!(dos_call(&mut cpu, 0x5701, handle, time, date).is_ok());
+    assert!(dos_call(&mut cpu, 0x5700, handle, 0, 0).is_ok());
+    assert_eq!((cpu.cx, cpu.dx), (time, date));
+    dos_call(&mut cpu, 0x3E00, handle, 0, 0).unwrap();
+
+    let modified = fs::metadata(root_path.join("DATA.TXT"))
+        .unwrap()
+        .modified()
+        .unwrap();
+    assert_eq!(disk::to_dos_datetime(modified), (time, date));
+
+    assert_eq!(dos_call(&mut cpu, 0x5700, handle, 0, 0), Err(6));
+    let handle = path_call(&mut cpu, 0x3D00, "DATA.TXT", 0).unwrap();
+    assert_eq!(dos_call(&mut cpu, 0x5701, handle, 0, 0x01FF), Err(0x0D));
+    assert_eq!(dos_call(&mut cpu, 0x5702, handle, 0, 0), Err(1));
+
+    fs::remove_dir_all(&root_path).unwrap();
+}
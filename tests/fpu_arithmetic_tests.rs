@@ -336,3 +336,199 @@ fn test_trigonometry() {
     assert!((cpu.fpu_get(0).get_f64() - 1.0).abs() < 0.0001); // The pushed 1.0
     assert!((cpu.fpu_get(1).get_f64() - 1.0).abs() < 0.0001); // The result tan(pi/4)
 }
+
+#[test]
+fn test_fdiv_bit_exact_and_status() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+    let mut f1 = F80::new(); f1.set_f64(1.0);
+    let mut f3 = F80::new(); f3.set_f64(3.0);
+    cpu.fpu_push(f3);
+    cpu.fpu_push(f1);
+
+    // FDIV ST(0), ST(1): 1/3 rounds up in the last place -> PE and C1
+    testrunners::run_fpu_code(&mut cpu, &[0xD8, 0xF1]);
+    assert_eq!(cpu.fpu_get(0).get(), 0x3FFD_AAAA_AAAA_AAAA_AAAB);
+    let flags = cpu.get_fpu_flags();
+    assert!(flags.contains(FpuFlags::PE));
+    assert!(flags.contains(FpuFlags::C1));
+
+    // PC = 00 (single precision), RC = chop
+    cpu.fpu_control = 0x0C3F;
+    cpu.fpu_set(0, f1);
+    testrunners::run_fpu_code(&mut cpu, &[0xD8, 0xF1]);
+    assert_eq!(cpu.fpu_get(0).get(), 0x3FFD_AAAA_AA00_0000_0000);
+    assert!(!cpu.get_fpu_flags().contains(FpuFlags::C1));
+}
+
+#[test]
+fn test_fdiv_by_zero_sets_ze() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+    let mut f2 = F80::new(); f2.set_f64(-2.0);
+    cpu.fpu_push(F80::new());
+    cpu.fpu_push(f2);
+
+    // FDIV ST(0), ST(1): -2 / 0 = -inf
+    testrunners::run_fpu_code(&mut cpu, &[0xD8, 0xF1]);
+    assert_eq!(cpu.fpu_get(0).get(), F80::infinity(true).get());
+    assert!(cpu.get_fpu_flags().contains(FpuFlags::ZE));
+    assert!(!cpu.get_fpu_flags().contains(FpuFlags::IE));
+}
+
+#[test]
+fn test_fprem_large_exponent_gap() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+    let mut f3 = F80::new(); f3.set_f64(3.0);
+    let mut big = F80::new(); big.set_f64(1e30);
+    cpu.fpu_push(f3);
+    cpu.fpu_push(big);
+
+    // The usual FPREM / FSTSW / SAHF / JP loop, one pass at a time
+    let mut passes = 0;
+    loop {
+        testrunners::run_fpu_code(&mut cpu, &[0xD9, 0xF8]);
+        passes += 1;
+        if !cpu.get_fpu_flags().contains(FpuFlags::C2) {
+            break;
+        }
+    }
+    assert!(passes > 1);
+    assert_eq!(cpu.fpu_get(0).get_f64(), 1.0);
+}
+
+#[test]
+fn test_fst_m32_follows_rounding_control() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+    let mut f1 = F80::new(); f1.set_f64(1.0);
+    let mut f3 = F80::new(); f3.set_f64(3.0);
+    cpu.fpu_push(f3);
+    cpu.fpu_push(f1);
+    testrunners::run_fpu_code(&mut cpu, &[0xD8, 0xF1]); // ST(0) = 1/3
+
+    // FST DWORD [0x300] -> D9 16 00 03
+    testrunners::run_fpu_code(&mut cpu, &[0xD9, 0x16, 0x00, 0x03]);
+    assert_eq!(cpu.bus.read_32(0x300), 0x3EAA_AAAB);
+
+    cpu.fpu_control = 0x0F7F; // RC = chop
+    testrunners::run_fpu_code(&mut cpu, &[0xD9, 0x16, 0x00, 0x03]);
+    assert_eq!(cpu.bus.read_32(0x300), 0x3EAA_AAAA);
+
+    // FLD DWORD of a float denormal flags DE
+    cpu.bus.write_32(0x300, 0x0000_0001);
+    testrunners::run_fpu_code(&mut cpu, &[0xD9, 0x06, 0x00, 0x03]);
+    assert!(cpu.get_fpu_flags().contains(FpuFlags::DE));
+    assert_eq!(cpu.fpu_get(0).get_f64(), f32::from_bits(1) as f64);
+}
+
+#[test]
+fn test_fistp_m64_and_indefinite() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+
+    // FILD QWORD [0x300] / FISTP QWORD [0x308] round-trips all 64 bits
+    cpu.bus.write_64(0x300, 0x7FFF_FFFF_FFFF_FFFF);
+    testrunners::run_fpu_code(&mut cpu, &[0xDF, 0x2E, 0x00, 0x03]);
+    testrunners::run_fpu_code(&mut cpu, &[0xDF, 0x3E, 0x08, 0x03]);
+    assert_eq!(cpu.bus.read_64(0x308), 0x7FFF_FFFF_FFFF_FFFF);
+
+    // FISTP WORD of 40000 does not fit: integer indefinite and IE
+    let mut big = F80::new(); big.set_f64(40000.0);
+    cpu.fpu_push(big);
+    testrunners::run_fpu_code(&mut cpu, &[0xDF, 0x1E, 0x10, 0x03]);
+    assert_eq!(cpu.bus.read_16(0x310), 0x8000);
+    assert!(cpu.get_fpu_flags().contains(FpuFlags::IE));
+}
+
+fn push_bits(cpu: &mut Cpu, bits: u128) {
+    let mut f = F80::new();
+    f.set(bits);
+    cpu.fpu_push(f);
+}
+
+#[test]
+fn test_transcendentals_bit_exact() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+
+    // F2XM1: 2^0.5 - 1 and 2^-0.25 - 1, to the last bit
+    push_bits(&mut cpu, 0x3FFE_8000_0000_0000_0000);
+    testrunners::run_fpu_code(&mut cpu, &[0xD9, 0xF0]);
+    assert_eq!(cpu.fpu_get(0).get(), 0x3FFD_D413_CCCF_E779_9211);
+    assert!(cpu.get_fpu_flags().contains(FpuFlags::PE));
+    push_bits(&mut cpu, 0xBFFD_8000_0000_0000_0000);
+    testrunners::run_fpu_code(&mut cpu, &[0xD9, 0xF0]);
+    assert_eq!(cpu.fpu_get(0).get(), 0xBFFC_A2EC_0CD4_A58A_542F);
+
+    // FYL2X: 1 * log2(10) is FLDL2T; 3 * log2(0.75) is negative
+    push_bits(&mut cpu, 0x3FFF_8000_0000_0000_0000);
+    push_bits(&mut cpu, 0x4002_A000_0000_0000_0000);
+    testrunners::run_fpu_code(&mut cpu, &[0xD9, 0xF1]);
+    assert_eq!(cpu.fpu_get(0).get(), 0x4000_D49A_784B_CD1B_8AFE);
+    push_bits(&mut cpu, 0x4000_C000_0000_0000_0000);
+    push_bits(&mut cpu, 0x3FFE_C000_0000_0000_0000);
+    testrunners::run_fpu_code(&mut cpu, &[0xD9, 0xF1]);
+    assert_eq!(cpu.fpu_get(0).get(), 0xBFFF_9F5F_D8A9_063E_3491);
+
+    // FYL2XP1 keeps full precision where 1 + x would round to 1
+    push_bits(&mut cpu, 0x3FFF_8000_0000_0000_0000);
+    push_bits(&mut cpu, 0x3FB9_8000_0000_0000_0000);
+    testrunners::run_fpu_code(&mut cpu, &[0xD9, 0xF9]);
+    assert_eq!(cpu.fpu_get(0).get(), 0x3FB9_B8AA_3B29_5C17_F0BC);
+
+    // Exact results stay exact
+    cpu.set_fpu_flag(FpuFlags::PE, false);
+    push_bits(&mut cpu, 0x4000_C000_0000_0000_0000); // 3
+    push_bits(&mut cpu, 0x3FF8_8000_0000_0000_0000); // 1/128
+    testrunners::run_fpu_code(&mut cpu, &[0xD9, 0xF1]);
+    assert_eq!(cpu.fpu_get(0).get(), 0xC003_A800_0000_0000_0000); // -21
+    assert!(!cpu.get_fpu_flags().contains(FpuFlags::PE));
+}
+
+#[test]
+fn test_transcendental_special_operands() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+
+    // log2(0) divides by zero
+    push_bits(&mut cpu, 0x3FFF_8000_0000_0000_0000);
+    cpu.fpu_push(F80::new());
+    testrunners::run_fpu_code(&mut cpu, &[0xD9, 0xF1]);
+    assert_eq!(cpu.fpu_get(0).get(), F80::infinity(true).get());
+    assert!(cpu.get_fpu_flags().contains(FpuFlags::ZE));
+
+    // log2 of a negative number is invalid
+    push_bits(&mut cpu, 0x3FFF_8000_0000_0000_0000);
+    push_bits(&mut cpu, 0xBFFF_8000_0000_0000_0000);
+    testrunners::run_fpu_code(&mut cpu, &[0xD9, 0xF1]);
+    assert_eq!(cpu.fpu_get(0).get(), F80::indefinite().get());
+    assert!(cpu.get_fpu_flags().contains(FpuFlags::IE));
+
+    // 2^-inf - 1 = -1, and -0 stays -0
+    cpu.fpu_push(F80::infinity(true));
+    testrunners::run_fpu_code(&mut cpu, &[0xD9, 0xF0]);
+    assert_eq!(cpu.fpu_get(0).get(), 0xBFFF_8000_0000_0000_0000);
+    cpu.fpu_push(F80::zero(true));
+    testrunners::run_fpu_code(&mut cpu, &[0xD9, 0xF0]);
+    assert_eq!(cpu.fpu_get(0).get(), F80::zero(true).get());
+}
+
+#[test]
+fn test_fxtract_exact() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+
+    // The smallest denormal: 2^-16445, significand 1.0
+    push_bits(&mut cpu, 0x0000_0000_0000_0000_0001);
+    testrunners::run_fpu_code(&mut cpu, &[0xD9, 0xF4]);
+    assert_eq!(cpu.fpu_get(0).get(), 0x3FFF_8000_0000_0000_0000);
+    assert_eq!(cpu.fpu_get(1).get_f64(), -16445.0);
+    assert!(cpu.get_fpu_flags().contains(FpuFlags::DE));
+
+    // All 64 significand bits survive
+    push_bits(&mut cpu, 0xC123_8000_0000_0000_0001);
+    testrunners::run_fpu_code(&mut cpu, &[0xD9, 0xF4]);
+    assert_eq!(cpu.fpu_get(0).get(), 0xBFFF_8000_0000_0000_0001);
+    assert_eq!(cpu.fpu_get(1).get_f64(), (0x4123 - 16383) as f64);
+
+    // Zero: exponent -inf, ZE
+    cpu.fpu_push(F80::zero(true));
+    testrunners::run_fpu_code(&mut cpu, &[0xD9, 0xF4]);
+    assert_eq!(cpu.fpu_get(0).get(), F80::zero(true).get());
+    assert_eq!(cpu.fpu_get(1).get(), F80::infinity(true).get());
+    assert!(cpu.get_fpu_flags().contains(FpuFlags::ZE));
+}
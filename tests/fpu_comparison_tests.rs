@@ -1,4 +1,4 @@
-use rust_dos::cpu::{Cpu, FpuFlags, FPU_TAG_EMPTY};
+use rust_dos::cpu::{Cpu, CpuFlags, CpuModel, FpuFlags, FpuModel, FPU_TAG_EMPTY};
 use rust_dos::f80::F80;
 
 mod testrunners;
@@ -168,4 +168,170 @@ fn test_nan_unordered_compare() {
     assert!(cpu.get_fpu_flag(FpuFlags::C0));
     assert!(cpu.get_fpu_flag(FpuFlags::C2));
     assert!(cpu.get_fpu_flag(FpuFlags::C3));
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_fucom_sets_condition_codes() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+
+    push_val(&mut cpu, 10.0); // ST(1)
+    push_val(&mut cpu, 8.0);  // ST(0)
+
+    // DD E1: FUCOM ST(1) -> 8.0 < 10.0
+    testrunners::run_fpu_code(&mut cpu, &[0xDD, 0xE1]);
+
+    let flags = cpu.get_fpu_flags();
+    assert!(flags.contains(FpuFlags::C0), "ST(0) < ST(1) should set C0");
+    assert!(!flags.contains(FpuFlags::C3));
+    assert!(!flags.contains(FpuFlags::C2));
+}
+
+#[test]
+fn test_fucomp_unordered_and_pop() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+    let initial_top = cpu.fpu_top;
+
+    push_val(&mut cpu, f64::NAN); // ST(1)
+    push_val(&mut cpu, 1.0);      // ST(0)
+
+    // DD E9: FUCOMP ST(1)
+    testrunners::run_fpu_code(&mut cpu, &[0xDD, 0xE9]);
+
+    let flags = cpu.get_fpu_flags();
+    assert!(flags.contains(FpuFlags::C0 | FpuFlags::C2 | FpuFlags::C3), "NaN must compare unordered");
+    assert_eq!(cpu.fpu_top, (initial_top + 7) & 7, "FUCOMP should pop once");
+}
+
+#[test]
+fn test_fucompp_equal_and_double_pop() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+    let initial_top = cpu.fpu_top;
+
+    push_val(&mut cpu, 3.5);
+    push_val(&mut cpu, 3.5);
+
+    // DA E9: FUCOMPP
+    testrunners::run_fpu_code(&mut cpu, &[0xDA, 0xE9]);
+
+    assert!(cpu.get_fpu_flags().contains(FpuFlags::C3), "Equal should set C3");
+    assert_eq!(cpu.fpu_top, initial_top);
+    assert_eq!(cpu.fpu_tags[initial_top], FPU_TAG_EMPTY);
+}
+
+#[test]
+fn test_fucom_is_undefined_on_287() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+    cpu.model = CpuModel::I80286;
+    cpu.fpu_model = FpuModel::I80287;
+    cpu.cs = 0x1000;
+    cpu.ip = 0x0100;
+    cpu.ss = 0x2000;
+    cpu.sp = 0x0100;
+
+    // INT 6 handler at 0800:0010
+    cpu.bus.write_16(6 * 4, 0x0010);
+    cpu.bus.write_16(6 * 4 + 2, 0x0800);
+
+    push_val(&mut cpu, 1.0);
+    push_val(&mut cpu, 2.0);
+    let top = cpu.fpu_top;
+
+    // DA E9: FUCOMPP
+    testrunners::run_fpu_code(&mut cpu, &[0xDA, 0xE9]);
+
+    assert_eq!((cpu.cs, cpu.ip), (0x0800, 0x0010), "Expected #UD through INT 6");
+    // Fault: the return address points back at the instruction itself
+    let return_ip = cpu.bus.read_16(cpu.get_physical_addr(cpu.ss, cpu.sp));
+    assert_eq!(return_ip, 0x0100);
+    assert_eq!(cpu.fpu_top, top, "Stack must be untouched");
+}
+
+#[test]
+fn test_fucom_is_ignored_on_8086() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+    cpu.model = CpuModel::I8086;
+    cpu.fpu_model = FpuModel::I8087;
+    cpu.bus.write_16(6 * 4, 0x0010);
+    cpu.bus.write_16(6 * 4 + 2, 0x0800);
+
+    push_val(&mut cpu, 1.0);
+    let top = cpu.fpu_top;
+    let start_ip = cpu.ip;
+
+    // DD E1: FUCOM ST(1) on an 8087 is skipped, the 8086 has no #UD
+    testrunners::run_fpu_code(&mut cpu, &[0xDD, 0xE1]);
+
+    assert_ne!(cpu.cs, 0x0800);
+    assert_eq!(cpu.ip, start_ip.wrapping_add(2));
+    assert_eq!(cpu.fpu_top, top);
+}
+
+#[test]
+fn test_fcomi_requires_p6() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+    push_val(&mut cpu, 2.0); // ST(1)
+    push_val(&mut cpu, 1.0); // ST(0)
+
+    // DB F1: FCOMI ST, ST(1) doesn't exist on a 387
+    cpu.set_cpu_flag(CpuFlags::CF, false);
+    testrunners::run_fpu_code(&mut cpu, &[0xDB, 0xF1]);
+    assert!(!cpu.get_cpu_flag(CpuFlags::CF));
+
+    cpu.fpu_model = FpuModel::PentiumPro;
+    testrunners::run_fpu_code(&mut cpu, &[0xDB, 0xF1]);
+    assert!(cpu.get_cpu_flag(CpuFlags::CF), "1.0 < 2.0 should set CF");
+    assert!(!cpu.get_cpu_flag(CpuFlags::ZF));
+}
+
+fn push_bits(cpu: &mut Cpu, bits: u128) {
+    let mut f = F80::new();
+    f.set(bits);
+    cpu.fpu_push(f);
+}
+
+#[test]
+fn test_fcom_distinguishes_last_mantissa_bit() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+
+    // 1.0 + 1 ulp rounds to 1.0 as a double, but not as an extended
+    push_bits(&mut cpu, 0x3FFF_8000_0000_0000_0001); // ST(1)
+    push_bits(&mut cpu, 0x3FFF_8000_0000_0000_0000); // ST(0)
+
+    // D8 D1: FCOM ST(1)
+    testrunners::run_fpu_code(&mut cpu, &[0xD8, 0xD1]);
+
+    let flags = cpu.get_fpu_flags();
+    assert!(flags.contains(FpuFlags::C0), "1.0 < 1.0 + 1ulp should set C0");
+    assert!(!flags.contains(FpuFlags::C3));
+    assert!(!flags.contains(FpuFlags::C2));
+}
+
+#[test]
+fn test_fcom_qnan_is_invalid_but_fucom_is_not() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+
+    push_bits(&mut cpu, 0x7FFF_C000_0000_0000_0000); // ST(1) = QNaN
+    push_val(&mut cpu, 1.0); // ST(0)
+
+    // DD E1: FUCOM ST(1) is quiet for QNaN
+    testrunners::run_fpu_code(&mut cpu, &[0xDD, 0xE1]);
+    assert!(cpu.get_fpu_flags().contains(FpuFlags::C0 | FpuFlags::C2 | FpuFlags::C3));
+    assert!(!cpu.get_fpu_flag(FpuFlags::IE), "FUCOM must not signal on a QNaN");
+
+    // D8 D1: FCOM ST(1) signals on any NaN
+    testrunners::run_fpu_code(&mut cpu, &[0xD8, 0xD1]);
+    assert!(cpu.get_fpu_flags().contains(FpuFlags::C0 | FpuFlags::C2 | FpuFlags::C3));
+    assert!(cpu.get_fpu_flag(FpuFlags::IE), "FCOM must signal on a QNaN");
+}
+
+#[test]
+fn test_fucom_snan_is_invalid() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+
+    push_bits(&mut cpu, 0x7FFF_A000_0000_0000_0000); // ST(1) = SNaN
+    push_val(&mut cpu, 1.0);
+
+    testrunners::run_fpu_code(&mut cpu, &[0xDD, 0xE1]);
+    assert!(cpu.get_fpu_flags().contains(FpuFlags::C0 | FpuFlags::C2 | FpuFlags::C3));
+    assert!(cpu.get_fpu_flag(FpuFlags::IE), "FUCOM must signal on an SNaN");
+}
@@ -1,4 +1,5 @@
-use rust_dos::cpu::{Cpu, FpuFlags, FPU_TAG_EMPTY, FPU_TAG_VALID};
+use rust_dos::cpu::{Cpu, CpuModel, FpuFlags, FpuModel, FPU_TAG_EMPTY, FPU_TAG_VALID};
+use rust_dos::f80::F80;
 
 mod testrunners;
 
@@ -206,4 +207,180 @@ fn test_fsave_frstor_full_cycle() {
     let restored_val = cpu.fpu_stack[3].get_f64(); // CORRECTION: Use fpu_stack
     assert!((restored_val - 123.456).abs() < 0.001, "Register value lost during Save/Restore");
     assert_eq!(cpu.fpu_tags[3], FPU_TAG_VALID, "Tag Word not restored correctly");
-}
\ No newline at end of file
+}
+
+fn push_val(cpu: &mut Cpu, val: f64) {
+    let mut f = F80::new();
+    f.set_f64(val);
+    cpu.fpu_push(f);
+}
+
+#[test]
+fn test_ffreep_frees_and_pops() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+    push_val(&mut cpu, 1.0);
+    push_val(&mut cpu, 2.0);
+    let top = cpu.fpu_top;
+    let st1 = cpu.fpu_get_phys_index(1);
+
+    // DF C1: FFREEP ST(1)
+    testrunners::run_cpu_code(&mut cpu, &[0xDF, 0xC1]);
+
+    assert_eq!(cpu.fpu_tags[st1], FPU_TAG_EMPTY, "ST(1) should be freed");
+    assert_eq!(cpu.fpu_tags[top], FPU_TAG_VALID, "Old ST(0) tag is left alone");
+    assert_eq!(cpu.fpu_top, (top + 1) & 7);
+}
+
+#[test]
+fn test_feni_fdisi_only_affect_8087() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+    cpu.fpu_model = FpuModel::I8087;
+    cpu.fpu_control = 0x03FF;
+
+    // DB E0: FNENI clears the interrupt enable mask (bit 7)
+    testrunners::run_cpu_code(&mut cpu, &[0xDB, 0xE0]);
+    assert_eq!(cpu.fpu_control, 0x037F);
+
+    // DB E1: FNDISI sets it again
+    testrunners::run_cpu_code(&mut cpu, &[0xDB, 0xE1]);
+    assert_eq!(cpu.fpu_control, 0x03FF);
+
+    // The 287 ignores both
+    cpu.fpu_model = FpuModel::I80287;
+    testrunners::run_cpu_code(&mut cpu, &[0xDB, 0xE0]);
+    assert_eq!(cpu.fpu_control, 0x03FF);
+}
+
+#[test]
+fn test_fsetpm_is_a_no_op() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+    cpu.fpu_model = FpuModel::I80287;
+    push_val(&mut cpu, 1.0);
+    let top = cpu.fpu_top;
+    let cw = cpu.fpu_control;
+
+    // DB E4: FNSETPM
+    testrunners::run_cpu_code(&mut cpu, &[0xDB, 0xE4]);
+    assert_eq!(cpu.fpu_top, top);
+    assert_eq!(cpu.fpu_control, cw);
+}
+
+#[test]
+fn test_fnstsw_ax_not_on_8087() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+    cpu.model = CpuModel::I8086;
+    cpu.fpu_model = FpuModel::I8087;
+    cpu.set_fpu_flags(FpuFlags::C3);
+    cpu.ax = 0x1234;
+
+    // DF E0: FNSTSW AX is a 287 addition
+    testrunners::run_cpu_code(&mut cpu, &[0xDF, 0xE0]);
+    assert_eq!(cpu.ax, 0x1234);
+
+    // The memory form works on the 8087: DD 3E 00 10 (FNSTSW [1000])
+    testrunners::run_cpu_code(&mut cpu, &[0xDD, 0x3E, 0x00, 0x10]);
+    assert_eq!(cpu.bus.read_16(0x1000), FpuFlags::C3.bits());
+}
+
+#[test]
+fn test_fldcw_addressing_forms() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+    cpu.ds = 0;
+
+    // D9 2E 00 10: FLDCW [1000]
+    cpu.bus.write_16(0x1000, 0x0C7F);
+    testrunners::run_cpu_code(&mut cpu, &[0xD9, 0x2E, 0x00, 0x10]);
+    assert_eq!(cpu.fpu_control, 0x0C7F);
+
+    // D9 2F: FLDCW [BX]
+    cpu.bx = 0x1002;
+    cpu.bus.write_16(0x1002, 0x047F);
+    testrunners::run_cpu_code(&mut cpu, &[0xD9, 0x2F]);
+    assert_eq!(cpu.fpu_control, 0x047F);
+
+    // D9 68 04: FLDCW [BX+SI+4]
+    cpu.si = 0x0002;
+    cpu.bus.write_16(0x1008, 0x087F);
+    testrunners::run_cpu_code(&mut cpu, &[0xD9, 0x68, 0x04]);
+    assert_eq!(cpu.fpu_control, 0x087F);
+
+    // D9 3E 10 10: FNSTCW [1010] reads it back
+    testrunners::run_cpu_code(&mut cpu, &[0xD9, 0x3E, 0x10, 0x10]);
+    assert_eq!(cpu.bus.read_16(0x1010), 0x087F);
+}
+
+#[test]
+fn test_fstenv_fldenv_roundtrip() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+    push_val(&mut cpu, 0.0);
+    push_val(&mut cpu, 2.0);
+    cpu.fpu_control = 0x0360;
+    cpu.set_fpu_flags(FpuFlags::C1 | FpuFlags::PE);
+    let top = cpu.fpu_top;
+
+    // D9 36 00 10: FNSTENV [1000]
+    testrunners::run_cpu_code(&mut cpu, &[0xD9, 0x36, 0x00, 0x10]);
+    assert_eq!(cpu.bus.read_16(0x1000), 0x0360);
+    assert_eq!((cpu.bus.read_16(0x1002) >> 11) & 7, top as u16);
+    // ST(0)=2.0 is valid (00), ST(1)=0.0 is zero (01), the rest empty (11)
+    let tw = cpu.bus.read_16(0x1004);
+    assert_eq!((tw >> (top * 2)) & 3, 0b00);
+    assert_eq!((tw >> (((top + 1) & 7) * 2)) & 3, 0b01);
+    assert_eq!((tw >> (((top + 2) & 7) * 2)) & 3, 0b11);
+    assert_eq!(cpu.fpu_control, 0x037F, "FSTENV masks all exceptions");
+
+    // Trash the state, then D9 26 00 10: FLDENV [1000]
+    testrunners::run_cpu_code(&mut cpu, &[0xDB, 0xE3]);
+    testrunners::run_cpu_code(&mut cpu, &[0xD9, 0x26, 0x00, 0x10]);
+    assert_eq!(cpu.fpu_control, 0x0360);
+    assert_eq!(cpu.fpu_top, top);
+    assert!(cpu.get_fpu_flags().contains(FpuFlags::C1 | FpuFlags::PE));
+    assert_eq!(cpu.fpu_tags[top], FPU_TAG_VALID);
+    assert_eq!(cpu.fpu_tags[(top + 2) & 7], FPU_TAG_EMPTY);
+}
+
+#[test]
+fn test_fxsave_fxrstor() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+    cpu.fpu_model = FpuModel::PentiumPro;
+    push_val(&mut cpu, 1.5);
+    push_val(&mut cpu, -4.0);
+    let top = cpu.fpu_top;
+
+    // 0F AE 06 00 10: FXSAVE [1000]
+    testrunners::run_cpu_code(&mut cpu, &[0x0F, 0xAE, 0x06, 0x00, 0x10]);
+    assert_eq!(cpu.bus.read_16(0x1000), 0x037F);
+    let tag = cpu.bus.read_8(0x1004);
+    assert_eq!(tag, (1 << top) | (1 << ((top + 1) & 7)));
+    assert_eq!(cpu.bus.read_32(0x1018), 0x1F80);
+    // ST(0) lives at offset 20h
+    let mut st0 = [0u8; 10];
+    for (i, b) in st0.iter_mut().enumerate() {
+        *b = cpu.bus.read_8(0x1020 + i);
+    }
+    let mut f = F80::new();
+    f.set_bytes(&st0);
+    assert_eq!(f.get_f64(), -4.0);
+    // FXSAVE leaves the FPU alone
+    assert_eq!(cpu.fpu_top, top);
+
+    // FNINIT, then 0F AE 0E 00 10: FXRSTOR [1000]
+    testrunners::run_cpu_code(&mut cpu, &[0xDB, 0xE3]);
+    testrunners::run_cpu_code(&mut cpu, &[0x0F, 0xAE, 0x0E, 0x00, 0x10]);
+    assert_eq!(cpu.fpu_top, top);
+    assert_eq!(cpu.fpu_get(0).get_f64(), -4.0);
+    assert_eq!(cpu.fpu_get(1).get_f64(), 1.5);
+    assert_eq!(cpu.fpu_tags[(top + 2) & 7], FPU_TAG_EMPTY);
+}
+
+#[test]
+fn test_no_fpu_ignores_esc_opcodes() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+    cpu.fpu_model = FpuModel::None;
+    cpu.bus.write_16(0x1000, 0x5A5A);
+
+    // The classic detection sequence: FNINIT / FNSTSW [1000]
+    // With nothing on the bus the status word is never written
+    testrunners::run_cpu_code(&mut cpu, &[0xDB, 0xE3, 0xDD, 0x3E, 0x00, 0x10]);
+    assert_eq!(cpu.bus.read_16(0x1000), 0x5A5A);
+}
@@ -1,4 +1,4 @@
-use rust_dos::cpu::{Cpu, FPU_TAG_EMPTY};
+use rust_dos::cpu::{Cpu, CpuFlags, FpuModel, FPU_TAG_EMPTY};
 use rust_dos::f80::F80;
 mod testrunners;
 use testrunners::run_cpu_code;
@@ -191,4 +191,106 @@ fn test_fbstp_bcd_store() {
     
     assert_eq!(b0, 0x23);
     assert_eq!(b1, 0x01);
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_fbld_packed_bcd() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+    let addr = 0x1000;
+
+    // -1234567 as packed BCD, sign in bit 7 of the last byte
+    let bcd = [0x67, 0x45, 0x23, 0x01, 0, 0, 0, 0, 0, 0x80];
+    for (i, &b) in bcd.iter().enumerate() {
+        cpu.bus.write_8(addr + i, b);
+    }
+
+    // FBLD TBYTE PTR [1000] (DF 26 00 10)
+    run_cpu_code(&mut cpu, &[0xDF, 0x26, 0x00, 0x10]);
+    assert_top_f64(&cpu, -1234567.0);
+
+    // FBSTP TBYTE PTR [1010] (DF 36 10 10) writes the same bytes back
+    run_cpu_code(&mut cpu, &[0xDF, 0x36, 0x10, 0x10]);
+    for (i, &b) in bcd.iter().enumerate() {
+        assert_eq!(cpu.bus.read_8(addr + 0x10 + i), b, "BCD byte {}", i);
+    }
+}
+
+#[test]
+fn test_fisttp_truncates_regardless_of_rounding() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+    cpu.fpu_model = FpuModel::PentiumPro;
+    let mut f = F80::new();
+    f.set_f64(-2.75);
+    cpu.fpu_push(f);
+
+    // Round to nearest would give -3
+    cpu.fpu_control &= !0x0C00;
+
+    // FISTTP WORD PTR [1000] (DF 0E 00 10)
+    run_cpu_code(&mut cpu, &[0xDF, 0x0E, 0x00, 0x10]);
+    assert_eq!(cpu.bus.read_16(0x1000) as i16, -2);
+    assert_eq!(cpu.fpu_tags[cpu.fpu_get_phys_index(7)], FPU_TAG_EMPTY);
+}
+
+#[test]
+fn test_fstp_alias_d9_d8() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+    let mut f = F80::new();
+    f.set_f64(1.0);
+    cpu.fpu_push(f);
+    f.set_f64(5.0);
+    cpu.fpu_push(f);
+
+    // D9 D9: undocumented FSTP ST(1)
+    run_cpu_code(&mut cpu, &[0xD9, 0xD9]);
+    assert_top_f64(&cpu, 5.0);
+    assert_eq!(cpu.fpu_tags[cpu.fpu_get_phys_index(1)], FPU_TAG_EMPTY);
+}
+
+#[test]
+fn test_fcmov_conditions() {
+    // (opcode, modrm, CF, ZF, PF, should move)
+    let cases: [(u8, u8, bool, bool, bool, bool); 8] = [
+        (0xDA, 0xC1, true, false, false, true),   // FCMOVB
+        (0xDA, 0xC9, false, true, false, true),   // FCMOVE
+        (0xDA, 0xD1, false, false, false, false), // FCMOVBE
+        (0xDA, 0xD9, false, false, true, true),   // FCMOVU
+        (0xDB, 0xC1, true, false, false, false),  // FCMOVNB
+        (0xDB, 0xC9, false, false, false, true),  // FCMOVNE
+        (0xDB, 0xD1, false, true, false, false),  // FCMOVNBE
+        (0xDB, 0xD9, false, false, false, true),  // FCMOVNU
+    ];
+
+    for (op, modrm, cf, zf, pf, moves) in cases {
+        let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+        cpu.fpu_model = FpuModel::PentiumPro;
+        let mut f = F80::new();
+        f.set_f64(7.0);
+        cpu.fpu_push(f); // ST(1)
+        f.set_f64(1.0);
+        cpu.fpu_push(f); // ST(0)
+
+        cpu.set_cpu_flag(CpuFlags::CF, cf);
+        cpu.set_cpu_flag(CpuFlags::ZF, zf);
+        cpu.set_cpu_flag(CpuFlags::PF, pf);
+        run_cpu_code(&mut cpu, &[op, modrm]);
+
+        let expected = if moves { 7.0 } else { 1.0 };
+        assert_eq!(cpu.fpu_get(0).get_f64(), expected, "{:02X} {:02X}", op, modrm);
+    }
+}
+
+#[test]
+fn test_fcmov_is_undefined_on_387() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+    let mut f = F80::new();
+    f.set_f64(7.0);
+    cpu.fpu_push(f);
+    f.set_f64(1.0);
+    cpu.fpu_push(f);
+
+    // DA C9: FCMOVE ST, ST(1) with ZF=1 -- no handler installed for INT 6, so it's skipped
+    cpu.set_cpu_flag(CpuFlags::ZF, true);
+    run_cpu_code(&mut cpu, &[0xDA, 0xC9]);
+    assert_top_f64(&cpu, 1.0);
+}
@@ -0,0 +1,84 @@
+use rust_dos::cpu::{Cpu, CpuState, FpuModel};
+
+fn load(cpu: &mut Cpu, code: &[u8]) {
+    cpu.load_shell();
+    cpu.cs = 0x2000;
+    cpu.ip = 0x0000;
+    cpu.ss = 0x1000;
+    cpu.sp = 0xFFFE;
+    for (i, &byte) in code.iter().enumerate() {
+        cpu.bus.write_8(0x20000 + i, byte);
+    }
+}
+
+fn run(cpu: &mut Cpu) {
+    for _ in 0..100 {
+        if cpu.state == CpuState::Halted {
+            break;
+        }
+        cpu.step();
+    }
+}
+
+#[test]
+fn test_emulator_interrupts_without_fpu() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+    cpu.fpu_model = FpuModel::None;
+
+    let code = [
+        0xCD, 0x37, 0xE3, // FNINIT
+        0xCD, 0x35, 0xE8, // FLD1
+        0xCD, 0x34, 0x06, 0x00, 0x03, // FADD DWORD [0300]
+        0xCD, 0x3C, 0x19, 0x16, 0x00, 0x04, // FST DWORD DS:[0400]
+        0xCD, 0x3C, 0xD9, 0x1E, 0x00, 0x04, // FSTP DWORD ES:[0400]
+        0xCD, 0x3D, // FWAIT
+        0xF4, // HLT
+    ];
+    load(&mut cpu, &code);
+    cpu.ds = 0x3000;
+    cpu.es = 0x4000;
+    cpu.bus.write_32(0x30300, 2.5f32.to_bits());
+    run(&mut cpu);
+
+    assert_eq!(cpu.state, CpuState::Halted);
+    assert_eq!(cpu.cs, 0x2000);
+    assert_eq!(cpu.ip as usize, code.len());
+    assert_eq!(f32::from_bits(cpu.bus.read_32(0x30400)), 3.5);
+    assert_eq!(f32::from_bits(cpu.bus.read_32(0x40400)), 3.5);
+    assert_eq!(cpu.sp, 0xFFFE, "Every INT returned");
+}
+
+#[test]
+fn test_emulator_segment_override_encoding() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+
+    // INT 3Ch: bits 7-6 = 01 selects SS, 10 selects CS
+    let code = [
+        0xCD, 0x3C, 0x59, 0x06, 0x00, 0x01, // FLD DWORD SS:[0100]
+        0xCD, 0x3C, 0x99, 0x06, 0x40, 0x00, // FLD DWORD CS:[0040]
+        0xCD, 0x3A, 0xC1, // FADDP ST(1), ST(0)
+        0xCD, 0x3B, 0x1E, 0x00, 0x02, // FISTP WORD [0200]
+        0xF4,
+    ];
+    load(&mut cpu, &code);
+    cpu.ds = 0x4000;
+    cpu.bus.write_32(0x10100, 10.0f32.to_bits());
+    cpu.bus.write_32(0x20040, 32.0f32.to_bits());
+    run(&mut cpu);
+
+    assert_eq!(cpu.state, CpuState::Halted);
+    assert_eq!(cpu.bus.read_16(0x40200), 42);
+    assert_eq!(cpu.fpu_top, 0);
+}
+
+#[test]
+fn test_esc_opcodes_ignored_without_fpu() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+    cpu.fpu_model = FpuModel::None;
+
+    // The same FLD1 as a real ESC opcode has nobody to answer it
+    load(&mut cpu, &[0x9B, 0xD9, 0xE8, 0xF4]);
+    run(&mut cpu);
+    assert_eq!(cpu.state, CpuState::Halted);
+    assert_eq!(cpu.fpu_top, 0);
+}
@@ -0,0 +1,191 @@
+use rust_dos::cpu::{Cpu, CpuFlags, FpuErrorLine, FpuFlags, FpuModel};
+
+mod testrunners;
+
+// FLD1 / FLDZ / FDIVP: 1 / 0 raises ZE
+const DIVIDE_BY_ZERO: [u8; 6] = [0xD9, 0xE8, 0xD9, 0xEE, 0xDE, 0xF9];
+
+fn setup(line: FpuErrorLine, vector: u8) -> Cpu {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+    cpu.fpu_error_line = line;
+    cpu.cs = 0;
+    cpu.ip = 0x100;
+    cpu.ss = 0;
+    cpu.sp = 0x8000;
+    cpu.set_cpu_flag(CpuFlags::IF, true);
+
+    // Error handler at 2000:0000
+    cpu.bus.write_16(vector as usize * 4, 0x0000);
+    cpu.bus.write_16(vector as usize * 4 + 2, 0x2000);
+
+    // Unmask zero divide
+    cpu.fpu_control = 0x037B;
+    cpu
+}
+
+#[test]
+fn test_unmasked_exception_waits_for_fwait() {
+    let mut cpu = setup(FpuErrorLine::Nmi, 0x02);
+    cpu.fpu_model = FpuModel::I8087;
+
+    testrunners::run_cpu_code(&mut cpu, &DIVIDE_BY_ZERO);
+    let flags = cpu.get_fpu_flags();
+    assert!(flags.contains(FpuFlags::ZE));
+    assert!(flags.contains(FpuFlags::ES));
+    assert_eq!(cpu.cs, 0, "The faulting instruction itself completes");
+
+    // 9B: FWAIT reports it through INT 02h
+    let fwait_ip = cpu.ip;
+    testrunners::run_cpu_code(&mut cpu, &[0x9B]);
+    assert_eq!(cpu.cs, 0x2000);
+    assert_eq!(cpu.ip, 0x0000);
+    assert_eq!(cpu.bus.read_16(cpu.sp as usize), fwait_ip, "Return address is the FWAIT");
+}
+
+#[test]
+fn test_next_fpu_instruction_reports_error_once() {
+    let mut cpu = setup(FpuErrorLine::Nmi, 0x02);
+
+    testrunners::run_cpu_code(&mut cpu, &DIVIDE_BY_ZERO);
+    let fld_ip = cpu.ip;
+    // D9 E8: FLD1 is held back and the handler runs first
+    testrunners::run_cpu_code(&mut cpu, &[0xD9, 0xE8]);
+    assert_eq!(cpu.cs, 0x2000);
+    assert_eq!(cpu.bus.read_16(cpu.sp as usize), fld_ip);
+
+    // Handler returns without clearing the exception: the retry goes through
+    cpu.ip = cpu.pop();
+    cpu.cs = cpu.pop();
+    cpu.pop();
+    let top = cpu.fpu_top;
+    testrunners::run_cpu_code(&mut cpu, &[0xD9, 0xE8]);
+    assert_eq!(cpu.cs, 0);
+    assert_ne!(cpu.fpu_top, top, "FLD1 executed");
+
+    // FNCLEX re-arms reporting
+    testrunners::run_cpu_code(&mut cpu, &[0xDB, 0xE2]);
+    assert!(!cpu.get_fpu_flags().contains(FpuFlags::ES));
+    assert!(!cpu.fpu_error_latched);
+}
+
+#[test]
+fn test_unmasked_exception_leaves_destination() {
+    let mut cpu = setup(FpuErrorLine::Nmi, 0x02);
+    let top = cpu.fpu_top;
+
+    // FDIVP faults: no store and no pop, the handler sees 1 and 0
+    testrunners::run_cpu_code(&mut cpu, &DIVIDE_BY_ZERO);
+    assert_eq!(cpu.fpu_top, top.wrapping_sub(2) & 7);
+    assert!(cpu.fpu_get(0).is_zero());
+    assert_eq!(cpu.fpu_get(1).get_f64(), 1.0);
+
+    // DC F9: FDIV ST(1), ST(0) faults the same way.
+    // The first error was already reported, so nothing redirects the CPU
+    cpu.fpu_error_latched = true;
+    testrunners::run_cpu_code(&mut cpu, &[0xDC, 0xF9]);
+    assert_eq!(cpu.fpu_get(1).get_f64(), 1.0);
+
+    // Masked, the quotient is stored as infinity
+    cpu.fpu_control = 0x037F;
+    testrunners::run_cpu_code(&mut cpu, &[0xDC, 0xF9]);
+    assert!(cpu.fpu_get(1).is_infinite());
+}
+
+#[test]
+fn test_no_wait_forms_skip_pending_error() {
+    let mut cpu = setup(FpuErrorLine::Nmi, 0x02);
+    testrunners::run_cpu_code(&mut cpu, &DIVIDE_BY_ZERO);
+
+    // DF E0: FNSTSW AX, the usual way to look at the error without trapping
+    testrunners::run_cpu_code(&mut cpu, &[0xDF, 0xE0]);
+    assert_eq!(cpu.cs, 0);
+    assert_eq!(cpu.ax & 0x0084, 0x0084, "ES and ZE visible in AX");
+}
+
+#[test]
+fn test_masked_exception_is_silent() {
+    let mut cpu = setup(FpuErrorLine::Nmi, 0x02);
+    cpu.fpu_control = 0x037F;
+
+    testrunners::run_cpu_code(&mut cpu, &DIVIDE_BY_ZERO);
+    testrunners::run_cpu_code(&mut cpu, &[0x9B]);
+    assert!(cpu.get_fpu_flags().contains(FpuFlags::ZE));
+    assert!(!cpu.get_fpu_flags().contains(FpuFlags::ES));
+    assert_eq!(cpu.cs, 0);
+}
+
+#[test]
+fn test_8087_interrupt_mask() {
+    let mut cpu = setup(FpuErrorLine::Nmi, 0x02);
+    cpu.fpu_model = FpuModel::I8087;
+
+    // DB E1: FDISI sets IEM, the request is held off
+    testrunners::run_cpu_code(&mut cpu, &[0xDB, 0xE1]);
+    testrunners::run_cpu_code(&mut cpu, &DIVIDE_BY_ZERO);
+    testrunners::run_cpu_code(&mut cpu, &[0x9B]);
+    assert_eq!(cpu.cs, 0);
+
+    // DB E0: FENI lets it through
+    testrunners::run_cpu_code(&mut cpu, &[0xDB, 0xE0]);
+    testrunners::run_cpu_code(&mut cpu, &[0x9B]);
+    assert_eq!(cpu.cs, 0x2000);
+}
+
+#[test]
+fn test_irq13_honours_interrupt_flag() {
+    let mut cpu = setup(FpuErrorLine::Irq13, 0x75);
+    // F4: HLT in the handler
+    cpu.bus.write_8(0x20000, 0xF4);
+
+    cpu.set_cpu_flag(CpuFlags::IF, false);
+    testrunners::run_cpu_code(&mut cpu, &DIVIDE_BY_ZERO);
+    let fwait_ip = cpu.ip;
+    cpu.bus.write_8(fwait_ip as usize, 0x9B);
+    cpu.step();
+    assert_eq!(cpu.ip, fwait_ip, "FWAIT held back while IRQ13 is raised");
+    assert_ne!(cpu.bus.pic().pending & (1 << 13), 0);
+
+    cpu.step();
+    assert_eq!(cpu.cs, 0, "IRQ13 stays pending while IF is clear");
+    assert_eq!(cpu.ip, fwait_ip + 1);
+
+    cpu.set_cpu_flag(CpuFlags::IF, true);
+    cpu.step();
+    assert_eq!(cpu.cs, 0x2000);
+    assert!(!cpu.get_cpu_flag(CpuFlags::IF));
+}
+
+#[test]
+fn test_irq13_honours_slave_mask() {
+    let mut cpu = setup(FpuErrorLine::Irq13, 0x75);
+    cpu.bus.write_8(0x20000, 0xF4);
+    // Bit 5 of the slave mask is IRQ13
+    cpu.bus.io_write(0xA1, 0x20);
+
+    testrunners::run_cpu_code(&mut cpu, &DIVIDE_BY_ZERO);
+    let fwait_ip = cpu.ip;
+    cpu.bus.write_8(fwait_ip as usize, 0x9B);
+    cpu.step();
+    cpu.step();
+    assert_eq!(cpu.cs, 0, "Masked IRQ13 isn't delivered");
+
+    cpu.bus.io_write(0xA1, 0x00);
+    cpu.step();
+    assert_eq!(cpu.cs, 0x2000);
+    assert_eq!(cpu.bus.read_16(cpu.sp as usize), fwait_ip + 1);
+}
+
+#[test]
+fn test_bios_reflects_irq13_to_nmi() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+    cpu.load_shell();
+
+    let ip = cpu.bus.read_16(0x75 * 4) as usize;
+    let cs = cpu.bus.read_16(0x75 * 4 + 2) as usize;
+    let stub = (cs << 4) + ip;
+    // PUSH AX / MOV AL, 20h / OUT A0h, AL / OUT 20h, AL / POP AX / INT 02h / IRET
+    let expected = [0x50, 0xB0, 0x20, 0xE6, 0xA0, 0xE6, 0x20, 0x58, 0xCD, 0x02, 0xCF];
+    for (i, &byte) in expected.iter().enumerate() {
+        assert_eq!(cpu.bus.read_8(stub + i), byte);
+    }
+}
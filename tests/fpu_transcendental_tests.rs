@@ -133,4 +133,96 @@ fn test_fpatan_negative_coordinates() {
 
     let expected = -3.0 * FRAC_PI_4;
     assert_f64_eq(cpu.fpu_get(0).get_f64(), expected, "atan2(-1,-1) should be -3PI/4");
-}
\ No newline at end of file
+}
+
+fn push_bits(cpu: &mut Cpu, bits: u128) {
+    let mut f = F80::new();
+    f.set(bits);
+    cpu.fpu_push(f);
+}
+
+#[test]
+fn test_trigonometry_bit_exact() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+
+    // sin(1), cos(-3) and tan(0.5), to the last bit
+    push_bits(&mut cpu, 0x3FFF_8000_0000_0000_0000);
+    run_cpu_code(&mut cpu, &[0xD9, 0xFE]);
+    assert_eq!(cpu.fpu_get(0).get(), 0x3FFE_D76A_A478_4867_7021);
+    assert!(cpu.get_fpu_flags().contains(FpuFlags::PE));
+    push_bits(&mut cpu, 0xC000_C000_0000_0000_0000);
+    run_cpu_code(&mut cpu, &[0xD9, 0xFF]);
+    assert_eq!(cpu.fpu_get(0).get(), 0xBFFE_FD70_25F4_2F2E_9308);
+    push_bits(&mut cpu, 0x3FFE_8000_0000_0000_0000);
+    run_cpu_code(&mut cpu, &[0xD9, 0xF2]);
+    assert_eq!(cpu.fpu_get(1).get(), 0x3FFE_8BDA_7ADF_9A3A_5219);
+
+    // FSINCOS of -3: sine in ST(1), cosine on top
+    push_bits(&mut cpu, 0xC000_C000_0000_0000_0000);
+    run_cpu_code(&mut cpu, &[0xD9, 0xFB]);
+    assert_eq!(cpu.fpu_get(0).get(), 0xBFFE_FD70_25F4_2F2E_9308);
+    assert_eq!(cpu.fpu_get(1).get(), 0xBFFC_9081_C36D_B6AA_DA79);
+
+    // sin of the extended pi is the tiny gap to the real one
+    push_bits(&mut cpu, 0x4000_C90F_DAA2_2168_C235);
+    run_cpu_code(&mut cpu, &[0xD9, 0xFE]);
+    assert_eq!(cpu.fpu_get(0).get(), 0xBFBE_ECE6_75D1_FC8F_8CBB);
+
+    // The largest operand in range still reduces exactly
+    push_bits(&mut cpu, 0x403D_FFFF_FFFF_FFFF_FFFF);
+    run_cpu_code(&mut cpu, &[0xD9, 0xFE]);
+    assert_eq!(cpu.fpu_get(0).get(), 0x3FFE_DF32_7E11_2ABE_EF8F);
+    assert!(!cpu.get_fpu_flag(FpuFlags::C2));
+}
+
+#[test]
+fn test_fpatan_bit_exact() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+
+    // atan2(1, 2), atan2(3, -0.5) and atan2(-1, -1)
+    for (y, x, expected) in [
+        (0x3FFF_8000_0000_0000_0000, 0x4000_8000_0000_0000_0000, 0x3FFD_ED63_382B_0DDA_7B45),
+        (0x4000_C000_0000_0000_0000, 0xBFFE_8000_0000_0000_0000, 0x3FFF_DE33_7226_5E03_9FA1),
+        (0xBFFF_8000_0000_0000_0000, 0xBFFF_8000_0000_0000_0000, 0xC000_96CB_E3F9_990E_91A8),
+    ] {
+        push_bits(&mut cpu, y);
+        push_bits(&mut cpu, x);
+        run_cpu_code(&mut cpu, &[0xD9, 0xF3]);
+        assert_eq!(cpu.fpu_get(0).get(), expected);
+    }
+
+    // Signed zeros pick the side: atan2(-0, -0) = -pi
+    cpu.fpu_push(F80::zero(true));
+    cpu.fpu_push(F80::zero(true));
+    run_cpu_code(&mut cpu, &[0xD9, 0xF3]);
+    assert_eq!(cpu.fpu_get(0).get(), 0xC000_C90F_DAA2_2168_C235);
+}
+
+#[test]
+fn test_trigonometry_out_of_range_and_special() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+
+    // 2^63: C2 set, the operand is left alone and FPTAN pushes nothing
+    let top = cpu.fpu_top;
+    push_bits(&mut cpu, 0x403E_8000_0000_0000_0000);
+    run_cpu_code(&mut cpu, &[0xD9, 0xF2]);
+    assert!(cpu.get_fpu_flag(FpuFlags::C2));
+    assert_eq!(cpu.fpu_get(0).get(), 0x403E_8000_0000_0000_0000);
+    assert_eq!(cpu.fpu_top, top.wrapping_sub(1) & 7);
+
+    // sin(-0) = -0 and cos(-0) = 1, both exact
+    cpu.set_fpu_flag(FpuFlags::PE, false);
+    cpu.fpu_push(F80::zero(true));
+    run_cpu_code(&mut cpu, &[0xD9, 0xFE]);
+    assert_eq!(cpu.fpu_get(0).get(), F80::zero(true).get());
+    assert!(!cpu.get_fpu_flag(FpuFlags::C2));
+    run_cpu_code(&mut cpu, &[0xD9, 0xFF]);
+    assert_eq!(cpu.fpu_get(0).get(), 0x3FFF_8000_0000_0000_0000);
+    assert!(!cpu.get_fpu_flags().contains(FpuFlags::PE));
+
+    // Infinity is invalid
+    cpu.fpu_push(F80::infinity(false));
+    run_cpu_code(&mut cpu, &[0xD9, 0xFF]);
+    assert_eq!(cpu.fpu_get(0).get(), F80::indefinite().get());
+    assert!(cpu.get_fpu_flags().contains(FpuFlags::IE));
+}
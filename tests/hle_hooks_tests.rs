@@ -0,0 +1,108 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use rust_dos::cpu::{Cpu, CpuState};
+use rust_dos::interrupts::hooks::HleAction;
+
+fn run(cpu: &mut Cpu, code: &[u8]) {
+    cpu.cs = 0x2000;
+    cpu.ip = 0x0000;
+    cpu.ss = 0x1000;
+    cpu.sp = 0x0100;
+    cpu.state = CpuState::Running;
+    for (i, &byte) in code.iter().enumerate() {
+        cpu.bus.write_8(0x20000 + i, byte);
+    }
+    for _ in 0..50 {
+        if cpu.state == CpuState::Halted {
+            break;
+        }
+        cpu.step();
+    }
+}
+
+#[test]
+fn test_hook_new_vector() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+    cpu.hook_interrupt(0x60, |cpu| {
+        cpu.ax = cpu.ax.wrapping_add(0x0100);
+        HleAction::Handled
+    });
+
+    // The vector now points at a BOP stub in the BIOS segment
+    assert_eq!(cpu.bus.read_16(0x60 * 4 + 2), 0xF000);
+
+    // MOV AX,0100h / INT 60h / HLT
+    run(&mut cpu, &[0xB8, 0x00, 0x01, 0xCD, 0x60, 0xF4]);
+    assert_eq!(cpu.state, CpuState::Halted);
+    assert_eq!(cpu.ax, 0x0200);
+    assert_eq!(cpu.sp, 0x0100);
+}
+
+#[test]
+fn test_hook_single_function() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+    cpu.hook_interrupt_function(0x21, 0xF0, |cpu| {
+        cpu.bx = 0x1234;
+        HleAction::Handled
+    });
+
+    // MOV AH,F0h / INT 21h / HLT: our function
+    run(&mut cpu, &[0xB4, 0xF0, 0xCD, 0x21, 0xF4]);
+    assert_eq!(cpu.bx, 0x1234);
+
+    // MOV AH,30h / INT 21h / HLT: DOS still answers Get Version
+    cpu.bx = 0;
+    run(&mut cpu, &[0xB4, 0x30, 0xCD, 0x21, 0xF4]);
+    assert_eq!(cpu.ax & 0xFF, 5);
+    assert_eq!(cpu.bx, 0xFF00);
+}
+
+#[test]
+fn test_hooks_chain_newest_first() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+    let calls = Rc::new(RefCell::new(Vec::new()));
+
+    let log = calls.clone();
+    cpu.hook_interrupt(0x61, move |_| {
+        log.borrow_mut().push("first");
+        HleAction::Handled
+    });
+    let log = calls.clone();
+    cpu.hook_interrupt(0x61, move |_| {
+        log.borrow_mut().push("second");
+        HleAction::Chain
+    });
+    let log = calls.clone();
+    cpu.hook_interrupt_function(0x61, 0x05, move |_| {
+        log.borrow_mut().push("function 5");
+        HleAction::Chain
+    });
+
+    // MOV AH,01h / INT 61h / HLT
+    run(&mut cpu, &[0xB4, 0x01, 0xCD, 0x61, 0xF4]);
+    assert_eq!(*calls.borrow(), vec!["second", "first"]);
+
+    calls.borrow_mut().clear();
+    // MOV AH,05h / INT 61h / HLT
+    run(&mut cpu, &[0xB4, 0x05, 0xCD, 0x61, 0xF4]);
+    assert_eq!(*calls.borrow(), vec!["function 5", "second", "first"]);
+}
+
+#[test]
+fn test_hooks_survive_shell_reload() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+    cpu.hook_interrupt(0x62, |_| HleAction::Handled);
+
+    // A program trashing the IVT entry is undone when the shell comes back
+    cpu.bus.write_16(0x62 * 4, 0);
+    cpu.bus.write_16(0x62 * 4 + 2, 0);
+    cpu.load_shell();
+    assert_eq!(cpu.bus.read_16(0x62 * 4 + 2), 0xF000);
+
+    // BIOS vectors are installed by Cpu::new, with or without hooks
+    let fresh = Cpu::new(std::path::PathBuf::from("."));
+    for &vector in rust_dos::interrupts::BIOS_VECTORS {
+        assert_eq!(fresh.bus.read_16(vector as usize * 4 + 2), 0xF000);
+    }
+}
@@ -1,5 +1,6 @@
 use iced_x86::Register;
 use rust_dos::cpu::{Cpu, CpuFlags};
+use rust_dos::mcb::{self, Mcb};
 use std::fs;
 use std::path::PathBuf;
 
@@ -139,13 +140,12 @@ fn test_int21_ah4b_exec() {
     );
 
     // Verify CS:IP reset (COM file)
-    assert_eq!(cpu.cs, 0x2000);
+    // PSP is at DS:0000 (after load), in the first free block after the environment
+    let psp_seg = cpu.ds;
+    assert_eq!(cpu.cs, psp_seg);
     assert_eq!(cpu.ip, 0x100);
 
     // Verify PSP Command Tail
-    // PSP is at DS:0000 (after load)
-    let psp_seg = cpu.ds;
-    assert_eq!(psp_seg, 0x2000);
 
     let psp_phys = cpu.get_physical_addr(psp_seg, 0x80);
     let tail_len = cpu.bus.read_8(psp_phys);
@@ -159,10 +159,16 @@ fn test_int21_ah4b_exec() {
 
     // Verify Environment Block
     // Since we didn't specify one, it should use Default.
-    // Check offsets, etc.
+    // Both blocks come from the arena and belong to the child.
     let env_seg_ptr_phys = cpu.get_physical_addr(psp_seg, 0x2C);
     let new_env_seg = cpu.bus.read_16(env_seg_ptr_phys);
-    assert_eq!(new_env_seg, 0x0C00, "Should use scratch segment 0x0C00");
+    let env_mcb = Mcb::read(&cpu.bus, new_env_seg - 1).unwrap();
+    assert_eq!(new_env_seg, mcb::FIRST_MCB + 1);
+    assert_eq!(env_mcb.owner, psp_seg);
+    assert_eq!(env_mcb.end(), psp_seg - 1);
+    let psp_mcb = Mcb::read(&cpu.bus, psp_seg - 1).unwrap();
+    assert_eq!(psp_mcb.owner, psp_seg);
+    assert_eq!(psp_mcb.name(&cpu.bus), "RUNME");
 
     fs::remove_dir_all(&root_path).unwrap();
 }
@@ -220,8 +226,8 @@ fn test_int21_ah4b_exec_with_env() {
 
     assert!(!cpu.get_cpu_flag(CpuFlags::CF));
 
-    // Verify New Env Block at 0x0C00
-    let new_env_seg = 0x0C00;
+    // Verify New Env Block, given to the child
+    let new_env_seg = cpu.bus.read_16(cpu.get_physical_addr(cpu.ds, 0x2C)); // DS is new PSP
     let new_env_phys = cpu.get_physical_addr(new_env_seg, 0);
 
     for (i, &b) in env_data.iter().enumerate() {
@@ -233,9 +239,8 @@ fn test_int21_ah4b_exec_with_env() {
         );
     }
 
-    // Verify PSP -> Env Pointer
-    let psp_phys = cpu.get_physical_addr(cpu.ds, 0x2C); // DS is new PSP
-    assert_eq!(cpu.bus.read_16(psp_phys), new_env_seg);
+    // Verify the env block belongs to the new PSP
+    assert_eq!(Mcb::read(&cpu.bus, new_env_seg - 1).unwrap().owner, cpu.ds);
 
     fs::remove_dir_all(&root_path).unwrap();
 }
@@ -298,8 +303,8 @@ fn test_int21_ah4b_exec_inheritance() {
 
     assert!(!cpu.get_cpu_flag(CpuFlags::CF));
 
-    // Verify New Env Block at 0x0C00
-    let new_env_seg = 0x0C00;
+    // Verify New Env Block, given to the child
+    let new_env_seg = cpu.bus.read_16(cpu.get_physical_addr(cpu.ds, 0x2C)); // DS is new PSP
     let new_env_phys = cpu.get_physical_addr(new_env_seg, 0);
 
     for (i, &b) in env_data.iter().enumerate() {
@@ -311,9 +316,8 @@ fn test_int21_ah4b_exec_inheritance() {
         );
     }
 
-    // Verify PSP -> Env Pointer
-    let psp_phys = cpu.get_physical_addr(cpu.ds, 0x2C); // DS is new PSP
-    assert_eq!(cpu.bus.read_16(psp_phys), new_env_seg);
+    // Verify the env block belongs to the new PSP
+    assert_eq!(Mcb::read(&cpu.bus, new_env_seg - 1).unwrap().owner, cpu.ds);
 
     fs::remove_dir_all(&root_path).unwrap();
 }
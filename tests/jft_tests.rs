@@ -0,0 +1,201 @@
+use rust_dos::cpu::{Cpu, CpuFlags};
+use rust_dos::disk;
+use rust_dos::handles;
+use rust_dos::interrupts::int21;
+use std::fs;
+use std::path::PathBuf;
+
+const PARENT: u16 = 0x2000;
+const CHILD: u16 = 0x3000;
+const BUFFER: u16 = 0x4000;
+
+fn setup(name: &str) -> (Cpu, PathBuf) {
+    let root_path = PathBuf::from(format!("target/test_jft_{}", name));
+    if root_path.exists() {
+        fs::remove_dir_all(&root_path).unwrap();
+    }
+    fs::create_dir_all(&root_path).unwrap();
+    fs::write(root_path.join("DATA.TXT"), b"0123456789").unwrap();
+
+    let mut cpu = Cpu::new(root_path.clone());
+    handles::init_psp(&mut cpu.bus, PARENT, 0);
+    cpu.current_psp = PARENT;
+    (cpu, root_path)
+}
+
+fn dos_call(cpu: &mut Cpu, ax: u16, bx: u16, cx: u16) -> Result<u16, u16> {
+    cpu.ax = ax;
+    cpu.bx = bx;
+    cpu.cx = cx;
+    int21::handle(cpu);
+    if cpu.get_cpu_flag(CpuFlags::CF) {
+        Err(cpu.ax)
+    } else {
+        Ok(cpu.ax)
+    }
+}
+
+fn open(cpu: &mut Cpu, name: &str, mode: u8) -> Result<u16, u16> {
+    let addr = (BUFFER as usize) << 4;
+    for (i, b) in name.bytes().chain([0]).enumerate() {
+        cpu.bus.write_8(addr + 0x100 + i, b);
+    }
+    cpu.ds = BUFFER;
+    cpu.dx = 0x100;
+    dos_call(cpu, 0x3D00 | mode as u16, 0, 0)
+}
+
+fn read(cpu: &mut Cpu, handle: u16, count: u16) -> Result<Vec<u8>, u16> {
+    cpu.ds = BUFFER;
+    cpu.dx = 0;
+    let read = dos_call(cpu, 0x3F00, handle, count)?;
+    let addr = (BUFFER as usize) << 4;
+    Ok((0..read as usize).map(|i| cpu.bus.read_8(addr + i)).collect())
+}
+
+fn jft(cpu: &Cpu, psp: u16) -> Vec<u8> {
+    let addr = ((psp as usize) << 4) + handles::JFT_OFFSET as usize;
+    (0..5).map(|i| cpu.bus.read_8(addr + i)).collect()
+}
+
+fn refs(cpu: &Cpu, sft: u8) -> Option<u16> {
+    cpu.bus.disk.sft_entry(sft).map(|e| e.refs)
+}
+
+#[test]
+fn test_jft_standard_handles_and_reuse() {
+    let (mut cpu, root_path) = setup("reuse");
+    let psp = (PARENT as usize) << 4;
+    assert_eq!(jft(&cpu, PARENT), vec![0, 0, 0, 1, 2]);
+    assert_eq!(cpu.bus.read_16(psp + 0x32), 20);
+    assert_eq!(cpu.bus.read_16(psp + 0x34), 0x18);
+    assert_eq!(cpu.bus.read_16(psp + 0x36), PARENT);
+    // The shell and this process both hold CON for stdin, stdout and stderr
+    assert_eq!(refs(&cpu, disk::SFT_CON), Some(6));
+
+    // Handles are the lowest free ones, and come back after closing
+    let a = open(&mut cpu, "DATA.TXT", 0).unwrap();
+    let b = open(&mut cpu, "DATA.TXT", 0).unwrap();
+    assert_eq!((a, b), (5, 6));
+    assert!(dos_call(&mut cpu, 0x3E00, a, 0).is_ok());
+    assert_eq!(dos_call(&mut cpu, 0x3E00, a, 0), Err(6));
+    assert_eq!(open(&mut cpu, "DATA.TXT", 0), Ok(5));
+    assert_eq!(dos_call(&mut cpu, 0x3F00, 19, 1), Err(6));
+    assert_eq!(dos_call(&mut cpu, 0x3F00, 200, 1), Err(6));
+
+    // Closing stdin leaves handle 0 free for the next open
+    assert!(dos_call(&mut cpu, 0x3E00, 0, 0).is_ok());
+    assert_eq!(open(&mut cpu, "DATA.TXT", 0), Ok(0));
+    assert_eq!(read(&mut cpu, 0, 4).unwrap(), b"0123");
+
+    // The rest fill the table
+    for _ in 7..20 {
+        open(&mut cpu, "DATA.TXT", 0).unwrap();
+    }
+    assert_eq!(open(&mut cpu, "DATA.TXT", 0), Err(4));
+
+    fs::remove_dir_all(&root_path).unwrap();
+}
+
+#[test]
+fn test_jft_dup_and_force_dup() {
+    let (mut cpu, root_path) = setup("dup");
+
+    let a = open(&mut cpu, "DATA.TXT", 2).unwrap();
+    let b = dos_call(&mut cpu, 0x4500, a, 0).unwrap();
+    assert_eq!(b, a + 1);
+    let file_sft = cpu.bus.read_8(((PARENT as usize) << 4) + 0x18 + a as usize);
+    assert_eq!(refs(&cpu, file_sft), Some(2));
+
+    // Both handles share one file pointer
+    assert_eq!(read(&mut cpu, a, 3).unwrap(), b"012");
+    assert_eq!(read(&mut cpu, b, 3).unwrap(), b"345");
+
+    // Redirect stdout into the file; closing the originals keeps it open
+    assert!(dos_call(&mut cpu, 0x4600, a, 1).is_ok());
+    assert_eq!(refs(&cpu, file_sft), Some(3));
+    assert_eq!(refs(&cpu, disk::SFT_CON), Some(5));
+    dos_call(&mut cpu, 0x3E00, a, 0).unwrap();
+    dos_call(&mut cpu, 0x3E00, b, 0).unwrap();
+    let addr = (BUFFER as usize) << 4;
+    for (i, &byte) in b"AB".iter().enumerate() {
+        cpu.bus.write_8(addr + i, byte);
+    }
+    cpu.ds = BUFFER;
+    cpu.dx = 0;
+    assert_eq!(dos_call(&mut cpu, 0x4000, 1, 2), Ok(2));
+    dos_call(&mut cpu, 0x3E00, 1, 0).unwrap();
+    assert!(refs(&cpu, file_sft).is_none());
+    assert_eq!(fs::read(root_path.join("DATA.TXT")).unwrap(), b"012345AB89");
+
+    assert_eq!(dos_call(&mut cpu, 0x4600, 1, 0), Err(6));
+    assert_eq!(dos_call(&mut cpu, 0x4600, 2, 20), Err(6));
+    assert_eq!(dos_call(&mut cpu, 0x4500, 7, 0), Err(6));
+
+    fs::remove_dir_all(&root_path).unwrap();
+}
+
+#[test]
+fn test_jft_set_handle_count() {
+    let (mut cpu, root_path) = setup("count");
+
+    for _ in 5..20 {
+        open(&mut cpu, "DATA.TXT", 0).unwrap();
+    }
+    assert_eq!(open(&mut cpu, "DATA.TXT", 0), Err(4));
+
+    // The table moves out of the PSP, handles and all
+    assert!(dos_call(&mut cpu, 0x6700, 30, 0).is_ok());
+    let psp = (PARENT as usize) << 4;
+    assert_eq!(cpu.bus.read_16(psp + 0x32), 30);
+    assert_eq!(cpu.bus.read_16(psp + 0x34), 0);
+    assert_ne!(cpu.bus.read_16(psp + 0x36), PARENT);
+    assert_eq!(open(&mut cpu, "DATA.TXT", 0), Ok(20));
+    assert_eq!(read(&mut cpu, 19, 2).unwrap(), b"01");
+
+    // It can't shrink below the handles in use
+    assert_eq!(dos_call(&mut cpu, 0x6700, 20, 0), Err(4));
+    dos_call(&mut cpu, 0x3E00, 20, 0).unwrap();
+    assert!(dos_call(&mut cpu, 0x6700, 20, 0).is_ok());
+    assert_eq!(cpu.bus.read_16(psp + 0x36), PARENT);
+    assert_eq!(read(&mut cpu, 19, 2).unwrap(), b"23");
+
+    fs::remove_dir_all(&root_path).unwrap();
+}
+
+#[test]
+fn test_jft_inheritance_and_termination() {
+    let (mut cpu, root_path) = setup("inherit");
+
+    let shared = open(&mut cpu, "DATA.TXT", 0).unwrap();
+    let private = open(&mut cpu, "DATA.TXT", handles::NO_INHERIT).unwrap();
+    let shared_sft = cpu.bus.read_8(((PARENT as usize) << 4) + 0x18 + shared as usize);
+
+    // The child gets everything but the no-inherit handle
+    cpu.save_process_context();
+    handles::init_psp(&mut cpu.bus, CHILD, PARENT);
+    cpu.current_psp = CHILD;
+    let child_jft = |cpu: &Cpu| {
+        let addr = ((CHILD as usize) << 4) + 0x18;
+        (0..8).map(|i| cpu.bus.read_8(addr + i)).collect::<Vec<u8>>()
+    };
+    assert_eq!(
+        child_jft(&cpu),
+        vec![0, 0, 0, 1, 2, shared_sft, handles::UNUSED, handles::UNUSED]
+    );
+    assert_eq!(refs(&cpu, shared_sft), Some(2));
+    assert_eq!(dos_call(&mut cpu, 0x3F00, private, 1), Err(6));
+
+    // The child's own files close when it exits; inherited ones stay open for the parent
+    let own = open(&mut cpu, "DATA.TXT", 0).unwrap();
+    let own_sft = child_jft(&cpu)[own as usize];
+    assert!(dos_call(&mut cpu, 0x4C00, 0, 0).is_ok());
+    assert_eq!(cpu.current_psp, PARENT);
+    assert!(refs(&cpu, own_sft).is_none());
+    assert_eq!(refs(&cpu, shared_sft), Some(1));
+    assert_eq!(refs(&cpu, disk::SFT_CON), Some(6));
+    assert_eq!(read(&mut cpu, shared, 2).unwrap(), b"01");
+    assert_eq!(read(&mut cpu, private, 2).unwrap(), b"01");
+
+    fs::remove_dir_all(&root_path).unwrap();
+}
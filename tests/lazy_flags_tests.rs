@@ -0,0 +1,138 @@
+use rust_dos::cpu::{Cpu, CpuFlags};
+mod testrunners;
+use testrunners::run_cpu_code;
+
+const ARITH: [CpuFlags; 6] = [
+    CpuFlags::CF,
+    CpuFlags::PF,
+    CpuFlags::AF,
+    CpuFlags::ZF,
+    CpuFlags::SF,
+    CpuFlags::OF,
+];
+
+// Eagerly computed reference flags for an 8-bit add/sub with carry in
+fn reference_8(dest: u8, src: u8, cin: u8, sub: bool) -> [bool; 6] {
+    let (wide, result) = if sub {
+        let w = (dest as u16)
+            .wrapping_sub(src as u16)
+            .wrapping_sub(cin as u16);
+        (w, w as u8)
+    } else {
+        let w = dest as u16 + src as u16 + cin as u16;
+        (w, w as u8)
+    };
+    let d = dest & 0x80 != 0;
+    let s = src & 0x80 != 0;
+    let r = result & 0x80 != 0;
+    let of = if sub {
+        d != s && r != d
+    } else {
+        d == s && r != d
+    };
+    [
+        wide > 0xFF,
+        result.count_ones() % 2 == 0,
+        (dest ^ src ^ result) & 0x10 != 0,
+        result == 0,
+        r,
+        of,
+    ]
+}
+
+#[test]
+fn test_lazy_flags_match_eager_for_all_8bit_operands() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+    for dest in 0..=255u8 {
+        for src in 0..=255u8 {
+            for cin in 0..=1u8 {
+                for sub in [false, true] {
+                    cpu.set_cpu_flag(CpuFlags::CF, cin == 1);
+                    let res = match sub {
+                        false => cpu.alu_adc_8(dest, src),
+                        true => cpu.alu_sbb_8(dest, src),
+                    };
+                    let expected = reference_8(dest, src, cin, sub);
+                    assert_eq!(
+                        res,
+                        if sub {
+                            dest.wrapping_sub(src).wrapping_sub(cin)
+                        } else {
+                            dest.wrapping_add(src).wrapping_add(cin)
+                        }
+                    );
+                    for (flag, want) in ARITH.iter().zip(expected) {
+                        assert_eq!(
+                            cpu.get_cpu_flag(*flag),
+                            want,
+                            "{:?} for {:02X} {} {:02X} cin={}",
+                            flag,
+                            dest,
+                            if sub { "-" } else { "+" },
+                            src,
+                            cin
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_logic_op_keeps_af_from_previous_op() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+    cpu.ip = 0x100;
+
+    // ADD AL, 0Fh with AL=01 sets AF; AND AL, AL must leave AF alone and clear CF/OF
+    // B0 01    MOV AL, 1
+    // 04 0F    ADD AL, 0Fh
+    // 20 C0    AND AL, AL
+    run_cpu_code(&mut cpu, &[0xB0, 0x01, 0x04, 0x0F, 0x20, 0xC0]);
+    assert_eq!(cpu.get_al(), 0x10);
+    assert!(cpu.get_cpu_flag(CpuFlags::AF));
+    assert!(!cpu.get_cpu_flag(CpuFlags::CF));
+    assert!(!cpu.get_cpu_flag(CpuFlags::ZF));
+}
+
+#[test]
+fn test_inc_dec_preserve_carry() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+    cpu.ip = 0x100;
+
+    // STC / INC AX (FFFF -> 0) / DEC BX
+    cpu.ax = 0xFFFF;
+    cpu.bx = 0x0001;
+    run_cpu_code(&mut cpu, &[0xF9, 0x40, 0x4B]);
+    assert!(cpu.get_cpu_flag(CpuFlags::CF), "INC/DEC must not touch CF");
+    assert!(cpu.get_cpu_flag(CpuFlags::ZF));
+    assert_eq!(cpu.ax, 0);
+    assert_eq!(cpu.bx, 0);
+}
+
+#[test]
+fn test_pushf_and_lahf_see_pending_flags() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+    cpu.ip = 0x100;
+    cpu.ss = 0x2000;
+    cpu.sp = 0x100;
+
+    // MOV AL, 80h / ADD AL, 80h (CF, ZF, OF, PF) / PUSHF / LAHF
+    run_cpu_code(&mut cpu, &[0xB0, 0x80, 0x04, 0x80, 0x9C, 0x9F]);
+    let pushed = cpu.bus.read_16(cpu.get_physical_addr(cpu.ss, cpu.sp));
+    let expected = (CpuFlags::CF | CpuFlags::ZF | CpuFlags::OF | CpuFlags::PF).bits();
+    assert_eq!(pushed & 0x08D5, expected);
+    assert_eq!((cpu.ax >> 8) as u8 & 0xD5, (expected & 0xD5) as u8);
+}
+
+#[test]
+fn test_set_flag_overrides_pending_result() {
+    let mut cpu = Cpu::new(std::path::PathBuf::from("."));
+    cpu.alu_sub_16(0, 1); // CF=1, SF=1
+    cpu.set_cpu_flag(CpuFlags::CF, false);
+    assert!(!cpu.get_cpu_flag(CpuFlags::CF));
+    assert!(cpu.get_cpu_flag(CpuFlags::SF));
+
+    cpu.set_cpu_flags(CpuFlags::from_bits_truncate(0x0002));
+    assert!(!cpu.get_cpu_flag(CpuFlags::SF));
+}
@@ -0,0 +1,252 @@
+use std::cell::RefCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use rust_dos::devices::Device;
+use rust_dos::cpu::{ClockSpeed, CpuModel, FpuModel};
+use rust_dos::machine::{Machine, MachineBuilder};
+
+const LIMIT: u64 = 20_000_000;
+
+fn test_dir(name: &str) -> PathBuf {
+    let root = PathBuf::from(format!("target/{}", name));
+    if root.exists() {
+        fs::remove_dir_all(&root).unwrap();
+    }
+    fs::create_dir_all(&root).unwrap();
+    root
+}
+
+fn at_prompt(machine: &Machine) -> bool {
+    machine.screen_text().lines().any(|l| l.ends_with("C:\\>"))
+}
+
+#[test]
+fn test_run_program_from_shell() {
+    let root = test_dir("test_machine_run");
+    // MOV SI,0113h / next: LODSB / OR AL,AL / JZ done / MOV AH,0Eh / INT 10h / JMP next
+    // done: MOV AX,4C07h / INT 21h / "HELLO\r\n"
+    let mut program = vec![
+        0xBE, 0x13, 0x01, 0xAC, 0x08, 0xC0, 0x74, 0x06, 0xB4, 0x0E, 0xCD, 0x10, 0xEB, 0xF5, 0xB8,
+        0x07, 0x4C, 0xCD, 0x21,
+    ];
+    program.extend_from_slice(b"HELLO\r\n\0");
+    fs::write(root.join("HELLO.COM"), program).unwrap();
+
+    let mut machine = MachineBuilder::new(&root).build();
+    assert!(machine.run_until(LIMIT, at_prompt));
+    assert_eq!(machine.exit_code(), None);
+
+    machine.send_keys("hello\r");
+    assert!(machine.run_until(LIMIT, |m| m.exit_code().is_some()));
+    assert_eq!(machine.exit_code(), Some(7));
+
+    assert!(machine.run_until(LIMIT, |m| m.screen_text().matches("C:\\>").count() == 2));
+    let screen = machine.screen_text();
+    assert!(screen.contains("C:\\>hello"), "{}", screen);
+    assert!(screen.lines().any(|l| l == "HELLO"), "{}", screen);
+}
+
+// Runs a .COM program from the shell prompt and waits for it to exit
+fn run_program(builder: MachineBuilder, root: &Path, program: &[u8]) -> Machine {
+    fs::write(root.join("PROG.COM"), program).unwrap();
+    let mut machine = builder.build();
+    assert!(machine.run_until(LIMIT, at_prompt));
+    machine.send_keys("prog\r");
+    assert!(machine.run_until(LIMIT, |m| m.exit_code().is_some()), "{}", machine.screen_text());
+    machine
+}
+
+#[test]
+fn test_xms_far_call_from_program() {
+    let root = test_dir("test_machine_xms");
+    // MOV AX,4310h / INT 2Fh / MOV [0120h],BX / MOV [0122h],ES
+    // MOV AH,00h / CALL FAR [0120h] / MOV AL,AH / MOV AH,4Ch / INT 21h
+    let mut program = vec![
+        0xB8, 0x10, 0x43, 0xCD, 0x2F, 0x89, 0x1E, 0x20, 0x01, 0x8C, 0x06, 0x22, 0x01, 0xB4, 0x00,
+        0xFF, 0x1E, 0x20, 0x01, 0x88, 0xE0, 0xB4, 0x4C, 0xCD, 0x21,
+    ];
+    program.resize(0x24, 0);
+
+    let machine = run_program(MachineBuilder::new(&root), &root, &program);
+    assert_eq!(machine.exit_code(), Some(0x03), "XMS 3.0 driver version");
+}
+
+#[test]
+fn test_ems_alter_map_and_call_from_program() {
+    let root = test_dir("test_machine_ems");
+    // MOV AH,43h / MOV BX,1 / INT 67h / MOV [0122h],CS / MOV SI,0120h
+    // MOV AX,5600h / INT 67h / MOV AL,CL / MOV AH,4Ch / INT 21h
+    // 0119: MOV CL,2Ah / RETF
+    let mut program = vec![
+        0xB4, 0x43, 0xBB, 0x01, 0x00, 0xCD, 0x67, 0x8C, 0x0E, 0x22, 0x01, 0xBE, 0x20, 0x01, 0xB8,
+        0x00, 0x56, 0xCD, 0x67, 0x88, 0xC8, 0xB4, 0x4C, 0xCD, 0x21, 0xB1, 0x2A, 0xCB,
+    ];
+    // 0120: target 0119h, segment filled in, empty new and old maps
+    program.resize(0x20, 0);
+    program.extend([0x19, 0x01, 0x00, 0x00]);
+    program.resize(0x2E, 0);
+
+    let machine = run_program(MachineBuilder::new(&root), &root, &program);
+    assert_eq!(machine.exit_code(), Some(0x2A), "Target ran and returned");
+    assert!(machine.cpu.bus.ems().call_stack.is_empty());
+}
+
+#[test]
+fn test_critical_error_prompt_from_program() {
+    let root = test_dir("test_machine_int24");
+    // MOV AH,3Ch / XOR CX,CX / MOV DX,0110h / INT 21h / MOV AH,4Ch / INT 21h / "NEW.TXT"
+    let mut program = vec![
+        0xB4, 0x3C, 0x31, 0xC9, 0xBA, 0x10, 0x01, 0xCD, 0x21, 0xB4, 0x4C, 0xCD, 0x21,
+    ];
+    program.resize(0x10, 0);
+    program.extend_from_slice(b"NEW.TXT\0");
+    fs::write(root.join("PROG.COM"), program).unwrap();
+
+    let mut machine = MachineBuilder::new(&root).write_protect(true).build();
+    assert!(machine.run_until(LIMIT, at_prompt));
+    machine.send_keys("prog\r");
+    assert!(machine.run_until(LIMIT, |m| m.screen_text().contains("Abort, Retry, Fail?")));
+
+    // The default handler returns through the FE 39 stub at F000:0F28
+    machine.send_keys("f");
+    assert!(machine.run_until(LIMIT, |m| m.exit_code().is_some()));
+    assert_eq!(machine.exit_code(), Some(0x53), "Fail on INT 24h");
+    assert!(!root.join("NEW.TXT").exists());
+}
+
+#[test]
+fn test_psp_exit_only_at_the_programs_psp() {
+    let root = test_dir("test_machine_psp_exit");
+    // MOV SP,0200h / MOV AH,4Ah / MOV BX,0020h / INT 21h / MOV [0144h],CS
+    // MOV DX,0130h / MOV BX,0140h / MOV AX,4B00h / INT 21h / MOV AX,4C11h / INT 21h
+    let mut parent = vec![
+        0xBC, 0x00, 0x02, 0xB4, 0x4A, 0xBB, 0x20, 0x00, 0xCD, 0x21, 0x8C, 0x0E, 0x44, 0x01, 0xBA,
+        0x30, 0x01, 0xBB, 0x40, 0x01, 0xB8, 0x00, 0x4B, 0xCD, 0x21, 0xB8, 0x11, 0x4C, 0xCD, 0x21,
+    ];
+    // 0130: name, 0140: parameter block, 0150: empty command tail
+    parent.resize(0x30, 0);
+    parent.extend_from_slice(b"CHILD.COM\0");
+    parent.resize(0x40, 0);
+    parent.extend([0x00, 0x00, 0x50, 0x01]);
+    parent.resize(0x50, 0);
+    parent.extend([0x00, 0x0D]);
+    fs::write(root.join("PARENT.COM"), parent).unwrap();
+
+    // Puts MOV AX,4C2Ah / INT 21h over the parent's PSP at 1000:0000 and jumps there:
+    // MOV AX,1000h / MOV ES,AX / MOV WORD ES:[0],2AB8h / MOV WORD ES:[2],0CD4Ch
+    // MOV BYTE ES:[4],21h / JMP FAR 1000:0000
+    let child = [
+        0xB8, 0x00, 0x10, 0x8E, 0xC0, 0x26, 0xC7, 0x06, 0x00, 0x00, 0xB8, 0x2A, 0x26, 0xC7, 0x06,
+        0x02, 0x00, 0x4C, 0xCD, 0x26, 0xC6, 0x06, 0x04, 0x00, 0x21, 0xEA, 0x00, 0x00, 0x00, 0x10,
+    ];
+    fs::write(root.join("CHILD.COM"), child).unwrap();
+
+    let mut machine = MachineBuilder::new(&root).build();
+    assert!(machine.run_until(LIMIT, at_prompt));
+    machine.send_keys("parent\r");
+    // The child loads above the parent
+    assert!(machine.run_until(LIMIT, |m| m.cpu.current_psp > 0x1000));
+    assert!(machine.run_until(LIMIT, |m| m.exit_code().is_some()));
+    assert_eq!(machine.exit_code(), Some(0x2A), "1000:0000 isn't the child's PSP");
+    assert!(machine.run_until(LIMIT, |m| m.screen_text().matches("C:\\>").count() == 2));
+    assert_eq!(machine.exit_code(), Some(0x11), "The parent carried on");
+
+    // RET: the zero word DOS leaves on the stack sends it to PSP:0000
+    let machine = run_program(MachineBuilder::new(&root), &root, &[0xC3]);
+    assert_eq!(machine.exit_code(), Some(0));
+}
+
+#[test]
+fn test_unknown_command() {
+    let root = test_dir("test_machine_unknown");
+    let mut machine = MachineBuilder::new(&root).build();
+    machine.send_keys("nosuch\n");
+    assert!(machine.run_until(LIMIT, |m| m.screen_text().contains("Bad command or file name.")));
+    assert_eq!(machine.exit_code(), None);
+}
+
+#[test]
+fn test_exit_powers_off() {
+    let root = test_dir("test_machine_exit");
+    let mut machine = MachineBuilder::new(&root).build();
+    machine.send_keys("EXIT\r");
+    assert!(!machine.run_until(LIMIT, |_| false));
+    assert!(machine.is_powered_off());
+}
+
+#[test]
+fn test_builder_settings() {
+    let root = test_dir("test_machine_settings");
+    let machine = MachineBuilder::new(&root)
+        .memory_kb(512)
+        .cpu(CpuModel::I8088)
+        .clock(ClockSpeed::Max)
+        .build();
+
+    assert_eq!(machine.cpu.model, CpuModel::I8088);
+    assert_eq!(machine.cpu.fpu_model, FpuModel::for_cpu(CpuModel::I8088));
+    assert_eq!(machine.cpu.clock, ClockSpeed::Max);
+    assert_eq!(machine.cpu.bus.read_16(0x0413), 512);
+    assert_eq!(machine.cpu.bus.memory_top(), 0x8000);
+}
+
+#[test]
+fn test_try_build_reports_bad_settings() {
+    let root = test_dir("test_machine_bad_settings");
+    let result = MachineBuilder::new(&root).expanded_memory(4, 0x1234).try_build();
+    assert!(result.is_err(), "EMS frame must be a 16KB aligned segment");
+
+    let result = MachineBuilder::new(&root).country(999).try_build();
+    assert!(result.is_err());
+}
+
+struct Latch {
+    value: Rc<RefCell<u8>>,
+}
+
+impl Device for Latch {
+    fn ports(&self) -> Vec<u16> {
+        vec![0x300]
+    }
+
+    fn io_read(&mut self, _port: u16) -> u8 {
+        *self.value.borrow() ^ 0xFF
+    }
+
+    fn io_write(&mut self, _port: u16, value: u8) {
+        *self.value.borrow_mut() = value;
+    }
+}
+
+#[test]
+fn test_attached_device_and_video_sink() {
+    let root = test_dir("test_machine_device");
+    // MOV DX,0300h / MOV AL,5Ah / OUT DX,AL / IN AL,DX / MOV AH,4Ch / INT 21h
+    let program = [0xBA, 0x00, 0x03, 0xB0, 0x5A, 0xEE, 0xEC, 0xB4, 0x4C, 0xCD, 0x21];
+    fs::write(root.join("LATCH.COM"), program).unwrap();
+
+    let value = Rc::new(RefCell::new(0));
+    let frames = Rc::new(RefCell::new(0));
+    let seen = frames.clone();
+    let mut machine = MachineBuilder::new(&root)
+        .device(Latch { value: value.clone() })
+        .video_sink(move |frame: &[u8]| {
+            assert_eq!(frame.len(), 640 * 400 * 3);
+            *seen.borrow_mut() += 1;
+        })
+        .build();
+
+    machine.send_keys("LATCH\r");
+    assert!(machine.run_until(LIMIT, |m| m.exit_code().is_some()));
+    assert_eq!(*value.borrow(), 0x5A);
+    assert_eq!(machine.exit_code(), Some(0xA5));
+
+    // A 386 at its nominal clock presents a frame every ~550,000 cycles
+    machine.run_until(2_000_000, |_| false);
+    assert!(*frames.borrow() >= 3);
+
+    // Something was drawn: the prompt
+    assert!(machine.framebuffer().iter().any(|&b| b != 0));
+}
@@ -0,0 +1,156 @@
+use iced_x86::Register;
+use rust_dos::cpu::{Cpu, CpuFlags, CpuState};
+use rust_dos::interrupts::int21;
+use rust_dos::mcb::{self, Mcb};
+use std::fs;
+use std::path::PathBuf;
+
+fn dos_call(cpu: &mut Cpu, ax: u16, bx: u16) -> Result<u16, u16> {
+    cpu.ax = ax;
+    cpu.bx = bx;
+    int21::handle(cpu);
+    if cpu.get_cpu_flag(CpuFlags::CF) {
+        Err(cpu.ax)
+    } else {
+        Ok(cpu.ax)
+    }
+}
+
+#[test]
+fn test_mcb_allocate_free_resize() {
+    let mut cpu = Cpu::new(PathBuf::from("."));
+    cpu.current_psp = 0x1234;
+    let total = 0xA000 - mcb::FIRST_MCB - 1;
+
+    // Too much: BX says what is left
+    assert_eq!(dos_call(&mut cpu, 0x4800, 0xFFFF), Err(8));
+    assert_eq!(cpu.bx, total);
+
+    let a = dos_call(&mut cpu, 0x4800, 0x100).unwrap();
+    let b = dos_call(&mut cpu, 0x4800, 0x100).unwrap();
+    assert_eq!(a, mcb::FIRST_MCB + 1);
+    assert_eq!(b, a + 0x101);
+    let block = Mcb::read(&cpu.bus, a - 1).unwrap();
+    assert_eq!((block.last, block.owner, block.size), (false, 0x1234, 0x100));
+
+    // Shrinking leaves a free block behind; growing back into it works
+    cpu.es = a;
+    assert!(dos_call(&mut cpu, 0x4A00, 0x80).is_ok());
+    assert!(Mcb::read(&cpu.bus, a + 0x80).unwrap().is_free());
+    assert!(dos_call(&mut cpu, 0x4A00, 0x100).is_ok());
+
+    // Growing into a used block fails and reports the most it can have
+    assert_eq!(dos_call(&mut cpu, 0x4A00, 0x101), Err(8));
+    assert_eq!(cpu.bx, 0x100);
+
+    // Freeing twice, or something that isn't a block, fails
+    cpu.es = b;
+    assert!(dos_call(&mut cpu, 0x4900, 0).is_ok());
+    assert_eq!(dos_call(&mut cpu, 0x4900, 0), Err(9));
+    cpu.es = b + 1;
+    assert_eq!(dos_call(&mut cpu, 0x4900, 0), Err(9));
+
+    // The freed block joins the rest of memory again
+    assert_eq!(dos_call(&mut cpu, 0x4800, 0xFFFF), Err(8));
+    assert_eq!(cpu.bx, total - 0x101);
+
+    // A trashed chain is reported
+    cpu.bus.write_8((a as usize - 1) << 4, b'X');
+    assert_eq!(dos_call(&mut cpu, 0x4800, 0x10), Err(7));
+}
+
+#[test]
+fn test_mcb_allocation_strategies() {
+    let mut cpu = Cpu::new(PathBuf::from("."));
+    cpu.current_psp = 0x1234;
+
+    // Holes of 40h and 20h paragraphs, then the rest of memory
+    let blocks: Vec<u16> = [0x40, 0x10, 0x20, 0x10]
+        .iter()
+        .map(|&size| dos_call(&mut cpu, 0x4800, size).unwrap())
+        .collect();
+    for &hole in &[blocks[0], blocks[2]] {
+        cpu.es = hole;
+        dos_call(&mut cpu, 0x4900, 0).unwrap();
+    }
+
+    assert_eq!(dos_call(&mut cpu, 0x5800, 0), Ok(0x0000));
+    assert_eq!(dos_call(&mut cpu, 0x4800, 0x18), Ok(blocks[0]));
+
+    assert!(dos_call(&mut cpu, 0x5801, 0x0001).is_ok());
+    assert_eq!(dos_call(&mut cpu, 0x5800, 0), Ok(0x0001));
+    assert_eq!(dos_call(&mut cpu, 0x4800, 0x18), Ok(blocks[2]));
+
+    // Last fit comes from the top of memory
+    assert!(dos_call(&mut cpu, 0x5801, 0x0002).is_ok());
+    assert_eq!(dos_call(&mut cpu, 0x4800, 0x10), Ok(0xA000 - 0x10));
+    assert!(Mcb::read(&cpu.bus, 0xA000 - 0x11).unwrap().last);
+
+    assert_eq!(dos_call(&mut cpu, 0x5801, 0x0003), Err(1));
+    assert_eq!(dos_call(&mut cpu, 0x5800, 0), Ok(0x0002));
+
+    // UMB link state: without upper memory there is nothing to link
+    assert_eq!(dos_call(&mut cpu, 0x5803, 0x0001), Err(1));
+    dos_call(&mut cpu, 0x5802, 0).unwrap();
+    assert_eq!(cpu.get_reg8(Register::AL), 0);
+    assert_eq!(dos_call(&mut cpu, 0x5803, 0x0002), Err(1));
+    assert_eq!(dos_call(&mut cpu, 0x5804, 0), Err(1));
+}
+
+#[test]
+fn test_mcb_shrink_exec_and_terminate() {
+    let root_path = PathBuf::from("target/test_mcb_exec");
+    if root_path.exists() {
+        fs::remove_dir_all(&root_path).unwrap();
+    }
+    fs::create_dir_all(&root_path).unwrap();
+
+    // The child grabs some memory and exits with code 7 without freeing it
+    fs::write(
+        root_path.join("CHILD.COM"),
+        [0xB4, 0x48, 0xBB, 0x10, 0x00, 0xCD, 0x21, 0xB8, 0x07, 0x4C, 0xCD, 0x21],
+    )
+    .unwrap();
+
+    // The parent shrinks itself to 64KB, then runs the child
+    let mut parent = vec![
+        0xBB, 0x00, 0x10, // MOV BX,1000h
+        0xB4, 0x4A, //       MOV AH,4Ah
+        0xCD, 0x21, //       INT 21h
+        0xBA, 0x14, 0x01, // MOV DX,name
+        0xBB, 0x1E, 0x01, // MOV BX,params
+        0xB8, 0x00, 0x4B, // MOV AX,4B00h
+        0xCD, 0x21, //       INT 21h
+        0xF4, //             HLT
+        0x90,
+    ];
+    parent.extend(b"CHILD.COM\0");
+    parent.extend([0x00, 0x00, 0x80, 0x00, 0x00, 0x10]); // Env, command tail at PSP:80
+    fs::write(root_path.join("PARENT.COM"), parent).unwrap();
+
+    let mut cpu = Cpu::new(root_path.clone());
+    assert!(cpu.load_executable("PARENT.COM", None));
+    assert_eq!(cpu.current_psp, 0x1000);
+    let block = Mcb::read(&cpu.bus, 0x0FFF).unwrap();
+    assert_eq!((block.owner, block.end()), (0x1000, 0xA000));
+    assert_eq!(block.name(&cpu.bus), "PARENT");
+
+    for _ in 0..100 {
+        if cpu.state == CpuState::Halted {
+            break;
+        }
+        cpu.step();
+    }
+    assert_eq!(cpu.state, CpuState::Halted);
+    assert!(!cpu.get_cpu_flag(CpuFlags::CF));
+    assert_eq!(cpu.get_reg8(Register::AL), 7);
+    assert_eq!((cpu.cs, cpu.current_psp), (0x1000, 0x1000));
+
+    // Everything the child had is free again
+    let blocks = mcb::chain(&cpu.bus).unwrap();
+    assert_eq!(blocks[0].size, 0x1000);
+    assert!(blocks[1..].iter().all(|b| b.is_free()));
+    assert_eq!(mcb::largest_free(&mut cpu.bus), 0xA000 - 0x2000 - 1);
+
+    fs::remove_dir_all(&root_path).unwrap();
+}
@@ -0,0 +1,223 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use iced_x86::Register;
+use rust_dos::clock::StartTime;
+use rust_dos::cpu::{Cpu, CpuFlags};
+use rust_dos::interrupts::int21;
+use rust_dos::machine::MachineBuilder;
+use rust_dos::nls;
+use rust_dos::shell;
+use rust_dos::video::Font;
+use std::fs;
+use std::path::PathBuf;
+
+const SEGMENT: u16 = 0x4000;
+const BUFFER: u16 = 0x0200;
+
+fn addr(offset: u16) -> usize {
+    ((SEGMENT as usize) << 4) + offset as usize
+}
+
+fn dos_call(cpu: &mut Cpu, ax: u16, bx: u16, cx: u16, dx: u16) -> bool {
+    cpu.ax = ax;
+    cpu.bx = bx;
+    cpu.cx = cx;
+    cpu.dx = dx;
+    cpu.ds = SEGMENT;
+    cpu.es = SEGMENT;
+    cpu.set_reg16(Register::DI, BUFFER);
+    int21::handle(cpu);
+    !cpu.get_cpu_flag(CpuFlags::CF)
+}
+
+fn read_far(cpu: &Cpu, addr: usize) -> usize {
+    let offset = cpu.bus.read_16(addr) as usize;
+    let segment = cpu.bus.read_16(addr + 2) as usize;
+    (segment << 4) + offset
+}
+
+#[test]
+fn test_country_information() {
+    let mut cpu = Cpu::new(PathBuf::from("."));
+
+    // United States: m-d-y, $, 1,234.56, 12-hour clock
+    assert!(dos_call(&mut cpu, 0x3800, 0, 0, BUFFER));
+    assert_eq!(cpu.bx, 1);
+    assert_eq!(cpu.bus.read_16(addr(BUFFER)), 0);
+    assert_eq!(cpu.bus.read_8(addr(BUFFER) + 0x02), b'$');
+    assert_eq!(cpu.bus.read_8(addr(BUFFER) + 0x07), b',');
+    assert_eq!(cpu.bus.read_8(addr(BUFFER) + 0x11), 0);
+
+    // Germany by code, without switching to it
+    assert!(dos_call(&mut cpu, 0x3831, 0, 0, BUFFER));
+    assert_eq!(cpu.bx, 49);
+    assert_eq!(cpu.bus.read_16(addr(BUFFER)), nls::DMY);
+    let currency: Vec<u8> = (2..5).map(|i| cpu.bus.read_8(addr(BUFFER) + i)).collect();
+    assert_eq!(currency, b"DM\0");
+    assert_eq!(cpu.bus.read_8(addr(BUFFER) + 0x0B), b'.');
+    assert_eq!(cpu.bus.nls.country, 1);
+
+    // Set it, then codes above FEh go through BX
+    assert!(dos_call(&mut cpu, 0x3831, 0, 0, 0xFFFF));
+    assert_eq!(cpu.bus.nls.country, 49);
+    assert!(dos_call(&mut cpu, 0x38FF, 358, 0, 0xFFFF));
+    assert_eq!(cpu.bus.nls.country, 358);
+    assert!(!dos_call(&mut cpu, 0x38FF, 999, 0, 0xFFFF));
+    assert_eq!(cpu.ax, 0x02);
+    assert_eq!(cpu.bus.nls.country, 358);
+}
+
+#[test]
+fn test_extended_country_information() {
+    let mut cpu = Cpu::new(PathBuf::from("."));
+
+    // General information: ID, size, country, code page, then the 38h data
+    assert!(dos_call(&mut cpu, 0x6501, 0xFFFF, 41, 0xFFFF));
+    assert_eq!(cpu.cx, 41);
+    assert_eq!(cpu.bus.read_8(addr(BUFFER)), 0x01);
+    assert_eq!(cpu.bus.read_16(addr(BUFFER) + 1), 0x26);
+    assert_eq!(cpu.bus.read_16(addr(BUFFER) + 3), 1);
+    assert_eq!(cpu.bus.read_16(addr(BUFFER) + 5), 437);
+    assert_eq!(cpu.bus.read_8(addr(BUFFER) + 7 + 0x02), b'$');
+
+    // The upper-case table for 80h-FFh: é has a capital in 437, â doesn't
+    assert!(dos_call(&mut cpu, 0x6502, 0xFFFF, 5, 0xFFFF));
+    assert_eq!(cpu.cx, 5);
+    let table = read_far(&cpu, addr(BUFFER) + 1);
+    assert_eq!(cpu.bus.read_16(table), 0x80);
+    assert_eq!(cpu.bus.read_8(table + 2 + 0x02), 0x90);
+    assert_eq!(cpu.bus.read_8(table + 2 + 0x03), b'A');
+
+    // In 850 it does
+    assert!(dos_call(&mut cpu, 0x6502, 850, 5, 0xFFFF));
+    let table = read_far(&cpu, addr(BUFFER) + 1);
+    assert_eq!(cpu.bus.read_8(table + 2 + 0x03), 0xB6);
+
+    // Collating: case and accents sort together
+    assert!(dos_call(&mut cpu, 0x6506, 0xFFFF, 5, 0xFFFF));
+    let table = read_far(&cpu, addr(BUFFER) + 1);
+    assert_eq!(cpu.bus.read_16(table), 0x100);
+    assert_eq!(cpu.bus.read_8(table + 2 + b'e' as usize), b'E');
+    assert_eq!(cpu.bus.read_8(table + 2 + 0x82), b'E');
+
+    // Filename terminators
+    assert!(dos_call(&mut cpu, 0x6505, 0xFFFF, 5, 0xFFFF));
+    let table = read_far(&cpu, addr(BUFFER) + 1);
+    assert_eq!(cpu.bus.read_8(table + 9), 14);
+    assert_eq!(cpu.bus.read_8(table + 10), b'.');
+
+    assert!(!dos_call(&mut cpu, 0x6501, 1252, 41, 0xFFFF));
+    assert_eq!(cpu.ax, 0x02);
+    assert!(!dos_call(&mut cpu, 0x6502, 0xFFFF, 4, 0xFFFF));
+}
+
+#[test]
+fn test_capitalisation_and_yes_no() {
+    let mut cpu = Cpu::new(PathBuf::from("."));
+
+    assert!(dos_call(&mut cpu, 0x6520, 0, 0, 0x0081));
+    assert_eq!(cpu.get_reg8(Register::DL), 0x9A);
+
+    for (i, &b) in b"gr\x81n\0x".iter().enumerate() {
+        cpu.bus.write_8(addr(BUFFER) + i, b);
+    }
+    assert!(dos_call(&mut cpu, 0x6521, 0, 2, BUFFER));
+    assert!(dos_call(&mut cpu, 0x6522, 0, 0, BUFFER + 2));
+    let text: Vec<u8> = (0..6).map(|i| cpu.bus.read_8(addr(BUFFER) + i)).collect();
+    assert_eq!(text, b"GR\x9AN\0x");
+
+    // The country's own yes and no
+    nls::set_country(&mut cpu.bus, 49).unwrap();
+    for (dl, answer) in [(b'j', 1), (b'N', 0), (b'y', 2)] {
+        assert!(dos_call(&mut cpu, 0x6523, 0, 0, dl as u16));
+        assert_eq!(cpu.ax, answer);
+    }
+
+    // The case map routine from the country information is a real far call
+    dos_call(&mut cpu, 0x3800, 0, 0, BUFFER);
+    let routine = cpu.bus.read_32(addr(BUFFER) + 0x12);
+    let [off_lo, off_hi, seg_lo, seg_hi] = routine.to_le_bytes();
+    for (i, b) in [0x9A, off_lo, off_hi, seg_lo, seg_hi].into_iter().enumerate() {
+        cpu.bus.write_8(0x30100 + i, b);
+    }
+    cpu.cs = 0x3000;
+    cpu.ip = 0x0100;
+    cpu.ss = 0x2000;
+    cpu.sp = 0xFFFE;
+    cpu.set_reg8(Register::AL, 0x84);
+    cpu.step();
+    while cpu.cs != 0x3000 {
+        cpu.step();
+    }
+    assert_eq!(cpu.ip, 0x0105);
+    assert_eq!(cpu.get_reg8(Register::AL), 0x8E);
+}
+
+#[test]
+fn test_code_page_switching_loads_fonts() {
+    let font_dir = PathBuf::from("target/test_nls_fonts");
+    fs::create_dir_all(&font_dir).unwrap();
+    fs::write(font_dir.join("CP866.F16"), vec![0x5A; 4096]).unwrap();
+
+    let mut cpu = Cpu::new(PathBuf::from("."));
+    cpu.bus.nls.font_dir = Some(font_dir.clone());
+    let builtin = cpu.bus.vga().font.glyphs_8x16.to_vec();
+
+    assert!(dos_call(&mut cpu, 0x6601, 0, 0, 0));
+    assert_eq!((cpu.bx, cpu.dx), (437, 437));
+    assert!(dos_call(&mut cpu, 0x6602, 866, 0, 0));
+    assert!(dos_call(&mut cpu, 0x6601, 0, 0, 0));
+    assert_eq!((cpu.bx, cpu.dx), (866, 437));
+    assert_eq!(cpu.bus.vga().font.glyphs_8x16[0x100], 0x5A);
+
+    // The 8x8 glyphs weren't in the directory, so they're the bundled ones
+    assert_eq!(
+        cpu.bus.vga().font.glyphs_8x8.to_vec(),
+        Font::bundled(866).unwrap().glyphs_8x8.to_vec()
+    );
+
+    // No font file: the bundled glyphs
+    assert!(dos_call(&mut cpu, 0x6602, 852, 0, 0));
+    let glyphs = cpu.bus.vga().font.glyphs_8x16.to_vec();
+    assert_eq!(glyphs[..0x800], builtin[..0x800]);
+    assert_ne!(glyphs[0x800..], builtin[0x800..]);
+    assert!(dos_call(&mut cpu, 0x6602, 437, 0, 0));
+    assert_eq!(cpu.bus.vga().font.glyphs_8x16.to_vec(), builtin);
+    assert!(dos_call(&mut cpu, 0x6602, 852, 0, 0));
+    assert!(!dos_call(&mut cpu, 0x6602, 1252, 0, 0));
+    assert_eq!(cpu.ax, 0x02);
+    assert_eq!(cpu.bus.nls.code_page, 852);
+
+    fs::remove_dir_all(&font_dir).unwrap();
+}
+
+#[test]
+fn test_shell_follows_the_country() {
+    let start = NaiveDate::from_ymd_opt(1998, 3, 1)
+        .unwrap()
+        .and_hms_opt(8, 30, 0)
+        .unwrap();
+    let mut machine = MachineBuilder::new(".")
+        .country(49)
+        .start_time(StartTime::Fixed(start))
+        .build();
+    assert_eq!(machine.cpu.bus.nls.code_page, 850);
+
+    let cpu = &mut machine.cpu;
+    cpu.pending_command = Some("DATE".to_string());
+    shell::run_pending_command(cpu);
+    assert!(machine.screen_text().contains("Current date is Sun 01.03.1998"));
+
+    let cpu = &mut machine.cpu;
+    cpu.pending_command = Some("DATE 31.12.99".to_string());
+    shell::run_pending_command(cpu);
+    cpu.pending_command = Some("TIME 23:15:02,50".to_string());
+    shell::run_pending_command(cpu);
+    let expected: NaiveDateTime = NaiveDate::from_ymd_opt(1999, 12, 31)
+        .unwrap()
+        .and_hms_milli_opt(23, 15, 2, 500)
+        .unwrap();
+    assert_eq!(cpu.bus.clock().now(), expected);
+    cpu.pending_command = Some("TIME".to_string());
+    shell::run_pending_command(cpu);
+    assert!(machine.screen_text().contains("Current time is 23:15:02,50"));
+}
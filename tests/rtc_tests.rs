@@ -0,0 +1,201 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use rust_dos::bus::{Bus, PIT_FREQUENCY};
+use rust_dos::clock::{self, StartTime};
+use rust_dos::cpu::{Cpu, CpuFlags};
+use rust_dos::devices::rtc::{REG_A, REG_B, REG_C, REG_D};
+use rust_dos::interrupts::int1a;
+use rust_dos::machine::MachineBuilder;
+use std::fs;
+use std::path::PathBuf;
+
+fn at(y: i32, mo: u32, d: u32, h: u32, mi: u32, s: u32) -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(y, mo, d)
+        .unwrap()
+        .and_hms_opt(h, mi, s)
+        .unwrap()
+}
+
+fn cpu_at(start: NaiveDateTime) -> Cpu {
+    let mut cpu = Cpu::new(PathBuf::from("."));
+    clock::start(&mut cpu.bus, StartTime::Fixed(start));
+    cpu
+}
+
+fn cmos_read(bus: &mut Bus, index: u8) -> u8 {
+    bus.io_write(0x70, index);
+    bus.io_read(0x71)
+}
+
+fn cmos_write(bus: &mut Bus, index: u8, value: u8) {
+    bus.io_write(0x70, index);
+    bus.io_write(0x71, value);
+}
+
+fn irq8_pending(bus: &Bus) -> bool {
+    bus.pic().pending & 0x0100 != 0
+}
+
+#[test]
+fn test_time_registers() {
+    let mut cpu = cpu_at(at(2024, 2, 29, 13, 5, 9));
+    let bus = &mut cpu.bus;
+
+    // BCD, 24-hour: Thursday is day 5
+    let time: Vec<u8> = [0x00, 0x02, 0x04, 0x06, 0x07, 0x08, 0x09, 0x32]
+        .iter()
+        .map(|&i| cmos_read(bus, i))
+        .collect();
+    assert_eq!(time, [0x09, 0x05, 0x13, 0x05, 0x29, 0x02, 0x24, 0x20]);
+    assert_eq!(cmos_read(bus, REG_D as u8), 0x80);
+
+    // Binary, 12-hour: 1 PM
+    cmos_write(bus, REG_B as u8, 0x04);
+    assert_eq!(cmos_read(bus, 0x04), 0x81);
+    assert_eq!(cmos_read(bus, 0x07), 29);
+
+    // SET freezes the clock while the registers are written
+    cmos_write(bus, REG_B as u8, 0x86);
+    for (index, value) in [(0x04, 23), (0x02, 59), (0x00, 58), (0x07, 28), (0x09, 99), (0x32, 19)] {
+        cmos_write(bus, index, value);
+    }
+    bus.tick_devices(5 * PIT_FREQUENCY, PIT_FREQUENCY);
+    assert_eq!(bus.clock().now(), at(2024, 2, 29, 13, 5, 9));
+    cmos_write(bus, REG_B as u8, 0x06);
+    assert_eq!(bus.clock().now(), at(1999, 2, 28, 23, 59, 58));
+
+    // Running writes take effect at once
+    cmos_write(bus, 0x09, 96);
+    assert_eq!(bus.clock().now(), at(1996, 2, 28, 23, 59, 58));
+}
+
+#[test]
+fn test_update_in_progress_and_update_interrupt() {
+    let mut cpu = cpu_at(at(2000, 1, 1, 0, 0, 0));
+    let bus = &mut cpu.bus;
+
+    assert_eq!(cmos_read(bus, REG_A as u8), 0x26);
+    bus.tick_devices(PIT_FREQUENCY - 100, PIT_FREQUENCY);
+    assert_eq!(cmos_read(bus, REG_A as u8), 0xA6);
+
+    // The update ends with UF, which only interrupts when UIE allows it.
+    // PF keeps time at 1024 Hz all along.
+    bus.tick_devices(100, PIT_FREQUENCY);
+    assert_eq!(cmos_read(bus, REG_A as u8), 0x26);
+    assert_eq!(cmos_read(bus, 0x00), 0x01);
+    assert!(!irq8_pending(bus));
+    assert_eq!(cmos_read(bus, REG_C as u8), 0x50);
+    assert_eq!(cmos_read(bus, REG_C as u8), 0x00);
+
+    cmos_write(bus, REG_B as u8, 0x12);
+    bus.tick_devices(PIT_FREQUENCY, PIT_FREQUENCY);
+    assert!(irq8_pending(bus));
+    assert_eq!(cmos_read(bus, REG_C as u8), 0xD0);
+}
+
+#[test]
+fn test_periodic_interrupt_through_int70() {
+    let mut cpu = cpu_at(at(2000, 1, 1, 0, 0, 0));
+    cpu.ss = 0x2000;
+    cpu.sp = 0xFFFE;
+    cpu.cs = 0x3000;
+    cpu.ip = 0x0100;
+    cpu.bus.write_8(0x30100, 0x90); // NOP
+
+    // 1024 Hz
+    cmos_write(&mut cpu.bus, REG_B as u8, 0x42);
+    cpu.bus.tick_devices(1100, PIT_FREQUENCY);
+    assert!(!irq8_pending(&cpu.bus));
+    cpu.bus.tick_devices(66, PIT_FREQUENCY);
+    assert!(irq8_pending(&cpu.bus));
+
+    // The slave is masked on its own and through the master's IRQ2
+    cpu.set_cpu_flag(CpuFlags::IF, true);
+    cpu.bus.pic_mut().pending &= 0xFF00;
+    cpu.bus.pic_mut().slave_mask = 0x01;
+    assert!(!cpu.service_irqs());
+    cpu.bus.pic_mut().slave_mask = 0x00;
+    cpu.bus.pic_mut().mask = 0x04;
+    assert!(!cpu.service_irqs());
+    cpu.bus.pic_mut().mask = 0x00;
+
+    // The BIOS handler reads register C, which lets the next period interrupt
+    assert!(cpu.service_irqs());
+    assert_eq!((cpu.cs, cpu.ip), (0xF000, 0x0F10));
+    for _ in 0..20 {
+        if cpu.cs != 0xF000 {
+            break;
+        }
+        cpu.step();
+    }
+    assert_eq!((cpu.cs, cpu.ip), (0x3000, 0x0100));
+    assert_eq!(cmos_read(&mut cpu.bus, REG_C as u8), 0x00);
+    cpu.bus.pic_mut().pending = 0;
+    cpu.bus.tick_devices(PIT_FREQUENCY / 1024, PIT_FREQUENCY);
+    assert!(irq8_pending(&cpu.bus));
+}
+
+#[test]
+fn test_bios_alarm() {
+    let mut cpu = cpu_at(at(2000, 1, 1, 6, 29, 58));
+    cpu.bus.pic_mut().slave_mask = 0xFF;
+
+    // 06:30:00
+    cpu.ax = 0x0600;
+    cpu.cx = 0x0630;
+    cpu.dx = 0x0000;
+    int1a::handle(&mut cpu);
+    assert!(!cpu.get_cpu_flag(CpuFlags::CF));
+    assert_eq!(cmos_read(&mut cpu.bus, 0x03), 0x30);
+    assert_eq!(cpu.bus.pic().slave_mask, 0xFE);
+
+    cpu.bus.tick_devices(PIT_FREQUENCY, PIT_FREQUENCY);
+    cmos_read(&mut cpu.bus, REG_C as u8);
+    assert!(!irq8_pending(&cpu.bus));
+    cpu.bus.tick_devices(PIT_FREQUENCY, PIT_FREQUENCY);
+    assert!(irq8_pending(&cpu.bus));
+    assert_eq!(cmos_read(&mut cpu.bus, REG_C as u8), 0xF0);
+
+    // Don't-care bytes: every second of every minute
+    cmos_write(&mut cpu.bus, 0x01, 0xC0);
+    cmos_write(&mut cpu.bus, 0x03, 0xFF);
+    cpu.bus.tick_devices(PIT_FREQUENCY, PIT_FREQUENCY);
+    assert_eq!(cmos_read(&mut cpu.bus, REG_C as u8), 0xF0);
+
+    // Flagged but no longer interrupting
+    cpu.ax = 0x0700;
+    int1a::handle(&mut cpu);
+    cpu.bus.tick_devices(PIT_FREQUENCY, PIT_FREQUENCY);
+    assert_eq!(cmos_read(&mut cpu.bus, REG_C as u8), 0x70);
+}
+
+#[test]
+fn test_nvram_configuration_and_persistence() {
+    let path = PathBuf::from("target/test_nvram.bin");
+    let _ = fs::remove_file(&path);
+
+    let mut machine = MachineBuilder::new(".")
+        .memory_kb(512)
+        .extended_kb(1024)
+        .nvram_file(&path)
+        .build();
+    let bus = &mut machine.cpu.bus;
+    assert_eq!(cmos_read(bus, 0x14), 0x03); // Floppy and FPU
+    assert_eq!(cmos_read(bus, 0x15), 0x00);
+    assert_eq!(cmos_read(bus, 0x16), 0x02);
+    assert_eq!(cmos_read(bus, 0x17), 0x00);
+    assert_eq!(cmos_read(bus, 0x18), 0x04);
+    assert_eq!(cmos_read(bus, 0x31), 0x04);
+    let sum: u16 = (0x10..0x2E).map(|i| cmos_read(bus, i) as u16).sum();
+    let checksum = u16::from_be_bytes([cmos_read(bus, 0x2E), cmos_read(bus, 0x2F)]);
+    assert_eq!(checksum, sum);
+
+    // User bytes outlive the machine
+    cmos_write(bus, 0x40, 0x5A);
+    assert_eq!(fs::read(&path).unwrap()[0x40], 0x5A);
+    let mut machine = MachineBuilder::new(".").nvram_file(&path).build();
+    assert_eq!(cmos_read(&mut machine.cpu.bus, 0x40), 0x5A);
+    // POST records the new memory size
+    assert_eq!(cmos_read(&mut machine.cpu.bus, 0x15), 0x80);
+
+    fs::remove_file(&path).unwrap();
+}
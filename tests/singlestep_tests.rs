@@ -0,0 +1,476 @@
+// Conformance harness for the SingleStepTests per-opcode JSON suites
+// (https://github.com/SingleStepTests/8088, https://github.com/SingleStepTests/80286).
+//
+// Each vector gives the initial registers and RAM, the instruction bytes and the
+// expected final state. We load the initial state, execute one instruction and
+// diff registers, flags and memory.
+//
+// The full suite is not shipped with the repo. Point the harness at a local copy:
+//
+//   SINGLESTEP_DIR=/path/to/8088/v2 cargo test --test singlestep_tests -- --nocapture
+//
+// Options (environment variables):
+//   SINGLESTEP_IGNORE_UNDEFINED_FLAGS=1  mask flags the metadata marks as undefined
+//   SINGLESTEP_FILTER=D2                 only run files whose name starts with this
+//   SINGLESTEP_LIMIT=1000                stop each file after this many vectors
+//   SINGLESTEP_CPU=286                   CPU model to run as; guessed from the path
+//                                        (80286 suites contain "286"), else 8088
+//
+// Files may be plain `.json` or gzipped `.json.gz`. A `metadata.json` next to them
+// supplies the per-opcode `flags-mask`.
+
+use flate2::read::GzDecoder;
+use rust_dos::cpu::{Cpu, CpuFlags, CpuModel};
+use rust_dos::instructions::execute_instruction;
+use rust_dos::memory::Region;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+// Flags that exist on the 8086. The upper nibble is hardwired on real chips
+// and we don't model it.
+const ARCH_FLAGS: u16 = 0x0FD5;
+const RAM_SIZE: u64 = 1024 * 1024;
+const REGS: [&str; 13] = [
+    "ax", "bx", "cx", "dx", "cs", "ss", "ds", "es", "sp", "bp", "si", "di", "ip",
+];
+
+#[derive(Default)]
+struct OpcodeResult {
+    passed: usize,
+    failed: usize,
+    skipped: usize,
+    first_failure: Option<String>,
+}
+
+struct Harness {
+    cpu: Cpu,
+    ignore_undefined_flags: bool,
+    metadata: Value,
+}
+
+impl Harness {
+    fn new(model: CpuModel, metadata: Value, ignore_undefined_flags: bool) -> Self {
+        let mut cpu = Cpu::new(PathBuf::from("."));
+        cpu.model = model;
+        // The vectors assume plain RAM everywhere: no ROM, no video memory
+        cpu.bus.memory.map(0, RAM_SIZE as usize, Region::Ram);
+        Self {
+            cpu,
+            ignore_undefined_flags,
+            metadata,
+        }
+    }
+
+    // Defined-flags mask for an opcode key like "00" or "D2.4"
+    fn flags_mask(&self, opcode: &str) -> u16 {
+        if !self.ignore_undefined_flags {
+            return ARCH_FLAGS;
+        }
+        let mut parts = opcode.split('.');
+        let op = parts.next().unwrap_or("").to_ascii_uppercase();
+        let mut entry = &self.metadata["opcodes"][op.as_str()];
+        if let Some(reg) = parts.next() {
+            entry = &entry["reg"][reg];
+        }
+        entry["flags-mask"]
+            .as_u64()
+            .map(|m| m as u16 & ARCH_FLAGS)
+            .unwrap_or(ARCH_FLAGS)
+    }
+
+    fn set_reg(&mut self, name: &str, value: u16) {
+        let cpu = &mut self.cpu;
+        match name {
+            "ax" => cpu.ax = value,
+            "bx" => cpu.bx = value,
+            "cx" => cpu.cx = value,
+            "dx" => cpu.dx = value,
+            "cs" => cpu.cs = value,
+            "ss" => cpu.ss = value,
+            "ds" => cpu.ds = value,
+            "es" => cpu.es = value,
+            "sp" => cpu.sp = value,
+            "bp" => cpu.bp = value,
+            "si" => cpu.si = value,
+            "di" => cpu.di = value,
+            "ip" => cpu.ip = value,
+            "flags" => cpu.set_cpu_flags(CpuFlags::from_bits_truncate(value)),
+            _ => {}
+        }
+    }
+
+    fn get_reg(&self, name: &str) -> u16 {
+        let cpu = &self.cpu;
+        match name {
+            "ax" => cpu.ax,
+            "bx" => cpu.bx,
+            "cx" => cpu.cx,
+            "dx" => cpu.dx,
+            "cs" => cpu.cs,
+            "ss" => cpu.ss,
+            "ds" => cpu.ds,
+            "es" => cpu.es,
+            "sp" => cpu.sp,
+            "bp" => cpu.bp,
+            "si" => cpu.si,
+            "di" => cpu.di,
+            "ip" => cpu.ip,
+            "flags" => cpu.get_cpu_flags().bits(),
+            _ => 0,
+        }
+    }
+
+    // Runs one vector. Ok(true) = pass, Ok(false) = skipped, Err = mismatch description.
+    fn run(&mut self, opcode: &str, test: &Value) -> Result<bool, String> {
+        let initial = &test["initial"];
+        let expected = &test["final"];
+
+        let ram_entries = |state: &Value| -> Vec<(u64, u8)> {
+            state["ram"]
+                .as_array()
+                .map(|a| {
+                    a.iter()
+                        .filter_map(|e| Some((e[0].as_u64()?, e[1].as_u64()? as u8)))
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        let initial_ram = ram_entries(initial);
+        let final_ram = ram_entries(expected);
+
+        // The 80286 suite can touch memory above 1 MB
+        if initial_ram
+            .iter()
+            .chain(final_ram.iter())
+            .any(|(addr, _)| *addr >= RAM_SIZE)
+        {
+            return Ok(false);
+        }
+
+        // Load state
+        for (addr, value) in &initial_ram {
+            self.cpu.bus.write_rom(*addr as usize, &[*value]);
+        }
+        let regs = &initial["regs"];
+        for name in REGS.iter().chain(["flags"].iter()) {
+            if let Some(v) = regs[*name].as_u64() {
+                self.set_reg(name, v as u16);
+            }
+        }
+
+        // Just the instruction: no interrupts or BOP traps around it
+        let cpu = &mut self.cpu;
+        let phys_ip = cpu.get_physical_addr(cpu.cs, cpu.ip);
+        let instr = cpu.bus.fetch_instruction(phys_ip, cpu.ip);
+        cpu.ip = instr.next_ip() as u16;
+        execute_instruction(cpu, &instr);
+
+        // Compare
+        let mut errors = Vec::new();
+        let final_regs = &expected["regs"];
+        for name in REGS {
+            let want = final_regs[name]
+                .as_u64()
+                .or_else(|| regs[name].as_u64())
+                .unwrap_or(0) as u16;
+            let got = self.get_reg(name);
+            if got != want {
+                errors.push(format!("{}={:04X} (want {:04X})", name, got, want));
+            }
+        }
+
+        let mask = self.flags_mask(opcode);
+        let want_flags = final_regs["flags"]
+            .as_u64()
+            .or_else(|| regs["flags"].as_u64())
+            .unwrap_or(0) as u16;
+        let got_flags = self.get_reg("flags");
+        if got_flags & mask != want_flags & mask {
+            errors.push(format!(
+                "flags={:04X} (want {:04X}, mask {:04X})",
+                got_flags & mask,
+                want_flags & mask,
+                mask
+            ));
+        }
+
+        for (addr, want) in &final_ram {
+            let got = self.cpu.bus.ram[*addr as usize];
+            if got != *want {
+                errors.push(format!("[{:05X}]={:02X} (want {:02X})", addr, got, want));
+            }
+        }
+
+        // Clear the bytes this vector touched before the next one
+        for (addr, _) in initial_ram.iter().chain(final_ram.iter()) {
+            self.cpu.bus.write_rom(*addr as usize, &[0]);
+        }
+
+        if errors.is_empty() {
+            Ok(true)
+        } else {
+            let name = test["name"].as_str().unwrap_or("?");
+            Err(format!("{}: {}", name, errors.join(", ")))
+        }
+    }
+
+    fn run_vectors(&mut self, opcode: &str, tests: &[Value], limit: usize) -> OpcodeResult {
+        let mut result = OpcodeResult::default();
+        for test in tests.iter().take(limit) {
+            match self.run(opcode, test) {
+                Ok(true) => result.passed += 1,
+                Ok(false) => result.skipped += 1,
+                Err(msg) => {
+                    result.failed += 1;
+                    if result.first_failure.is_none() {
+                        result.first_failure = Some(msg);
+                    }
+                }
+            }
+        }
+        result
+    }
+}
+
+fn read_json(path: &Path) -> Option<Value> {
+    let bytes = std::fs::read(path).ok()?;
+    let text = if path.extension().is_some_and(|e| e == "gz") {
+        let mut s = String::new();
+        GzDecoder::new(&bytes[..]).read_to_string(&mut s).ok()?;
+        s
+    } else {
+        String::from_utf8(bytes).ok()?
+    };
+    serde_json::from_str(&text).ok()
+}
+
+// "D2.4.json.gz" -> "D2.4"
+fn opcode_key(path: &Path) -> Option<String> {
+    let name = path.file_name()?.to_str()?;
+    let stem = name.strip_suffix(".gz").unwrap_or(name);
+    Some(stem.strip_suffix(".json")?.to_string())
+}
+
+// The suites live in directories named after the chip
+fn suite_model(dir: &Path) -> CpuModel {
+    if dir.to_string_lossy().contains("286") {
+        CpuModel::I80286
+    } else {
+        CpuModel::I8088
+    }
+}
+
+fn print_report(results: &BTreeMap<String, OpcodeResult>) {
+    let (mut passed, mut total) = (0, 0);
+    println!(
+        "{:<8} {:>8} {:>8} {:>8}",
+        "opcode", "passed", "total", "rate"
+    );
+    for (opcode, r) in results {
+        let run = r.passed + r.failed;
+        passed += r.passed;
+        total += run;
+        let rate = if run > 0 {
+            100.0 * r.passed as f64 / run as f64
+        } else {
+            0.0
+        };
+        println!("{:<8} {:>8} {:>8} {:>7.2}%", opcode, r.passed, run, rate);
+        if let Some(msg) = &r.first_failure {
+            println!("         first failure: {}", msg);
+        }
+        if r.skipped > 0 {
+            println!("         skipped: {}", r.skipped);
+        }
+    }
+    if total > 0 {
+        println!(
+            "TOTAL    {:>8} {:>8} {:>7.2}%",
+            passed,
+            total,
+            100.0 * passed as f64 / total as f64
+        );
+    }
+}
+
+#[test]
+fn test_singlestep_suite() {
+    let Ok(dir) = std::env::var("SINGLESTEP_DIR") else {
+        println!("SINGLESTEP_DIR not set, skipping SingleStepTests conformance run");
+        return;
+    };
+    let dir = PathBuf::from(dir);
+    let ignore_undefined =
+        std::env::var("SINGLESTEP_IGNORE_UNDEFINED_FLAGS").is_ok_and(|v| v != "0");
+    let filter = std::env::var("SINGLESTEP_FILTER")
+        .unwrap_or_default()
+        .to_ascii_uppercase();
+    let limit = std::env::var("SINGLESTEP_LIMIT")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(usize::MAX);
+
+    let model = match std::env::var("SINGLESTEP_CPU") {
+        Ok(model) => model.parse().expect("Bad SINGLESTEP_CPU"),
+        Err(_) => suite_model(&dir),
+    };
+
+    let metadata = read_json(&dir.join("metadata.json")).unwrap_or(Value::Null);
+    let mut harness = Harness::new(model, metadata, ignore_undefined);
+
+    let mut files: Vec<PathBuf> = std::fs::read_dir(&dir)
+        .expect("Cannot read SINGLESTEP_DIR")
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| {
+            opcode_key(p)
+                .is_some_and(|k| k != "metadata" && k.to_ascii_uppercase().starts_with(&filter))
+        })
+        .collect();
+    files.sort();
+    assert!(!files.is_empty(), "No test vectors found in {:?}", dir);
+
+    let mut results = BTreeMap::new();
+    for path in files {
+        let key = opcode_key(&path).unwrap();
+        let Some(Value::Array(tests)) = read_json(&path) else {
+            println!("Could not parse {:?}", path);
+            continue;
+        };
+        results.insert(key.clone(), harness.run_vectors(&key, &tests, limit));
+    }
+
+    print_report(&results);
+}
+
+// A few vectors in the suite's format, to keep the harness itself honest
+const SAMPLE_VECTORS: &str = r#"[
+    {
+        "name": "add al, 01h",
+        "bytes": [4, 1],
+        "initial": {
+            "regs": {"ax": 255, "bx": 0, "cx": 0, "dx": 0, "cs": 4096, "ss": 8192, "ds": 12288, "es": 12288,
+                     "sp": 256, "bp": 0, "si": 0, "di": 0, "ip": 256, "flags": 61442},
+            "ram": [[65792, 4], [65793, 1]],
+            "queue": []
+        },
+        "final": {
+            "regs": {"ax": 0, "ip": 258, "flags": 61527},
+            "ram": [[65792, 4], [65793, 1]],
+            "queue": []
+        }
+    },
+    {
+        "name": "mov word [bx], ax",
+        "bytes": [137, 7],
+        "initial": {
+            "regs": {"ax": 4660, "bx": 16, "cx": 0, "dx": 0, "cs": 4096, "ss": 8192, "ds": 12288, "es": 12288,
+                     "sp": 256, "bp": 0, "si": 0, "di": 0, "ip": 256, "flags": 61442},
+            "ram": [[65792, 137], [65793, 7], [196624, 0], [196625, 0]],
+            "queue": []
+        },
+        "final": {
+            "regs": {"ip": 258},
+            "ram": [[65792, 137], [65793, 7], [196624, 52], [196625, 18]],
+            "queue": []
+        }
+    }
+]"#;
+
+// SHL AL, CL leaves AF undefined. This vector expects AF=1, which we don't produce.
+const UNDEFINED_FLAG_VECTOR: &str = r#"[
+    {
+        "name": "shl al, cl",
+        "bytes": [210, 224],
+        "initial": {
+            "regs": {"ax": 1, "bx": 0, "cx": 1, "dx": 0, "cs": 4096, "ss": 8192, "ds": 12288, "es": 12288,
+                     "sp": 256, "bp": 0, "si": 0, "di": 0, "ip": 256, "flags": 61442},
+            "ram": [[65792, 210], [65793, 224]],
+            "queue": []
+        },
+        "final": {
+            "regs": {"ax": 2, "ip": 258, "flags": 61458},
+            "ram": [[65792, 210], [65793, 224]],
+            "queue": []
+        }
+    }
+]"#;
+
+const SAMPLE_METADATA: &str =
+    r#"{"opcodes": {"D2": {"reg": {"4": {"status": "normal", "flags-mask": 65519}}}}}"#;
+
+#[test]
+fn test_singlestep_harness_sample_vectors() {
+    let tests: Vec<Value> = serde_json::from_str(SAMPLE_VECTORS).unwrap();
+    let mut harness = Harness::new(CpuModel::I8088, Value::Null, false);
+    let result = harness.run_vectors("sample", &tests, usize::MAX);
+    assert_eq!(result.passed, 2, "{:?}", result.first_failure);
+    assert_eq!(result.failed, 0);
+}
+
+#[test]
+fn test_singlestep_harness_ignores_undefined_flags() {
+    let tests: Vec<Value> = serde_json::from_str(UNDEFINED_FLAG_VECTOR).unwrap();
+    let metadata: Value = serde_json::from_str(SAMPLE_METADATA).unwrap();
+
+    let mut strict = Harness::new(CpuModel::I8088, metadata.clone(), false);
+    let result = strict.run_vectors("D2.4", &tests, usize::MAX);
+    assert_eq!(
+        result.failed, 1,
+        "AF difference should be reported in strict mode"
+    );
+
+    let mut lenient = Harness::new(CpuModel::I8088, metadata, true);
+    let result = lenient.run_vectors("D2.4", &tests, usize::MAX);
+    assert_eq!(result.passed, 1, "{:?}", result.first_failure);
+}
+
+#[test]
+fn test_singlestep_suite_cpu() {
+    assert_eq!(suite_model(Path::new("tests/80286/v1_real_mode")), CpuModel::I80286);
+    assert_eq!(suite_model(Path::new("tests/8088/v2")), CpuModel::I8088);
+    let harness = Harness::new(CpuModel::I80286, Value::Null, false);
+    assert_eq!(harness.cpu.model, CpuModel::I80286);
+}
+
+#[test]
+fn test_singlestep_harness_loads_rom_and_video_memory() {
+    // MOV AL, [BX] with the byte in the BIOS ROM area, then MOV [DI], AL into video memory
+    let vector = r#"[
+        {
+            "name": "mov al, [bx]",
+            "bytes": [138, 7],
+            "initial": {
+                "regs": {"ax": 0, "bx": 0, "cx": 0, "dx": 0, "cs": 61440, "ss": 8192, "ds": 61440, "es": 40960,
+                         "sp": 256, "bp": 0, "si": 0, "di": 0, "ip": 256, "flags": 61442},
+                "ram": [[983296, 138], [983297, 7], [983040, 90]],
+                "queue": []
+            },
+            "final": {
+                "regs": {"ax": 90, "ip": 258},
+                "ram": [[983296, 138], [983297, 7], [983040, 90]],
+                "queue": []
+            }
+        },
+        {
+            "name": "mov [di], al",
+            "bytes": [38, 136, 5],
+            "initial": {
+                "regs": {"ax": 165, "bx": 0, "cx": 0, "dx": 0, "cs": 4096, "ss": 8192, "ds": 12288, "es": 40960,
+                         "sp": 256, "bp": 0, "si": 0, "di": 16, "ip": 256, "flags": 61442},
+                "ram": [[65792, 38], [65793, 136], [65794, 5], [655376, 0]],
+                "queue": []
+            },
+            "final": {
+                "regs": {"ip": 259},
+                "ram": [[65792, 38], [65793, 136], [65794, 5], [655376, 165]],
+                "queue": []
+            }
+        }
+    ]"#;
+    let tests: Vec<Value> = serde_json::from_str(vector).unwrap();
+    let mut harness = Harness::new(CpuModel::I8088, Value::Null, false);
+    let result = harness.run_vectors("8A", &tests, usize::MAX);
+    assert_eq!(result.passed, 2, "{:?}", result.first_failure);
+}
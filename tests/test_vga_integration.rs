@@ -57,8 +57,8 @@ fn test_vga_initialization() {
             for col in 0..80 {
                 let offset = (row * 80 + col) * 2;
                 // vram_text stores Char, Attribute pairs.
-                if offset < cpu.bus.vga.vram_text.len() {
-                    let char_code = cpu.bus.vga.vram_text[offset];
+                if offset < cpu.bus.vga().vram_text.len() {
+                    let char_code = cpu.bus.vga().vram_text[offset];
                     let c = if char_code >= 32 && char_code <= 126 {
                         char_code as char
                     } else {
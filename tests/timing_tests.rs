@@ -0,0 +1,143 @@
+use rust_dos::bus::PIT_FREQUENCY;
+use rust_dos::cpu::{ClockSpeed, Cpu, CpuFlags, CpuModel, CpuState};
+use std::path::PathBuf;
+
+// Places code at 1000:0100 and returns a CPU of the given model ready to run it
+fn setup(model: CpuModel, code: &[u8]) -> Cpu {
+    let mut cpu = Cpu::new(PathBuf::from("."));
+    cpu.model = model;
+    cpu.clock = ClockSpeed::Hz(model.nominal_hz());
+    cpu.cs = 0x1000;
+    cpu.ds = 0x2000;
+    cpu.es = 0x3000;
+    cpu.ss = 0x4000;
+    cpu.sp = 0xFFFE;
+    cpu.ip = 0x100;
+    let base = cpu.get_physical_addr(cpu.cs, cpu.ip);
+    for (i, &b) in code.iter().enumerate() {
+        cpu.bus.write_8(base + i, b);
+    }
+    cpu
+}
+
+// Runs one instruction and returns the cycles it was charged
+fn step_cycles(cpu: &mut Cpu) -> u64 {
+    let before = cpu.cycles;
+    cpu.step();
+    cpu.cycles - before
+}
+
+#[test]
+fn test_register_and_memory_forms_8086() {
+    // MOV AX, BX
+    let mut cpu = setup(CpuModel::I8086, &[0x89, 0xD8]);
+    assert_eq!(step_cycles(&mut cpu), 2);
+
+    // MOV AX, [BX+SI] -> 8 + EA(7)
+    let mut cpu = setup(CpuModel::I8086, &[0x8B, 0x00]);
+    assert_eq!(step_cycles(&mut cpu), 15);
+
+    // ADD [BX+DI+12h], AX -> 16 + EA(12)
+    let mut cpu = setup(CpuModel::I8086, &[0x01, 0x41, 0x12]);
+    assert_eq!(step_cycles(&mut cpu), 28);
+
+    // ES: MOV AX, [BX] -> 8 + EA(5) + override(2)
+    let mut cpu = setup(CpuModel::I8086, &[0x26, 0x8B, 0x07]);
+    assert_eq!(step_cycles(&mut cpu), 15);
+}
+
+#[test]
+fn test_8088_word_penalty() {
+    // MOV AX, [BX+SI]: 8086 = 15, 8088 pays 4 more for the second bus cycle
+    let mut cpu = setup(CpuModel::I8088, &[0x8B, 0x00]);
+    assert_eq!(step_cycles(&mut cpu), 19);
+
+    // MOV AL, [BX+SI]: byte access has no penalty
+    let mut cpu = setup(CpuModel::I8088, &[0x8A, 0x00]);
+    assert_eq!(step_cycles(&mut cpu), 15);
+}
+
+#[test]
+fn test_later_models_are_faster() {
+    // MOV AX, [BX+SI]
+    let mut cpu = setup(CpuModel::I80286, &[0x8B, 0x00]);
+    assert_eq!(step_cycles(&mut cpu), 5);
+
+    let mut cpu = setup(CpuModel::I80386, &[0x8B, 0x00]);
+    assert_eq!(step_cycles(&mut cpu), 4);
+}
+
+#[test]
+fn test_conditional_branch_timing() {
+    // JZ +2 with ZF set (taken)
+    let mut cpu = setup(CpuModel::I8086, &[0x74, 0x02]);
+    cpu.set_cpu_flag(CpuFlags::ZF, true);
+    assert_eq!(step_cycles(&mut cpu), 16);
+    assert_eq!(cpu.ip, 0x104);
+
+    // JZ +2 with ZF clear (not taken)
+    let mut cpu = setup(CpuModel::I8086, &[0x74, 0x02]);
+    cpu.set_cpu_flag(CpuFlags::ZF, false);
+    assert_eq!(step_cycles(&mut cpu), 4);
+    assert_eq!(cpu.ip, 0x102);
+}
+
+#[test]
+fn test_rep_movsb_scales_with_count() {
+    // REP MOVSB, CX = 10 -> 9 + 17 * 10
+    let mut cpu = setup(CpuModel::I8086, &[0xF3, 0xA4]);
+    cpu.cx = 10;
+    assert_eq!(step_cycles(&mut cpu), 179);
+    assert_eq!(cpu.cx, 0);
+}
+
+#[test]
+fn test_clock_speed_parsing() {
+    assert_eq!("4.77".parse::<ClockSpeed>(), Ok(ClockSpeed::Hz(4_772_727)));
+    assert_eq!("8".parse::<ClockSpeed>(), Ok(ClockSpeed::Hz(8_000_000)));
+    assert_eq!("33".parse::<ClockSpeed>(), Ok(ClockSpeed::Hz(33_000_000)));
+    assert_eq!("max".parse::<ClockSpeed>(), Ok(ClockSpeed::Max));
+    assert!("fast".parse::<ClockSpeed>().is_err());
+
+    assert_eq!("286".parse::<CpuModel>(), Ok(CpuModel::I80286));
+    assert_eq!("8088".parse::<CpuModel>(), Ok(CpuModel::I8088));
+    assert!("68000".parse::<CpuModel>().is_err());
+}
+
+#[test]
+fn test_pit_raises_irq0_from_cycles() {
+    let mut cpu = Cpu::new(PathBuf::from("."));
+    cpu.model = CpuModel::I8088;
+    cpu.clock = ClockSpeed::Hz(PIT_FREQUENCY);
+
+    // At 1.193182 MHz one CPU cycle is one PIT tick. Default divisor is 0xFFFF.
+    cpu.add_cycles(0xFFFE);
+    assert_eq!(cpu.bus.pic().pending & 1, 0);
+    cpu.add_cycles(1);
+    assert_eq!(cpu.bus.pic().pending & 1, 1);
+}
+
+#[test]
+fn test_irq_delivery_respects_if_and_wakes_halt() {
+    let mut cpu = setup(CpuModel::I8086, &[0xF4]); // HLT
+    cpu.bus.write_16(0x08 * 4, 0x1234);
+    cpu.bus.write_16(0x08 * 4 + 2, 0x5000);
+
+    cpu.step();
+    assert_eq!(cpu.state, CpuState::Halted);
+
+    // IF clear: the request stays pending
+    cpu.set_cpu_flag(CpuFlags::IF, false);
+    cpu.bus.pic_mut().pending = 0x01;
+    assert!(!cpu.service_irqs());
+    assert_eq!(cpu.state, CpuState::Halted);
+
+    // IF set: vector through INT 08h and resume
+    cpu.set_cpu_flag(CpuFlags::IF, true);
+    assert!(cpu.service_irqs());
+    assert_eq!(cpu.state, CpuState::Running);
+    assert_eq!(cpu.cs, 0x5000);
+    assert_eq!(cpu.ip, 0x1234);
+    assert_eq!(cpu.bus.pic().pending, 0);
+    assert!(!cpu.get_cpu_flag(CpuFlags::IF));
+}
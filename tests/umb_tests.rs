@@ -0,0 +1,226 @@
+use iced_x86::Register;
+use rust_dos::cpu::{Cpu, CpuFlags, CpuState};
+use rust_dos::interrupts::{int21, int2f};
+use rust_dos::mcb::{self, Mcb};
+use rust_dos::shell;
+use rust_dos::xms;
+use std::fs;
+use std::path::PathBuf;
+
+fn umb_cpu(root: PathBuf) -> Cpu {
+    let mut cpu = Cpu::new(root);
+    cpu.bus.set_upper_memory(true);
+    mcb::init(&mut cpu.bus);
+    cpu.current_psp = 0x1234;
+    cpu
+}
+
+fn dos_call(cpu: &mut Cpu, ax: u16, bx: u16) -> Result<u16, u16> {
+    cpu.ax = ax;
+    cpu.bx = bx;
+    int21::handle(cpu);
+    if cpu.get_cpu_flag(CpuFlags::CF) {
+        Err(cpu.ax)
+    } else {
+        Ok(cpu.ax)
+    }
+}
+
+// Far calls the XMS driver with AX/BX/DX set, from code at 2000:0000
+fn xms_call(cpu: &mut Cpu, ax: u16, bx: u16, dx: u16) {
+    cpu.ax = 0x4310;
+    int2f::handle(cpu);
+    let (seg, off) = (cpu.es, cpu.bx);
+
+    let mut code = vec![0xB8];
+    code.extend(ax.to_le_bytes());
+    code.push(0xBB);
+    code.extend(bx.to_le_bytes());
+    code.push(0xBA);
+    code.extend(dx.to_le_bytes());
+    code.push(0x9A);
+    code.extend(off.to_le_bytes());
+    code.extend(seg.to_le_bytes());
+    code.push(0xF4);
+    for (i, &byte) in code.iter().enumerate() {
+        cpu.bus.write_8(0x20000 + i, byte);
+    }
+    cpu.cs = 0x2000;
+    cpu.ip = 0x0000;
+    cpu.ss = 0x3000;
+    cpu.sp = 0x0100;
+    cpu.state = CpuState::Running;
+    for _ in 0..50 {
+        if cpu.state == CpuState::Halted {
+            break;
+        }
+        cpu.step();
+    }
+    assert_eq!(cpu.state, CpuState::Halted);
+}
+
+#[test]
+fn test_umb_carved_around_ems_frame() {
+    let mut cpu = Cpu::new(PathBuf::from("."));
+    assert!(cpu.bus.upper_memory().is_empty());
+
+    // The default EMS frame takes E000-EFFF
+    cpu.bus.set_upper_memory(true);
+    assert_eq!(cpu.bus.upper_memory().len(), 1);
+    assert_eq!(cpu.bus.upper_memory()[0], 0xC800..0xE000);
+    cpu.bus.write_8(0xC8000, 0x5A);
+    assert_eq!(cpu.bus.read_8(0xC8000), 0x5A);
+
+    // Moving the frame re-carves around it
+    cpu.bus.set_expanded_memory(64, 0xD000).unwrap();
+    assert_eq!(cpu.bus.upper_memory(), &[0xC800..0xD000, 0xE000..0xF000]);
+
+    cpu.bus.set_upper_memory(false);
+    assert!(cpu.bus.upper_memory().is_empty());
+    assert_eq!(cpu.bus.read_8(0xC8000), 0xFF);
+}
+
+#[test]
+fn test_umb_chain_linking() {
+    let mut cpu = Cpu::new(PathBuf::from("."));
+    cpu.bus.set_expanded_memory(64, 0xD000).unwrap();
+    cpu.bus.set_upper_memory(true);
+    mcb::init(&mut cpu.bus);
+    cpu.current_psp = 0x1234;
+
+    // Unlinked, DOS sees conventional memory only
+    let blocks = mcb::chain(&cpu.bus).unwrap();
+    assert_eq!(blocks.len(), 1);
+    assert_eq!((blocks[0].end(), blocks[0].last), (0x9FFF, true));
+
+    assert!(dos_call(&mut cpu, 0x5803, 0x0001).is_ok());
+    dos_call(&mut cpu, 0x5802, 0).unwrap();
+    assert_eq!(cpu.get_reg8(Register::AL), 1);
+
+    // Linked: system blocks bridge the video memory and the EMS frame
+    let blocks = mcb::chain(&cpu.bus).unwrap();
+    let layout: Vec<_> = blocks
+        .iter()
+        .map(|b| (b.segment, b.owner, b.end(), b.last))
+        .collect();
+    assert_eq!(
+        layout,
+        vec![
+            (mcb::FIRST_MCB, 0, 0x9FFF, false),
+            (0x9FFF, mcb::OWNER_DOS, 0xC800, false),
+            (0xC800, 0, 0xCFFF, false),
+            (0xCFFF, mcb::OWNER_DOS, 0xE000, false),
+            (0xE000, 0, 0xF000, true),
+        ]
+    );
+    assert_eq!(blocks[1].name(&cpu.bus), "SC");
+
+    // Upper only, upper first, then low memory when the UMBs are too small
+    assert!(dos_call(&mut cpu, 0x5801, 0x0040).is_ok());
+    let high = dos_call(&mut cpu, 0x4800, 0x100).unwrap();
+    assert_eq!(high, 0xC801);
+    assert_eq!(dos_call(&mut cpu, 0x4800, 0x2000), Err(8));
+    assert_eq!(cpu.bx, 0x0FFF);
+    assert!(dos_call(&mut cpu, 0x5801, 0x0080).is_ok());
+    let low = dos_call(&mut cpu, 0x4800, 0x2000).unwrap();
+    assert_eq!(low, mcb::FIRST_MCB + 1);
+
+    // Blocks in upper memory resize and free like any other
+    cpu.es = high;
+    assert!(dos_call(&mut cpu, 0x4A00, 0x600).is_ok());
+    assert_eq!(dos_call(&mut cpu, 0x4A00, 0x800), Err(8));
+    assert_eq!(cpu.bx, 0x7FE);
+
+    // Unlinking hides the UMBs again, but their blocks can still be freed
+    assert!(dos_call(&mut cpu, 0x5803, 0x0000).is_ok());
+    assert!(dos_call(&mut cpu, 0x5801, 0x0040).is_ok());
+    assert_eq!(dos_call(&mut cpu, 0x4800, 0x100), Ok(low + 0x2001));
+    assert!(mcb::chain(&cpu.bus).unwrap().iter().all(|b| b.end() < 0xA000));
+    assert!(dos_call(&mut cpu, 0x4900, 0).is_ok());
+    assert!(Mcb::read(&cpu.bus, 0xC800).unwrap().is_free());
+}
+
+#[test]
+fn test_xms_upper_memory_blocks() {
+    let mut cpu = umb_cpu(PathBuf::from("."));
+    let conventional = mcb::largest_free(&mut cpu.bus);
+
+    // Too big: DX has the largest UMB
+    xms_call(&mut cpu, 0x1000, 0, 0x2000);
+    assert_eq!(cpu.ax, 0);
+    assert_eq!(cpu.get_reg8(Register::BL), xms::ERR_SMALLER_UMB);
+    assert_eq!(cpu.dx, 0x17FF);
+
+    xms_call(&mut cpu, 0x1000, 0, 0x100);
+    assert_eq!(cpu.ax, 1);
+    let segment = cpu.bx;
+    assert_eq!((segment, cpu.dx), (0xC801, 0x100));
+    assert_eq!(mcb::largest_free(&mut cpu.bus), conventional);
+
+    // Grow within the UMB, then fail past its end
+    xms_call(&mut cpu, 0x1200, 0x400, segment);
+    assert_eq!(cpu.ax, 1);
+    assert_eq!(Mcb::read(&cpu.bus, segment - 1).unwrap().size, 0x400);
+    xms_call(&mut cpu, 0x1200, 0x1800, segment);
+    assert_eq!(cpu.get_reg8(Register::BL), xms::ERR_SMALLER_UMB);
+    assert_eq!(cpu.dx, 0x17FF);
+
+    // Only segments XMS handed out can be released, and only once
+    xms_call(&mut cpu, 0x1100, 0, mcb::FIRST_MCB + 1);
+    assert_eq!(cpu.get_reg8(Register::BL), xms::ERR_INVALID_UMB);
+    xms_call(&mut cpu, 0x1100, 0, segment);
+    assert_eq!(cpu.ax, 1);
+    xms_call(&mut cpu, 0x1100, 0, segment);
+    assert_eq!(cpu.get_reg8(Register::BL), xms::ERR_INVALID_UMB);
+
+    // Without upper memory there is nothing to hand out
+    cpu.bus.set_upper_memory(false);
+    mcb::init(&mut cpu.bus);
+    xms_call(&mut cpu, 0x1000, 0, 0x10);
+    assert_eq!(cpu.get_reg8(Register::BL), xms::ERR_NO_UMB);
+    assert_eq!(cpu.dx, 0);
+}
+
+#[test]
+fn test_loadhigh_keeps_conventional_memory_free() {
+    let root_path = PathBuf::from("target/test_loadhigh");
+    if root_path.exists() {
+        fs::remove_dir_all(&root_path).unwrap();
+    }
+    fs::create_dir_all(&root_path).unwrap();
+    // MOV AH,48h / MOV BX,FFFFh / INT 21h / HLT: BX reports the largest block
+    fs::write(
+        root_path.join("BIG.COM"),
+        [0xB4, 0x48, 0xBB, 0xFF, 0xFF, 0xCD, 0x21, 0xF4],
+    )
+    .unwrap();
+
+    let mut cpu = umb_cpu(root_path.clone());
+    cpu.load_shell();
+    cpu.pending_command = Some("LH BIG".to_string());
+    assert!(shell::run_pending_command(&mut cpu));
+    assert_eq!(cpu.cs, 0xC801);
+    let block = Mcb::read(&cpu.bus, 0xC800).unwrap();
+    assert_eq!((block.owner, block.name(&cpu.bus)), (0xC801, "BIG".to_string()));
+
+    for _ in 0..20 {
+        if cpu.state == CpuState::Halted {
+            break;
+        }
+        cpu.step();
+    }
+    assert_eq!(cpu.state, CpuState::Halted);
+    assert_eq!(cpu.bx, 0xA000 - mcb::FIRST_MCB - 2);
+
+    // A plain load takes conventional memory; unknown programs are reported
+    cpu.load_shell();
+    cpu.pending_command = Some("BIG".to_string());
+    shell::run_pending_command(&mut cpu);
+    assert_eq!(cpu.cs, 0x1000);
+    cpu.load_shell();
+    cpu.pending_command = Some("LOADHIGH NOPE.COM".to_string());
+    shell::run_pending_command(&mut cpu);
+    assert_eq!(cpu.cs, 0);
+
+    fs::remove_dir_all(&root_path).unwrap();
+}
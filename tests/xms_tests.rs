@@ -0,0 +1,229 @@
+use iced_x86::Register;
+use rust_dos::cpu::{Cpu, CpuFlags, CpuState};
+use rust_dos::interrupts::{int15, int2f};
+use rust_dos::xms;
+use std::path::PathBuf;
+
+fn run(cpu: &mut Cpu, code: &[u8]) {
+    cpu.cs = 0x2000;
+    cpu.ip = 0x0000;
+    cpu.ss = 0x1000;
+    cpu.sp = 0x0100;
+    cpu.state = CpuState::Running;
+    for (i, &byte) in code.iter().enumerate() {
+        cpu.bus.write_8(0x20000 + i, byte);
+    }
+    for _ in 0..50 {
+        if cpu.state == CpuState::Halted {
+            break;
+        }
+        cpu.step();
+    }
+}
+
+// Looks the driver up through INT 2Fh, then far calls it with AX/BX/DX set:
+// MOV AX / MOV BX / MOV DX / CALL FAR entry / HLT
+fn xms_call(cpu: &mut Cpu, ax: u16, bx: u16, dx: u16) {
+    cpu.ax = 0x4310;
+    int2f::handle(cpu);
+    let (seg, off) = (cpu.es, cpu.bx);
+
+    let mut code = vec![0xB8];
+    code.extend(ax.to_le_bytes());
+    code.push(0xBB);
+    code.extend(bx.to_le_bytes());
+    code.push(0xBA);
+    code.extend(dx.to_le_bytes());
+    code.push(0x9A);
+    code.extend(off.to_le_bytes());
+    code.extend(seg.to_le_bytes());
+    code.push(0xF4);
+    run(cpu, &code);
+    assert_eq!(cpu.state, CpuState::Halted);
+    assert_eq!(cpu.sp, 0x0100, "unbalanced stack after AH={:02X}", ax >> 8);
+}
+
+#[test]
+fn test_xms_installation_check() {
+    let mut cpu = Cpu::new(PathBuf::from("."));
+    cpu.ax = 0x4300;
+    int2f::handle(&mut cpu);
+    assert_eq!(cpu.get_reg8(Register::AL), 0x80);
+    assert!(cpu.pending_command.is_none());
+
+    xms_call(&mut cpu, 0x0000, 0, 0);
+    assert_eq!(cpu.ax, 0x0300);
+    assert_eq!(cpu.dx, 0x0001); // HMA present
+}
+
+#[test]
+fn test_xms_allocate_move_free() {
+    let mut cpu = Cpu::new(PathBuf::from("."));
+    cpu.bus.set_extended_memory(1024);
+
+    // 1024KB, minus the 64KB HMA
+    xms_call(&mut cpu, 0x0800, 0, 0);
+    assert_eq!((cpu.ax, cpu.dx), (960, 960));
+
+    xms_call(&mut cpu, 0x0900, 0, 100);
+    assert_eq!(cpu.ax, 1);
+    let handle = cpu.dx;
+    xms_call(&mut cpu, 0x0800, 0, 0);
+    assert_eq!((cpu.ax, cpu.dx), (860, 860));
+
+    // Move 8 bytes from 3000:0000 into the block at offset 1000h and back to 3000:0100
+    for (i, b) in b"XMS TEST".iter().enumerate() {
+        cpu.bus.write_8(0x30000 + i, *b);
+    }
+    let params = 0x4000 * 16;
+    let write_move = |cpu: &mut Cpu, src: (u16, u32), dst: (u16, u32)| {
+        cpu.bus.write_32(params, 8);
+        cpu.bus.write_16(params + 4, src.0);
+        cpu.bus.write_32(params + 6, src.1);
+        cpu.bus.write_16(params + 10, dst.0);
+        cpu.bus.write_32(params + 12, dst.1);
+    };
+    write_move(&mut cpu, (0, 0x3000_0000), (handle, 0x1000));
+    cpu.ds = 0x4000;
+    cpu.si = 0;
+    xms_call(&mut cpu, 0x0B00, 0, 0);
+    assert_eq!(cpu.ax, 1);
+
+    // The data went above 1MB
+    xms_call(&mut cpu, 0x0C00, 0, handle);
+    assert_eq!(cpu.ax, 1);
+    let addr = ((cpu.dx as usize) << 16) | cpu.bx as usize;
+    assert!(addr >= 0x110000);
+    assert_eq!(&cpu.bus.ram[addr + 0x1000..addr + 0x1008], b"XMS TEST");
+
+    write_move(&mut cpu, (handle, 0x1000), (0, 0x3000_0100));
+    xms_call(&mut cpu, 0x0B00, 0, 0);
+    assert_eq!(cpu.ax, 1);
+    assert_eq!(cpu.bus.read_8(0x30100), b'X');
+    assert_eq!(cpu.bus.read_8(0x30107), b'T');
+
+    // Past the end of the block
+    write_move(&mut cpu, (handle, 100 * 1024 - 4), (0, 0x3000_0000));
+    xms_call(&mut cpu, 0x0B00, 0, 0);
+    assert_eq!((cpu.ax, cpu.get_reg8(Register::BL)), (0, xms::ERR_INVALID_LENGTH));
+
+    // Locked blocks can't be freed
+    xms_call(&mut cpu, 0x0E00, 0, handle);
+    assert_eq!(cpu.get_reg8(Register::BH), 1);
+    assert_eq!(cpu.dx, 100);
+    xms_call(&mut cpu, 0x0A00, 0, handle);
+    assert_eq!((cpu.ax, cpu.get_reg8(Register::BL)), (0, xms::ERR_LOCKED));
+    xms_call(&mut cpu, 0x0D00, 0, handle);
+    xms_call(&mut cpu, 0x0A00, 0, handle);
+    assert_eq!(cpu.ax, 1);
+    xms_call(&mut cpu, 0x0A00, 0, handle);
+    assert_eq!((cpu.ax, cpu.get_reg8(Register::BL)), (0, xms::ERR_INVALID_HANDLE));
+
+    // Too big
+    xms_call(&mut cpu, 0x0900, 0, 2000);
+    assert_eq!((cpu.ax, cpu.get_reg8(Register::BL)), (0, xms::ERR_OUT_OF_MEMORY));
+}
+
+#[test]
+fn test_xms_reallocate_keeps_contents() {
+    let mut cpu = Cpu::new(PathBuf::from("."));
+    cpu.bus.set_extended_memory(512);
+
+    let first = cpu.bus.xms.allocate(16).unwrap();
+    let second = cpu.bus.xms.allocate(16).unwrap();
+    let addr = cpu.bus.xms.block(first).unwrap().addr;
+    cpu.bus.ram[addr] = 0xAB;
+    cpu.bus.ram[addr + 16 * 1024 - 1] = 0xCD;
+
+    // Growing past its neighbour moves the block, data included
+    xms_call(&mut cpu, 0x0F00, 64, first);
+    assert_eq!(cpu.ax, 1);
+    let moved = *cpu.bus.xms.block(first).unwrap();
+    assert_ne!(moved.addr, addr);
+    assert_eq!(moved.size_kb, 64);
+    assert_eq!(cpu.bus.ram[moved.addr], 0xAB);
+    assert_eq!(cpu.bus.ram[moved.addr + 16 * 1024 - 1], 0xCD);
+
+    // Shrinking stays in place
+    xms_call(&mut cpu, 0x0F00, 8, second);
+    assert_eq!(cpu.ax, 1);
+    assert_eq!(cpu.bus.xms.block(second).unwrap().size_kb, 8);
+}
+
+#[test]
+fn test_xms_hma_and_a20() {
+    let mut cpu = Cpu::new(PathBuf::from("."));
+
+    xms_call(&mut cpu, 0x0100, 0, 0xFFFF);
+    assert_eq!(cpu.ax, 1);
+    xms_call(&mut cpu, 0x0100, 0, 0xFFFF);
+    assert_eq!((cpu.ax, cpu.get_reg8(Register::BL)), (0, xms::ERR_HMA_IN_USE));
+
+    // Local enables nest
+    xms_call(&mut cpu, 0x0500, 0, 0);
+    xms_call(&mut cpu, 0x0500, 0, 0);
+    assert!(cpu.bus.a20_enabled());
+    xms_call(&mut cpu, 0x0400, 0, 0);
+    assert_eq!(cpu.get_reg8(Register::BL), xms::ERR_A20_STILL_ENABLED);
+    xms_call(&mut cpu, 0x0600, 0, 0);
+    assert!(cpu.bus.a20_enabled());
+    xms_call(&mut cpu, 0x0600, 0, 0);
+    assert_eq!(cpu.ax, 1);
+    xms_call(&mut cpu, 0x0700, 0, 0);
+    assert_eq!(cpu.ax, 0);
+
+    // With A20 on, FFFF:0010 reaches the HMA
+    xms_call(&mut cpu, 0x0300, 0, 0);
+    cpu.bus.write_8(cpu.get_physical_addr(0xFFFF, 0x0010), 0x77);
+    assert_eq!(cpu.bus.ram[0x100000], 0x77);
+    xms_call(&mut cpu, 0x0200, 0, 0);
+    assert_eq!(cpu.ax, 1);
+    xms_call(&mut cpu, 0x0200, 0, 0);
+    assert_eq!(cpu.get_reg8(Register::BL), xms::ERR_HMA_NOT_ALLOCATED);
+
+    // Without extended memory there's no HMA
+    cpu.bus.set_extended_memory(0);
+    xms_call(&mut cpu, 0x0000, 0, 0);
+    assert_eq!(cpu.dx, 0);
+    xms_call(&mut cpu, 0x0100, 0, 0xFFFF);
+    assert_eq!(cpu.get_reg8(Register::BL), xms::ERR_HMA_MISSING);
+    assert_eq!(cpu.bus.read_8(cpu.get_physical_addr(0xFFFF, 0x0010)), 0xFF);
+}
+
+#[test]
+fn test_int15_extended_memory() {
+    let mut cpu = Cpu::new(PathBuf::from("."));
+    cpu.bus.set_extended_memory(20 * 1024);
+
+    cpu.ax = 0x8800;
+    int15::handle(&mut cpu);
+    assert_eq!(cpu.ax, 20 * 1024);
+
+    cpu.ax = 0xE801;
+    int15::handle(&mut cpu);
+    assert_eq!((cpu.ax, cpu.cx), (15 * 1024, 15 * 1024));
+    assert_eq!((cpu.bx, cpu.dx), (80, 80));
+
+    // Block move: 2 words from 3000:0000 to 200000h
+    cpu.bus.write_16(0x30000, 0x1234);
+    cpu.bus.write_16(0x30002, 0x5678);
+    let gdt = 0x40000;
+    let descriptor = |base: u32| {
+        let [b0, b1, b2, _] = base.to_le_bytes();
+        [0xFF, 0xFF, b0, b1, b2, 0x93, 0x00, 0x00]
+    };
+    for (i, b) in descriptor(0x30000).into_iter().enumerate() {
+        cpu.bus.write_8(gdt + 0x10 + i, b);
+    }
+    for (i, b) in descriptor(0x200000).into_iter().enumerate() {
+        cpu.bus.write_8(gdt + 0x18 + i, b);
+    }
+    cpu.es = 0x4000;
+    cpu.si = 0;
+    cpu.cx = 2;
+    cpu.ax = 0x8700;
+    int15::handle(&mut cpu);
+    assert!(!cpu.get_cpu_flag(CpuFlags::CF));
+    assert_eq!(cpu.get_reg8(Register::AH), 0);
+    assert_eq!(&cpu.bus.ram[0x200000..0x200004], &[0x34, 0x12, 0x78, 0x56]);
+}